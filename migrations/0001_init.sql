@@ -0,0 +1,82 @@
+CREATE EXTENSION IF NOT EXISTS postgis;
+
+CREATE TABLE IF NOT EXISTS walk_requests (
+    id BIGSERIAL PRIMARY KEY,
+    dogs JSONB NOT NULL,
+    dog_ids TEXT[] NOT NULL DEFAULT '{}',
+    should_start_after TIMESTAMPTZ,
+    should_start_before TIMESTAMPTZ,
+    should_end_after TIMESTAMPTZ,
+    should_end_before TIMESTAMPTZ,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    location GEOGRAPHY(POINT, 4326) NOT NULL,
+    timezone TEXT,
+    region TEXT,
+    max_applicants BIGINT,
+    created_by TEXT,
+    accepted_by TEXT,
+    accepted_at TIMESTAMPTZ,
+    canceled_at TIMESTAMPTZ,
+    started_at TIMESTAMPTZ,
+    finished_at TIMESTAMPTZ,
+    sla_breached_at TIMESTAMPTZ,
+    expired_at TIMESTAMPTZ,
+    acceptances TEXT[] NOT NULL DEFAULT '{}',
+    dismissed_applicants TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS walk_requests_location_idx ON walk_requests USING GIST (location);
+CREATE INDEX IF NOT EXISTS walk_requests_created_by_idx ON walk_requests (created_by, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS walking_locations (
+    id BIGSERIAL PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS walking_locations_request_idx ON walking_locations (request_id, created_at);
+
+CREATE TABLE IF NOT EXISTS holidays (
+    region TEXT NOT NULL,
+    date DATE NOT NULL,
+    name TEXT NOT NULL,
+    price_multiplier DOUBLE PRECISION NOT NULL,
+    opens_at TIME,
+    closes_at TIME,
+    PRIMARY KEY (region, date)
+);
+
+CREATE TABLE IF NOT EXISTS daily_metrics (
+    region TEXT NOT NULL,
+    date DATE NOT NULL,
+    body JSONB NOT NULL,
+    PRIMARY KEY (region, date)
+);
+
+CREATE TABLE IF NOT EXISTS applications (
+    request_id TEXT NOT NULL,
+    applicant_id TEXT NOT NULL,
+    state TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (request_id, applicant_id)
+);
+
+CREATE INDEX IF NOT EXISTS applications_applicant_idx ON applications (applicant_id, applied_at DESC);
+
+CREATE TABLE IF NOT EXISTS booking_sagas (
+    id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS tenants (
+    id TEXT PRIMARY KEY,
+    body JSONB NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS track_visibility TEXT NOT NULL DEFAULT 'OwnerAndWalker';
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS legacy_imports (
+    key TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    imported_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
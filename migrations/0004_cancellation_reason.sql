@@ -0,0 +1,3 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS canceled_by TEXT,
+    ADD COLUMN IF NOT EXISTS cancellation_reason TEXT;
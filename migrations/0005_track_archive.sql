@@ -0,0 +1,2 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS track_archived_at TIMESTAMPTZ;
@@ -0,0 +1,8 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS requires_large_breed BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS requires_puppy BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS walker_capabilities (
+    user_id TEXT PRIMARY KEY,
+    body JSONB NOT NULL
+);
@@ -0,0 +1,5 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+-- Same shape as walk_requests, columns added to one must be added to both.
+CREATE TABLE IF NOT EXISTS walk_requests_archive (LIKE walk_requests INCLUDING ALL);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS fitness_tokens (
+    user_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    body JSONB NOT NULL,
+    PRIMARY KEY (user_id, provider)
+);
+
+CREATE TABLE IF NOT EXISTS fitness_exports (
+    id TEXT PRIMARY KEY,
+    status TEXT NOT NULL,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS fitness_exports_due_idx ON fitness_exports (status, next_attempt_at);
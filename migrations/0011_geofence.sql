@@ -0,0 +1,7 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS max_radius DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS geofence_violated_at TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS max_radius DOUBLE PRECISION,
+    ADD COLUMN IF NOT EXISTS geofence_violated_at TIMESTAMPTZ;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS walk_schedules (
+    id TEXT PRIMARY KEY,
+    owner_id TEXT NOT NULL,
+    canceled_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS walk_schedules_owner_idx ON walk_schedules (owner_id);
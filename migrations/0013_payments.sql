@@ -0,0 +1,16 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS price_minor_units BIGINT,
+    ADD COLUMN IF NOT EXISTS price_currency TEXT;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS price_minor_units BIGINT,
+    ADD COLUMN IF NOT EXISTS price_currency TEXT;
+
+CREATE TABLE IF NOT EXISTS payments (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS payments_request_idx ON payments (request_id);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS device_sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS device_sessions_user_idx ON device_sessions (user_id);
@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS walk_request_funnels (
+    request_id TEXT PRIMARY KEY,
+    first_viewed_at TIMESTAMPTZ,
+    first_applied_at TIMESTAMPTZ
+);
@@ -0,0 +1 @@
+ALTER TABLE walk_request_funnels ADD COLUMN IF NOT EXISTS impressions BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS track_downsampled_at TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS track_downsampled_at TIMESTAMPTZ;
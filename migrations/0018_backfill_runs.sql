@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS backfill_runs (
+    name TEXT PRIMARY KEY,
+    body JSONB NOT NULL
+);
@@ -0,0 +1,5 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS api_usage (
+    api_key TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    month TEXT NOT NULL,
+    calls BIGINT NOT NULL DEFAULT 0,
+    rejected BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (api_key, endpoint, month)
+);
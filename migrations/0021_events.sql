@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS events (
+    id BIGSERIAL PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS events_request_idx ON events (request_id, occurred_at);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS messages (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    body TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS messages_request_idx ON messages (request_id, sent_at);
+
+CREATE TABLE IF NOT EXISTS message_reads (
+    request_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    unread BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (request_id, user_id)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS walker_strikes (
+    walker_id TEXT NOT NULL,
+    request_id TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL,
+    PRIMARY KEY (walker_id, request_id)
+);
@@ -0,0 +1,7 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS max_walkers BIGINT,
+    ADD COLUMN IF NOT EXISTS pool_walkers JSONB NOT NULL DEFAULT '[]';
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS max_walkers BIGINT,
+    ADD COLUMN IF NOT EXISTS pool_walkers JSONB NOT NULL DEFAULT '[]';
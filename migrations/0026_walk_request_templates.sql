@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS walk_request_templates (
+    id TEXT PRIMARY KEY,
+    owner_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS walk_request_templates_owner_idx ON walk_request_templates (owner_id, created_at);
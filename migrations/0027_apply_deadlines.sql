@@ -0,0 +1,5 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS apply_before TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS apply_before TIMESTAMPTZ;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS user_reputations (
+    user_id TEXT PRIMARY KEY,
+    completed_walks BIGINT NOT NULL DEFAULT 0,
+    strikes BIGINT NOT NULL DEFAULT 0,
+    banned_until TIMESTAMPTZ
+);
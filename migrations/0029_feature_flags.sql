@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS feature_flags (
+    feature TEXT PRIMARY KEY,
+    body JSONB NOT NULL
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS offers (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    walker_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS offers_request_id ON offers (request_id, walker_id);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS start_reminders (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    remind_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS start_reminders_remind_at ON start_reminders (remind_at);
+CREATE INDEX IF NOT EXISTS start_reminders_request_id ON start_reminders (request_id);
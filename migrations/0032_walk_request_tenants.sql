@@ -0,0 +1,7 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS tenant_id TEXT;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS tenant_id TEXT;
+
+CREATE INDEX IF NOT EXISTS walk_requests_tenant_id ON walk_requests (tenant_id, created_at DESC);
@@ -0,0 +1,5 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS verification_waived_at TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS verification_waived_at TIMESTAMPTZ;
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS favorite_walkers (
+    owner_id TEXT NOT NULL,
+    walker_id TEXT NOT NULL,
+    body JSONB NOT NULL,
+    PRIMARY KEY (owner_id, walker_id)
+);
+
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS invited_walker_id TEXT,
+    ADD COLUMN IF NOT EXISTS invitation_expires_at TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS invited_walker_id TEXT,
+    ADD COLUMN IF NOT EXISTS invitation_expires_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS walk_requests_invited_walker_id ON walk_requests (invited_walker_id)
+    WHERE invited_walker_id IS NOT NULL;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS counters (
+    name TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
+
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS reference_code TEXT;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS reference_code TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS walk_requests_reference_code ON walk_requests (reference_code);
@@ -0,0 +1,18 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}';
+
+CREATE INDEX IF NOT EXISTS walk_requests_tags ON walk_requests USING GIN (tags);
+
+CREATE TABLE IF NOT EXISTS saved_searches (
+    id TEXT PRIMARY KEY,
+    walker_id TEXT NOT NULL,
+    notify BOOLEAN NOT NULL,
+    tags TEXT[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS saved_searches_walker_idx ON saved_searches (walker_id, created_at);
@@ -0,0 +1,12 @@
+ALTER TABLE walk_requests
+    ADD COLUMN IF NOT EXISTS start_received_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS finish_received_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS clock_skew_detected_at TIMESTAMPTZ;
+
+ALTER TABLE walk_requests_archive
+    ADD COLUMN IF NOT EXISTS start_received_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS finish_received_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS clock_skew_detected_at TIMESTAMPTZ;
+
+ALTER TABLE walking_locations
+    ADD COLUMN IF NOT EXISTS received_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS receipts (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    owner_id TEXT NOT NULL,
+    issued_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS receipts_owner_idx ON receipts (owner_id, issued_at);
+
+CREATE TABLE IF NOT EXISTS payouts (
+    id TEXT PRIMARY KEY,
+    request_id TEXT NOT NULL,
+    walker_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    body JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS payouts_walker_idx ON payouts (walker_id, created_at);
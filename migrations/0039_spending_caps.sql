@@ -0,0 +1,4 @@
+CREATE TABLE IF NOT EXISTS spending_caps (
+    owner_id TEXT PRIMARY KEY,
+    body JSONB NOT NULL
+);
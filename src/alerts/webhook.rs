@@ -0,0 +1,45 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::core::alert::{Alert, AlertSink};
+
+/// Posts alerts to a Slack incoming webhook, or to a Discord webhook when
+/// the url is one.
+pub struct ChatWebhook {
+    url: String,
+    discord: bool,
+    client: reqwest::Client,
+}
+
+impl ChatWebhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            discord: url.contains("discord.com/") || url.contains("discordapp.com/"),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl AlertSink for ChatWebhook {
+    async fn send(&self, alert: &Alert) -> Result<(), Error> {
+        let text = format!(
+            "[{:?}][{}] {} ({})",
+            alert.kind, alert.region, alert.message, alert.request_id
+        );
+        let body = if self.discord {
+            json!({ "content": text })
+        } else {
+            json!({ "text": text })
+        };
+        self.client
+            .post(&self.url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
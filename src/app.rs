@@ -0,0 +1,511 @@
+use std::future::Future;
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Service as _, ServiceRequest, ServiceResponse},
+    http::header::{ACCEPT_LANGUAGE, CONTENT_LANGUAGE},
+    web::{delete, get, post, put, resource, scope, JsonConfig, ServiceConfig},
+    HttpResponse, Scope,
+};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::core::{
+    error::ServiceError,
+    i18n::{self, Locale},
+    repository::Repository,
+};
+use crate::handlers::{
+    self, accept, assign_accepter, cancel_accepted_request, cancel_unaccepted_request,
+    dismiss_accepter, finish_walk, record_walking_location, remove_acceptance, resign_acceptance,
+    start_walk,
+};
+use crate::metering::Metering;
+use crate::openapi::ApiDoc;
+use crate::rate_limiting::RateLimiting;
+use crate::responses::{
+    add_display_times, convert_distances, preferred_locale, requested_unit, rewrite_json,
+    wants_display_times, DistanceUnit, ResponsePolicy,
+};
+use crate::tenancy::Tenancy;
+
+/// Legacy dumps are posted inline, well above the default JSON limit.
+const IMPORT_BODY_LIMIT: usize = 64 * 1024 * 1024;
+
+fn api<R>(path: &str) -> Scope
+where
+    R: Repository + Clone + 'static,
+{
+    scope(path)
+        .route("meta", get().to(handlers::meta::<R>))
+        .route(
+            "reports/walker/earnings",
+            get().to(handlers::walker_earnings::<R>),
+        )
+        .route(
+            "reports/owner/activity",
+            get().to(handlers::owner_activity::<R>),
+        )
+        .route("payouts", get().to(handlers::payouts::<R>))
+        .route("spending_cap", put().to(handlers::set_spending_cap::<R>))
+        .route(
+            "spending_cap",
+            delete().to(handlers::remove_spending_cap::<R>),
+        )
+        .route(
+            "public/open_requests",
+            get().to(handlers::open_request_counts::<R>),
+        )
+        .service(
+            scope("admin")
+                .route(
+                    "walk_requests",
+                    get().to(handlers::admin_walk_requests::<R>),
+                )
+                .route(
+                    "walk_requests/query",
+                    post().to(handlers::admin_query_walk_requests::<R>),
+                )
+                .route(
+                    "walk_requests/{id}/audit",
+                    get().to(handlers::verify_audit_chain::<R>),
+                )
+                .route(
+                    "walk_requests/{id}/timeline",
+                    get().to(handlers::admin_timeline::<R>),
+                )
+                .route(
+                    "walk_requests/{id}",
+                    delete().to(handlers::admin_delete_walk_request::<R>),
+                )
+                .route(
+                    "walk_requests/{id}/cancel",
+                    put().to(handlers::admin_cancel::<R>),
+                )
+                .route(
+                    "walk_requests/{id}/reassign",
+                    put().to(handlers::admin_reassign::<R>),
+                )
+                .route("metrics/daily", get().to(handlers::daily_metrics::<R>))
+                .route("metrics/funnel", get().to(handlers::funnel_metrics::<R>))
+                .route("metrics/sla", get().to(handlers::sla_metrics::<R>))
+                .route("sagas/stuck", get().to(handlers::stuck_sagas::<R>))
+                .route(
+                    "walkers/{id}/strikes",
+                    get().to(handlers::walker_strikes::<R>),
+                )
+                .route("outbox/dead", get().to(handlers::dead_letters::<R>))
+                .route(
+                    "outbox/{id}/requeue",
+                    post().to(handlers::requeue_outbox_message::<R>),
+                )
+                .route(
+                    "walk_requests/reconcile",
+                    post().to(handlers::reconcile_walk_requests::<R>),
+                )
+                .route(
+                    "retention/purge",
+                    post().to(handlers::purge_expired_data::<R>),
+                )
+                .route("simulations", post().to(handlers::simulate_day::<R>))
+                .route(
+                    "summaries/recompute",
+                    post().to(handlers::recompute_summaries::<R>),
+                )
+                .route(
+                    "summaries/recompute/{id}",
+                    get().to(handlers::recompute_progress::<R>),
+                )
+                .route("usage", get().to(handlers::api_usage::<R>))
+                .route("backfills", get().to(handlers::backfills::<R>))
+                .route("backfills/{name}", get().to(handlers::backfill_run::<R>))
+                .route("backfills/{name}", post().to(handlers::run_backfill::<R>))
+                .route("feature_flags", get().to(handlers::feature_flags::<R>))
+                .route(
+                    "feature_flags/{feature}",
+                    put().to(handlers::set_feature_flag::<R>),
+                )
+                .route("keys", get().to(handlers::secret_keys::<R>))
+                .route(
+                    "keys/{ring}/rotate",
+                    post().to(handlers::rotate_secret::<R>),
+                )
+                .route(
+                    "keys/{ring}/{kid}",
+                    delete().to(handlers::retire_secret::<R>),
+                )
+                .service(
+                    resource("imports")
+                        .app_data(JsonConfig::default().limit(IMPORT_BODY_LIMIT))
+                        .route(post().to(handlers::import_legacy::<R>)),
+                )
+                .route("tenants/{id}", get().to(handlers::tenant::<R>))
+                .route("tenants/{id}", put().to(handlers::set_tenant::<R>))
+                .route(
+                    "walk_requests",
+                    put().to(handlers::bulk_update_walk_requests::<R>),
+                )
+                .service(
+                    scope("holidays")
+                        .route("/{region}", get().to(handlers::holidays::<R>))
+                        .route("/{region}/{date}", put().to(handlers::set_holiday::<R>))
+                        .route(
+                            "/{region}/{date}",
+                            delete().to(handlers::remove_holiday::<R>),
+                        ),
+                ),
+        )
+        .route(
+            "internal/feeds/{region}/walk_requests.atom",
+            get().to(handlers::walk_request_feed::<R>),
+        )
+        .service(
+            scope("internal/users/{user_id}/walk_requests")
+                .route("", post().to(handlers::create_walk_request_for_user::<R>))
+                .route("", get().to(handlers::walk_requests_of_user::<R>))
+                .route(
+                    "/{id}",
+                    delete().to(handlers::cancel_unaccepted_request_for_user::<R>),
+                )
+                .route(
+                    "/{id}/accepted_by/{uid}",
+                    delete().to(handlers::cancel_accepted_request_for_user::<R>),
+                )
+                .route(
+                    "/{id}/track_visibility",
+                    put().to(handlers::set_track_visibility_for_user::<R>),
+                ),
+        )
+        .service(scope("walkers").route("me/conflicts", get().to(handlers::walker_conflicts::<R>)))
+        .service(scope("users").route("{id}/reputation", get().to(handlers::user_reputation::<R>)))
+        .service(
+            scope("walk_requests")
+                .route("", post().to(handlers::create_walk_request::<R>))
+                .route("preview", post().to(handlers::preview_walk_request::<R>))
+                .route("nearby", get().to(handlers::nearby_walk_requests::<R>))
+                .route("in_area", get().to(handlers::walk_requests_in_area::<R>))
+                .route("impressions", post().to(handlers::record_impressions::<R>))
+                .route("mine", get().to(handlers::my_walk_requests::<R>))
+                .route("export", get().to(handlers::export_walk_requests::<R>))
+                .route("accepted", get().to(handlers::accepted_walk_requests::<R>))
+                .route("applied", get().to(handlers::applied_walk_requests::<R>))
+                .route(
+                    "history/mine",
+                    get().to(handlers::walk_request_history::<R>),
+                )
+                .route(
+                    "capabilities/mine",
+                    get().to(handlers::my_capabilities::<R>),
+                )
+                .route(
+                    "capabilities/mine",
+                    put().to(handlers::set_my_capabilities::<R>),
+                )
+                .route(
+                    "applications/mine",
+                    get().to(handlers::my_applications::<R>),
+                )
+                .route("schedules/mine", get().to(handlers::my_walk_schedules::<R>))
+                .route(
+                    "schedules/mine",
+                    post().to(handlers::create_walk_schedule::<R>),
+                )
+                .route(
+                    "schedules/mine/{id}",
+                    delete().to(handlers::cancel_walk_schedule::<R>),
+                )
+                .route(
+                    "saved_searches/mine",
+                    get().to(handlers::my_saved_searches::<R>),
+                )
+                .route(
+                    "saved_searches/mine",
+                    post().to(handlers::create_saved_search::<R>),
+                )
+                .route(
+                    "saved_searches/mine/{id}",
+                    get().to(handlers::get_saved_search::<R>),
+                )
+                .route(
+                    "saved_searches/mine/{id}",
+                    put().to(handlers::update_saved_search::<R>),
+                )
+                .route(
+                    "saved_searches/mine/{id}",
+                    delete().to(handlers::delete_saved_search::<R>),
+                )
+                .route(
+                    "saved_searches/mine/{id}/results",
+                    get().to(handlers::saved_search_results::<R>),
+                )
+                .route(
+                    "templates/mine",
+                    get().to(handlers::my_walk_request_templates::<R>),
+                )
+                .route(
+                    "templates/mine",
+                    post().to(handlers::create_walk_request_template::<R>),
+                )
+                .route(
+                    "templates/mine/{id}",
+                    get().to(handlers::get_walk_request_template::<R>),
+                )
+                .route(
+                    "templates/mine/{id}",
+                    put().to(handlers::update_walk_request_template::<R>),
+                )
+                .route(
+                    "templates/mine/{id}",
+                    delete().to(handlers::delete_walk_request_template::<R>),
+                )
+                .route(
+                    "templates/mine/{id}/post",
+                    post().to(handlers::post_walk_request_template::<R>),
+                )
+                .route("sessions/mine", get().to(handlers::my_device_sessions::<R>))
+                .route(
+                    "sessions/mine/{device_id}",
+                    put().to(handlers::register_device_session::<R>),
+                )
+                .route(
+                    "sessions/mine/{device_id}",
+                    delete().to(handlers::revoke_device_session::<R>),
+                )
+                .route(
+                    "sessions/mine/{device_id}/subscriptions/{request_id}",
+                    delete().to(handlers::unfollow_walk_request::<R>),
+                )
+                .route(
+                    "fitness/mine",
+                    get().to(handlers::my_fitness_connections::<R>),
+                )
+                .route(
+                    "fitness/mine/{provider}",
+                    put().to(handlers::connect_fitness::<R>),
+                )
+                .route(
+                    "fitness/mine/{provider}",
+                    delete().to(handlers::disconnect_fitness::<R>),
+                )
+                .route(
+                    "favorites/mine",
+                    get().to(handlers::my_favorite_walkers::<R>),
+                )
+                .route(
+                    "favorites/mine/{walker_id}",
+                    put().to(handlers::add_favorite_walker::<R>),
+                )
+                .route(
+                    "favorites/mine/{walker_id}",
+                    delete().to(handlers::remove_favorite_walker::<R>),
+                )
+                .route("walk_budget/mine", get().to(handlers::my_walk_budget::<R>))
+                .route("calendar.ics", get().to(handlers::calendar::<R>))
+                .route("calendar_token", get().to(handlers::calendar_token))
+                .route("/{id}/accepted_by", put().to(accept::<R>))
+                .route("/{id}/acceptances", post().to(handlers::apply::<R>))
+                .route("/{id}/acceptances", delete().to(remove_acceptance::<R>))
+                .route("/{id}/accepter", put().to(handlers::swap_accepter::<R>))
+                .route("/{id}/accepter/{uid}", put().to(assign_accepter::<R>))
+                .route("/{id}/accepter/{uid}", delete().to(dismiss_accepter::<R>))
+                .route(
+                    "/{id}/applicants",
+                    delete().to(handlers::dismiss_applicants::<R>),
+                )
+                .route("/{id}/resign", delete().to(resign_acceptance::<R>))
+                .route(
+                    "/{id}/accepted_by/{uid}",
+                    delete().to(cancel_accepted_request::<R>),
+                )
+                .route("/{id}", get().to(handlers::get_walk_request::<R>))
+                .route("/{id}", put().to(handlers::edit_walk_request::<R>))
+                .route("/{id}", delete().to(cancel_unaccepted_request::<R>))
+                .route("/{id}/clone", post().to(handlers::clone_walk_request::<R>))
+                .route(
+                    "/{id}/duplicate",
+                    post().to(handlers::duplicate_walk_request::<R>),
+                )
+                .route("/{id}/undo_cancel", put().to(handlers::undo_cancel::<R>))
+                .route(
+                    "/{id}/invitation",
+                    delete().to(handlers::decline_invitation::<R>),
+                )
+                .route("/{id}/start", put().to(start_walk::<R>))
+                .route(
+                    "/{id}/verification_waiver",
+                    put().to(handlers::waive_walk_verification::<R>),
+                )
+                .route("/{id}/finish", put().to(finish_walk::<R>))
+                .route("/{id}/summary", get().to(handlers::walk_summary::<R>))
+                .route("/{id}/card", get().to(handlers::walk_summary_card::<R>))
+                .route("/{id}/route", get().to(handlers::walk_route::<R>))
+                .route("/{id}/progress", get().to(handlers::walk_progress::<R>))
+                .route(
+                    "/{id}/card.svg",
+                    get().to(handlers::walk_summary_card_svg::<R>),
+                )
+                .route(
+                    "/{id}/route.png",
+                    get().to(handlers::walk_route_thumbnail::<R>),
+                )
+                .route("/{id}/locations", post().to(record_walking_location::<R>))
+                .route(
+                    "/{id}/locations/batch",
+                    post().to(handlers::record_walking_locations::<R>),
+                )
+                .route(
+                    "/{id}/track_visibility",
+                    put().to(handlers::set_track_visibility::<R>),
+                )
+                .route(
+                    "/{id}/locations",
+                    get().to(handlers::walking_locations::<R>),
+                )
+                .route("/{id}/poll", get().to(handlers::poll_walk_request::<R>))
+                .route("/{id}/payment", get().to(handlers::payment::<R>))
+                .route("/{id}/receipt", get().to(handlers::receipt::<R>))
+                .route("/{id}/timeline", get().to(handlers::timeline::<R>))
+                .route("/{id}/messages", post().to(handlers::send_message::<R>))
+                .route("/{id}/messages", get().to(handlers::messages::<R>))
+                .route("/{id}/offers", get().to(handlers::offers::<R>))
+                .route("/{id}/offers", post().to(handlers::make_offer::<R>))
+                .route(
+                    "/{id}/offers/{offer_id}/counter",
+                    post().to(handlers::counter_offer::<R>),
+                )
+                .route(
+                    "/{id}/offers/{offer_id}/accept",
+                    put().to(handlers::accept_offer::<R>),
+                )
+                .route(
+                    "/{id}/locations/export",
+                    get().to(handlers::export_walking_locations::<R>),
+                )
+                .route(
+                    "/{id}/locations/live",
+                    get().to(handlers::live_walking_locations::<R>),
+                ),
+        )
+}
+
+/// The HTTP routes, `v2` under `apis/v2` with the response `policy`, the
+/// original API under `apis`. Distances are in `distance_unit` unless a
+/// call asks for another.
+pub fn routes<R>(cfg: &mut ServiceConfig, policy: ResponsePolicy, distance_unit: DistanceUnit)
+where
+    R: Repository + Clone + 'static,
+{
+    cfg.route("/readyz", get().to(handlers::readyz))
+        .service(
+            SwaggerUi::new("/apis/swagger-ui/{_:.*}").url("/apis/openapi.json", ApiDoc::openapi()),
+        )
+        .service(
+            api::<R>("apis/v2")
+                .wrap(Tenancy::<R>::default())
+                .wrap(Metering::<R>::default())
+                .wrap(RateLimiting)
+                .wrap_fn(localize_errors)
+                .wrap_fn(localize_times)
+                .wrap_fn(move |req, srv| localize_distances(req, srv, distance_unit))
+                .wrap_fn(move |req, srv| {
+                    let res = srv.call(req);
+                    async move { policy.apply(res.await?).await }
+                }),
+        )
+        .service(
+            api::<R>("apis")
+                .wrap(Tenancy::<R>::default())
+                .wrap(Metering::<R>::default())
+                .wrap(RateLimiting)
+                .wrap_fn(localize_errors)
+                .wrap_fn(localize_times)
+                .wrap_fn(move |req, srv| localize_distances(req, srv, distance_unit)),
+        );
+}
+
+/// Error messages in the language of `Accept-Language`, service errors get
+/// their localized JSON body and known plain messages are translated.
+fn localize_errors<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: actix_web::dev::Service<
+        ServiceRequest,
+        Response = ServiceResponse<B>,
+        Error = actix_web::Error,
+    >,
+    B: MessageBody + 'static,
+{
+    let locale = Locale::negotiate(
+        req.headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let res = srv.call(req);
+    async move {
+        let res = res.await?;
+        if locale == Locale::default() {
+            return Ok(res.map_into_boxed_body());
+        }
+        let localized = res.response().error().and_then(|e| {
+            if let Some(e) = e.as_error::<ServiceError>() {
+                return Some(handlers::localized_error_response(e, locale));
+            }
+            let message = i18n::translate(&e.to_string(), locale)?;
+            Some(
+                HttpResponse::build(res.status())
+                    .insert_header((CONTENT_LANGUAGE, locale.tag()))
+                    .body(message),
+            )
+        });
+        Ok(match localized {
+            Some(response) => res.into_response(response),
+            None => res.map_into_boxed_body(),
+        })
+    }
+}
+
+/// Opt-in `display_times=true` transformation adding localized display strings.
+fn localize_times<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: actix_web::dev::Service<
+        ServiceRequest,
+        Response = ServiceResponse<B>,
+        Error = actix_web::Error,
+    >,
+    B: MessageBody + 'static,
+{
+    let locale = wants_display_times(req.request()).then(|| preferred_locale(req.request()));
+    let res = srv.call(req);
+    async move {
+        let res = res.await?;
+        match locale {
+            Some(locale) => rewrite_json(res, |value| add_display_times(value, &locale)).await,
+            None => Ok(res.map_into_boxed_body()),
+        }
+    }
+}
+
+/// Distances of search results in the unit of `unit=`, `default` otherwise,
+/// rounded and with a display text.
+fn localize_distances<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+    default: DistanceUnit,
+) -> impl Future<Output = Result<ServiceResponse<BoxBody>, actix_web::Error>>
+where
+    S: actix_web::dev::Service<
+        ServiceRequest,
+        Response = ServiceResponse<B>,
+        Error = actix_web::Error,
+    >,
+    B: MessageBody + 'static,
+{
+    let unit = requested_unit(req.request()).unwrap_or(default);
+    let res = srv.call(req);
+    async move { rewrite_json(res.await?, |value| convert_distances(value, unit)).await }
+}
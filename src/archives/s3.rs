@@ -0,0 +1,57 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use s3::{creds::Credentials, region::Region, Bucket};
+
+use crate::core::archive::TrackArchive;
+
+/// Track archive in a bucket of any S3 compatible object storage.
+pub struct S3Archive {
+    bucket: Bucket,
+}
+
+impl S3Archive {
+    /// Path style addressing, which MinIO and most self-hosted stores expect.
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        bucket: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, Error> {
+        let region = Region::Custom {
+            region: region.to_owned(),
+            endpoint: endpoint.to_owned(),
+        };
+        let credentials = Credentials::new(Some(access_key), Some(secret_key), None, None, None)?;
+        Ok(Self {
+            bucket: Bucket::new(bucket, region, credentials)?.with_path_style(),
+        })
+    }
+}
+
+#[async_trait]
+impl TrackArchive for S3Archive {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error> {
+        let response = self
+            .bucket
+            .put_object_with_content_type(key, &body, "application/gzip")
+            .await?;
+        if response.status_code() / 100 != 2 {
+            return Err(Error::msg(format!(
+                "归档轨迹失败: {} {}",
+                key,
+                response.status_code()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let response = self.bucket.get_object(key).await?;
+        match response.status_code() {
+            404 => Ok(None),
+            code if code / 100 == 2 => Ok(Some(response.bytes().to_vec())),
+            code => Err(Error::msg(format!("读取归档轨迹失败: {} {}", key, code))),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Error;
+use async_trait::async_trait;
+use futures::{future::ready, lock::Mutex, stream::LocalBoxStream, StreamExt};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{entities::WalkingLocation, live::LocationBus};
+
+/// Locations go to a channel per walk request, this prefix and its id.
+const CHANNEL_PREFIX: &str = "walking_locations:";
+
+#[derive(Serialize, Deserialize)]
+struct Message {
+    /// The instance which published it.
+    origin: String,
+    location: WalkingLocation,
+}
+
+/// Relays locations through Redis pub/sub. Every instance subscribes to the
+/// channels of all walk requests and keeps what its own subscribers follow.
+pub struct RedisLocationBus {
+    client: Client,
+    /// Tells this instance's messages apart when they come back.
+    origin: String,
+    connection: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisLocationBus {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        Ok(Self {
+            client: Client::open(url)?,
+            origin: format!("{}-{:x}", process::id(), started),
+            connection: Mutex::new(None),
+        })
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, Error> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+        let connected = self.client.get_multiplexed_tokio_connection().await?;
+        *connection = Some(connected.clone());
+        Ok(connected)
+    }
+}
+
+#[async_trait]
+impl LocationBus for RedisLocationBus {
+    async fn publish(&self, location: &WalkingLocation) -> Result<(), Error> {
+        let message = serde_json::to_string(&Message {
+            origin: self.origin.clone(),
+            location: location.clone(),
+        })?;
+        let channel = format!("{}{}", CHANNEL_PREFIX, location.request_id);
+        let _: i64 = self.connection().await?.publish(channel, message).await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self) -> Result<LocalBoxStream<'static, WalkingLocation>, Error> {
+        // Subscribed connections can't run other commands, each gets its own.
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub.psubscribe(format!("{}*", CHANNEL_PREFIX)).await?;
+        let origin = self.origin.clone();
+        let locations = pubsub.into_on_message().filter_map(move |message| {
+            let location = message
+                .get_payload::<String>()
+                .map_err(Error::from)
+                .and_then(|payload| Ok(serde_json::from_str::<Message>(&payload)?))
+                .map_err(|e| log::error!("dropped a malformed relayed location: {}", e))
+                .ok()
+                .filter(|message| message.origin != origin)
+                .map(|message| message.location);
+            ready(location)
+        });
+        Ok(locations.boxed_local())
+    }
+}
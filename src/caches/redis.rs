@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use redis::{aio::MultiplexedConnection, AsyncCommands, Client};
+
+use crate::core::cache::Cache;
+
+/// A cache in Redis, connected on first use and shared by all callers.
+pub struct RedisCache {
+    client: Client,
+    connection: Mutex<Option<MultiplexedConnection>>,
+}
+
+impl RedisCache {
+    pub fn new(url: &str) -> Result<Self, Error> {
+        Ok(Self {
+            client: Client::open(url)?,
+            connection: Mutex::new(None),
+        })
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, Error> {
+        let mut connection = self.connection.lock().await;
+        if let Some(connection) = connection.as_ref() {
+            return Ok(connection.clone());
+        }
+        let connected = self.client.get_multiplexed_tokio_connection().await?;
+        *connection = Some(connected.clone());
+        Ok(connected)
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        Ok(self.connection().await?.get(key).await?)
+    }
+
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), Error> {
+        let seconds = ttl.as_secs().max(1) as usize;
+        Ok(self.connection().await?.set_ex(key, value, seconds).await?)
+    }
+
+    async fn incr(&self, key: &str) -> Result<i64, Error> {
+        Ok(self.connection().await?.incr(key, 1).await?)
+    }
+
+    async fn incr_with_ttl(&self, key: &str, ttl: Duration) -> Result<i64, Error> {
+        let seconds = ttl.as_secs().max(1) as usize;
+        let (count,): (i64,) = redis::pipe()
+            .atomic()
+            .incr(key, 1)
+            .expire(key, seconds)
+            .ignore()
+            .query_async(&mut self.connection().await?)
+            .await?;
+        Ok(count)
+    }
+}
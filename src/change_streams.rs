@@ -0,0 +1,57 @@
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+use mongodb::change_stream::event::ResumeToken;
+
+use crate::core::{repository::Repository, service::Service};
+use crate::repositories::mongodb::{walk_request_change, Mongodb};
+
+/// Follows the MongoDB change stream of walk requests, so the long polls
+/// and in-process caches of this instance see the writes of every other.
+#[derive(Clone)]
+pub struct WalkRequestListener<R> {
+    mongodb: Mongodb,
+    service: Service<R>,
+    /// Of the last change handled, shared by the restarts of the listener.
+    resume_token: Arc<Mutex<Option<ResumeToken>>>,
+}
+
+impl<R> WalkRequestListener<R>
+where
+    R: Repository + Clone,
+{
+    pub fn new(mongodb: Mongodb, service: Service<R>) -> Self {
+        Self {
+            mongodb,
+            service,
+            resume_token: Arc::default(),
+        }
+    }
+
+    /// Listens until the stream fails, the supervisor starts it again after
+    /// the last change handled.
+    pub async fn run(self) {
+        let resume_after = self.resume_token.lock().unwrap().clone();
+        let mut changes = match self.mongodb.walk_request_changes(resume_after).await {
+            Ok(changes) => changes,
+            Err(e) => {
+                log::error!("failed to watch walk requests: {}", e);
+                return;
+            }
+        };
+        while let Some(event) = changes.next().await {
+            match event {
+                Ok(event) => {
+                    if let Some(change) = walk_request_change(&event) {
+                        self.service.apply_remote_change(change);
+                    }
+                    *self.resume_token.lock().unwrap() = changes.resume_token();
+                }
+                Err(e) => {
+                    log::error!("walk request change stream failed: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,491 @@
+use std::{fmt, path::Path};
+
+use figment::{
+    providers::{Env, Format, Serialized, Toml},
+    Figment,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{distance::DistanceStrategy, ids::IdFormat, ranking::RankerKind},
+    responses::{Casing, DistanceUnit},
+};
+
+/// Read when neither `--config` nor `CONFIG_FILE` names a file, skipped
+/// when missing.
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Shown instead of secrets by `--print-config`.
+const REDACTED: &str = "<redacted>";
+
+/// Settings of the service, each layer overriding the one before: the
+/// defaults below, the TOML config file, then environment variables named
+/// after the fields in upper case (`MONGODB_*` for the `[mongodb]` table).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub listen_address: String,
+    pub grpc_listen_address: String,
+    pub database_url: String,
+    pub database_name: String,
+    pub database_kind: String,
+    pub persistence_mode: String,
+    pub id_format: String,
+    /// Prefix of the reference codes new walk requests get, like
+    /// `WR-2024-000123`. Empty leaves them without.
+    pub reference_code_prefix: String,
+    pub skip_index_bootstrap: bool,
+    pub shutdown_timeout_seconds: u64,
+    /// PEM certificate chain, serves HTTPS with HTTP/2 when set along with
+    /// `tls_key_file`.
+    pub tls_cert_file: String,
+    pub tls_key_file: String,
+    /// HTTP worker threads, 0 for one per CPU core.
+    pub http_workers: usize,
+    /// How long idle connections are kept open, 0 closes them after each
+    /// request.
+    pub keep_alive_seconds: u64,
+    /// Compresses responses with gzip, brotli or zstd as the client
+    /// accepts, streamed events aren't.
+    pub compress_responses: bool,
+    /// How long clients have to send the request head.
+    pub client_request_timeout_millis: u64,
+    /// Default filter, `RUST_LOG` overrides it.
+    pub log_level: String,
+    /// Access log line, the timestamp and the level are added to it.
+    pub log_format: String,
+    /// One JSON object per log line, with the request id of the call the
+    /// line belongs to. Plain text otherwise.
+    pub log_json: bool,
+    pub calendar_token_secret: String,
+    /// `kid:secret,...`, the active key first, overrides CALENDAR_TOKEN_SECRET.
+    pub calendar_token_keys: String,
+    /// `jwt` or `header`, which trusts the identity headers set by a
+    /// gateway and needs `trust_identity_headers`.
+    pub auth_mode: String,
+    /// Opts in to `auth_mode = "header"`, only for deployments where
+    /// nothing but the gateway reaches the server.
+    pub trust_identity_headers: bool,
+    pub jwt_algorithm: String,
+    pub jwt_key: String,
+    /// `kid:key,...`, tokens are verified with the key named by their `kid`
+    /// header, overrides JWT_KEY.
+    pub jwt_keys: String,
+    pub holidays_file: String,
+    pub holiday_cache_seconds: u64,
+    pub max_applicants: i64,
+    pub sla_accept_minutes: i64,
+    pub sla_regions: String,
+    pub cancel_undo_seconds: i64,
+    pub archive_after_days: i64,
+    pub walking_location_ttl_days: u64,
+    pub track_downsample_after_days: i64,
+    pub track_downsample_seconds: i64,
+    pub backfill_batch_size: i64,
+    pub backfill_pause_millis: u64,
+    pub schedule_horizon_hours: i64,
+    /// Minutes before an accepted walk starts to remind both parties at,
+    /// comma separated, empty for no reminders.
+    pub start_reminder_minutes: String,
+    pub walk_budget_daily_minutes: i64,
+    pub walk_budget_regions: String,
+    pub payment_service_url: String,
+    pub user_service_url: String,
+    pub walker_capabilities_source: String,
+    pub webhook_url: String,
+    /// `kid:secret,...`, webhooks are signed with the first one.
+    pub webhook_secrets: String,
+    pub alert_routes: String,
+    pub fitness_providers: String,
+    pub osrm_url: String,
+    pub fcm_credentials_file: String,
+    pub osrm_profile: String,
+    /// Static maps API drawing route thumbnails, drawn here when unset.
+    pub static_map_url: String,
+    pub static_map_key: String,
+    pub distance_strategy: String,
+    pub retention_policy: String,
+    pub retention_dry_run: bool,
+    pub track_archive_endpoint: String,
+    pub track_archive_region: String,
+    pub track_archive_bucket: String,
+    pub track_archive_access_key: String,
+    pub track_archive_secret_key: String,
+    pub default_currency: String,
+    pub region_currencies: String,
+    pub exchange_rates: String,
+    pub regions_served: String,
+    /// Nearby searches beyond this radius are refused, 0 allows any.
+    pub max_nearby_radius_meters: f64,
+    /// Listings refuse pages deeper or larger than these.
+    pub max_page: i64,
+    pub max_page_size: i64,
+    pub impression_sample_rate: f64,
+    pub nearby_ranking: String,
+    pub ranking_service_url: String,
+    pub experiments: String,
+    /// `feature=percent` entries, features not listed are on for everyone.
+    pub feature_flags: String,
+    /// How soon flags changed by admins reach the other instances.
+    pub feature_flag_cache_seconds: u64,
+    /// Buffered single location writes, 0 writes them directly.
+    pub location_queue_capacity: usize,
+    pub location_flush_batch_size: usize,
+    pub location_flush_millis: u64,
+    pub location_retry_after_seconds: u64,
+    /// Single location writes closer than this to the walk's previous
+    /// point are dropped, 0 keeps them.
+    pub location_min_distance_meters: f64,
+    /// Single location writes sooner than this after the walk's previous
+    /// point are dropped, 0 keeps them.
+    pub location_min_interval_millis: i64,
+    /// How far from the pickup point walkers may start a walk, 0 doesn't
+    /// check where they are.
+    pub walk_start_radius_meters: f64,
+    /// Walks shorter than this, or with a shorter track than
+    /// WALK_MIN_DISTANCE_METERS, can't be finished yet, 0 doesn't check.
+    pub walk_min_minutes: i64,
+    pub walk_min_distance_meters: f64,
+    /// How long a walker invited to a request has it to themselves before
+    /// it opens to everyone.
+    pub invitation_timeout_minutes: i64,
+    /// An experiment whose variants are named after rankers, overrides
+    /// NEARBY_RANKING.
+    pub nearby_ranking_experiment: String,
+    pub redis_url: String,
+    pub nearby_cache_seconds: u64,
+    /// Calls per user and window to read routes, 0 for no limit.
+    pub rate_limit_reads: u64,
+    /// Calls per user and window to write routes, 0 for no limit.
+    pub rate_limit_writes: u64,
+    pub rate_limit_window_seconds: u64,
+    /// Counts calls in REDIS_URL so that instances share the budgets.
+    pub rate_limit_shared: bool,
+    /// Relays live walking locations through REDIS_URL, so that followers
+    /// connected to any instance see the points recorded on the others.
+    pub relay_locations: bool,
+    /// Origins allowed to call the API from a browser, comma separated or
+    /// `*`. Empty disables CORS.
+    pub cors_allowed_origins: String,
+    pub cors_allowed_methods: String,
+    pub cors_allowed_headers: String,
+    pub cors_max_age_seconds: usize,
+    /// Sends Strict-Transport-Security with this max age, 0 for none.
+    pub hsts_max_age_seconds: u64,
+    pub research_api_keys: String,
+    pub internal_api_keys: String,
+    pub research_quota_window_seconds: u64,
+    pub partner_monthly_quotas: String,
+    pub v2_field_casing: String,
+    /// `m`, `km` or `mi`, distances of search results are returned in.
+    pub distance_unit: String,
+    pub smtp_host: String,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    pub smtp_from: String,
+    pub mongodb: MongodbConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MongodbConfig {
+    pub transactions: bool,
+    /// Follows the change stream of walk requests so that several instances
+    /// see each other's writes, needs a replica set.
+    pub change_streams: bool,
+    pub max_pool_size: u32,
+    pub min_pool_size: u32,
+    pub connect_attempts: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen_address: String::new(),
+            grpc_listen_address: String::new(),
+            database_url: String::new(),
+            database_name: String::new(),
+            database_kind: "mongodb".to_owned(),
+            persistence_mode: "crud".to_owned(),
+            id_format: "object_id".to_owned(),
+            reference_code_prefix: "WR".to_owned(),
+            skip_index_bootstrap: false,
+            shutdown_timeout_seconds: 30,
+            tls_cert_file: String::new(),
+            tls_key_file: String::new(),
+            http_workers: 0,
+            keep_alive_seconds: 5,
+            compress_responses: true,
+            client_request_timeout_millis: 5000,
+            log_level: "info".to_owned(),
+            log_format: "%{X-Request-ID}i %r %s %T".to_owned(),
+            log_json: true,
+            calendar_token_secret: String::new(),
+            calendar_token_keys: String::new(),
+            auth_mode: "jwt".to_owned(),
+            trust_identity_headers: false,
+            jwt_algorithm: "HS256".to_owned(),
+            jwt_key: String::new(),
+            jwt_keys: String::new(),
+            holidays_file: String::new(),
+            holiday_cache_seconds: 300,
+            max_applicants: 0,
+            sla_accept_minutes: 30,
+            sla_regions: String::new(),
+            cancel_undo_seconds: 0,
+            archive_after_days: 0,
+            walking_location_ttl_days: 0,
+            track_downsample_after_days: 0,
+            track_downsample_seconds: 10,
+            backfill_batch_size: 200,
+            backfill_pause_millis: 500,
+            schedule_horizon_hours: 48,
+            start_reminder_minutes: "30,5".to_owned(),
+            walk_budget_daily_minutes: 0,
+            walk_budget_regions: String::new(),
+            payment_service_url: String::new(),
+            user_service_url: String::new(),
+            walker_capabilities_source: "local".to_owned(),
+            webhook_url: String::new(),
+            webhook_secrets: String::new(),
+            alert_routes: String::new(),
+            fitness_providers: String::new(),
+            osrm_url: String::new(),
+            fcm_credentials_file: String::new(),
+            osrm_profile: "foot".to_owned(),
+            static_map_url: String::new(),
+            static_map_key: String::new(),
+            distance_strategy: "raw".to_owned(),
+            retention_policy: String::new(),
+            retention_dry_run: false,
+            track_archive_endpoint: String::new(),
+            track_archive_region: "us-east-1".to_owned(),
+            track_archive_bucket: String::new(),
+            track_archive_access_key: String::new(),
+            track_archive_secret_key: String::new(),
+            default_currency: "CNY".to_owned(),
+            region_currencies: String::new(),
+            exchange_rates: String::new(),
+            regions_served: String::new(),
+            max_nearby_radius_meters: 50_000.0,
+            max_page: 1000,
+            max_page_size: 100,
+            impression_sample_rate: 1.0,
+            nearby_ranking: "distance".to_owned(),
+            ranking_service_url: String::new(),
+            experiments: String::new(),
+            feature_flags: String::new(),
+            feature_flag_cache_seconds: 30,
+            location_queue_capacity: 0,
+            location_flush_batch_size: 500,
+            location_flush_millis: 200,
+            location_retry_after_seconds: 2,
+            location_min_distance_meters: 2.0,
+            location_min_interval_millis: 1000,
+            walk_start_radius_meters: 300.0,
+            walk_min_minutes: 5,
+            walk_min_distance_meters: 100.0,
+            invitation_timeout_minutes: 120,
+            nearby_ranking_experiment: String::new(),
+            redis_url: String::new(),
+            nearby_cache_seconds: 5,
+            rate_limit_reads: 0,
+            rate_limit_writes: 0,
+            rate_limit_window_seconds: 60,
+            rate_limit_shared: false,
+            relay_locations: false,
+            cors_allowed_origins: String::new(),
+            cors_allowed_methods: "GET,POST,PUT,PATCH,DELETE".to_owned(),
+            cors_allowed_headers: "Authorization,Content-Type,Accept-Language,If-Match,X-Api-Key"
+                .to_owned(),
+            cors_max_age_seconds: 3600,
+            hsts_max_age_seconds: 0,
+            research_api_keys: String::new(),
+            internal_api_keys: String::new(),
+            research_quota_window_seconds: 3600,
+            partner_monthly_quotas: String::new(),
+            v2_field_casing: "snake_case".to_owned(),
+            distance_unit: "m".to_owned(),
+            smtp_host: String::new(),
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            smtp_from: String::new(),
+            mongodb: MongodbConfig::default(),
+        }
+    }
+}
+
+impl Default for MongodbConfig {
+    fn default() -> Self {
+        Self {
+            transactions: false,
+            change_streams: false,
+            max_pool_size: 0,
+            min_pool_size: 0,
+            connect_attempts: 10,
+        }
+    }
+}
+
+/// Every problem found in the configuration, reported together.
+#[derive(Debug)]
+pub struct ConfigError(Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.0 {
+            write!(f, "\n  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// `MONGODB_MAX_POOL_SIZE` sets `mongodb.max_pool_size` and so on.
+fn env() -> Env {
+    Env::raw().map(|key| {
+        let key = key.as_str().to_ascii_lowercase();
+        match key.strip_prefix("mongodb_") {
+            Some(option) => format!("mongodb.{}", option).into(),
+            None => key.into(),
+        }
+    })
+}
+
+impl Config {
+    /// Layers the defaults, `file` or the default config file, and the
+    /// environment, then validates the result.
+    pub fn load(file: Option<&str>) -> Result<Self, ConfigError> {
+        if let Some(file) = file {
+            if !Path::new(file).is_file() {
+                return Err(ConfigError(vec![format!("config file {} not found", file)]));
+            }
+        }
+        let config: Config = Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::file(file.unwrap_or(DEFAULT_CONFIG_FILE)))
+            .merge(env())
+            .extract()
+            .map_err(|e| ConfigError(e.into_iter().map(|e| e.to_string()).collect()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut require = |value: &str, name: &str| {
+            if value.is_empty() {
+                problems.push(format!("{} is required", name));
+            }
+        };
+        require(&self.listen_address, "listen_address");
+        if self.calendar_token_keys.is_empty() {
+            require(&self.calendar_token_secret, "calendar_token_secret");
+        }
+        if self.persistence_mode != "memory" {
+            require(&self.database_url, "database_url");
+            if self.database_kind == "mongodb" {
+                require(&self.database_name, "database_name");
+            }
+        }
+        let mut one_of = |value: &str, name: &str, allowed: &[&str]| {
+            if !allowed.contains(&value) {
+                problems.push(format!(
+                    "{} must be one of {}, got {:?}",
+                    name,
+                    allowed.join(", "),
+                    value
+                ));
+            }
+        };
+        one_of(
+            &self.database_kind,
+            "database_kind",
+            &["mongodb", "postgres"],
+        );
+        one_of(
+            &self.persistence_mode,
+            "persistence_mode",
+            &["crud", "event_sourced", "memory"],
+        );
+        one_of(
+            &self.walker_capabilities_source,
+            "walker_capabilities_source",
+            &["local", "user_service"],
+        );
+        if self.max_page < 1 || self.max_page_size < 1 {
+            problems.push("max_page and max_page_size must be at least 1".to_owned());
+        }
+        let mut invalid = |valid: bool, name: &str, value: &str| {
+            if !valid {
+                problems.push(format!("{} {:?} is invalid", name, value));
+            }
+        };
+        invalid(
+            IdFormat::parse(&self.id_format).is_some(),
+            "id_format",
+            &self.id_format,
+        );
+        invalid(
+            DistanceStrategy::parse(&self.distance_strategy).is_ok(),
+            "distance_strategy",
+            &self.distance_strategy,
+        );
+        invalid(
+            DistanceUnit::parse(&self.distance_unit).is_some(),
+            "distance_unit",
+            &self.distance_unit,
+        );
+        invalid(
+            Casing::parse(&self.v2_field_casing).is_some(),
+            "v2_field_casing",
+            &self.v2_field_casing,
+        );
+        invalid(
+            RankerKind::parse(&self.nearby_ranking).is_ok(),
+            "nearby_ranking",
+            &self.nearby_ranking,
+        );
+        if self.auth_mode == "header" && !self.trust_identity_headers {
+            problems.push("auth_mode header requires trust_identity_headers".to_owned());
+        }
+        if self.tls_cert_file.is_empty() != self.tls_key_file.is_empty() {
+            problems.push("tls_cert_file and tls_key_file must be set together".to_owned());
+        }
+        let pool = &self.mongodb;
+        if pool.max_pool_size > 0 && pool.min_pool_size > pool.max_pool_size {
+            problems.push("mongodb.min_pool_size exceeds mongodb.max_pool_size".to_owned());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError(problems))
+        }
+    }
+
+    /// The configuration as TOML with secrets left out, for `--print-config`.
+    pub fn printable(&self) -> Result<String, toml::ser::Error> {
+        let mut config = self.clone();
+        for secret in [
+            &mut config.database_url,
+            &mut config.calendar_token_secret,
+            &mut config.calendar_token_keys,
+            &mut config.jwt_key,
+            &mut config.jwt_keys,
+            &mut config.webhook_secrets,
+            &mut config.track_archive_access_key,
+            &mut config.track_archive_secret_key,
+            &mut config.research_api_keys,
+            &mut config.internal_api_keys,
+            &mut config.redis_url,
+            &mut config.smtp_password,
+            &mut config.static_map_key,
+        ] {
+            if !secret.is_empty() {
+                *secret = REDACTED.to_owned();
+            }
+        }
+        toml::to_string_pretty(&config)
+    }
+}
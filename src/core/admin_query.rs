@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use serde::Deserialize;
+
+use super::{
+    entities::WalkRequest,
+    filter::filter_from_json,
+    repository::{Order, Pagination, SortBy, WalkRequestQuery},
+};
+
+/// Requests an ad-hoc admin query returns at most per page.
+pub const MAX_ADMIN_QUERY_SIZE: i64 = 500;
+
+/// Fields admin queries may sort on, those every repository indexes or
+/// orders by.
+pub const SORTABLE_FIELDS: [&str; 4] = [
+    "created_at",
+    "updated_at",
+    "should_start_after",
+    "should_start_before",
+];
+
+fn first_page() -> i64 {
+    1
+}
+
+fn default_size() -> i64 {
+    50
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminSort {
+    pub field: String,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// An ad-hoc query over walk requests for support and ops, e.g. started
+/// walks older than three hours in a region.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminQuery {
+    /// See `filter_from_json`.
+    #[serde(default)]
+    pub filter: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+    /// Newest first when unset.
+    pub sort: Option<AdminSort>,
+    #[serde(default = "first_page")]
+    pub page: i64,
+    #[serde(default = "default_size")]
+    pub size: i64,
+    /// Fields of the requests to return, all when empty.
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl AdminQuery {
+    pub fn walk_request_query(&self) -> Result<WalkRequestQuery, Error> {
+        filter_from_json(&self.filter)
+    }
+
+    pub fn sort_by(&self) -> Result<SortBy, Error> {
+        let Some(sort) = &self.sort else {
+            return Ok(SortBy {
+                field: WalkRequest::created_at(),
+                order: Order::Desc,
+            });
+        };
+        if !SORTABLE_FIELDS.contains(&sort.field.as_str()) {
+            return Err(Error::msg(format!("不支持按{}排序", sort.field)));
+        }
+        Ok(SortBy {
+            field: sort.field.clone(),
+            order: if sort.descending {
+                Order::Desc
+            } else {
+                Order::Asc
+            },
+        })
+    }
+
+    pub fn pagination(&self) -> Result<Pagination, Error> {
+        if self.page < 1 || !(1..=MAX_ADMIN_QUERY_SIZE).contains(&self.size) {
+            return Err(Error::msg(format!(
+                "页码必须大于0，每页数量必须在1到{}之间",
+                MAX_ADMIN_QUERY_SIZE
+            )));
+        }
+        Ok(Pagination::new(self.page, self.size))
+    }
+
+    /// The selected fields of the request, fields it has no value for are
+    /// left out.
+    pub fn project(&self, request: &WalkRequest) -> Result<serde_json::Value, Error> {
+        let mut value = serde_json::to_value(request)?;
+        if let (false, Some(object)) = (self.fields.is_empty(), value.as_object_mut()) {
+            object.retain(|field, _| self.fields.iter().any(|f| f == field));
+        }
+        Ok(value)
+    }
+}
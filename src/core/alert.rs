@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::Serialize;
+
+/// Operational events the ops team is alerted about in their chat tools.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    SlaBreach,
+    GeofenceViolation,
+    /// An accepted walker didn't start the walk in time.
+    NoShow,
+}
+
+impl AlertKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "sla_breach" => Some(AlertKind::SlaBreach),
+            "geofence_violation" => Some(AlertKind::GeofenceViolation),
+            "no_show" => Some(AlertKind::NoShow),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub region: String,
+    pub request_id: String,
+    pub message: String,
+}
+
+/// A chat channel alerts are posted to.
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn send(&self, alert: &Alert) -> Result<(), Error>;
+}
+
+struct AlertRoute {
+    /// `None` matches every kind.
+    kind: Option<AlertKind>,
+    /// `None` matches every region.
+    region: Option<String>,
+    sink: Arc<dyn AlertSink>,
+}
+
+/// Which sinks receive which alerts, by kind and region.
+#[derive(Clone, Default)]
+pub struct AlertRouter {
+    routes: Arc<Vec<AlertRoute>>,
+}
+
+impl AlertRouter {
+    /// Parses `kind@region=url` entries separated by `;`, `*` matching any
+    /// kind or region, e.g. `sla_breach@cn-east=https://hooks.slack.com/...`.
+    /// `sink` builds the sink posting to a url.
+    pub fn parse(spec: &str, sink: impl Fn(&str) -> Arc<dyn AlertSink>) -> Result<Self, Error> {
+        let mut routes = Vec::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || Error::msg(format!("无效的告警路由配置: {}", entry));
+            let (selector, url) = entry.split_once('=').ok_or_else(invalid)?;
+            let (kind, region) = selector.split_once('@').ok_or_else(invalid)?;
+            let kind = match kind.trim() {
+                "*" => None,
+                name => Some(AlertKind::parse(name).ok_or_else(invalid)?),
+            };
+            let region = match region.trim() {
+                "*" => None,
+                name => Some(name.to_owned()),
+            };
+            routes.push(AlertRoute {
+                kind,
+                region,
+                sink: sink(url.trim()),
+            });
+        }
+        Ok(Self {
+            routes: Arc::new(routes),
+        })
+    }
+
+    /// Posts `alert` to every matching sink, failures are logged.
+    pub async fn send(&self, alert: &Alert) {
+        let matching = self.routes.iter().filter(|r| {
+            r.kind.map_or(true, |k| k == alert.kind)
+                && r.region
+                    .as_ref()
+                    .map_or(true, |region| *region == alert.region)
+        });
+        for route in matching {
+            if let Err(e) = route.sink.send(alert).await {
+                log::error!(
+                    "failed to send {:?} alert of {}: {}",
+                    alert.kind,
+                    alert.request_id,
+                    e
+                );
+            }
+        }
+    }
+}
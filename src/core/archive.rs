@@ -0,0 +1,45 @@
+use std::io::{Read, Write};
+
+use anyhow::Error;
+use async_trait::async_trait;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use super::entities::WalkingLocation;
+
+/// Object storage keeping walking tracks past the tracks retention window.
+#[async_trait]
+pub trait TrackArchive: Send + Sync {
+    async fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error>;
+    /// `None` when nothing is stored under `key`.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+}
+
+pub fn track_key(walk_request_id: &str) -> String {
+    format!("tracks/{}.json.gz", walk_request_id)
+}
+
+/// Gzipped JSON array of the track's locations.
+pub fn compress(locations: &[WalkingLocation]) -> Result<Vec<u8>, Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec(locations)?)?;
+    Ok(encoder.finish()?)
+}
+
+pub fn decompress(body: &[u8]) -> Result<Vec<WalkingLocation>, Error> {
+    let mut json = Vec::new();
+    GzDecoder::new(body).read_to_end(&mut json)?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Archived and live locations of one track in recording order, locations
+/// present in both are kept once.
+pub fn merge(archived: Vec<WalkingLocation>, live: Vec<WalkingLocation>) -> Vec<WalkingLocation> {
+    let mut locations = archived;
+    for location in live {
+        if !locations.iter().any(|l| l.id == location.id) {
+            locations.push(location);
+        }
+    }
+    locations.sort_by_key(|l| l.created_at);
+    locations
+}
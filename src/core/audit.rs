@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Hash of an audit entry chained to the previous entry of the same request,
+/// so editing or removing an entry breaks every hash after it.
+pub fn chain_hash(
+    prev_hash: &str,
+    request_id: &str,
+    seq: i64,
+    kind: &str,
+    payload: &str,
+    occurred_at: DateTime<Utc>,
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [
+        prev_hash,
+        request_id,
+        &seq.to_string(),
+        kind,
+        payload,
+        &occurred_at.timestamp_millis().to_string(),
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Result of re-validating the audit chain of one walk request.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditVerification {
+    pub request_id: String,
+    pub entries: u64,
+    pub valid: bool,
+    /// First entry whose hash or link doesn't match.
+    pub first_invalid_seq: Option<i64>,
+    /// Whether entries before the first one checked were purged by retention.
+    pub truncated: bool,
+}
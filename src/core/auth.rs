@@ -0,0 +1,125 @@
+use anyhow::Error;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+
+use super::security::KeyRing;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Owner,
+    Walker,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "owner" => Some(Role::Owner),
+            "walker" => Some(Role::Walker),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Claims read from access tokens, `sub` is the user id and `tenant` the
+/// city the user signed up in.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+/// Verifies bearer tokens issued by the user service.
+#[derive(Clone)]
+pub struct JwtVerifier {
+    algorithm: Algorithm,
+    keys: KeyRing,
+    validation: Validation,
+}
+
+impl JwtVerifier {
+    /// Keys of the ring are shared secrets for HS256, or PEM encoded public
+    /// keys for RS256. Tokens naming a `kid` are checked with that key,
+    /// others with every key of the ring.
+    pub fn new(algorithm: &str, keys: KeyRing) -> Result<Self, Error> {
+        if keys.is_empty() {
+            return Err(Error::msg("JWT模式需要配置密钥"));
+        }
+        let algorithm = match algorithm {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            other => return Err(Error::msg(format!("不支持的JWT算法: {}", other))),
+        };
+        let verifier = Self {
+            algorithm,
+            keys,
+            validation: Validation::new(algorithm),
+        };
+        for (_, secret) in verifier.keys.secrets() {
+            verifier.decoding_key(&secret)?;
+        }
+        Ok(verifier)
+    }
+
+    fn decoding_key(&self, secret: &[u8]) -> Result<DecodingKey, Error> {
+        Ok(match self.algorithm {
+            Algorithm::RS256 => DecodingKey::from_rsa_pem(secret)?,
+            _ => DecodingKey::from_secret(secret),
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, Error> {
+        let secrets = match decode_header(token)?.kid {
+            Some(kid) => self.keys.get(&kid).into_iter().collect(),
+            None => self.keys.secrets().into_iter().map(|(_, s)| s).collect(),
+        };
+        let mut error = Error::msg("无效的访问令牌");
+        for secret in secrets {
+            // A malformed key rotated in at runtime must not lock out the
+            // others.
+            let Ok(key) = self.decoding_key(&secret) else {
+                continue;
+            };
+            match decode::<Claims>(token, &key, &self.validation) {
+                Ok(data) => return Ok(data.claims),
+                Err(e) => error = e.into(),
+            }
+        }
+        Err(error)
+    }
+}
+
+/// How callers are identified. Callers of servers without one aren't.
+#[derive(Clone)]
+pub enum Authenticator {
+    /// Trusts the `X-User-ID` and `X-User-Role` headers, only for internal
+    /// deployments where a gateway sets them, see `parse`.
+    Header,
+    Jwt(JwtVerifier),
+}
+
+impl Authenticator {
+    /// `mode` is `jwt` or `header`. Anyone able to reach the server can
+    /// claim any user and role in header mode, which is refused unless
+    /// `trust_identity_headers` opts in to it.
+    pub fn parse(
+        mode: &str,
+        algorithm: &str,
+        keys: KeyRing,
+        trust_identity_headers: bool,
+    ) -> Result<Self, Error> {
+        match mode {
+            "jwt" => Ok(Authenticator::Jwt(JwtVerifier::new(algorithm, keys)?)),
+            "header" if trust_identity_headers => Ok(Authenticator::Header),
+            "header" => Err(Error::msg(
+                "header mode requires TRUST_IDENTITY_HEADERS=true",
+            )),
+            other => Err(Error::msg(format!("无效的认证模式: {}", other))),
+        }
+    }
+}
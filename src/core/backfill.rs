@@ -0,0 +1,135 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{entities::WalkRequest, repository::WalkRequestUpdate, timezone::DEFAULT_TIMEZONE};
+
+/// A data migration applied walk request by walk request, for schema
+/// changes older documents and rows have to catch up with.
+pub trait Backfill: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// The change to write, `None` when the request needs none. Must return
+    /// `None` once applied, runs are resumed by going over some requests
+    /// again.
+    fn migrate(&self, request: &WalkRequest) -> Option<WalkRequestUpdate>;
+}
+
+/// Requests created before the timezone was stored get the default one.
+pub struct DefaultTimezone;
+
+impl Backfill for DefaultTimezone {
+    fn name(&self) -> &'static str {
+        "default_timezone"
+    }
+
+    fn description(&self) -> &'static str {
+        "为缺少时区的代遛请求补全默认时区"
+    }
+
+    fn migrate(&self, request: &WalkRequest) -> Option<WalkRequestUpdate> {
+        if request.timezone.is_some() {
+            return None;
+        }
+        Some(WalkRequestUpdate {
+            timezone: Some(DEFAULT_TIMEZONE.to_owned()),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillDefinition {
+    pub name: String,
+    pub description: String,
+    /// The last run, `None` when it never ran.
+    pub run: Option<BackfillRun>,
+}
+
+/// A run of a backfill, persisted after every batch so that a stopped run
+/// picks up where it left off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackfillRun {
+    pub name: String,
+    pub total: u64,
+    pub processed: u64,
+    pub changed: u64,
+    pub failed: u64,
+    /// Creation time of the last request processed, a resumed run starts
+    /// from there.
+    pub checkpoint: Option<DateTime<Utc>>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl BackfillRun {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            total: 0,
+            processed: 0,
+            changed: 0,
+            failed: 0,
+            checkpoint: None,
+            started_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+}
+
+/// What a backfill would do, nothing is written.
+#[derive(Debug, Clone, Serialize)]
+pub struct BackfillDryRun {
+    pub name: String,
+    pub total: u64,
+    pub would_change: u64,
+}
+
+/// The known backfills and how fast they go. Each one runs at most once at
+/// a time in this process.
+#[derive(Clone)]
+pub struct Backfills {
+    definitions: Arc<Vec<Arc<dyn Backfill>>>,
+    running: Arc<Mutex<HashSet<String>>>,
+    pub batch_size: i64,
+    /// Slept between batches to spare the database.
+    pub pause: Duration,
+}
+
+impl Default for Backfills {
+    fn default() -> Self {
+        Self {
+            definitions: Arc::new(vec![Arc::new(DefaultTimezone)]),
+            running: Arc::new(Mutex::new(HashSet::new())),
+            batch_size: 200,
+            pause: Duration::from_millis(500),
+        }
+    }
+}
+
+impl Backfills {
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Backfill>> {
+        self.definitions.iter().find(|d| d.name() == name).cloned()
+    }
+
+    pub fn all(&self) -> &[Arc<dyn Backfill>] {
+        &self.definitions
+    }
+
+    /// Whether the backfill was free to run, it is then taken until
+    /// `release`.
+    pub fn claim(&self, name: &str) -> bool {
+        self.running.lock().unwrap().insert(name.to_owned())
+    }
+
+    pub fn release(&self, name: &str) {
+        self.running.lock().unwrap().remove(name);
+    }
+}
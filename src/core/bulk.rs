@@ -0,0 +1,252 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{entities::WalkRequest, repository::WalkRequestUpdate};
+
+pub const DIFF_SAMPLE_SIZE: usize = 20;
+
+/// Requests one bulk update may match, a query matching more is refused.
+pub const MAX_BULK_UPDATE: i64 = 1000;
+
+#[derive(Debug, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalkRequestDiff {
+    pub id: String,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkUpdateReport {
+    pub dry_run: bool,
+    pub matched: usize,
+    pub affected_ids: Vec<String>,
+    pub diff_sample: Vec<WalkRequestDiff>,
+    pub modified: Option<u64>,
+}
+
+fn change<B: Serialize, A: Serialize>(
+    changes: &mut Vec<FieldChange>,
+    field: &'static str,
+    before: B,
+    after: A,
+) {
+    let before = json!(before);
+    let after = json!(after);
+    if before != after {
+        changes.push(FieldChange {
+            field,
+            before,
+            after,
+        });
+    }
+}
+
+/// Field-level changes `update` would make to `request`.
+pub fn diff(request: &WalkRequest, update: &WalkRequestUpdate) -> WalkRequestDiff {
+    let mut changes = Vec::new();
+    if let Some(dogs) = &update.dogs {
+        change(&mut changes, "dogs", &request.dogs, dogs);
+    }
+    if let Some(v) = &update.should_start_after {
+        change(
+            &mut changes,
+            "should_start_after",
+            request.should_start_after,
+            v,
+        );
+    }
+    if let Some(v) = &update.should_start_before {
+        change(
+            &mut changes,
+            "should_start_before",
+            request.should_start_before,
+            v,
+        );
+    }
+    if let Some(v) = &update.should_end_after {
+        change(
+            &mut changes,
+            "should_end_after",
+            request.should_end_after,
+            v,
+        );
+    }
+    if let Some(v) = &update.should_end_before {
+        change(
+            &mut changes,
+            "should_end_before",
+            request.should_end_before,
+            v,
+        );
+    }
+    if let Some(v) = &update.latitude {
+        change(&mut changes, "latitude", request.latitude, v);
+    }
+    if let Some(v) = &update.longitude {
+        change(&mut changes, "longitude", request.longitude, v);
+    }
+    if let Some(v) = &update.timezone {
+        change(&mut changes, "timezone", &request.timezone, v);
+    }
+    if let Some(v) = &update.accepted_by {
+        change(&mut changes, "accepted_by", &request.accepted_by, v);
+    }
+    if let Some(v) = &update.accepted_at {
+        change(&mut changes, "accepted_at", request.accepted_at, v);
+    }
+    if let Some(v) = &update.canceled_at {
+        change(&mut changes, "canceled_at", request.canceled_at, v);
+    }
+    if let Some(v) = &update.canceled_by {
+        change(&mut changes, "canceled_by", &request.canceled_by, v);
+    }
+    if let Some(v) = &update.cancellation_reason {
+        change(
+            &mut changes,
+            "cancellation_reason",
+            &request.cancellation_reason,
+            v,
+        );
+    }
+    if let Some(v) = &update.cancel_requested_at {
+        change(
+            &mut changes,
+            "cancel_requested_at",
+            request.cancel_requested_at,
+            v,
+        );
+    }
+    if let Some(v) = &update.started_at {
+        change(&mut changes, "started_at", request.started_at, v);
+    }
+    if let Some(v) = &update.finished_at {
+        change(&mut changes, "finished_at", request.finished_at, v);
+    }
+    if let Some(v) = &update.sla_breached_at {
+        change(&mut changes, "sla_breached_at", request.sla_breached_at, v);
+    }
+    if let Some(v) = &update.expired_at {
+        change(&mut changes, "expired_at", request.expired_at, v);
+    }
+    if let Some(v) = &update.requirements {
+        change(&mut changes, "requirements", request.requirements, v);
+    }
+    if let Some(v) = &update.deleted_at {
+        change(&mut changes, "deleted_at", request.deleted_at, v);
+    }
+    if let Some(v) = &update.geofence_violated_at {
+        change(
+            &mut changes,
+            "geofence_violated_at",
+            request.geofence_violated_at,
+            v,
+        );
+    }
+    if let Some(v) = &update.verification_waived_at {
+        change(
+            &mut changes,
+            "verification_waived_at",
+            request.verification_waived_at,
+            v,
+        );
+    }
+    if let Some(v) = &update.track_archived_at {
+        change(
+            &mut changes,
+            "track_archived_at",
+            request.track_archived_at,
+            v,
+        );
+    }
+    if let Some(v) = &update.track_downsampled_at {
+        change(
+            &mut changes,
+            "track_downsampled_at",
+            request.track_downsampled_at,
+            v,
+        );
+    }
+    if update.unset_accepted_by {
+        change(
+            &mut changes,
+            "accepted_by",
+            &request.accepted_by,
+            Value::Null,
+        );
+    }
+    if update.unset_accepted_at {
+        change(
+            &mut changes,
+            "accepted_at",
+            request.accepted_at,
+            Value::Null,
+        );
+    }
+    if update.unset_cancel_request {
+        change(
+            &mut changes,
+            "cancel_requested_at",
+            request.cancel_requested_at,
+            Value::Null,
+        );
+        change(
+            &mut changes,
+            "canceled_by",
+            &request.canceled_by,
+            Value::Null,
+        );
+        change(
+            &mut changes,
+            "cancellation_reason",
+            &request.cancellation_reason,
+            Value::Null,
+        );
+    }
+    let acceptances = request.acceptances.clone().unwrap_or_default();
+    if let Some(user_id) = &update.add_to_acceptances {
+        if !acceptances.contains(user_id) {
+            let mut after = acceptances.clone();
+            after.push(user_id.clone());
+            change(&mut changes, "acceptances", &acceptances, after);
+        }
+    }
+    if let Some(user_id) = &update.remove_from_acceptances {
+        let after: Vec<&String> = acceptances.iter().filter(|a| *a != user_id).collect();
+        change(&mut changes, "acceptances", &acceptances, after);
+    }
+    if let Some(user_id) = &update.add_to_dismissed_applicants {
+        let dismissed = request.dismissed_applicants.clone().unwrap_or_default();
+        if !dismissed.contains(user_id) {
+            let mut after = dismissed.clone();
+            after.push(user_id.clone());
+            change(&mut changes, "dismissed_applicants", &dismissed, after);
+        }
+    }
+    if let Some(user_ids) = &update.remove_all_from_acceptances {
+        let after: Vec<&String> = acceptances
+            .iter()
+            .filter(|a| !user_ids.contains(a))
+            .collect();
+        change(&mut changes, "acceptances", &acceptances, after);
+    }
+    if let Some(user_ids) = &update.add_all_to_dismissed_applicants {
+        let dismissed = request.dismissed_applicants.clone().unwrap_or_default();
+        let mut after = dismissed.clone();
+        for user_id in user_ids {
+            if !after.contains(user_id) {
+                after.push(user_id.clone());
+            }
+        }
+        change(&mut changes, "dismissed_applicants", &dismissed, after);
+    }
+    WalkRequestDiff {
+        id: request.id.clone(),
+        changes,
+    }
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Error;
+use async_trait::async_trait;
+
+use super::repository::{NearbyFilter, Pagination};
+
+/// A shared key-value store for short-lived copies of query results.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    async fn get(&self, key: &str) -> Result<Option<String>, Error>;
+    async fn set(&self, key: &str, value: &str, ttl: Duration) -> Result<(), Error>;
+    /// Increments the counter at `key`, returns the new value.
+    async fn incr(&self, key: &str) -> Result<i64, Error>;
+    /// Increments the counter at `key`, expiring `ttl` from now, returns the
+    /// new value.
+    async fn incr_with_ttl(&self, key: &str, ttl: Duration) -> Result<i64, Error>;
+}
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Cells of 7 characters are about 150 meters wide.
+const NEARBY_CELL_PRECISION: usize = 7;
+
+const NEARBY_GENERATION_KEY: &str = "nearby:generation";
+
+/// The geohash of a point with `precision` characters.
+pub fn geohash(latitude: f64, longitude: f64, precision: usize) -> String {
+    let (mut lat, mut lon) = ((-90.0, 90.0), (-180.0, 180.0));
+    let mut hash = String::with_capacity(precision);
+    let (mut bits, mut value, mut even) = (0, 0usize, true);
+    while hash.len() < precision {
+        let (range, coordinate) = if even {
+            (&mut lon, longitude)
+        } else {
+            (&mut lat, latitude)
+        };
+        let middle = (range.0 + range.1) / 2.0;
+        value <<= 1;
+        if coordinate >= middle {
+            value |= 1;
+            range.0 = middle;
+        } else {
+            range.1 = middle;
+        }
+        even = !even;
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_ALPHABET[value] as char);
+            bits = 0;
+            value = 0;
+        }
+    }
+    hash
+}
+
+/// Caches nearby searches by the geohash cell of the searched point. Every
+/// search from within a cell gets the results of the first one, distances
+/// included, until they expire or a request is created, accepted or
+/// canceled anywhere.
+#[derive(Clone)]
+pub struct NearbyCache {
+    cache: Arc<dyn Cache>,
+    ttl: Duration,
+}
+
+impl NearbyCache {
+    pub fn new(cache: Arc<dyn Cache>, ttl: Duration) -> Self {
+        Self { cache, ttl }
+    }
+
+    /// The key of a search, `None` when the cache can't be reached.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn key(
+        &self,
+        tenant: Option<&str>,
+        latitude: f64,
+        longitude: f64,
+        radius: f64,
+        walker: Option<&str>,
+        filter: &NearbyFilter,
+        pagination: Pagination,
+        cursor: Option<&str>,
+    ) -> Option<String> {
+        let generation = match self.cache.get(NEARBY_GENERATION_KEY).await {
+            Ok(generation) => generation.unwrap_or_default(),
+            Err(e) => {
+                log::error!("failed to read the nearby cache generation: {}", e);
+                return None;
+            }
+        };
+        Some(format!(
+            "nearby:{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            generation,
+            tenant.unwrap_or("-"),
+            geohash(latitude, longitude, NEARBY_CELL_PRECISION),
+            radius,
+            walker.unwrap_or("-"),
+            filter.key(),
+            pagination.page,
+            pagination.size,
+            cursor.unwrap_or("-")
+        ))
+    }
+
+    pub async fn get(&self, key: &str) -> Option<String> {
+        self.cache.get(key).await.unwrap_or_else(|e| {
+            log::error!("failed to read {} from the cache: {}", key, e);
+            None
+        })
+    }
+
+    pub async fn put(&self, key: &str, value: &str) {
+        if let Err(e) = self.cache.set(key, value, self.ttl).await {
+            log::error!("failed to write {} to the cache: {}", key, e);
+        }
+    }
+
+    /// Moves every search to new keys, the old entries expire unread.
+    pub async fn invalidate(&self) {
+        if let Err(e) = self.cache.incr(NEARBY_GENERATION_KEY).await {
+            log::error!("failed to invalidate the nearby cache: {}", e);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+
+use super::{
+    entities::WalkRequest,
+    security::{constant_time_eq, hmac_sha256, KeyRing},
+    timezone::to_local,
+};
+
+/// Signs and verifies the per-user tokens embedded in calendar subscription urls,
+/// calendar apps can't send the X-User-ID header when polling the feed.
+#[derive(Clone)]
+pub struct CalendarTokenSigner {
+    keys: KeyRing,
+}
+
+impl CalendarTokenSigner {
+    pub fn new(keys: KeyRing) -> Self {
+        Self { keys }
+    }
+
+    /// `user.signature.kid`, signed with the active key of the ring.
+    pub fn sign(&self, user_id: &str) -> String {
+        let (kid, secret) = self.keys.active().unwrap_or_default();
+        format!(
+            "{}.{}.{}",
+            hex::encode(user_id),
+            hex::encode(hmac_sha256(&secret, user_id.as_bytes())),
+            kid
+        )
+    }
+
+    /// Tokens signed before keys had ids are checked with every key.
+    pub fn verify(&self, token: &str) -> Result<String, Error> {
+        let invalid = || Error::msg("无效的日历令牌");
+        let mut parts = token.splitn(3, '.');
+        let user_id = parts.next().ok_or_else(invalid)?;
+        let signature = hex::decode(parts.next().ok_or_else(invalid)?)?;
+        let user_id = String::from_utf8(hex::decode(user_id)?)?;
+        let secrets = match parts.next() {
+            Some(kid) => self.keys.get(kid).into_iter().collect(),
+            None => self.keys.secrets().into_iter().map(|(_, s)| s).collect(),
+        };
+        secrets
+            .iter()
+            .any(|secret: &Vec<u8>| {
+                constant_time_eq(&hmac_sha256(secret, user_id.as_bytes()), &signature)
+            })
+            .then_some(user_id)
+            .ok_or_else(invalid)
+    }
+}
+
+/// How often calendar apps are asked to poll the feed.
+const REFRESH_INTERVAL: &str = "PT1H";
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_local_time(name: &str, time: &DateTime<Utc>, timezone: Option<&str>) -> String {
+    match timezone {
+        Some(tz) => format!(
+            "{};TZID={}:{}",
+            name,
+            tz,
+            to_local(time, Some(tz)).format("%Y%m%dT%H%M%S")
+        ),
+        None => format!("{}:{}", name, format_time(time)),
+    }
+}
+
+pub fn render_ics(requests: &[WalkRequest], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//little-walk//walk requests//CN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        "METHOD:PUBLISH".to_owned(),
+        "X-WR-CALNAME:遛狗".to_owned(),
+        format!("REFRESH-INTERVAL;VALUE=DURATION:{}", REFRESH_INTERVAL),
+        format!("X-PUBLISHED-TTL:{}", REFRESH_INTERVAL),
+    ];
+    for request in requests {
+        let start = request.should_start_after.or(request.should_start_before);
+        let end = request.should_end_before.or(request.should_end_after);
+        let Some(start) = start else {
+            continue;
+        };
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:{}@little-walk", request.id));
+        lines.push(format!("DTSTAMP:{}", format_time(&now)));
+        let timezone = request.timezone.as_deref();
+        lines.push(format_local_time("DTSTART", &start, timezone));
+        if let Some(end) = end {
+            lines.push(format_local_time("DTEND", &end, timezone));
+        }
+        lines.push(format!("SUMMARY:遛狗（{}只）", request.dogs.len()));
+        lines.push(format!("GEO:{};{}", request.latitude, request.longitude));
+        lines.push(format!(
+            "STATUS:{}",
+            if request.accepted_by.is_some() {
+                "CONFIRMED"
+            } else {
+                "TENTATIVE"
+            }
+        ));
+        lines.push("END:VEVENT".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+    lines.join("\r\n") + "\r\n"
+}
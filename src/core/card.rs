@@ -0,0 +1,135 @@
+use chrono::{DateTime, Utc};
+use little_walk_dog::core::entities::Dog;
+use serde::Serialize;
+
+use super::{entities::WalkingLocation, geo::WalkSummary, route_map::project};
+
+/// Most route points kept on a card, enough for a preview.
+const ROUTE_PREVIEW_POINTS: usize = 100;
+
+const CARD_WIDTH: f64 = 600.0;
+const CARD_HEIGHT: f64 = 400.0;
+const ROUTE_MARGIN: f64 = 20.0;
+const ROUTE_HEIGHT: f64 = 260.0;
+
+/// What a finished walk looks like when shared.
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryCard {
+    pub request_id: String,
+    pub dog_names: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub summary: WalkSummary,
+    /// The track thinned out to at most `ROUTE_PREVIEW_POINTS`, as
+    /// `[longitude, latitude]`.
+    pub route: Vec<[f64; 2]>,
+}
+
+/// Names of the dogs which have one, dogs are owned by the dog service so
+/// their fields are read loosely.
+fn dog_names(dogs: &[Dog]) -> Vec<String> {
+    dogs.iter()
+        .filter_map(|d| {
+            serde_json::to_value(d)
+                .ok()?
+                .get("name")?
+                .as_str()
+                .map(str::to_owned)
+        })
+        .collect()
+}
+
+/// The track thinned out to at most `ROUTE_PREVIEW_POINTS`, in recording
+/// order, keeping the last point.
+pub fn preview_route(locations: &[WalkingLocation]) -> Vec<[f64; 2]> {
+    let mut points: Vec<&WalkingLocation> = locations.iter().collect();
+    points.sort_by_key(|l| l.created_at);
+    let step = points.len().div_ceil(ROUTE_PREVIEW_POINTS).max(1);
+    let mut route: Vec<[f64; 2]> = points
+        .iter()
+        .step_by(step)
+        .map(|l| [l.longitude, l.latitude])
+        .collect();
+    if let Some(last) = points.last() {
+        if points.len() > 1 && (points.len() - 1) % step != 0 {
+            route.push([last.longitude, last.latitude]);
+        }
+    }
+    route
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl SummaryCard {
+    pub fn new(
+        request_id: &str,
+        dogs: &[Dog],
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        summary: WalkSummary,
+        locations: &[WalkingLocation],
+    ) -> Self {
+        Self {
+            request_id: request_id.to_owned(),
+            dog_names: dog_names(dogs),
+            started_at,
+            finished_at,
+            summary,
+            route: preview_route(locations),
+        }
+    }
+
+    /// The route scaled into the card's route area, north up.
+    fn route_path(&self) -> String {
+        project(
+            &self.route,
+            CARD_WIDTH - 2.0 * ROUTE_MARGIN,
+            ROUTE_HEIGHT,
+            ROUTE_MARGIN,
+        )
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    pub fn svg(&self) -> String {
+        let minutes = self.summary.duration_seconds / 60;
+        let lines = [
+            format!(
+                "{:.2} 公里 · {}小时{}分",
+                self.summary.distance.kilometers(),
+                minutes / 60,
+                minutes % 60
+            ),
+            self.dog_names.join("、"),
+        ];
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" \
+             viewBox=\"0 0 {w} {h}\">\n<rect width=\"{w}\" height=\"{h}\" fill=\"#ffffff\"/>\n",
+            w = CARD_WIDTH,
+            h = CARD_HEIGHT
+        );
+        if self.route.len() > 1 {
+            svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"#ff7a00\" stroke-width=\"4\" \
+                 stroke-linejoin=\"round\" stroke-linecap=\"round\"/>\n",
+                self.route_path()
+            ));
+        }
+        for (i, line) in lines.iter().filter(|l| !l.is_empty()).enumerate() {
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"24\" fill=\"#333333\">{}</text>\n",
+                ROUTE_MARGIN,
+                2.0 * ROUTE_MARGIN + ROUTE_HEIGHT + 32.0 * (i as f64 + 1.0),
+                escape(line)
+            ));
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
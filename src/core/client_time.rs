@@ -0,0 +1,63 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::error::ServiceError;
+
+/// How far ahead of the server a client's clock may run before its
+/// timestamps are taken as skewed.
+pub const MAX_CLOCK_AHEAD_SECONDS: i64 = 60;
+
+/// How long after the fact walks recorded offline may still be synced.
+pub const MAX_SYNC_DELAY_HOURS: i64 = 72;
+
+pub const TOO_LATE_TO_SYNC: &str = "事件发生过早，已超出离线同步期限";
+
+/// When a walk event happened by the client's clock, settled against the
+/// server's clock when the call came in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientTime {
+    /// What is recorded as the time of the event.
+    pub occurred_at: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+    /// The claimed time was out of bounds and clamped, see `settle`.
+    pub skewed: bool,
+}
+
+impl ClientTime {
+    /// Settles the time the client claims, the receive time without one.
+    /// Times ahead of the receive time are clamped to it, skewed when
+    /// beyond `MAX_CLOCK_AHEAD_SECONDS`. Times before `earliest`, the
+    /// previous step of the walk, are clamped to it and skewed. Times
+    /// older than `MAX_SYNC_DELAY_HOURS` are refused.
+    pub fn settle(
+        claimed: Option<DateTime<Utc>>,
+        earliest: Option<DateTime<Utc>>,
+        received_at: DateTime<Utc>,
+    ) -> Result<Self, ServiceError> {
+        let Some(claimed) = claimed else {
+            return Ok(Self {
+                occurred_at: received_at,
+                received_at,
+                skewed: false,
+            });
+        };
+        if claimed < received_at - Duration::hours(MAX_SYNC_DELAY_HOURS) {
+            return Err(ServiceError::Validation(TOO_LATE_TO_SYNC.to_owned()));
+        }
+        let mut occurred_at = claimed.min(received_at);
+        let mut skewed = claimed > received_at + Duration::seconds(MAX_CLOCK_AHEAD_SECONDS);
+        if let Some(earliest) = earliest.filter(|earliest| occurred_at < *earliest) {
+            occurred_at = earliest.min(received_at);
+            skewed = true;
+        }
+        Ok(Self {
+            occurred_at,
+            received_at,
+            skewed,
+        })
+    }
+
+    /// `clock_skew_detected_at` of the update recording the event.
+    pub fn skew_detected_at(&self) -> Option<DateTime<Utc>> {
+        self.skewed.then_some(self.received_at)
+    }
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+
+/// A value a field is compared with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// A record id, which stores may keep in a native id type.
+    Id(String),
+    Text(String),
+    Int(i64),
+    Bool(bool),
+    Time(DateTime<Utc>),
+    Null,
+}
+
+impl From<String> for Operand {
+    fn from(value: String) -> Self {
+        Operand::Text(value)
+    }
+}
+
+impl From<i64> for Operand {
+    fn from(value: i64) -> Self {
+        Operand::Int(value)
+    }
+}
+
+impl From<bool> for Operand {
+    fn from(value: bool) -> Self {
+        Operand::Bool(value)
+    }
+}
+
+impl From<DateTime<Utc>> for Operand {
+    fn from(value: DateTime<Utc>) -> Self {
+        Operand::Time(value)
+    }
+}
+
+/// How a field is matched. On array fields `Eq`, `Ne` and `In` look at the
+/// elements.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Eq(Operand),
+    Ne(Operand),
+    In(Vec<Operand>),
+    /// Arrays holding every operand.
+    All(Vec<Operand>),
+    /// `true` also matches missing fields.
+    IsNull(bool),
+    /// Bounds left unset aren't checked.
+    Range {
+        gt: Option<Operand>,
+        gte: Option<Operand>,
+        lt: Option<Operand>,
+        lte: Option<Operand>,
+    },
+    /// Points within `max_distance` meters, farther than `min_distance`
+    /// when set, nearest first.
+    GeoNear {
+        longitude: f64,
+        latitude: f64,
+        max_distance: f64,
+        min_distance: Option<f64>,
+    },
+}
+
+impl Filter {
+    /// The range of the bounds which are set, `None` without any.
+    pub fn range<T: Into<Operand>>(
+        gt: Option<T>,
+        gte: Option<T>,
+        lt: Option<T>,
+        lte: Option<T>,
+    ) -> Option<Self> {
+        if gt.is_none() && gte.is_none() && lt.is_none() && lte.is_none() {
+            return None;
+        }
+        Some(Filter::Range {
+            gt: gt.map(Into::into),
+            gte: gte.map(Into::into),
+            lt: lt.map(Into::into),
+            lte: lte.map(Into::into),
+        })
+    }
+}
+
+/// A filter on one field, fields of embedded records are dotted, array
+/// elements are addressed by index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: String,
+    pub filter: Filter,
+}
+
+impl Condition {
+    pub fn new(field: impl Into<String>, filter: Filter) -> Self {
+        Self {
+            field: field.into(),
+            filter,
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::tenant::Tenant;
+
+/// Which currency prices are charged in. A tenant's own currency wins over
+/// the region's, regions without one use `default`.
+#[derive(Debug, Clone)]
+pub struct CurrencyZones {
+    default: String,
+    regions: BTreeMap<String, String>,
+}
+
+impl Default for CurrencyZones {
+    fn default() -> Self {
+        Self {
+            default: "CNY".to_owned(),
+            regions: BTreeMap::new(),
+        }
+    }
+}
+
+impl CurrencyZones {
+    /// Parses `region:CODE,region:CODE`, e.g. `hk:HKD,tokyo:JPY`.
+    pub fn parse(default: &str, spec: &str) -> Result<Self, Error> {
+        let mut regions = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (region, currency) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的货币区域配置: {}", entry)))?;
+            regions.insert(region.trim().to_owned(), currency.trim().to_uppercase());
+        }
+        Ok(Self {
+            default: default.trim().to_uppercase(),
+            regions,
+        })
+    }
+
+    pub fn default_currency(&self) -> &str {
+        &self.default
+    }
+
+    pub fn currency_for(&self, tenant: Option<&Tenant>, region: Option<&str>) -> &str {
+        tenant
+            .and_then(|t| t.currency.as_deref())
+            .or_else(|| region.and_then(|r| self.regions.get(r)).map(String::as_str))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Units of `to` per unit of `from`, as known at `as_of`. Kept alongside
+/// converted amounts so they can be explained later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+    pub as_of: DateTime<Utc>,
+}
+
+impl fmt::Display for ExchangeRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "1 {} = {:.4} {}", self.from, self.rate, self.to)
+    }
+}
+
+#[async_trait]
+pub trait RatesProvider: Send + Sync {
+    async fn rate(&self, from: &str, to: &str) -> Result<ExchangeRate, Error>;
+}
+
+/// Rates fixed at startup, for deployments without a rates feed.
+#[derive(Debug, Clone, Default)]
+pub struct FixedRates {
+    rates: BTreeMap<(String, String), f64>,
+    as_of: DateTime<Utc>,
+}
+
+impl FixedRates {
+    /// Parses `FROM:TO=rate,FROM:TO=rate`, e.g. `USD:CNY=7.2`. The inverse of
+    /// every pair is available too.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut rates = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || Error::msg(format!("无效的汇率配置: {}", entry));
+            let (pair, rate) = entry.split_once('=').ok_or_else(invalid)?;
+            let (from, to) = pair.split_once(':').ok_or_else(invalid)?;
+            let rate: f64 = rate.trim().parse()?;
+            if rate <= 0.0 {
+                return Err(invalid());
+            }
+            let (from, to) = (from.trim().to_uppercase(), to.trim().to_uppercase());
+            rates.insert((to.clone(), from.clone()), 1.0 / rate);
+            rates.insert((from, to), rate);
+        }
+        Ok(Self {
+            rates,
+            as_of: Utc::now(),
+        })
+    }
+}
+
+#[async_trait]
+impl RatesProvider for FixedRates {
+    async fn rate(&self, from: &str, to: &str) -> Result<ExchangeRate, Error> {
+        let rate = if from == to {
+            1.0
+        } else {
+            *self
+                .rates
+                .get(&(from.to_owned(), to.to_owned()))
+                .ok_or_else(|| Error::msg(format!("缺少汇率: {} -> {}", from, to)))?
+        };
+        Ok(ExchangeRate {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            rate,
+            as_of: self.as_of,
+        })
+    }
+}
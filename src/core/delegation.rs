@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::security::find_key;
+
+/// Internal services allowed to act for users, by API key.
+#[derive(Debug, Clone, Default)]
+pub struct ServiceClients {
+    names: HashMap<String, String>,
+}
+
+impl ServiceClients {
+    /// Parses `name:key,name:key`, e.g. `concierge:s3cr3t`.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut names = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, key) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的服务密钥配置: {}", entry)))?;
+            names.insert(key.trim().to_owned(), name.trim().to_owned());
+        }
+        Ok(Self { names })
+    }
+
+    /// Name of the client holding `key`.
+    pub fn client(&self, key: &str) -> Option<&str> {
+        find_key(&self.names, key).map(String::as_str)
+    }
+}
+
+/// An internal service acting for a user, recorded on what it changes.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct Delegation {
+    pub client: String,
+    pub on_behalf_of: String,
+}
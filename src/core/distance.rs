@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{
+    entities::WalkingLocation,
+    geo::track_length,
+    routing::{snap, RoutingProvider},
+    units::Meters,
+};
+
+/// Points averaged on each side of a location by `Smoothed`.
+const SMOOTHING_RADIUS: usize = 2;
+
+/// How the distance of a walk is computed from its track.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceStrategy {
+    /// Straight segments between the points as recorded.
+    #[default]
+    Raw,
+    /// Points averaged with their neighbours first, GPS jitter otherwise
+    /// adds up to distance not walked.
+    Smoothed,
+    /// Points snapped to the path network first.
+    MapMatched,
+}
+
+impl DistanceStrategy {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "raw" => Ok(DistanceStrategy::Raw),
+            "smoothed" => Ok(DistanceStrategy::Smoothed),
+            "map_matched" => Ok(DistanceStrategy::MapMatched),
+            other => Err(Error::msg(format!("无效的距离计算方式: {}", other))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceStrategy::Raw => "raw",
+            DistanceStrategy::Smoothed => "smoothed",
+            DistanceStrategy::MapMatched => "map_matched",
+        }
+    }
+}
+
+#[async_trait]
+pub trait DistanceCalculator: Send + Sync {
+    fn strategy(&self) -> DistanceStrategy;
+    async fn distance(&self, track: &[WalkingLocation]) -> Result<Meters, Error>;
+}
+
+pub struct Haversine;
+
+#[async_trait]
+impl DistanceCalculator for Haversine {
+    fn strategy(&self) -> DistanceStrategy {
+        DistanceStrategy::Raw
+    }
+
+    async fn distance(&self, track: &[WalkingLocation]) -> Result<Meters, Error> {
+        Ok(track_length(track))
+    }
+}
+
+/// Moving average over `SMOOTHING_RADIUS` points on each side.
+pub struct Smoothed;
+
+#[async_trait]
+impl DistanceCalculator for Smoothed {
+    fn strategy(&self) -> DistanceStrategy {
+        DistanceStrategy::Smoothed
+    }
+
+    async fn distance(&self, track: &[WalkingLocation]) -> Result<Meters, Error> {
+        let mut points: Vec<&WalkingLocation> = track.iter().collect();
+        points.sort_by_key(|l| l.created_at);
+        let smoothed: Vec<WalkingLocation> = (0..points.len())
+            .map(|i| {
+                let window = &points[i.saturating_sub(SMOOTHING_RADIUS)
+                    ..(i + SMOOTHING_RADIUS + 1).min(points.len())];
+                let n = window.len() as f64;
+                WalkingLocation {
+                    longitude: window.iter().map(|l| l.longitude).sum::<f64>() / n,
+                    latitude: window.iter().map(|l| l.latitude).sum::<f64>() / n,
+                    ..points[i].clone()
+                }
+            })
+            .collect();
+        Ok(track_length(&smoothed))
+    }
+}
+
+pub struct MapMatched {
+    routing: Arc<dyn RoutingProvider>,
+}
+
+impl MapMatched {
+    pub fn new(routing: Arc<dyn RoutingProvider>) -> Self {
+        Self { routing }
+    }
+}
+
+#[async_trait]
+impl DistanceCalculator for MapMatched {
+    fn strategy(&self) -> DistanceStrategy {
+        DistanceStrategy::MapMatched
+    }
+
+    async fn distance(&self, track: &[WalkingLocation]) -> Result<Meters, Error> {
+        let matched = self.routing.match_track(track).await?;
+        Ok(track_length(&snap(track.to_vec(), matched)))
+    }
+}
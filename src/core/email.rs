@@ -0,0 +1,118 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use handlebars::Handlebars;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailTemplate {
+    Receipt,
+    MonthlyReport,
+    DisputeUpdate,
+    AccountNotice,
+}
+
+impl EmailTemplate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::Receipt => "receipt",
+            EmailTemplate::MonthlyReport => "monthly_report",
+            EmailTemplate::DisputeUpdate => "dispute_update",
+            EmailTemplate::AccountNotice => "account_notice",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+}
+
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), Error>;
+}
+
+pub const DEFAULT_LOCALE: &str = "zh-CN";
+
+#[rustfmt::skip]
+const TEMPLATES: &[(&str, &str, &str)] = &[
+    ("zh-CN", "receipt", include_str!("../../templates/email/zh-CN/receipt.hbs")),
+    ("zh-CN", "monthly_report", include_str!("../../templates/email/zh-CN/monthly_report.hbs")),
+    ("zh-CN", "dispute_update", include_str!("../../templates/email/zh-CN/dispute_update.hbs")),
+    ("zh-CN", "account_notice", include_str!("../../templates/email/zh-CN/account_notice.hbs")),
+    ("en-US", "receipt", include_str!("../../templates/email/en-US/receipt.hbs")),
+    ("en-US", "monthly_report", include_str!("../../templates/email/en-US/monthly_report.hbs")),
+    ("en-US", "dispute_update", include_str!("../../templates/email/en-US/dispute_update.hbs")),
+    ("en-US", "account_notice", include_str!("../../templates/email/en-US/account_notice.hbs")),
+];
+
+/// Renders the localized email templates. Each template file starts with a
+/// `subject:` line followed by the html body.
+pub struct EmailRenderer {
+    registry: Handlebars<'static>,
+}
+
+impl EmailRenderer {
+    pub fn new() -> Result<Self, Error> {
+        let mut registry = Handlebars::new();
+        registry.set_strict_mode(true);
+        for (locale, name, source) in TEMPLATES {
+            registry.register_template_string(&format!("{}/{}", locale, name), source)?;
+        }
+        Ok(Self { registry })
+    }
+
+    pub fn render<T: Serialize>(
+        &self,
+        template: EmailTemplate,
+        locale: &str,
+        data: &T,
+    ) -> Result<(String, String), Error> {
+        let key = format!("{}/{}", locale, template.name());
+        let key = if self.registry.has_template(&key) {
+            key
+        } else {
+            format!("{}/{}", DEFAULT_LOCALE, template.name())
+        };
+        let rendered = self.registry.render(&key, data)?;
+        let (subject, html) = rendered
+            .split_once('\n')
+            .ok_or(Error::msg("邮件模板缺少标题"))?;
+        let subject = subject
+            .strip_prefix("subject:")
+            .ok_or(Error::msg("邮件模板缺少标题"))?
+            .trim()
+            .to_owned();
+        Ok((subject, html.to_owned()))
+    }
+}
+
+pub struct Emailer {
+    renderer: EmailRenderer,
+    sender: Box<dyn EmailSender>,
+}
+
+impl Emailer {
+    pub fn new(renderer: EmailRenderer, sender: Box<dyn EmailSender>) -> Self {
+        Self { renderer, sender }
+    }
+
+    pub async fn send<T: Serialize>(
+        &self,
+        to: &str,
+        template: EmailTemplate,
+        locale: &str,
+        data: &T,
+    ) -> Result<(), Error> {
+        let (subject, html) = self.renderer.render(template, locale, data)?;
+        self.sender
+            .send(EmailMessage {
+                to: to.to_owned(),
+                subject,
+                html,
+            })
+            .await
+    }
+}
@@ -2,10 +2,19 @@ use chrono::{DateTime, Utc};
 use little_walk_dog::core::entities::Dog;
 use nb_field_names::FieldNames;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Serialize, FieldNames, Default)]
+use super::{
+    geo::WalkSummary,
+    profile::UserProfile,
+    units::{Meters, Money},
+    walker_capabilities::DogRequirements,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize, FieldNames, Default, ToSchema)]
 pub struct WalkRequest {
     pub id: String,
+    #[schema(value_type = Vec<Object>)]
     pub dogs: Vec<Dog>,
     pub should_start_after: Option<DateTime<Utc>>,
     pub should_start_before: Option<DateTime<Utc>>,
@@ -13,22 +22,292 @@ pub struct WalkRequest {
     pub should_end_before: Option<DateTime<Utc>>,
     pub latitude: f64,
     pub longitude: f64,
-    pub distance: Option<f64>,
+    pub timezone: Option<String>,
+    pub region: Option<String>,
+    /// The city the request was posted in, see `Service::for_tenant`. Unset
+    /// for requests posted without a tenant.
+    pub tenant_id: Option<String>,
+    /// Human-friendly reference for other systems, see `IdGenerator`. Unset
+    /// for requests created before references.
+    pub reference_code: Option<String>,
+    /// Labels the owner chose, such as `morning` or `park-route`, see
+    /// `tag::normalize_tags`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub distance: Option<Meters>,
     pub canceled_at: Option<DateTime<Utc>>,
+    pub canceled_by: Option<String>,
+    pub cancellation_reason: Option<String>,
+    /// Set while an owner's cancellation can still be undone, `canceled_at`
+    /// follows once the undo window closes.
+    pub cancel_requested_at: Option<DateTime<Utc>>,
     pub accepted_by: Option<String>,
     pub accepted_at: Option<DateTime<Utc>>,
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
-    pub status: String,
+    pub status: WalkRequestStatus,
+    pub created_by: Option<String>,
     pub acceptances: Option<Vec<String>>,
+    pub dismissed_applicants: Option<Vec<String>>,
+    pub max_applicants: Option<i64>,
+    /// Applications are refused from then on.
+    pub apply_before: Option<DateTime<Utc>>,
+    /// Applications `max_applicants` still leaves room for, unset when the
+    /// request has no cap of its own.
+    pub remaining_slots: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    pub time_to_accept_seconds: Option<i64>,
+    pub sla_breached_at: Option<DateTime<Utc>>,
+    pub expired_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    /// Set once locations of the track were moved to the track archive.
+    pub track_archived_at: Option<DateTime<Utc>>,
+    /// Set once the track was thinned out by the downsampling job.
+    pub track_downsampled_at: Option<DateTime<Utc>>,
+    /// Cached once the walk finished and its summary was first requested.
+    pub summary: Option<WalkSummary>,
+    /// Soft-deleted, such requests are left out of queries unless asked for.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// How far from the pickup point the walker may go before the owner is
+    /// alerted.
+    pub max_radius: Option<Meters>,
+    /// When the walker first went beyond `max_radius`.
+    pub geofence_violated_at: Option<DateTime<Utc>>,
+    /// When the owner let the walker start and finish the walk without the
+    /// proximity and sanity checks, see `WalkVerification`.
+    pub verification_waived_at: Option<DateTime<Utc>>,
+    /// When the server got the start and finish, `started_at` and
+    /// `finished_at` may be earlier for walks synced after the fact.
+    pub start_received_at: Option<DateTime<Utc>>,
+    pub finish_received_at: Option<DateTime<Utc>>,
+    /// When a start, finish or location came with a time out of bounds,
+    /// see `ClientTime::settle`.
+    pub clock_skew_detected_at: Option<DateTime<Utc>>,
+    /// A favorite walker of the owner the request was sent to directly, see
+    /// `FavoriteWalker`. Only they see it until `invitation_expires_at`.
+    pub invited_walker_id: Option<String>,
+    /// When the request opens to every walker, brought forward when the
+    /// invited walker declines.
+    pub invitation_expires_at: Option<DateTime<Utc>>,
+    /// What the owner pays once the walk is finished.
+    pub price: Option<Money>,
+    /// Walkers sharing the walk, a pool walk when more than one.
+    pub max_walkers: Option<i64>,
+    /// Who joined a pool walk, in the order they accepted. The request is
+    /// accepted once the pool is full, started with the first walker and
+    /// finished with the last, `accepted_by` is the first walker.
+    #[serde(default)]
+    pub pool_walkers: Vec<PoolWalker>,
+    /// Bumped by every update, see `WalkRequestQuery::version`.
+    #[serde(default)]
+    pub version: i64,
+    /// Messages from the other party the viewer hasn't read, only set in
+    /// responses to the owner or the walker. Not stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unread_messages: Option<u64>,
+    /// Of `created_by`, only set in nearby results. Not stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner_profile: Option<UserProfile>,
+    /// Of `accepted_by`, only set in nearby results. Not stored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub walker_profile: Option<UserProfile>,
     pub created_at: Option<DateTime<Utc>>,
     pub updated_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Deserialize, Serialize, FieldNames, Default)]
+/// Most walkers a pool walk can be shared by.
+pub const MAX_POOL_WALKERS: i64 = 5;
+
+/// A walker of a pool walk, who starts and finishes on their own.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, ToSchema)]
+pub struct PoolWalker {
+    pub walker_id: String,
+    pub accepted_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Who may see the recorded walking track of a request, chosen by the owner.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+pub enum TrackVisibility {
+    OwnerOnly,
+    #[default]
+    OwnerAndWalker,
+    /// Anyone, so the track can be attached to public reviews.
+    Public,
+}
+
+impl TrackVisibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackVisibility::OwnerOnly => "OwnerOnly",
+            TrackVisibility::OwnerAndWalker => "OwnerAndWalker",
+            TrackVisibility::Public => "Public",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+pub enum WalkRequestStatus {
+    #[default]
+    Waiting,
+    Accepted,
+    Started,
+    Finished,
+    Canceled,
+    Expired,
+    /// Canceled by the owner, who may still undo it.
+    PendingCancel,
+}
+
+impl WalkRequestStatus {
+    /// Waiting → Accepted → Started → Finished. An accepter can step back out
+    /// before the walk starts, and a walk can only be canceled before it starts.
+    /// Owner cancellations may pass through PendingCancel, from where they are
+    /// undone or finalized. Requests nobody accepted before their start window
+    /// closed expire.
+    pub fn can_transition_to(&self, next: WalkRequestStatus) -> bool {
+        use WalkRequestStatus::*;
+        matches!(
+            (self, next),
+            (Waiting, Accepted)
+                | (Waiting, Canceled)
+                | (Waiting, PendingCancel)
+                | (Waiting, Expired)
+                | (Accepted, Started)
+                | (Accepted, Waiting)
+                | (Accepted, Canceled)
+                | (Accepted, PendingCancel)
+                | (PendingCancel, Canceled)
+                | (PendingCancel, Waiting)
+                | (PendingCancel, Accepted)
+                | (Started, Finished)
+        )
+    }
+}
+
+impl WalkRequest {
+    /// Changes whenever the request is updated, as milliseconds since the
+    /// epoch of its last update. Unlike `version` it also orders requests
+    /// by recency, long polls compare against it.
+    pub fn revision(&self) -> i64 {
+        self.updated_at
+            .or(self.created_at)
+            .map_or(0, |t| t.timestamp_millis())
+    }
+
+    /// Applications `max_applicants` still leaves room for.
+    pub fn open_slots(&self) -> Option<i64> {
+        let applied = self.acceptances.as_ref().map_or(0, |a| a.len() as i64);
+        self.max_applicants.map(|max| (max - applied).max(0))
+    }
+
+    /// Whether more than one walker shares the walk.
+    pub fn is_pool(&self) -> bool {
+        self.max_walkers.map_or(false, |m| m > 1)
+    }
+
+    /// Whether `user` is the walker, or one of the walkers of a pool walk.
+    pub fn walked_by(&self, user: &str) -> bool {
+        self.accepted_by.as_deref() == Some(user)
+            || self.pool_walkers.iter().any(|w| w.walker_id == user)
+    }
+
+    /// The walker, or the walkers of a pool walk.
+    pub fn walkers(&self) -> Vec<&str> {
+        if self.is_pool() {
+            self.pool_walkers
+                .iter()
+                .map(|w| w.walker_id.as_str())
+                .collect()
+        } else {
+            self.accepted_by.as_deref().into_iter().collect()
+        }
+    }
+
+    /// Whether the request is still reserved for its invited walker at `at`.
+    pub fn invitation_running(&self, at: DateTime<Utc>) -> bool {
+        self.invited_walker_id.is_some() && self.invitation_expires_at.map_or(false, |e| e > at)
+    }
+
+    /// Whether `viewer`, `None` when anonymous, may see the walking track.
+    pub fn track_visible_to(&self, viewer: Option<&str>) -> bool {
+        let is = |user: &Option<String>| viewer.is_some() && user.as_deref() == viewer;
+        let walks = viewer.map_or(false, |v| self.walked_by(v));
+        match self.track_visibility {
+            TrackVisibility::OwnerOnly => is(&self.created_by),
+            TrackVisibility::OwnerAndWalker => is(&self.created_by) || walks,
+            TrackVisibility::Public => true,
+        }
+    }
+
+    /// Which timestamps are null (`true`) or set (`false`) for requests in
+    /// `status`, the storage-side form of `derive_status`.
+    pub fn status_conditions(status: WalkRequestStatus) -> Vec<(&'static str, bool)> {
+        let order = [
+            ("canceled_at", WalkRequestStatus::Canceled),
+            ("expired_at", WalkRequestStatus::Expired),
+            ("cancel_requested_at", WalkRequestStatus::PendingCancel),
+            ("finished_at", WalkRequestStatus::Finished),
+            ("started_at", WalkRequestStatus::Started),
+            ("accepted_at", WalkRequestStatus::Accepted),
+        ];
+        let mut conditions = Vec::new();
+        for (field, field_status) in order {
+            if field_status == status {
+                conditions.push((field, false));
+                return conditions;
+            }
+            conditions.push((field, true));
+        }
+        conditions
+    }
+
+    /// Same derivation as the MongoDB projection's `status` field.
+    pub fn derive_status(&self) -> WalkRequestStatus {
+        if self.canceled_at.is_some() {
+            WalkRequestStatus::Canceled
+        } else if self.expired_at.is_some() {
+            WalkRequestStatus::Expired
+        } else if self.cancel_requested_at.is_some() {
+            WalkRequestStatus::PendingCancel
+        } else if self.finished_at.is_some() {
+            WalkRequestStatus::Finished
+        } else if self.started_at.is_some() {
+            WalkRequestStatus::Started
+        } else if self.accepted_at.is_some() {
+            WalkRequestStatus::Accepted
+        } else {
+            WalkRequestStatus::Waiting
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FieldNames, Default, ToSchema)]
 pub struct WalkingLocation {
     pub id: String,
     pub request_id: String,
     pub longitude: f64,
     pub latitude: f64,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ApplicationState {
+    Pending,
+    Withdrawn,
+    Dismissed,
+    Assigned,
+    Expired,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, FieldNames)]
+pub struct Application {
+    pub request_id: String,
+    pub applicant_id: String,
+    pub state: ApplicationState,
+    pub applied_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
 }
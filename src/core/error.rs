@@ -0,0 +1,109 @@
+use std::fmt;
+
+use super::{
+    correlation,
+    feature_flags::Feature,
+    i18n::{self, Locale},
+    onboarding::OnboardingStep,
+    spending::MonthlySpending,
+    validation::FieldError,
+    walker_capabilities::CapabilityMismatch,
+};
+
+/// Errors returned by `Service` and `Repository`, classified so the HTTP
+/// layer can tell a missing request from a rejected operation or a fault.
+#[derive(Debug)]
+pub enum ServiceError {
+    NotFound(String),
+    Conflict(String),
+    Validation(String),
+    /// A payload failed validation, field by field.
+    InvalidFields(Vec<FieldError>),
+    Unauthorized(String),
+    /// The walker still has onboarding steps to finish.
+    OnboardingIncomplete(Vec<OnboardingStep>),
+    /// The walker can't handle the dogs of the request.
+    CapabilityMismatch(Vec<CapabilityMismatch>),
+    /// The feature is turned off, or not rolled out to the user yet.
+    FeatureDisabled(Feature),
+    /// The quote takes the owner over their monthly spending cap, they have
+    /// to confirm it.
+    SpendingCapExceeded(MonthlySpending),
+    /// The write was shed under load, it may be retried after this long.
+    Overloaded(std::time::Duration),
+    Internal(anyhow::Error),
+}
+
+impl ServiceError {
+    /// A fault, naming the call it happened in when there is one, see
+    /// `correlation::current`.
+    pub fn internal(e: impl Into<anyhow::Error>) -> Self {
+        let e = e.into();
+        ServiceError::Internal(match correlation::current() {
+            Some(request_id) => e.context(format!("request {}", request_id)),
+            None => e,
+        })
+    }
+
+    /// Machine readable kind, used as the `code` of error responses.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServiceError::NotFound(_) => "not_found",
+            ServiceError::Conflict(_) => "conflict",
+            ServiceError::Validation(_) | ServiceError::InvalidFields(_) => "validation",
+            ServiceError::Unauthorized(_) => "unauthorized",
+            ServiceError::OnboardingIncomplete(_) => "onboarding_incomplete",
+            ServiceError::CapabilityMismatch(_) => "capability_mismatch",
+            ServiceError::FeatureDisabled(_) => "feature_disabled",
+            ServiceError::SpendingCapExceeded(_) => "spending_cap_exceeded",
+            ServiceError::Overloaded(_) => "overloaded",
+            ServiceError::Internal(_) => "internal",
+        }
+    }
+
+    /// Finer than `code`, names the exact failure for messages of the
+    /// catalog, e.g. `applicants_full`.
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            ServiceError::Internal(_) => None,
+            e => i18n::reason(&e.to_string()),
+        }
+    }
+
+    /// The message shown to clients, internal faults are not disclosed.
+    pub fn message(&self, locale: Locale) -> String {
+        let message = match self {
+            ServiceError::Internal(_) => "服务器内部错误".to_owned(),
+            e => e.to_string(),
+        };
+        i18n::localize(&message, self.code(), locale)
+    }
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::NotFound(msg)
+            | ServiceError::Conflict(msg)
+            | ServiceError::Validation(msg)
+            | ServiceError::Unauthorized(msg) => write!(f, "{}", msg),
+            ServiceError::InvalidFields(_) => write!(f, "请求参数有误"),
+            ServiceError::OnboardingIncomplete(_) => write!(f, "请先完成入职流程"),
+            ServiceError::CapabilityMismatch(_) => write!(f, "您的接单能力不满足该代遛请求的要求"),
+            ServiceError::FeatureDisabled(_) => write!(f, "该功能暂未开放"),
+            ServiceError::SpendingCapExceeded(_) => {
+                write!(f, "报价超出本月消费上限，请确认后重试")
+            }
+            ServiceError::Overloaded(_) => write!(f, "服务繁忙，请稍后重试"),
+            ServiceError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+impl From<anyhow::Error> for ServiceError {
+    fn from(e: anyhow::Error) -> Self {
+        ServiceError::Internal(e)
+    }
+}
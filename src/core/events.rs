@@ -0,0 +1,80 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::experiments::Exposure;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum WalkRequestEventKind {
+    Created,
+    /// A walker applied, the owner picks among applicants.
+    Applied,
+    /// An applicant took their application back.
+    Withdrawn,
+    /// The accepted walker gave the walk up before starting it, the request
+    /// is open again.
+    Resigned,
+    /// The walk was taken, by a walker or by the owner assigning the walker
+    /// in `subjects`.
+    Accepted,
+    /// The owner dismissed the accepted walker, in `subjects`.
+    Dismissed,
+    Started,
+    Finished,
+    Canceled,
+    /// The owner canceled, the walk is off unless they undo it in time.
+    CancelRequested,
+    CancelUndone,
+    /// The owner dismissed applicants, listed in `subjects`, in one go.
+    ApplicantsDismissed,
+    /// Soft-deleted by an admin.
+    Deleted,
+    /// The walker went beyond the request's `max_radius`, reported once.
+    GeofenceViolated,
+    /// The accepted walker never started the walk and was dismissed, the
+    /// request is open again unless its start window closed, see
+    /// `Expired`.
+    NoShow,
+    /// Nobody took the request before its start window closed, the actor
+    /// is the owner.
+    Expired,
+}
+
+/// A state transition of a walk request, published to downstream services
+/// and kept as the request's timeline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkRequestEvent {
+    pub kind: WalkRequestEventKind,
+    pub request_id: String,
+    /// The user whose action caused the transition, or the internal service
+    /// which acted for `on_behalf_of`.
+    pub actor: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_behalf_of: Option<String>,
+    /// Users the transition is about, other than the actor.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub subjects: Vec<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Destination of walk request events.
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    async fn publish(&self, event: &WalkRequestEvent) -> Result<(), Error>;
+
+    /// Destinations not interested in experiments drop exposures.
+    async fn publish_exposure(&self, _: &Exposure) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Drops every event, used when no destination is configured.
+pub struct NoopPublisher;
+
+#[async_trait]
+impl EventPublisher for NoopPublisher {
+    async fn publish(&self, _: &WalkRequestEvent) -> Result<(), Error> {
+        Ok(())
+    }
+}
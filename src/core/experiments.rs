@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// An experiment and its variants with their weights.
+#[derive(Debug, Clone)]
+struct Experiment {
+    name: String,
+    variants: Vec<(String, u64)>,
+    total: u64,
+}
+
+impl Experiment {
+    fn assign(&self, user_id: &str) -> &str {
+        // Hashed with the experiment's name so that users don't land in the
+        // same bucket of every experiment.
+        let digest = Sha256::digest(format!("{}:{}", self.name, user_id).as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        let mut bucket = u64::from_be_bytes(bytes) % self.total;
+        for (variant, weight) in &self.variants {
+            if bucket < *weight {
+                return variant;
+            }
+            bucket -= weight;
+        }
+        &self.variants[self.variants.len() - 1].0
+    }
+}
+
+/// Running experiments. A user always gets the same variant of an
+/// experiment as long as its variants and weights don't change.
+#[derive(Debug, Clone, Default)]
+pub struct Experiments {
+    experiments: Arc<Vec<Experiment>>,
+}
+
+impl Experiments {
+    /// Parses `experiment=variant:weight,variant:weight` entries separated
+    /// by `;`, e.g. `nearby_feed=distance:90,exposure_balanced:10`.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut experiments = Vec::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || Error::msg(format!("无效的实验配置: {}", entry));
+            let (name, variants) = entry.split_once('=').ok_or_else(invalid)?;
+            let variants = variants
+                .split(',')
+                .map(|v| {
+                    let (variant, weight) = v.split_once(':')?;
+                    Some((variant.trim().to_owned(), weight.trim().parse().ok()?))
+                })
+                .collect::<Option<Vec<(String, u64)>>>()
+                .ok_or_else(invalid)?;
+            let total = variants.iter().map(|(_, w)| w).sum();
+            if total == 0 || variants.iter().any(|(v, _)| v.is_empty()) {
+                return Err(invalid());
+            }
+            experiments.push(Experiment {
+                name: name.trim().to_owned(),
+                variants,
+                total,
+            });
+        }
+        Ok(Self {
+            experiments: Arc::new(experiments),
+        })
+    }
+
+    /// The user's variant, `None` when no such experiment runs.
+    pub fn variant(&self, experiment: &str, user_id: &str) -> Option<&str> {
+        self.experiments
+            .iter()
+            .find(|e| e.name == experiment)
+            .map(|e| e.assign(user_id))
+    }
+
+    /// Names of the experiment's variants, `None` when no such experiment runs.
+    pub fn variants(&self, experiment: &str) -> Option<Vec<&str>> {
+        self.experiments
+            .iter()
+            .find(|e| e.name == experiment)
+            .map(|e| e.variants.iter().map(|(v, _)| v.as_str()).collect())
+    }
+}
+
+/// A user was served the variant of an experiment, published so that
+/// analyses only count users who actually saw it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename = "ExperimentExposed")]
+pub struct Exposure {
+    pub experiment: String,
+    pub variant: String,
+    pub user_id: String,
+    pub occurred_at: DateTime<Utc>,
+}
@@ -0,0 +1,105 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{entities::WalkRequest, error::ServiceError, walker_capabilities::dog_field};
+
+/// Locations read from storage at a time while exporting a track.
+pub const EXPORT_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    GeoJson,
+}
+
+impl ExportFormat {
+    /// Fails unless the export is offered in `self`.
+    pub fn require(self, format: ExportFormat) -> Result<(), ServiceError> {
+        if self != format {
+            return Err(ServiceError::Validation("不支持的导出格式".to_owned()));
+        }
+        Ok(())
+    }
+}
+
+const CSV_COLUMNS: [&str; 13] = [
+    "id",
+    "status",
+    "dogs",
+    "should_start_after",
+    "should_start_before",
+    "latitude",
+    "longitude",
+    "accepted_by",
+    "started_at",
+    "finished_at",
+    "canceled_at",
+    "price",
+    "created_at",
+];
+
+fn csv_record(record: &[String]) -> Result<Vec<u8>, ServiceError> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record(record)
+        .map_err(|e| ServiceError::Internal(anyhow::Error::msg(e.to_string())))?;
+    writer
+        .into_inner()
+        .map_err(|e| ServiceError::Internal(anyhow::Error::msg(e.to_string())))
+}
+
+pub fn csv_header() -> Result<Vec<u8>, ServiceError> {
+    csv_record(&CSV_COLUMNS.map(str::to_owned))
+}
+
+fn cell<T: Display>(value: &Option<T>) -> String {
+    value.as_ref().map(T::to_string).unwrap_or_default()
+}
+
+/// One line of the history export, dogs are listed by name.
+pub fn csv_row(request: &WalkRequest) -> Result<Vec<u8>, ServiceError> {
+    let dogs = request
+        .dogs
+        .iter()
+        .filter_map(|dog| dog_field(dog, "name"))
+        .collect::<Vec<_>>()
+        .join(";");
+    csv_record(&[
+        request.id.clone(),
+        format!("{:?}", request.status),
+        dogs,
+        cell(&request.should_start_after.map(|t| t.to_rfc3339())),
+        cell(&request.should_start_before.map(|t| t.to_rfc3339())),
+        request.latitude.to_string(),
+        request.longitude.to_string(),
+        cell(&request.accepted_by),
+        cell(&request.started_at.map(|t| t.to_rfc3339())),
+        cell(&request.finished_at.map(|t| t.to_rfc3339())),
+        cell(&request.canceled_at.map(|t| t.to_rfc3339())),
+        cell(&request.price),
+        cell(&request.created_at.map(|t| t.to_rfc3339())),
+    ])
+}
+
+/// The track is written as a Feature whose LineString coordinates follow
+/// one by one between this and `GEOJSON_END`.
+pub fn geojson_start(walk_request_id: &str) -> String {
+    format!(
+        r#"{{"type":"Feature","properties":{{"walk_request_id":{}}},"geometry":{{"type":"LineString","coordinates":["#,
+        serde_json::Value::from(walk_request_id)
+    )
+}
+
+pub fn geojson_coordinate(first: bool, longitude: f64, latitude: f64) -> String {
+    format!(
+        "{}[{},{}]",
+        if first { "" } else { "," },
+        longitude,
+        latitude
+    )
+}
+
+pub const GEOJSON_END: &str = "]}}";
@@ -0,0 +1,14 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Favorites an owner may keep.
+pub const MAX_FAVORITES_PER_OWNER: usize = 100;
+
+/// A walker an owner saved, whom they may invite to a request directly, see
+/// `WalkRequest::invited_walker_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FavoriteWalker {
+    pub owner_id: String,
+    pub walker_id: String,
+    pub created_at: DateTime<Utc>,
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Features rolled out gradually, each can be turned off or opened to a
+/// share of users without a redeploy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    LiveTracking,
+    Chat,
+    Payments,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::LiveTracking, Feature::Chat, Feature::Payments];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Feature::LiveTracking => "live_tracking",
+            Feature::Chat => "chat",
+            Feature::Payments => "payments",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.as_str() == name)
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeatureFlag {
+    pub feature: Feature,
+    pub enabled: bool,
+    /// Share of users, from 0 to 100, the feature is enabled for.
+    pub rollout_percent: u8,
+    /// Unset for flags of the configuration no admin changed.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl FeatureFlag {
+    fn on(feature: Feature, rollout_percent: u8) -> Self {
+        Self {
+            feature,
+            enabled: true,
+            rollout_percent,
+            updated_at: None,
+        }
+    }
+
+    /// Whether the feature is enabled for the user. A user stays in the
+    /// rollout as long as the share doesn't shrink.
+    pub fn enabled_for(&self, user_id: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let digest = Sha256::digest(format!("{}:{}", self.feature, user_id).as_bytes());
+        (digest[0] as u32 * 100 / 256) < self.rollout_percent as u32
+    }
+}
+
+fn full_rollout() -> u8 {
+    100
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeatureFlagUpdate {
+    pub enabled: bool,
+    #[serde(default = "full_rollout")]
+    pub rollout_percent: u8,
+}
+
+/// The configured flags, with those stored by admins overriding them. The
+/// merged result is cached for `ttl`, so every instance picks changes up
+/// within it.
+#[derive(Clone)]
+pub struct FeatureFlags {
+    ttl: Duration,
+    defaults: Arc<HashMap<Feature, FeatureFlag>>,
+    merged: Arc<RwLock<Option<(Instant, Vec<FeatureFlag>)>>>,
+}
+
+impl Default for FeatureFlags {
+    fn default() -> Self {
+        Self::new(HashMap::new(), Duration::from_secs(30))
+    }
+}
+
+impl FeatureFlags {
+    fn new(defaults: HashMap<Feature, FeatureFlag>, ttl: Duration) -> Self {
+        Self {
+            ttl,
+            defaults: Arc::new(defaults),
+            merged: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Parses `feature=percent` entries separated by `,`, e.g.
+    /// `live_tracking=100,chat=0,payments=25`. Features not listed are
+    /// enabled for everyone.
+    pub fn parse(spec: &str, ttl: Duration) -> Result<Self, Error> {
+        let mut defaults = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || Error::msg(format!("无效的功能开关配置: {}", entry));
+            let (name, percent) = entry.split_once('=').ok_or_else(invalid)?;
+            let feature = Feature::parse(name.trim()).ok_or_else(invalid)?;
+            let percent: u8 = percent.trim().parse().map_err(|_| invalid())?;
+            if percent > 100 {
+                return Err(invalid());
+            }
+            let mut flag = FeatureFlag::on(feature, percent);
+            flag.enabled = percent > 0;
+            defaults.insert(feature, flag);
+        }
+        Ok(Self::new(defaults, ttl))
+    }
+
+    pub fn cached(&self) -> Option<Vec<FeatureFlag>> {
+        let merged = self.merged.read().expect("feature flag cache poisoned");
+        merged
+            .as_ref()
+            .filter(|(loaded_at, _)| loaded_at.elapsed() < self.ttl)
+            .map(|(_, flags)| flags.clone())
+    }
+
+    /// Every feature's flag, `overrides` taking precedence over the
+    /// configuration.
+    pub fn store(&self, overrides: Vec<FeatureFlag>) -> Vec<FeatureFlag> {
+        let flags: Vec<FeatureFlag> = Feature::ALL
+            .into_iter()
+            .map(|feature| {
+                overrides
+                    .iter()
+                    .find(|o| o.feature == feature)
+                    .or_else(|| self.defaults.get(&feature))
+                    .cloned()
+                    .unwrap_or_else(|| FeatureFlag::on(feature, 100))
+            })
+            .collect();
+        *self.merged.write().expect("feature flag cache poisoned") =
+            Some((Instant::now(), flags.clone()));
+        flags
+    }
+
+    pub fn invalidate(&self) {
+        *self.merged.write().expect("feature flag cache poisoned") = None;
+    }
+}
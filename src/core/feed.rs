@@ -0,0 +1,84 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+
+use super::events::{WalkRequestEvent, WalkRequestEventKind};
+
+/// Events kept for feeds, older ones are dropped.
+const RECENT_EVENTS: usize = 1000;
+
+/// The latest events this instance published, in memory only, so a feed
+/// starts empty after a restart and covers the events of one instance.
+#[derive(Clone, Default)]
+pub struct RecentEvents {
+    events: Arc<Mutex<VecDeque<WalkRequestEvent>>>,
+}
+
+impl RecentEvents {
+    pub fn record(&self, event: &WalkRequestEvent) {
+        let mut events = self.events.lock().unwrap();
+        if events.len() == RECENT_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(event.clone());
+    }
+
+    /// Newest first.
+    pub fn of_kind(&self, kind: WalkRequestEventKind) -> Vec<WalkRequestEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .filter(|e| e.kind == kind)
+            .cloned()
+            .collect()
+    }
+}
+
+pub struct AtomEntry {
+    pub id: String,
+    pub title: String,
+    pub summary: String,
+    pub updated: DateTime<Utc>,
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An Atom document of `entries`, updated when its newest entry was.
+pub fn atom(id: &str, title: &str, entries: &[AtomEntry]) -> String {
+    let updated = entries
+        .iter()
+        .map(|e| e.updated)
+        .max()
+        .unwrap_or_else(Utc::now);
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n\
+         <author><name>little-walk-request</name></author>\n",
+        escape(id),
+        escape(title),
+        updated.to_rfc3339()
+    );
+    for entry in entries {
+        xml.push_str(&format!(
+            "<entry>\n<id>{}</id>\n<title>{}</title>\n<updated>{}</updated>\n\
+             <summary>{}</summary>\n</entry>\n",
+            escape(&entry.id),
+            escape(&entry.title),
+            entry.updated.to_rfc3339(),
+            escape(&entry.summary)
+        ));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
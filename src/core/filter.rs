@@ -0,0 +1,377 @@
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use chrono::{DateTime, Duration, Utc};
+
+use super::{
+    entities::WalkRequestStatus, repository::WalkRequestQuery, walker_capabilities::DogSize,
+};
+
+/// A tiny filter language for admin endpoints, e.g.
+/// `created_by in [u1, u2] and created_at gte "2024-01-01T00:00:00Z"`, or
+/// the same clauses as JSON, see `filter_from_json`.
+/// Clauses are `field op value` joined by `and`; only whitelisted field/operator
+/// pairs are accepted so a filter can never reach arbitrary document paths.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Single(String),
+    List(Vec<String>),
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '[' => {
+                chars.next();
+                tokens.push(Token::LBracket);
+            }
+            ']' => {
+                chars.next();
+                tokens.push(Token::RBracket);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => s.push(escaped),
+                            None => return Err(Error::msg("过滤表达式字符串未结束")),
+                        },
+                        Some(c) => s.push(c),
+                        None => return Err(Error::msg("过滤表达式字符串未结束")),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '[' | ']' | ',' | '"') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn word(&mut self, what: &str) -> Result<String, Error> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(w),
+            _ => Err(Error::msg(format!("过滤表达式缺少{}", what))),
+        }
+    }
+
+    fn scalar(&mut self) -> Result<String, Error> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Str(w)) => Ok(w),
+            _ => Err(Error::msg("过滤表达式缺少值")),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        if self.tokens.get(self.pos) != Some(&Token::LBracket) {
+            return self.scalar().map(Value::Single);
+        }
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            items.push(self.scalar()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RBracket) => break,
+                _ => return Err(Error::msg("过滤表达式列表未结束")),
+            }
+        }
+        Ok(Value::List(items))
+    }
+}
+
+fn single(value: Value) -> Result<String, Error> {
+    match value {
+        Value::Single(v) => Ok(v),
+        Value::List(_) => Err(Error::msg("该操作符不接受列表")),
+    }
+}
+
+fn list(value: Value) -> Result<Vec<String>, Error> {
+    match value {
+        Value::List(v) => Ok(v),
+        Value::Single(v) => Ok(vec![v]),
+    }
+}
+
+/// Bounds the amount of relative times, far beyond any stored time.
+const MAX_RELATIVE_OFFSET: i64 = 100_000;
+
+/// An RFC 3339 time, or one relative to now such as `now-3h`, counted in
+/// minutes (`m`), hours (`h`) or days (`d`).
+fn time(value: Value) -> Result<DateTime<Utc>, Error> {
+    let value = single(value)?;
+    let invalid = || Error::msg(format!("无效的时间: {}", value));
+    if let Some(offset) = value.strip_prefix("now") {
+        if offset.is_empty() {
+            return Ok(Utc::now());
+        }
+        let (sign, offset) = match (offset.strip_prefix('-'), offset.strip_prefix('+')) {
+            (Some(offset), _) => (-1, offset),
+            (_, Some(offset)) => (1, offset),
+            _ => return Err(invalid()),
+        };
+        let unit = offset.chars().last().ok_or_else(invalid)?;
+        let amount: i64 = offset[..offset.len() - unit.len_utf8()]
+            .parse()
+            .ok()
+            .filter(|a: &i64| a.abs() <= MAX_RELATIVE_OFFSET)
+            .ok_or_else(invalid)?;
+        let offset = match unit {
+            'm' => Duration::minutes(amount),
+            'h' => Duration::hours(amount),
+            'd' => Duration::days(amount),
+            _ => return Err(invalid()),
+        };
+        return Ok(Utc::now() + offset * sign);
+    }
+    DateTime::parse_from_rfc3339(&value)
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|_| invalid())
+}
+
+fn boolean(value: Value) -> Result<bool, Error> {
+    let value = single(value)?;
+    value
+        .parse()
+        .map_err(|_| Error::msg(format!("无效的布尔值: {}", value)))
+}
+
+fn status(value: Value) -> Result<WalkRequestStatus, Error> {
+    let value = single(value)?;
+    serde_json::from_value(serde_json::Value::String(value.clone()))
+        .map_err(|_| Error::msg(format!("无效的状态: {}", value)))
+}
+
+fn dog_size(value: Value) -> Result<DogSize, Error> {
+    let value = single(value)?;
+    serde_json::from_value(serde_json::Value::String(value.clone()))
+        .map_err(|_| Error::msg(format!("无效的狗狗体型: {}", value)))
+}
+
+fn apply(query: &mut WalkRequestQuery, field: &str, op: &str, value: Value) -> Result<(), Error> {
+    match (field, op) {
+        ("id", "eq") => query.id = Some(single(value)?),
+        ("id", "in") => query.ids_in = Some(list(value)?),
+        ("created_by", "eq") => query.created_by = Some(single(value)?),
+        ("created_by", "in") => query.created_by_in = Some(list(value)?),
+        ("accepted_by", "eq") => query.accepted_by = Some(single(value)?),
+        ("accepted_by", "ne") => query.accepted_by_neq = Some(single(value)?),
+        ("accepted_by", "in") => query.accepted_by_in = Some(list(value)?),
+        ("acceptances", "in") => query.acceptances_includes_any = Some(list(value)?),
+        ("created_at", "gte") => query.created_at_gte = Some(time(value)?),
+        ("created_at", "lte") => query.created_at_lte = Some(time(value)?),
+        ("should_start_after", "gte") => query.should_start_after_gte = Some(time(value)?),
+        ("should_start_after", "lte") => query.should_start_after_lte = Some(time(value)?),
+        ("started_at", "gte") => query.started_at_gte = Some(time(value)?),
+        ("started_at", "lte") => query.started_at_lte = Some(time(value)?),
+        ("started_at", "exists") => query.started_at_is_null = Some(!boolean(value)?),
+        ("finished_at", "gte") => query.finished_at_gte = Some(time(value)?),
+        ("finished_at", "lte") => query.finished_at_lte = Some(time(value)?),
+        ("finished_at", "exists") => query.finished_at_is_null = Some(!boolean(value)?),
+        ("canceled_at", "exists") => query.canceled_at_is_null = Some(!boolean(value)?),
+        ("status", "eq") => query.status = Some(status(value)?),
+        ("region", "eq") => query.regions_in = Some(vec![single(value)?]),
+        ("region", "in") => query.regions_in = Some(list(value)?),
+        ("dog_name", "contains") => query.dog_name_contains = Some(single(value)?),
+        ("dog_breed", "eq") => query.dog_breed = Some(single(value)?),
+        ("dog_size", "eq") => query.dog_size = Some(dog_size(value)?),
+        _ => return Err(Error::msg(format!("不支持的过滤条件: {} {}", field, op))),
+    }
+    Ok(())
+}
+
+pub fn parse_filter(input: &str) -> Result<WalkRequestQuery, Error> {
+    let mut query = WalkRequestQuery::default();
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    if parser.tokens.is_empty() {
+        return Ok(query);
+    }
+    loop {
+        let field = parser.word("字段")?;
+        let op = parser.word("操作符")?;
+        let value = parser.value()?;
+        apply(&mut query, &field, &op, value)?;
+        match parser.next() {
+            None => break,
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("and") => continue,
+            _ => return Err(Error::msg("过滤条件之间需要使用 and 连接")),
+        }
+    }
+    Ok(query)
+}
+
+fn json_scalar(value: &serde_json::Value) -> Result<String, Error> {
+    match value {
+        serde_json::Value::String(s) => Ok(s.clone()),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(Error::msg(
+            "过滤条件的值只能是字符串、数字、布尔值或它们的列表",
+        )),
+    }
+}
+
+/// The clauses of `parse_filter` as `{"field": {"op": value}}`, e.g.
+/// `{"status": {"eq": "Started"}, "started_at": {"lte": "now-3h"}}`.
+/// Arrays are lists, every clause must hold.
+pub fn filter_from_json(
+    clauses: &BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+) -> Result<WalkRequestQuery, Error> {
+    let mut query = WalkRequestQuery::default();
+    for (field, ops) in clauses {
+        for (op, value) in ops {
+            let value = match value {
+                serde_json::Value::Array(items) => {
+                    Value::List(items.iter().map(json_scalar).collect::<Result<_, _>>()?)
+                }
+                value => Value::Single(json_scalar(value)?),
+            };
+            apply(&mut query, field, op, value)?;
+        }
+    }
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rejected(input: &str) -> String {
+        parse_filter(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn rejects_pairs_off_the_whitelist() {
+        assert_eq!(rejected("location eq x"), "不支持的过滤条件: location eq");
+        assert_eq!(
+            rejected("created_by gte u1"),
+            "不支持的过滤条件: created_by gte"
+        );
+        assert_eq!(
+            rejected("status in [Waiting]"),
+            "不支持的过滤条件: status in"
+        );
+    }
+
+    #[test]
+    fn rejects_unterminated_strings() {
+        assert_eq!(rejected(r#"created_by eq "u1"#), "过滤表达式字符串未结束");
+        assert_eq!(rejected(r#"created_by eq "u1\"#), "过滤表达式字符串未结束");
+    }
+
+    #[test]
+    fn rejects_lists_for_scalar_ops() {
+        assert_eq!(rejected("created_by eq [u1, u2]"), "该操作符不接受列表");
+        assert_eq!(rejected("created_at gte [now]"), "该操作符不接受列表");
+    }
+
+    #[test]
+    fn rejects_malformed_relative_times() {
+        assert_eq!(rejected("created_at gte now-"), "无效的时间: now-");
+        assert_eq!(rejected("created_at gte now-5"), "无效的时间: now-5");
+        let beyond = format!("now-{}d", MAX_RELATIVE_OFFSET + 1);
+        assert_eq!(
+            rejected(&format!("created_at gte {}", beyond)),
+            format!("无效的时间: {}", beyond)
+        );
+        let within = format!("created_at gte now-{}m", MAX_RELATIVE_OFFSET);
+        assert!(parse_filter(&within).unwrap().created_at_gte.unwrap() < Utc::now());
+    }
+
+    #[test]
+    fn joins_clauses_with_and() {
+        let query =
+            parse_filter("created_by in [u1, u2] AND status eq Started and dog_size eq large")
+                .unwrap();
+        assert_eq!(
+            query.created_by_in,
+            Some(vec!["u1".to_owned(), "u2".to_owned()])
+        );
+        assert_eq!(query.status, Some(WalkRequestStatus::Started));
+        assert_eq!(query.dog_size, Some(DogSize::Large));
+        assert_eq!(
+            rejected("status eq Started or status eq Waiting"),
+            "过滤条件之间需要使用 and 连接"
+        );
+        assert_eq!(rejected("status eq Started and"), "过滤表达式缺少字段");
+    }
+
+    #[test]
+    fn json_matches_text() {
+        let text = parse_filter(
+            r#"created_by in [u1, u2] and created_at gte "2024-01-01T00:00:00Z" and started_at exists false and region eq default"#,
+        )
+        .unwrap();
+        let clauses = serde_json::from_value(json!({
+            "created_by": {"in": ["u1", "u2"]},
+            "created_at": {"gte": "2024-01-01T00:00:00Z"},
+            "started_at": {"exists": false},
+            "region": {"eq": "default"},
+        }))
+        .unwrap();
+        let json = filter_from_json(&clauses).unwrap();
+        assert_eq!(
+            serde_json::to_value(&json).unwrap(),
+            serde_json::to_value(&text).unwrap()
+        );
+
+        let clauses = serde_json::from_value(json!({"created_by": {"eq": ["u1"]}})).unwrap();
+        assert_eq!(
+            filter_from_json(&clauses).unwrap_err().to_string(),
+            "该操作符不接受列表"
+        );
+        let clauses = serde_json::from_value(json!({"location": {"eq": "x"}})).unwrap();
+        assert_eq!(
+            filter_from_json(&clauses).unwrap_err().to_string(),
+            "不支持的过滤条件: location eq"
+        );
+    }
+}
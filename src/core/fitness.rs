@@ -0,0 +1,126 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{entities::WalkingLocation, geo::WalkSummary};
+
+/// Exports are given up after this many failed attempts.
+pub const MAX_EXPORT_ATTEMPTS: u32 = 5;
+
+const EXPORT_RETRY_BASE_SECONDS: i64 = 60;
+
+/// A walker's OAuth authorization to post activities to a provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FitnessToken {
+    pub user_id: String,
+    pub provider: String,
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl FitnessToken {
+    pub fn expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.map_or(false, |t| t <= now)
+    }
+}
+
+/// A finished walk as uploaded to providers.
+#[derive(Debug, Clone, Serialize)]
+pub struct FitnessActivity {
+    pub request_id: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub summary: WalkSummary,
+    pub locations: Vec<WalkingLocation>,
+}
+
+/// A Strava or Google Fit style platform walkers count their mileage on.
+#[async_trait]
+pub trait FitnessProvider: Send + Sync {
+    fn name(&self) -> &str;
+    /// Exchanges the code of a completed OAuth consent for a token.
+    async fn authorize(&self, user_id: &str, code: &str) -> Result<FitnessToken, Error>;
+    async fn refresh(&self, token: &FitnessToken) -> Result<FitnessToken, Error>;
+    /// Posts the activity, returns the provider's id for it.
+    async fn upload(
+        &self,
+        token: &FitnessToken,
+        activity: &FitnessActivity,
+    ) -> Result<String, Error>;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExportStatus {
+    Pending,
+    Exported,
+    Failed,
+}
+
+/// One walk to upload to one provider, retried with backoff until it is
+/// uploaded or `MAX_EXPORT_ATTEMPTS` is reached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FitnessExport {
+    pub id: String,
+    pub request_id: String,
+    pub user_id: String,
+    pub provider: String,
+    pub status: ExportStatus,
+    pub attempts: u32,
+    pub error: Option<String>,
+    /// The provider's id of the uploaded activity.
+    pub external_id: Option<String>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FitnessExport {
+    pub fn new(request_id: &str, user_id: &str, provider: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: format!("{}:{}:{}", request_id, user_id, provider),
+            request_id: request_id.to_owned(),
+            user_id: user_id.to_owned(),
+            provider: provider.to_owned(),
+            status: ExportStatus::Pending,
+            attempts: 0,
+            error: None,
+            external_id: None,
+            next_attempt_at: now,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn exported(&mut self, external_id: String) {
+        self.attempts += 1;
+        self.status = ExportStatus::Exported;
+        self.external_id = Some(external_id);
+        self.error = None;
+        self.updated_at = Utc::now();
+    }
+
+    /// Schedules the next attempt, doubling the wait each time, or gives up.
+    pub fn failed(&mut self, error: &str) {
+        let now = Utc::now();
+        self.attempts += 1;
+        self.error = Some(error.to_owned());
+        self.updated_at = now;
+        if self.attempts >= MAX_EXPORT_ATTEMPTS {
+            self.status = ExportStatus::Failed;
+        } else {
+            self.next_attempt_at =
+                now + Duration::seconds(EXPORT_RETRY_BASE_SECONDS << (self.attempts - 1));
+        }
+    }
+
+    /// Gives up right away, for failures retrying can't fix.
+    pub fn abandon(&mut self, error: &str) {
+        self.attempts += 1;
+        self.status = ExportStatus::Failed;
+        self.error = Some(error.to_owned());
+        self.updated_at = Utc::now();
+    }
+}
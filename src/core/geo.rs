@@ -0,0 +1,322 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{distance::DistanceStrategy, entities::WalkingLocation, units::Meters};
+
+/// Mean earth radius used for great-circle distances.
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// A map viewport. Its edges are straight on the map, stores matching along
+/// great circles may differ slightly on boxes spanning many degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_longitude: f64,
+    pub min_latitude: f64,
+    pub max_longitude: f64,
+    pub max_latitude: f64,
+}
+
+impl BoundingBox {
+    /// Whether the corners are coordinates with the minimum below the
+    /// maximum. Boxes across the antimeridian aren't supported.
+    pub fn is_valid(&self) -> bool {
+        (-180.0..=180.0).contains(&self.min_longitude)
+            && (-180.0..=180.0).contains(&self.max_longitude)
+            && (-90.0..=90.0).contains(&self.min_latitude)
+            && (-90.0..=90.0).contains(&self.max_latitude)
+            && self.min_longitude < self.max_longitude
+            && self.min_latitude < self.max_latitude
+    }
+
+    pub fn contains(&self, (longitude, latitude): (f64, f64)) -> bool {
+        (self.min_longitude..=self.max_longitude).contains(&longitude)
+            && (self.min_latitude..=self.max_latitude).contains(&latitude)
+    }
+
+    /// The closed ring of its corners, counterclockwise as GeoJSON wants.
+    pub fn ring(&self) -> [[f64; 2]; 5] {
+        [
+            [self.min_longitude, self.min_latitude],
+            [self.max_longitude, self.min_latitude],
+            [self.max_longitude, self.max_latitude],
+            [self.min_longitude, self.max_latitude],
+            [self.min_longitude, self.min_latitude],
+        ]
+    }
+
+    /// Half the diagonal, the radius of a search covering the box.
+    pub fn radius(&self) -> Meters {
+        Meters(
+            distance(
+                (self.min_longitude, self.min_latitude),
+                (self.max_longitude, self.max_latitude),
+            )
+            .value()
+                / 2.0,
+        )
+    }
+}
+
+/// Haversine distance between two `(longitude, latitude)` points.
+pub fn distance(from: (f64, f64), to: (f64, f64)) -> Meters {
+    let (lat1, lat2) = (from.1.to_radians(), to.1.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.0 - from.0).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    Meters(2.0 * EARTH_RADIUS_METERS * a.sqrt().asin())
+}
+
+/// Length of the path through `locations`, taken in recording order.
+pub fn track_length(locations: &[WalkingLocation]) -> Meters {
+    let mut points: Vec<&WalkingLocation> = locations.iter().collect();
+    points.sort_by_key(|l| l.created_at);
+    Meters(
+        points
+            .windows(2)
+            .map(|w| {
+                distance(
+                    (w[0].longitude, w[0].latitude),
+                    (w[1].longitude, w[1].latitude),
+                )
+                .0
+            })
+            .sum(),
+    )
+}
+
+/// Figures of a finished walk, computed once from its track.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WalkSummary {
+    pub distance: Meters,
+    /// What `distance` was computed with.
+    #[serde(default)]
+    pub distance_strategy: DistanceStrategy,
+    /// The raw length when another strategy was used, kept to compare the
+    /// two during rollout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_distance: Option<Meters>,
+    pub duration_seconds: i64,
+    /// Zero for walks without duration.
+    pub average_speed_kmh: f64,
+    pub points: i64,
+    /// The track simplified for drawing, as `[longitude, latitude]`.
+    #[serde(default)]
+    pub route: Vec<[f64; 2]>,
+    /// Share of the walk covered by the track, from 0 to 1. Stretches
+    /// between points further apart than `MAX_TRACK_GAP_SECONDS` count as
+    /// uncovered.
+    #[serde(default)]
+    pub quality: f64,
+    pub computed_at: DateTime<Utc>,
+}
+
+impl WalkSummary {
+    pub fn compute(
+        locations: &[WalkingLocation],
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+    ) -> Self {
+        let distance = track_length(locations);
+        let duration_seconds = (finished_at - started_at).num_seconds().max(0);
+        Self {
+            distance,
+            distance_strategy: DistanceStrategy::Raw,
+            raw_distance: None,
+            duration_seconds,
+            average_speed_kmh: average_speed_kmh(distance, duration_seconds),
+            points: locations.len() as i64,
+            route: simplify(locations, Meters(SUMMARY_ROUTE_TOLERANCE_METERS)),
+            quality: track_quality(locations, duration_seconds),
+            computed_at: Utc::now(),
+        }
+    }
+
+    /// Whether both have the same figures, whenever they were computed.
+    pub fn same_figures(&self, other: &Self) -> bool {
+        Self {
+            computed_at: other.computed_at,
+            ..self.clone()
+        } == *other
+    }
+
+    /// Replaces the raw distance with one computed by `strategy`, keeping
+    /// the raw one alongside.
+    pub fn measured_with(self, strategy: DistanceStrategy, distance: Meters) -> Self {
+        if strategy == DistanceStrategy::Raw {
+            return self;
+        }
+        Self {
+            raw_distance: Some(self.distance),
+            distance,
+            distance_strategy: strategy,
+            average_speed_kmh: average_speed_kmh(distance, self.duration_seconds),
+            ..self
+        }
+    }
+}
+
+/// Points recorded this long before the last one give the current speed.
+const CURRENT_SPEED_WINDOW_SECONDS: i64 = 60;
+
+/// Where a walk stands, for the owner's progress card.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct WalkProgress {
+    pub started_at: DateTime<Utc>,
+    pub elapsed_seconds: i64,
+    pub distance: Meters,
+    /// Over the last minute of the track, `None` until there is one.
+    pub current_speed_kmh: Option<f64>,
+    pub last_location_at: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+    /// Until `should_end_before`, negative once past it.
+    pub remaining_seconds: Option<i64>,
+    pub overrunning: bool,
+    pub finished: bool,
+}
+
+impl WalkProgress {
+    /// `finished_at` stops the clock, otherwise it runs until `now`.
+    pub fn compute(
+        locations: &[WalkingLocation],
+        started_at: DateTime<Utc>,
+        finished_at: Option<DateTime<Utc>>,
+        should_end_before: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let until = finished_at.unwrap_or(now);
+        let mut points: Vec<&WalkingLocation> = locations
+            .iter()
+            .filter(|l| l.created_at.is_some())
+            .collect();
+        points.sort_by_key(|l| l.created_at);
+        let last_location_at = points.last().and_then(|l| l.created_at);
+        let current_speed_kmh = last_location_at.and_then(|last| {
+            let since = last - chrono::Duration::seconds(CURRENT_SPEED_WINDOW_SECONDS);
+            let recent: Vec<WalkingLocation> = points
+                .iter()
+                .filter(|l| l.created_at.map_or(false, |c| c >= since))
+                .map(|l| (*l).clone())
+                .collect();
+            let first = recent.first()?.created_at?;
+            let seconds = (last - first).num_seconds();
+            (seconds > 0).then(|| average_speed_kmh(track_length(&recent), seconds))
+        });
+        let remaining_seconds = should_end_before.map(|end| (end - until).num_seconds());
+        Self {
+            started_at,
+            elapsed_seconds: (until - started_at).num_seconds().max(0),
+            distance: track_length(locations),
+            current_speed_kmh,
+            last_location_at,
+            should_end_before,
+            remaining_seconds,
+            overrunning: remaining_seconds.map_or(false, |r| r < 0),
+            finished: finished_at.is_some(),
+        }
+    }
+}
+
+/// Points further apart leave the stretch between them uncovered.
+const MAX_TRACK_GAP_SECONDS: i64 = 60;
+
+/// Drawing tolerance of summary routes.
+const SUMMARY_ROUTE_TOLERANCE_METERS: f64 = 5.0;
+
+fn track_quality(locations: &[WalkingLocation], duration_seconds: i64) -> f64 {
+    if duration_seconds == 0 {
+        return 0.0;
+    }
+    let mut times: Vec<DateTime<Utc>> = locations.iter().filter_map(|l| l.created_at).collect();
+    times.sort();
+    let covered: i64 = times
+        .windows(2)
+        .map(|w| (w[1] - w[0]).num_seconds())
+        .filter(|gap| *gap <= MAX_TRACK_GAP_SECONDS)
+        .sum();
+    (covered as f64 / duration_seconds as f64).min(1.0)
+}
+
+/// Zero for walks without duration.
+fn average_speed_kmh(distance: Meters, duration_seconds: i64) -> f64 {
+    if duration_seconds == 0 {
+        0.0
+    } else {
+        distance.kilometers() / (duration_seconds as f64 / 3600.0)
+    }
+}
+
+/// Ids of the points to drop so that at most one point per `interval` is
+/// kept, the first of each, plus the last point of the track.
+pub fn downsample(locations: &[WalkingLocation], interval: chrono::Duration) -> Vec<String> {
+    let mut points: Vec<&WalkingLocation> = locations.iter().collect();
+    points.sort_by_key(|l| l.created_at);
+    let mut dropped = Vec::new();
+    let mut kept_at: Option<DateTime<Utc>> = None;
+    for (i, point) in points.iter().enumerate() {
+        let Some(created_at) = point.created_at else {
+            continue;
+        };
+        let due = kept_at.map_or(true, |kept| created_at - kept >= interval);
+        if due || i == points.len() - 1 {
+            kept_at = Some(created_at);
+        } else {
+            dropped.push(point.id.clone());
+        }
+    }
+    dropped
+}
+
+/// Distance in meters from `point` to the segment `from`-`to`, on a local
+/// flat projection which is accurate at walking scale.
+fn segment_distance(point: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+    let scale = (
+        point.1.to_radians().cos() * EARTH_RADIUS_METERS.to_radians(),
+        EARTH_RADIUS_METERS.to_radians(),
+    );
+    let project = |p: (f64, f64)| ((p.0 - from.0) * scale.0, (p.1 - from.1) * scale.1);
+    let (px, py) = project(point);
+    let (dx, dy) = project(to);
+    let length = dx * dx + dy * dy;
+    let t = if length == 0.0 {
+        0.0
+    } else {
+        ((px * dx + py * dy) / length).clamp(0.0, 1.0)
+    };
+    ((px - t * dx).powi(2) + (py - t * dy).powi(2)).sqrt()
+}
+
+/// The track as `[longitude, latitude]` simplified with Douglas–Peucker:
+/// points closer than `tolerance` to the line through their neighbours
+/// kept are dropped.
+pub fn simplify(locations: &[WalkingLocation], tolerance: Meters) -> Vec<[f64; 2]> {
+    let mut points: Vec<&WalkingLocation> = locations.iter().collect();
+    points.sort_by_key(|l| l.created_at);
+    let points: Vec<(f64, f64)> = points.iter().map(|l| (l.longitude, l.latitude)).collect();
+    if points.len() < 3 {
+        return points.iter().map(|p| [p.0, p.1]).collect();
+    }
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // Iterative, long tracks would overflow the stack otherwise.
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(points[i], points[first], points[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance.value() {
+                keep[i] = true;
+                ranges.push((first, i));
+                ranges.push((i, last));
+            }
+        }
+    }
+    points
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(p, _)| [p.0, p.1])
+        .collect()
+}
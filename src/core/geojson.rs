@@ -0,0 +1,60 @@
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::{entities::WalkRequest, error::ServiceError, repository::Paged};
+
+pub const GEOJSON: &str = "application/geo+json";
+
+/// A point Feature whose properties are the fields of `item`, which must
+/// serialize to an object.
+pub fn point_feature<T: Serialize>(
+    item: &T,
+    longitude: f64,
+    latitude: f64,
+) -> Result<Value, ServiceError> {
+    let properties = match serde_json::to_value(item).map_err(anyhow::Error::from)? {
+        Value::Object(fields) => fields,
+        _ => {
+            return Err(ServiceError::Internal(anyhow::Error::msg(
+                "GeoJSON properties must be an object",
+            )))
+        }
+    };
+    Ok(json!({
+        "type": "Feature",
+        "geometry": {"type": "Point", "coordinates": [longitude, latitude]},
+        "properties": properties,
+    }))
+}
+
+/// A FeatureCollection of the page, the pager fields kept as foreign
+/// members next to `features`.
+pub fn feature_collection<T: Serialize>(
+    page: &Paged<T>,
+    position: impl Fn(&T) -> (f64, f64),
+) -> Result<Value, ServiceError> {
+    let features = page
+        .items
+        .iter()
+        .map(|item| {
+            let (longitude, latitude) = position(item);
+            point_feature(item, longitude, latitude)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut collection = Map::new();
+    collection.insert("type".to_owned(), Value::from("FeatureCollection"));
+    collection.insert("features".to_owned(), Value::Array(features));
+    collection.insert("total".to_owned(), Value::from(page.total));
+    collection.insert("page".to_owned(), Value::from(page.page));
+    collection.insert("size".to_owned(), Value::from(page.size));
+    collection.insert("has_more".to_owned(), Value::from(page.has_more));
+    if let Some(cursor) = &page.next_cursor {
+        collection.insert("next_cursor".to_owned(), Value::from(cursor.as_str()));
+    }
+    Ok(Value::Object(collection))
+}
+
+/// Walk requests placed at where the walk starts.
+pub fn walk_requests(page: &Paged<WalkRequest>) -> Result<Value, ServiceError> {
+    feature_collection(page, |request| (request.longitude, request.latitude))
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use chrono::{NaiveDate, NaiveTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Holiday {
+    pub region: String,
+    pub date: NaiveDate,
+    pub name: String,
+    #[serde(default = "default_multiplier")]
+    pub price_multiplier: f64,
+    pub opens_at: Option<NaiveTime>,
+    pub closes_at: Option<NaiveTime>,
+}
+
+fn default_multiplier() -> f64 {
+    1.0
+}
+
+/// Per-region holiday calendars. Built-in dates come from the configured file,
+/// dates stored by admins override them, and the merged result is cached per
+/// region for `ttl`.
+#[derive(Clone)]
+pub struct HolidayCalendar {
+    ttl: Duration,
+    defaults: Arc<Vec<Holiday>>,
+    entries: Arc<RwLock<HashMap<String, (Instant, Vec<Holiday>)>>>,
+}
+
+impl Default for HolidayCalendar {
+    fn default() -> Self {
+        Self::new(Vec::new(), Duration::from_secs(300))
+    }
+}
+
+impl HolidayCalendar {
+    pub fn new(defaults: Vec<Holiday>, ttl: Duration) -> Self {
+        Self {
+            ttl,
+            defaults: Arc::new(defaults),
+            entries: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_file(path: &str, ttl: Duration) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::new(serde_json::from_str(&content)?, ttl))
+    }
+
+    pub fn cached(&self, region: &str) -> Option<Vec<Holiday>> {
+        let entries = self.entries.read().expect("holiday cache poisoned");
+        entries
+            .get(region)
+            .filter(|(loaded_at, _)| loaded_at.elapsed() < self.ttl)
+            .map(|(_, holidays)| holidays.clone())
+    }
+
+    pub fn store(&self, region: &str, overrides: Vec<Holiday>) -> Vec<Holiday> {
+        let mut holidays: Vec<Holiday> = self
+            .defaults
+            .iter()
+            .filter(|h| h.region == region && !overrides.iter().any(|o| o.date == h.date))
+            .cloned()
+            .collect();
+        holidays.extend(overrides);
+        holidays.sort_by_key(|h| h.date);
+        self.entries
+            .write()
+            .expect("holiday cache poisoned")
+            .insert(region.to_owned(), (Instant::now(), holidays.clone()));
+        holidays
+    }
+
+    pub fn invalidate(&self, region: &str) {
+        self.entries
+            .write()
+            .expect("holiday cache poisoned")
+            .remove(region);
+    }
+}
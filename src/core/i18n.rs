@@ -0,0 +1,733 @@
+/// Languages error messages are available in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    /// The language messages are written in.
+    #[default]
+    ZhCn,
+    EnUs,
+}
+
+impl Locale {
+    fn of_tag(tag: &str) -> Option<Self> {
+        let language = tag.split(['-', '_']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "zh" => Some(Locale::ZhCn),
+            "en" => Some(Locale::EnUs),
+            _ => None,
+        }
+    }
+
+    /// The supported locale the `Accept-Language` header weighs highest,
+    /// zh-CN when it names none.
+    pub fn negotiate(accept_language: Option<&str>) -> Self {
+        let Some(header) = accept_language else {
+            return Self::default();
+        };
+        let mut best: Option<(f32, Self)> = None;
+        for entry in header.split(',') {
+            let mut parts = entry.split(';');
+            let tag = parts.next().unwrap_or_default().trim();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let Some(locale) = Self::of_tag(tag) else {
+                continue;
+            };
+            if quality > 0.0 && best.map_or(true, |(q, _)| quality > q) {
+                best = Some((quality, locale));
+            }
+        }
+        best.map_or_else(Self::default, |(_, locale)| locale)
+    }
+
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Locale::ZhCn => "zh-CN",
+            Locale::EnUs => "en-US",
+        }
+    }
+}
+
+/// Known messages by reason code, in zh-CN then en-US. `{}` stands for an
+/// argument, filled in the same order in both languages.
+const CATALOG: &[(&str, &str, &str)] = &[
+    (
+        "walk_request_not_found",
+        "代遛请求不存在",
+        "Walk request not found",
+    ),
+    (
+        "walk_request_not_found",
+        "未找到代遛请求: {}",
+        "Walk request not found: {}",
+    ),
+    ("request_not_found", "请求不存在", "Request not found"),
+    ("payment_not_found", "付款记录不存在", "Payment not found"),
+    ("receipt_not_found", "收据不存在", "Receipt not found"),
+    (
+        "audit_record_not_found",
+        "审计记录不存在",
+        "Audit record not found",
+    ),
+    (
+        "audit_log_disabled",
+        "未开启审计日志",
+        "Audit log is not enabled",
+    ),
+    (
+        "live_location_disabled",
+        "未开启实时定位",
+        "Live location is not enabled",
+    ),
+    (
+        "fitness_not_connected",
+        "未连接该健身平台",
+        "Fitness platform not connected",
+    ),
+    ("tenant_not_found", "租户不存在", "Tenant not found"),
+    ("holiday_not_found", "节假日不存在", "Holiday not found"),
+    (
+        "device_session_not_found",
+        "设备会话不存在",
+        "Device session not found",
+    ),
+    (
+        "device_session_revoked",
+        "设备会话已撤销",
+        "Device session was revoked",
+    ),
+    (
+        "schedule_not_found",
+        "重复代遛计划不存在",
+        "Walk schedule not found",
+    ),
+    (
+        "template_not_found",
+        "代遛模板不存在",
+        "Walk request template not found",
+    ),
+    (
+        "template_name_invalid",
+        "模板名称不能为空且不能超过{}个字符",
+        "Template name must be non-empty and at most {} characters",
+    ),
+    (
+        "template_limit_reached",
+        "最多只能保存{}个代遛模板",
+        "At most {} walk request templates can be saved",
+    ),
+    (
+        "recompute_not_found",
+        "重算任务不存在",
+        "Recomputation not found",
+    ),
+    ("backfill_not_found", "回填任务不存在", "Backfill not found"),
+    ("key_ring_not_found", "密钥环不存在", "Key ring not found"),
+    ("key_not_found", "密钥不存在", "Key not found"),
+    ("key_exists", "密钥ID已存在", "Key id already exists"),
+    (
+        "active_key_retired",
+        "不能停用当前使用的密钥",
+        "The active key can't be retired",
+    ),
+    (
+        "key_required",
+        "密钥ID和密钥不能为空",
+        "Key id and secret are required",
+    ),
+    (
+        "backfill_never_run",
+        "回填任务尚未运行",
+        "Backfill has not run yet",
+    ),
+    (
+        "backfill_running",
+        "回填任务正在运行",
+        "Backfill is already running",
+    ),
+    (
+        "already_accepted",
+        "请求不存在或已有人接单",
+        "Request not found or already accepted",
+    ),
+    (
+        "canceled_by_owner",
+        "请求不存在或已被狗狗主人取消",
+        "Request not found or canceled by the owner",
+    ),
+    (
+        "undo_window_passed",
+        "请求不存在或已超过撤销期限",
+        "Request not found or the undo window has passed",
+    ),
+    (
+        "application_withdrawn",
+        "请求不存在或该用户已取消报名",
+        "Request not found or the application was withdrawn",
+    ),
+    (
+        "not_waiting",
+        "代遛请求已不在等待接单状态",
+        "Walk request is no longer waiting",
+    ),
+    (
+        "only_waiting_editable",
+        "只能修改等待接单的代遛请求",
+        "Only waiting walk requests can be changed",
+    ),
+    (
+        "invalid_transition",
+        "代遛请求无法从{}状态变为{}状态",
+        "Walk request can't go from {} to {}",
+    ),
+    (
+        "version_conflict",
+        "代遛请求已被修改，请刷新后重试",
+        "The walk request was changed by someone else, please reload it",
+    ),
+    (
+        "cancel_not_undoable",
+        "取消操作无法撤销",
+        "The cancellation can't be undone",
+    ),
+    (
+        "cancellation_reason_too_long",
+        "取消原因不能超过{}个字符",
+        "Cancellation reason must not exceed {} characters",
+    ),
+    (
+        "message_empty",
+        "消息不能为空",
+        "The message must not be empty",
+    ),
+    (
+        "message_too_long",
+        "消息不能超过{}个字符",
+        "Messages must not exceed {} characters",
+    ),
+    (
+        "walk_not_started",
+        "遛狗尚未开始",
+        "The walk has not started",
+    ),
+    (
+        "walk_not_finished",
+        "遛狗尚未结束",
+        "The walk has not finished",
+    ),
+    (
+        "not_applied",
+        "您尚未报名该代遛请求",
+        "You have not applied to this walk request",
+    ),
+    (
+        "dismissed",
+        "您已被狗狗主人移除，无法再次报名",
+        "You were dismissed by the owner and can't apply again",
+    ),
+    (
+        "applicants_full",
+        "报名人数已满",
+        "No more applicants are taken",
+    ),
+    (
+        "apply_deadline_passed",
+        "报名已截止",
+        "Applications for this walk request have closed",
+    ),
+    (
+        "cannot_apply_own",
+        "不能报名自己发布的代遛请求",
+        "You can't apply to your own walk request",
+    ),
+    (
+        "cannot_assign_to_owner",
+        "不能将代遛请求指派给发布者本人",
+        "A walk request can't be assigned to its owner",
+    ),
+    (
+        "walker_banned",
+        "您因多次违约已被暂停接单至{}",
+        "You can't accept walks until {} after repeated strikes",
+    ),
+    (
+        "daily_budget_reached",
+        "今日遛狗时长已达上限（{}分钟）",
+        "Today's walking time limit of {} minutes is reached",
+    ),
+    (
+        "walk_conflict",
+        "与已接的遛狗时间冲突",
+        "Overlaps a walk already accepted",
+    ),
+    (
+        "outbox_message_not_found",
+        "投递消息不存在",
+        "Delivery not found",
+    ),
+    (
+        "outbox_message_not_dead",
+        "只能重新投递已放弃的消息",
+        "Only deliveries given up on can be requeued",
+    ),
+    (
+        "export_format_unsupported",
+        "不支持的导出格式",
+        "This export is not available in that format",
+    ),
+    (
+        "capability_mismatch",
+        "您的接单能力不满足该代遛请求的要求",
+        "You can't take the dogs of this walk request",
+    ),
+    (
+        "capabilities_managed_externally",
+        "接单能力由用户服务管理",
+        "Capabilities are managed by the user service",
+    ),
+    (
+        "onboarding_incomplete",
+        "请先完成入职流程",
+        "Please finish onboarding first",
+    ),
+    (
+        "feature_disabled",
+        "该功能暂未开放",
+        "This feature isn't available yet",
+    ),
+    (
+        "spending_cap_exceeded",
+        "报价超出本月消费上限，请确认后重试",
+        "This goes over your monthly spending cap, confirm to go ahead",
+    ),
+    (
+        "spending_cap_currency",
+        "消费上限须使用默认币种",
+        "Spending caps are set in the default currency",
+    ),
+    (
+        "spending_cap_invalid",
+        "消费上限须大于0",
+        "Spending caps must be above zero",
+    ),
+    (
+        "rollout_percent_invalid",
+        "开放比例必须在0到100之间",
+        "Rollout percent must be between 0 and 100",
+    ),
+    (
+        "track_forbidden",
+        "无权查看遛狗轨迹",
+        "You may not see this walking track",
+    ),
+    ("forbidden", "无权限", "Forbidden"),
+    (
+        "invalid_access_token",
+        "无效的访问令牌",
+        "Invalid access token",
+    ),
+    ("invalid_api_key", "无效的API密钥", "Invalid API key"),
+    ("missing_api_key", "缺少API密钥", "Missing API key"),
+    (
+        "quota_exceeded",
+        "请求次数超出配额",
+        "Request quota exceeded",
+    ),
+    ("rate_limited", "请求过于频繁", "Too many requests"),
+    (
+        "invalid_calendar_token",
+        "无效的日历令牌",
+        "Invalid calendar token",
+    ),
+    (
+        "authorization_failed",
+        "授权失败: {}",
+        "Authorization failed: {}",
+    ),
+    (
+        "authorization_expired",
+        "授权已过期，请重新连接",
+        "Authorization expired, please reconnect",
+    ),
+    (
+        "invalid_fields",
+        "请求参数有误",
+        "Invalid request parameters",
+    ),
+    ("invalid_id", "无效的ID", "Invalid id"),
+    ("invalid_cursor", "无效的游标", "Invalid cursor"),
+    ("invalid_version", "无效的版本号", "Invalid version"),
+    ("invalid_month", "无效的月份", "Invalid month"),
+    ("invalid_area", "无效的区域", "Invalid area"),
+    ("invalid_timezone", "无效的时区", "Invalid timezone"),
+    ("invalid_timezone", "无效的时区: {}", "Invalid timezone: {}"),
+    ("invalid_time", "无效的时间: {}", "Invalid time: {}"),
+    ("invalid_number", "无效的数字: {}", "Invalid number: {}"),
+    ("invalid_currency", "无效的币种", "Invalid currency"),
+    ("invalid_dog", "无效的狗信息: {}", "Invalid dog: {}"),
+    (
+        "invalid_schedule_rule",
+        "无效的重复规则: {}",
+        "Invalid recurrence rule: {}",
+    ),
+    ("no_dogs", "至少需要一只狗", "At least one dog is required"),
+    (
+        "latitude_out_of_range",
+        "纬度超出范围",
+        "Latitude out of range",
+    ),
+    (
+        "longitude_out_of_range",
+        "经度超出范围",
+        "Longitude out of range",
+    ),
+    (
+        "coordinates_out_of_range",
+        "坐标超出范围",
+        "Coordinates out of range",
+    ),
+    (
+        "start_window_inverted",
+        "开始时间范围起点不得大于等于终点",
+        "The start window must begin before it ends",
+    ),
+    (
+        "end_window_inverted",
+        "结束时间范围起点不得大于等于终点",
+        "The end window must begin before it ends",
+    ),
+    (
+        "end_before_start",
+        "结束时间不得早于开始时间",
+        "The end must not be before the start",
+    ),
+    (
+        "price_not_positive",
+        "价格必须大于0",
+        "Price must be greater than 0",
+    ),
+    (
+        "negative_base_price",
+        "基础价格不能为负",
+        "Base price must not be negative",
+    ),
+    (
+        "max_applicants_not_positive",
+        "报名人数上限必须大于0",
+        "Maximum applicants must be greater than 0",
+    ),
+    (
+        "max_walkers_not_positive",
+        "遛狗人数必须大于0",
+        "Number of walkers must be greater than 0",
+    ),
+    (
+        "too_many_walkers",
+        "遛狗人数不能超过{}",
+        "Number of walkers must not exceed {}",
+    ),
+    (
+        "more_walkers_than_dogs",
+        "遛狗人数不能多于狗的数量",
+        "There can't be more walkers than dogs",
+    ),
+    (
+        "max_radius_not_positive",
+        "活动半径必须大于0",
+        "Maximum radius must be greater than 0",
+    ),
+    (
+        "apply_before_after_start",
+        "报名截止时间不得晚于最晚开始时间",
+        "Applications must close before the latest start",
+    ),
+    (
+        "max_dogs_not_positive",
+        "最多可遛狗数量必须大于0",
+        "Maximum dogs must be greater than 0",
+    ),
+    (
+        "radius_too_large",
+        "搜索半径不得超过{}",
+        "Search radius must not exceed {}",
+    ),
+    (
+        "too_many_locations",
+        "一次最多上传{}个定位",
+        "At most {} locations can be uploaded at once",
+    ),
+    (
+        "location_in_future",
+        "定位时间晚于当前时间",
+        "Location is recorded in the future",
+    ),
+    (
+        "too_late_to_sync",
+        "事件发生过早，已超出离线同步期限",
+        "The event is too old to be synced",
+    ),
+    (
+        "location_before_start",
+        "定位时间早于遛狗开始时间",
+        "Location is recorded before the walk started",
+    ),
+    (
+        "tolerance_negative",
+        "tolerance不能为负数",
+        "tolerance must not be negative",
+    ),
+    (
+        "unsupported_sort_field",
+        "不支持的排序字段: {}",
+        "Unsupported sort field: {}",
+    ),
+    (
+        "unsupported_fitness_provider",
+        "不支持的健身平台: {}",
+        "Unsupported fitness platform: {}",
+    ),
+    (
+        "unsupported_filter",
+        "不支持的过滤条件: {} {}",
+        "Unsupported filter: {} {}",
+    ),
+    (
+        "filter_missing",
+        "过滤表达式缺少{}",
+        "Filter expression is missing a {}",
+    ),
+    (
+        "filter_missing_value",
+        "过滤表达式缺少值",
+        "Filter expression is missing a value",
+    ),
+    (
+        "filter_unterminated_string",
+        "过滤表达式字符串未结束",
+        "Unterminated string in filter expression",
+    ),
+    (
+        "filter_unterminated_list",
+        "过滤表达式列表未结束",
+        "Unterminated list in filter expression",
+    ),
+    (
+        "filter_and_required",
+        "过滤条件之间需要使用 and 连接",
+        "Filter conditions must be joined with and",
+    ),
+    (
+        "filter_list_not_allowed",
+        "该操作符不接受列表",
+        "This operator doesn't take a list",
+    ),
+    ("field", "字段", "field"),
+    ("operator", "操作符", "operator"),
+    (
+        "request_id_or_range_required",
+        "需指定代遛请求ID或起止时间",
+        "A walk request id or a time range is required",
+    ),
+    (
+        "invalid_schedule_window",
+        "时间窗口和时长必须大于0",
+        "Window and duration must be greater than 0",
+    ),
+    (
+        "invalid_range",
+        "时间范围需大于0且不超过{}天",
+        "The time range must be positive and at most {} days",
+    ),
+    (
+        "invalid_range",
+        "时间范围需不早于开始日期且不超过{}天",
+        "The time range must not end before it starts and span at most {} days",
+    ),
+    (
+        "invalid_service_area",
+        "服务区域至少需要4个点组成的闭合多边形",
+        "A service area is a closed polygon of at least 4 points",
+    ),
+    (
+        "impressions_too_many",
+        "每批曝光最多{}条",
+        "At most {} impressions per batch",
+    ),
+    (
+        "import_unparsable",
+        "无法解析导入文件: {}",
+        "Import file can't be parsed: {}",
+    ),
+    ("missing_field", "缺少字段: {}", "Missing field: {}"),
+    (
+        "accepted_fields_pair",
+        "accepted_by与accepted_at必须同时存在",
+        "accepted_by and accepted_at must be given together",
+    ),
+    (
+        "missing_rate",
+        "缺少汇率: {} -> {}",
+        "Missing exchange rate: {} -> {}",
+    ),
+    (
+        "location_required",
+        "请提供当前位置",
+        "Current location is required",
+    ),
+    (
+        "too_far_from_pickup",
+        "距接狗地点太远，请到达后再开始",
+        "Too far from the pickup point, start once there",
+    ),
+    (
+        "walk_too_short",
+        "遛狗时间或距离过短，暂不能结束",
+        "The walk is too short to be finished yet",
+    ),
+    (
+        "too_many_tags",
+        "标签不能超过{}个",
+        "At most {} tags are allowed",
+    ),
+    (
+        "tag_invalid",
+        "标签只能包含字母、数字、-和_，且不能超过{}个字符",
+        "Tags may only contain letters, digits, - and _, at most {} characters",
+    ),
+    (
+        "saved_search_not_found",
+        "保存的搜索不存在",
+        "Saved search not found",
+    ),
+    (
+        "saved_search_name_invalid",
+        "搜索名称不能为空且不能超过{}个字符",
+        "Search name must be non-empty and at most {} characters",
+    ),
+    (
+        "saved_search_limit_reached",
+        "最多只能保存{}个搜索",
+        "At most {} searches can be saved",
+    ),
+    (
+        "page_out_of_range",
+        "页码必须在1到{}之间，每页数量必须在1到{}之间",
+        "Page must be between 1 and {}, page size between 1 and {}",
+    ),
+    (
+        "radius_not_positive",
+        "搜索半径必须大于0",
+        "Search radius must be greater than 0",
+    ),
+    (
+        "dog_range_inverted",
+        "狗的数量上限不得小于下限",
+        "Maximum dogs must not be below the minimum",
+    ),
+    (
+        "overloaded",
+        "服务繁忙，请稍后重试",
+        "Service is busy, please retry later",
+    ),
+    ("offer_not_found", "报价不存在", "Offer not found"),
+    (
+        "no_accepter",
+        "代遛请求尚无人接单",
+        "Walk request has no walker yet",
+    ),
+    ("offer_not_open", "该报价已失效", "Offer is no longer open"),
+    (
+        "offer_currency_mismatch",
+        "报价币种与代遛请求不一致",
+        "Offer currency differs from the walk request's",
+    ),
+    ("internal", "服务器内部错误", "Internal server error"),
+];
+
+/// Arguments of `message` when it follows `template`.
+fn arguments<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut literals = template.split("{}");
+    let first = literals.next().unwrap_or_default();
+    let mut rest = message.strip_prefix(first)?;
+    let literals: Vec<&str> = literals.collect();
+    let mut args = Vec::with_capacity(literals.len());
+    for (i, literal) in literals.iter().enumerate() {
+        let end = if i == literals.len() - 1 {
+            if literal.is_empty() {
+                rest.len()
+            } else {
+                rest.strip_suffix(literal).map(str::len)?
+            }
+        } else {
+            rest.find(literal)?
+        };
+        args.push(&rest[..end]);
+        rest = &rest[end + literal.len()..];
+    }
+    rest.is_empty().then_some(args)
+}
+
+fn entry(
+    message: &str,
+) -> Option<(
+    &'static (&'static str, &'static str, &'static str),
+    Vec<&str>,
+)> {
+    CATALOG
+        .iter()
+        .find_map(|e| arguments(e.1, message).map(|args| (e, args)))
+}
+
+/// Reason code of a message of the catalog.
+pub fn reason(message: &str) -> Option<&'static str> {
+    entry(message).map(|(e, _)| e.0)
+}
+
+/// `message` in `locale`, `None` when the catalog doesn't know it.
+/// Arguments are translated too when they are known themselves.
+pub fn translate(message: &str, locale: Locale) -> Option<String> {
+    let (entry, args) = entry(message)?;
+    let template = match locale {
+        Locale::ZhCn => return Some(message.to_owned()),
+        Locale::EnUs => entry.2,
+    };
+    let mut args = args.into_iter();
+    let mut translated = String::new();
+    for (i, literal) in template.split("{}").enumerate() {
+        if i > 0 {
+            let arg = args.next().unwrap_or_default();
+            translated.push_str(&translate(arg, locale).unwrap_or_else(|| arg.to_owned()));
+        }
+        translated.push_str(literal);
+    }
+    Some(translated)
+}
+
+fn is_chinese(text: &str) -> bool {
+    text.chars().any(|c| ('\u{4e00}'..='\u{9fff}').contains(&c))
+}
+
+fn generic(code: &str) -> &'static str {
+    match code {
+        "not_found" => "Not found",
+        "conflict" => "The operation conflicts with the current state",
+        "validation" => "Invalid request",
+        "unauthorized" => "Unauthorized",
+        "onboarding_incomplete" => "Please finish onboarding first",
+        "capability_mismatch" => "You can't take the dogs of this walk request",
+        "feature_disabled" => "This feature isn't available yet",
+        "spending_cap_exceeded" => "This goes over your monthly spending cap, confirm to go ahead",
+        "overloaded" => "Service is busy, please retry later",
+        _ => "Internal server error",
+    }
+}
+
+/// `message` of an error of kind `code` in `locale`. Chinese messages
+/// missing from the catalog fall back to a generic one for the code.
+pub fn localize(message: &str, code: &str, locale: Locale) -> String {
+    translate(message, locale).unwrap_or_else(|| match locale {
+        Locale::EnUs if is_chinese(message) => generic(code).to_owned(),
+        _ => message.to_owned(),
+    })
+}
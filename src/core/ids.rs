@@ -0,0 +1,90 @@
+use chrono::{DateTime, Datelike, Utc};
+use ulid::Ulid;
+
+/// Format of the ids generated for newly created resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdFormat {
+    /// 24 character hex MongoDB ObjectId.
+    #[default]
+    ObjectId,
+    /// 26 character, lexicographically sortable ULID.
+    Ulid,
+}
+
+impl IdFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "object_id" => Some(IdFormat::ObjectId),
+            "ulid" => Some(IdFormat::Ulid),
+            _ => None,
+        }
+    }
+}
+
+/// Accepts ids in the `ObjectId("...")` form older releases returned from
+/// create endpoints, as well as bare ids.
+pub fn normalize_id(id: &str) -> &str {
+    id.strip_prefix("ObjectId(\"")
+        .and_then(|rest| rest.strip_suffix("\")"))
+        .unwrap_or(id)
+}
+
+pub fn is_ulid(id: &str) -> bool {
+    Ulid::from_string(id).is_ok()
+}
+
+pub fn new_ulid() -> String {
+    Ulid::new().to_string()
+}
+
+/// Makes the human-friendly reference codes walk requests get besides their
+/// ids, see `WalkRequest::reference_code`.
+pub trait IdGenerator: Send + Sync {
+    /// The counter numbering the code of a request created at `at`, `None`
+    /// when codes aren't numbered.
+    fn counter(&self, at: DateTime<Utc>) -> Option<String>;
+
+    /// The code of a request created at `at`, `number` drawn from `counter`.
+    fn generate(&self, at: DateTime<Utc>, number: Option<u64>) -> String;
+
+    /// Whether `code` is one of the codes this generator makes, rather than
+    /// an id.
+    fn recognizes(&self, code: &str) -> bool;
+}
+
+/// Codes like `WR-2024-000123`, numbered from 1 each year.
+pub struct YearlySequence {
+    prefix: String,
+}
+
+impl YearlySequence {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_owned(),
+        }
+    }
+}
+
+impl IdGenerator for YearlySequence {
+    fn counter(&self, at: DateTime<Utc>) -> Option<String> {
+        Some(format!("reference_code:{}:{}", self.prefix, at.year()))
+    }
+
+    fn generate(&self, at: DateTime<Utc>, number: Option<u64>) -> String {
+        format!("{}-{}-{:06}", self.prefix, at.year(), number.unwrap_or(0))
+    }
+
+    fn recognizes(&self, code: &str) -> bool {
+        let Some(rest) = code
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|rest| rest.strip_prefix('-'))
+        else {
+            return false;
+        };
+        let Some((year, number)) = rest.split_once('-') else {
+            return false;
+        };
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        year.len() == 4 && digits(year) && digits(number)
+    }
+}
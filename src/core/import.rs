@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    metrics::DEFAULT_REGION,
+    repository::{WalkRequestCreate, WalkRequestUpdate},
+    timezone::DEFAULT_TIMEZONE,
+};
+
+/// Prefix of ledger keys of imported tracks, walk requests use the legacy id.
+pub const TRACK_KEY_PREFIX: &str = "track:";
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DumpFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Which column of the legacy dump holds each field, fields mapped to nothing
+/// are read from the column of the same name.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct FieldMapping(HashMap<String, String>);
+
+impl FieldMapping {
+    fn column<'a>(&'a self, field: &'a str) -> &'a str {
+        self.0.get(field).map_or(field, String::as_str)
+    }
+}
+
+/// One record of a dump, columns to raw values.
+pub type Row = HashMap<String, String>;
+
+/// Reads a JSON array of objects or a CSV file with a header line.
+pub fn parse_dump(format: DumpFormat, dump: &str) -> Result<Vec<Row>, Error> {
+    match format {
+        DumpFormat::Json => {
+            let records: Vec<HashMap<String, Value>> = serde_json::from_str(dump)?;
+            Ok(records
+                .into_iter()
+                .map(|record| {
+                    record
+                        .into_iter()
+                        .filter(|(_, v)| !v.is_null())
+                        .map(|(k, v)| match v {
+                            Value::String(s) => (k, s),
+                            other => (k, other.to_string()),
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+        DumpFormat::Csv => {
+            let mut reader = csv::Reader::from_reader(dump.as_bytes());
+            let headers = reader.headers()?.clone();
+            reader
+                .records()
+                .map(|record| {
+                    Ok(headers
+                        .iter()
+                        .zip(record?.iter())
+                        .filter(|(_, v)| !v.is_empty())
+                        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                        .collect())
+                })
+                .collect()
+        }
+    }
+}
+
+struct Fields<'a> {
+    row: &'a Row,
+    mapping: &'a FieldMapping,
+}
+
+impl<'a> Fields<'a> {
+    fn text(&self, field: &str) -> Option<String> {
+        self.row.get(self.mapping.column(field)).cloned()
+    }
+
+    fn required(&self, field: &str) -> Result<String, String> {
+        self.text(field)
+            .ok_or_else(|| format!("缺少字段: {}", field))
+    }
+
+    fn number(&self, field: &str) -> Result<f64, String> {
+        self.required(field)?
+            .parse()
+            .map_err(|_| format!("无效的数字: {}", field))
+    }
+
+    fn time(&self, field: &str) -> Result<Option<DateTime<Utc>>, String> {
+        self.text(field)
+            .map(|t| {
+                DateTime::parse_from_rfc3339(&t)
+                    .map(|t| t.with_timezone(&Utc))
+                    .map_err(|_| format!("无效的时间: {}", field))
+            })
+            .transpose()
+    }
+}
+
+/// A legacy walk request: the create payload, and the update replaying what
+/// happened to it afterwards.
+pub struct LegacyWalkRequest {
+    pub legacy_id: String,
+    pub create: WalkRequestCreate,
+    pub history: WalkRequestUpdate,
+}
+
+impl LegacyWalkRequest {
+    /// Whether anything happened to the request after it was created.
+    pub fn has_history(&self) -> bool {
+        self.history.accepted_by.is_some()
+            || self.history.canceled_at.is_some()
+            || self.history.started_at.is_some()
+            || self.history.finished_at.is_some()
+    }
+}
+
+pub fn map_walk_request(row: &Row, mapping: &FieldMapping) -> Result<LegacyWalkRequest, String> {
+    let fields = Fields { row, mapping };
+    let latitude = fields.number("latitude")?;
+    let longitude = fields.number("longitude")?;
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err("坐标超出范围".to_owned());
+    }
+    let accepted_by = fields.text("accepted_by");
+    let accepted_at = fields.time("accepted_at")?;
+    if accepted_by.is_some() != accepted_at.is_some() {
+        return Err("accepted_by与accepted_at必须同时存在".to_owned());
+    }
+    Ok(LegacyWalkRequest {
+        legacy_id: fields.required("legacy_id")?,
+        create: WalkRequestCreate {
+            dogs: Vec::new(),
+            should_start_after: fields.time("should_start_after")?,
+            should_start_before: fields.time("should_start_before")?,
+            should_end_before: fields.time("should_end_before")?,
+            should_end_after: fields.time("should_end_after")?,
+            latitude,
+            longitude,
+            timezone: fields
+                .text("timezone")
+                .unwrap_or_else(|| DEFAULT_TIMEZONE.to_owned()),
+            region: fields
+                .text("region")
+                .unwrap_or_else(|| DEFAULT_REGION.to_owned()),
+            max_applicants: None,
+            requirements: Default::default(),
+            track_visibility: Default::default(),
+            max_radius: None,
+            price: None,
+            max_walkers: None,
+            apply_before: None,
+            created_by: fields.required("created_by")?,
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: None,
+            invitation_expires_at: None,
+            tags: Vec::new(),
+        },
+        history: WalkRequestUpdate {
+            accepted_by: accepted_by.clone(),
+            accepted_at,
+            add_to_acceptances: accepted_by,
+            canceled_at: fields.time("canceled_at")?,
+            started_at: fields.time("started_at")?,
+            finished_at: fields.time("finished_at")?,
+            ..Default::default()
+        },
+    })
+}
+
+/// One recorded location of a legacy track.
+pub struct LegacyTrackPoint {
+    pub legacy_request_id: String,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl LegacyTrackPoint {
+    /// Ledger key, a point is identified by its request and timestamp.
+    pub fn key(&self) -> String {
+        format!(
+            "{}{}@{}",
+            TRACK_KEY_PREFIX,
+            self.legacy_request_id,
+            self.recorded_at.to_rfc3339()
+        )
+    }
+}
+
+pub fn map_track_point(row: &Row, mapping: &FieldMapping) -> Result<LegacyTrackPoint, String> {
+    let fields = Fields { row, mapping };
+    Ok(LegacyTrackPoint {
+        legacy_request_id: fields.required("legacy_request_id")?,
+        longitude: fields.number("longitude")?,
+        latitude: fields.number("latitude")?,
+        recorded_at: fields
+            .time("recorded_at")?
+            .ok_or_else(|| "缺少字段: recorded_at".to_owned())?,
+    })
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportIssue {
+    /// `walk_requests` or `tracks`.
+    pub dump: &'static str,
+    /// Zero based position of the record in its dump.
+    pub record: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub walk_requests_imported: u64,
+    pub walk_requests_skipped: u64,
+    pub track_points_imported: u64,
+    pub track_points_skipped: u64,
+    pub issues: Vec<ImportIssue>,
+}
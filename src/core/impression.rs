@@ -0,0 +1,47 @@
+use anyhow::Error;
+use sha2::{Digest, Sha256};
+
+/// Most request ids one impression batch may carry, about a few pages of a
+/// feed.
+pub const MAX_IMPRESSIONS_PER_BATCH: usize = 100;
+
+/// Which walkers' impressions are recorded. Walkers are picked by a hash of
+/// their id so that the sample stays the same walkers, and each recorded
+/// impression counts for the walkers left out.
+#[derive(Debug, Clone, Copy)]
+pub struct ImpressionSampling {
+    rate: f64,
+}
+
+impl Default for ImpressionSampling {
+    fn default() -> Self {
+        Self { rate: 1.0 }
+    }
+}
+
+impl ImpressionSampling {
+    pub fn new(rate: f64) -> Result<Self, Error> {
+        if !(rate > 0.0 && rate <= 1.0) {
+            return Err(Error::msg(format!(
+                "无效的曝光采样率: {}，需在 (0, 1] 之间",
+                rate
+            )));
+        }
+        Ok(Self { rate })
+    }
+
+    pub fn sampled(&self, user_id: &str) -> bool {
+        if self.rate >= 1.0 {
+            return true;
+        }
+        let digest = Sha256::digest(user_id.as_bytes());
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        (u64::from_be_bytes(bytes) as f64 / u64::MAX as f64) < self.rate
+    }
+
+    /// How many impressions one recorded impression stands for.
+    pub fn weight(&self) -> u64 {
+        (1.0 / self.rate).round() as u64
+    }
+}
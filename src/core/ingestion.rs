@@ -0,0 +1,156 @@
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_web::rt::time::timeout;
+use chrono::{DateTime, Utc};
+use futures::{
+    channel::{
+        mpsc::{channel, Sender},
+        oneshot,
+    },
+    StreamExt,
+};
+
+use super::{
+    entities::WalkingLocation,
+    live::LocationBroker,
+    repository::{Repository, WalkingLocationCreate},
+};
+
+/// A walking location waiting to be written.
+#[derive(Debug, Clone)]
+pub struct QueuedLocation {
+    pub walk_request_id: String,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// The queue had no room, the client should retry after `retry_after`.
+#[derive(Debug, Clone, Copy)]
+pub struct QueueFull {
+    pub retry_after: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocationQueueConfig {
+    /// Locations buffered at most, beyond which writes are shed.
+    pub capacity: usize,
+    /// Most locations per bulk insert.
+    pub batch_size: usize,
+    /// How long the flusher waits to fill a batch.
+    pub flush_interval: Duration,
+    /// Suggested to clients whose writes were shed.
+    pub retry_after: Duration,
+}
+
+/// Buffers single location writes in a bounded queue flushed by bulk
+/// inserts in the background, so that spikes wait in memory rather than on
+/// the database. Writes are shed once the queue is full.
+#[derive(Clone)]
+pub struct LocationQueue {
+    /// Shared rather than cloned, each clone of a sender gets a slot of its
+    /// own past the capacity.
+    sender: Arc<Mutex<Sender<QueuedLocation>>>,
+    retry_after: Duration,
+    flushed: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+}
+
+impl LocationQueue {
+    /// The queue and its flusher, which must be spawned. Stored locations
+    /// are published to `broker`.
+    pub fn new<R>(
+        repository: R,
+        broker: Option<LocationBroker>,
+        config: LocationQueueConfig,
+    ) -> (Self, impl Future<Output = ()>)
+    where
+        R: Repository + 'static,
+    {
+        let (sender, mut receiver) = channel::<QueuedLocation>(config.capacity);
+        let (done, flushed) = oneshot::channel();
+        let flusher = async move {
+            let mut open = true;
+            while open {
+                let Some(first) = receiver.next().await else {
+                    break;
+                };
+                let mut batch = vec![first];
+                while batch.len() < config.batch_size {
+                    match timeout(config.flush_interval, receiver.next()).await {
+                        Ok(Some(location)) => batch.push(location),
+                        Ok(None) => {
+                            open = false;
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                flush(&repository, broker.as_ref(), batch).await;
+            }
+            let _ = done.send(());
+        };
+        (
+            Self {
+                sender: Arc::new(Mutex::new(sender)),
+                retry_after: config.retry_after,
+                flushed: Arc::new(Mutex::new(Some(flushed))),
+            },
+            flusher,
+        )
+    }
+
+    pub fn push(&self, location: QueuedLocation) -> Result<(), QueueFull> {
+        self.sender
+            .lock()
+            .unwrap()
+            .try_send(location)
+            .map_err(|_| QueueFull {
+                retry_after: self.retry_after,
+            })
+    }
+
+    /// Stops taking locations and waits until the queued ones are written.
+    pub async fn close(&self) {
+        self.sender.lock().unwrap().close_channel();
+        let flushed = self.flushed.lock().unwrap().take();
+        if let Some(flushed) = flushed {
+            let _ = flushed.await;
+        }
+    }
+}
+
+async fn flush<R>(repository: &R, broker: Option<&LocationBroker>, batch: Vec<QueuedLocation>)
+where
+    R: Repository,
+{
+    let creates = batch
+        .iter()
+        .map(|l| WalkingLocationCreate {
+            walk_request_id: &l.walk_request_id,
+            longitude: l.longitude,
+            latitude: l.latitude,
+            recorded_at: Some(l.recorded_at),
+        })
+        .collect();
+    match repository.create_walking_locations(creates).await {
+        Ok(ids) => {
+            let Some(broker) = broker else {
+                return;
+            };
+            for (id, location) in ids.into_iter().zip(batch) {
+                broker.publish(&WalkingLocation {
+                    id,
+                    request_id: location.walk_request_id,
+                    longitude: location.longitude,
+                    latitude: location.latitude,
+                    created_at: Some(location.recorded_at),
+                });
+            }
+        }
+        Err(e) => log::error!("failed to write {} queued locations: {}", batch.len(), e),
+    }
+}
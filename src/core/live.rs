@@ -0,0 +1,266 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use async_trait::async_trait;
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    stream::LocalBoxStream,
+    Future, StreamExt,
+};
+
+use super::{
+    entities::WalkingLocation,
+    events::{WalkRequestEvent, WalkRequestEventKind},
+};
+
+/// How long an event this instance published is remembered, to recognize
+/// it when the change stream brings it back.
+const ECHO_WINDOW: Duration = Duration::from_secs(30);
+
+type Subscriber = (Option<String>, UnboundedSender<WalkingLocation>);
+
+/// Carries walking locations between the instances behind a load balancer,
+/// each subscribed to the locations of the others.
+#[async_trait]
+pub trait LocationBus: Send + Sync {
+    /// Sends a location recorded on this instance to the others.
+    async fn publish(&self, location: &WalkingLocation) -> Result<(), Error>;
+    /// The locations other instances publish from now on, ending when the
+    /// connection is lost.
+    async fn subscribe(&self) -> Result<LocalBoxStream<'static, WalkingLocation>, Error>;
+}
+
+/// In-process pub/sub of walking locations keyed by walk request id. Closed
+/// subscriptions are dropped on the next publish.
+#[derive(Clone, Default)]
+pub struct LocationBroker {
+    /// With the device session each subscription was made from, if any.
+    subscribers: Arc<Mutex<HashMap<String, Vec<Subscriber>>>>,
+    bus: Option<Arc<dyn LocationBus>>,
+    /// Locations to pass on to `bus`.
+    relayed: Option<UnboundedSender<WalkingLocation>>,
+}
+
+impl LocationBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A broker which also passes locations on to other instances through
+    /// `bus`, and its relay, which must be spawned. The locations of other
+    /// instances are delivered by `listen`.
+    pub fn with_bus(bus: Arc<dyn LocationBus>) -> (Self, impl Future<Output = ()>) {
+        let (tx, mut rx) = unbounded::<WalkingLocation>();
+        let relay = {
+            let bus = bus.clone();
+            async move {
+                while let Some(location) = rx.next().await {
+                    if let Err(e) = bus.publish(&location).await {
+                        log::error!(
+                            "failed to relay location of walk request {}: {}",
+                            location.request_id,
+                            e
+                        );
+                    }
+                }
+            }
+        };
+        let broker = Self {
+            bus: Some(bus),
+            relayed: Some(tx),
+            ..Self::default()
+        };
+        (broker, relay)
+    }
+
+    /// Delivers the locations other instances publish until the bus
+    /// connection is lost, to be started again by a supervisor.
+    pub async fn listen(self) {
+        let Some(bus) = &self.bus else {
+            return;
+        };
+        let mut locations = match bus.subscribe().await {
+            Ok(locations) => locations,
+            Err(e) => {
+                log::error!("failed to subscribe to relayed locations: {}", e);
+                return;
+            }
+        };
+        while let Some(location) = locations.next().await {
+            self.deliver(&location);
+        }
+    }
+
+    pub fn subscribe(
+        &self,
+        request_id: &str,
+        session_id: Option<&str>,
+    ) -> UnboundedReceiver<WalkingLocation> {
+        let (tx, rx) = unbounded();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(request_id.to_owned())
+            .or_default()
+            .push((session_id.map(str::to_owned), tx));
+        rx
+    }
+
+    /// Ends the streams opened from the device session.
+    pub fn close_session(&self, session_id: &str) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        for senders in subscribers.values_mut() {
+            senders.retain(|(session, _)| session.as_deref() != Some(session_id));
+        }
+        subscribers.retain(|_, senders| !senders.is_empty());
+    }
+
+    /// Ends every stream, for shutdown.
+    pub fn close_all(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+
+    /// Delivers a location recorded on this instance to its subscribers
+    /// here and, with a bus, on every other instance.
+    pub fn publish(&self, location: &WalkingLocation) {
+        if let Some(relayed) = &self.relayed {
+            let _ = relayed.unbounded_send(location.clone());
+        }
+        self.deliver(location);
+    }
+
+    fn deliver(&self, location: &WalkingLocation) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(senders) = subscribers.get_mut(&location.request_id) {
+            senders.retain(|(_, tx)| tx.unbounded_send(location.clone()).is_ok());
+            if senders.is_empty() {
+                subscribers.remove(&location.request_id);
+            }
+        }
+    }
+}
+
+/// In-process pub/sub of the events of each walk request, so long-polling
+/// clients wake up as soon as their request changes.
+#[derive(Clone, Default)]
+pub struct ChangeBroker {
+    subscribers: Arc<Mutex<HashMap<String, Vec<UnboundedSender<WalkRequestEvent>>>>>,
+    /// Recently published by this instance, oldest first.
+    published: Arc<Mutex<VecDeque<(String, WalkRequestEventKind, Instant)>>>,
+}
+
+impl ChangeBroker {
+    pub fn subscribe(&self, request_id: &str) -> UnboundedReceiver<WalkRequestEvent> {
+        let (tx, rx) = unbounded();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(request_id.to_owned())
+            .or_default()
+            .push(tx);
+        rx
+    }
+
+    /// Wakes every long poll with its channel closed, for shutdown.
+    pub fn close_all(&self) {
+        self.subscribers.lock().unwrap().clear();
+    }
+
+    pub fn publish(&self, event: &WalkRequestEvent) {
+        let now = Instant::now();
+        let mut published = self.published.lock().unwrap();
+        while published
+            .front()
+            .map_or(false, |(_, _, at)| now - *at > ECHO_WINDOW)
+        {
+            published.pop_front();
+        }
+        published.push_back((event.request_id.clone(), event.kind, now));
+        drop(published);
+        self.deliver(event);
+    }
+
+    /// Publishes an event another instance caused, returning `false` when
+    /// it was this instance's own, already published.
+    pub fn publish_remote(&self, event: &WalkRequestEvent) -> bool {
+        let mut published = self.published.lock().unwrap();
+        let own = published
+            .iter()
+            .position(|(id, kind, at)| {
+                *id == event.request_id && *kind == event.kind && at.elapsed() <= ECHO_WINDOW
+            })
+            .and_then(|i| published.remove(i));
+        drop(published);
+        if own.is_some() {
+            return false;
+        }
+        self.deliver(event);
+        true
+    }
+
+    fn deliver(&self, event: &WalkRequestEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if let Some(senders) = subscribers.get_mut(&event.request_id) {
+            senders.retain(|tx| tx.unbounded_send(event.clone()).is_ok());
+            if senders.is_empty() {
+                subscribers.remove(&event.request_id);
+            }
+        }
+    }
+}
+
+/// A write to a walk request read back from the database, which any
+/// instance may have made.
+#[derive(Debug, Clone)]
+pub struct WalkRequestChange {
+    pub request_id: String,
+    pub kind: WalkRequestEventKind,
+    pub actor: Option<String>,
+}
+
+impl WalkRequestChange {
+    /// The transition an update of the request's `fields` stands for, each
+    /// paired with whether it was set rather than cleared. Updates which
+    /// aren't transitions, or can't be told apart, give `None`.
+    pub fn kind_of_update<'a>(
+        fields: impl IntoIterator<Item = (&'a str, bool)>,
+    ) -> Option<WalkRequestEventKind> {
+        use WalkRequestEventKind::*;
+        let fields: HashMap<&str, bool> = fields.into_iter().collect();
+        let set = |field: &str| fields.get(field).copied();
+        [
+            (set("deleted_at") == Some(true), Deleted),
+            (set("canceled_at") == Some(true), Canceled),
+            (set("finished_at") == Some(true), Finished),
+            (set("started_at") == Some(true), Started),
+            (set("accepted_by") == Some(true), Accepted),
+            (set("cancel_requested_at") == Some(true), CancelRequested),
+            (set("cancel_requested_at") == Some(false), CancelUndone),
+            (set("geofence_violated_at") == Some(true), GeofenceViolated),
+            (set("expired_at") == Some(true), Expired),
+            (set("accepted_by") == Some(false), Dismissed),
+        ]
+        .into_iter()
+        .find_map(|(matches, kind)| matches.then_some(kind))
+    }
+
+    /// The field of the request holding the user who caused `kind`.
+    pub fn actor_field(kind: WalkRequestEventKind) -> Option<&'static str> {
+        match kind {
+            WalkRequestEventKind::Created
+            | WalkRequestEventKind::CancelRequested
+            | WalkRequestEventKind::CancelUndone
+            | WalkRequestEventKind::Dismissed => Some("created_by"),
+            WalkRequestEventKind::Canceled => Some("canceled_by"),
+            WalkRequestEventKind::Accepted
+            | WalkRequestEventKind::Started
+            | WalkRequestEventKind::Finished
+            | WalkRequestEventKind::GeofenceViolated => Some("accepted_by"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::{geo::distance, units::Meters};
+
+/// Requests tracked before points older than `STALE_AFTER_MINUTES` are
+/// forgotten.
+const MAX_TRACKED_REQUESTS: usize = 4096;
+
+const STALE_AFTER_MINUTES: i64 = 10;
+
+/// What became of a single location write.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", content = "id", rename_all = "snake_case")]
+pub enum LocationWrite {
+    Stored(String),
+    /// Buffered by the location queue, stored shortly.
+    Queued,
+    /// Too close to the previous point of the walk, dropped.
+    Coalesced,
+}
+
+/// Drops GPS duplicates and jitter: a point within `min_distance` of, or
+/// sooner than `min_interval` after, the previous kept point of the same
+/// request is coalesced into it. A zero threshold is not checked.
+///
+/// Previous points are remembered per instance, so the first point an
+/// instance sees of a walk is always kept.
+#[derive(Debug, Clone)]
+pub struct LocationFilter {
+    min_distance: Meters,
+    min_interval: Duration,
+    last: Arc<Mutex<HashMap<String, ((f64, f64), DateTime<Utc>)>>>,
+}
+
+impl Default for LocationFilter {
+    fn default() -> Self {
+        Self::new(Meters(0.0), Duration::zero())
+    }
+}
+
+impl LocationFilter {
+    pub fn new(min_distance: Meters, min_interval: Duration) -> Self {
+        Self {
+            min_distance,
+            min_interval,
+            last: Arc::default(),
+        }
+    }
+
+    fn is_disabled(&self) -> bool {
+        self.min_distance.value() <= 0.0 && self.min_interval <= Duration::zero()
+    }
+
+    /// Whether the point, as (longitude, latitude), is worth keeping, in
+    /// which case it becomes the previous point of the request.
+    pub fn keep(&self, request_id: &str, point: (f64, f64), at: DateTime<Utc>) -> bool {
+        if self.is_disabled() {
+            return true;
+        }
+        let mut last = self.last.lock().unwrap();
+        if let Some((previous, previous_at)) = last.get(request_id) {
+            let too_close = distance(*previous, point) < self.min_distance;
+            let too_soon = at - *previous_at < self.min_interval;
+            if too_close || too_soon {
+                return false;
+            }
+        }
+        if last.len() >= MAX_TRACKED_REQUESTS {
+            let stale = at - Duration::minutes(STALE_AFTER_MINUTES);
+            last.retain(|_, (_, kept_at)| *kept_at > stale);
+        }
+        last.insert(request_id.to_owned(), (point, at));
+        true
+    }
+}
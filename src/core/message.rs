@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Longest message body, in characters.
+pub const MAX_MESSAGE_CHARS: usize = 1000;
+
+/// Most messages returned at once, clients page on with `after`.
+pub const MESSAGE_PAGE_SIZE: i64 = 100;
+
+/// A chat message between the owner and the walker of a walk request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub id: String,
+    pub request_id: String,
+    pub sender: String,
+    pub body: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MessageCreate {
+    pub body: String,
+}
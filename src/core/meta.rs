@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+use super::{
+    feature_flags::{Feature, FeatureFlag},
+    units::Meters,
+};
+
+pub const API_VERSIONS: &[&str] = &["v1", "v2"];
+
+/// What this deployment supports, served at `/apis/meta` so clients can adapt
+/// instead of hardcoding per-deployment differences.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub api_versions: &'static [&'static str],
+    /// The features, on where the deployment supports them and their flag
+    /// is on for the caller, see `with_features`.
+    pub payments: bool,
+    pub chat: bool,
+    pub live_tracking: bool,
+    pub email_notifications: bool,
+    /// How live walking locations are delivered.
+    pub streaming: &'static str,
+    /// Empty means all regions are served.
+    pub regions: Vec<String>,
+    pub max_nearby_radius: Option<Meters>,
+    pub max_applicants: Option<i64>,
+    /// ISO 4217 code of regions without a currency of their own.
+    pub currency: String,
+}
+
+impl Capabilities {
+    /// Turns off the features whose flag is off for `user_id`, or off for
+    /// everyone when the caller is anonymous.
+    pub fn with_features(mut self, flags: &[FeatureFlag], user_id: Option<&str>) -> Self {
+        for flag in flags {
+            let on =
+                flag.enabled && user_id.map_or(flag.rollout_percent > 0, |u| flag.enabled_for(u));
+            let supported = match flag.feature {
+                Feature::Payments => &mut self.payments,
+                Feature::Chat => &mut self.chat,
+                Feature::LiveTracking => &mut self.live_tracking,
+            };
+            *supported &= on;
+        }
+        self
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::entities::WalkRequest;
+
+pub const DEFAULT_REGION: &str = "default";
+
+/// Most creation days one funnel query covers.
+pub const MAX_FUNNEL_DAYS: i64 = 31;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyMetrics {
+    pub region: String,
+    pub date: NaiveDate,
+    pub created: u64,
+    pub accepted: u64,
+    pub finished: u64,
+    pub canceled: u64,
+    pub sla_breaches: u64,
+    pub fill_rate: f64,
+    pub median_time_to_accept_seconds: Option<i64>,
+}
+
+fn on(day: NaiveDate, time: Option<DateTime<Utc>>) -> bool {
+    time.map_or(false, |t| t.date_naive() == day)
+}
+
+/// Rolls the requests touched on `day` up into one row per region. Fill rate is
+/// the share of requests created that day which have been accepted.
+pub fn rollup(day: NaiveDate, requests: &[WalkRequest]) -> Vec<DailyMetrics> {
+    let mut regions: BTreeMap<String, (DailyMetrics, u64, Vec<i64>)> = BTreeMap::new();
+    for request in requests {
+        let region = request
+            .region
+            .clone()
+            .unwrap_or_else(|| DEFAULT_REGION.to_owned());
+        let (metrics, filled, waits) = regions.entry(region.clone()).or_insert_with(|| {
+            (
+                DailyMetrics {
+                    region,
+                    date: day,
+                    ..Default::default()
+                },
+                0,
+                Vec::new(),
+            )
+        });
+        if on(day, request.created_at) {
+            metrics.created += 1;
+            if request.accepted_at.is_some() {
+                *filled += 1;
+            }
+        }
+        if on(day, request.accepted_at) {
+            metrics.accepted += 1;
+            if let (Some(created_at), Some(accepted_at)) = (request.created_at, request.accepted_at)
+            {
+                waits.push((accepted_at - created_at).num_seconds());
+            }
+        }
+        if on(day, request.finished_at) {
+            metrics.finished += 1;
+        }
+        if on(day, request.canceled_at) {
+            metrics.canceled += 1;
+        }
+        if on(day, request.sla_breached_at) {
+            metrics.sla_breaches += 1;
+        }
+    }
+    regions
+        .into_values()
+        .map(|(mut metrics, filled, mut waits)| {
+            if metrics.created > 0 {
+                metrics.fill_rate = filled as f64 / metrics.created as f64;
+            }
+            waits.sort_unstable();
+            metrics.median_time_to_accept_seconds = waits.get(waits.len() / 2).copied();
+            metrics
+        })
+        .collect()
+}
+
+/// Funnel steps which are not recorded on the walk request itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunnelStage {
+    /// A walker saw the request in a feed.
+    Viewed,
+    /// A walker applied to the request.
+    Applied,
+}
+
+impl FunnelStage {
+    pub fn field(self) -> &'static str {
+        match self {
+            FunnelStage::Viewed => "first_viewed_at",
+            FunnelStage::Applied => "first_applied_at",
+        }
+    }
+}
+
+/// When a walk request first reached each of the `FunnelStage`s.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunnelMarks {
+    pub request_id: String,
+    pub first_viewed_at: Option<DateTime<Utc>>,
+    pub first_applied_at: Option<DateTime<Utc>>,
+    /// Times walkers saw the request, estimated when impressions are
+    /// sampled. Lets matching favour requests few walkers have seen.
+    #[serde(default)]
+    pub impressions: u64,
+}
+
+/// How far the requests created in a region on one day made it, each count
+/// is a subset of the one before.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FunnelMetrics {
+    pub region: String,
+    /// The day the requests were created.
+    pub cohort: NaiveDate,
+    pub created: u64,
+    /// Impressions of all requests of the cohort, not part of the funnel.
+    pub impressions: u64,
+    pub viewed: u64,
+    pub applied: u64,
+    pub accepted: u64,
+    pub started: u64,
+    pub finished: u64,
+}
+
+/// Groups `requests` into cohorts by region and creation day. A request only
+/// counts at a step when it reached every step before, so that requests
+/// accepted without being seen in a feed drop off at `viewed`.
+pub fn funnel(requests: &[WalkRequest], marks: &[FunnelMarks]) -> Vec<FunnelMetrics> {
+    let marks: BTreeMap<&str, &FunnelMarks> =
+        marks.iter().map(|m| (m.request_id.as_str(), m)).collect();
+    let mut cohorts: BTreeMap<(String, NaiveDate), FunnelMetrics> = BTreeMap::new();
+    for request in requests {
+        let Some(created_at) = request.created_at else {
+            continue;
+        };
+        let region = request
+            .region
+            .clone()
+            .unwrap_or_else(|| DEFAULT_REGION.to_owned());
+        let cohort = created_at.date_naive();
+        let metrics = cohorts
+            .entry((region.clone(), cohort))
+            .or_insert_with(|| FunnelMetrics {
+                region,
+                cohort,
+                ..Default::default()
+            });
+        let mark = marks.get(request.id.as_str());
+        let steps = [
+            mark.map_or(false, |m| m.first_viewed_at.is_some()),
+            mark.map_or(false, |m| m.first_applied_at.is_some()),
+            request.accepted_at.is_some(),
+            request.started_at.is_some(),
+            request.finished_at.is_some(),
+        ];
+        let reached = steps.iter().take_while(|s| **s).count();
+        metrics.created += 1;
+        metrics.impressions += mark.map_or(0, |m| m.impressions);
+        let counts = [
+            &mut metrics.viewed,
+            &mut metrics.applied,
+            &mut metrics.accepted,
+            &mut metrics.started,
+            &mut metrics.finished,
+        ];
+        for count in counts.into_iter().take(reached) {
+            *count += 1;
+        }
+    }
+    cohorts.into_values().collect()
+}
@@ -1,3 +1,80 @@
+pub mod admin_query;
+pub mod alert;
+pub mod archive;
+pub mod audit;
+pub mod auth;
+pub mod backfill;
+pub mod bulk;
+pub mod cache;
+pub mod calendar;
+pub mod card;
+pub mod client_time;
+pub mod condition;
+pub mod correlation;
+pub mod currency;
+pub mod delegation;
+pub mod distance;
+pub mod email;
 pub mod entities;
+pub mod error;
+pub mod events;
+pub mod experiments;
+pub mod export;
+pub mod favorite;
+pub mod feature_flags;
+pub mod feed;
+pub mod filter;
+pub mod fitness;
+pub mod geo;
+pub mod geojson;
+pub mod holiday;
+pub mod i18n;
+pub mod ids;
+pub mod import;
+pub mod impression;
+pub mod ingestion;
+pub mod live;
+pub mod location_filter;
+pub mod message;
+pub mod meta;
+pub mod metrics;
+pub mod notification;
+pub mod offer;
+pub mod onboarding;
+pub mod outbox;
+pub mod payment;
+pub mod preview;
+pub mod profile;
+pub mod ranking;
+pub mod rate_limit;
+pub mod receipt;
+pub mod recompute;
+pub mod reconcile;
+pub mod reminder;
+pub mod report;
 pub mod repository;
+pub mod reputation;
+pub mod research;
+pub mod retention;
+pub mod route_map;
+pub mod routing;
+pub mod saga;
+pub mod saved_search;
+pub mod schedule;
+pub mod security;
 pub mod service;
+pub mod session;
+pub mod simulation;
+pub mod sla;
+pub mod spending;
+pub mod strike;
+pub mod tag;
+pub mod template;
+pub mod tenant;
+pub mod timezone;
+pub mod units;
+pub mod usage;
+pub mod validation;
+pub mod verification;
+pub mod walk_budget;
+pub mod walker_capabilities;
@@ -0,0 +1,101 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    /// To the owner, a walker applied.
+    Applied,
+    /// To the owner, the walk started.
+    Started,
+    /// To the owner, the walk finished.
+    Finished,
+    /// To the owner, the walker went beyond the request's `max_radius`.
+    GeofenceViolated,
+    /// To the walker, the owner picked them.
+    Assigned,
+    /// To the walker, the owner turned them down.
+    Dismissed,
+    /// To the owner, the walker didn't show up and the request is open
+    /// again.
+    NoShow,
+    /// To the owner, the walker didn't show up and the start window closed,
+    /// the request expired.
+    NoShowExpired,
+    /// To the other party of a price negotiation, a price was offered.
+    Offered,
+    /// To whoever offered the price, it was agreed to.
+    OfferAccepted,
+    /// To the owner and the walker, the walk starts soon.
+    StartReminder,
+    /// To a favorite walker, the owner sent them a request directly.
+    Invited,
+    /// To the owner, the invited walker declined, the request is open to
+    /// everyone.
+    InvitationDeclined,
+    /// To a walker, a new request turned up in one of their saved
+    /// searches.
+    SavedSearchMatched,
+}
+
+impl NotificationKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            NotificationKind::Applied => "有人报名了您的代遛请求",
+            NotificationKind::Started => "遛狗已开始",
+            NotificationKind::Finished => "遛狗已结束",
+            NotificationKind::GeofenceViolated => "遛狗人已超出活动范围",
+            NotificationKind::Assigned => "您已被选为遛狗人",
+            NotificationKind::Dismissed => "您的报名未被接受",
+            NotificationKind::NoShow => "遛狗人未按时开始，请求已重新开放",
+            NotificationKind::NoShowExpired => "遛狗人未按时开始，请求已过期",
+            NotificationKind::Offered => "您收到了新的报价",
+            NotificationKind::OfferAccepted => "您的报价已被接受",
+            NotificationKind::StartReminder => "遛狗即将开始",
+            NotificationKind::Invited => "您收到了代遛邀请",
+            NotificationKind::InvitationDeclined => "遛狗人谢绝了您的邀请，请求已公开",
+            NotificationKind::SavedSearchMatched => "有新的代遛请求符合您保存的搜索",
+        }
+    }
+}
+
+/// A push notification about a walk request to one user's devices.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub user_id: String,
+    pub kind: NotificationKind,
+    pub request_id: String,
+    /// Push tokens of the devices to reach, the user's topic when `None`.
+    pub devices: Option<Vec<String>>,
+    /// Shown under the title, such as when the walk starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+impl Notification {
+    pub fn new(user_id: &str, kind: NotificationKind, request_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            kind,
+            request_id: request_id.to_owned(),
+            devices: None,
+            body: None,
+        }
+    }
+
+    pub fn with_body(mut self, body: String) -> Self {
+        self.body = Some(body);
+        self
+    }
+
+    pub fn to_devices(mut self, devices: Option<Vec<String>>) -> Self {
+        self.devices = devices;
+        self
+    }
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, notification: &Notification) -> Result<(), Error>;
+}
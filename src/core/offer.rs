@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{ids::new_ulid, units::Money};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OfferState {
+    /// Waiting for the other party to accept or counter it.
+    Open,
+    /// Superseded by a later offer of the same negotiation.
+    Countered,
+    /// Agreed on, the price the walk is assigned at.
+    Accepted,
+}
+
+/// One round of the price negotiation between the owner of a request and a
+/// walker who applied to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Offer {
+    pub id: String,
+    pub request_id: String,
+    /// The walker the negotiation is with, whoever made the offer.
+    pub walker_id: String,
+    pub offered_by: String,
+    pub price: Money,
+    pub state: OfferState,
+    /// The offer this one counters.
+    pub counters: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Offer {
+    pub fn new(request_id: &str, walker_id: &str, offered_by: &str, price: Money) -> Self {
+        let now = Utc::now();
+        Self {
+            id: new_ulid(),
+            request_id: request_id.to_owned(),
+            walker_id: walker_id.to_owned(),
+            offered_by: offered_by.to_owned(),
+            price,
+            state: OfferState::Open,
+            counters: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Whether the offer is up to `user_id` to accept or counter, it always
+    /// is to the party who didn't make it.
+    pub fn awaits(&self, user_id: &str, owner_id: Option<&str>) -> bool {
+        if self.offered_by == self.walker_id {
+            owner_id == Some(user_id)
+        } else {
+            self.walker_id == user_id
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OfferCreate {
+    pub price: Money,
+}
@@ -0,0 +1,37 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+/// Steps a walker finishes in the user service before taking walks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OnboardingStep {
+    DocumentsSubmitted,
+    TrainingCompleted,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Onboarding {
+    pub documents_submitted: bool,
+    pub training_completed: bool,
+}
+
+impl Onboarding {
+    pub fn missing_steps(&self) -> Vec<OnboardingStep> {
+        let mut missing = Vec::new();
+        if !self.documents_submitted {
+            missing.push(OnboardingStep::DocumentsSubmitted);
+        }
+        if !self.training_completed {
+            missing.push(OnboardingStep::TrainingCompleted);
+        }
+        missing
+    }
+}
+
+/// Source of walkers' onboarding state, owned by the user service.
+#[async_trait]
+pub trait OnboardingDirectory: Send + Sync {
+    async fn onboarding(&self, user_id: &str) -> Result<Onboarding, Error>;
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::WalkRequest,
+    events::{WalkRequestEvent, WalkRequestEventKind},
+    ids::new_ulid,
+    notification::{Notification, NotificationKind},
+};
+
+/// Delivery attempts before a message is dead-lettered.
+pub const MAX_OUTBOX_ATTEMPTS: u32 = 8;
+
+/// Pause after the first failed attempt, doubled after every further one.
+const OUTBOX_BASE_BACKOFF_SECONDS: i64 = 10;
+
+/// Messages delivered per dispatcher run.
+pub const OUTBOX_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutboxPayload {
+    /// To the event publisher, e.g. the webhook.
+    Event(WalkRequestEvent),
+    /// To the user's devices.
+    Notification(Notification),
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Pending,
+    /// Given up after `MAX_OUTBOX_ATTEMPTS`, only delivered again when
+    /// requeued by an admin.
+    DeadLettered,
+}
+
+/// An event of a state change, stamped when it is written to the outbox.
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    pub kind: WalkRequestEventKind,
+    pub request_id: String,
+    pub actor: String,
+    pub subjects: Vec<String>,
+}
+
+/// What a state change publishes, written to the outbox in the transaction
+/// of the change, see `Service::transition`.
+#[derive(Debug, Default)]
+pub struct Publications {
+    pub events: Vec<PendingEvent>,
+    pub notifications: Vec<Notification>,
+}
+
+impl Publications {
+    pub fn event(kind: WalkRequestEventKind, request_id: &str, actor: &str) -> Self {
+        Self::default().emit(kind, request_id, actor)
+    }
+
+    pub fn emit(self, kind: WalkRequestEventKind, request_id: &str, actor: &str) -> Self {
+        self.emit_about(kind, request_id, actor, Vec::new())
+    }
+
+    /// An event about `subjects`, users other than the actor.
+    pub fn emit_about(
+        mut self,
+        kind: WalkRequestEventKind,
+        request_id: &str,
+        actor: &str,
+        subjects: Vec<String>,
+    ) -> Self {
+        self.events.push(PendingEvent {
+            kind,
+            request_id: request_id.to_owned(),
+            actor: actor.to_owned(),
+            subjects,
+        });
+        self
+    }
+
+    pub fn notify(mut self, user_id: &str, kind: NotificationKind, request_id: &str) -> Self {
+        self.notifications
+            .push(Notification::new(user_id, kind, request_id));
+        self
+    }
+
+    /// A notification built by the caller, with a body say.
+    pub fn notify_with(mut self, notification: Notification) -> Self {
+        self.notifications.push(notification);
+        self
+    }
+
+    /// Notifies the owner of the request, if it has one.
+    pub fn notify_owner(self, request: &WalkRequest, kind: NotificationKind) -> Self {
+        match &request.created_by {
+            Some(owner) => self.notify(owner, kind, &request.id),
+            None => self,
+        }
+    }
+}
+
+/// Something to deliver outside the service, written before delivery is
+/// tried so it survives the process and is retried until it goes through.
+/// Delivered messages are deleted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub id: String,
+    pub payload: OutboxPayload,
+    pub status: OutboxStatus,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl OutboxMessage {
+    pub fn new(payload: OutboxPayload) -> Self {
+        let now = Utc::now();
+        Self {
+            id: new_ulid(),
+            payload,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+        }
+    }
+
+    /// Records a failed attempt, backing off exponentially and dead-lettering
+    /// the message once it ran out of attempts.
+    pub fn failed(&mut self, error: &str, now: DateTime<Utc>) {
+        self.attempts += 1;
+        self.last_error = Some(error.to_owned());
+        if self.attempts >= MAX_OUTBOX_ATTEMPTS {
+            self.status = OutboxStatus::DeadLettered;
+            return;
+        }
+        let backoff = OUTBOX_BASE_BACKOFF_SECONDS << (self.attempts - 1).min(20);
+        self.next_attempt_at = now + Duration::seconds(backoff);
+    }
+
+    /// Gives a dead-lettered message a fresh set of attempts.
+    pub fn requeue(&mut self, now: DateTime<Utc>) {
+        self.status = OutboxStatus::Pending;
+        self.attempts = 0;
+        self.next_attempt_at = now;
+    }
+}
@@ -0,0 +1,70 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::units::Money;
+
+/// Charges owners for finished walks.
+#[async_trait]
+pub trait PaymentGateway: Send + Sync {
+    /// Charges the payer the payment's amount, returns the charge id.
+    async fn charge(&self, payment: &Payment) -> Result<String, Error>;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub enum PaymentStatus {
+    Pending,
+    Charged,
+    Failed,
+}
+
+/// The charge of one finished walk, keyed by the walk request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Payment {
+    pub id: String,
+    pub request_id: String,
+    /// The owner.
+    pub payer_id: String,
+    /// The walker.
+    pub payee_id: Option<String>,
+    pub amount: Money,
+    pub status: PaymentStatus,
+    /// The gateway's id of the charge.
+    pub charge_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Payment {
+    pub fn new(request_id: &str, payer_id: &str, payee_id: Option<&str>, amount: Money) -> Self {
+        let now = Utc::now();
+        Self {
+            id: request_id.to_owned(),
+            request_id: request_id.to_owned(),
+            payer_id: payer_id.to_owned(),
+            payee_id: payee_id.map(str::to_owned),
+            amount,
+            status: PaymentStatus::Pending,
+            charge_id: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn charged(&mut self, charge_id: String) {
+        self.status = PaymentStatus::Charged;
+        self.charge_id = Some(charge_id);
+        self.error = None;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn failed(&mut self, error: &str) {
+        self.status = PaymentStatus::Failed;
+        self.error = Some(error.to_owned());
+        self.updated_at = Utc::now();
+    }
+}
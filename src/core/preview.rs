@@ -0,0 +1,103 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use super::{
+    repository::WalkRequestCreate,
+    units::{Meters, Money},
+    validation::FieldError,
+};
+
+/// Around the draft's location, walkers active there count towards its
+/// visibility.
+pub const VISIBILITY_RADIUS: Meters = Meters(3000.0);
+
+/// How far back walkers count as active around a location.
+pub const VISIBILITY_LOOKBACK_DAYS: i64 = 30;
+
+/// Requests around the location looked at for the estimate.
+pub const VISIBILITY_SAMPLE_SIZE: i64 = 500;
+
+/// Drafts starting sooner than this are warned about.
+const SHORT_NOTICE_MINUTES: i64 = 60;
+
+/// Something about a valid draft which may keep it from finding a walker.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct PreviewWarning {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl PreviewWarning {
+    fn new(code: &'static str, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_owned(),
+        }
+    }
+
+    /// The draft's price takes the owner over their monthly spending cap,
+    /// creating it takes a confirmation.
+    pub fn over_spending_cap() -> Self {
+        Self::new("over_spending_cap", "报价超出本月消费上限，创建时需确认")
+    }
+}
+
+/// The outcome of checking a draft request without posting it.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalkRequestPreview {
+    pub valid: bool,
+    pub errors: Vec<FieldError>,
+    pub warnings: Vec<PreviewWarning>,
+    /// Distinct walkers who applied to or walked requests within `radius`
+    /// lately, an estimate of who would see the request.
+    pub nearby_walkers: u64,
+    pub radius: Meters,
+    /// The draft's price as it would be stored, holidays included.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<Money>,
+}
+
+impl WalkRequestPreview {
+    pub fn new(create: &WalkRequestCreate, errors: Vec<FieldError>, nearby_walkers: u64) -> Self {
+        Self {
+            valid: errors.is_empty(),
+            errors,
+            warnings: warnings(create, nearby_walkers, Utc::now()),
+            nearby_walkers,
+            radius: VISIBILITY_RADIUS,
+            price: create.price.clone(),
+        }
+    }
+}
+
+fn warnings(
+    create: &WalkRequestCreate,
+    nearby_walkers: u64,
+    now: DateTime<Utc>,
+) -> Vec<PreviewWarning> {
+    let mut warnings = Vec::new();
+    if nearby_walkers == 0 {
+        warnings.push(PreviewWarning::new(
+            "no_nearby_walkers",
+            "附近近期没有活跃的遛狗人，可能无人接单",
+        ));
+    }
+    if create.should_start_after.is_none() && create.should_start_before.is_none() {
+        warnings.push(PreviewWarning::new("no_start_window", "未指定开始时间"));
+    }
+    let latest_start = create.should_start_before.or(create.should_start_after);
+    if let Some(start) = latest_start {
+        if start <= now {
+            warnings.push(PreviewWarning::new("start_passed", "开始时间已过"));
+        } else if start - now < Duration::minutes(SHORT_NOTICE_MINUTES) {
+            warnings.push(PreviewWarning::new(
+                "short_notice",
+                "距开始时间较近，可能来不及找到遛狗人",
+            ));
+        }
+    }
+    if create.apply_before.map_or(false, |b| b <= now) {
+        warnings.push(PreviewWarning::new("apply_closed", "报名截止时间已过"));
+    }
+    warnings
+}
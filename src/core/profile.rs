@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What cards show of a user, owned by the user service.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct UserProfile {
+    pub id: String,
+    pub nickname: String,
+    pub avatar_url: Option<String>,
+}
+
+/// Where transactional emails to a user go, never shown to other users.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UserContact {
+    pub email: Option<String>,
+    /// BCP 47 tag of the user's language, e.g. `en-US`.
+    pub locale: Option<String>,
+}
+
+/// Source of users' profiles.
+#[async_trait]
+pub trait UserDirectory: Send + Sync {
+    /// Profiles by user id in one call, unknown users are left out.
+    async fn profiles(&self, user_ids: &[String]) -> Result<HashMap<String, UserProfile>, Error>;
+    async fn contact(&self, user_id: &str) -> Result<UserContact, Error>;
+}
@@ -0,0 +1,169 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::entities::WalkRequest;
+
+/// Waiting longer than this earns no further boost.
+const MAX_BOOSTED_WAIT_HOURS: f64 = 24.0;
+
+/// What rankers may take into account besides the requests themselves.
+#[derive(Debug, Clone, Default)]
+pub struct RankingContext {
+    /// The searching walker, when known.
+    pub walker: Option<String>,
+    /// Times each request was seen, by request id.
+    pub impressions: HashMap<String, u64>,
+    /// The walker's variant of the experiment the ranker runs, if any.
+    pub variant: Option<String>,
+    pub now: DateTime<Utc>,
+}
+
+/// Orders one page of nearby results, which come sorted by distance. Lives
+/// apart from the repository so that ranking experiments don't touch
+/// queries.
+#[async_trait]
+pub trait Ranker: Send + Sync {
+    async fn rank(
+        &self,
+        requests: &mut [WalkRequest],
+        context: &RankingContext,
+    ) -> Result<(), Error>;
+
+    /// The experiment whose variant the ranker needs in the context.
+    fn experiment(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Keeps the distance order.
+pub struct ByDistance;
+
+#[async_trait]
+impl Ranker for ByDistance {
+    async fn rank(&self, _: &mut [WalkRequest], _: &RankingContext) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Rolls out rankers through an experiment: walkers are ranked by the
+/// ranker named after their variant, or by distance when there is none.
+pub struct VariantRanker {
+    pub experiment: String,
+    pub rankers: HashMap<String, Arc<dyn Ranker>>,
+}
+
+#[async_trait]
+impl Ranker for VariantRanker {
+    async fn rank(
+        &self,
+        requests: &mut [WalkRequest],
+        context: &RankingContext,
+    ) -> Result<(), Error> {
+        match context.variant.as_ref().and_then(|v| self.rankers.get(v)) {
+            Some(ranker) => ranker.rank(requests, context).await,
+            None => Ok(()),
+        }
+    }
+
+    fn experiment(&self) -> Option<&str> {
+        Some(&self.experiment)
+    }
+}
+
+/// Requests which should start soonest first, ties by distance.
+pub struct SoonestStart;
+
+#[async_trait]
+impl Ranker for SoonestStart {
+    async fn rank(&self, requests: &mut [WalkRequest], _: &RankingContext) -> Result<(), Error> {
+        // Stable, so ties keep the distance order.
+        requests.sort_by_key(|r| (r.should_start_after.is_none(), r.should_start_after));
+        Ok(())
+    }
+}
+
+/// Spreads exposure among open requests: each request is ranked as if it
+/// were closer by `boost_per_hour` meters for every hour it has waited, and
+/// farther by `penalty` meters per doubling of its impressions.
+pub struct ExposureBalanced {
+    pub boost_per_hour: f64,
+    pub penalty: f64,
+}
+
+impl Default for ExposureBalanced {
+    fn default() -> Self {
+        Self {
+            boost_per_hour: 50.0,
+            penalty: 20.0,
+        }
+    }
+}
+
+impl ExposureBalanced {
+    fn score(&self, request: &WalkRequest, context: &RankingContext) -> f64 {
+        let distance = request.distance.map_or(0.0, |d| d.value());
+        let waited_hours = request.created_at.map_or(0.0, |created_at| {
+            ((context.now - created_at).num_minutes() as f64 / 60.0)
+                .clamp(0.0, MAX_BOOSTED_WAIT_HOURS)
+        });
+        let impressions = context.impressions.get(&request.id).copied().unwrap_or(0);
+        distance - waited_hours * self.boost_per_hour
+            + (1.0 + impressions as f64).log2() * self.penalty
+    }
+}
+
+#[async_trait]
+impl Ranker for ExposureBalanced {
+    async fn rank(
+        &self,
+        requests: &mut [WalkRequest],
+        context: &RankingContext,
+    ) -> Result<(), Error> {
+        requests.sort_by(|a, b| self.score(a, context).total_cmp(&self.score(b, context)));
+        Ok(())
+    }
+}
+
+/// Orders by scores, highest first. `scores` are in the order of
+/// `requests`, as returned by scoring services.
+pub fn sort_by_scores(requests: &mut [WalkRequest], scores: &[f64]) -> Result<(), Error> {
+    if scores.len() != requests.len() {
+        return Err(Error::msg(format!(
+            "expected {} scores, got {}",
+            requests.len(),
+            scores.len()
+        )));
+    }
+    let scores: HashMap<String, f64> = requests
+        .iter()
+        .map(|r| r.id.clone())
+        .zip(scores.iter().copied())
+        .collect();
+    requests.sort_by(|a, b| scores[&b.id].total_cmp(&scores[&a.id]));
+    Ok(())
+}
+
+/// The rankers `NEARBY_RANKING` can select.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RankerKind {
+    Distance,
+    SoonestStart,
+    ExposureBalanced,
+    /// Scored by an external model service.
+    Scored,
+}
+
+impl RankerKind {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        match name {
+            "distance" => Ok(RankerKind::Distance),
+            "soonest_start" => Ok(RankerKind::SoonestStart),
+            "exposure_balanced" => Ok(RankerKind::ExposureBalanced),
+            "scored" => Ok(RankerKind::Scored),
+            other => Err(Error::msg(format!("无效的排序方式: {}", other))),
+        }
+    }
+}
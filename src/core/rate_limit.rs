@@ -0,0 +1,122 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::cache::Cache;
+
+/// Local counters of past windows are dropped once there are this many.
+const MAX_LOCAL_COUNTERS: usize = 10_000;
+
+/// Routes sharing a budget. Writes are limited apart so that a client
+/// flooding uploads doesn't also lose its reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Reads,
+    Writes,
+}
+
+impl RouteGroup {
+    pub fn of(method: &str) -> Self {
+        match method {
+            "GET" | "HEAD" | "OPTIONS" => RouteGroup::Reads,
+            _ => RouteGroup::Writes,
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            RouteGroup::Reads => "reads",
+            RouteGroup::Writes => "writes",
+        }
+    }
+}
+
+/// The user is over the budget of the group until the window ends.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+/// Calls per user and route group over fixed windows. Counters are kept in
+/// the process, or in the shared cache when instances have to agree.
+#[derive(Clone)]
+pub struct RateLimiter {
+    reads: u64,
+    writes: u64,
+    window: Duration,
+    shared: Option<Arc<dyn Cache>>,
+    local: Arc<Mutex<HashMap<(RouteGroup, String), (u64, u64)>>>,
+}
+
+impl RateLimiter {
+    /// Budgets per window, 0 leaves the group unlimited.
+    pub fn new(reads: u64, writes: u64, window: Duration) -> Self {
+        Self {
+            reads,
+            writes,
+            window: window.max(Duration::from_secs(1)),
+            shared: None,
+            local: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_shared_counters(mut self, cache: Arc<dyn Cache>) -> Self {
+        self.shared = Some(cache);
+        self
+    }
+
+    fn budget(&self, group: RouteGroup) -> u64 {
+        match group {
+            RouteGroup::Reads => self.reads,
+            RouteGroup::Writes => self.writes,
+        }
+    }
+
+    /// Counts a call, shared counters which can't be reached let it
+    /// through.
+    pub async fn check(&self, user_id: &str, group: RouteGroup) -> Result<(), RateLimited> {
+        let budget = self.budget(group);
+        if budget == 0 {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let window = self.window.as_secs();
+        let index = now.as_secs() / window;
+        let count = match &self.shared {
+            Some(cache) => {
+                let key = format!("rate:{}:{}:{}", group.key(), user_id, index);
+                match cache.incr_with_ttl(&key, self.window).await {
+                    Ok(count) => count as u64,
+                    Err(e) => {
+                        log::error!("failed to count a call of {}: {}", user_id, e);
+                        return Ok(());
+                    }
+                }
+            }
+            None => {
+                let mut local = self.local.lock().unwrap();
+                if local.len() >= MAX_LOCAL_COUNTERS {
+                    local.retain(|_, (i, _)| *i == index);
+                }
+                let counter = local
+                    .entry((group, user_id.to_owned()))
+                    .or_insert((index, 0));
+                if counter.0 != index {
+                    *counter = (index, 0);
+                }
+                counter.1 += 1;
+                counter.1
+            }
+        };
+        if count <= budget {
+            return Ok(());
+        }
+        Err(RateLimited {
+            retry_after: Duration::from_secs((index + 1) * window) - now,
+        })
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{
+    currency::ExchangeRate, entities::WalkRequest, payment::Payment, timezone::display,
+    units::Money,
+};
+
+/// What an owner paid for a walk, issued once its charge went through and
+/// keyed by the walk request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Receipt {
+    pub id: String,
+    pub request_id: String,
+    pub owner_id: String,
+    /// The gateway's id of the charge.
+    pub charge_id: String,
+    /// In the currency the walk was priced in.
+    pub amount: Money,
+    /// `amount` in the deployment's default currency at `rate`, so spending
+    /// across currency zones adds up.
+    pub settled: Money,
+    /// Taken when the receipt was issued.
+    #[schema(value_type = Object)]
+    pub rate: ExchangeRate,
+    pub issued_at: DateTime<Utc>,
+}
+
+impl Receipt {
+    pub fn new(payment: &Payment, charge_id: &str, rate: ExchangeRate) -> Self {
+        Self {
+            id: payment.request_id.clone(),
+            request_id: payment.request_id.clone(),
+            owner_id: payment.payer_id.clone(),
+            charge_id: charge_id.to_owned(),
+            amount: payment.amount.clone(),
+            settled: payment.amount.convert(&rate),
+            rate,
+            issued_at: Utc::now(),
+        }
+    }
+
+    /// The payouts of the walkers, who share the amount evenly. Nobody is
+    /// paid out for walks without a walker.
+    pub fn payouts(&self, walkers: &[&str]) -> Vec<Payout> {
+        let amounts = self.amount.split(walkers.len());
+        walkers
+            .iter()
+            .zip(amounts)
+            .map(|(walker, amount)| Payout {
+                id: format!("{}:{}", self.request_id, walker),
+                request_id: self.request_id.clone(),
+                walker_id: walker.to_string(),
+                settled: amount.convert(&self.rate),
+                amount,
+                rate: self.rate.clone(),
+                created_at: self.issued_at,
+            })
+            .collect()
+    }
+}
+
+/// A walker's share of a receipt, paid out by the payments service.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Payout {
+    pub id: String,
+    pub request_id: String,
+    pub walker_id: String,
+    pub amount: Money,
+    /// See `Receipt::settled`.
+    pub settled: Money,
+    #[schema(value_type = Object)]
+    pub rate: ExchangeRate,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The data of the receipt email templates. Amounts are rendered by
+/// `Money`'s `Display`, with the minor unit of their currency.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReceiptEmail {
+    pub name: String,
+    pub request_id: String,
+    /// When the walk started, or was to start, in the request's timezone.
+    pub walked_at: Option<String>,
+    pub amount: String,
+    /// What the amount was settled as when charged in another currency.
+    pub settled: Option<String>,
+    pub rate: Option<String>,
+    pub total: String,
+}
+
+impl ReceiptEmail {
+    pub fn new(receipt: &Receipt, request: &WalkRequest, name: &str, locale: &str) -> Self {
+        let converted = receipt.settled.currency != receipt.amount.currency;
+        Self {
+            name: name.to_owned(),
+            request_id: receipt.request_id.clone(),
+            walked_at: request
+                .started_at
+                .or(request.should_start_after)
+                .map(|t| display(&t, request.timezone.as_deref(), locale)),
+            amount: receipt.amount.to_string(),
+            settled: converted.then(|| receipt.settled.to_string()),
+            rate: converted.then(|| receipt.rate.to_string()),
+            total: receipt.amount.to_string(),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Which finished walks to recompute the summary of: one walk request, or
+/// those created in `[from, to)`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RecomputeScope {
+    pub request_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Where a recomputation stands. Summaries which come out the same are left
+/// untouched, so running one again only writes what changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecomputeProgress {
+    pub id: String,
+    pub scope: RecomputeScope,
+    pub total: u64,
+    pub processed: u64,
+    pub updated: u64,
+    pub unchanged: u64,
+    pub failed: u64,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// Recomputations of this process, by id.
+#[derive(Clone, Default)]
+pub struct RecomputeJobs {
+    jobs: Arc<Mutex<HashMap<String, RecomputeProgress>>>,
+}
+
+impl RecomputeJobs {
+    pub fn start(&self, id: String, scope: RecomputeScope) -> RecomputeProgress {
+        let progress = RecomputeProgress {
+            id: id.clone(),
+            scope,
+            total: 0,
+            processed: 0,
+            updated: 0,
+            unchanged: 0,
+            failed: 0,
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+        self.jobs.lock().unwrap().insert(id, progress.clone());
+        progress
+    }
+
+    pub fn update(&self, id: &str, update: impl FnOnce(&mut RecomputeProgress)) {
+        if let Some(progress) = self.jobs.lock().unwrap().get_mut(id) {
+            update(progress);
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<RecomputeProgress> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+}
@@ -0,0 +1,138 @@
+use serde::Serialize;
+
+use super::{entities::WalkRequest, repository::WalkRequestUpdate};
+
+/// Findings listed in a report, the counts go on past them.
+pub const MAX_REPORTED_FINDINGS: usize = 1000;
+
+/// A combination of timestamps the state machine, see
+/// `WalkRequestStatus::can_transition_to`, can't reach. Older requests were
+/// written before transitions were enforced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Inconsistency {
+    /// Accepted by someone without a time, or at a time without anyone.
+    HalfAccepted,
+    StartedUnaccepted,
+    FinishedUnstarted,
+    FinishedBeforeStarted,
+    /// Only requests not started yet can be canceled.
+    CanceledAfterStart,
+    /// Only requests nobody accepted expire.
+    ExpiredAfterAccept,
+}
+
+/// What is wrong with a request and the update repairing what can be
+/// repaired without guessing who walked.
+#[derive(Debug, Default)]
+pub struct Diagnosis {
+    pub repairable: Vec<Inconsistency>,
+    pub unresolved: Vec<Inconsistency>,
+    pub update: Option<WalkRequestUpdate>,
+}
+
+impl Diagnosis {
+    pub fn is_consistent(&self) -> bool {
+        self.repairable.is_empty() && self.unresolved.is_empty()
+    }
+}
+
+/// Checks the request against the state machine. A missing accept time is
+/// taken from the earliest later step, a missing start from the finish,
+/// which records the walk without making up its duration. An accept time
+/// without anyone is dropped before the walk started.
+pub fn diagnose(request: &WalkRequest) -> Diagnosis {
+    use Inconsistency::*;
+    let mut diagnosis = Diagnosis::default();
+    let mut update = WalkRequestUpdate::default();
+    let accepter = request.accepted_by.is_some();
+    let walked = request.started_at.is_some() || request.finished_at.is_some();
+    match (accepter, request.accepted_at) {
+        (true, None) => match request
+            .started_at
+            .or(request.finished_at)
+            .or(request.created_at)
+        {
+            Some(at) => {
+                update.accepted_at = Some(at);
+                diagnosis.repairable.push(HalfAccepted);
+            }
+            None => diagnosis.unresolved.push(HalfAccepted),
+        },
+        (false, Some(_)) if !walked => {
+            update.unset_accepted_at = true;
+            diagnosis.repairable.push(HalfAccepted);
+        }
+        (false, Some(_)) => diagnosis.unresolved.push(HalfAccepted),
+        _ => {}
+    }
+    if walked && !accepter {
+        diagnosis.unresolved.push(StartedUnaccepted);
+    }
+    match (request.started_at, request.finished_at) {
+        (None, Some(finished_at)) if accepter => {
+            update.started_at = Some(finished_at);
+            diagnosis.repairable.push(FinishedUnstarted);
+        }
+        (None, Some(_)) => diagnosis.unresolved.push(FinishedUnstarted),
+        (Some(started_at), Some(finished_at)) if finished_at < started_at => {
+            diagnosis.unresolved.push(FinishedBeforeStarted);
+        }
+        _ => {}
+    }
+    if request.canceled_at.is_some() && walked {
+        diagnosis.unresolved.push(CanceledAfterStart);
+    }
+    if request.expired_at.is_some() && (accepter || request.accepted_at.is_some()) {
+        diagnosis.unresolved.push(ExpiredAfterAccept);
+    }
+    if !diagnosis.repairable.is_empty() {
+        diagnosis.update = Some(update);
+    }
+    diagnosis
+}
+
+/// One inconsistency of one request.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub request_id: String,
+    pub inconsistency: Inconsistency,
+    /// Whether it was, or on a dry run would be, repaired.
+    pub repaired: bool,
+}
+
+/// The outcome of a reconciliation, nothing is written on a dry run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReconcileReport {
+    pub dry_run: bool,
+    pub scanned: u64,
+    pub inconsistent: u64,
+    pub repaired: u64,
+    /// Requests left with inconsistencies only an admin can settle.
+    pub unresolved: u64,
+    /// Repairs which failed, mostly requests changed meanwhile.
+    pub failed: u64,
+    /// At most `MAX_REPORTED_FINDINGS`, unresolved ones first.
+    pub findings: Vec<Finding>,
+}
+
+impl ReconcileReport {
+    pub fn record(&mut self, request_id: &str, diagnosis: &Diagnosis) {
+        let repaired = diagnosis.repairable.iter().map(|i| (i, true));
+        let unresolved = diagnosis.unresolved.iter().map(|i| (i, false));
+        for (inconsistency, repaired) in repaired.chain(unresolved) {
+            if self.findings.len() < MAX_REPORTED_FINDINGS {
+                self.findings.push(Finding {
+                    request_id: request_id.to_owned(),
+                    inconsistency: *inconsistency,
+                    repaired,
+                });
+            }
+        }
+    }
+
+    /// Puts unresolved findings first, they are what needs looking at.
+    pub fn finish(&mut self) {
+        self.findings.sort_by_key(|f| f.repaired);
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::{WalkRequest, WalkRequestStatus},
+    ids::new_ulid,
+};
+
+/// Minutes before `should_start_after` the owner and the walker are
+/// reminded at, unless configured otherwise.
+pub const DEFAULT_START_REMINDER_MINUTES: [i64; 2] = [30, 5];
+
+/// Reminders sent per run.
+pub const REMINDER_BATCH_SIZE: i64 = 100;
+
+/// A push to one party of an accepted walk that it starts soon, deleted once
+/// sent or when the walk is called off or started early.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartReminder {
+    pub id: String,
+    pub request_id: String,
+    pub user_id: String,
+    pub minutes_before: i64,
+    pub remind_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StartReminder {
+    /// Reminders of everyone taking part in `request` for each of `minutes`
+    /// still ahead of `now`, none unless the request is accepted.
+    pub fn schedule(request: &WalkRequest, minutes: &[i64], now: DateTime<Utc>) -> Vec<Self> {
+        let Some(start) = request.should_start_after else {
+            return Vec::new();
+        };
+        if request.status != WalkRequestStatus::Accepted {
+            return Vec::new();
+        }
+        let mut users: Vec<&str> = request
+            .created_by
+            .iter()
+            .chain(request.accepted_by.iter())
+            .map(String::as_str)
+            .chain(request.pool_walkers.iter().map(|w| w.walker_id.as_str()))
+            .collect();
+        users.sort_unstable();
+        users.dedup();
+        let mut reminders = Vec::new();
+        for &minutes_before in minutes {
+            let remind_at = start - Duration::minutes(minutes_before);
+            if remind_at <= now {
+                continue;
+            }
+            reminders.extend(users.iter().map(|user| Self {
+                id: new_ulid(),
+                request_id: request.id.clone(),
+                user_id: (*user).to_owned(),
+                minutes_before,
+                remind_at,
+                created_at: now,
+            }));
+        }
+        reminders
+    }
+
+    /// Whether the walk is still on for the reminded user.
+    pub fn still_due(&self, request: &WalkRequest) -> bool {
+        request.status == WalkRequestStatus::Accepted
+            && (request.created_by.as_deref() == Some(self.user_id.as_str())
+                || request.walked_by(&self.user_id))
+    }
+}
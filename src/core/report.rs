@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::WalkRequest,
+    spending::MonthlySpending,
+    units::{Meters, Money},
+};
+
+/// Longest range one report may cover.
+pub const MAX_REPORT_DAYS: i64 = 366;
+
+/// Range covered when the caller doesn't say.
+pub const DEFAULT_REPORT_DAYS: i64 = 30;
+
+/// Whose walks a report sums up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportRole {
+    /// The walks a walker finished, `accepted_by`.
+    Walker,
+    /// The walks of an owner's requests, `created_by`.
+    Owner,
+}
+
+/// Length of the periods a report is grouped by, in UTC. Weeks start on
+/// Monday.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportInterval {
+    #[default]
+    Day,
+    Week,
+    Month,
+}
+
+impl ReportInterval {
+    /// The first day of the period `at` falls in.
+    pub fn period_start(self, at: DateTime<Utc>) -> NaiveDate {
+        let day = at.date_naive();
+        match self {
+            ReportInterval::Day => day,
+            ReportInterval::Week => {
+                day - chrono::Duration::days(day.weekday().num_days_from_monday().into())
+            }
+            ReportInterval::Month => day.with_day(1).expect("every month has a first day"),
+        }
+    }
+
+    /// The unit of MongoDB's `$dateTrunc`.
+    pub fn unit(self) -> &'static str {
+        match self {
+            ReportInterval::Day => "day",
+            ReportInterval::Week => "week",
+            ReportInterval::Month => "month",
+        }
+    }
+}
+
+/// Sums over finished walks. Only walks with a summary, see
+/// `WalkRequest::summary`, count towards `distance`, and only priced ones
+/// towards `amounts`, which are kept per currency.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReportTotals {
+    pub walks: u64,
+    pub distance: Meters,
+    pub hours: f64,
+    pub amounts: Vec<Money>,
+}
+
+impl ReportTotals {
+    /// Adds `walks` walks, lasting `seconds` and covering `distance`
+    /// together, paid `amount` if any.
+    pub fn add(&mut self, walks: u64, distance: Meters, seconds: f64, amount: Option<Money>) {
+        self.walks += walks;
+        self.distance = Meters(self.distance.value() + distance.value());
+        self.hours += seconds / 3600.0;
+        if let Some(amount) = amount {
+            self.add_amount(amount);
+        }
+    }
+
+    fn add_amount(&mut self, amount: Money) {
+        match self
+            .amounts
+            .binary_search_by(|a| a.currency.cmp(&amount.currency))
+        {
+            Ok(i) => self.amounts[i].minor_units += amount.minor_units,
+            Err(i) => self.amounts.insert(i, amount),
+        }
+    }
+
+    fn merge(&mut self, other: &ReportTotals) {
+        self.walks += other.walks;
+        self.distance = Meters(self.distance.value() + other.distance.value());
+        self.hours += other.hours;
+        for amount in &other.amounts {
+            self.add_amount(amount.clone());
+        }
+    }
+}
+
+/// The walks finished in the period starting `period_start`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportBucket {
+    pub period_start: NaiveDate,
+    #[serde(flatten)]
+    pub totals: ReportTotals,
+}
+
+/// The walks finished between `from` and `to`, periods without any are
+/// left out of `buckets`.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalkReport {
+    pub role: ReportRole,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub interval: ReportInterval,
+    pub totals: ReportTotals,
+    pub buckets: Vec<ReportBucket>,
+    /// The owner's spending this month against their cap, in owner reports
+    /// only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spending: Option<MonthlySpending>,
+}
+
+impl WalkReport {
+    pub fn new(
+        role: ReportRole,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        interval: ReportInterval,
+        buckets: Vec<ReportBucket>,
+    ) -> Self {
+        let mut totals = ReportTotals::default();
+        for bucket in &buckets {
+            totals.merge(&bucket.totals);
+        }
+        Self {
+            role,
+            from,
+            to,
+            interval,
+            totals,
+            buckets,
+            spending: None,
+        }
+    }
+}
+
+/// Walks of one period with one currency, or none, as stores aggregate
+/// them: the first day of the period, the number of walks, their distance,
+/// their duration in seconds and the amount paid.
+pub type ReportRow = (NaiveDate, u64, Meters, f64, Option<Money>);
+
+/// Buckets of `rows`, oldest first.
+pub fn collect_buckets(rows: impl IntoIterator<Item = ReportRow>) -> Vec<ReportBucket> {
+    let mut buckets: BTreeMap<NaiveDate, ReportTotals> = BTreeMap::new();
+    for (period_start, walks, distance, seconds, amount) in rows {
+        buckets
+            .entry(period_start)
+            .or_default()
+            .add(walks, distance, seconds, amount);
+    }
+    buckets
+        .into_iter()
+        .map(|(period_start, totals)| ReportBucket {
+            period_start,
+            totals,
+        })
+        .collect()
+}
+
+/// Buckets of the finished walks among `requests`, for stores which can't
+/// aggregate themselves.
+pub fn buckets(requests: &[WalkRequest], interval: ReportInterval) -> Vec<ReportBucket> {
+    collect_buckets(requests.iter().filter_map(|request| {
+        let finished_at = request.finished_at?;
+        let seconds = request.started_at.map_or(0, |started_at| {
+            (finished_at - started_at).num_seconds().max(0)
+        });
+        Some((
+            interval.period_start(finished_at),
+            1,
+            request.summary.as_ref().map_or(Meters(0.0), |s| s.distance),
+            seconds as f64,
+            request.price.clone(),
+        ))
+    }))
+}
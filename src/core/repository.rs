@@ -1,9 +1,55 @@
-use crate::core::entities::WalkRequest;
-use anyhow::Error;
-use chrono::{DateTime, Utc};
+use crate::core::{
+    audit::AuditVerification,
+    backfill::BackfillRun,
+    condition::{Condition, Filter, Operand},
+    delegation::Delegation,
+    entities::{
+        Application, ApplicationState, PoolWalker, TrackVisibility, WalkRequest, WalkRequestStatus,
+        WalkingLocation,
+    },
+    error::ServiceError,
+    events::WalkRequestEvent,
+    favorite::FavoriteWalker,
+    feature_flags::FeatureFlag,
+    fitness::{FitnessExport, FitnessToken},
+    geo::{simplify, BoundingBox, WalkSummary},
+    holiday::Holiday,
+    message::Message,
+    metrics::{DailyMetrics, FunnelMarks, FunnelStage, DEFAULT_REGION},
+    offer::Offer,
+    outbox::OutboxMessage,
+    payment::Payment,
+    receipt::{Payout, Receipt},
+    reminder::StartReminder,
+    report::{ReportBucket, ReportInterval},
+    reputation::{Reputation, ReputationUpdate},
+    retention::DataClass,
+    saga::BookingSaga,
+    saved_search::SavedSearch,
+    schedule::WalkSchedule,
+    session::DeviceSession,
+    spending::SpendingCap,
+    strike::Strike,
+    template::WalkRequestTemplate,
+    tenant::Tenant,
+    timezone::DEFAULT_TIMEZONE,
+    units::{Meters, Money},
+    usage::UsageRecord,
+    walker_capabilities::{DogRequirements, DogSize, WalkerCapabilities},
+};
+use std::{collections::HashMap, future::Future};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{
+    stream::{self, LocalBoxStream},
+    StreamExt,
+};
 use little_walk_dog::core::entities::Dog;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
+/// Stored as the payload of creation events, see `handlers::dto` for the
+/// API body.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WalkRequestCreate {
     pub dogs: Vec<Dog>,
@@ -13,15 +59,62 @@ pub struct WalkRequestCreate {
     pub should_end_after: Option<DateTime<Utc>>,
     pub latitude: f64,
     pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    /// See `WalkRequest::max_radius`.
+    pub max_radius: Option<Meters>,
+    /// See `WalkRequest::price`.
+    pub price: Option<Money>,
+    /// See `WalkRequest::max_walkers`.
+    pub max_walkers: Option<i64>,
+    /// See `WalkRequest::apply_before`.
+    #[serde(default)]
+    pub apply_before: Option<DateTime<Utc>>,
     #[serde(default = "empty_string")]
     pub created_by: String,
+    /// Set by the service when an internal client creates the request, kept
+    /// in the audit log only.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
+    /// Set by the service from the tenant it is scoped to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant_id: Option<String>,
+    /// Set by the service, see `WalkRequest::reference_code`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_code: Option<String>,
+    /// See `WalkRequest::invited_walker_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invited_walker_id: Option<String>,
+    /// Set by the service along with `invited_walker_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invitation_expires_at: Option<DateTime<Utc>>,
+    /// See `WalkRequest::tags`, normalized by the service.
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 fn empty_string() -> String {
     String::new()
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+fn default_timezone() -> String {
+    DEFAULT_TIMEZONE.to_owned()
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_owned()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(default)]
 pub struct WalkRequestUpdate {
     pub dogs: Option<Vec<Dog>>,
     pub should_start_after: Option<DateTime<Utc>>,
@@ -30,35 +123,590 @@ pub struct WalkRequestUpdate {
     pub should_end_after: Option<DateTime<Utc>>,
     pub latitude: Option<f64>,
     pub longitude: Option<f64>,
+    pub timezone: Option<String>,
     pub accepted_by: Option<String>,
     pub accepted_at: Option<DateTime<Utc>>,
     pub canceled_at: Option<DateTime<Utc>>,
+    pub canceled_by: Option<String>,
+    pub cancellation_reason: Option<String>,
+    pub cancel_requested_at: Option<DateTime<Utc>>,
     pub started_at: Option<DateTime<Utc>>,
     pub finished_at: Option<DateTime<Utc>>,
+    pub sla_breached_at: Option<DateTime<Utc>>,
+    pub expired_at: Option<DateTime<Utc>>,
+    pub requirements: Option<DogRequirements>,
+    pub track_visibility: Option<TrackVisibility>,
+    pub track_archived_at: Option<DateTime<Utc>>,
+    pub track_downsampled_at: Option<DateTime<Utc>>,
+    pub summary: Option<WalkSummary>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub geofence_violated_at: Option<DateTime<Utc>>,
+    pub verification_waived_at: Option<DateTime<Utc>>,
+    pub start_received_at: Option<DateTime<Utc>>,
+    pub finish_received_at: Option<DateTime<Utc>>,
+    pub clock_skew_detected_at: Option<DateTime<Utc>>,
+    pub invitation_expires_at: Option<DateTime<Utc>>,
+    pub tags: Option<Vec<String>>,
+    /// Replaces the walkers of a pool walk, see `WalkRequest::pool_walkers`.
+    pub pool_walkers: Option<Vec<PoolWalker>>,
+    pub price: Option<Money>,
     pub unset_accepted_by: bool,
     pub unset_accepted_at: bool,
+    /// Undoes a pending cancellation, clearing who requested it and why.
+    pub unset_cancel_request: bool,
     pub add_to_acceptances: Option<String>,
     pub remove_from_acceptances: Option<String>,
+    pub add_to_dismissed_applicants: Option<String>,
+    /// Many-user forms of `remove_from_acceptances` and
+    /// `add_to_dismissed_applicants`.
+    pub remove_all_from_acceptances: Option<Vec<String>>,
+    pub add_all_to_dismissed_applicants: Option<Vec<String>>,
+    /// Like `WalkRequestCreate::delegation`, not stored on the request.
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub delegation: Option<Delegation>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+impl WalkRequestCreate {
+    /// The walk request this payload creates, used when replaying history.
+    pub fn into_walk_request(self, id: &str, created_at: DateTime<Utc>) -> WalkRequest {
+        let mut request = WalkRequest {
+            id: id.to_owned(),
+            dogs: self.dogs,
+            should_start_after: self.should_start_after,
+            should_start_before: self.should_start_before,
+            should_end_after: self.should_end_after,
+            should_end_before: self.should_end_before,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: Some(self.timezone),
+            region: Some(self.region),
+            tenant_id: self.tenant_id,
+            reference_code: self.reference_code,
+            invited_walker_id: self.invited_walker_id,
+            invitation_expires_at: self.invitation_expires_at,
+            tags: self.tags,
+            max_applicants: self.max_applicants,
+            requirements: self.requirements,
+            track_visibility: self.track_visibility,
+            max_radius: self.max_radius,
+            price: self.price,
+            max_walkers: self.max_walkers,
+            apply_before: self.apply_before,
+            created_by: Some(self.created_by),
+            created_at: Some(created_at),
+            updated_at: Some(created_at),
+            ..Default::default()
+        };
+        request.status = request.derive_status();
+        request
+    }
+}
+
+impl WalkRequestUpdate {
+    /// Applies the update in memory, mirroring what the MongoDB update does.
+    pub fn apply_to(self, request: &mut WalkRequest) {
+        request.version += 1;
+        if let Some(dogs) = self.dogs {
+            request.dogs = dogs;
+        }
+        if self.should_start_after.is_some() {
+            request.should_start_after = self.should_start_after;
+        }
+        if self.should_start_before.is_some() {
+            request.should_start_before = self.should_start_before;
+        }
+        if self.should_end_after.is_some() {
+            request.should_end_after = self.should_end_after;
+        }
+        if self.should_end_before.is_some() {
+            request.should_end_before = self.should_end_before;
+        }
+        if let Some(latitude) = self.latitude {
+            request.latitude = latitude;
+        }
+        if let Some(longitude) = self.longitude {
+            request.longitude = longitude;
+        }
+        if self.timezone.is_some() {
+            request.timezone = self.timezone;
+        }
+        if self.accepted_by.is_some() {
+            request.accepted_by = self.accepted_by;
+        }
+        if self.accepted_at.is_some() {
+            request.accepted_at = self.accepted_at;
+        }
+        if self.canceled_at.is_some() {
+            request.canceled_at = self.canceled_at;
+        }
+        if self.canceled_by.is_some() {
+            request.canceled_by = self.canceled_by;
+        }
+        if self.cancellation_reason.is_some() {
+            request.cancellation_reason = self.cancellation_reason;
+        }
+        if self.cancel_requested_at.is_some() {
+            request.cancel_requested_at = self.cancel_requested_at;
+        }
+        if self.started_at.is_some() {
+            request.started_at = self.started_at;
+        }
+        if self.finished_at.is_some() {
+            request.finished_at = self.finished_at;
+        }
+        if self.sla_breached_at.is_some() {
+            request.sla_breached_at = self.sla_breached_at;
+        }
+        if self.expired_at.is_some() {
+            request.expired_at = self.expired_at;
+        }
+        if let Some(requirements) = self.requirements {
+            request.requirements = requirements;
+        }
+        if let Some(track_visibility) = self.track_visibility {
+            request.track_visibility = track_visibility;
+        }
+        if self.price.is_some() {
+            request.price = self.price;
+        }
+        if self.track_archived_at.is_some() {
+            request.track_archived_at = self.track_archived_at;
+        }
+        if self.track_downsampled_at.is_some() {
+            request.track_downsampled_at = self.track_downsampled_at;
+        }
+        if self.summary.is_some() {
+            request.summary = self.summary;
+        }
+        if self.deleted_at.is_some() {
+            request.deleted_at = self.deleted_at;
+        }
+        if self.geofence_violated_at.is_some() {
+            request.geofence_violated_at = self.geofence_violated_at;
+        }
+        if self.verification_waived_at.is_some() {
+            request.verification_waived_at = self.verification_waived_at;
+        }
+        if self.start_received_at.is_some() {
+            request.start_received_at = self.start_received_at;
+        }
+        if self.finish_received_at.is_some() {
+            request.finish_received_at = self.finish_received_at;
+        }
+        if self.clock_skew_detected_at.is_some() {
+            request.clock_skew_detected_at = self.clock_skew_detected_at;
+        }
+        if self.invitation_expires_at.is_some() {
+            request.invitation_expires_at = self.invitation_expires_at;
+        }
+        if let Some(tags) = self.tags {
+            request.tags = tags;
+        }
+        if let Some(pool_walkers) = self.pool_walkers {
+            request.pool_walkers = pool_walkers;
+        }
+        if self.unset_accepted_by {
+            request.accepted_by = None;
+        }
+        if self.unset_accepted_at {
+            request.accepted_at = None;
+        }
+        if self.unset_cancel_request {
+            request.cancel_requested_at = None;
+            request.canceled_by = None;
+            request.cancellation_reason = None;
+        }
+        if let Some(user_id) = self.add_to_acceptances {
+            let acceptances = request.acceptances.get_or_insert_with(Vec::new);
+            if !acceptances.contains(&user_id) {
+                acceptances.push(user_id);
+            }
+        }
+        if let Some(user_id) = self.remove_from_acceptances {
+            if let Some(acceptances) = request.acceptances.as_mut() {
+                acceptances.retain(|a| a != &user_id);
+            }
+        }
+        if let Some(user_id) = self.add_to_dismissed_applicants {
+            let dismissed = request.dismissed_applicants.get_or_insert_with(Vec::new);
+            if !dismissed.contains(&user_id) {
+                dismissed.push(user_id);
+            }
+        }
+        if let Some(user_ids) = self.remove_all_from_acceptances {
+            if let Some(acceptances) = request.acceptances.as_mut() {
+                acceptances.retain(|a| !user_ids.contains(a));
+            }
+        }
+        if let Some(user_ids) = self.add_all_to_dismissed_applicants {
+            let dismissed = request.dismissed_applicants.get_or_insert_with(Vec::new);
+            for user_id in user_ids {
+                if !dismissed.contains(&user_id) {
+                    dismissed.push(user_id);
+                }
+            }
+        }
+        request.status = request.derive_status();
+    }
+}
+
+/// Who looks for open requests when: requests whose invitation still runs
+/// at `at`, see `WalkRequest::invitation_running`, are only seen by the
+/// invited walker. Anonymous viewers have no `walker`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InvitationViewer {
+    pub walker: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+impl InvitationViewer {
+    pub fn sees(&self, request: &WalkRequest) -> bool {
+        !request.invitation_running(self.at)
+            || request.invited_walker_id.is_some() && request.invited_walker_id == self.walker
+    }
+}
+
+/// Walk requests read one at a time, see `Repository::stream_walk_requests`.
+pub type WalkRequestStream = LocalBoxStream<'static, Result<WalkRequest, ServiceError>>;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct WalkRequestQuery {
     pub id: Option<String>,
+    pub reference_code: Option<String>,
     pub dog_ids_includes_all: Option<Vec<String>>,
     pub dog_ids_includes_any: Option<Vec<String>>,
     pub nearby: Option<Vec<f64>>,
+    /// Only nearby requests past the cursor, ordered by distance then id.
+    pub nearby_after: Option<NearbyCursor>,
+    /// Only requests picked up within the box.
+    pub within_box: Option<BoundingBox>,
     pub accepted_by: Option<String>,
     pub accepted_by_neq: Option<String>,
     pub accepted_by_is_null: Option<bool>,
     pub acceptances_includes_all: Option<Vec<String>>,
     pub acceptances_includes_any: Option<Vec<String>>,
+    pub tags_includes_all: Option<Vec<String>>,
+    pub tags_includes_any: Option<Vec<String>>,
     pub created_by: Option<String>,
+    pub ids_in: Option<Vec<String>>,
+    pub created_by_in: Option<Vec<String>>,
+    pub created_by_neq: Option<String>,
+    pub accepted_by_in: Option<Vec<String>>,
+    /// Only pool walks the walker joined, see `WalkRequest::pool_walkers`.
+    pub pool_walker: Option<String>,
+    pub created_at_gte: Option<DateTime<Utc>>,
+    pub created_at_lte: Option<DateTime<Utc>>,
+    /// Derived status, see `WalkRequest::derive_status`.
+    pub status: Option<WalkRequestStatus>,
+    pub should_start_after_gte: Option<DateTime<Utc>>,
+    pub should_start_after_lte: Option<DateTime<Utc>>,
+    pub should_start_after_lt: Option<DateTime<Utc>>,
+    pub should_end_before_gt: Option<DateTime<Utc>>,
+    pub should_start_before_lt: Option<DateTime<Utc>>,
+    pub canceled_at_is_null: Option<bool>,
+    pub cancel_requested_at_is_null: Option<bool>,
+    pub cancel_requested_at_gte: Option<DateTime<Utc>>,
+    pub cancel_requested_at_lte: Option<DateTime<Utc>>,
+    pub started_at_is_null: Option<bool>,
+    pub started_at_gte: Option<DateTime<Utc>>,
+    pub started_at_lte: Option<DateTime<Utc>>,
+    pub finished_at_is_null: Option<bool>,
+    pub finished_at_gte: Option<DateTime<Utc>>,
+    pub finished_at_lte: Option<DateTime<Utc>>,
+    pub sla_breached_at_is_null: Option<bool>,
+    pub expired_at_is_null: Option<bool>,
+    pub geofence_violated_at_is_null: Option<bool>,
+    pub regions_in: Option<Vec<String>>,
+    /// Only requests of the tenant, set by repositories scoped to one.
+    pub tenant_id: Option<String>,
+    pub dismissed_applicants_excludes: Option<String>,
+    /// Only requests whose applicant count is below their own `max_applicants`,
+    /// or below this default when the request doesn't set one.
+    pub below_applicant_cap: Option<i64>,
+    /// Only requests still taking applications at this time, see
+    /// `WalkRequest::apply_before`.
+    pub apply_open_at: Option<DateTime<Utc>>,
+    /// Leaves out requests reserved for another walker, see
+    /// `InvitationViewer`.
+    pub invitation_visible_to: Option<InvitationViewer>,
+    pub dog_count_lte: Option<i64>,
+    pub dog_count_gte: Option<i64>,
+    /// Requests with a dog whose name contains this, ignoring case. With
+    /// `dog_breed` and `dog_size`, one dog has to match all of them.
+    pub dog_name_contains: Option<String>,
+    /// Requests with a dog of this breed, ignoring case.
+    pub dog_breed: Option<String>,
+    pub dog_size: Option<DogSize>,
+    /// `Some(true)` keeps requests someone applied to, `Some(false)` fresh ones.
+    pub has_acceptances: Option<bool>,
+    /// `Some(false)` leaves out requests with large breeds.
+    pub requires_large_breed: Option<bool>,
+    /// `Some(false)` leaves out requests with puppies.
+    pub requires_puppy: Option<bool>,
+    /// Only the request at this version, so that an update based on a stale
+    /// read matches nothing.
+    pub version: Option<i64>,
+    /// Soft-deleted requests are left out unless set.
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+impl WalkRequestQuery {
+    /// The query confined to `tenant_id`, as is without one.
+    pub fn within_tenant(mut self, tenant_id: Option<&str>) -> Self {
+        if let Some(tenant_id) = tenant_id {
+            self.tenant_id = Some(tenant_id.to_owned());
+        }
+        self
+    }
+
+    /// The typed filters of the query, several may apply to one field. Dog
+    /// details, the applicant cap, the apply deadline, invitations and the
+    /// box are left to each store.
+    pub fn conditions(&self) -> Result<Vec<Condition>, ServiceError> {
+        let mut conditions = Vec::new();
+        let mut push = |field: &str, filter: Filter| conditions.push(Condition::new(field, filter));
+        let texts = |values: &[String]| values.iter().cloned().map(Operand::from).collect();
+        if let Some(id) = &self.id {
+            push("id", Filter::Eq(Operand::Id(id.clone())));
+        }
+        if let Some(code) = &self.reference_code {
+            push("reference_code", Filter::Eq(code.clone().into()));
+        }
+        if let Some(ids) = &self.ids_in {
+            push(
+                "id",
+                Filter::In(ids.iter().cloned().map(Operand::Id).collect()),
+            );
+        }
+        // Requests never updated since versions were introduced have none.
+        match self.version {
+            Some(0) => push("version", Filter::In(vec![Operand::Int(0), Operand::Null])),
+            Some(version) => push("version", Filter::Eq(version.into())),
+            None => {}
+        }
+        if let Some(ids) = &self.dog_ids_includes_any {
+            push("dogs.id", Filter::In(texts(ids)));
+        }
+        if let Some(ids) = &self.dog_ids_includes_all {
+            push("dogs.id", Filter::All(texts(ids)));
+        }
+        for (field, eq, ne, is_null, within) in [
+            (
+                "accepted_by",
+                &self.accepted_by,
+                &self.accepted_by_neq,
+                self.accepted_by_is_null,
+                &self.accepted_by_in,
+            ),
+            (
+                "created_by",
+                &self.created_by,
+                &self.created_by_neq,
+                None,
+                &self.created_by_in,
+            ),
+        ] {
+            if let Some(user) = eq {
+                push(field, Filter::Eq(user.clone().into()));
+            }
+            if let Some(user) = ne {
+                push(field, Filter::Ne(user.clone().into()));
+            }
+            if let Some(is_null) = is_null {
+                push(field, Filter::IsNull(is_null));
+            }
+            if let Some(users) = within {
+                push(field, Filter::In(texts(users)));
+            }
+        }
+        if let Some(users) = &self.acceptances_includes_all {
+            push("acceptances", Filter::All(texts(users)));
+        }
+        if let Some(users) = &self.acceptances_includes_any {
+            push("acceptances", Filter::In(texts(users)));
+        }
+        if let Some(tags) = &self.tags_includes_all {
+            push("tags", Filter::All(texts(tags)));
+        }
+        if let Some(tags) = &self.tags_includes_any {
+            push("tags", Filter::In(texts(tags)));
+        }
+        if let Some(user) = &self.dismissed_applicants_excludes {
+            push("dismissed_applicants", Filter::Ne(user.clone().into()));
+        }
+        if let Some(walker) = &self.pool_walker {
+            push("pool_walkers.walker_id", Filter::Eq(walker.clone().into()));
+        }
+        if let Some(regions) = &self.regions_in {
+            push("region", Filter::In(texts(regions)));
+        }
+        if let Some(tenant_id) = &self.tenant_id {
+            push("tenant_id", Filter::Eq(tenant_id.clone().into()));
+        }
+        for (field, is_null, gt, gte, lt, lte) in [
+            (
+                "created_at",
+                None,
+                None,
+                self.created_at_gte,
+                None,
+                self.created_at_lte,
+            ),
+            (
+                "should_start_after",
+                None,
+                None,
+                self.should_start_after_gte,
+                self.should_start_after_lt,
+                self.should_start_after_lte,
+            ),
+            (
+                "should_start_before",
+                None,
+                None,
+                None,
+                self.should_start_before_lt,
+                None,
+            ),
+            (
+                "should_end_before",
+                None,
+                self.should_end_before_gt,
+                None,
+                None,
+                None,
+            ),
+            (
+                "canceled_at",
+                self.canceled_at_is_null,
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "cancel_requested_at",
+                self.cancel_requested_at_is_null,
+                None,
+                self.cancel_requested_at_gte,
+                None,
+                self.cancel_requested_at_lte,
+            ),
+            (
+                "started_at",
+                self.started_at_is_null,
+                None,
+                self.started_at_gte,
+                None,
+                self.started_at_lte,
+            ),
+            (
+                "finished_at",
+                self.finished_at_is_null,
+                None,
+                self.finished_at_gte,
+                None,
+                self.finished_at_lte,
+            ),
+            (
+                "sla_breached_at",
+                self.sla_breached_at_is_null,
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "expired_at",
+                self.expired_at_is_null,
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "geofence_violated_at",
+                self.geofence_violated_at_is_null,
+                None,
+                None,
+                None,
+                None,
+            ),
+        ] {
+            if let Some(is_null) = is_null {
+                push(field, Filter::IsNull(is_null));
+            }
+            if let Some(range) = Filter::range(gt, gte, lt, lte) {
+                push(field, range);
+            }
+        }
+        if !self.include_deleted {
+            push("deleted_at", Filter::IsNull(true));
+        }
+        if let Some(status) = self.status {
+            for (field, is_null) in WalkRequest::status_conditions(status) {
+                push(field, Filter::IsNull(is_null));
+            }
+        }
+        if let Some(has_acceptances) = self.has_acceptances {
+            push("acceptances.0", Filter::IsNull(!has_acceptances));
+        }
+        for (field, required) in [
+            ("requirements.large_breed", self.requires_large_breed),
+            ("requirements.puppy", self.requires_puppy),
+        ] {
+            match required {
+                Some(true) => push(field, Filter::Eq(true.into())),
+                Some(false) => push(field, Filter::Ne(true.into())),
+                None => {}
+            }
+        }
+        // A list has at most `max` elements when it has none at index `max`.
+        if let Some(max) = self.dog_count_lte {
+            push(&format!("dogs.{}", max), Filter::IsNull(true));
+        }
+        if let Some(min) = self.dog_count_gte.filter(|min| *min > 0) {
+            if self.dog_count_lte.map_or(false, |max| min > max) {
+                push("dogs", Filter::In(Vec::new()));
+            } else {
+                push(&format!("dogs.{}", min - 1), Filter::IsNull(false));
+            }
+        }
+        if let Some(nearby) = &self.nearby {
+            let &[longitude, latitude, max_distance] = nearby.as_slice() else {
+                return Err(ServiceError::Validation(
+                    "Invalid nearby query, expect [f64;3]".to_owned(),
+                ));
+            };
+            push(
+                "location",
+                Filter::GeoNear {
+                    longitude,
+                    latitude,
+                    max_distance,
+                    min_distance: self.nearby_after.as_ref().map(|after| after.distance),
+                },
+            );
+        }
+        Ok(conditions)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct WalkingLocationQuery {
+    pub walk_request_id: String,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
 }
 
 pub struct WalkingLocationCreate<'a> {
     pub walk_request_id: &'a str,
     pub longitude: f64,
     pub latitude: f64,
+    /// When the location was recorded, now when unset. The time it was
+    /// received is always stored too.
+    pub recorded_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -73,7 +721,7 @@ pub struct SortBy {
     pub order: Order,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Pagination {
     pub page: i64,
     pub size: i64,
@@ -83,32 +731,516 @@ impl Pagination {
     pub fn new(page: i64, size: i64) -> Self {
         Self { page, size }
     }
+
+    /// Refuses pages out of `limits` rather than reading them.
+    pub fn check(&self, limits: PageLimits) -> Result<(), ServiceError> {
+        if !(1..=limits.max_page).contains(&self.page)
+            || !(1..=limits.max_size).contains(&self.size)
+        {
+            return Err(ServiceError::Validation(format!(
+                "页码必须在1到{}之间，每页数量必须在1到{}之间",
+                limits.max_page, limits.max_size
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// How deep and how large pages callers ask for may be.
+#[derive(Debug, Clone, Copy)]
+pub struct PageLimits {
+    pub max_page: i64,
+    pub max_size: i64,
+}
+
+impl Default for PageLimits {
+    fn default() -> Self {
+        Self {
+            max_page: 1000,
+            max_size: 100,
+        }
+    }
+}
+
+/// Narrows nearby listings, every filter given must hold. `max_dogs` is
+/// combined with the walker's own capacity, the lower one applies, and
+/// `starting_within_minutes` leaves out requests without a start time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NearbyFilter {
+    pub has_acceptances: Option<bool>,
+    pub min_dogs: Option<i64>,
+    pub max_dogs: Option<i64>,
+    pub starting_within_minutes: Option<i64>,
+    /// Requests carrying all of them, normalized.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl NearbyFilter {
+    /// Sets the filters on a nearby query, relative to `now`.
+    pub fn apply(&self, query: &mut WalkRequestQuery, now: DateTime<Utc>) {
+        query.has_acceptances = self.has_acceptances;
+        query.dog_count_gte = self.min_dogs;
+        if let Some(max) = self.max_dogs {
+            query.dog_count_lte = Some(query.dog_count_lte.map_or(max, |m| m.min(max)));
+        }
+        query.should_start_after_lte = self
+            .starting_within_minutes
+            .map(|minutes| now + chrono::Duration::minutes(minutes));
+        if !self.tags.is_empty() {
+            query.tags_includes_all = Some(self.tags.clone());
+        }
+    }
+
+    /// Part of cache keys, `-` when no filter is set.
+    pub fn key(&self) -> String {
+        if *self == Self::default() {
+            return "-".to_owned();
+        }
+        let part = |v: Option<i64>| v.map_or("-".to_owned(), |v| v.to_string());
+        format!(
+            "{}/{}/{}/{}/{}",
+            self.has_acceptances
+                .map_or("-".to_owned(), |v| v.to_string()),
+            part(self.min_dogs),
+            part(self.max_dogs),
+            part(self.starting_within_minutes),
+            self.tags.join(",")
+        )
+    }
+}
+
+/// Where a page of nearby results ended: its last request's distance and
+/// id. Paging from it doesn't drift when requests are added, and spares the
+/// skipped results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NearbyCursor {
+    pub distance: f64,
+    pub id: String,
+}
+
+impl NearbyCursor {
+    pub fn after(request: &WalkRequest) -> Option<Self> {
+        Some(Self {
+            distance: request.distance?.value(),
+            id: request.id.clone(),
+        })
+    }
+
+    /// The opaque form handed to clients.
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}:{}", self.distance, self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+        let (distance, id) = decoded.split_once(':')?;
+        Some(Self {
+            distance: distance.parse().ok()?,
+            id: id.to_owned(),
+        })
+    }
+}
+
+/// One page of a list together with what clients need to render the pager.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[aliases(PagedWalkRequest = Paged<WalkRequest>)]
+pub struct Paged<T> {
+    pub items: Vec<T>,
+    /// Not counted when paging by cursor, where it is 0.
+    pub total: u64,
+    pub page: i64,
+    pub size: i64,
+    pub has_more: bool,
+    /// Fetches the next page when passed back as `cursor`, for lists that
+    /// support it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Paged<T> {
+    pub fn new(items: Vec<T>, total: u64, pagination: Pagination) -> Self {
+        Self {
+            has_more: ((pagination.page.max(1) * pagination.size) as u64) < total,
+            items,
+            total,
+            page: pagination.page,
+            size: pagination.size,
+            next_cursor: None,
+        }
+    }
 }
 
 pub trait Repository {
-    async fn create_walk_request(&self, request: WalkRequestCreate) -> Result<String, Error>;
+    /// Runs `operation` on a repository whose writes are committed together,
+    /// or not at all, where the store supports it. Stores without
+    /// transactions run it as is.
+    async fn transaction<T, F, Fut>(&self, operation: F) -> Result<T, ServiceError>
+    where
+        Self: Clone,
+        F: FnOnce(Self) -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        operation(self.clone()).await
+    }
+    /// A copy of the repository which only reads and updates the walk
+    /// requests of `tenant_id`. Records hanging off a request are reached
+    /// through it and aren't scoped themselves, see `Tenant` for what is
+    /// left unscoped.
+    fn for_tenant(&self, tenant_id: &str) -> Self
+    where
+        Self: Sized;
+    async fn create_walk_request(&self, request: WalkRequestCreate)
+        -> Result<String, ServiceError>;
     async fn update_walk_request(
         &self,
         id: &str,
         request: WalkRequestUpdate,
-    ) -> Result<WalkRequest, Error>;
+    ) -> Result<WalkRequest, ServiceError>;
     async fn update_walk_request_by_query(
         &self,
         query: WalkRequestQuery,
         update: WalkRequestUpdate,
-    ) -> Result<WalkRequest, Error>;
+    ) -> Result<WalkRequest, ServiceError>;
     async fn update_walk_requests_by_query(
         &self,
         query: WalkRequestQuery,
         update: WalkRequestUpdate,
-    ) -> Result<u64, Error>;
-    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, Error>;
+    ) -> Result<u64, ServiceError>;
+    async fn count_walk_requests(&self, query: WalkRequestQuery) -> Result<u64, ServiceError>;
+    /// The finished walks among the requests of `query` per period of
+    /// `interval`, oldest first.
+    async fn walk_report(
+        &self,
+        query: WalkRequestQuery,
+        interval: ReportInterval,
+    ) -> Result<Vec<ReportBucket>, ServiceError>;
+    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, ServiceError>;
     async fn query_walk_requests(
         &self,
         query: WalkRequestQuery,
         sort_by: Option<SortBy>,
         pagination: Option<Pagination>,
-    ) -> Result<Vec<WalkRequest>, Error>;
-    async fn create_walking_location(&self, create: WalkingLocationCreate)
-        -> Result<String, Error>;
+    ) -> Result<Vec<WalkRequest>, ServiceError>;
+    /// `query_walk_requests` yielding the requests as they are read rather
+    /// than holding all of them, for large results. Stores without cursors
+    /// read them all first.
+    async fn stream_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<WalkRequestStream, ServiceError> {
+        let requests = self.query_walk_requests(query, sort_by, pagination).await?;
+        Ok(stream::iter(requests.into_iter().map(Ok)).boxed_local())
+    }
+    async fn create_walking_location(
+        &self,
+        create: WalkingLocationCreate,
+    ) -> Result<String, ServiceError>;
+    /// Ids of the created locations, in the order of `creates`.
+    async fn create_walking_locations<'a>(
+        &self,
+        creates: Vec<WalkingLocationCreate<'a>>,
+    ) -> Result<Vec<String>, ServiceError>;
+    async fn query_walking_locations(
+        &self,
+        query: WalkingLocationQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError>;
+    /// The recorded track simplified to `[longitude, latitude]` points, see
+    /// `geo::simplify`. Meant for old walks, whose full tracks are large.
+    async fn simplified_track(
+        &self,
+        walk_request_id: &str,
+        tolerance: Meters,
+    ) -> Result<Vec<[f64; 2]>, ServiceError> {
+        let track = self
+            .query_walking_locations(
+                WalkingLocationQuery {
+                    walk_request_id: walk_request_id.to_owned(),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?;
+        Ok(simplify(&track, tolerance))
+    }
+    async fn delete_walking_locations(&self, ids: &[String]) -> Result<u64, ServiceError>;
+    /// Walk requests with locations recorded before `before`.
+    async fn walk_requests_with_tracks_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, ServiceError>;
+    async fn query_holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError>;
+    async fn upsert_holiday(&self, holiday: Holiday) -> Result<(), ServiceError>;
+    async fn delete_holiday(&self, region: &str, date: NaiveDate) -> Result<u64, ServiceError>;
+    /// Moves requests finished or canceled before `before` out of the walk
+    /// requests into the archive, returning how many were moved.
+    async fn archive_walk_requests(&self, before: DateTime<Utc>) -> Result<u64, ServiceError>;
+    /// Archived requests, most recently created first.
+    async fn query_archived_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError>;
+    async fn walk_requests_active_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError>;
+    /// Marks unaccepted requests whose `should_start_before` is before `now`
+    /// as expired, returning how many were.
+    async fn save_daily_metrics(&self, metrics: Vec<DailyMetrics>) -> Result<(), ServiceError>;
+    async fn query_daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError>;
+    async fn upsert_application(
+        &self,
+        request_id: &str,
+        applicant_id: &str,
+        state: ApplicationState,
+    ) -> Result<(), ServiceError>;
+    async fn query_applications(
+        &self,
+        applicant_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError>;
+    async fn save_saga(&self, saga: &BookingSaga) -> Result<(), ServiceError>;
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError>;
+    async fn upsert_tenant(&self, tenant: Tenant) -> Result<(), ServiceError>;
+    async fn get_walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<WalkerCapabilities>, ServiceError>;
+    async fn upsert_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError>;
+    /// Failed sagas and those still running since before `updated_before`,
+    /// at most `limit`, least recently updated first.
+    async fn query_stuck_sagas(
+        &self,
+        updated_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError>;
+    async fn query_fitness_tokens(&self, user_id: &str) -> Result<Vec<FitnessToken>, ServiceError>;
+    async fn upsert_fitness_token(&self, token: FitnessToken) -> Result<(), ServiceError>;
+    async fn delete_fitness_token(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<u64, ServiceError>;
+    /// The owner's favorite walkers, most recently saved first.
+    async fn query_favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError>;
+    async fn upsert_favorite_walker(&self, favorite: FavoriteWalker) -> Result<(), ServiceError>;
+    async fn delete_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<u64, ServiceError>;
+    async fn save_fitness_export(&self, export: &FitnessExport) -> Result<(), ServiceError>;
+    /// Pending exports whose next attempt is due at `now`.
+    async fn due_fitness_exports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<FitnessExport>, ServiceError>;
+    async fn save_payment(&self, payment: &Payment) -> Result<(), ServiceError>;
+    async fn get_payment(&self, request_id: &str) -> Result<Option<Payment>, ServiceError>;
+    async fn save_receipt(&self, receipt: &Receipt) -> Result<(), ServiceError>;
+    async fn get_receipt(&self, request_id: &str) -> Result<Option<Receipt>, ServiceError>;
+    /// The owner's receipts issued in `[from, to)`, oldest first.
+    async fn query_receipts(
+        &self,
+        owner_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Receipt>, ServiceError>;
+    async fn save_payout(&self, payout: &Payout) -> Result<(), ServiceError>;
+    /// The walker's payouts created in `[from, to)`, oldest first.
+    async fn query_payouts(
+        &self,
+        walker_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payout>, ServiceError>;
+    async fn get_spending_cap(&self, owner_id: &str) -> Result<Option<SpendingCap>, ServiceError>;
+    async fn save_spending_cap(&self, cap: &SpendingCap) -> Result<(), ServiceError>;
+    async fn delete_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError>;
+    /// Records that the request reached `stage` at `at`, unless it already
+    /// had earlier.
+    async fn mark_funnel_stage(
+        &self,
+        request_id: &str,
+        stage: FunnelStage,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError>;
+    /// Adds `weight` impressions to each request, and marks those not viewed
+    /// before `at` as first viewed at `at`.
+    async fn record_impressions(
+        &self,
+        request_ids: &[String],
+        weight: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError>;
+    /// Marks of those of the requests which reached any stage.
+    async fn query_funnel_marks(
+        &self,
+        request_ids: &[String],
+    ) -> Result<Vec<FunnelMarks>, ServiceError>;
+    async fn save_device_session(&self, session: &DeviceSession) -> Result<(), ServiceError>;
+    /// Every device session of the user, revoked ones included.
+    async fn query_device_sessions(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<DeviceSession>, ServiceError>;
+    /// Records a strike, once per walker and request. Returns whether it
+    /// wasn't recorded before.
+    async fn record_strike(&self, strike: &Strike) -> Result<bool, ServiceError>;
+    /// Strikes of the walker, most recent first.
+    async fn query_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError>;
+    /// Adds to the counters of the user's reputation, creating it if needed.
+    async fn update_reputation(
+        &self,
+        user_id: &str,
+        update: ReputationUpdate,
+    ) -> Result<(), ServiceError>;
+    async fn get_reputation(&self, user_id: &str) -> Result<Option<Reputation>, ServiceError>;
+    /// Stores the flag of its feature, replacing the one stored before.
+    async fn save_feature_flag(&self, flag: &FeatureFlag) -> Result<(), ServiceError>;
+    /// Flags stored by admins, features never changed are missing.
+    async fn query_feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError>;
+    async fn save_offer(&self, offer: &Offer) -> Result<(), ServiceError>;
+    async fn get_offer(&self, id: &str) -> Result<Option<Offer>, ServiceError>;
+    /// Offers on the request, of the negotiation with `walker_id` when
+    /// given, oldest first.
+    async fn query_offers(
+        &self,
+        request_id: &str,
+        walker_id: Option<&str>,
+    ) -> Result<Vec<Offer>, ServiceError>;
+    /// Stores a message to deliver, or its state after a failed attempt.
+    async fn save_outbox_message(&self, message: &OutboxMessage) -> Result<(), ServiceError>;
+    async fn get_outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, ServiceError>;
+    async fn delete_outbox_message(&self, id: &str) -> Result<(), ServiceError>;
+    /// Pending messages due by `now`, oldest first.
+    async fn due_outbox_messages(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, ServiceError>;
+    /// Dead-lettered messages, most recently created first.
+    async fn dead_outbox_messages(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError>;
+    async fn save_start_reminders(&self, reminders: &[StartReminder]) -> Result<(), ServiceError>;
+    /// Reminders due by `now`, earliest first.
+    async fn due_start_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<StartReminder>, ServiceError>;
+    async fn delete_start_reminder(&self, id: &str) -> Result<(), ServiceError>;
+    /// Drops every pending reminder of the request.
+    async fn delete_start_reminders(&self, request_id: &str) -> Result<(), ServiceError>;
+    async fn save_walk_schedule(&self, schedule: &WalkSchedule) -> Result<(), ServiceError>;
+    async fn get_walk_schedule(&self, id: &str) -> Result<Option<WalkSchedule>, ServiceError>;
+    /// Schedules which are not canceled, of `owner_id` or of everyone.
+    async fn query_walk_schedules(
+        &self,
+        owner_id: Option<&str>,
+    ) -> Result<Vec<WalkSchedule>, ServiceError>;
+    async fn save_walk_request_template(
+        &self,
+        template: &WalkRequestTemplate,
+    ) -> Result<(), ServiceError>;
+    async fn get_walk_request_template(
+        &self,
+        id: &str,
+    ) -> Result<Option<WalkRequestTemplate>, ServiceError>;
+    /// Templates of `owner_id`, oldest first.
+    async fn query_walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError>;
+    async fn delete_walk_request_template(&self, id: &str) -> Result<(), ServiceError>;
+    async fn save_saved_search(&self, search: &SavedSearch) -> Result<(), ServiceError>;
+    async fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>, ServiceError>;
+    /// Searches of `walker_id`, oldest first.
+    async fn query_saved_searches(&self, walker_id: &str)
+        -> Result<Vec<SavedSearch>, ServiceError>;
+    /// Searches to notify of, whose tags are all among `tags`, oldest first.
+    async fn query_notifying_saved_searches(
+        &self,
+        tags: &[String],
+    ) -> Result<Vec<SavedSearch>, ServiceError>;
+    async fn delete_saved_search(&self, id: &str) -> Result<(), ServiceError>;
+    async fn get_backfill_run(&self, name: &str) -> Result<Option<BackfillRun>, ServiceError>;
+    async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), ServiceError>;
+    /// Appends an event to the timeline of its walk request.
+    async fn record_event(&self, event: &WalkRequestEvent) -> Result<(), ServiceError>;
+    /// The timeline of a walk request, oldest first.
+    async fn query_events(&self, request_id: &str) -> Result<Vec<WalkRequestEvent>, ServiceError>;
+    /// Stores a message and counts it as unread by `recipient`.
+    async fn create_message(&self, message: &Message, recipient: &str) -> Result<(), ServiceError>;
+    /// Messages of a request sent after `after`, oldest first.
+    async fn query_messages(
+        &self,
+        request_id: &str,
+        after: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Message>, ServiceError>;
+    async fn mark_messages_read(&self, request_id: &str, user_id: &str)
+        -> Result<(), ServiceError>;
+    /// Unread messages of `user_id` by request, requests without any are
+    /// left out.
+    async fn unread_messages(
+        &self,
+        user_id: &str,
+        request_ids: &[String],
+    ) -> Result<HashMap<String, u64>, ServiceError>;
+    /// Counts one call of `api_key` to `endpoint` in `month`, as rejected
+    /// when it was over the quota.
+    async fn record_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        month: &str,
+        rejected: bool,
+    ) -> Result<(), ServiceError>;
+    /// Usage of `month`, of one key or of every key.
+    async fn query_api_usage(
+        &self,
+        month: &str,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UsageRecord>, ServiceError>;
+    /// Id of the resource created by importing the legacy record `key`.
+    async fn imported_id(&self, key: &str) -> Result<Option<String>, ServiceError>;
+    async fn record_import(&self, key: &str, id: &str) -> Result<(), ServiceError>;
+    /// Counts `name` up, returning the new count, 1 for a new counter.
+    async fn next_sequence(&self, name: &str) -> Result<u64, ServiceError>;
+    /// Re-validates the tamper-evident audit log of a walk request, only kept
+    /// in event-sourced mode.
+    async fn verify_audit_chain(
+        &self,
+        _request_id: &str,
+    ) -> Result<AuditVerification, ServiceError> {
+        Err(ServiceError::NotFound("未开启审计日志".to_owned()))
+    }
+    /// Deletes the data of `class` created before `before`, returning how many
+    /// records were, or would be when `dry_run` is set, deleted.
+    async fn purge(
+        &self,
+        class: DataClass,
+        before: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<u64, ServiceError>;
 }
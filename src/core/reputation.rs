@@ -0,0 +1,56 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::strike::Strike;
+
+/// Strikes within `STRIKE_WINDOW_DAYS` that get a walker banned.
+pub const BAN_STRIKES: usize = 3;
+
+pub const STRIKE_WINDOW_DAYS: i64 = 30;
+
+/// How long a ban keeps a walker from accepting walks.
+pub const BAN_DAYS: i64 = 7;
+
+/// How reliable a user has been as a walker.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Reputation {
+    pub user_id: String,
+    pub completed_walks: i64,
+    pub strikes: i64,
+    /// Accepting walks is refused until then.
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+impl Reputation {
+    /// The reputation of a user with no record yet.
+    pub fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_owned(),
+            completed_walks: 0,
+            strikes: 0,
+            banned_until: None,
+        }
+    }
+
+    pub fn banned_at(&self, now: DateTime<Utc>) -> bool {
+        self.banned_until.map_or(false, |until| until > now)
+    }
+}
+
+/// Counters to add to a reputation, and a ban to set when given.
+#[derive(Debug, Clone, Default)]
+pub struct ReputationUpdate {
+    pub completed_walks: i64,
+    pub strikes: i64,
+    pub banned_until: Option<DateTime<Utc>>,
+}
+
+/// When the walker is banned until, if `strikes`, most recent first, reach
+/// the ban threshold within the window.
+pub fn ban_for(strikes: &[Strike], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let recent = strikes
+        .iter()
+        .filter(|s| s.recorded_at > now - Duration::days(STRIKE_WINDOW_DAYS))
+        .count();
+    (recent >= BAN_STRIKES).then(|| now + Duration::days(BAN_DAYS))
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Error;
+use chrono::{DateTime, DurationRound, Utc};
+use serde::Serialize;
+
+use super::{entities::WalkRequest, security::find_key};
+
+/// Side of the grid cells requests are bucketed into, in degrees (~5km).
+pub const CELL_DEGREES: f64 = 0.05;
+
+/// Buckets with fewer open requests are left out so no single owner can be
+/// singled out.
+pub const MIN_REPORTED_COUNT: u64 = 3;
+
+/// Longest range one call may cover.
+pub const MAX_RANGE_DAYS: i64 = 7;
+
+/// Anonymized number of requests waiting for a walker in one grid cell during
+/// one hour. The cell is identified by its south-west corner.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AreaHourCount {
+    pub cell_latitude: f64,
+    pub cell_longitude: f64,
+    pub hour: DateTime<Utc>,
+    pub open_requests: u64,
+}
+
+fn cell(value: f64) -> i64 {
+    (value / CELL_DEGREES).floor() as i64
+}
+
+/// When the request stopped waiting: accepted, canceled or expired.
+fn closed_at(request: &WalkRequest) -> Option<DateTime<Utc>> {
+    [request.accepted_at, request.canceled_at, request.expired_at]
+        .into_iter()
+        .flatten()
+        .min()
+}
+
+/// Counts, per cell and hour of `[from, to)`, the requests that were waiting
+/// for a walker at some point during that hour.
+pub fn open_request_counts(
+    requests: &[WalkRequest],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Vec<AreaHourCount> {
+    let hour = chrono::Duration::hours(1);
+    let mut buckets: BTreeMap<(DateTime<Utc>, i64, i64), u64> = BTreeMap::new();
+    for request in requests {
+        let Some(created_at) = request.created_at else {
+            continue;
+        };
+        let end = closed_at(request).map_or(to, |c| c.min(to));
+        let mut start = created_at
+            .max(from)
+            .duration_trunc(hour)
+            .expect("hour truncation");
+        while start < end {
+            *buckets
+                .entry((start, cell(request.latitude), cell(request.longitude)))
+                .or_default() += 1;
+            start += hour;
+        }
+    }
+    buckets
+        .into_iter()
+        .filter(|(_, n)| *n >= MIN_REPORTED_COUNT)
+        .map(|((hour, lat, lon), open_requests)| AreaHourCount {
+            cell_latitude: lat as f64 * CELL_DEGREES,
+            cell_longitude: lon as f64 * CELL_DEGREES,
+            hour,
+            open_requests,
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum QuotaError {
+    UnknownKey,
+    Exceeded,
+}
+
+/// Per API key request quotas over a fixed window, keys and limits come from
+/// configuration.
+#[derive(Clone)]
+pub struct ApiQuotas {
+    limits: HashMap<String, u32>,
+    window: Duration,
+    usage: Arc<Mutex<HashMap<String, (Instant, u32)>>>,
+}
+
+impl ApiQuotas {
+    /// Parses `key:limit,key:limit`.
+    pub fn parse(spec: &str, window: Duration) -> Result<Self, Error> {
+        let mut limits = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, limit) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的API密钥配置: {}", entry)))?;
+            limits.insert(key.to_owned(), limit.parse()?);
+        }
+        Ok(Self {
+            limits,
+            window,
+            usage: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    pub fn check(&self, key: &str) -> Result<(), QuotaError> {
+        let limit = *find_key(&self.limits, key).ok_or(QuotaError::UnknownKey)?;
+        let now = Instant::now();
+        let mut usage = self.usage.lock().unwrap();
+        let (started, used) = usage.entry(key.to_owned()).or_insert((now, 0));
+        if now.duration_since(*started) >= self.window {
+            *started = now;
+            *used = 0;
+        }
+        if *used >= limit {
+            return Err(QuotaError::Exceeded);
+        }
+        *used += 1;
+        Ok(())
+    }
+}
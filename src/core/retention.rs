@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use anyhow::Error;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Kinds of data with their own retention period.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DataClass {
+    /// Recorded walking locations.
+    Tracks,
+    Chat,
+    Photos,
+    /// The walk request event log.
+    Audit,
+}
+
+impl DataClass {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(name.to_owned())).ok()
+    }
+}
+
+/// How long each data class is kept, classes without a rule are kept forever.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    rules: BTreeMap<DataClass, Duration>,
+}
+
+impl RetentionPolicy {
+    /// Parses `class:days,class:days`, e.g. `tracks:90,audit:730`.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut rules = BTreeMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (class, days) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的保留策略配置: {}", entry)))?;
+            let class = DataClass::parse(class.trim())
+                .ok_or_else(|| Error::msg(format!("未知的数据类别: {}", class)))?;
+            let days: i64 = days.trim().parse()?;
+            if days <= 0 {
+                return Err(Error::msg(format!("保留天数必须大于0: {}", entry)));
+            }
+            rules.insert(class, Duration::days(days));
+        }
+        Ok(Self { rules })
+    }
+
+    /// Each class with a rule and the instant before which its data expires.
+    pub fn cutoffs(&self, now: DateTime<Utc>) -> Vec<(DataClass, DateTime<Utc>)> {
+        self.rules
+            .iter()
+            .map(|(class, keep)| (*class, now - *keep))
+            .collect()
+    }
+}
+
+/// Tracks of walks older than `after` are thinned out to one point per
+/// `interval`, long before the retention policy purges them.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackDownsampling {
+    pub after: Duration,
+    pub interval: Duration,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClassPurge {
+    pub class: DataClass,
+    pub before: DateTime<Utc>,
+    /// Deleted, or that would be deleted on a dry run.
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PurgeReport {
+    pub dry_run: bool,
+    pub run_at: DateTime<Utc>,
+    pub purged: Vec<ClassPurge>,
+}
@@ -0,0 +1,181 @@
+use std::io::Write;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use flate2::{write::ZlibEncoder, Compression};
+
+/// Size of route thumbnails, in pixels.
+pub const THUMBNAIL_WIDTH: u32 = 400;
+pub const THUMBNAIL_HEIGHT: u32 = 300;
+
+const MARGIN: f64 = 20.0;
+const BACKGROUND: [u8; 3] = [0xf4, 0xf1, 0xea];
+const ROUTE_COLOR: [u8; 3] = [0xff, 0x7a, 0x00];
+const START_COLOR: [u8; 3] = [0x2e, 0xa0, 0x43];
+const FINISH_COLOR: [u8; 3] = [0xd0, 0x3a, 0x2f];
+
+/// Static map services drawing a route over map tiles.
+#[async_trait]
+pub trait StaticMapProvider: Send + Sync {
+    /// A PNG of `width` by `height` pixels with the route of `polyline`,
+    /// see `encode_polyline`, drawn on the map.
+    async fn render(&self, polyline: &str, width: u32, height: u32) -> Result<Vec<u8>, Error>;
+}
+
+fn encode_value(encoded: &mut String, value: i64) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        encoded.push(char::from((((value & 0x1f) | 0x20) + 63) as u8));
+        value >>= 5;
+    }
+    encoded.push(char::from((value + 63) as u8));
+}
+
+/// The route of `[longitude, latitude]` points in the encoded polyline
+/// format static map services take, at five decimal places.
+pub fn encode_polyline(route: &[[f64; 2]]) -> String {
+    let mut encoded = String::new();
+    let (mut last_lat, mut last_lon) = (0, 0);
+    for [lon, lat] in route {
+        let (lat, lon) = ((lat * 1e5).round() as i64, (lon * 1e5).round() as i64);
+        encode_value(&mut encoded, lat - last_lat);
+        encode_value(&mut encoded, lon - last_lon);
+        (last_lat, last_lon) = (lat, lon);
+    }
+    encoded
+}
+
+/// The route scaled into a `width` by `height` box offset by `margin`,
+/// north up, as (x, y).
+pub fn project(route: &[[f64; 2]], width: f64, height: f64, margin: f64) -> Vec<(f64, f64)> {
+    let (mut min_lon, mut max_lon, mut min_lat, mut max_lat) =
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+    for [lon, lat] in route {
+        min_lon = min_lon.min(*lon);
+        max_lon = max_lon.max(*lon);
+        min_lat = min_lat.min(*lat);
+        max_lat = max_lat.max(*lat);
+    }
+    // Degrees of longitude shrink away from the equator.
+    let x_scale = ((min_lat + max_lat) / 2.0).to_radians().cos();
+    let route_width = ((max_lon - min_lon) * x_scale).max(f64::EPSILON);
+    let route_height = (max_lat - min_lat).max(f64::EPSILON);
+    let scale = (width / route_width).min(height / route_height);
+    route
+        .iter()
+        .map(|[lon, lat]| {
+            (
+                margin + (lon - min_lon) * x_scale * scale,
+                margin + (max_lat - lat) * scale,
+            )
+        })
+        .collect()
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: BACKGROUND.repeat((width * height) as usize),
+        }
+    }
+
+    fn disc(&mut self, (cx, cy): (f64, f64), radius: f64, color: [u8; 3]) {
+        let r = radius.ceil() as i64;
+        for y in (cy as i64 - r)..=(cy as i64 + r) {
+            for x in (cx as i64 - r)..=(cx as i64 + r) {
+                let inside = (x as f64 - cx).powi(2) + (y as f64 - cy).powi(2) <= radius * radius;
+                if inside
+                    && (0..self.width as i64).contains(&x)
+                    && (0..self.height as i64).contains(&y)
+                {
+                    let i = (y as usize * self.width as usize + x as usize) * 3;
+                    self.pixels[i..i + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), radius: f64, color: [u8; 3]) {
+        let steps = (to.0 - from.0)
+            .abs()
+            .max((to.1 - from.1).abs())
+            .ceil()
+            .max(1.0);
+        for step in 0..=steps as usize {
+            let t = step as f64 / steps;
+            let point = (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t);
+            self.disc(point, radius, color);
+        }
+    }
+
+    fn png(&self) -> Result<Vec<u8>, Error> {
+        let mut scanlines = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in self.pixels.chunks(self.width as usize * 3) {
+            // Each scanline starts with its filter type, none.
+            scanlines.write_all(&[0])?;
+            scanlines.write_all(row)?;
+        }
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // 8 bit RGB, default compression and filtering, not interlaced.
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"IDAT", &scanlines.finish()?);
+        chunk(&mut png, b"IEND", &[]);
+        Ok(png)
+    }
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// The route drawn on a plain background, for when no static map service
+/// is configured or it fails.
+pub fn render_png(route: &[[f64; 2]], width: u32, height: u32) -> Result<Vec<u8>, Error> {
+    let mut canvas = Canvas::new(width, height);
+    let points = project(
+        route,
+        width as f64 - 2.0 * MARGIN,
+        height as f64 - 2.0 * MARGIN,
+        MARGIN,
+    );
+    for pair in points.windows(2) {
+        canvas.line(pair[0], pair[1], 2.0, ROUTE_COLOR);
+    }
+    if let (Some(start), Some(finish)) = (points.first(), points.last()) {
+        canvas.disc(*start, 6.0, START_COLOR);
+        canvas.disc(*finish, 6.0, FINISH_COLOR);
+    }
+    canvas.png()
+}
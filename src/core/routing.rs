@@ -0,0 +1,32 @@
+use anyhow::Error;
+use async_trait::async_trait;
+
+use super::entities::WalkingLocation;
+
+/// Road and path network services.
+#[async_trait]
+pub trait RoutingProvider: Send + Sync {
+    /// Snaps a recorded track to the network, one point per location of
+    /// `track` as (longitude, latitude), `None` where nothing matched.
+    async fn match_track(
+        &self,
+        track: &[WalkingLocation],
+    ) -> Result<Vec<Option<(f64, f64)>>, Error>;
+}
+
+/// The track with each location moved to its matched point, unmatched ones
+/// are left where they were recorded.
+pub fn snap(track: Vec<WalkingLocation>, matched: Vec<Option<(f64, f64)>>) -> Vec<WalkingLocation> {
+    track
+        .into_iter()
+        .zip(matched.into_iter().chain(std::iter::repeat(None)))
+        .map(|(location, point)| match point {
+            Some((longitude, latitude)) => WalkingLocation {
+                longitude,
+                latitude,
+                ..location
+            },
+            None => location,
+        })
+        .collect()
+}
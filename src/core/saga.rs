@@ -0,0 +1,74 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::error::ServiceError;
+
+/// Payment service operations the booking saga depends on.
+#[async_trait]
+pub trait PaymentHolds: Send + Sync {
+    /// Places a hold on the owner's payment method, returns the hold id.
+    async fn hold(&self, request_id: &str, owner_id: &str) -> Result<String, Error>;
+    async fn confirm(&self, hold_id: &str) -> Result<(), Error>;
+    async fn release(&self, hold_id: &str) -> Result<(), Error>;
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SagaStep {
+    Started,
+    Accepted,
+    PaymentHeld,
+    Confirmed,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SagaStatus {
+    Running,
+    Completed,
+    Compensated,
+    Failed,
+}
+
+/// Persisted progress of one accept → hold payment → confirm booking, so a
+/// crashed or half-compensated booking can be found and repaired.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookingSaga {
+    pub id: String,
+    pub request_id: String,
+    pub walker_id: String,
+    pub step: SagaStep,
+    pub status: SagaStatus,
+    pub hold_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl BookingSaga {
+    pub fn new(request_id: &str, walker_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: format!("{}:{}:{}", request_id, walker_id, now.timestamp_millis()),
+            request_id: request_id.to_owned(),
+            walker_id: walker_id.to_owned(),
+            step: SagaStep::Started,
+            status: SagaStatus::Running,
+            hold_id: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn advance(&mut self, step: SagaStep) {
+        self.step = step;
+        self.updated_at = Utc::now();
+    }
+
+    pub fn finish(&mut self, status: SagaStatus, error: Option<&ServiceError>) {
+        self.status = status;
+        self.error = error.map(|e| e.to_string());
+        self.updated_at = Utc::now();
+    }
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{geo::distance, repository::NearbyFilter, tag::normalize_tags, units::Meters};
+
+/// Searches a walker may keep.
+pub const MAX_SAVED_SEARCHES_PER_WALKER: usize = 10;
+
+pub const MAX_SAVED_SEARCH_NAME_CHARS: usize = 50;
+
+/// Walkers notified of one new request at most, the ones who saved their
+/// search first.
+pub const MAX_SAVED_SEARCH_NOTIFICATIONS: usize = 200;
+
+fn notify_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SavedSearchCreate {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius: Meters,
+    /// Requests must carry all of them.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub min_dogs: Option<i64>,
+    pub max_dogs: Option<i64>,
+    #[serde(default = "notify_by_default")]
+    pub notify: bool,
+}
+
+/// A nearby search a walker saved to run again, and to be notified of new
+/// requests matching it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub id: String,
+    pub walker_id: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius: Meters,
+    /// Normalized, see `tag::normalize_tags`.
+    pub tags: Vec<String>,
+    pub min_dogs: Option<i64>,
+    pub max_dogs: Option<i64>,
+    /// Whether the walker is notified of new requests matching it.
+    pub notify: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl SavedSearch {
+    pub fn new(id: String, walker_id: &str, create: SavedSearchCreate) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            walker_id: walker_id.to_owned(),
+            name: create.name.trim().to_owned(),
+            latitude: create.latitude,
+            longitude: create.longitude,
+            radius: create.radius,
+            tags: normalize_tags(&create.tags),
+            min_dogs: create.min_dogs,
+            max_dogs: create.max_dogs,
+            notify: create.notify,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// The filters of the search as a nearby search takes them.
+    pub fn filter(&self) -> NearbyFilter {
+        NearbyFilter {
+            min_dogs: self.min_dogs,
+            max_dogs: self.max_dogs,
+            tags: self.tags.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a request picked up at `point`, as (longitude, latitude),
+    /// with `dogs` dogs and `tags` turns up in the search.
+    pub fn matches(&self, point: (f64, f64), dogs: usize, tags: &[String]) -> bool {
+        let dogs = dogs as i64;
+        distance((self.longitude, self.latitude), point) <= self.radius
+            && self.min_dogs.map_or(true, |min| dogs >= min)
+            && self.max_dogs.map_or(true, |max| dogs <= max)
+            && self.tags.iter().all(|t| tags.contains(t))
+    }
+}
@@ -0,0 +1,290 @@
+use anyhow::Error;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use little_walk_dog::core::entities::Dog;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::TrackVisibility,
+    metrics::DEFAULT_REGION,
+    repository::WalkRequestCreate,
+    timezone::{parse_timezone, DEFAULT_TIMEZONE},
+    units::{Meters, Money},
+    walker_capabilities::DogRequirements,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+}
+
+/// The subset of RFC 5545 RRULEs schedules support, e.g.
+/// `FREQ=WEEKLY;BYDAY=MO,WE,FR;BYHOUR=8;BYMINUTE=30;UNTIL=20261231`.
+/// Times are local to the schedule's timezone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    frequency: Frequency,
+    /// Only used by weekly rules.
+    weekdays: Vec<Weekday>,
+    hours: Vec<u32>,
+    minutes: Vec<u32>,
+    /// Last local date with occurrences.
+    until: Option<NaiveDate>,
+}
+
+fn parse_weekday(name: &str) -> Option<Weekday> {
+    Some(match name {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+fn parse_numbers(values: &str, max: u32) -> Option<Vec<u32>> {
+    values
+        .split(',')
+        .map(|v| v.trim().parse::<u32>().ok().filter(|n| *n <= max))
+        .collect()
+}
+
+impl Recurrence {
+    pub fn parse(rule: &str) -> Result<Self, Error> {
+        let invalid = || Error::msg(format!("无效的重复规则: {}", rule));
+        let mut frequency = None;
+        let mut weekdays = Vec::new();
+        let mut hours = vec![0];
+        let mut minutes = vec![0];
+        let mut until = None;
+        for part in rule.split(';').map(str::trim).filter(|p| !p.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(invalid)?;
+            match name.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.trim().to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "BYDAY" => {
+                    weekdays = value
+                        .split(',')
+                        .map(|d| parse_weekday(&d.trim().to_ascii_uppercase()))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?
+                }
+                "BYHOUR" => hours = parse_numbers(value, 23).ok_or_else(invalid)?,
+                "BYMINUTE" => minutes = parse_numbers(value, 59).ok_or_else(invalid)?,
+                "UNTIL" => {
+                    // Only the date of `YYYYMMDD` or `YYYYMMDDTHHMMSSZ` is kept.
+                    let date = value.trim().get(..8).ok_or_else(invalid)?;
+                    until = Some(NaiveDate::parse_from_str(date, "%Y%m%d").map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        let frequency = frequency.ok_or_else(invalid)?;
+        if frequency == Frequency::Weekly && weekdays.is_empty() {
+            return Err(invalid());
+        }
+        hours.sort_unstable();
+        hours.dedup();
+        minutes.sort_unstable();
+        minutes.dedup();
+        Ok(Self {
+            frequency,
+            weekdays,
+            hours,
+            minutes,
+            until,
+        })
+    }
+
+    fn on(&self, date: NaiveDate) -> bool {
+        if self.until.map_or(false, |until| date > until) {
+            return false;
+        }
+        match self.frequency {
+            Frequency::Daily => true,
+            Frequency::Weekly => self.weekdays.contains(&date.weekday()),
+        }
+    }
+
+    /// Occurrences after `from` up to and including `to`, in order. Local
+    /// times skipped by a DST change have no occurrence.
+    pub fn occurrences(
+        &self,
+        tz: Tz,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        let mut occurrences = Vec::new();
+        let last = to.with_timezone(&tz).date_naive();
+        let mut date = from.with_timezone(&tz).date_naive();
+        while date <= last {
+            if self.on(date) {
+                for hour in &self.hours {
+                    for minute in &self.minutes {
+                        let Some(local) = date.and_hms_opt(*hour, *minute, 0) else {
+                            continue;
+                        };
+                        let Some(time) = tz.from_local_datetime(&local).earliest() else {
+                            continue;
+                        };
+                        let time = time.with_timezone(&Utc);
+                        if time > from && time <= to {
+                            occurrences.push(time);
+                        }
+                    }
+                }
+            }
+            date = match date.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        occurrences
+    }
+
+    /// Whether no occurrence is left after `time`.
+    pub fn ended(&self, tz: Tz, time: DateTime<Utc>) -> bool {
+        self.until
+            .map_or(false, |until| time.with_timezone(&tz).date_naive() > until)
+    }
+}
+
+fn default_timezone() -> String {
+    DEFAULT_TIMEZONE.to_owned()
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_owned()
+}
+
+fn default_start_window_minutes() -> i64 {
+    30
+}
+
+fn default_duration_minutes() -> i64 {
+    60
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalkScheduleCreate {
+    /// See `Recurrence`.
+    pub rule: String,
+    /// How long after each occurrence the walk may start.
+    #[serde(default = "default_start_window_minutes")]
+    pub start_window_minutes: i64,
+    #[serde(default = "default_duration_minutes")]
+    pub duration_minutes: i64,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+}
+
+/// A recurring walk, materialized into one walk request per occurrence
+/// ahead of time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkSchedule {
+    pub id: String,
+    pub owner_id: String,
+    pub rule: String,
+    pub start_window_minutes: i64,
+    pub duration_minutes: i64,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub timezone: String,
+    pub region: String,
+    pub max_applicants: Option<i64>,
+    pub requirements: DogRequirements,
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+    /// Occurrences up to this time have their walk request.
+    pub materialized_until: DateTime<Utc>,
+    pub canceled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WalkSchedule {
+    pub fn new(id: String, owner_id: &str, create: WalkScheduleCreate) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            owner_id: owner_id.to_owned(),
+            rule: create.rule,
+            start_window_minutes: create.start_window_minutes,
+            duration_minutes: create.duration_minutes,
+            dogs: create.dogs,
+            latitude: create.latitude,
+            longitude: create.longitude,
+            timezone: create.timezone,
+            region: create.region,
+            max_applicants: create.max_applicants,
+            requirements: create.requirements,
+            track_visibility: create.track_visibility,
+            max_radius: create.max_radius,
+            price: create.price,
+            materialized_until: now,
+            canceled_at: None,
+            created_at: now,
+        }
+    }
+
+    pub fn recurrence(&self) -> Result<(Recurrence, Tz), Error> {
+        Ok((
+            Recurrence::parse(&self.rule)?,
+            parse_timezone(&self.timezone)?,
+        ))
+    }
+
+    /// The walk request of the occurrence at `time`.
+    pub fn request_at(&self, time: DateTime<Utc>) -> WalkRequestCreate {
+        let window = Duration::minutes(self.start_window_minutes);
+        let end = time + Duration::minutes(self.duration_minutes);
+        WalkRequestCreate {
+            dogs: self.dogs.clone(),
+            should_start_after: Some(time),
+            should_start_before: Some(time + window),
+            should_end_after: Some(end),
+            should_end_before: Some(end + window),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: self.timezone.clone(),
+            region: self.region.clone(),
+            max_applicants: self.max_applicants,
+            requirements: self.requirements,
+            track_visibility: self.track_visibility,
+            max_radius: self.max_radius,
+            price: self.price.clone(),
+            max_walkers: None,
+            apply_before: None,
+            created_by: self.owner_id.clone(),
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: None,
+            invitation_expires_at: None,
+            tags: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::Error;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::error::ServiceError;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Key id of a secret configured on its own, without a key ring.
+pub const DEFAULT_KID: &str = "default";
+
+/// Compares secrets in time independent of where they differ, so that
+/// response times don't tell how much of a guess was right. Only the
+/// length may leak.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Finds the entry whose key equals `key`, comparing every one of them in
+/// constant time rather than hashing the candidate.
+pub fn find_key<'a, V>(entries: impl IntoIterator<Item = (&'a String, V)>, key: &str) -> Option<V> {
+    let mut found = None;
+    for (candidate, value) in entries {
+        if constant_time_eq(candidate.as_bytes(), key.as_bytes()) && found.is_none() {
+            found = Some(value);
+        }
+    }
+    found
+}
+
+pub fn hmac_sha256(secret: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(payload);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A key of a ring without its secret, for listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyInfo {
+    pub kid: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyRotation {
+    pub kid: String,
+    pub secret: String,
+}
+
+#[derive(Debug, Default)]
+struct Keys {
+    /// The active key first, older ones are only used to verify.
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+/// Secrets of one purpose by key id. New signatures use the active key,
+/// every key of the ring is accepted when verifying, so that keys can be
+/// rotated without invalidating what was signed with the previous one.
+/// Clones share the keys, rotations are seen by every holder.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRing {
+    keys: Arc<RwLock<Keys>>,
+}
+
+impl KeyRing {
+    /// `ring` is `kid:secret,kid:secret` with the active key first. When
+    /// empty, `secret` alone is the key, with id `default`.
+    pub fn parse(ring: &str, secret: &str) -> Result<Self, Error> {
+        let mut keys = Vec::new();
+        for entry in ring.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kid, secret) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的密钥配置: {}", entry)))?;
+            keys.push((kid.trim().to_owned(), secret.trim().as_bytes().to_vec()));
+        }
+        if keys.is_empty() && !secret.is_empty() {
+            keys.push((DEFAULT_KID.to_owned(), secret.as_bytes().to_vec()));
+        }
+        Ok(Self {
+            keys: Arc::new(RwLock::new(Keys { keys })),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.read().unwrap().keys.is_empty()
+    }
+
+    /// Id and secret of the key new signatures use.
+    pub fn active(&self) -> Option<(String, Vec<u8>)> {
+        self.keys.read().unwrap().keys.first().cloned()
+    }
+
+    pub fn get(&self, kid: &str) -> Option<Vec<u8>> {
+        let keys = self.keys.read().unwrap();
+        keys.keys
+            .iter()
+            .find(|(k, _)| k == kid)
+            .map(|(_, secret)| secret.clone())
+    }
+
+    /// Every secret, the active one first.
+    pub fn secrets(&self) -> Vec<(String, Vec<u8>)> {
+        self.keys.read().unwrap().keys.clone()
+    }
+
+    pub fn list(&self) -> Vec<KeyInfo> {
+        self.keys
+            .read()
+            .unwrap()
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, (kid, _))| KeyInfo {
+                kid: kid.clone(),
+                active: i == 0,
+            })
+            .collect()
+    }
+
+    /// Makes a new key the active one, the previous keys stay valid until
+    /// retired.
+    pub fn rotate(&self, rotation: KeyRotation) -> Result<(), ServiceError> {
+        if rotation.kid.is_empty() || rotation.secret.is_empty() {
+            return Err(ServiceError::Validation("密钥ID和密钥不能为空".to_owned()));
+        }
+        let mut keys = self.keys.write().unwrap();
+        if keys.keys.iter().any(|(k, _)| *k == rotation.kid) {
+            return Err(ServiceError::Conflict("密钥ID已存在".to_owned()));
+        }
+        keys.keys
+            .insert(0, (rotation.kid, rotation.secret.into_bytes()));
+        Ok(())
+    }
+
+    /// Stops accepting a key, the active key can't be retired.
+    pub fn retire(&self, kid: &str) -> Result<bool, ServiceError> {
+        let mut keys = self.keys.write().unwrap();
+        match keys.keys.iter().position(|(k, _)| k == kid) {
+            Some(0) => Err(ServiceError::Conflict("不能停用当前使用的密钥".to_owned())),
+            Some(i) => {
+                keys.keys.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// The rotatable secrets of the service.
+#[derive(Debug, Clone, Default)]
+pub struct SecretRings {
+    /// Verifies access tokens.
+    pub jwt: KeyRing,
+    /// Signs calendar subscription tokens.
+    pub calendar: KeyRing,
+    /// Signs outgoing webhooks.
+    pub webhook: KeyRing,
+}
+
+impl SecretRings {
+    pub fn get(&self, name: &str) -> Option<&KeyRing> {
+        match name {
+            "jwt" => Some(&self.jwt),
+            "calendar" => Some(&self.calendar),
+            "webhook" => Some(&self.webhook),
+            _ => None,
+        }
+    }
+}
@@ -1,293 +1,6494 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::default;
+use std::future::Future;
+use std::sync::Arc;
 
 use super::{
-    entities::WalkRequest,
+    alert::{Alert, AlertKind, AlertRouter},
+    archive::{self, TrackArchive},
+    audit::AuditVerification,
+    backfill::{Backfill, BackfillDefinition, BackfillDryRun, BackfillRun, Backfills},
+    bulk::{diff, BulkUpdateReport, DIFF_SAMPLE_SIZE, MAX_BULK_UPDATE},
+    cache::NearbyCache,
+    card::{preview_route, SummaryCard},
+    client_time::{ClientTime, MAX_CLOCK_AHEAD_SECONDS},
+    currency::{CurrencyZones, FixedRates, RatesProvider},
+    delegation::Delegation,
+    distance::{DistanceCalculator, DistanceStrategy, Haversine, MapMatched, Smoothed},
+    email::{EmailTemplate, Emailer, DEFAULT_LOCALE},
+    entities::{
+        Application, ApplicationState, PoolWalker, TrackVisibility, WalkRequest, WalkRequestStatus,
+        WalkingLocation,
+    },
+    error::ServiceError,
+    events::{EventPublisher, NoopPublisher, WalkRequestEvent, WalkRequestEventKind},
+    experiments::{Experiments, Exposure},
+    export::EXPORT_PAGE_SIZE,
+    favorite::{FavoriteWalker, MAX_FAVORITES_PER_OWNER},
+    feature_flags::{Feature, FeatureFlag, FeatureFlagUpdate, FeatureFlags},
+    feed::RecentEvents,
+    fitness::{FitnessActivity, FitnessExport, FitnessProvider},
+    geo::{self, distance, BoundingBox, WalkProgress, WalkSummary},
+    holiday::{Holiday, HolidayCalendar},
+    ids::{new_ulid, IdGenerator},
+    import::{
+        map_track_point, map_walk_request, parse_dump, DumpFormat, FieldMapping, ImportIssue,
+        ImportReport,
+    },
+    impression::{ImpressionSampling, MAX_IMPRESSIONS_PER_BATCH},
+    ingestion::{LocationQueue, QueuedLocation},
+    live::{ChangeBroker, LocationBroker, WalkRequestChange},
+    location_filter::{LocationFilter, LocationWrite},
+    message::{Message, MessageCreate, MESSAGE_PAGE_SIZE},
+    metrics::{
+        funnel, rollup, DailyMetrics, FunnelMetrics, FunnelStage, DEFAULT_REGION, MAX_FUNNEL_DAYS,
+    },
+    notification::{Notification, NotificationKind, Notifier},
+    offer::{Offer, OfferCreate, OfferState},
+    onboarding::OnboardingDirectory,
+    outbox::{
+        OutboxMessage, OutboxPayload, OutboxStatus, PendingEvent, Publications, OUTBOX_BATCH_SIZE,
+    },
+    payment::{Payment, PaymentGateway},
+    preview::{
+        PreviewWarning, WalkRequestPreview, VISIBILITY_LOOKBACK_DAYS, VISIBILITY_RADIUS,
+        VISIBILITY_SAMPLE_SIZE,
+    },
+    profile::UserDirectory,
+    ranking::{Ranker, RankingContext},
+    receipt::{Payout, Receipt, ReceiptEmail},
+    recompute::{RecomputeJobs, RecomputeProgress, RecomputeScope},
+    reconcile::{diagnose, ReconcileReport},
+    reminder::{StartReminder, DEFAULT_START_REMINDER_MINUTES, REMINDER_BATCH_SIZE},
+    report::{ReportInterval, ReportRole, WalkReport, DEFAULT_REPORT_DAYS, MAX_REPORT_DAYS},
     repository::{
-        Order, Pagination, Repository, SortBy, WalkRequestCreate, WalkRequestQuery,
-        WalkRequestUpdate, WalkingLocationCreate,
+        InvitationViewer, NearbyCursor, NearbyFilter, Order, PageLimits, Paged, Pagination,
+        Repository, SortBy, WalkRequestCreate, WalkRequestQuery, WalkRequestStream,
+        WalkRequestUpdate, WalkingLocationCreate, WalkingLocationQuery,
+    },
+    reputation::{ban_for, Reputation, ReputationUpdate},
+    research::{open_request_counts, AreaHourCount, MAX_RANGE_DAYS},
+    retention::{ClassPurge, DataClass, PurgeReport, RetentionPolicy, TrackDownsampling},
+    route_map::{
+        encode_polyline, render_png, StaticMapProvider, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH,
+    },
+    routing::{self, RoutingProvider},
+    saga::{BookingSaga, PaymentHolds, SagaStatus, SagaStep},
+    saved_search::{
+        SavedSearch, SavedSearchCreate, MAX_SAVED_SEARCHES_PER_WALKER,
+        MAX_SAVED_SEARCH_NOTIFICATIONS,
+    },
+    schedule::{WalkSchedule, WalkScheduleCreate},
+    security::{KeyInfo, KeyRing, KeyRotation, SecretRings},
+    session::{push_tokens, DeviceSession, DeviceSessionRegister},
+    simulation::{simulate, RegionSimulation, SimulationParams},
+    sla::SlaPolicy,
+    spending::{month_of, MonthlySpending, SpendingCap},
+    strike::{Strike, StrikeReason},
+    tag::normalize_tags,
+    template::{
+        WalkRequestTemplate, WalkRequestTemplateCreate, WindowOffsets, MAX_TEMPLATES_PER_OWNER,
+        MAX_TEMPLATE_NAME_CHARS,
     },
+    tenant::Tenant,
+    timezone::{display, parse_timezone, to_local, DEFAULT_TIMEZONE},
+    units::{Meters, Money},
+    usage::{self, MonthlyQuotas, QuotaStatus, UsageRecord},
+    validation::Validate,
+    verification::WalkVerification,
+    walk_budget::{active_minutes, WalkBudget, WalkBudgetPolicy},
+    walker_capabilities::{CapabilityDirectory, DogSize, WalkerCapabilities},
 };
-use anyhow::Error;
-use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+use futures::{
+    channel::mpsc::UnboundedReceiver,
+    stream::{self, LocalBoxStream},
+    StreamExt, TryStreamExt,
+};
+use little_walk_dog::core::entities::Dog;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ApplyError {
+    PreviouslyDismissed,
+    ApplicantCapReached,
+    DeadlinePassed,
+    NotWaiting,
+    NotApplied,
+}
+
+impl fmt::Display for ApplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplyError::PreviouslyDismissed => write!(f, "您已被狗狗主人移除，无法再次报名"),
+            ApplyError::ApplicantCapReached => write!(f, "报名人数已满"),
+            ApplyError::DeadlinePassed => write!(f, "报名已截止"),
+            ApplyError::NotWaiting => write!(f, "代遛请求已不在等待接单状态"),
+            ApplyError::NotApplied => write!(f, "您尚未报名该代遛请求"),
+        }
+    }
+}
+
+impl std::error::Error for ApplyError {}
+
+impl From<ApplyError> for ServiceError {
+    fn from(e: ApplyError) -> Self {
+        ServiceError::Conflict(e.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidTransition {
+    pub from: WalkRequestStatus,
+    pub to: WalkRequestStatus,
+}
+
+impl fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "代遛请求无法从{:?}状态变为{:?}状态", self.from, self.to)
+    }
+}
+
+impl std::error::Error for InvalidTransition {}
+
+impl From<InvalidTransition> for ServiceError {
+    fn from(e: InvalidTransition) -> Self {
+        ServiceError::Conflict(e.to_string())
+    }
+}
+
+const MAX_CANCELLATION_REASON_CHARS: usize = 500;
+
+/// Trims the reason a walk was called off, blank reasons are dropped.
+fn cancellation_reason(reason: Option<String>) -> Result<Option<String>, ServiceError> {
+    let Some(reason) = reason
+        .map(|r| r.trim().to_owned())
+        .filter(|r| !r.is_empty())
+    else {
+        return Ok(None);
+    };
+    if reason.chars().count() > MAX_CANCELLATION_REASON_CHARS {
+        return Err(ServiceError::Validation(format!(
+            "取消原因不能超过{}个字符",
+            MAX_CANCELLATION_REASON_CHARS
+        )));
+    }
+    Ok(Some(reason))
+}
+
+/// Walks accepted by the walker, not yet over, whose time window overlaps
+/// `start..end`. Walks without a full time window never overlap.
+fn overlapping_walks(user_id: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> WalkRequestQuery {
+    WalkRequestQuery {
+        accepted_by: Some(user_id.to_owned()),
+        canceled_at_is_null: Some(true),
+        finished_at_is_null: Some(true),
+        should_start_after_lt: Some(end),
+        should_end_before_gt: Some(start),
+        ..Default::default()
+    }
+}
+
+/// Fields users may sort their own request lists by.
+const MY_SORT_FIELDS: &[&str] = &[
+    "created_at",
+    "updated_at",
+    "should_start_after",
+    "should_start_before",
+];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct MyWalkRequestsFilter {
+    pub status: Option<WalkRequestStatus>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub sort_by: Option<String>,
+    pub order: Option<Order>,
+    pub dog_name: Option<String>,
+    pub dog_breed: Option<String>,
+    pub dog_size: Option<DogSize>,
+}
+
+/// Which side of their archived walks a user's history lists.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryRole {
+    #[default]
+    Owner,
+    Walker,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryFilter {
+    #[serde(default)]
+    pub role: HistoryRole,
+}
+
+/// Applicants targeted by a bulk dismissal, `{"users": [...]}` or
+/// `{"all_except": [...]}`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplicantSelection {
+    Users(Vec<String>),
+    AllExcept(Vec<String>),
+}
+
+/// Time windows of a cloned request, the original's are never reused.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeWindows {
+    pub should_start_after: Option<DateTime<Utc>>,
+    pub should_start_before: Option<DateTime<Utc>>,
+    pub should_end_after: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+}
+
+/// What owners may change on a request nobody accepted yet, fields left out
+/// are kept.
+#[derive(Debug, Clone, Default)]
+pub struct WalkRequestEdit {
+    pub dogs: Option<Vec<Dog>>,
+    pub should_start_after: Option<DateTime<Utc>>,
+    pub should_start_before: Option<DateTime<Utc>>,
+    pub should_end_after: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub tags: Option<Vec<String>>,
+    /// The version the edit is based on, it fails when the request changed
+    /// since. Also taken from `If-Match`.
+    pub expected_version: Option<i64>,
+}
+
+/// Most points accepted in one batch upload.
+pub const MAX_LOCATION_BATCH: usize = 1000;
+
+/// How far ahead schedules create walk requests by default.
+const DEFAULT_SCHEDULE_HORIZON_HOURS: i64 = 48;
+
+/// Walk requests loaded at once when recomputing summaries.
+const RECOMPUTE_PAGE_SIZE: i64 = 100;
+
+/// Reads and writes of a pool walk tried before giving up on walkers of
+/// the pool racing each other.
+const POOL_UPDATE_ATTEMPTS: usize = 3;
+
+/// How long after the start window opened an accepted walker counts as a
+/// no-show when they haven't started.
+const NO_SHOW_GRACE_MINUTES: i64 = 30;
+
+/// A GPS point buffered by the walker app while offline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecordedLocation {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedLocation {
+    /// Position of the point in the uploaded batch.
+    pub index: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LocationBatchReport {
+    /// Ids of the stored points, in upload order.
+    pub ids: Vec<String>,
+    pub rejected: Vec<RejectedLocation>,
+}
+
+#[derive(Clone)]
+pub struct Service<R>
+where
+    R: Repository + Clone,
+{
+    repository: R,
+    emailer: Option<Arc<Emailer>>,
+    holidays: HolidayCalendar,
+    sla: SlaPolicy,
+    max_applicants: Option<i64>,
+    payments: Option<Arc<dyn PaymentHolds>>,
+    gateway: Option<Arc<dyn PaymentGateway>>,
+    onboarding: Option<Arc<dyn OnboardingDirectory>>,
+    capabilities: Option<Arc<dyn CapabilityDirectory>>,
+    users: Option<Arc<dyn UserDirectory>>,
+    events: Arc<dyn EventPublisher>,
+    /// Whether events go out through the outbox, without a configured
+    /// publisher they would only be dropped.
+    publishes_events: bool,
+    retention: RetentionPolicy,
+    archive: Option<Arc<dyn TrackArchive>>,
+    track_downsampling: Option<TrackDownsampling>,
+    walk_budget: Option<WalkBudgetPolicy>,
+    cancel_undo_window: Option<chrono::Duration>,
+    archive_after: Option<chrono::Duration>,
+    currencies: CurrencyZones,
+    rates: Arc<dyn RatesProvider>,
+    delegation: Option<Delegation>,
+    /// The city the service is scoped to, see `for_tenant`.
+    tenant: Option<Tenant>,
+    /// See `confirming_over_cap`.
+    over_cap_confirmed: bool,
+    locations: Option<LocationBroker>,
+    changes: ChangeBroker,
+    fitness: Vec<Arc<dyn FitnessProvider>>,
+    routing: Option<Arc<dyn RoutingProvider>>,
+    static_maps: Option<Arc<dyn StaticMapProvider>>,
+    notifier: Option<Arc<dyn Notifier>>,
+    distance_strategy: DistanceStrategy,
+    recent_events: RecentEvents,
+    alerts: AlertRouter,
+    max_radius: Option<Meters>,
+    page_limits: PageLimits,
+    schedule_horizon: chrono::Duration,
+    /// Minutes before the start both parties are reminded at.
+    start_reminders: Vec<i64>,
+    impressions: ImpressionSampling,
+    nearby_cache: Option<NearbyCache>,
+    ranker: Option<Arc<dyn Ranker>>,
+    experiments: Experiments,
+    feature_flags: FeatureFlags,
+    location_queue: Option<LocationQueue>,
+    location_filter: LocationFilter,
+    verification: WalkVerification,
+    /// How long an invited walker has the request to themselves.
+    invitation_timeout: chrono::Duration,
+    id_generator: Option<Arc<dyn IdGenerator>>,
+    recompute_jobs: RecomputeJobs,
+    backfills: Backfills,
+    monthly_quotas: MonthlyQuotas,
+    secrets: SecretRings,
+}
+
+impl<R> Service<R>
+where
+    R: Repository + Clone,
+{
+    pub fn new(repository: R) -> Self {
+        Self {
+            repository,
+            emailer: None,
+            holidays: HolidayCalendar::default(),
+            sla: SlaPolicy::default(),
+            max_applicants: None,
+            payments: None,
+            gateway: None,
+            onboarding: None,
+            capabilities: None,
+            users: None,
+            events: Arc::new(NoopPublisher),
+            publishes_events: false,
+            retention: RetentionPolicy::default(),
+            archive: None,
+            track_downsampling: None,
+            walk_budget: None,
+            cancel_undo_window: None,
+            archive_after: None,
+            currencies: CurrencyZones::default(),
+            rates: Arc::new(FixedRates::default()),
+            delegation: None,
+            tenant: None,
+            over_cap_confirmed: false,
+            locations: None,
+            changes: ChangeBroker::default(),
+            fitness: Vec::new(),
+            routing: None,
+            static_maps: None,
+            notifier: None,
+            distance_strategy: DistanceStrategy::default(),
+            recent_events: RecentEvents::default(),
+            alerts: AlertRouter::default(),
+            max_radius: None,
+            page_limits: PageLimits::default(),
+            schedule_horizon: chrono::Duration::hours(DEFAULT_SCHEDULE_HORIZON_HOURS),
+            start_reminders: DEFAULT_START_REMINDER_MINUTES.to_vec(),
+            impressions: ImpressionSampling::default(),
+            nearby_cache: None,
+            ranker: None,
+            experiments: Experiments::default(),
+            feature_flags: FeatureFlags::default(),
+            location_queue: None,
+            location_filter: LocationFilter::default(),
+            verification: WalkVerification::default(),
+            invitation_timeout: chrono::Duration::hours(2),
+            id_generator: None,
+            recompute_jobs: RecomputeJobs::default(),
+            backfills: Backfills::default(),
+            monthly_quotas: MonthlyQuotas::default(),
+            secrets: SecretRings::default(),
+        }
+    }
+
+    /// Upper bound on the radius of nearby searches.
+    pub fn with_max_radius(mut self, max_radius: Meters) -> Self {
+        self.max_radius = Some(max_radius);
+        self
+    }
+
+    /// Bounds on the pages callers may ask for, see `pagination`.
+    pub fn with_page_limits(mut self, page_limits: PageLimits) -> Self {
+        self.page_limits = page_limits;
+        self
+    }
+
+    /// The page asked for, refused when out of the page limits.
+    pub fn pagination(&self, page: i64, size: i64) -> Result<Pagination, ServiceError> {
+        let pagination = Pagination::new(page, size);
+        pagination.check(self.page_limits)?;
+        Ok(pagination)
+    }
+
+    /// Enables live location streaming for walks in progress.
+    pub fn with_location_broker(mut self, locations: LocationBroker) -> Self {
+        self.locations = Some(locations);
+        self
+    }
+
+    /// Ends live location streams and long polls, which would otherwise hold
+    /// a graceful shutdown until it times out.
+    pub fn shutdown(&self) {
+        if let Some(locations) = &self.locations {
+            locations.close_all();
+        }
+        self.changes.close_all();
+    }
+
+    pub fn with_payments(mut self, payments: Arc<dyn PaymentHolds>) -> Self {
+        self.payments = Some(payments);
+        self
+    }
+
+    /// Charges owners of priced walks once they are finished.
+    pub fn with_payment_gateway(mut self, gateway: Arc<dyn PaymentGateway>) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+
+    pub fn with_event_publisher(mut self, events: Arc<dyn EventPublisher>) -> Self {
+        self.events = events;
+        self.publishes_events = true;
+        self
+    }
+
+    /// A copy of the service acting as internal service `client` for
+    /// `user_id`, its events and audit entries name both.
+    pub fn on_behalf_of(&self, client: &str, user_id: &str) -> Self {
+        Self {
+            delegation: Some(Delegation {
+                client: client.to_owned(),
+                on_behalf_of: user_id.to_owned(),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// A copy of the service for an owner who confirmed going over their
+    /// monthly spending cap, see `ensure_within_cap`.
+    pub fn confirming_over_cap(&self) -> Self {
+        Self {
+            over_cap_confirmed: true,
+            ..self.clone()
+        }
+    }
+
+    /// A copy of the service confined to the walk requests of `tenant_id`,
+    /// using the tenant's own settings where it has them.
+    pub async fn for_tenant(&self, tenant_id: &str) -> Result<Self, ServiceError> {
+        let tenant = self
+            .repository
+            .get_tenant(tenant_id)
+            .await?
+            .ok_or(ServiceError::NotFound("租户不存在".to_owned()))?;
+        Ok(Self {
+            repository: self.repository.for_tenant(&tenant.id),
+            max_radius: tenant.max_search_radius.or(self.max_radius),
+            tenant: Some(tenant),
+            ..self.clone()
+        })
+    }
+
+    /// Base of the updates made by owner actions, carrying the delegation.
+    fn owner_update(&self) -> WalkRequestUpdate {
+        WalkRequestUpdate {
+            delegation: self.delegation.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Runs `change`, a state change, and writes what it publishes to the
+    /// outbox in one transaction, see `Repository::transaction`, so neither
+    /// is committed without the other. The events are fanned out in
+    /// process once committed.
+    async fn transition<T, F, Fut>(&self, change: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce(R) -> Fut,
+        Fut: Future<Output = Result<(T, Publications), ServiceError>>,
+    {
+        let (value, events) = self
+            .repository
+            .transaction(|repository| async move {
+                let (value, publications) = change(repository.clone()).await?;
+                let events: Vec<WalkRequestEvent> = publications
+                    .events
+                    .into_iter()
+                    .map(|event| self.stamp(event))
+                    .collect();
+                if self.publishes_events {
+                    for event in &events {
+                        let message = OutboxMessage::new(OutboxPayload::Event(event.clone()));
+                        repository.save_outbox_message(&message).await?;
+                    }
+                }
+                for notification in publications.notifications {
+                    self.enqueue_notification(&repository, notification).await?;
+                }
+                Ok((value, events))
+            })
+            .await?;
+        for event in &events {
+            self.emitted(event).await;
+        }
+        Ok(value)
+    }
+
+    /// Updates the request matching `query` as a `transition`, publishing
+    /// what `publish` gives for the updated request.
+    async fn update_request<P>(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+        publish: P,
+    ) -> Result<WalkRequest, ServiceError>
+    where
+        P: FnOnce(&WalkRequest) -> Publications,
+    {
+        self.transition(|repository| async move {
+            let request = repository
+                .update_walk_request_by_query(query, update)
+                .await?;
+            let publications = publish(&request);
+            Ok((request, publications))
+        })
+        .await
+    }
+
+    /// Updates the requests matching `query` as a `transition`, publishing
+    /// `publications` when any was updated. Returns how many were.
+    async fn update_requests(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+        publications: Publications,
+    ) -> Result<u64, ServiceError> {
+        self.transition(|repository| async move {
+            let n = repository
+                .update_walk_requests_by_query(query, update)
+                .await?;
+            Ok((
+                n,
+                if n > 0 {
+                    publications
+                } else {
+                    Publications::default()
+                },
+            ))
+        })
+        .await
+    }
+
+    /// Writes publications not coming with a state change to the outbox.
+    async fn publish(&self, publications: Publications) -> Result<(), ServiceError> {
+        self.transition(|_| async { Ok(((), publications)) }).await
+    }
+
+    pub fn with_experiments(mut self, experiments: Experiments) -> Self {
+        self.experiments = experiments;
+        self
+    }
+
+    pub fn with_feature_flags(mut self, flags: FeatureFlags) -> Self {
+        self.feature_flags = flags;
+        self
+    }
+
+    /// The flag of every feature, stored changes included.
+    pub async fn feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError> {
+        if let Some(flags) = self.feature_flags.cached() {
+            return Ok(flags);
+        }
+        let overrides = self.repository.query_feature_flags().await?;
+        Ok(self.feature_flags.store(overrides))
+    }
+
+    /// Turns a feature on or off, taking effect on other instances once
+    /// their cache expires.
+    pub async fn set_feature_flag(
+        &self,
+        feature: Feature,
+        update: FeatureFlagUpdate,
+    ) -> Result<FeatureFlag, ServiceError> {
+        if update.rollout_percent > 100 {
+            return Err(ServiceError::Validation(
+                "开放比例必须在0到100之间".to_owned(),
+            ));
+        }
+        let flag = FeatureFlag {
+            feature,
+            enabled: update.enabled,
+            rollout_percent: update.rollout_percent,
+            updated_at: Some(Utc::now()),
+        };
+        self.repository.save_feature_flag(&flag).await?;
+        self.feature_flags.invalidate();
+        Ok(flag)
+    }
+
+    async fn feature_enabled(&self, feature: Feature, user_id: &str) -> Result<bool, ServiceError> {
+        Ok(self
+            .feature_flags()
+            .await?
+            .iter()
+            .any(|f| f.feature == feature && f.enabled_for(user_id)))
+    }
+
+    /// Fails unless the feature is enabled for the user.
+    async fn ensure_feature(&self, feature: Feature, user_id: &str) -> Result<(), ServiceError> {
+        if self.feature_enabled(feature, user_id).await? {
+            Ok(())
+        } else {
+            Err(ServiceError::FeatureDisabled(feature))
+        }
+    }
+
+    pub fn with_monthly_quotas(mut self, quotas: MonthlyQuotas) -> Self {
+        self.monthly_quotas = quotas;
+        self
+    }
+
+    pub fn with_secret_rings(mut self, secrets: SecretRings) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// The key rings shared with the token verifiers and signers, rotations
+    /// through the service take effect for them at once.
+    pub fn secret_rings(&self) -> &SecretRings {
+        &self.secrets
+    }
+
+    /// How many walk requests a backfill migrates per batch and how long it
+    /// pauses between batches.
+    pub fn with_backfill_rate(mut self, batch_size: i64, pause: std::time::Duration) -> Self {
+        self.backfills.batch_size = batch_size;
+        self.backfills.pause = pause;
+        self
+    }
+
+    /// The user's variant of `experiment`, for ranking, pricing or matching
+    /// code to branch on. Serving it is published as an exposure.
+    pub async fn variant(&self, experiment: &str, user_id: &str) -> Option<String> {
+        let variant = self.experiments.variant(experiment, user_id)?.to_owned();
+        let exposure = Exposure {
+            experiment: experiment.to_owned(),
+            variant: variant.clone(),
+            user_id: user_id.to_owned(),
+            occurred_at: Utc::now(),
+        };
+        if let Err(e) = self.events.publish_exposure(&exposure).await {
+            log::error!("failed to publish exposure to {}: {}", experiment, e);
+        }
+        Some(variant)
+    }
+
+    /// The event, made by the internal service the service acts for if
+    /// any, see `Delegation`.
+    fn stamp(&self, event: PendingEvent) -> WalkRequestEvent {
+        let (actor, on_behalf_of) = match &self.delegation {
+            Some(d) => (d.client.clone(), Some(event.actor)),
+            None => (event.actor, None),
+        };
+        WalkRequestEvent {
+            kind: event.kind,
+            request_id: event.request_id,
+            actor,
+            on_behalf_of,
+            subjects: event.subjects,
+            occurred_at: Utc::now(),
+        }
+    }
+
+    /// Fans out a committed event to this instance's listeners, the event
+    /// timeline and the caches it outdates. Failures are logged, the
+    /// transition already happened.
+    async fn emitted(&self, event: &WalkRequestEvent) {
+        let (kind, request_id) = (event.kind, event.request_id.as_str());
+        self.recent_events.record(event);
+        self.changes.publish(event);
+        if let Err(e) = self.repository.record_event(event).await {
+            log::error!("failed to record {:?} of {}: {}", kind, request_id, e);
+        }
+        if let Some(cache) = &self.nearby_cache {
+            if matches!(
+                kind,
+                WalkRequestEventKind::Created
+                    | WalkRequestEventKind::Accepted
+                    | WalkRequestEventKind::Canceled
+                    | WalkRequestEventKind::CancelRequested
+                    | WalkRequestEventKind::CancelUndone
+                    | WalkRequestEventKind::Deleted
+                    | WalkRequestEventKind::Resigned
+                    | WalkRequestEventKind::NoShow
+                    | WalkRequestEventKind::Expired
+            ) {
+                cache.invalidate().await;
+            }
+        }
+        if matches!(
+            kind,
+            WalkRequestEventKind::Accepted
+                | WalkRequestEventKind::Dismissed
+                | WalkRequestEventKind::Started
+                | WalkRequestEventKind::Canceled
+                | WalkRequestEventKind::CancelRequested
+                | WalkRequestEventKind::CancelUndone
+                | WalkRequestEventKind::Deleted
+                | WalkRequestEventKind::NoShow
+                | WalkRequestEventKind::Resigned
+        ) {
+            self.reschedule_start_reminders(request_id).await;
+        }
+    }
+
+    /// Fans out a change read from the database to this instance's long
+    /// polls and recent events, so they see the writes of other instances.
+    /// Changes this instance made itself come back too and are skipped. The
+    /// nearby cache is shared and was already invalidated by the writer.
+    pub fn apply_remote_change(&self, change: WalkRequestChange) {
+        let event = WalkRequestEvent {
+            kind: change.kind,
+            request_id: change.request_id,
+            actor: change.actor.unwrap_or_default(),
+            on_behalf_of: None,
+            subjects: Vec::new(),
+            occurred_at: Utc::now(),
+        };
+        if self.changes.publish_remote(&event) {
+            self.recent_events.record(&event);
+        }
+    }
+
+    /// Requests created in `region` among the recent events, newest first,
+    /// with when they were created.
+    pub async fn recently_created(
+        &self,
+        region: &str,
+    ) -> Result<Vec<(DateTime<Utc>, WalkRequest)>, ServiceError> {
+        let created = self.recent_events.of_kind(WalkRequestEventKind::Created);
+        if created.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut requests: HashMap<String, WalkRequest> = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    ids_in: Some(created.iter().map(|e| e.request_id.clone()).collect()),
+                    regions_in: Some(vec![region.to_owned()]),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|r| (r.id.clone(), r))
+            .collect();
+        Ok(created
+            .into_iter()
+            .filter_map(|e| requests.remove(&e.request_id).map(|r| (e.occurred_at, r)))
+            .collect())
+    }
+
+    /// Routes operational alerts to the ops team's chat channels.
+    pub fn with_alerts(mut self, alerts: AlertRouter) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    async fn alert(&self, kind: AlertKind, request: &WalkRequest, message: &str) {
+        self.alerts
+            .send(&Alert {
+                kind,
+                region: request
+                    .region
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_REGION.to_owned()),
+                request_id: request.id.clone(),
+                message: message.to_owned(),
+            })
+            .await
+    }
+
+    /// Pushes notifications through the outbox, see `dispatch_outbox`.
+    pub fn with_notifications(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Writes the notification, addressed to the user's devices, to the
+    /// outbox of `repository`. Nothing is written without a notifier.
+    async fn enqueue_notification(
+        &self,
+        repository: &R,
+        notification: Notification,
+    ) -> Result<(), ServiceError> {
+        if self.notifier.is_none() {
+            return Ok(());
+        }
+        let user_id = notification.user_id.clone();
+        let devices = match repository.query_device_sessions(&user_id).await {
+            Ok(sessions) => push_tokens(&sessions, &notification.request_id),
+            Err(e) => {
+                log::error!("failed to load the devices of {}: {}", user_id, e);
+                None
+            }
+        };
+        let payload = OutboxPayload::Notification(notification.to_devices(devices));
+        repository
+            .save_outbox_message(&OutboxMessage::new(payload))
+            .await
+    }
+
+    async fn deliver(&self, payload: &OutboxPayload) -> Result<(), anyhow::Error> {
+        match payload {
+            OutboxPayload::Event(event) => self.events.publish(event).await,
+            OutboxPayload::Notification(notification) => match &self.notifier {
+                Some(notifier) => notifier.notify(notification).await,
+                None => Err(anyhow::Error::msg("no notifier configured")),
+            },
+        }
+    }
+
+    /// Delivers the outbox messages which are due, deleting those delivered
+    /// and backing off the others. Returns how many were delivered.
+    /// Delivery is at least once, a message whose deletion fails is sent
+    /// again.
+    pub async fn dispatch_outbox(&self) -> Result<u64, ServiceError> {
+        let now = Utc::now();
+        let due = self
+            .repository
+            .due_outbox_messages(now, OUTBOX_BATCH_SIZE)
+            .await?;
+        let mut delivered = 0;
+        for mut message in due {
+            match self.deliver(&message.payload).await {
+                Ok(()) => {
+                    self.repository.delete_outbox_message(&message.id).await?;
+                    delivered += 1;
+                }
+                Err(e) => {
+                    message.failed(&e.to_string(), Utc::now());
+                    if message.status == OutboxStatus::DeadLettered {
+                        log::error!(
+                            "gave up delivering outbox message {} after {} attempts: {}",
+                            message.id,
+                            message.attempts,
+                            e
+                        );
+                    }
+                    self.repository.save_outbox_message(&message).await?;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Messages given up on, newest first.
+    pub async fn dead_letters(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        self.repository.dead_outbox_messages(pagination).await
+    }
+
+    /// Puts a dead-lettered message back in line for delivery.
+    pub async fn requeue_outbox_message(&self, id: &str) -> Result<OutboxMessage, ServiceError> {
+        let mut message = self
+            .repository
+            .get_outbox_message(id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("投递消息不存在".to_owned()))?;
+        if message.status != OutboxStatus::DeadLettered {
+            return Err(ServiceError::Conflict(
+                "只能重新投递已放弃的消息".to_owned(),
+            ));
+        }
+        message.requeue(Utc::now());
+        self.repository.save_outbox_message(&message).await?;
+        Ok(message)
+    }
+
+    /// Funnel marks only feed metrics, so failing to record one is logged
+    /// rather than failing the caller.
+    async fn mark_funnel(&self, request_id: &str, stage: FunnelStage) {
+        if let Err(e) = self
+            .repository
+            .mark_funnel_stage(request_id, stage, Utc::now())
+            .await
+        {
+            log::error!("failed to mark {:?} of {}: {}", stage, request_id, e);
+        }
+    }
+
+    /// Requires walkers to have finished onboarding before applying or accepting.
+    pub fn with_onboarding(mut self, onboarding: Arc<dyn OnboardingDirectory>) -> Self {
+        self.onboarding = Some(onboarding);
+        self
+    }
+
+    async fn ensure_onboarded(&self, user_id: &str) -> Result<(), ServiceError> {
+        let Some(onboarding) = &self.onboarding else {
+            return Ok(());
+        };
+        let missing = onboarding.onboarding(user_id).await?.missing_steps();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::OnboardingIncomplete(missing))
+        }
+    }
+
+    /// Embeds the profiles of owners and walkers in nearby results, so cards
+    /// render without a call per request.
+    pub fn with_user_directory(mut self, users: Arc<dyn UserDirectory>) -> Self {
+        self.users = Some(users);
+        self
+    }
+
+    /// Fills the profiles of the requests' owners and walkers, fetched in
+    /// one batch. Failing to fetch leaves them unset rather than failing the
+    /// read.
+    pub async fn attach_profiles(&self, requests: &mut [WalkRequest]) {
+        let Some(users) = &self.users else {
+            return;
+        };
+        let mut ids: Vec<String> = requests
+            .iter()
+            .flat_map(|r| [r.created_by.clone(), r.accepted_by.clone()])
+            .flatten()
+            .collect();
+        ids.sort();
+        ids.dedup();
+        if ids.is_empty() {
+            return;
+        }
+        let profiles = match users.profiles(&ids).await {
+            Ok(profiles) => profiles,
+            Err(e) => {
+                log::error!("failed to fetch user profiles: {}", e);
+                return;
+            }
+        };
+        let profile = |user: &Option<String>| user.as_ref().and_then(|u| profiles.get(u)).cloned();
+        for request in requests {
+            request.owner_profile = profile(&request.created_by);
+            request.walker_profile = profile(&request.accepted_by);
+        }
+    }
+
+    /// Reads walkers' capabilities from the user service instead of the
+    /// repository.
+    pub fn with_capability_directory(mut self, capabilities: Arc<dyn CapabilityDirectory>) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub async fn walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<WalkerCapabilities, ServiceError> {
+        match &self.capabilities {
+            Some(directory) => Ok(directory.capabilities(user_id).await?),
+            None => Ok(self
+                .repository
+                .get_walker_capabilities(user_id)
+                .await?
+                .unwrap_or_default()),
+        }
+    }
+
+    pub async fn set_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError> {
+        if self.capabilities.is_some() {
+            return Err(ServiceError::Conflict("接单能力由用户服务管理".to_owned()));
+        }
+        if capabilities.max_dogs.map_or(false, |max| max < 1) {
+            return Err(ServiceError::Validation(
+                "最多可遛狗数量必须大于0".to_owned(),
+            ));
+        }
+        self.repository
+            .upsert_walker_capabilities(user_id, capabilities)
+            .await
+    }
+
+    async fn ensure_capable(&self, request_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        // The transition explains a missing request.
+        let request = match self.repository.get_walk_request(request_id).await {
+            Ok(request) => request,
+            Err(ServiceError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mismatches = self
+            .walker_capabilities(user_id)
+            .await?
+            .mismatches(&request.dogs, request.requirements);
+        if mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::CapabilityMismatch(mismatches))
+        }
+    }
+
+    /// Currency each region charges in, and where conversion rates come from.
+    pub fn with_currencies(
+        mut self,
+        currencies: CurrencyZones,
+        rates: Arc<dyn RatesProvider>,
+    ) -> Self {
+        self.currencies = currencies;
+        self.rates = rates;
+        self
+    }
+
+    /// Caps the minutes each walker spends walking per day.
+    pub fn with_walk_budget(mut self, walk_budget: WalkBudgetPolicy) -> Self {
+        self.walk_budget = Some(walk_budget);
+        self
+    }
+
+    /// Minutes the walker has walked today, and what is left of the cap.
+    pub async fn walk_budget(&self, user_id: &str) -> Result<WalkBudget, ServiceError> {
+        let now = Utc::now();
+        let walks = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    accepted_by: Some(user_id.to_owned()),
+                    started_at_is_null: Some(false),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?;
+        let used_minutes = active_minutes(&walks, now.date_naive(), now);
+        let daily_minutes = self.walk_budget.as_ref().map(|p| p.daily_minutes);
+        Ok(WalkBudget {
+            date: now.date_naive(),
+            used_minutes,
+            daily_minutes,
+            remaining_minutes: daily_minutes.map(|d| (d - used_minutes).max(0)),
+        })
+    }
+
+    /// Refuses walkers who used up today's budget when the request's region
+    /// is covered by the cap.
+    async fn ensure_within_walk_budget(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let Some(policy) = &self.walk_budget else {
+            return Ok(());
+        };
+        let request = match self.repository.get_walk_request(request_id).await {
+            Ok(request) => request,
+            Err(ServiceError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !policy.covers(request.region.as_deref()) {
+            return Ok(());
+        }
+        if self.walk_budget(user_id).await?.remaining_minutes == Some(0) {
+            return Err(ServiceError::Conflict(format!(
+                "今日遛狗时长已达上限（{}分钟）",
+                policy.daily_minutes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Refuses a walker banned for collecting too many strikes.
+    async fn ensure_not_banned(&self, user_id: &str) -> Result<(), ServiceError> {
+        let reputation = self.reputation(user_id).await?;
+        match reputation.banned_until {
+            Some(until) if reputation.banned_at(Utc::now()) => {
+                Err(ServiceError::Conflict(format!(
+                    "您因多次违约已被暂停接单至{}",
+                    until.format("%Y-%m-%d %H:%M UTC")
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuses a walker who already accepted a walk overlapping the
+    /// request's window. Requests without a full window aren't checked.
+    async fn ensure_available(&self, request_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        let request = match self.repository.get_walk_request(request_id).await {
+            Ok(request) => request,
+            Err(ServiceError::NotFound(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let (Some(start), Some(end)) = (request.should_start_after, request.should_end_before)
+        else {
+            return Ok(());
+        };
+        let overlapping = self
+            .repository
+            .count_walk_requests(overlapping_walks(user_id, start, end))
+            .await?;
+        if overlapping > 0 {
+            return Err(ServiceError::Conflict("与已接的遛狗时间冲突".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Default cap on simultaneous applicants for requests that don't set their own.
+    pub fn with_max_applicants(mut self, max_applicants: i64) -> Self {
+        self.max_applicants = Some(max_applicants);
+        self
+    }
+
+    /// Explains why a guarded transition matched nothing: the caller isn't the
+    /// owner required by `owner_id`, the request's current status doesn't allow
+    /// it, or the request is missing / not the caller's, in which case
+    /// `fallback` is returned.
+    async fn rejected(
+        &self,
+        request_id: &str,
+        owner_id: Option<&str>,
+        to: WalkRequestStatus,
+        fallback: &str,
+    ) -> ServiceError {
+        match self.repository.get_walk_request(request_id).await {
+            Ok(request) if owner_id.is_some() && request.created_by.as_deref() != owner_id => {
+                ServiceError::Unauthorized("无权限".to_owned())
+            }
+            Ok(request) if !request.status.can_transition_to(to) => InvalidTransition {
+                from: request.status,
+                to,
+            }
+            .into(),
+            Ok(_) => ServiceError::NotFound(fallback.to_owned()),
+            Err(e) => e,
+        }
+    }
+
+    /// Updates a pool walk from a fresh read, retried when another walker of
+    /// the pool updated it in between. `change` gives `None` when the caller
+    /// can't make the change, which is then explained by `rejected`.
+    /// `publish` gives what the updated request publishes, see `transition`.
+    async fn update_pool<F, P>(
+        &self,
+        request_id: &str,
+        to: WalkRequestStatus,
+        change: F,
+        publish: P,
+    ) -> Result<WalkRequest, ServiceError>
+    where
+        F: Fn(&WalkRequest, DateTime<Utc>) -> Option<WalkRequestUpdate>,
+        P: Fn(&WalkRequest) -> Publications,
+    {
+        for _ in 0..POOL_UPDATE_ATTEMPTS {
+            let request = self.repository.get_walk_request(request_id).await?;
+            let Some(update) = change(&request, Utc::now()) else {
+                return Err(self.rejected(request_id, None, to, "代遛请求不存在").await);
+            };
+            let query = WalkRequestQuery {
+                id: Some(request_id.to_owned()),
+                version: Some(request.version),
+                ..Default::default()
+            };
+            let updated = self.update_request(query, update, &publish).await;
+            match updated {
+                Err(ServiceError::NotFound(_)) => continue,
+                updated => return updated,
+            }
+        }
+        Err(ServiceError::Conflict(
+            "代遛请求已被修改，请刷新后重试".to_owned(),
+        ))
+    }
+
+    fn default_applicant_cap(&self) -> i64 {
+        self.max_applicants.unwrap_or(i64::MAX)
+    }
+
+    pub fn with_retention(mut self, retention: RetentionPolicy) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Archives tracks before the retention policy purges them.
+    pub fn with_track_archive(mut self, archive: Arc<dyn TrackArchive>) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    pub fn with_track_downsampling(mut self, downsampling: TrackDownsampling) -> Self {
+        self.track_downsampling = Some(downsampling);
+        self
+    }
+
+    pub fn with_sla(mut self, sla: SlaPolicy) -> Self {
+        self.sla = sla;
+        self
+    }
+
+    pub fn with_holidays(mut self, holidays: HolidayCalendar) -> Self {
+        self.holidays = holidays;
+        self
+    }
+
+    pub fn with_emailer(mut self, emailer: Emailer) -> Self {
+        self.emailer = Some(Arc::new(emailer));
+        self
+    }
+
+    pub async fn send_email<T: Serialize>(
+        &self,
+        to: &str,
+        template: EmailTemplate,
+        locale: &str,
+        data: &T,
+    ) -> Result<(), ServiceError> {
+        match &self.emailer {
+            Some(emailer) => Ok(emailer.send(to, template, locale, data).await?),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn create_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<String, ServiceError> {
+        parse_timezone(&request.timezone).map_err(|e| ServiceError::Validation(e.to_string()))?;
+        let request = self.holiday_priced(request).await?;
+        let owner_id = request.created_by.clone();
+        let invited = request.invited_walker_id.clone();
+        if let Some(walker) = &invited {
+            self.ensure_invitable(&request, walker).await?;
+        }
+        if let Some(price) = &request.price {
+            self.ensure_within_cap(&owner_id, price).await?;
+        }
+        let tags = normalize_tags(&request.tags);
+        let point = (request.longitude, request.latitude);
+        let dogs = request.dogs.len();
+        let request = WalkRequestCreate {
+            delegation: self.delegation.clone(),
+            tenant_id: self.tenant.as_ref().map(|t| t.id.clone()),
+            reference_code: self.next_reference_code().await?,
+            invitation_expires_at: invited
+                .is_some()
+                .then(|| Utc::now() + self.invitation_timeout),
+            tags: tags.clone(),
+            ..request
+        };
+        let walkers = match &invited {
+            Some(_) => HashSet::new(),
+            None => {
+                self.saved_search_walkers(&owner_id, point, dogs, &tags)
+                    .await
+            }
+        };
+        self.transition(|repository| async move {
+            let id = repository.create_walk_request(request).await?;
+            let mut publications =
+                Publications::event(WalkRequestEventKind::Created, &id, &owner_id);
+            if let Some(walker) = &invited {
+                publications = publications.notify(walker, NotificationKind::Invited, &id);
+            }
+            for walker in walkers {
+                publications =
+                    publications.notify(&walker, NotificationKind::SavedSearchMatched, &id);
+            }
+            Ok((id, publications))
+        })
+        .await
+    }
+
+    /// The draft with its price adjusted for a holiday in its region on the
+    /// day it starts, in its own timezone.
+    async fn holiday_priced(
+        &self,
+        mut request: WalkRequestCreate,
+    ) -> Result<WalkRequestCreate, ServiceError> {
+        let start = request.should_start_after.or(request.should_start_before);
+        if let (Some(price), Some(start)) = (&request.price, start) {
+            let date = to_local(&start, Some(&request.timezone)).date_naive();
+            request.price = Some(self.holiday_price(&request.region, date, price).await?);
+        }
+        Ok(request)
+    }
+
+    /// Walkers to tell about a new request turning up in their saved
+    /// searches, told once each however many of their searches it matches.
+    async fn saved_search_walkers(
+        &self,
+        owner_id: &str,
+        point: (f64, f64),
+        dogs: usize,
+        tags: &[String],
+    ) -> HashSet<String> {
+        let searches = match self.repository.query_notifying_saved_searches(tags).await {
+            Ok(searches) => searches,
+            Err(e) => {
+                log::error!("failed to match a new request with saved searches: {}", e);
+                return HashSet::new();
+            }
+        };
+        let mut walkers = HashSet::new();
+        for search in searches {
+            if walkers.len() >= MAX_SAVED_SEARCH_NOTIFICATIONS {
+                break;
+            }
+            if search.walker_id != owner_id && search.matches(point, dogs, tags) {
+                walkers.insert(search.walker_id);
+            }
+        }
+        walkers
+    }
+
+    /// Gives new requests reference codes, see `WalkRequest::reference_code`.
+    pub fn with_id_generator(mut self, generator: Arc<dyn IdGenerator>) -> Self {
+        self.id_generator = Some(generator);
+        self
+    }
+
+    async fn next_reference_code(&self) -> Result<Option<String>, ServiceError> {
+        let Some(generator) = &self.id_generator else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        let number = match generator.counter(now) {
+            Some(counter) => Some(self.repository.next_sequence(&counter).await?),
+            None => None,
+        };
+        Ok(Some(generator.generate(now, number)))
+    }
+
+    /// Only favorites of the owner may be invited, and only to requests
+    /// one walker takes.
+    async fn ensure_invitable(
+        &self,
+        request: &WalkRequestCreate,
+        walker_id: &str,
+    ) -> Result<(), ServiceError> {
+        if request.max_walkers.map_or(false, |m| m > 1) {
+            return Err(ServiceError::Validation(
+                "多人遛狗的请求不能邀请遛狗人".to_owned(),
+            ));
+        }
+        let favorite = self
+            .repository
+            .query_favorite_walkers(&request.created_by)
+            .await?
+            .iter()
+            .any(|f| f.walker_id == walker_id);
+        if !favorite {
+            return Err(ServiceError::Validation("只能邀请收藏的遛狗人".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Checks a draft as `create_walk_request` would and estimates how many
+    /// walkers would see it, without storing anything.
+    pub async fn preview_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<WalkRequestPreview, ServiceError> {
+        let errors = request.field_errors();
+        let request = self.holiday_priced(request).await?;
+        let nearby = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    nearby: Some(vec![
+                        request.longitude,
+                        request.latitude,
+                        VISIBILITY_RADIUS.value(),
+                    ]),
+                    created_at_gte: Some(
+                        Utc::now() - chrono::Duration::days(VISIBILITY_LOOKBACK_DAYS),
+                    ),
+                    created_by_neq: Some(request.created_by.clone()),
+                    ..Default::default()
+                },
+                None,
+                Some(Pagination::new(1, VISIBILITY_SAMPLE_SIZE)),
+            )
+            .await?;
+        let walkers: HashSet<&str> = nearby
+            .iter()
+            .flat_map(|r| {
+                r.accepted_by
+                    .iter()
+                    .chain(r.acceptances.iter().flatten())
+                    .map(String::as_str)
+                    .chain(r.pool_walkers.iter().map(|w| w.walker_id.as_str()))
+            })
+            .collect();
+        let mut preview = WalkRequestPreview::new(&request, errors, walkers.len() as u64);
+        if let Some(price) = &request.price {
+            match self.ensure_within_cap(&request.created_by, price).await {
+                Ok(()) => {}
+                Err(ServiceError::SpendingCapExceeded(_)) => {
+                    preview.warnings.push(PreviewWarning::over_spending_cap())
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(preview)
+    }
+
+    /// Changes the dogs, time windows, location or tags of the owner's
+    /// request while it is still waiting for a walker.
+    pub async fn edit_walk_request(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        edit: WalkRequestEdit,
+    ) -> Result<WalkRequest, ServiceError> {
+        let current = self.repository.get_walk_request(request_id).await?;
+        if current.created_by.as_deref() != Some(owner_id) {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        // Windows are checked merged with the stored ones, so moving one
+        // bound can't invert a window.
+        WalkRequestUpdate {
+            dogs: edit.dogs.clone(),
+            should_start_after: edit.should_start_after.or(current.should_start_after),
+            should_start_before: edit.should_start_before.or(current.should_start_before),
+            should_end_after: edit.should_end_after.or(current.should_end_after),
+            should_end_before: edit.should_end_before.or(current.should_end_before),
+            latitude: edit.latitude,
+            longitude: edit.longitude,
+            tags: edit.tags.clone(),
+            ..Default::default()
+        }
+        .validate()?;
+        let not_waiting = || ServiceError::Conflict("只能修改等待接单的代遛请求".to_owned());
+        let stale = || ServiceError::Conflict("代遛请求已被修改，请刷新后重试".to_owned());
+        let expected_version = edit.expected_version;
+        if expected_version.map_or(false, |v| v != current.version) {
+            return Err(stale());
+        }
+        if current.status != WalkRequestStatus::Waiting {
+            return Err(not_waiting());
+        }
+        let edited = self
+            .repository
+            .update_walk_request_by_query(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    created_by: Some(owner_id.to_owned()),
+                    accepted_by_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_is_null: Some(true),
+                    expired_at_is_null: Some(true),
+                    version: expected_version,
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    dogs: edit.dogs,
+                    should_start_after: edit.should_start_after,
+                    should_start_before: edit.should_start_before,
+                    should_end_after: edit.should_end_after,
+                    should_end_before: edit.should_end_before,
+                    latitude: edit.latitude,
+                    longitude: edit.longitude,
+                    tags: edit.tags.as_deref().map(normalize_tags),
+                    ..self.owner_update()
+                },
+            )
+            .await;
+        if edited.is_ok() {
+            return edited;
+        }
+        // Someone else's write came in between the read and this one.
+        match expected_version {
+            Some(version)
+                if self.repository.get_walk_request(request_id).await?.version != version =>
+            {
+                Err(stale())
+            }
+            _ => Err(not_waiting()),
+        }
+    }
+
+    /// Creates a new Waiting request with the dogs, location and requirements
+    /// of one of the owner's requests.
+    pub async fn clone_walk_request(
+        &self,
+        id: &str,
+        owner_id: &str,
+        windows: TimeWindows,
+    ) -> Result<String, ServiceError> {
+        let original = self.repository.get_walk_request(id).await?;
+        if original.created_by.as_deref() != Some(owner_id) {
+            return Err(ServiceError::NotFound("代遛请求不存在".to_owned()));
+        }
+        self.create_walk_request(WalkRequestCreate {
+            dogs: original.dogs,
+            should_start_after: windows.should_start_after,
+            should_start_before: windows.should_start_before,
+            should_end_after: windows.should_end_after,
+            should_end_before: windows.should_end_before,
+            latitude: original.latitude,
+            longitude: original.longitude,
+            timezone: original
+                .timezone
+                .unwrap_or_else(|| DEFAULT_TIMEZONE.to_owned()),
+            region: original.region.unwrap_or_else(|| DEFAULT_REGION.to_owned()),
+            max_applicants: original.max_applicants,
+            requirements: original.requirements,
+            track_visibility: original.track_visibility,
+            max_radius: original.max_radius,
+            price: original.price,
+            max_walkers: original.max_walkers,
+            apply_before: None,
+            created_by: owner_id.to_owned(),
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: None,
+            invitation_expires_at: None,
+            tags: original.tags,
+        })
+        .await
+    }
+
+    /// Posts one of the owner's requests again, its time windows kept the
+    /// same distance ahead of now as they were ahead of its creation.
+    pub async fn duplicate_walk_request(
+        &self,
+        id: &str,
+        owner_id: &str,
+    ) -> Result<String, ServiceError> {
+        let original = self.repository.get_walk_request(id).await?;
+        let windows = WindowOffsets::of(&original).at(Utc::now());
+        self.clone_walk_request(id, owner_id, windows).await
+    }
+
+    /// The request by its id, or by its reference code.
+    pub async fn get_walk_request(&self, id: &str) -> Result<Option<WalkRequest>, ServiceError> {
+        let query = match &self.id_generator {
+            Some(generator) if generator.recognizes(id) => WalkRequestQuery {
+                reference_code: Some(id.to_owned()),
+                ..Default::default()
+            },
+            _ => WalkRequestQuery {
+                id: Some(id.to_owned()),
+                ..Default::default()
+            },
+        };
+        Ok(self
+            .repository
+            .query_walk_requests(query, None, Some(Pagination::new(1, 1)))
+            .await?
+            .pop())
+    }
+
+    /// Orders each page of nearby results by `ranker` rather than distance.
+    pub fn with_ranker(mut self, ranker: Arc<dyn Ranker>) -> Self {
+        self.ranker = Some(ranker);
+        self
+    }
+
+    /// Caches nearby searches for a short while.
+    pub fn with_nearby_cache(mut self, cache: NearbyCache) -> Self {
+        self.nearby_cache = Some(cache);
+        self
+    }
+
+    /// Open requests around a point. When the searching walker is known, only
+    /// requests matching their capabilities are listed.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn nearby_walk_requests(
+        &self,
+        latitute: f64,
+        longitude: f64,
+        radius: Meters,
+        walker: Option<&str>,
+        filter: NearbyFilter,
+        pagination: Pagination,
+        cursor: Option<&str>,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        self.check_search_radius(radius)?;
+        pagination.check(self.page_limits)?;
+        let Some(cache) = &self.nearby_cache else {
+            return self
+                .query_nearby(
+                    latitute, longitude, radius, walker, filter, pagination, cursor,
+                )
+                .await;
+        };
+        let key = cache
+            .key(
+                self.tenant.as_ref().map(|t| t.id.as_str()),
+                latitute,
+                longitude,
+                radius.value(),
+                walker,
+                &filter,
+                pagination,
+                cursor,
+            )
+            .await;
+        if let Some(key) = &key {
+            if let Some(hit) = cache.get(key).await {
+                match serde_json::from_str(&hit) {
+                    Ok(paged) => return Ok(paged),
+                    Err(e) => log::error!("failed to decode cached {}: {}", key, e),
+                }
+            }
+        }
+        let paged = self
+            .query_nearby(
+                latitute, longitude, radius, walker, filter, pagination, cursor,
+            )
+            .await?;
+        if let Some(key) = &key {
+            match serde_json::to_string(&paged) {
+                Ok(value) => cache.put(key, &value).await,
+                Err(e) => log::error!("failed to encode {} for the cache: {}", key, e),
+            }
+        }
+        Ok(paged)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn query_nearby(
+        &self,
+        latitute: f64,
+        longitude: f64,
+        radius: Meters,
+        walker: Option<&str>,
+        filter: NearbyFilter,
+        pagination: Pagination,
+        cursor: Option<&str>,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        let cursor = cursor
+            .map(|c| {
+                NearbyCursor::decode(c)
+                    .ok_or_else(|| ServiceError::Validation("无效的游标".to_owned()))
+            })
+            .transpose()?;
+        let mut query = self.open_requests_query(walker).await?;
+        query.nearby = Some(vec![longitude, latitute, radius.value()]);
+        filter.apply(&mut query, Utc::now());
+        let mut paged = if cursor.is_some() {
+            // One more than asked tells whether there is a next page.
+            query.nearby_after = cursor;
+            let mut items = self
+                .repository
+                .query_walk_requests(query, None, Some(Pagination::new(1, pagination.size + 1)))
+                .await?;
+            let has_more = items.len() as i64 > pagination.size;
+            items.truncate(pagination.size.max(0) as usize);
+            let next_cursor = has_more
+                .then(|| items.last().and_then(NearbyCursor::after))
+                .flatten();
+            self.retain_holiday_hours(&mut items).await?;
+            Paged {
+                items,
+                total: 0,
+                page: pagination.page,
+                size: pagination.size,
+                has_more,
+                next_cursor: next_cursor.map(|c| c.encode()),
+            }
+        } else {
+            let total = self.repository.count_walk_requests(query.clone()).await?;
+            let items = self
+                .repository
+                .query_walk_requests(query, None, Some(pagination))
+                .await?;
+            let mut paged = Paged::new(items, total, pagination);
+            if paged.has_more {
+                paged.next_cursor = paged
+                    .items
+                    .last()
+                    .and_then(NearbyCursor::after)
+                    .map(|c| c.encode());
+            }
+            let dropped = self.retain_holiday_hours(&mut paged.items).await?;
+            paged.total = paged.total.saturating_sub(dropped);
+            paged
+        };
+        // Pages are cut by distance, ranking only reorders within one so
+        // that paging stays stable.
+        self.rank(&mut paged.items, walker).await;
+        self.attach_profiles(&mut paged.items).await;
+        Ok(paged)
+    }
+
+    fn check_search_radius(&self, radius: Meters) -> Result<(), ServiceError> {
+        if !radius.value().is_finite() || radius.value() <= 0.0 {
+            return Err(ServiceError::Validation("搜索半径必须大于0".to_owned()));
+        }
+        match self.max_radius {
+            Some(max_radius) if radius > max_radius => Err(ServiceError::Validation(format!(
+                "搜索半径不得超过{}",
+                max_radius
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every open request around a point, nearest first, read as they come
+    /// rather than paged. Neither cached, ranked, given profiles nor held to
+    /// holiday hours.
+    pub async fn stream_nearby_walk_requests(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius: Meters,
+        walker: Option<&str>,
+        filter: NearbyFilter,
+    ) -> Result<WalkRequestStream, ServiceError> {
+        self.check_search_radius(radius)?;
+        let mut query = self.open_requests_query(walker).await?;
+        query.nearby = Some(vec![longitude, latitude, radius.value()]);
+        filter.apply(&mut query, Utc::now());
+        self.repository
+            .stream_walk_requests(query, None, None)
+            .await
+    }
+
+    /// Requests still open to walkers, of which `walker` can take.
+    async fn open_requests_query(
+        &self,
+        walker: Option<&str>,
+    ) -> Result<WalkRequestQuery, ServiceError> {
+        let mut query = WalkRequestQuery {
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            cancel_requested_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            below_applicant_cap: Some(self.default_applicant_cap()),
+            apply_open_at: Some(Utc::now()),
+            invitation_visible_to: Some(InvitationViewer {
+                walker: walker.map(str::to_owned),
+                at: Utc::now(),
+            }),
+            ..Default::default()
+        };
+        if let Some(walker) = walker {
+            let capabilities = self.walker_capabilities(walker).await?;
+            query.dog_count_lte = capabilities.max_dogs;
+            query.requires_large_breed = (!capabilities.large_breeds).then_some(false);
+            query.requires_puppy = (!capabilities.puppies).then_some(false);
+        }
+        Ok(query)
+    }
+
+    /// Open requests picked up within a map viewport, soonest to start
+    /// first. Viewports wider than the nearby search radius allows are
+    /// refused.
+    pub async fn walk_requests_in_area(
+        &self,
+        area: BoundingBox,
+        walker: Option<&str>,
+        filter: NearbyFilter,
+        pagination: Pagination,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        if !area.is_valid() {
+            return Err(ServiceError::Validation("无效的区域".to_owned()));
+        }
+        self.check_search_radius(area.radius())?;
+        let mut query = self.open_requests_query(walker).await?;
+        query.within_box = Some(area);
+        filter.apply(&mut query, Utc::now());
+        let total = self.repository.count_walk_requests(query.clone()).await?;
+        let mut items = self
+            .repository
+            .query_walk_requests(
+                query,
+                Some(SortBy {
+                    field: WalkRequest::should_start_after(),
+                    order: Order::Asc,
+                }),
+                Some(pagination),
+            )
+            .await?;
+        let dropped = self.retain_holiday_hours(&mut items).await?;
+        Ok(Paged::new(items, total.saturating_sub(dropped), pagination))
+    }
+
+    /// Drops the requests starting outside the adjusted operating hours of a
+    /// holiday in their region, on the day in their own timezone, and tells
+    /// how many were dropped. Holidays may change after a request was posted,
+    /// so this is decided when matching rather than stored.
+    async fn retain_holiday_hours(
+        &self,
+        requests: &mut Vec<WalkRequest>,
+    ) -> Result<u64, ServiceError> {
+        let mut closed = HashSet::new();
+        for request in requests.iter() {
+            let Some(start) = request.should_start_after.or(request.should_start_before) else {
+                continue;
+            };
+            let local = to_local(&start, request.timezone.as_deref());
+            let region = request.region.as_deref().unwrap_or(DEFAULT_REGION);
+            let Some((opens_at, closes_at)) = self
+                .holiday_operating_hours(region, local.date_naive())
+                .await?
+            else {
+                continue;
+            };
+            let time = local.time();
+            if opens_at.map_or(false, |o| time < o) || closes_at.map_or(false, |c| time >= c) {
+                closed.insert(request.id.clone());
+            }
+        }
+        requests.retain(|r| !closed.contains(&r.id));
+        Ok(closed.len() as u64)
+    }
+
+    /// Results stay in distance order when ranking fails.
+    async fn rank(&self, requests: &mut [WalkRequest], walker: Option<&str>) {
+        let Some(ranker) = &self.ranker else {
+            return;
+        };
+        let ids: Vec<String> = requests.iter().map(|r| r.id.clone()).collect();
+        let impressions = match self.repository.query_funnel_marks(&ids).await {
+            Ok(marks) => marks
+                .into_iter()
+                .map(|m| (m.request_id, m.impressions))
+                .collect(),
+            Err(e) => {
+                log::error!("failed to load impressions for ranking: {}", e);
+                return;
+            }
+        };
+        let variant = match (ranker.experiment(), walker) {
+            (Some(experiment), Some(walker)) => self.variant(experiment, walker).await,
+            _ => None,
+        };
+        let context = RankingContext {
+            walker: walker.map(str::to_owned),
+            impressions,
+            variant,
+            now: Utc::now(),
+        };
+        if let Err(e) = ranker.rank(requests, &context).await {
+            log::error!("failed to rank nearby results: {}", e);
+        }
+    }
+
+    /// The owner's requests, newest first unless `sort_by` says otherwise.
+    pub async fn my_walk_requests(
+        &self,
+        user_id: &str,
+        filter: MyWalkRequestsFilter,
+        pagination: Pagination,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        let mut paged = self
+            .filtered_walk_requests(
+                WalkRequestQuery {
+                    created_by: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                filter,
+                pagination,
+            )
+            .await?;
+        self.count_unread_messages(user_id, &mut paged.items).await;
+        Ok(paged)
+    }
+
+    /// Every request the owner made, oldest first, read lazily for exports.
+    pub async fn export_walk_requests(
+        &self,
+        user_id: &str,
+    ) -> Result<WalkRequestStream, ServiceError> {
+        self.repository
+            .stream_walk_requests(
+                WalkRequestQuery {
+                    created_by: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                Some(SortBy {
+                    field: WalkRequest::created_at(),
+                    order: Order::Asc,
+                }),
+                None,
+            )
+            .await
+    }
+
+    /// Walks the walker was picked for, their schedule.
+    pub async fn accepted_walk_requests(
+        &self,
+        user_id: &str,
+        filter: MyWalkRequestsFilter,
+        pagination: Pagination,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        let mut paged = self
+            .filtered_walk_requests(
+                WalkRequestQuery {
+                    accepted_by: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                filter,
+                pagination,
+            )
+            .await?;
+        self.count_unread_messages(user_id, &mut paged.items).await;
+        Ok(paged)
+    }
+
+    /// Requests the walker applied to, whether or not they were picked.
+    pub async fn applied_walk_requests(
+        &self,
+        user_id: &str,
+        filter: MyWalkRequestsFilter,
+        pagination: Pagination,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        self.filtered_walk_requests(
+            WalkRequestQuery {
+                acceptances_includes_any: Some(vec![user_id.to_owned()]),
+                ..Default::default()
+            },
+            filter,
+            pagination,
+        )
+        .await
+    }
+
+    /// The walker's accepted walks whose time window overlaps `start..end`.
+    pub async fn conflicting_walks(
+        &self,
+        user_id: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        if start >= end {
+            return Err(ServiceError::Validation(
+                "结束时间不得早于开始时间".to_owned(),
+            ));
+        }
+        self.repository
+            .query_walk_requests(
+                overlapping_walks(user_id, start, end),
+                Some(SortBy {
+                    field: WalkRequest::should_start_after(),
+                    order: Order::Asc,
+                }),
+                None,
+            )
+            .await
+    }
+
+    async fn filtered_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        filter: MyWalkRequestsFilter,
+        pagination: Pagination,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        let sort_by = match filter.sort_by {
+            Some(field) if !MY_SORT_FIELDS.contains(&field.as_str()) => {
+                return Err(ServiceError::Validation(format!(
+                    "不支持的排序字段: {}",
+                    field
+                )));
+            }
+            Some(field) => field,
+            None => WalkRequest::created_at(),
+        };
+        let query = WalkRequestQuery {
+            status: filter.status,
+            created_at_gte: filter.created_after,
+            created_at_lte: filter.created_before,
+            dog_name_contains: filter.dog_name,
+            dog_breed: filter.dog_breed,
+            dog_size: filter.dog_size,
+            ..query
+        };
+        let total = self.repository.count_walk_requests(query.clone()).await?;
+        let items = self
+            .repository
+            .query_walk_requests(
+                query,
+                Some(SortBy {
+                    field: sort_by,
+                    order: filter.order.unwrap_or(Order::Desc),
+                }),
+                Some(pagination),
+            )
+            .await?;
+        Ok(Paged::new(items, total, pagination))
+    }
+
+    /// Upcoming walks the user posted or walks, pool walks not yet full
+    /// included.
+    pub async fn calendar_walk_requests(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let created = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    created_by: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?;
+        let accepted = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    accepted_by: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?;
+        let pooled = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    pool_walker: Some(user_id.to_owned()),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?;
+        let now = Utc::now();
+        let mut requests: Vec<WalkRequest> = Vec::new();
+        for request in created.into_iter().chain(accepted).chain(pooled) {
+            if request.canceled_at.is_some()
+                || request.finished_at.is_some()
+                || request.expired_at.is_some()
+            {
+                continue;
+            }
+            if request.should_end_before.map_or(false, |end| end < now) {
+                continue;
+            }
+            if requests.iter().any(|r| r.id == request.id) {
+                continue;
+            }
+            requests.push(request);
+        }
+        requests.sort_by_key(|r| r.should_start_after);
+        Ok(requests)
+    }
+
+    pub async fn accept(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<WalkRequest, ServiceError> {
+        self.ensure_not_banned(user_id).await?;
+        self.ensure_onboarded(user_id).await?;
+        self.ensure_capable(request_id, user_id).await?;
+        self.ensure_within_walk_budget(request_id, user_id).await?;
+        self.ensure_available(request_id, user_id).await?;
+        if self
+            .repository
+            .get_walk_request(request_id)
+            .await?
+            .is_pool()
+        {
+            return self.join_pool(request_id, user_id).await;
+        }
+        let query = WalkRequestQuery {
+            id: Some(request_id.into()),
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            cancel_requested_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            invitation_visible_to: Some(InvitationViewer {
+                walker: Some(user_id.to_owned()),
+                at: Utc::now(),
+            }),
+            ..Default::default()
+        };
+        let update = WalkRequestUpdate {
+            accepted_by: Some(user_id.to_owned()),
+            accepted_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        match self
+            .update_request(query, update, |_| {
+                Publications::event(WalkRequestEventKind::Accepted, request_id, user_id)
+            })
+            .await
+        {
+            Ok(request) => Ok(request),
+            Err(ServiceError::NotFound(_)) => Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Accepted,
+                    "代遛请求不存在",
+                )
+                .await),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds the walker to a pool walk, which is accepted once full.
+    async fn join_pool(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self
+            .update_pool(
+                request_id,
+                WalkRequestStatus::Accepted,
+                |request, now| {
+                    if request.status != WalkRequestStatus::Waiting
+                        || request.created_by.as_deref() == Some(user_id)
+                        || request.walked_by(user_id)
+                    {
+                        return None;
+                    }
+                    let mut walkers = request.pool_walkers.clone();
+                    walkers.push(PoolWalker {
+                        walker_id: user_id.to_owned(),
+                        accepted_at: now,
+                        started_at: None,
+                        finished_at: None,
+                    });
+                    let full = walkers.len() as i64 >= request.max_walkers.unwrap_or(1);
+                    Some(WalkRequestUpdate {
+                        accepted_by: full.then(|| walkers[0].walker_id.clone()),
+                        accepted_at: full.then_some(now),
+                        pool_walkers: Some(walkers),
+                        ..Default::default()
+                    })
+                },
+                |_| Publications::event(WalkRequestEventKind::Accepted, request_id, user_id),
+            )
+            .await?;
+        Ok(request)
+    }
+
+    pub async fn apply(&self, request_id: &str, user_id: &str) -> Result<(), ServiceError> {
+        self.ensure_onboarded(user_id).await?;
+        self.ensure_capable(request_id, user_id).await?;
+        let query = WalkRequestQuery {
+            id: Some(request_id.to_owned()),
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            cancel_requested_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            created_by_neq: Some(user_id.to_owned()),
+            dismissed_applicants_excludes: Some(user_id.to_owned()),
+            below_applicant_cap: Some(self.default_applicant_cap()),
+            apply_open_at: Some(Utc::now()),
+            invitation_visible_to: Some(InvitationViewer {
+                walker: Some(user_id.to_owned()),
+                at: Utc::now(),
+            }),
+            ..Default::default()
+        };
+        // The request, the application and the outbox are written together.
+        let applied = self
+            .transition(|repository| async move {
+                let update = WalkRequestUpdate {
+                    add_to_acceptances: Some(user_id.to_owned()),
+                    ..Default::default()
+                };
+                let n = repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Pending)
+                    .await?;
+                let request = repository.get_walk_request(request_id).await?;
+                let publications =
+                    Publications::event(WalkRequestEventKind::Applied, request_id, user_id)
+                        .notify_owner(&request, NotificationKind::Applied);
+                Ok((true, publications))
+            })
+            .await?;
+        if applied {
+            self.mark_funnel(request_id, FunnelStage::Applied).await;
+            return Ok(());
+        }
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.created_by.as_deref() == Some(user_id) {
+            return Err(ServiceError::Validation(
+                "不能报名自己发布的代遛请求".to_owned(),
+            ));
+        }
+        if request.status != WalkRequestStatus::Waiting {
+            return Err(ApplyError::NotWaiting.into());
+        }
+        if request
+            .dismissed_applicants
+            .map_or(false, |d| d.iter().any(|u| u == user_id))
+        {
+            return Err(ApplyError::PreviouslyDismissed.into());
+        }
+        let acceptances = request.acceptances.unwrap_or_default();
+        if acceptances.iter().any(|u| u == user_id) {
+            return Ok(());
+        }
+        if request.apply_before.map_or(false, |b| b <= Utc::now()) {
+            return Err(ApplyError::DeadlinePassed.into());
+        }
+        let cap = request
+            .max_applicants
+            .unwrap_or(self.default_applicant_cap());
+        if request.accepted_by.is_none() && acceptances.len() as i64 >= cap {
+            return Err(ApplyError::ApplicantCapReached.into());
+        }
+        Err(ServiceError::Conflict("请求不存在或已有人接单".to_owned()))
+    }
+
+    pub async fn remove_acceptance(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let query = WalkRequestQuery {
+            id: Some(request_id.to_owned()),
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            acceptances_includes_all: Some(vec![user_id.to_owned()]),
+            ..Default::default()
+        };
+        let update = WalkRequestUpdate {
+            remove_from_acceptances: Some(user_id.to_owned()),
+            ..Default::default()
+        };
+        let withdrawn = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Withdrawn)
+                    .await?;
+                let publications =
+                    Publications::event(WalkRequestEventKind::Withdrawn, request_id, user_id);
+                Ok((true, publications))
+            })
+            .await?;
+        if !withdrawn {
+            let request = self.repository.get_walk_request(request_id).await?;
+            if request.status != WalkRequestStatus::Waiting {
+                return Err(ApplyError::NotWaiting.into());
+            }
+            return Err(ApplyError::NotApplied.into());
+        }
+        Ok(())
+    }
+
+    /// Picks the walker among the applicants. Walkers busy with another
+    /// walk at the same time are refused unless `force` is set.
+    pub async fn assign_accepter(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        user_id: &str,
+        force: bool,
+    ) -> Result<(), ServiceError> {
+        self.ensure_capable(request_id, user_id).await?;
+        self.ensure_within_walk_budget(request_id, user_id).await?;
+        if !force {
+            self.ensure_available(request_id, user_id).await?;
+        }
+        let query = WalkRequestQuery {
+            id: Some(request_id.to_owned()),
+            created_by: Some(owner_id.to_owned()),
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            cancel_requested_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            acceptances_includes_all: Some(vec![user_id.to_owned()]),
+            ..Default::default()
+        };
+        let update = WalkRequestUpdate {
+            accepted_by: Some(user_id.to_owned()),
+            accepted_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        let assigned = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Assigned)
+                    .await?;
+                let publications = Publications::default()
+                    .emit_about(
+                        WalkRequestEventKind::Accepted,
+                        request_id,
+                        owner_id,
+                        vec![user_id.to_owned()],
+                    )
+                    .notify(user_id, NotificationKind::Assigned, request_id);
+                Ok((true, publications))
+            })
+            .await?;
+        if !assigned {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    WalkRequestStatus::Accepted,
+                    "请求不存在或该用户已取消报名",
+                )
+                .await);
+        }
+        self.settle_price(request_id, user_id).await
+    }
+
+    /// Sets the price agreed on with the assigned walker, if they agreed on
+    /// one.
+    async fn settle_price(&self, request_id: &str, walker_id: &str) -> Result<(), ServiceError> {
+        let agreed = self
+            .repository
+            .query_offers(request_id, Some(walker_id))
+            .await?
+            .into_iter()
+            .find(|o| o.state == OfferState::Accepted);
+        let Some(offer) = agreed else {
+            return Ok(());
+        };
+        self.repository
+            .update_walk_requests_by_query(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    accepted_by: Some(walker_id.to_owned()),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    price: Some(offer.price),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// The request an offer is made on, which must still be waiting for a
+    /// walker.
+    async fn negotiable(
+        &self,
+        request_id: &str,
+        price: &Money,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.status != WalkRequestStatus::Waiting {
+            return Err(ApplyError::NotWaiting.into());
+        }
+        if request
+            .price
+            .as_ref()
+            .map_or(false, |p| p.currency != price.currency)
+        {
+            return Err(ServiceError::Validation(
+                "报价币种与代遛请求不一致".to_owned(),
+            ));
+        }
+        Ok(request)
+    }
+
+    /// Offers a price for a request the walker applied to. An open offer of
+    /// theirs is superseded.
+    pub async fn make_offer(
+        &self,
+        request_id: &str,
+        walker_id: &str,
+        create: OfferCreate,
+    ) -> Result<Offer, ServiceError> {
+        create.validate()?;
+        let request = self.negotiable(request_id, &create.price).await?;
+        if !request
+            .acceptances
+            .unwrap_or_default()
+            .iter()
+            .any(|u| u == walker_id)
+        {
+            return Err(ApplyError::NotApplied.into());
+        }
+        let open: Vec<Offer> = self
+            .repository
+            .query_offers(request_id, Some(walker_id))
+            .await?
+            .into_iter()
+            .filter(|o| o.state == OfferState::Open)
+            .collect();
+        let offer = Offer::new(request_id, walker_id, walker_id, create.price);
+        let saved = &offer;
+        self.transition(|repository| async move {
+            for mut open in open {
+                open.state = OfferState::Countered;
+                open.updated_at = Utc::now();
+                repository.save_offer(&open).await?;
+            }
+            repository.save_offer(saved).await?;
+            Ok((
+                (),
+                Publications::default().notify_owner(&request, NotificationKind::Offered),
+            ))
+        })
+        .await?;
+        Ok(offer)
+    }
+
+    /// An open offer awaiting `user_id`, see `Offer::awaits`.
+    async fn awaiting_offer(
+        &self,
+        request: &WalkRequest,
+        offer_id: &str,
+        user_id: &str,
+    ) -> Result<Offer, ServiceError> {
+        let offer = self
+            .repository
+            .get_offer(offer_id)
+            .await?
+            .filter(|o| o.request_id == request.id)
+            .ok_or_else(|| ServiceError::NotFound("报价不存在".to_owned()))?;
+        if !offer.awaits(user_id, request.created_by.as_deref()) {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        if offer.state != OfferState::Open {
+            return Err(ServiceError::Conflict("该报价已失效".to_owned()));
+        }
+        Ok(offer)
+    }
+
+    /// Answers an offer with another price, by either party.
+    pub async fn counter_offer(
+        &self,
+        request_id: &str,
+        offer_id: &str,
+        user_id: &str,
+        create: OfferCreate,
+    ) -> Result<Offer, ServiceError> {
+        create.validate()?;
+        let request = self.negotiable(request_id, &create.price).await?;
+        let mut countered = self.awaiting_offer(&request, offer_id, user_id).await?;
+        countered.state = OfferState::Countered;
+        countered.updated_at = Utc::now();
+        let mut offer = Offer::new(request_id, &countered.walker_id, user_id, create.price);
+        offer.counters = Some(countered.id.clone());
+        let saved = &offer;
+        self.transition(|repository| async move {
+            repository.save_offer(&countered).await?;
+            repository.save_offer(saved).await?;
+            let publications = Publications::default().notify(
+                &countered.offered_by,
+                NotificationKind::Offered,
+                request_id,
+            );
+            Ok(((), publications))
+        })
+        .await?;
+        Ok(offer)
+    }
+
+    /// Agrees on the price of an offer. The owner still assigns the walker,
+    /// the request takes the price then. Owners confirm prices over their
+    /// spending cap, see `ensure_within_cap`.
+    pub async fn accept_offer(
+        &self,
+        request_id: &str,
+        offer_id: &str,
+        user_id: &str,
+    ) -> Result<Offer, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.status != WalkRequestStatus::Waiting {
+            return Err(ApplyError::NotWaiting.into());
+        }
+        let mut offer = self.awaiting_offer(&request, offer_id, user_id).await?;
+        if request.created_by.as_deref() == Some(user_id) {
+            self.ensure_within_cap(user_id, &offer.price).await?;
+        }
+        offer.state = OfferState::Accepted;
+        offer.updated_at = Utc::now();
+        let saved = &offer;
+        self.transition(|repository| async move {
+            repository.save_offer(saved).await?;
+            let publications = Publications::default().notify(
+                &saved.offered_by,
+                NotificationKind::OfferAccepted,
+                request_id,
+            );
+            Ok(((), publications))
+        })
+        .await?;
+        Ok(offer)
+    }
+
+    /// Every negotiation of the request for its owner, only their own for a
+    /// walker.
+    pub async fn offers(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<Vec<Offer>, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        let walker_id = (request.created_by.as_deref() != Some(user_id)).then_some(user_id);
+        self.repository.query_offers(request_id, walker_id).await
+    }
+
+    pub async fn dismiss_accepter(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let query = WalkRequestQuery {
+            id: Some(request_id.to_owned()),
+            created_by: Some(owner_id.to_owned()),
+            accepted_by: Some(user_id.to_owned()),
+            started_at_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            ..Default::default()
+        };
+        let update = WalkRequestUpdate {
+            unset_accepted_by: true,
+            unset_accepted_at: true,
+            remove_from_acceptances: Some(user_id.to_owned()),
+            add_to_dismissed_applicants: Some(user_id.to_owned()),
+            ..Default::default()
+        };
+        let dismissed = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Dismissed)
+                    .await?;
+                let publications = Publications::default()
+                    .emit_about(
+                        WalkRequestEventKind::Dismissed,
+                        request_id,
+                        owner_id,
+                        vec![user_id.to_owned()],
+                    )
+                    .notify(user_id, NotificationKind::Dismissed, request_id);
+                Ok((true, publications))
+            })
+            .await?;
+        if !dismissed {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    WalkRequestStatus::Waiting,
+                    "请求不存在或该用户已取消报名",
+                )
+                .await);
+        }
+        if let Err(e) = self
+            .strike(user_id, request_id, StrikeReason::Dismissed)
+            .await
+        {
+            log::error!("failed to record a strike against {}: {}", user_id, e);
+        }
+        Ok(())
+    }
+
+    /// Replaces the accepted walker with another applicant in one update, so
+    /// that the request is never left open in between. The replaced walker
+    /// is dismissed as by `dismiss_accepter`.
+    pub async fn swap_accepter(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        walker_id: &str,
+        force: bool,
+    ) -> Result<(), ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        let pool = request.is_pool();
+        let Some(previous) = request.accepted_by.filter(|_| !pool) else {
+            return Err(ServiceError::Conflict("代遛请求尚无人接单".to_owned()));
+        };
+        if previous == walker_id {
+            return Ok(());
+        }
+        self.ensure_capable(request_id, walker_id).await?;
+        self.ensure_within_walk_budget(request_id, walker_id)
+            .await?;
+        if !force {
+            self.ensure_available(request_id, walker_id).await?;
+        }
+        // The request, both applications and the outbox are written
+        // together.
+        let replaced = previous.clone();
+        let swapped = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(
+                        WalkRequestQuery {
+                            id: Some(request_id.to_owned()),
+                            created_by: Some(owner_id.to_owned()),
+                            accepted_by: Some(replaced.clone()),
+                            started_at_is_null: Some(true),
+                            canceled_at_is_null: Some(true),
+                            cancel_requested_at_is_null: Some(true),
+                            acceptances_includes_all: Some(vec![walker_id.to_owned()]),
+                            ..Default::default()
+                        },
+                        WalkRequestUpdate {
+                            accepted_by: Some(walker_id.to_owned()),
+                            accepted_at: Some(Utc::now()),
+                            remove_from_acceptances: Some(replaced.clone()),
+                            add_to_dismissed_applicants: Some(replaced.clone()),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, &replaced, ApplicationState::Dismissed)
+                    .await?;
+                repository
+                    .upsert_application(request_id, walker_id, ApplicationState::Assigned)
+                    .await?;
+                let publications = Publications::default()
+                    .emit_about(
+                        WalkRequestEventKind::Dismissed,
+                        request_id,
+                        owner_id,
+                        vec![replaced.clone()],
+                    )
+                    .emit_about(
+                        WalkRequestEventKind::Accepted,
+                        request_id,
+                        owner_id,
+                        vec![walker_id.to_owned()],
+                    )
+                    .notify(&replaced, NotificationKind::Dismissed, request_id)
+                    .notify(walker_id, NotificationKind::Assigned, request_id);
+                Ok((true, publications))
+            })
+            .await?;
+        if !swapped {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    WalkRequestStatus::Accepted,
+                    "请求不存在或该用户已取消报名",
+                )
+                .await);
+        }
+        if let Err(e) = self
+            .strike(&previous, request_id, StrikeReason::Dismissed)
+            .await
+        {
+            log::error!("failed to record a strike against {}: {}", previous, e);
+        }
+        self.settle_price(request_id, walker_id).await
+    }
+
+    /// Dismisses many applicants at once. The accepted walker, if any, is
+    /// left alone, see `dismiss_accepter`. Returns who was dismissed.
+    pub async fn dismiss_applicants(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        selection: ApplicantSelection,
+    ) -> Result<Vec<String>, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.created_by.as_deref() != Some(owner_id) {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        let dismissed: Vec<String> = request
+            .acceptances
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|a| request.accepted_by.as_ref() != Some(a))
+            .filter(|a| match &selection {
+                ApplicantSelection::Users(users) => users.contains(a),
+                ApplicantSelection::AllExcept(kept) => !kept.contains(a),
+            })
+            .collect();
+        if dismissed.is_empty() {
+            return Ok(dismissed);
+        }
+        let query = WalkRequestQuery {
+            id: Some(request_id.to_owned()),
+            created_by: Some(owner_id.to_owned()),
+            started_at_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            ..Default::default()
+        };
+        let update = WalkRequestUpdate {
+            remove_all_from_acceptances: Some(dismissed.clone()),
+            add_all_to_dismissed_applicants: Some(dismissed.clone()),
+            ..Default::default()
+        };
+        let users = &dismissed;
+        let done = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?;
+                if n != 1 {
+                    return Ok((false, Publications::default()));
+                }
+                let mut publications = Publications::default().emit_about(
+                    WalkRequestEventKind::ApplicantsDismissed,
+                    request_id,
+                    owner_id,
+                    users.clone(),
+                );
+                for user_id in users {
+                    repository
+                        .upsert_application(request_id, user_id, ApplicationState::Dismissed)
+                        .await?;
+                    publications =
+                        publications.notify(user_id, NotificationKind::Dismissed, request_id);
+                }
+                Ok((true, publications))
+            })
+            .await?;
+        if !done {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    WalkRequestStatus::Waiting,
+                    "请求不存在",
+                )
+                .await);
+        }
+        Ok(dismissed)
+    }
+
+    pub async fn set_track_visibility(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        track_visibility: TrackVisibility,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.created_by.as_deref() != Some(owner_id) {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        self.repository
+            .update_walk_request(
+                request_id,
+                WalkRequestUpdate {
+                    track_visibility: Some(track_visibility),
+                    ..self.owner_update()
+                },
+            )
+            .await
+    }
+
+    /// How long owners may undo a cancellation. Without a window cancellations
+    /// are final right away.
+    pub fn with_cancel_undo_window(mut self, window: chrono::Duration) -> Self {
+        self.cancel_undo_window = Some(window);
+        self
+    }
+
+    fn owner_cancellation_status(&self) -> WalkRequestStatus {
+        match self.cancel_undo_window {
+            Some(_) => WalkRequestStatus::PendingCancel,
+            None => WalkRequestStatus::Canceled,
+        }
+    }
+
+    fn owner_cancellation_kind(&self) -> WalkRequestEventKind {
+        match self.owner_cancellation_status() {
+            WalkRequestStatus::PendingCancel => WalkRequestEventKind::CancelRequested,
+            _ => WalkRequestEventKind::Canceled,
+        }
+    }
+
+    fn owner_cancellation(&self, owner_id: &str, reason: Option<String>) -> WalkRequestUpdate {
+        let update = WalkRequestUpdate {
+            canceled_by: Some(owner_id.to_owned()),
+            cancellation_reason: reason,
+            ..self.owner_update()
+        };
+        match self.cancel_undo_window {
+            Some(_) => WalkRequestUpdate {
+                cancel_requested_at: Some(Utc::now()),
+                ..update
+            },
+            None => WalkRequestUpdate {
+                canceled_at: Some(Utc::now()),
+                ..update
+            },
+        }
+    }
+
+    /// Reverts a pending cancellation while its undo window is still open.
+    pub async fn undo_cancel(&self, request_id: &str, owner_id: &str) -> Result<(), ServiceError> {
+        let Some(window) = self.cancel_undo_window else {
+            return Err(ServiceError::Conflict("取消操作无法撤销".to_owned()));
+        };
+        let n = self
+            .update_requests(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    created_by: Some(owner_id.to_owned()),
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_gte: Some(Utc::now() - window),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    unset_cancel_request: true,
+                    ..self.owner_update()
+                },
+                Publications::event(WalkRequestEventKind::CancelUndone, request_id, owner_id),
+            )
+            .await?;
+        if n != 1 {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    WalkRequestStatus::Waiting,
+                    "请求不存在或已超过撤销期限",
+                )
+                .await);
+        }
+        Ok(())
+    }
+
+    /// Cancels the requests whose undo window closed.
+    pub async fn finalize_cancellations(&self) -> Result<u64, ServiceError> {
+        let Some(window) = self.cancel_undo_window else {
+            return Ok(0);
+        };
+        let cutoff = Utc::now() - window;
+        let pending = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_lte: Some(cutoff),
+                    ..Default::default()
+                },
+                None,
+                None,
+            )
+            .await?;
+        let mut finalized = 0;
+        for request in pending {
+            let actor = request
+                .canceled_by
+                .or(request.created_by)
+                .unwrap_or_default();
+            // Only a request still pending since before the cutoff is updated,
+            // and only an update publishes, so each cancellation is
+            // finalized once.
+            finalized += self
+                .update_requests(
+                    WalkRequestQuery {
+                        id: Some(request.id.clone()),
+                        canceled_at_is_null: Some(true),
+                        cancel_requested_at_lte: Some(cutoff),
+                        ..Default::default()
+                    },
+                    WalkRequestUpdate {
+                        canceled_at: Some(Utc::now()),
+                        ..Default::default()
+                    },
+                    Publications::event(WalkRequestEventKind::Canceled, &request.id, &actor),
+                )
+                .await?;
+        }
+        Ok(finalized)
+    }
+
+    pub async fn cancel_unaccepted_request(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        reason: Option<String>,
+    ) -> Result<(), ServiceError> {
+        let reason = cancellation_reason(reason)?;
+        let n = self
+            .update_requests(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    created_by: Some(owner_id.to_owned()),
+                    accepted_by_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_is_null: Some(true),
+                    expired_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                self.owner_cancellation(owner_id, reason),
+                Publications::event(self.owner_cancellation_kind(), request_id, owner_id),
+            )
+            .await?;
+        if n != 1 {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    self.owner_cancellation_status(),
+                    "请求不存在",
+                )
+                .await);
+        }
+        Ok(())
+    }
+
+    pub async fn cancel_accepted_request(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        user_id: &str,
+        reason: Option<String>,
+    ) -> Result<(), ServiceError> {
+        let reason = cancellation_reason(reason)?;
+        let n = self
+            .update_requests(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    created_by: Some(owner_id.to_owned()),
+                    accepted_by: Some(user_id.to_owned()),
+                    started_at_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                self.owner_cancellation(owner_id, reason),
+                Publications::event(self.owner_cancellation_kind(), request_id, owner_id),
+            )
+            .await?;
+        if n != 1 {
+            return Err(self
+                .rejected(
+                    request_id,
+                    Some(owner_id),
+                    self.owner_cancellation_status(),
+                    "请求不存在",
+                )
+                .await);
+        }
+        Ok(())
+    }
+
+    pub async fn resign_acceptance(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        let n = self
+            .transition(|repository| async move {
+                let n = repository
+                    .update_walk_requests_by_query(
+                        WalkRequestQuery {
+                            id: Some(request_id.to_owned()),
+                            accepted_by: Some(user_id.to_owned()),
+                            started_at_is_null: Some(true),
+                            canceled_at_is_null: Some(true),
+                            ..Default::default()
+                        },
+                        WalkRequestUpdate {
+                            unset_accepted_by: true,
+                            unset_accepted_at: true,
+                            remove_from_acceptances: Some(user_id.to_owned()),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                if n != 1 {
+                    return Ok((n, Publications::default()));
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Withdrawn)
+                    .await?;
+                let publications =
+                    Publications::event(WalkRequestEventKind::Resigned, request_id, user_id);
+                Ok((n, publications))
+            })
+            .await?;
+        if n != 1 {
+            return Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Waiting,
+                    "请求不存在或已被狗狗主人取消",
+                )
+                .await);
+        }
+        if let Err(e) = self
+            .strike(user_id, request_id, StrikeReason::Resigned)
+            .await
+        {
+            log::error!("failed to record a strike against {}: {}", user_id, e);
+        }
+        Ok(())
+    }
+
+    /// Starts the walk, the walker's `location`, as (longitude, latitude),
+    /// is checked against the pickup point, see `WalkVerification`. Walks
+    /// started offline pass `occurred_at`, see `ClientTime::settle`.
+    pub async fn start_walk(
+        &self,
+        request_id: &str,
+        user_id: &str,
+        location: Option<(f64, f64)>,
+        occurred_at: Option<DateTime<Utc>>,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        if request.walked_by(user_id) {
+            self.verification.check_start(&request, location)?;
+        }
+        let own_accept = request
+            .pool_walkers
+            .iter()
+            .find(|w| w.walker_id == user_id)
+            .map(|w| w.accepted_at);
+        let time = ClientTime::settle(occurred_at, own_accept.or(request.accepted_at), Utc::now())?;
+        if request.is_pool() {
+            return self.start_pool_walk(request_id, user_id, time).await;
+        }
+        match self
+            .update_request(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    accepted_by: Some(user_id.to_owned()),
+                    started_at_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    cancel_requested_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    started_at: Some(time.occurred_at),
+                    start_received_at: Some(time.received_at),
+                    clock_skew_detected_at: time.skew_detected_at(),
+                    ..Default::default()
+                },
+                |request| {
+                    Publications::event(WalkRequestEventKind::Started, request_id, user_id)
+                        .notify_owner(request, NotificationKind::Started)
+                },
+            )
+            .await
+        {
+            Ok(request) => Ok(request),
+            Err(ServiceError::NotFound(_)) => Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Started,
+                    "代遛请求不存在",
+                )
+                .await),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Starts the walker's part of a full pool walk, the first to start
+    /// starts the request.
+    async fn start_pool_walk(
+        &self,
+        request_id: &str,
+        user_id: &str,
+        time: ClientTime,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self
+            .update_pool(
+                request_id,
+                WalkRequestStatus::Started,
+                |request, _| {
+                    if !matches!(
+                        request.status,
+                        WalkRequestStatus::Accepted | WalkRequestStatus::Started
+                    ) {
+                        return None;
+                    }
+                    let mut walkers = request.pool_walkers.clone();
+                    let walker = walkers
+                        .iter_mut()
+                        .find(|w| w.walker_id == user_id && w.started_at.is_none())?;
+                    walker.started_at = Some(time.occurred_at);
+                    let first = request.started_at.is_none();
+                    Some(WalkRequestUpdate {
+                        started_at: first.then_some(time.occurred_at),
+                        start_received_at: first.then_some(time.received_at),
+                        clock_skew_detected_at: time.skew_detected_at(),
+                        pool_walkers: Some(walkers),
+                        ..Default::default()
+                    })
+                },
+                |request| {
+                    let publications =
+                        Publications::event(WalkRequestEventKind::Started, request_id, user_id);
+                    let first = request
+                        .pool_walkers
+                        .iter()
+                        .filter(|w| w.started_at.is_some())
+                        .count()
+                        == 1;
+                    if first {
+                        publications.notify_owner(request, NotificationKind::Started)
+                    } else {
+                        publications
+                    }
+                },
+            )
+            .await?;
+        Ok(request)
+    }
+
+    pub fn with_invitation_timeout(mut self, timeout: chrono::Duration) -> Self {
+        self.invitation_timeout = timeout;
+        self
+    }
+
+    /// The owner's favorite walkers, most recently saved first.
+    pub async fn favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError> {
+        self.repository.query_favorite_walkers(owner_id).await
+    }
+
+    /// Saves the walker as a favorite of the owner, saving one again keeps
+    /// it where it was.
+    pub async fn add_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<FavoriteWalker, ServiceError> {
+        if owner_id == walker_id {
+            return Err(ServiceError::Validation("不能收藏自己".to_owned()));
+        }
+        let favorites = self.repository.query_favorite_walkers(owner_id).await?;
+        if let Some(favorite) = favorites.iter().find(|f| f.walker_id == walker_id) {
+            return Ok(favorite.clone());
+        }
+        if favorites.len() >= MAX_FAVORITES_PER_OWNER {
+            return Err(ServiceError::Conflict(format!(
+                "最多只能收藏{}个遛狗人",
+                MAX_FAVORITES_PER_OWNER
+            )));
+        }
+        let favorite = FavoriteWalker {
+            owner_id: owner_id.to_owned(),
+            walker_id: walker_id.to_owned(),
+            created_at: Utc::now(),
+        };
+        self.repository
+            .upsert_favorite_walker(favorite.clone())
+            .await?;
+        Ok(favorite)
+    }
+
+    pub async fn remove_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<(), ServiceError> {
+        if self
+            .repository
+            .delete_favorite_walker(owner_id, walker_id)
+            .await?
+            == 0
+        {
+            return Err(ServiceError::NotFound("未收藏该遛狗人".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Opens the request the walker was invited to to every walker.
+    pub async fn decline_invitation(
+        &self,
+        request_id: &str,
+        walker_id: &str,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        let now = Utc::now();
+        if request.invited_walker_id.as_deref() != Some(walker_id) {
+            return Err(ServiceError::NotFound("邀请不存在".to_owned()));
+        }
+        if !request.invitation_running(now) || request.accepted_by.is_some() {
+            return Err(ServiceError::Conflict("邀请已失效".to_owned()));
+        }
+        self.update_request(
+            WalkRequestQuery {
+                id: Some(request_id.to_owned()),
+                accepted_by_is_null: Some(true),
+                version: Some(request.version),
+                ..Default::default()
+            },
+            WalkRequestUpdate {
+                invitation_expires_at: Some(now),
+                ..Default::default()
+            },
+            |declined| {
+                Publications::default().notify_owner(declined, NotificationKind::InvitationDeclined)
+            },
+        )
+        .await
+        .map_err(|e| match e {
+            // Taken or declined since it was read.
+            ServiceError::NotFound(_) => ServiceError::Conflict("邀请已失效".to_owned()),
+            e => e,
+        })
+    }
+
+    /// Buffers single location writes, see `LocationQueue`.
+    pub fn with_location_queue(mut self, queue: LocationQueue) -> Self {
+        self.location_queue = Some(queue);
+        self
+    }
+
+    /// Writes what the location queue holds, for shutdown.
+    pub async fn flush_locations(&self) {
+        if let Some(queue) = &self.location_queue {
+            queue.close().await;
+        }
+    }
+
+    pub fn with_location_filter(mut self, filter: LocationFilter) -> Self {
+        self.location_filter = filter;
+        self
+    }
+
+    pub fn with_walk_verification(mut self, verification: WalkVerification) -> Self {
+        self.verification = verification;
+        self
+    }
+
+    /// Lets the walker start and finish the walk without the checks of
+    /// `WalkVerification`, by the owner while the walk isn't over.
+    pub async fn waive_walk_verification(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+    ) -> Result<WalkRequest, ServiceError> {
+        match self
+            .repository
+            .update_walk_request_by_query(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    created_by: Some(owner_id.to_owned()),
+                    finished_at_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    verification_waived_at: Some(Utc::now()),
+                    ..self.owner_update()
+                },
+            )
+            .await
+        {
+            Ok(request) => Ok(request),
+            Err(ServiceError::NotFound(_)) => {
+                Err(ServiceError::NotFound("代遛请求不存在".to_owned()))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The walker's own start of a pool walk, or the start of the request.
+    fn walker_started_at(request: &WalkRequest, user_id: &str) -> Option<DateTime<Utc>> {
+        request
+            .pool_walkers
+            .iter()
+            .find(|w| w.walker_id == user_id)
+            .and_then(|w| w.started_at)
+            .or(request.started_at)
+    }
+
+    /// Whether the walker may finish the walk at `finished_at`, see
+    /// `WalkVerification`.
+    async fn verify_finish(
+        &self,
+        request: &WalkRequest,
+        user_id: &str,
+        finished_at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        let Some(started_at) = Self::walker_started_at(request, user_id) else {
+            return Ok(());
+        };
+        let track = if self.verification.checks_track(request) {
+            self.repository
+                .query_walking_locations(
+                    WalkingLocationQuery {
+                        walk_request_id: request.id.clone(),
+                        created_after: Some(started_at),
+                        created_before: None,
+                    },
+                    None,
+                )
+                .await?
+        } else {
+            Vec::new()
+        };
+        self.verification
+            .check_finish(request, started_at, finished_at, &track)
+    }
+
+    /// Stores the location, or queues it when there is a location queue.
+    /// Points too close to the previous one are dropped, see
+    /// `LocationFilter`. Points recorded offline pass `recorded_at`, see
+    /// `ClientTime::settle`.
+    pub async fn record_walking_location(
+        &self,
+        walk_request_id: &str,
+        longitude: f64,
+        latitute: f64,
+        recorded_at: Option<DateTime<Utc>>,
+    ) -> Result<LocationWrite, ServiceError> {
+        let mut create = WalkingLocationCreate {
+            walk_request_id,
+            longitude,
+            latitude: latitute,
+            recorded_at: None,
+        };
+        create.validate()?;
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let time = ClientTime::settle(recorded_at, request.started_at, Utc::now())?;
+        create.recorded_at = Some(time.occurred_at);
+        if !self
+            .location_filter
+            .keep(walk_request_id, (longitude, latitute), time.occurred_at)
+        {
+            return Ok(LocationWrite::Coalesced);
+        }
+        self.flag_clock_skew(&request, time).await;
+        if let Some(queue) = &self.location_queue {
+            queue
+                .push(QueuedLocation {
+                    walk_request_id: walk_request_id.to_owned(),
+                    longitude,
+                    latitude: latitute,
+                    recorded_at: time.occurred_at,
+                })
+                .map_err(|full| ServiceError::Overloaded(full.retry_after))?;
+            self.check_geofence(&request, &[(longitude, latitute)])
+                .await;
+            return Ok(LocationWrite::Queued);
+        }
+        let id = self.repository.create_walking_location(create).await?;
+        self.check_geofence(&request, &[(longitude, latitute)])
+            .await;
+        if let Some(locations) = &self.locations {
+            locations.publish(&WalkingLocation {
+                id: id.clone(),
+                request_id: walk_request_id.to_owned(),
+                longitude,
+                latitude: latitute,
+                created_at: Some(time.occurred_at),
+            });
+        }
+        Ok(LocationWrite::Stored(id))
+    }
+
+    /// Records on the request that a location came with a skewed time, once
+    /// per walk. Failures are logged, the location is stored regardless.
+    async fn flag_clock_skew(&self, request: &WalkRequest, time: ClientTime) {
+        if !time.skewed || request.clock_skew_detected_at.is_some() {
+            return;
+        }
+        if let Err(e) = self
+            .repository
+            .update_walk_requests_by_query(
+                WalkRequestQuery {
+                    id: Some(request.id.clone()),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    clock_skew_detected_at: time.skew_detected_at(),
+                    ..Default::default()
+                },
+            )
+            .await
+        {
+            log::error!("failed to record clock skew of {}: {}", request.id, e);
+        }
+    }
+
+    /// Stores the valid points of an offline batch, reporting the rest.
+    pub async fn record_walking_locations(
+        &self,
+        walk_request_id: &str,
+        locations: Vec<RecordedLocation>,
+    ) -> Result<LocationBatchReport, ServiceError> {
+        if locations.len() > MAX_LOCATION_BATCH {
+            return Err(ServiceError::Validation(format!(
+                "一次最多上传{}个定位",
+                MAX_LOCATION_BATCH
+            )));
+        }
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let latest = Utc::now() + chrono::Duration::seconds(MAX_CLOCK_AHEAD_SECONDS);
+        let mut report = LocationBatchReport::default();
+        let mut creates = Vec::new();
+        for (index, location) in locations.into_iter().enumerate() {
+            let message = if let Some(error) = location.field_errors().pop() {
+                Some(error.message)
+            } else if location.recorded_at > latest {
+                Some("定位时间晚于当前时间".to_owned())
+            } else if request
+                .started_at
+                .map_or(false, |started_at| location.recorded_at < started_at)
+            {
+                Some("定位时间早于遛狗开始时间".to_owned())
+            } else {
+                None
+            };
+            match message {
+                Some(message) => report.rejected.push(RejectedLocation { index, message }),
+                None => creates.push(WalkingLocationCreate {
+                    walk_request_id,
+                    longitude: location.longitude,
+                    latitude: location.latitude,
+                    recorded_at: Some(location.recorded_at),
+                }),
+            }
+        }
+        let points: Vec<(f64, f64)> = creates.iter().map(|c| (c.longitude, c.latitude)).collect();
+        report.ids = self.repository.create_walking_locations(creates).await?;
+        self.check_geofence(&request, &points).await;
+        Ok(report)
+    }
+
+    /// Records the first of `points`, as (longitude, latitude), outside the
+    /// request's `max_radius` and alerts the owner, once per walk. Failures
+    /// are logged, the points are already stored.
+    async fn check_geofence(&self, request: &WalkRequest, points: &[(f64, f64)]) {
+        let Some(max_radius) = request.max_radius else {
+            return;
+        };
+        if request.geofence_violated_at.is_some() {
+            return;
+        }
+        let pickup = (request.longitude, request.latitude);
+        if !points.iter().any(|p| distance(pickup, *p) > max_radius) {
+            return;
+        }
+        let walker = request.accepted_by.as_deref().unwrap_or_default();
+        let recorded = self
+            .update_requests(
+                WalkRequestQuery {
+                    id: Some(request.id.clone()),
+                    geofence_violated_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    geofence_violated_at: Some(Utc::now()),
+                    ..Default::default()
+                },
+                Publications::event(WalkRequestEventKind::GeofenceViolated, &request.id, walker)
+                    .notify_owner(request, NotificationKind::GeofenceViolated),
+            )
+            .await;
+        match recorded {
+            Ok(1) => {
+                self.alert(
+                    AlertKind::GeofenceViolation,
+                    request,
+                    "遛狗人已超出活动范围",
+                )
+                .await;
+            }
+            Ok(_) => {}
+            Err(e) => log::error!(
+                "failed to record geofence violation of {}: {}",
+                request.id,
+                e
+            ),
+        }
+    }
+
+    /// Live feed of the walker's locations, open to the owner and the walker.
+    pub async fn subscribe_walking_locations(
+        &self,
+        walk_request_id: &str,
+        user_id: &str,
+        device_id: Option<&str>,
+    ) -> Result<UnboundedReceiver<WalkingLocation>, ServiceError> {
+        let Some(locations) = &self.locations else {
+            return Err(ServiceError::NotFound("未开启实时定位".to_owned()));
+        };
+        self.ensure_feature(Feature::LiveTracking, user_id).await?;
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let owner_or_walker = Some(user_id.to_owned());
+        if request.created_by != owner_or_walker && request.accepted_by != owner_or_walker {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        let session = match device_id {
+            Some(device_id) => Some(self.follow(user_id, device_id, walk_request_id).await?),
+            None => None,
+        };
+        Ok(locations.subscribe(walk_request_id, session.as_deref()))
+    }
+
+    /// Events of the request as this instance emits them, and as other
+    /// instances do when their changes are listened to, for its owner or
+    /// walker.
+    pub async fn watch_walk_request(
+        &self,
+        walk_request_id: &str,
+        user_id: &str,
+        device_id: Option<&str>,
+    ) -> Result<UnboundedReceiver<WalkRequestEvent>, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let owner_or_walker = Some(user_id.to_owned());
+        if request.created_by != owner_or_walker && request.accepted_by != owner_or_walker {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        if let Some(device_id) = device_id {
+            self.follow(user_id, device_id, walk_request_id).await?;
+        }
+        Ok(self.changes.subscribe(walk_request_id))
+    }
+
+    /// Every recorded event of the request, oldest first, for its owner or
+    /// walker.
+    pub async fn timeline(
+        &self,
+        walk_request_id: &str,
+        user_id: &str,
+    ) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let owner_or_walker = Some(user_id.to_owned());
+        if request.created_by != owner_or_walker && request.accepted_by != owner_or_walker {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        self.repository.query_events(walk_request_id).await
+    }
+
+    /// The timeline of any request, for support.
+    pub async fn admin_timeline(
+        &self,
+        walk_request_id: &str,
+    ) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        self.repository.get_walk_request(walk_request_id).await?;
+        self.repository.query_events(walk_request_id).await
+    }
+
+    /// The other party of the request's chat, erring unless `user_id` is
+    /// its owner or walker.
+    fn chat_partner(request: &WalkRequest, user_id: &str) -> Result<String, ServiceError> {
+        match (&request.created_by, &request.accepted_by) {
+            (Some(owner), Some(walker)) if owner == user_id => Ok(walker.clone()),
+            (Some(owner), Some(walker)) if walker == user_id => Ok(owner.clone()),
+            _ => Err(ServiceError::Unauthorized("无权限".to_owned())),
+        }
+    }
+
+    /// Sends a message to the other party of an accepted request.
+    pub async fn send_message(
+        &self,
+        walk_request_id: &str,
+        user_id: &str,
+        create: MessageCreate,
+    ) -> Result<Message, ServiceError> {
+        create.validate()?;
+        self.ensure_feature(Feature::Chat, user_id).await?;
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let recipient = Self::chat_partner(&request, user_id)?;
+        let message = Message {
+            id: new_ulid(),
+            request_id: request.id,
+            sender: user_id.to_owned(),
+            body: create.body.trim().to_owned(),
+            sent_at: Utc::now(),
+        };
+        self.repository.create_message(&message, &recipient).await?;
+        Ok(message)
+    }
+
+    /// Messages of the request sent after `after`, oldest first. Reading
+    /// them clears the unread count of the reader.
+    pub async fn messages(
+        &self,
+        walk_request_id: &str,
+        user_id: &str,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Message>, ServiceError> {
+        self.ensure_feature(Feature::Chat, user_id).await?;
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        Self::chat_partner(&request, user_id)?;
+        let messages = self
+            .repository
+            .query_messages(walk_request_id, after, MESSAGE_PAGE_SIZE)
+            .await?;
+        self.repository
+            .mark_messages_read(walk_request_id, user_id)
+            .await?;
+        Ok(messages)
+    }
+
+    /// Fills the unread message counts of the requests `user_id` is a party
+    /// of. Failing to count leaves them unset rather than failing the read.
+    pub async fn count_unread_messages(&self, user_id: &str, requests: &mut [WalkRequest]) {
+        let ids: Vec<String> = requests
+            .iter()
+            .filter(|r| Self::chat_partner(r, user_id).is_ok())
+            .map(|r| r.id.clone())
+            .collect();
+        if ids.is_empty() {
+            return;
+        }
+        let unread = match self.repository.unread_messages(user_id, &ids).await {
+            Ok(unread) => unread,
+            Err(e) => {
+                log::error!("failed to count unread messages of {}: {}", user_id, e);
+                return;
+            }
+        };
+        for request in requests.iter_mut().filter(|r| ids.contains(&r.id)) {
+            request.unread_messages = Some(unread.get(&request.id).copied().unwrap_or(0));
+        }
+    }
+
+    async fn device_session(
+        &self,
+        user_id: &str,
+        device_id: &str,
+    ) -> Result<Option<DeviceSession>, ServiceError> {
+        let id = DeviceSession::id_of(user_id, device_id);
+        Ok(self
+            .repository
+            .query_device_sessions(user_id)
+            .await?
+            .into_iter()
+            .find(|s| s.id == id))
+    }
+
+    /// Records that the device follows the request, registering the device
+    /// on first use. Returns the session id.
+    async fn follow(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        request_id: &str,
+    ) -> Result<String, ServiceError> {
+        let mut session = self
+            .device_session(user_id, device_id)
+            .await?
+            .unwrap_or_else(|| DeviceSession::new(user_id, device_id));
+        if !session.active() {
+            return Err(ServiceError::Unauthorized("设备会话已撤销".to_owned()));
+        }
+        session.subscribe(request_id);
+        self.repository.save_device_session(&session).await?;
+        Ok(session.id)
+    }
+
+    /// Registers the device, or updates its label and push token. A revoked
+    /// device starts over.
+    pub async fn register_device_session(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        register: DeviceSessionRegister,
+    ) -> Result<DeviceSession, ServiceError> {
+        let mut session = match self.device_session(user_id, device_id).await? {
+            Some(session) if session.active() => session,
+            _ => DeviceSession::new(user_id, device_id),
+        };
+        session.label = register.label.or(session.label);
+        session.push_token = register.push_token.or(session.push_token);
+        session.last_seen_at = Utc::now();
+        self.repository.save_device_session(&session).await?;
+        Ok(session)
+    }
+
+    pub async fn device_sessions(&self, user_id: &str) -> Result<Vec<DeviceSession>, ServiceError> {
+        let mut sessions = self.repository.query_device_sessions(user_id).await?;
+        sessions.retain(DeviceSession::active);
+        Ok(sessions)
+    }
+
+    /// Signs the device out: its streams are closed and it gets no more
+    /// pushes.
+    pub async fn revoke_device_session(
+        &self,
+        user_id: &str,
+        device_id: &str,
+    ) -> Result<(), ServiceError> {
+        let Some(mut session) = self
+            .device_session(user_id, device_id)
+            .await?
+            .filter(DeviceSession::active)
+        else {
+            return Err(ServiceError::NotFound("设备会话不存在".to_owned()));
+        };
+        session.revoked_at = Some(Utc::now());
+        self.repository.save_device_session(&session).await?;
+        if let Some(locations) = &self.locations {
+            locations.close_session(&session.id);
+        }
+        Ok(())
+    }
+
+    /// Stops the device following the request.
+    pub async fn unfollow(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        request_id: &str,
+    ) -> Result<(), ServiceError> {
+        let Some(mut session) = self.device_session(user_id, device_id).await? else {
+            return Err(ServiceError::NotFound("设备会话不存在".to_owned()));
+        };
+        session.subscriptions.retain(|r| r != request_id);
+        self.repository.save_device_session(&session).await
+    }
+
+    /// The recorded track, if the request's track visibility lets `viewer` see it.
+    pub async fn walking_locations(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+        created_after: Option<DateTime<Utc>>,
+        created_before: Option<DateTime<Utc>>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if !request.track_visible_to(viewer) {
+            return Err(ServiceError::Unauthorized("无权查看遛狗轨迹".to_owned()));
+        }
+        let query = WalkingLocationQuery {
+            walk_request_id: walk_request_id.to_owned(),
+            created_after,
+            created_before,
+        };
+        let (Some(store), Some(_)) = (&self.archive, request.track_archived_at) else {
+            return self
+                .repository
+                .query_walking_locations(query, pagination)
+                .await;
+        };
+        // Rehydrate the archived part of the track, then page over the whole.
+        let archived = match store.get(&archive::track_key(walk_request_id)).await? {
+            Some(body) => archive::decompress(&body)?
+                .into_iter()
+                .filter(|l| {
+                    created_after.map_or(true, |t| l.created_at.map_or(false, |c| c >= t))
+                        && created_before.map_or(true, |t| l.created_at.map_or(false, |c| c < t))
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        let live = self.repository.query_walking_locations(query, None).await?;
+        let locations = archive::merge(archived, live);
+        Ok(match pagination {
+            Some(p) => locations
+                .into_iter()
+                .skip(((p.page - 1) * p.size).max(0) as usize)
+                .take(p.size.max(0) as usize)
+                .collect(),
+            None => locations,
+        })
+    }
+
+    /// The track in recording order for exports, read a page at a time so
+    /// long tracks aren't held in memory. Archived tracks are rehydrated
+    /// whole.
+    pub async fn export_walking_locations(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+    ) -> Result<LocalBoxStream<'static, Result<WalkingLocation, ServiceError>>, ServiceError>
+    where
+        R: 'static,
+    {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if !request.track_visible_to(viewer) {
+            return Err(ServiceError::Unauthorized("无权查看遛狗轨迹".to_owned()));
+        }
+        if self.archive.is_some() && request.track_archived_at.is_some() {
+            let locations = self
+                .walking_locations(walk_request_id, viewer, None, None, None)
+                .await?;
+            return Ok(stream::iter(locations.into_iter().map(Ok)).boxed_local());
+        }
+        let repository = self.repository.clone();
+        let walk_request_id = walk_request_id.to_owned();
+        let pages = stream::try_unfold(Some(1), move |page| {
+            let repository = repository.clone();
+            let query = WalkingLocationQuery {
+                walk_request_id: walk_request_id.clone(),
+                ..Default::default()
+            };
+            async move {
+                let Some(page) = page else {
+                    return Ok(None);
+                };
+                let locations = repository
+                    .query_walking_locations(query, Some(Pagination::new(page, EXPORT_PAGE_SIZE)))
+                    .await?;
+                let next = (locations.len() as i64 == EXPORT_PAGE_SIZE).then_some(page + 1);
+                Ok::<_, ServiceError>(Some((stream::iter(locations.into_iter().map(Ok)), next)))
+            }
+        });
+        Ok(pages.try_flatten().boxed_local())
+    }
+
+    /// The track simplified to `[longitude, latitude]` points within
+    /// `tolerance`, for drawing old walks without loading every point.
+    pub async fn walk_route(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+        tolerance: Meters,
+    ) -> Result<Vec<[f64; 2]>, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if !request.track_visible_to(viewer) {
+            return Err(ServiceError::Unauthorized("无权查看遛狗轨迹".to_owned()));
+        }
+        if self.archive.is_some() && request.track_archived_at.is_some() {
+            let locations = self
+                .walking_locations(walk_request_id, viewer, None, None, None)
+                .await?;
+            return Ok(geo::simplify(&locations, tolerance));
+        }
+        self.repository
+            .simplified_track(walk_request_id, tolerance)
+            .await
+    }
+
+    /// Distance, duration and speed of a finished walk, computed from its
+    /// track on first request and cached on the walk request.
+    /// The shareable card of a finished walk, for whoever may see its track.
+    pub async fn summary_card(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+        tenant_id: Option<&str>,
+    ) -> Result<SummaryCard, ServiceError> {
+        let summary = self
+            .walk_summary(walk_request_id, viewer, tenant_id)
+            .await?;
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        let (Some(started_at), Some(finished_at)) = (request.started_at, request.finished_at)
+        else {
+            return Err(ServiceError::Conflict("遛狗尚未结束".to_owned()));
+        };
+        let locations = self
+            .walking_locations(walk_request_id, viewer, None, None, None)
+            .await?;
+        Ok(SummaryCard::new(
+            &request.id,
+            &request.dogs,
+            started_at,
+            finished_at,
+            summary,
+            &locations,
+        ))
+    }
+
+    /// Draws route thumbnails over map tiles.
+    pub fn with_static_maps(mut self, maps: Arc<dyn StaticMapProvider>) -> Self {
+        self.static_maps = Some(maps);
+        self
+    }
+
+    /// A PNG of the route of a finished walk, for whoever may see its
+    /// track. Drawn here without a static maps service or when it fails.
+    pub async fn route_thumbnail(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+    ) -> Result<Vec<u8>, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if request.finished_at.is_none() {
+            return Err(ServiceError::Conflict("遛狗尚未结束".to_owned()));
+        }
+        let locations = self
+            .walking_locations(walk_request_id, viewer, None, None, None)
+            .await?;
+        let route = preview_route(&locations);
+        if let Some(maps) = &self.static_maps {
+            match maps
+                .render(&encode_polyline(&route), THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)
+                .await
+            {
+                Ok(png) => return Ok(png),
+                Err(e) => log::error!("failed to render the route of {}: {}", walk_request_id, e),
+            }
+        }
+        Ok(render_png(&route, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT)?)
+    }
+
+    /// Snaps tracks to the path network before distances are computed.
+    pub fn with_routing(mut self, routing: Arc<dyn RoutingProvider>) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
+    /// The track snapped to paths, or as recorded without a routing provider
+    /// or when matching fails.
+    async fn matched_track(&self, locations: Vec<WalkingLocation>) -> Vec<WalkingLocation> {
+        let Some(provider) = &self.routing else {
+            return locations;
+        };
+        match provider.match_track(&locations).await {
+            Ok(matched) => routing::snap(locations, matched),
+            Err(e) => {
+                log::warn!("failed to map-match track, using it as recorded: {}", e);
+                locations
+            }
+        }
+    }
+
+    /// How walk distances are computed for requests of tenants without a
+    /// choice of their own.
+    pub fn with_distance_strategy(mut self, strategy: DistanceStrategy) -> Self {
+        self.distance_strategy = strategy;
+        self
+    }
+
+    /// Map matching falls back to the raw track without a routing provider.
+    fn distance_calculator(&self, strategy: DistanceStrategy) -> Box<dyn DistanceCalculator> {
+        match (strategy, &self.routing) {
+            (DistanceStrategy::Raw, _) => Box::new(Haversine),
+            (DistanceStrategy::Smoothed, _) => Box::new(Smoothed),
+            (DistanceStrategy::MapMatched, Some(routing)) => {
+                Box::new(MapMatched::new(routing.clone()))
+            }
+            (DistanceStrategy::MapMatched, None) => Box::new(Haversine),
+        }
+    }
+
+    /// The summary is computed on first request with the distance strategy
+    /// of `tenant_id`, then kept.
+    pub async fn walk_summary(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+        tenant_id: Option<&str>,
+    ) -> Result<WalkSummary, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if !request.track_visible_to(viewer) {
+            return Err(ServiceError::Unauthorized("无权查看遛狗轨迹".to_owned()));
+        }
+        if let Some(summary) = request.summary {
+            return Ok(summary);
+        }
+        let (Some(started_at), Some(finished_at)) = (request.started_at, request.finished_at)
+        else {
+            return Err(ServiceError::Conflict("遛狗尚未结束".to_owned()));
+        };
+        let locations = self
+            .walking_locations(walk_request_id, viewer, None, None, None)
+            .await?;
+        let strategy = match tenant_id {
+            Some(id) => self
+                .repository
+                .get_tenant(id)
+                .await?
+                .and_then(|t| t.distance_strategy)
+                .unwrap_or(self.distance_strategy),
+            None => self.distance_strategy,
+        };
+        let summary = self
+            .compute_summary(
+                walk_request_id,
+                &locations,
+                started_at,
+                finished_at,
+                strategy,
+            )
+            .await;
+        self.repository
+            .update_walk_request(
+                walk_request_id,
+                WalkRequestUpdate {
+                    summary: Some(summary.clone()),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        Ok(summary)
+    }
+
+    async fn compute_summary(
+        &self,
+        walk_request_id: &str,
+        locations: &[WalkingLocation],
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+        strategy: DistanceStrategy,
+    ) -> WalkSummary {
+        let calculator = self.distance_calculator(strategy);
+        let summary = WalkSummary::compute(locations, started_at, finished_at);
+        match calculator.distance(locations).await {
+            Ok(distance) => summary.measured_with(calculator.strategy(), distance),
+            Err(e) => {
+                log::warn!(
+                    "failed to compute {:?} distance of {}, using the raw one: {}",
+                    calculator.strategy(),
+                    walk_request_id,
+                    e
+                );
+                summary
+            }
+        }
+    }
+
+    /// Registers a recomputation of the summaries in `scope`, which
+    /// `recompute_summaries` then runs.
+    pub fn start_recompute(
+        &self,
+        scope: RecomputeScope,
+    ) -> Result<RecomputeProgress, ServiceError> {
+        if scope.request_id.is_none() && (scope.from.is_none() || scope.to.is_none()) {
+            return Err(ServiceError::Validation(
+                "需指定代遛请求ID或起止时间".to_owned(),
+            ));
+        }
+        Ok(self.recompute_jobs.start(new_ulid(), scope))
+    }
+
+    pub fn recompute_progress(&self, id: &str) -> Result<RecomputeProgress, ServiceError> {
+        self.recompute_jobs
+            .get(id)
+            .ok_or_else(|| ServiceError::NotFound("重算任务不存在".to_owned()))
+    }
+
+    /// Recomputes the summary of every finished walk in the job's scope
+    /// with the distance strategy it was computed with, writing only those
+    /// which changed. Failures are counted and logged.
+    pub async fn recompute_summaries(&self, job_id: &str) -> Result<(), ServiceError> {
+        let scope = self.recompute_progress(job_id)?.scope;
+        let query = WalkRequestQuery {
+            id: scope.request_id.clone(),
+            created_at_gte: scope.from,
+            created_at_lte: scope.to,
+            finished_at_is_null: Some(false),
+            ..Default::default()
+        };
+        let total = self.repository.count_walk_requests(query.clone()).await?;
+        self.recompute_jobs.update(job_id, |p| p.total = total);
+        let mut page = 1;
+        loop {
+            let requests = self
+                .repository
+                .query_walk_requests(
+                    query.clone(),
+                    Some(SortBy {
+                        field: WalkRequest::created_at(),
+                        order: Order::Asc,
+                    }),
+                    Some(Pagination::new(page, RECOMPUTE_PAGE_SIZE)),
+                )
+                .await?;
+            if requests.is_empty() {
+                break;
+            }
+            for request in &requests {
+                let outcome = self.recompute_summary(request).await;
+                if let Err(e) = &outcome {
+                    log::error!("failed to recompute the summary of {}: {}", request.id, e);
+                }
+                self.recompute_jobs.update(job_id, |p| {
+                    p.processed += 1;
+                    match outcome {
+                        Ok(true) => p.updated += 1,
+                        Ok(false) => p.unchanged += 1,
+                        Err(_) => p.failed += 1,
+                    }
+                });
+            }
+            if let Some(progress) = self.recompute_jobs.get(job_id) {
+                log::info!(
+                    "recomputed {}/{} summaries of job {}",
+                    progress.processed,
+                    progress.total,
+                    job_id
+                );
+            }
+            page += 1;
+        }
+        self.recompute_jobs
+            .update(job_id, |p| p.finished_at = Some(Utc::now()));
+        Ok(())
+    }
+
+    /// Whether the summary changed and was written.
+    async fn recompute_summary(&self, request: &WalkRequest) -> Result<bool, ServiceError> {
+        let (Some(started_at), Some(finished_at)) = (request.started_at, request.finished_at)
+        else {
+            return Ok(false);
+        };
+        // The owner may always see the track.
+        let locations = self
+            .walking_locations(&request.id, request.created_by.as_deref(), None, None, None)
+            .await?;
+        let strategy = request
+            .summary
+            .as_ref()
+            .map_or(self.distance_strategy, |s| s.distance_strategy);
+        let summary = self
+            .compute_summary(&request.id, &locations, started_at, finished_at, strategy)
+            .await;
+        if request
+            .summary
+            .as_ref()
+            .map_or(false, |current| current.same_figures(&summary))
+        {
+            return Ok(false);
+        }
+        self.repository
+            .update_walk_request(
+                &request.id,
+                WalkRequestUpdate {
+                    summary: Some(summary),
+                    ..Default::default()
+                },
+            )
+            .await?;
+        Ok(true)
+    }
+
+    /// Counts a call of `api_key` to `endpoint` against the key's monthly
+    /// quota, `None` when the key isn't metered. Concurrent calls may go
+    /// a little over the quota since the check and the count are apart.
+    pub async fn meter_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+    ) -> Result<Option<QuotaStatus>, ServiceError> {
+        let Some(limit) = self.monthly_quotas.limit(api_key) else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        let month = usage::month_of(now);
+        let used: u64 = self
+            .repository
+            .query_api_usage(&month, Some(api_key))
+            .await?
+            .iter()
+            .map(|r| r.calls)
+            .sum();
+        let exceeded = used >= limit;
+        self.repository
+            .record_api_call(api_key, endpoint, &month, exceeded)
+            .await?;
+        Ok(Some(QuotaStatus {
+            limit,
+            used: if exceeded { used } else { used + 1 },
+            reset_at: usage::month_end(now),
+            exceeded,
+        }))
+    }
+
+    /// Usage records of `month`, the current one by default, for billing.
+    pub async fn api_usage(&self, month: Option<&str>) -> Result<Vec<UsageRecord>, ServiceError> {
+        let month = match month {
+            Some(month) if usage::valid_month(month) => month.to_owned(),
+            Some(_) => return Err(ServiceError::Validation("无效的月份".to_owned())),
+            None => usage::month_of(Utc::now()),
+        };
+        self.repository.query_api_usage(&month, None).await
+    }
+
+    fn secret_ring(&self, ring: &str) -> Result<&KeyRing, ServiceError> {
+        self.secrets
+            .get(ring)
+            .ok_or_else(|| ServiceError::NotFound("密钥环不存在".to_owned()))
+    }
+
+    /// Key ids of every ring, secrets are never listed.
+    pub fn secret_keys(&self) -> BTreeMap<&'static str, Vec<KeyInfo>> {
+        BTreeMap::from([
+            ("jwt", self.secrets.jwt.list()),
+            ("calendar", self.secrets.calendar.list()),
+            ("webhook", self.secrets.webhook.list()),
+        ])
+    }
+
+    pub fn rotate_secret(&self, ring: &str, rotation: KeyRotation) -> Result<(), ServiceError> {
+        let kid = rotation.kid.clone();
+        self.secret_ring(ring)?.rotate(rotation)?;
+        log::info!("rotated {} key ring to key {}", ring, kid);
+        Ok(())
+    }
+
+    pub fn retire_secret(&self, ring: &str, kid: &str) -> Result<(), ServiceError> {
+        if !self.secret_ring(ring)?.retire(kid)? {
+            return Err(ServiceError::NotFound("密钥不存在".to_owned()));
+        }
+        log::info!("retired key {} of {} key ring", kid, ring);
+        Ok(())
+    }
+
+    fn backfill(&self, name: &str) -> Result<Arc<dyn Backfill>, ServiceError> {
+        self.backfills
+            .get(name)
+            .ok_or_else(|| ServiceError::NotFound("回填任务不存在".to_owned()))
+    }
+
+    fn backfill_query(checkpoint: Option<DateTime<Utc>>) -> WalkRequestQuery {
+        WalkRequestQuery {
+            created_at_gte: checkpoint,
+            include_deleted: true,
+            ..Default::default()
+        }
+    }
+
+    /// Every known backfill with its last run.
+    pub async fn backfills(&self) -> Result<Vec<BackfillDefinition>, ServiceError> {
+        let mut definitions = Vec::new();
+        for backfill in self.backfills.all() {
+            definitions.push(BackfillDefinition {
+                name: backfill.name().to_owned(),
+                description: backfill.description().to_owned(),
+                run: self.repository.get_backfill_run(backfill.name()).await?,
+            });
+        }
+        Ok(definitions)
+    }
+
+    pub async fn backfill_run(&self, name: &str) -> Result<BackfillRun, ServiceError> {
+        self.backfill(name)?;
+        self.repository
+            .get_backfill_run(name)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("回填任务尚未运行".to_owned()))
+    }
+
+    /// Counts the walk requests the backfill would change, without pausing
+    /// between batches since nothing is written.
+    pub async fn dry_run_backfill(&self, name: &str) -> Result<BackfillDryRun, ServiceError> {
+        let backfill = self.backfill(name)?;
+        let query = Self::backfill_query(None);
+        let mut result = BackfillDryRun {
+            name: name.to_owned(),
+            total: self.repository.count_walk_requests(query.clone()).await?,
+            would_change: 0,
+        };
+        let mut page = 1;
+        loop {
+            let requests = self
+                .repository
+                .query_walk_requests(
+                    query.clone(),
+                    Some(SortBy {
+                        field: WalkRequest::created_at(),
+                        order: Order::Asc,
+                    }),
+                    Some(Pagination::new(page, self.backfills.batch_size)),
+                )
+                .await?;
+            if requests.is_empty() {
+                break;
+            }
+            result.would_change += requests
+                .iter()
+                .filter(|r| backfill.migrate(r).is_some())
+                .count() as u64;
+            page += 1;
+        }
+        Ok(result)
+    }
+
+    /// Takes the backfill for a run, resuming its unfinished last run
+    /// unless `restart` is set. `run_backfill` then runs it.
+    pub async fn start_backfill(
+        &self,
+        name: &str,
+        restart: bool,
+    ) -> Result<BackfillRun, ServiceError> {
+        self.backfill(name)?;
+        if !self.backfills.claim(name) {
+            return Err(ServiceError::Conflict("回填任务正在运行".to_owned()));
+        }
+        let run = self.prepare_backfill_run(name, restart).await;
+        if run.is_err() {
+            self.backfills.release(name);
+        }
+        run
+    }
+
+    async fn prepare_backfill_run(
+        &self,
+        name: &str,
+        restart: bool,
+    ) -> Result<BackfillRun, ServiceError> {
+        if let Some(run) = self.repository.get_backfill_run(name).await? {
+            if !restart && run.finished_at.is_none() {
+                return Ok(run);
+            }
+        }
+        let mut run = BackfillRun::new(name);
+        run.total = self
+            .repository
+            .count_walk_requests(Self::backfill_query(None))
+            .await?;
+        self.repository.save_backfill_run(&run).await?;
+        Ok(run)
+    }
+
+    /// Migrates walk requests in creation order, batch by batch, saving the
+    /// run after each batch and pausing in between. Failures are counted
+    /// and logged. Releases the backfill once done.
+    pub async fn run_backfill(&self, name: &str) -> Result<BackfillRun, ServiceError> {
+        let result = self.migrate_batches(name).await;
+        self.backfills.release(name);
+        result
+    }
+
+    async fn migrate_batches(&self, name: &str) -> Result<BackfillRun, ServiceError> {
+        let backfill = self.backfill(name)?;
+        let mut run = self
+            .repository
+            .get_backfill_run(name)
+            .await?
+            .unwrap_or_else(|| BackfillRun::new(name));
+        // Requests created at the checkpoint are gone over again, which
+        // migrations must tolerate.
+        let query = Self::backfill_query(run.checkpoint);
+        let mut page = 1;
+        loop {
+            let requests = self
+                .repository
+                .query_walk_requests(
+                    query.clone(),
+                    Some(SortBy {
+                        field: WalkRequest::created_at(),
+                        order: Order::Asc,
+                    }),
+                    Some(Pagination::new(page, self.backfills.batch_size)),
+                )
+                .await?;
+            if requests.is_empty() {
+                break;
+            }
+            for request in &requests {
+                run.processed += 1;
+                run.checkpoint = request.created_at.or(run.checkpoint);
+                let Some(update) = backfill.migrate(request) else {
+                    continue;
+                };
+                match self
+                    .repository
+                    .update_walk_request(&request.id, update)
+                    .await
+                {
+                    Ok(_) => run.changed += 1,
+                    Err(e) => {
+                        log::error!("backfill {} failed on {}: {}", name, request.id, e);
+                        run.failed += 1;
+                    }
+                }
+            }
+            self.repository.save_backfill_run(&run).await?;
+            log::info!(
+                "backfill {} processed {}/{} walk requests",
+                name,
+                run.processed,
+                run.total
+            );
+            page += 1;
+            actix_web::rt::time::sleep(self.backfills.pause).await;
+        }
+        run.finished_at = Some(Utc::now());
+        self.repository.save_backfill_run(&run).await?;
+        Ok(run)
+    }
+
+    /// Elapsed time, distance so far and current speed of a started walk.
+    pub async fn walk_progress(
+        &self,
+        walk_request_id: &str,
+        viewer: Option<&str>,
+    ) -> Result<WalkProgress, ServiceError> {
+        let request = self.repository.get_walk_request(walk_request_id).await?;
+        if !request.track_visible_to(viewer) {
+            return Err(ServiceError::Unauthorized("无权查看遛狗轨迹".to_owned()));
+        }
+        let Some(started_at) = request.started_at else {
+            return Err(ServiceError::Conflict("遛狗尚未开始".to_owned()));
+        };
+        let locations = self
+            .walking_locations(walk_request_id, viewer, Some(started_at), None, None)
+            .await?;
+        Ok(WalkProgress::compute(
+            &locations,
+            started_at,
+            request.finished_at,
+            request.should_end_before,
+            Utc::now(),
+        ))
+    }
+
+    /// Finishes the walk, walks finished offline pass `occurred_at`, see
+    /// `ClientTime::settle`.
+    pub async fn finish_walk(
+        &self,
+        request_id: &str,
+        user_id: &str,
+        occurred_at: Option<DateTime<Utc>>,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        let started_at = Self::walker_started_at(&request, user_id);
+        let time = ClientTime::settle(occurred_at, started_at, Utc::now())?;
+        if request.walked_by(user_id) {
+            self.verify_finish(&request, user_id, time.occurred_at)
+                .await?;
+        }
+        if request.is_pool() {
+            return self.finish_pool_walk(request_id, user_id, time).await;
+        }
+        match self
+            .update_request(
+                WalkRequestQuery {
+                    id: Some(request_id.to_owned()),
+                    accepted_by: Some(user_id.to_owned()),
+                    started_at_is_null: Some(false),
+                    finished_at_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    ..Default::default()
+                },
+                WalkRequestUpdate {
+                    finished_at: Some(time.occurred_at),
+                    finish_received_at: Some(time.received_at),
+                    clock_skew_detected_at: time.skew_detected_at(),
+                    ..Default::default()
+                },
+                |request| {
+                    Publications::event(WalkRequestEventKind::Finished, request_id, user_id)
+                        .notify_owner(request, NotificationKind::Finished)
+                },
+            )
+            .await
+        {
+            Ok(request) => {
+                self.walk_finished(&request).await;
+                Ok(request)
+            }
+            Err(ServiceError::NotFound(_)) => Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Finished,
+                    "代遛请求不存在",
+                )
+                .await),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Finishes the walker's part of a pool walk, the request is finished
+    /// with the last walker.
+    async fn finish_pool_walk(
+        &self,
+        request_id: &str,
+        user_id: &str,
+        time: ClientTime,
+    ) -> Result<WalkRequest, ServiceError> {
+        let request = self
+            .update_pool(
+                request_id,
+                WalkRequestStatus::Finished,
+                |request, _| {
+                    if request.status != WalkRequestStatus::Started {
+                        return None;
+                    }
+                    let mut walkers = request.pool_walkers.clone();
+                    let walker = walkers.iter_mut().find(|w| {
+                        w.walker_id == user_id && w.started_at.is_some() && w.finished_at.is_none()
+                    })?;
+                    walker.finished_at = Some(time.occurred_at);
+                    let last = walkers.iter().all(|w| w.finished_at.is_some());
+                    Some(WalkRequestUpdate {
+                        finished_at: last.then_some(time.occurred_at),
+                        finish_received_at: last.then_some(time.received_at),
+                        clock_skew_detected_at: time.skew_detected_at(),
+                        pool_walkers: Some(walkers),
+                        ..Default::default()
+                    })
+                },
+                |request| {
+                    let publications =
+                        Publications::event(WalkRequestEventKind::Finished, request_id, user_id);
+                    if request.finished_at.is_some() {
+                        publications.notify_owner(request, NotificationKind::Finished)
+                    } else {
+                        publications
+                    }
+                },
+            )
+            .await?;
+        if request.finished_at.is_some() {
+            self.walk_finished(&request).await;
+        }
+        Ok(request)
+    }
+
+    /// Exports and charges for a walk once it finished, the owner was
+    /// notified with the transition.
+    async fn walk_finished(&self, request: &WalkRequest) {
+        if let Err(e) = self.queue_fitness_exports(request).await {
+            log::error!("failed to queue fitness exports of {}: {}", request.id, e);
+        }
+        if let Err(e) = self.charge(request).await {
+            log::error!("failed to charge for {}: {}", request.id, e);
+        }
+        for walker in request.walkers() {
+            let update = ReputationUpdate {
+                completed_walks: 1,
+                ..Default::default()
+            };
+            if let Err(e) = self.repository.update_reputation(walker, update).await {
+                log::error!("failed to count the walk of {}: {}", walker, e);
+            }
+        }
+    }
+
+    /// Records the charge of a finished priced walk and, with a gateway,
+    /// charges the owner, issuing the receipt once charged. A declined
+    /// charge is kept as failed.
+    async fn charge(&self, request: &WalkRequest) -> Result<(), ServiceError> {
+        let (Some(price), Some(owner)) = (&request.price, &request.created_by) else {
+            return Ok(());
+        };
+        // Owners payments aren't rolled out to yet walk for free.
+        if !self.feature_enabled(Feature::Payments, owner).await? {
+            return Ok(());
+        }
+        let mut payment = Payment::new(
+            &request.id,
+            owner,
+            request.accepted_by.as_deref(),
+            price.clone(),
+        );
+        self.repository.save_payment(&payment).await?;
+        let Some(gateway) = &self.gateway else {
+            return Ok(());
+        };
+        let charge_id = match gateway.charge(&payment).await {
+            Ok(charge_id) => {
+                payment.charged(charge_id.clone());
+                Some(charge_id)
+            }
+            Err(e) => {
+                payment.failed(&e.to_string());
+                None
+            }
+        };
+        self.repository.save_payment(&payment).await?;
+        match charge_id {
+            Some(charge_id) => self.issue_receipt(request, &payment, &charge_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Saves the receipt of a charged walk and the payouts of its walkers,
+    /// settled in the default currency at the rate of the moment, then
+    /// mails the receipt to the owner.
+    async fn issue_receipt(
+        &self,
+        request: &WalkRequest,
+        payment: &Payment,
+        charge_id: &str,
+    ) -> Result<(), ServiceError> {
+        let rate = self
+            .rates
+            .rate(&payment.amount.currency, self.currencies.default_currency())
+            .await?;
+        let receipt = Receipt::new(payment, charge_id, rate);
+        self.repository.save_receipt(&receipt).await?;
+        for payout in receipt.payouts(&request.walkers()) {
+            self.repository.save_payout(&payout).await?;
+        }
+        if let Err(e) = self.mail_receipt(&receipt, request).await {
+            log::error!(
+                "failed to mail the receipt of {}: {}",
+                receipt.request_id,
+                e
+            );
+        }
+        Ok(())
+    }
+
+    /// Sends the receipt to the owner's email address, in their language,
+    /// when the user service knows it.
+    async fn mail_receipt(
+        &self,
+        receipt: &Receipt,
+        request: &WalkRequest,
+    ) -> Result<(), ServiceError> {
+        let (Some(users), Some(_)) = (&self.users, &self.emailer) else {
+            return Ok(());
+        };
+        let contact = users.contact(&receipt.owner_id).await?;
+        let Some(email) = contact.email else {
+            return Ok(());
+        };
+        let owner = vec![receipt.owner_id.clone()];
+        let name = users
+            .profiles(&owner)
+            .await?
+            .remove(&receipt.owner_id)
+            .map_or_else(|| receipt.owner_id.clone(), |p| p.nickname);
+        let locale = contact.locale.as_deref().unwrap_or(DEFAULT_LOCALE);
+        self.send_email(
+            &email,
+            EmailTemplate::Receipt,
+            locale,
+            &ReceiptEmail::new(receipt, request, &name, locale),
+        )
+        .await
+    }
+
+    /// The receipt of a walk, for its owner.
+    pub async fn receipt(&self, request_id: &str, owner_id: &str) -> Result<Receipt, ServiceError> {
+        let receipt = self
+            .repository
+            .get_receipt(request_id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("收据不存在".to_owned()))?;
+        if receipt.owner_id != owner_id {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        Ok(receipt)
+    }
+
+    /// The owner's spending in the current UTC month, in the default
+    /// currency, with their cap if they set one.
+    pub async fn monthly_spending(&self, owner_id: &str) -> Result<MonthlySpending, ServiceError> {
+        let (from, to) = month_of(Utc::now());
+        let receipts = self.repository.query_receipts(owner_id, from, to).await?;
+        let cap = self.repository.get_spending_cap(owner_id).await?;
+        Ok(MonthlySpending::new(
+            from.date_naive(),
+            self.currencies.default_currency(),
+            &receipts,
+            cap.map(|c| c.monthly_limit),
+        ))
+    }
+
+    /// Holds back a quote which takes the owner over their cap, at today's
+    /// rate, unless they confirmed it.
+    async fn ensure_within_cap(&self, owner_id: &str, quote: &Money) -> Result<(), ServiceError> {
+        if self.over_cap_confirmed {
+            return Ok(());
+        }
+        let spending = self.monthly_spending(owner_id).await?;
+        if spending.cap.is_none() {
+            return Ok(());
+        }
+        let rate = self
+            .rates
+            .rate(&quote.currency, &spending.spent.currency)
+            .await?;
+        if spending.exceeded_by(&quote.convert(&rate)) {
+            return Err(ServiceError::SpendingCapExceeded(spending));
+        }
+        Ok(())
+    }
+
+    /// Caps what the owner spends on walks a month, in the default currency.
+    pub async fn set_spending_cap(
+        &self,
+        owner_id: &str,
+        monthly_limit: Money,
+    ) -> Result<SpendingCap, ServiceError> {
+        if monthly_limit.currency != self.currencies.default_currency() {
+            return Err(ServiceError::Validation(
+                "消费上限须使用默认币种".to_owned(),
+            ));
+        }
+        if monthly_limit.minor_units <= 0 {
+            return Err(ServiceError::Validation("消费上限须大于0".to_owned()));
+        }
+        let cap = SpendingCap {
+            owner_id: owner_id.to_owned(),
+            monthly_limit,
+            updated_at: Utc::now(),
+        };
+        self.repository.save_spending_cap(&cap).await?;
+        Ok(cap)
+    }
+
+    pub async fn remove_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError> {
+        self.repository.delete_spending_cap(owner_id).await
+    }
+
+    /// The walker's payouts created in `[from, to)`, the last
+    /// `DEFAULT_REPORT_DAYS` by default.
+    pub async fn payouts(
+        &self,
+        walker_id: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Payout>, ServiceError> {
+        let to = to.unwrap_or_else(Utc::now);
+        let from = from.unwrap_or(to - chrono::Duration::days(DEFAULT_REPORT_DAYS));
+        if to <= from || to - from > chrono::Duration::days(MAX_REPORT_DAYS) {
+            return Err(ServiceError::Validation(format!(
+                "时间范围需大于0且不超过{}天",
+                MAX_REPORT_DAYS
+            )));
+        }
+        self.repository.query_payouts(walker_id, from, to).await
+    }
+
+    /// The charge of a walk, for its owner or walker.
+    pub async fn payment(&self, request_id: &str, user_id: &str) -> Result<Payment, ServiceError> {
+        let request = self.repository.get_walk_request(request_id).await?;
+        let user_id = Some(user_id.to_owned());
+        if request.created_by != user_id && request.accepted_by != user_id {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        self.repository
+            .get_payment(request_id)
+            .await?
+            .ok_or_else(|| ServiceError::NotFound("付款记录不存在".to_owned()))
+    }
+
+    /// Platforms walkers can export their finished walks to.
+    pub fn with_fitness_providers(mut self, providers: Vec<Arc<dyn FitnessProvider>>) -> Self {
+        self.fitness = providers;
+        self
+    }
+
+    fn fitness_provider(&self, name: &str) -> Result<&Arc<dyn FitnessProvider>, ServiceError> {
+        self.fitness
+            .iter()
+            .find(|p| p.name() == name)
+            .ok_or_else(|| ServiceError::NotFound(format!("不支持的健身平台: {}", name)))
+    }
+
+    /// Providers the user connected.
+    pub async fn fitness_connections(&self, user_id: &str) -> Result<Vec<String>, ServiceError> {
+        Ok(self
+            .repository
+            .query_fitness_tokens(user_id)
+            .await?
+            .into_iter()
+            .map(|t| t.provider)
+            .collect())
+    }
+
+    /// Completes the OAuth consent the user gave `provider`.
+    pub async fn connect_fitness(
+        &self,
+        user_id: &str,
+        provider: &str,
+        code: &str,
+    ) -> Result<(), ServiceError> {
+        let token = self
+            .fitness_provider(provider)?
+            .authorize(user_id, code)
+            .await
+            .map_err(|e| ServiceError::Validation(format!("授权失败: {}", e)))?;
+        self.repository.upsert_fitness_token(token).await
+    }
+
+    pub async fn disconnect_fitness(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<(), ServiceError> {
+        if self
+            .repository
+            .delete_fitness_token(user_id, provider)
+            .await?
+            == 0
+        {
+            return Err(ServiceError::NotFound("未连接该健身平台".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Queues the export of a finished walk to each provider the walker
+    /// connected, unless the owner keeps the track to themselves.
+    async fn queue_fitness_exports(&self, request: &WalkRequest) -> Result<(), ServiceError> {
+        let Some(walker) = request.accepted_by.as_deref() else {
+            return Ok(());
+        };
+        if self.fitness.is_empty() || !request.track_visible_to(Some(walker)) {
+            return Ok(());
+        }
+        for token in self.repository.query_fitness_tokens(walker).await? {
+            self.repository
+                .save_fitness_export(&FitnessExport::new(&request.id, walker, &token.provider))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Attempts the exports which are due, returning how many were uploaded.
+    /// Faults are retried later, anything else gives the export up.
+    pub async fn run_fitness_exports(&self) -> Result<u64, ServiceError> {
+        let mut exported = 0;
+        for mut export in self.repository.due_fitness_exports(Utc::now()).await? {
+            match self.export_walk(&export).await {
+                Ok(external_id) => {
+                    export.exported(external_id);
+                    exported += 1;
+                }
+                Err(ServiceError::Internal(e)) => export.failed(&e.to_string()),
+                Err(e) => export.abandon(&e.to_string()),
+            }
+            self.repository.save_fitness_export(&export).await?;
+        }
+        Ok(exported)
+    }
+
+    async fn export_walk(&self, export: &FitnessExport) -> Result<String, ServiceError> {
+        let provider = self.fitness_provider(&export.provider)?;
+        let Some(mut token) = self
+            .repository
+            .query_fitness_tokens(&export.user_id)
+            .await?
+            .into_iter()
+            .find(|t| t.provider == export.provider)
+        else {
+            return Err(ServiceError::NotFound("未连接该健身平台".to_owned()));
+        };
+        if token.expired(Utc::now()) {
+            token = provider.refresh(&token).await?;
+            self.repository.upsert_fitness_token(token.clone()).await?;
+        }
+        let request = self.repository.get_walk_request(&export.request_id).await?;
+        let (Some(started_at), Some(finished_at)) = (request.started_at, request.finished_at)
+        else {
+            return Err(ServiceError::Conflict("遛狗尚未结束".to_owned()));
+        };
+        let locations = self
+            .walking_locations(&export.request_id, Some(&export.user_id), None, None, None)
+            .await?;
+        let locations = self.matched_track(locations).await;
+        let activity = FitnessActivity {
+            request_id: export.request_id.clone(),
+            started_at,
+            finished_at,
+            summary: WalkSummary::compute(&locations, started_at, finished_at),
+            locations,
+        };
+        Ok(provider.upload(&token, &activity).await?)
+    }
+
+    /// How far ahead of each occurrence schedules create its walk request.
+    pub fn with_schedule_horizon(mut self, horizon: chrono::Duration) -> Self {
+        self.schedule_horizon = horizon;
+        self
+    }
+
+    /// Creates a recurring walk of the owner and the walk requests of its
+    /// occurrences within the horizon.
+    pub async fn create_walk_schedule(
+        &self,
+        owner_id: &str,
+        create: WalkScheduleCreate,
+    ) -> Result<WalkSchedule, ServiceError> {
+        if create.start_window_minutes <= 0 || create.duration_minutes <= 0 {
+            return Err(ServiceError::Validation(
+                "时间窗口和时长必须大于0".to_owned(),
+            ));
+        }
+        let mut schedule = WalkSchedule::new(new_ulid(), owner_id, create);
+        schedule
+            .recurrence()
+            .map_err(|e| ServiceError::Validation(e.to_string()))?;
+        schedule.request_at(Utc::now()).validate()?;
+        self.repository.save_walk_schedule(&schedule).await?;
+        self.materialize_schedule(&mut schedule).await?;
+        Ok(schedule)
+    }
+
+    pub async fn walk_schedules(&self, owner_id: &str) -> Result<Vec<WalkSchedule>, ServiceError> {
+        self.repository.query_walk_schedules(Some(owner_id)).await
+    }
+
+    /// Stops creating walk requests for the schedule, those already created
+    /// are left to the owner.
+    pub async fn cancel_walk_schedule(&self, id: &str, owner_id: &str) -> Result<(), ServiceError> {
+        let Some(mut schedule) = self.repository.get_walk_schedule(id).await? else {
+            return Err(ServiceError::NotFound("重复代遛计划不存在".to_owned()));
+        };
+        if schedule.owner_id != owner_id {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        if schedule.canceled_at.is_some() {
+            return Ok(());
+        }
+        schedule.canceled_at = Some(Utc::now());
+        self.repository.save_walk_schedule(&schedule).await
+    }
+
+    /// Checks a template the way the requests posted from it will be checked.
+    fn validate_template(&self, template: &WalkRequestTemplate) -> Result<(), ServiceError> {
+        if template.name.is_empty() || template.name.chars().count() > MAX_TEMPLATE_NAME_CHARS {
+            return Err(ServiceError::Validation(format!(
+                "模板名称不能为空且不能超过{}个字符",
+                MAX_TEMPLATE_NAME_CHARS
+            )));
+        }
+        parse_timezone(&template.timezone).map_err(|e| ServiceError::Validation(e.to_string()))?;
+        template.request_at(Utc::now()).validate()
+    }
+
+    async fn owned_template(
+        &self,
+        id: &str,
+        owner_id: &str,
+    ) -> Result<WalkRequestTemplate, ServiceError> {
+        let Some(template) = self.repository.get_walk_request_template(id).await? else {
+            return Err(ServiceError::NotFound("代遛模板不存在".to_owned()));
+        };
+        if template.owner_id != owner_id {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        Ok(template)
+    }
+
+    pub async fn create_walk_request_template(
+        &self,
+        owner_id: &str,
+        create: WalkRequestTemplateCreate,
+    ) -> Result<WalkRequestTemplate, ServiceError> {
+        let template = WalkRequestTemplate::new(new_ulid(), owner_id, create);
+        self.validate_template(&template)?;
+        let saved = self
+            .repository
+            .query_walk_request_templates(owner_id)
+            .await?
+            .len();
+        if saved >= MAX_TEMPLATES_PER_OWNER {
+            return Err(ServiceError::Conflict(format!(
+                "最多只能保存{}个代遛模板",
+                MAX_TEMPLATES_PER_OWNER
+            )));
+        }
+        self.repository
+            .save_walk_request_template(&template)
+            .await?;
+        Ok(template)
+    }
+
+    pub async fn walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError> {
+        self.repository.query_walk_request_templates(owner_id).await
+    }
+
+    pub async fn walk_request_template(
+        &self,
+        id: &str,
+        owner_id: &str,
+    ) -> Result<WalkRequestTemplate, ServiceError> {
+        self.owned_template(id, owner_id).await
+    }
+
+    /// Replaces the content of a template, keeping its id and creation time.
+    pub async fn update_walk_request_template(
+        &self,
+        id: &str,
+        owner_id: &str,
+        create: WalkRequestTemplateCreate,
+    ) -> Result<WalkRequestTemplate, ServiceError> {
+        let current = self.owned_template(id, owner_id).await?;
+        let template = WalkRequestTemplate {
+            created_at: current.created_at,
+            ..WalkRequestTemplate::new(current.id, owner_id, create)
+        };
+        self.validate_template(&template)?;
+        self.repository
+            .save_walk_request_template(&template)
+            .await?;
+        Ok(template)
+    }
+
+    pub async fn delete_walk_request_template(
+        &self,
+        id: &str,
+        owner_id: &str,
+    ) -> Result<(), ServiceError> {
+        self.owned_template(id, owner_id).await?;
+        self.repository.delete_walk_request_template(id).await
+    }
+
+    async fn owned_saved_search(
+        &self,
+        id: &str,
+        walker_id: &str,
+    ) -> Result<SavedSearch, ServiceError> {
+        let Some(search) = self.repository.get_saved_search(id).await? else {
+            return Err(ServiceError::NotFound("保存的搜索不存在".to_owned()));
+        };
+        if search.walker_id != walker_id {
+            return Err(ServiceError::Unauthorized("无权限".to_owned()));
+        }
+        Ok(search)
+    }
+
+    pub async fn create_saved_search(
+        &self,
+        walker_id: &str,
+        create: SavedSearchCreate,
+    ) -> Result<SavedSearch, ServiceError> {
+        create.validate()?;
+        self.check_search_radius(create.radius)?;
+        let saved = self.repository.query_saved_searches(walker_id).await?.len();
+        if saved >= MAX_SAVED_SEARCHES_PER_WALKER {
+            return Err(ServiceError::Conflict(format!(
+                "最多只能保存{}个搜索",
+                MAX_SAVED_SEARCHES_PER_WALKER
+            )));
+        }
+        let search = SavedSearch::new(new_ulid(), walker_id, create);
+        self.repository.save_saved_search(&search).await?;
+        Ok(search)
+    }
+
+    pub async fn saved_searches(&self, walker_id: &str) -> Result<Vec<SavedSearch>, ServiceError> {
+        self.repository.query_saved_searches(walker_id).await
+    }
+
+    pub async fn saved_search(
+        &self,
+        id: &str,
+        walker_id: &str,
+    ) -> Result<SavedSearch, ServiceError> {
+        self.owned_saved_search(id, walker_id).await
+    }
+
+    /// Replaces a saved search, keeping its id and creation time.
+    pub async fn update_saved_search(
+        &self,
+        id: &str,
+        walker_id: &str,
+        create: SavedSearchCreate,
+    ) -> Result<SavedSearch, ServiceError> {
+        create.validate()?;
+        self.check_search_radius(create.radius)?;
+        let current = self.owned_saved_search(id, walker_id).await?;
+        let search = SavedSearch {
+            created_at: current.created_at,
+            ..SavedSearch::new(current.id, walker_id, create)
+        };
+        self.repository.save_saved_search(&search).await?;
+        Ok(search)
+    }
+
+    pub async fn delete_saved_search(&self, id: &str, walker_id: &str) -> Result<(), ServiceError> {
+        self.owned_saved_search(id, walker_id).await?;
+        self.repository.delete_saved_search(id).await
+    }
+
+    /// Runs a saved search as the nearby search it stands for.
+    pub async fn run_saved_search(
+        &self,
+        id: &str,
+        walker_id: &str,
+        pagination: Pagination,
+        cursor: Option<&str>,
+    ) -> Result<Paged<WalkRequest>, ServiceError> {
+        let search = self.owned_saved_search(id, walker_id).await?;
+        self.nearby_walk_requests(
+            search.latitude,
+            search.longitude,
+            search.radius,
+            Some(walker_id),
+            search.filter(),
+            pagination,
+            cursor,
+        )
+        .await
+    }
+
+    /// Posts a walk request from the template, its windows counted from now.
+    pub async fn post_walk_request_template(
+        &self,
+        id: &str,
+        owner_id: &str,
+    ) -> Result<String, ServiceError> {
+        let template = self.owned_template(id, owner_id).await?;
+        let request = template.request_at(Utc::now());
+        request.validate()?;
+        self.create_walk_request(request).await
+    }
+
+    /// Creates the walk requests of the occurrences which entered the horizon
+    /// since the schedule was last materialized, returning how many were
+    /// created. Occurrences already past are skipped.
+    async fn materialize_schedule(&self, schedule: &mut WalkSchedule) -> Result<u64, ServiceError> {
+        let (recurrence, tz) = schedule.recurrence()?;
+        let now = Utc::now();
+        let until = now + self.schedule_horizon;
+        let mut created = 0;
+        for time in recurrence.occurrences(tz, schedule.materialized_until.max(now), until) {
+            self.create_walk_request(schedule.request_at(time)).await?;
+            // Saved per occurrence so a failure doesn't create it twice.
+            schedule.materialized_until = time;
+            self.repository.save_walk_schedule(schedule).await?;
+            created += 1;
+        }
+        schedule.materialized_until = until;
+        if recurrence.ended(tz, until) {
+            schedule.canceled_at = Some(now);
+        }
+        self.repository.save_walk_schedule(schedule).await?;
+        Ok(created)
+    }
+
+    /// Materializes every active schedule, returning how many walk requests
+    /// were created. A failing schedule doesn't hold the others back.
+    pub async fn materialize_schedules(&self) -> Result<u64, ServiceError> {
+        let mut created = 0;
+        for mut schedule in self.repository.query_walk_schedules(None).await? {
+            match self.materialize_schedule(&mut schedule).await {
+                Ok(n) => created += n,
+                Err(e) => log::error!("failed to materialize schedule {}: {}", schedule.id, e),
+            }
+        }
+        Ok(created)
+    }
+
+    pub async fn holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError> {
+        if let Some(holidays) = self.holidays.cached(region) {
+            return Ok(holidays);
+        }
+        let overrides = self.repository.query_holidays(region).await?;
+        Ok(self.holidays.store(region, overrides))
+    }
+
+    pub async fn holiday_on(
+        &self,
+        region: &str,
+        date: NaiveDate,
+    ) -> Result<Option<Holiday>, ServiceError> {
+        Ok(self
+            .holidays(region)
+            .await?
+            .into_iter()
+            .find(|h| h.date == date))
+    }
+
+    /// Multiplier the pricing module applies to walks in `region` on `date`.
+    pub async fn holiday_price_multiplier(
+        &self,
+        region: &str,
+        date: NaiveDate,
+    ) -> Result<f64, ServiceError> {
+        Ok(self
+            .holiday_on(region, date)
+            .await?
+            .map_or(1.0, |h| h.price_multiplier))
+    }
 
-#[derive(Debug, Clone)]
-pub struct Service<R>
-where
-    R: Repository + Clone,
-{
-    repository: R,
-}
+    /// `base` adjusted by the holiday multiplier in `region` on `date`.
+    pub async fn holiday_price(
+        &self,
+        region: &str,
+        date: NaiveDate,
+        base: &Money,
+    ) -> Result<Money, ServiceError> {
+        Ok(base.scale(self.holiday_price_multiplier(region, date).await?))
+    }
 
-impl<R> Service<R>
-where
-    R: Repository + Clone,
-{
-    pub fn new(repository: R) -> Self {
-        Self { repository }
+    /// Adjusted operating hours for the matching module, `None` means regular hours.
+    pub async fn holiday_operating_hours(
+        &self,
+        region: &str,
+        date: NaiveDate,
+    ) -> Result<Option<(Option<NaiveTime>, Option<NaiveTime>)>, ServiceError> {
+        Ok(self
+            .holiday_on(region, date)
+            .await?
+            .map(|h| (h.opens_at, h.closes_at)))
     }
 
-    pub async fn create_walk_request(&self, request: WalkRequestCreate) -> Result<String, Error> {
-        // if request.should_start_after >= request.should_end_before {
-        //     return Err(Error::msg("开始时间范围起点不得大于等于终点"));
-        // }
-        // if request.should_end_after >= request.should_end_before {
-        //     return Err(Error::msg("结束时间范围起点不得大于等于终点"));
-        // }
-        // if request.should_start_after >= request.should_end_before {
-        //     return Err(Error::msg("结束时间不得早于开始时间"));
-        // }
-        self.repository.create_walk_request(request).await
+    pub async fn set_holiday(&self, holiday: Holiday) -> Result<(), ServiceError> {
+        let region = holiday.region.clone();
+        self.repository.upsert_holiday(holiday).await?;
+        self.holidays.invalidate(&region);
+        Ok(())
     }
 
-    pub async fn nearby_walk_requests(
+    pub async fn remove_holiday(&self, region: &str, date: NaiveDate) -> Result<(), ServiceError> {
+        let deleted = self.repository.delete_holiday(region, date).await?;
+        self.holidays.invalidate(region);
+        if deleted == 0 {
+            return Err(ServiceError::NotFound("节假日不存在".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Applies `update` to the requests `query` matches. Unless it's a dry
+    /// run, `expected_ids` must be the `affected_ids` of the dry run, and
+    /// only those requests are updated, so that what was previewed is what
+    /// changes.
+    pub async fn bulk_update_walk_requests(
         &self,
-        latitute: f64,
-        longitude: f64,
-        radius: f64,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+        dry_run: bool,
+        expected_ids: Option<Vec<String>>,
+    ) -> Result<BulkUpdateReport, ServiceError> {
+        // One more than allowed tells whether there are too many.
+        let matched = self
+            .repository
+            .query_walk_requests(
+                query.clone(),
+                None,
+                Some(Pagination::new(1, MAX_BULK_UPDATE + 1)),
+            )
+            .await?;
+        if matched.len() as i64 > MAX_BULK_UPDATE {
+            return Err(ServiceError::Validation(format!(
+                "批量更新最多匹配{}个请求",
+                MAX_BULK_UPDATE
+            )));
+        }
+        let diff_sample = matched
+            .iter()
+            .take(DIFF_SAMPLE_SIZE)
+            .map(|request| diff(request, &update))
+            .collect();
+        let affected_ids: Vec<String> = matched.into_iter().map(|r| r.id).collect();
+        let modified = if dry_run {
+            None
+        } else {
+            let expected_ids = expected_ids.ok_or_else(|| {
+                ServiceError::Validation("请先预览并提交预览的请求列表".to_owned())
+            })?;
+            let previewed: HashSet<&String> = expected_ids.iter().collect();
+            if previewed != affected_ids.iter().collect() {
+                return Err(ServiceError::Conflict(
+                    "匹配的请求已变化，请重新预览".to_owned(),
+                ));
+            }
+            let query = WalkRequestQuery {
+                ids_in: Some(expected_ids),
+                ..query
+            };
+            Some(
+                self.repository
+                    .update_walk_requests_by_query(query, update)
+                    .await?,
+            )
+        };
+        Ok(BulkUpdateReport {
+            dry_run,
+            matched: affected_ids.len(),
+            affected_ids,
+            diff_sample,
+            modified,
+        })
+    }
+
+    pub async fn admin_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: SortBy,
         pagination: Pagination,
-    ) -> Result<Vec<WalkRequest>, Error> {
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
         self.repository
-            .query_walk_requests(
+            .query_walk_requests(query, Some(sort_by), Some(pagination))
+            .await
+    }
+
+    /// Cancels any request which hasn't started yet, on behalf of support.
+    pub async fn admin_cancel(
+        &self,
+        request_id: &str,
+        admin_id: &str,
+        reason: Option<String>,
+    ) -> Result<WalkRequest, ServiceError> {
+        let reason = cancellation_reason(reason)?;
+        match self
+            .update_request(
                 WalkRequestQuery {
-                    accepted_by_is_null: Some(true),
-                    nearby: Some(vec![longitude, latitute, radius]),
+                    id: Some(request_id.to_owned()),
+                    started_at_is_null: Some(true),
+                    canceled_at_is_null: Some(true),
+                    expired_at_is_null: Some(true),
                     ..Default::default()
                 },
-                None,
-                Some(pagination),
+                WalkRequestUpdate {
+                    canceled_at: Some(Utc::now()),
+                    canceled_by: Some(admin_id.to_owned()),
+                    cancellation_reason: reason,
+                    ..Default::default()
+                },
+                |_| Publications::event(WalkRequestEventKind::Canceled, request_id, admin_id),
             )
             .await
+        {
+            Ok(request) => Ok(request),
+            Err(ServiceError::NotFound(_)) => Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Canceled,
+                    "代遛请求不存在",
+                )
+                .await),
+            Err(e) => Err(e),
+        }
     }
 
-    pub async fn my_walk_requests(
+    /// Hands a request which hasn't started yet to `user_id`, replacing the
+    /// current walker if there is one. Support overrides the walker's
+    /// onboarding, capability and budget checks.
+    pub async fn admin_reassign(
         &self,
+        request_id: &str,
+        admin_id: &str,
         user_id: &str,
-        pagination: Pagination,
-    ) -> Result<Vec<WalkRequest>, Error> {
-        self.repository
+    ) -> Result<WalkRequest, ServiceError> {
+        let previous = self.repository.get_walk_request(request_id).await?;
+        if previous.created_by.as_deref() == Some(user_id) {
+            return Err(ServiceError::Validation(
+                "不能将代遛请求指派给发布者本人".to_owned(),
+            ));
+        }
+        let replaced = previous.accepted_by.filter(|w| w != user_id);
+        match self
+            .transition(|repository| async move {
+                let request = repository
+                    .update_walk_request_by_query(
+                        WalkRequestQuery {
+                            id: Some(request_id.to_owned()),
+                            started_at_is_null: Some(true),
+                            canceled_at_is_null: Some(true),
+                            cancel_requested_at_is_null: Some(true),
+                            expired_at_is_null: Some(true),
+                            ..Default::default()
+                        },
+                        WalkRequestUpdate {
+                            accepted_by: Some(user_id.to_owned()),
+                            accepted_at: Some(Utc::now()),
+                            add_to_acceptances: Some(user_id.to_owned()),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                if let Some(replaced) = &replaced {
+                    repository
+                        .upsert_application(request_id, replaced, ApplicationState::Dismissed)
+                        .await?;
+                }
+                repository
+                    .upsert_application(request_id, user_id, ApplicationState::Assigned)
+                    .await?;
+                let publications =
+                    Publications::event(WalkRequestEventKind::Accepted, request_id, admin_id);
+                Ok((request, publications))
+            })
+            .await
+        {
+            Ok(request) => Ok(request),
+            Err(ServiceError::NotFound(_)) => Err(self
+                .rejected(
+                    request_id,
+                    None,
+                    WalkRequestStatus::Accepted,
+                    "代遛请求不存在",
+                )
+                .await),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn rollup_daily_metrics(
+        &self,
+        day: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        let from = Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).expect("midnight is valid"));
+        let to = from + chrono::Duration::days(1);
+        let requests = self
+            .repository
+            .walk_requests_active_between(from, to)
+            .await?;
+        let metrics = rollup(day, &requests);
+        self.repository.save_daily_metrics(metrics.clone()).await?;
+        Ok(metrics)
+    }
+
+    /// Replays the requests created on `params.date` with alternative matching
+    /// and pricing parameters, nothing is written.
+    pub async fn simulate_day(
+        &self,
+        params: SimulationParams,
+    ) -> Result<Vec<RegionSimulation>, ServiceError> {
+        if params.base_price.minor_units < 0 {
+            return Err(ServiceError::Validation("基础价格不能为负".to_owned()));
+        }
+        let from =
+            Utc.from_utc_datetime(&params.date.and_hms_opt(0, 0, 0).expect("midnight is valid"));
+        let to = from + chrono::Duration::days(1);
+        let requests = self
+            .repository
+            .walk_requests_active_between(from, to)
+            .await?;
+        let mut multipliers = HashMap::new();
+        let mut rates = HashMap::new();
+        for region in requests.iter().filter_map(|r| r.region.as_deref()) {
+            if !multipliers.contains_key(region) {
+                let multiplier = self.holiday_price_multiplier(region, params.date).await?;
+                multipliers.insert(region.to_owned(), multiplier);
+                let currency = self
+                    .currencies
+                    .currency_for(self.tenant.as_ref(), Some(region));
+                if currency != params.base_price.currency {
+                    // Snapshot once per region so every figure uses the same rate.
+                    let rate = self
+                        .rates
+                        .rate(&params.base_price.currency, currency)
+                        .await?;
+                    rates.insert(region.to_owned(), rate);
+                }
+            }
+        }
+        Ok(simulate(&requests, &params, &multipliers, &rates))
+    }
+
+    pub async fn daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        self.repository.query_daily_metrics(region, from, to).await
+    }
+
+    /// Records impressions of only a share of walkers.
+    pub fn with_impression_sampling(mut self, sampling: ImpressionSampling) -> Self {
+        self.impressions = sampling;
+        self
+    }
+
+    /// Records that a walker saw the requests in a feed, for the funnel and
+    /// matching. Only counts per request are kept, not who saw them. Unknown
+    /// requests and the walker's own are left out.
+    pub async fn record_impressions(
+        &self,
+        user_id: &str,
+        request_ids: Vec<String>,
+    ) -> Result<(), ServiceError> {
+        if request_ids.len() > MAX_IMPRESSIONS_PER_BATCH {
+            return Err(ServiceError::Validation(format!(
+                "每批曝光最多{}条",
+                MAX_IMPRESSIONS_PER_BATCH
+            )));
+        }
+        if !self.impressions.sampled(user_id) {
+            return Ok(());
+        }
+        let request_ids: Vec<String> = self
+            .repository
             .query_walk_requests(
                 WalkRequestQuery {
-                    created_by: Some(user_id.to_owned()),
+                    ids_in: Some(request_ids),
+                    created_by_neq: Some(user_id.to_owned()),
                     ..Default::default()
                 },
-                Some(SortBy {
-                    field: WalkRequest::created_at(),
-                    order: Order::Desc,
-                }),
-                Some(pagination),
+                None,
+                None,
             )
+            .await?
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+        if request_ids.is_empty() {
+            return Ok(());
+        }
+        self.repository
+            .record_impressions(&request_ids, self.impressions.weight(), Utc::now())
             .await
     }
 
-    pub async fn accept(&self, request_id: &str, user_id: &str) -> Result<WalkRequest, Error> {
-        self.repository
-            .update_walk_request_by_query(
+    /// Funnels of the requests created from `from` to `to` inclusive, of one
+    /// region or of all.
+    pub async fn funnel_metrics(
+        &self,
+        region: Option<&str>,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<FunnelMetrics>, ServiceError> {
+        if to < from || (to - from).num_days() >= MAX_FUNNEL_DAYS {
+            return Err(ServiceError::Validation(format!(
+                "时间范围需不早于开始日期且不超过{}天",
+                MAX_FUNNEL_DAYS
+            )));
+        }
+        let start = Utc.from_utc_datetime(&from.and_hms_opt(0, 0, 0).expect("midnight is valid"));
+        let end = Utc.from_utc_datetime(&to.and_hms_opt(0, 0, 0).expect("midnight is valid"))
+            + chrono::Duration::days(1);
+        let mut requests = self
+            .repository
+            .query_walk_requests(
                 WalkRequestQuery {
-                    id: Some(request_id.into()),
-                    accepted_by_is_null: Some(true),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    accepted_by: Some(user_id.to_owned()),
-                    accepted_at: Some(Utc::now()),
+                    created_at_gte: Some(start),
+                    include_deleted: true,
                     ..Default::default()
                 },
+                None,
+                None,
             )
-            .await
+            .await?;
+        // Requests without a region count as `DEFAULT_REGION`, which the
+        // repository filter can't match.
+        requests.retain(|r| {
+            r.created_at.map_or(false, |t| t < end)
+                && region.map_or(true, |region| {
+                    r.region.as_deref().unwrap_or(DEFAULT_REGION) == region
+                })
+        });
+        let ids: Vec<String> = requests.iter().map(|r| r.id.clone()).collect();
+        let marks = self.repository.query_funnel_marks(&ids).await?;
+        Ok(funnel(&requests, &marks))
+    }
+
+    fn sla_breach_query(&self) -> WalkRequestQuery {
+        WalkRequestQuery {
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            regions_in: (!self.sla.regions.is_empty()).then(|| self.sla.regions.clone()),
+            ..Default::default()
+        }
     }
 
-    pub async fn remove_acceptance(&self, request_id: &str, user_id: &str) -> Result<(), Error> {
+    /// Marks requests which just crossed the time-to-accept objective and
+    /// returns them, so callers can boost or re-notify.
+    pub async fn detect_sla_breaches(&self) -> Result<Vec<WalkRequest>, ServiceError> {
+        let now = Utc::now();
+        let breached = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    created_at_lte: Some(now - self.sla.accept_within),
+                    sla_breached_at_is_null: Some(true),
+                    ..self.sla_breach_query()
+                },
+                None,
+                None,
+            )
+            .await?;
+        if breached.is_empty() {
+            return Ok(breached);
+        }
         self.repository
             .update_walk_requests_by_query(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by_neq: Some(user_id.to_owned()),
+                    ids_in: Some(breached.iter().map(|r| r.id.clone()).collect()),
+                    sla_breached_at_is_null: Some(true),
                     ..Default::default()
                 },
                 WalkRequestUpdate {
-                    remove_from_acceptances: Some(user_id.to_owned()),
+                    sla_breached_at: Some(now),
                     ..Default::default()
                 },
             )
-            .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在或狗狗主人已通过请求"))
+            .await?;
+        for request in &breached {
+            self.alert(AlertKind::SlaBreach, request, "超过接单时限仍无人接单")
+                .await;
+        }
+        Ok(breached)
+    }
+
+    /// Marks waiting requests whose start window has passed as expired.
+    pub async fn verify_audit_chain(
+        &self,
+        request_id: &str,
+    ) -> Result<AuditVerification, ServiceError> {
+        self.repository.verify_audit_chain(request_id).await
+    }
+
+    /// Imports walk requests and their tracks from a dump of the previous
+    /// system. Records already imported are skipped so a dump can be re-run,
+    /// invalid ones are reported and don't stop the import.
+    pub async fn import_legacy(
+        &self,
+        format: DumpFormat,
+        mapping: &FieldMapping,
+        walk_requests: Option<&str>,
+        tracks: Option<&str>,
+        dry_run: bool,
+    ) -> Result<ImportReport, ServiceError> {
+        let parse = |dump: Option<&str>| {
+            dump.map_or(Ok(Vec::new()), |d| parse_dump(format, d))
+                .map_err(|e| ServiceError::Validation(format!("无法解析导入文件: {}", e)))
+        };
+        let (request_rows, track_rows) = (parse(walk_requests)?, parse(tracks)?);
+        let mut report = ImportReport {
+            dry_run,
+            ..Default::default()
+        };
+        // Legacy ids imported by this run, dry runs don't write the ledger.
+        let mut pending = HashMap::new();
+        for (record, row) in request_rows.iter().enumerate() {
+            let legacy = match map_walk_request(row, mapping) {
+                Ok(legacy) => legacy,
+                Err(message) => {
+                    report.issues.push(ImportIssue {
+                        dump: "walk_requests",
+                        record,
+                        message,
+                    });
+                    continue;
                 }
-            })
+            };
+            if pending.contains_key(&legacy.legacy_id)
+                || self
+                    .repository
+                    .imported_id(&legacy.legacy_id)
+                    .await?
+                    .is_some()
+            {
+                report.walk_requests_skipped += 1;
+                continue;
+            }
+            if dry_run {
+                pending.insert(legacy.legacy_id, String::new());
+                report.walk_requests_imported += 1;
+                continue;
+            }
+            let has_history = legacy.has_history();
+            let id = self.repository.create_walk_request(legacy.create).await?;
+            self.repository
+                .record_import(&legacy.legacy_id, &id)
+                .await?;
+            if has_history {
+                self.repository
+                    .update_walk_request(&id, legacy.history)
+                    .await?;
+            }
+            pending.insert(legacy.legacy_id, id);
+            report.walk_requests_imported += 1;
+        }
+        for (record, row) in track_rows.iter().enumerate() {
+            let point = match map_track_point(row, mapping) {
+                Ok(point) => point,
+                Err(message) => {
+                    report.issues.push(ImportIssue {
+                        dump: "tracks",
+                        record,
+                        message,
+                    });
+                    continue;
+                }
+            };
+            let request_id = match pending.get(&point.legacy_request_id) {
+                Some(id) => Some(id.clone()),
+                None => {
+                    self.repository
+                        .imported_id(&point.legacy_request_id)
+                        .await?
+                }
+            };
+            let Some(request_id) = request_id else {
+                report.issues.push(ImportIssue {
+                    dump: "tracks",
+                    record,
+                    message: format!("未找到代遛请求: {}", point.legacy_request_id),
+                });
+                continue;
+            };
+            let key = point.key();
+            if self.repository.imported_id(&key).await?.is_some() {
+                report.track_points_skipped += 1;
+                continue;
+            }
+            if !dry_run {
+                let id = self
+                    .repository
+                    .create_walking_location(WalkingLocationCreate {
+                        walk_request_id: &request_id,
+                        longitude: point.longitude,
+                        latitude: point.latitude,
+                        recorded_at: Some(point.recorded_at),
+                    })
+                    .await?;
+                self.repository.record_import(&key, &id).await?;
+            }
+            report.track_points_imported += 1;
+        }
+        Ok(report)
     }
 
-    pub async fn assign_accepter(&self, request_id: &str, user_id: &str) -> Result<(), Error> {
-        self.repository
-            .update_walk_requests_by_query(
+    /// Applies the retention policy, only counting what would go on a dry run.
+    pub async fn purge_expired_data(&self, dry_run: bool) -> Result<PurgeReport, ServiceError> {
+        let run_at = Utc::now();
+        let mut purged = Vec::new();
+        for (class, before) in self.retention.cutoffs(run_at) {
+            if class == DataClass::Tracks && !dry_run {
+                self.archive_tracks(before).await?;
+            }
+            let count = self.repository.purge(class, before, dry_run).await?;
+            purged.push(ClassPurge {
+                class,
+                before,
+                count,
+            });
+        }
+        Ok(PurgeReport {
+            dry_run,
+            run_at,
+            purged,
+        })
+    }
+
+    /// Goes over every request, deleted ones too, repairing timestamps the
+    /// state machine can't reach where that is safe and reporting the
+    /// rest, see `reconcile::diagnose`. Only reports on a dry run. Repairs
+    /// are guarded by the version read, requests changed meanwhile are
+    /// counted as failed.
+    pub async fn reconcile_walk_requests(
+        &self,
+        dry_run: bool,
+    ) -> Result<ReconcileReport, ServiceError> {
+        let mut report = ReconcileReport {
+            dry_run,
+            ..Default::default()
+        };
+        let mut requests = self
+            .repository
+            .stream_walk_requests(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by_is_null: Some(true),
-                    acceptances_includes_all: Some(vec![user_id.to_owned()]),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    accepted_by: Some(user_id.to_owned()),
-                    accepted_at: Some(Utc::now()),
+                    include_deleted: true,
                     ..Default::default()
                 },
+                Some(SortBy {
+                    field: WalkRequest::created_at(),
+                    order: Order::Asc,
+                }),
+                None,
             )
-            .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在或该用户已取消报名"))
+            .await?;
+        while let Some(request) = requests.try_next().await? {
+            report.scanned += 1;
+            let diagnosis = diagnose(&request);
+            if diagnosis.is_consistent() {
+                continue;
+            }
+            report.inconsistent += 1;
+            if !diagnosis.unresolved.is_empty() {
+                report.unresolved += 1;
+            }
+            report.record(&request.id, &diagnosis);
+            let Some(update) = diagnosis.update else {
+                continue;
+            };
+            if dry_run {
+                report.repaired += 1;
+                continue;
+            }
+            match self
+                .repository
+                .update_walk_request_by_query(
+                    WalkRequestQuery {
+                        id: Some(request.id.clone()),
+                        version: Some(request.version),
+                        include_deleted: true,
+                        ..Default::default()
+                    },
+                    update,
+                )
+                .await
+            {
+                Ok(_) => report.repaired += 1,
+                Err(e) => {
+                    log::error!("failed to reconcile walk request {}: {}", request.id, e);
+                    report.failed += 1;
                 }
-            })
+            }
+        }
+        report.finish();
+        Ok(report)
     }
 
-    pub async fn dismiss_accepter(&self, request_id: &str, user_id: &str) -> Result<(), Error> {
-        self.repository
-            .update_walk_requests_by_query(
+    /// Stores the whole track of every walk with locations recorded before
+    /// `before` in the track archive, merged with what an earlier purge kept.
+    async fn archive_tracks(&self, before: DateTime<Utc>) -> Result<(), ServiceError> {
+        let Some(store) = &self.archive else {
+            return Ok(());
+        };
+        for id in self
+            .repository
+            .walk_requests_with_tracks_before(before)
+            .await?
+        {
+            let live = self
+                .repository
+                .query_walking_locations(
+                    WalkingLocationQuery {
+                        walk_request_id: id.clone(),
+                        created_after: None,
+                        created_before: None,
+                    },
+                    None,
+                )
+                .await?;
+            let key = archive::track_key(&id);
+            let archived = match store.get(&key).await? {
+                Some(body) => archive::decompress(&body)?,
+                None => Vec::new(),
+            };
+            store
+                .put(&key, archive::compress(&archive::merge(archived, live))?)
+                .await?;
+            self.repository
+                .update_walk_request(
+                    &id,
+                    WalkRequestUpdate {
+                        track_archived_at: Some(Utc::now()),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Thins out the tracks of walks older than the downsampling policy's
+    /// threshold, each track once. Returns how many points were dropped.
+    pub async fn downsample_tracks(&self) -> Result<u64, ServiceError> {
+        let Some(policy) = self.track_downsampling else {
+            return Ok(0);
+        };
+        let ids = self
+            .repository
+            .walk_requests_with_tracks_before(Utc::now() - policy.after)
+            .await?;
+        let mut dropped = 0;
+        let requests = self
+            .repository
+            .query_walk_requests(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by: Some(user_id.to_owned()),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    unset_accepted_by: true,
-                    unset_accepted_at: true,
+                    ids_in: Some(ids),
                     ..Default::default()
                 },
+                None,
+                None,
             )
-            .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在或该用户已取消报名"))
+            .await?;
+        for request in requests {
+            if request.track_downsampled_at.is_some() {
+                continue;
+            }
+            let locations = self
+                .repository
+                .query_walking_locations(
+                    WalkingLocationQuery {
+                        walk_request_id: request.id.clone(),
+                        created_after: None,
+                        created_before: None,
+                    },
+                    None,
+                )
+                .await?;
+            let drop = geo::downsample(&locations, policy.interval);
+            if !drop.is_empty() {
+                dropped += self.repository.delete_walking_locations(&drop).await?;
+            }
+            self.repository
+                .update_walk_request(
+                    &request.id,
+                    WalkRequestUpdate {
+                        track_downsampled_at: Some(Utc::now()),
+                        ..Default::default()
+                    },
+                )
+                .await?;
+        }
+        Ok(dropped)
+    }
+
+    /// Expires the requests nobody took before their start window closed.
+    /// Returns how many expired.
+    pub async fn expire_walk_requests(&self) -> Result<u64, ServiceError> {
+        let now = Utc::now();
+        let query = WalkRequestQuery {
+            should_start_before_lt: Some(now),
+            accepted_by_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            expired_at_is_null: Some(true),
+            ..Default::default()
+        };
+        let stale = self
+            .repository
+            .query_walk_requests(query.clone(), None, None)
+            .await?;
+        let mut expired = 0;
+        for request in stale {
+            // Skipped when the request was taken or canceled meanwhile.
+            expired += self
+                .update_requests(
+                    WalkRequestQuery {
+                        id: Some(request.id.clone()),
+                        ..query.clone()
+                    },
+                    WalkRequestUpdate {
+                        expired_at: Some(now),
+                        ..Default::default()
+                    },
+                    Publications::event(
+                        WalkRequestEventKind::Expired,
+                        &request.id,
+                        request.created_by.as_deref().unwrap_or_default(),
+                    ),
+                )
+                .await?;
+        }
+        Ok(expired)
+    }
+
+    pub fn with_start_reminders(mut self, minutes: Vec<i64>) -> Self {
+        self.start_reminders = minutes;
+        self
+    }
+
+    /// Replaces the pending start reminders of the request with those of its
+    /// current state, dropping them once the walk is no longer accepted.
+    async fn reschedule_start_reminders(&self, request_id: &str) {
+        if let Err(e) = self.repository.delete_start_reminders(request_id).await {
+            log::error!(
+                "failed to drop the start reminders of {}: {}",
+                request_id,
+                e
+            );
+            return;
+        }
+        if self.notifier.is_none() || self.start_reminders.is_empty() {
+            return;
+        }
+        let reminders = match self.repository.get_walk_request(request_id).await {
+            Ok(request) => StartReminder::schedule(&request, &self.start_reminders, Utc::now()),
+            Err(e) => {
+                log::error!(
+                    "failed to load {} to remind of its start: {}",
+                    request_id,
+                    e
+                );
+                return;
+            }
+        };
+        if let Err(e) = self.repository.save_start_reminders(&reminders).await {
+            log::error!(
+                "failed to schedule the start reminders of {}: {}",
+                request_id,
+                e
+            );
+        }
+    }
+
+    /// Pushes the start reminders which are due, skipping those of walks
+    /// called off since. Returns how many were sent.
+    pub async fn send_start_reminders(&self) -> Result<u64, ServiceError> {
+        let due = self
+            .repository
+            .due_start_reminders(Utc::now(), REMINDER_BATCH_SIZE)
+            .await?;
+        let mut sent = 0;
+        for reminder in due {
+            let request = match self.repository.get_walk_request(&reminder.request_id).await {
+                Ok(request) => Some(request),
+                Err(ServiceError::NotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            if let Some(request) = request.filter(|r| reminder.still_due(r)) {
+                let mut notification = Notification::new(
+                    &reminder.user_id,
+                    NotificationKind::StartReminder,
+                    &reminder.request_id,
+                );
+                // Reminders are sent in the default locale, like the titles.
+                if let Some(start) = request.should_start_after {
+                    notification = notification.with_body(format!(
+                        "{}开始",
+                        display(&start, request.timezone.as_deref(), DEFAULT_LOCALE)
+                    ));
                 }
-            })
+                self.publish(Publications::default().notify_with(notification))
+                    .await?;
+                sent += 1;
+            }
+            self.repository.delete_start_reminder(&reminder.id).await?;
+        }
+        Ok(sent)
+    }
+
+    /// Dismisses accepted walkers who didn't start within
+    /// `NO_SHOW_GRACE_MINUTES` of the start window opening, or before it
+    /// closed, recording a strike on each and alerting ops. Requests whose
+    /// start window is still open are re-opened, the others expire.
+    /// Returns how many walkers were dismissed.
+    pub async fn time_out_no_shows(&self) -> Result<u64, ServiceError> {
+        let now = Utc::now();
+        let overdue = WalkRequestQuery {
+            accepted_by_is_null: Some(false),
+            started_at_is_null: Some(true),
+            canceled_at_is_null: Some(true),
+            cancel_requested_at_is_null: Some(true),
+            ..Default::default()
+        };
+        let mut no_shows = BTreeMap::new();
+        for query in [
+            WalkRequestQuery {
+                should_start_after_lt: Some(now - chrono::Duration::minutes(NO_SHOW_GRACE_MINUTES)),
+                ..overdue.clone()
+            },
+            WalkRequestQuery {
+                should_start_before_lt: Some(now),
+                ..overdue
+            },
+        ] {
+            for request in self
+                .repository
+                .query_walk_requests(query, None, None)
+                .await?
+            {
+                no_shows.insert(request.id.clone(), request);
+            }
+        }
+        let mut dismissed = 0;
+        for request in no_shows.into_values() {
+            let Some(walker) = request.accepted_by.clone() else {
+                continue;
+            };
+            let reopen = request
+                .should_start_before
+                .map_or(true, |before| before > now);
+            let (request, walker) = (&request, &walker);
+            // Skipped when the walk started or the walker left meanwhile.
+            let n = self
+                .transition(|repository| async move {
+                    let n = repository
+                        .update_walk_requests_by_query(
+                            WalkRequestQuery {
+                                id: Some(request.id.clone()),
+                                accepted_by: Some(walker.clone()),
+                                started_at_is_null: Some(true),
+                                canceled_at_is_null: Some(true),
+                                ..Default::default()
+                            },
+                            WalkRequestUpdate {
+                                unset_accepted_by: true,
+                                unset_accepted_at: true,
+                                remove_from_acceptances: Some(walker.clone()),
+                                add_to_dismissed_applicants: Some(walker.clone()),
+                                expired_at: (!reopen).then_some(now),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                    if n != 1 {
+                        return Ok((n, Publications::default()));
+                    }
+                    repository
+                        .upsert_application(&request.id, walker, ApplicationState::Dismissed)
+                        .await?;
+                    let publications =
+                        Publications::event(WalkRequestEventKind::NoShow, &request.id, walker)
+                            .notify(walker, NotificationKind::Dismissed, &request.id);
+                    let publications = if reopen {
+                        publications.notify_owner(request, NotificationKind::NoShow)
+                    } else {
+                        publications
+                            .emit(
+                                WalkRequestEventKind::Expired,
+                                &request.id,
+                                request.created_by.as_deref().unwrap_or_default(),
+                            )
+                            .notify_owner(request, NotificationKind::NoShowExpired)
+                    };
+                    Ok((n, publications))
+                })
+                .await?;
+            if n != 1 {
+                continue;
+            }
+            dismissed += 1;
+            self.strike(walker, &request.id, StrikeReason::NoShow)
+                .await?;
+            self.alert(AlertKind::NoShow, request, "遛狗人未按时开始遛狗")
+                .await;
+        }
+        Ok(dismissed)
+    }
+
+    /// Strikes against the walker, most recent first.
+    pub async fn walker_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError> {
+        self.repository.query_strikes(walker_id).await
     }
 
-    pub async fn cancel_unaccepted_request(&self, request_id: &str) -> Result<(), Error> {
+    /// Records a strike against the walker for the request, banning them
+    /// once enough were collected recently. Repeated strikes for the same
+    /// request count once.
+    async fn strike(
+        &self,
+        walker_id: &str,
+        request_id: &str,
+        reason: StrikeReason,
+    ) -> Result<(), ServiceError> {
+        let now = Utc::now();
+        let recorded = self
+            .repository
+            .record_strike(&Strike {
+                walker_id: walker_id.to_owned(),
+                request_id: request_id.to_owned(),
+                reason,
+                recorded_at: now,
+            })
+            .await?;
+        if !recorded {
+            return Ok(());
+        }
+        let strikes = self.repository.query_strikes(walker_id).await?;
         self.repository
-            .update_walk_requests_by_query(
-                WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by_is_null: Some(true),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    canceled_at: Some(Utc::now()),
+            .update_reputation(
+                walker_id,
+                ReputationUpdate {
+                    strikes: 1,
+                    banned_until: ban_for(&strikes, now),
                     ..Default::default()
                 },
             )
             .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在"))
-                }
-            })
     }
 
-    pub async fn cancel_accepted_request(
+    /// Completed walks and strikes of the user, zero when they have none.
+    pub async fn reputation(&self, user_id: &str) -> Result<Reputation, ServiceError> {
+        Ok(self
+            .repository
+            .get_reputation(user_id)
+            .await?
+            .unwrap_or_else(|| Reputation::new(user_id)))
+    }
+
+    /// Moves finished and canceled requests to the archive once they ended
+    /// longer than `after` ago.
+    pub fn with_archive_after(mut self, after: chrono::Duration) -> Self {
+        self.archive_after = Some(after);
+        self
+    }
+
+    pub async fn archive_walk_requests(&self) -> Result<u64, ServiceError> {
+        let Some(after) = self.archive_after else {
+            return Ok(0);
+        };
+        self.repository
+            .archive_walk_requests(Utc::now() - after)
+            .await
+    }
+
+    /// Archived walks of the user, most recent first.
+    pub async fn walk_request_history(
         &self,
-        request_id: &str,
         user_id: &str,
-    ) -> Result<(), Error> {
+        filter: HistoryFilter,
+        pagination: Pagination,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let query = match filter.role {
+            HistoryRole::Owner => WalkRequestQuery {
+                created_by: Some(user_id.to_owned()),
+                ..Default::default()
+            },
+            HistoryRole::Walker => WalkRequestQuery {
+                accepted_by: Some(user_id.to_owned()),
+                ..Default::default()
+            },
+        };
         self.repository
-            .update_walk_requests_by_query(
+            .query_archived_walk_requests(query, Some(pagination))
+            .await
+    }
+
+    /// Hides a request from every query, on behalf of support. The request
+    /// is kept and still archived once it ended.
+    pub async fn delete_walk_request(
+        &self,
+        request_id: &str,
+        admin_id: &str,
+    ) -> Result<(), ServiceError> {
+        self.update_request(
+            WalkRequestQuery {
+                id: Some(request_id.to_owned()),
+                ..Default::default()
+            },
+            WalkRequestUpdate {
+                deleted_at: Some(Utc::now()),
+                ..Default::default()
+            },
+            |_| Publications::event(WalkRequestEventKind::Deleted, request_id, admin_id),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Number of requests currently in breach and still waiting for a walker.
+    pub async fn open_sla_breaches(&self) -> Result<u64, ServiceError> {
+        Ok(self
+            .repository
+            .query_walk_requests(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by: Some(user_id.to_owned()),
-                    ..Default::default()
+                    sla_breached_at_is_null: Some(false),
+                    ..self.sla_breach_query()
                 },
-                WalkRequestUpdate {
-                    canceled_at: Some(Utc::now()),
+                None,
+                None,
+            )
+            .await?
+            .len() as u64)
+    }
+
+    /// The walker's applications, with pending ones reported as expired once
+    /// their request was canceled or its start window has passed.
+    pub async fn my_applications(
+        &self,
+        user_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError> {
+        let mut applications = self
+            .repository
+            .query_applications(user_id, pagination)
+            .await?;
+        let pending: Vec<String> = applications
+            .iter()
+            .filter(|a| a.state == ApplicationState::Pending)
+            .map(|a| a.request_id.clone())
+            .collect();
+        if pending.is_empty() {
+            return Ok(applications);
+        }
+        let now = Utc::now();
+        let expired: Vec<String> = self
+            .repository
+            .query_walk_requests(
+                WalkRequestQuery {
+                    ids_in: Some(pending),
                     ..Default::default()
                 },
+                None,
+                None,
             )
+            .await?
+            .into_iter()
+            .filter(|r| r.canceled_at.is_some() || r.should_start_before.map_or(false, |t| t < now))
+            .map(|r| r.id)
+            .collect();
+        for application in applications.iter_mut() {
+            if application.state == ApplicationState::Pending
+                && expired.contains(&application.request_id)
+            {
+                application.state = ApplicationState::Expired;
+            }
+        }
+        Ok(applications)
+    }
+
+    /// Assigns the walker and, when a payment service is configured, holds and
+    /// confirms the owner's payment, compensating completed steps on failure.
+    pub async fn book(
+        &self,
+        request_id: &str,
+        owner_id: &str,
+        walker_id: &str,
+        force: bool,
+    ) -> Result<(), ServiceError> {
+        let Some(payments) = self.payments.clone() else {
+            return self
+                .assign_accepter(request_id, owner_id, walker_id, force)
+                .await;
+        };
+        let mut saga = BookingSaga::new(request_id, walker_id);
+        self.repository.save_saga(&saga).await?;
+        if let Err(e) = self
+            .assign_accepter(request_id, owner_id, walker_id, force)
             .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在"))
+        {
+            saga.finish(SagaStatus::Failed, Some(&e));
+            self.repository.save_saga(&saga).await?;
+            return Err(e);
+        }
+        saga.advance(SagaStep::Accepted);
+        self.repository.save_saga(&saga).await?;
+        if let Err(e) = self.hold_and_confirm(payments.as_ref(), &mut saga).await {
+            let status = match self.compensate_booking(payments.as_ref(), &saga).await {
+                Ok(()) => SagaStatus::Compensated,
+                Err(compensation) => {
+                    log::error!("failed to compensate booking {}: {}", saga.id, compensation);
+                    SagaStatus::Failed
                 }
-            })
+            };
+            saga.finish(status, Some(&e));
+            self.repository.save_saga(&saga).await?;
+            return Err(e);
+        }
+        saga.advance(SagaStep::Confirmed);
+        saga.finish(SagaStatus::Completed, None);
+        self.repository.save_saga(&saga).await
     }
 
-    pub async fn resign_acceptance(&self, request_id: &str, user_id: &str) -> Result<(), Error> {
+    async fn hold_and_confirm(
+        &self,
+        payments: &dyn PaymentHolds,
+        saga: &mut BookingSaga,
+    ) -> Result<(), ServiceError> {
+        let request = self.repository.get_walk_request(&saga.request_id).await?;
+        let owner_id = request.created_by.unwrap_or_default();
+        let hold_id = payments.hold(&saga.request_id, &owner_id).await?;
+        saga.hold_id = Some(hold_id.clone());
+        saga.advance(SagaStep::PaymentHeld);
+        self.repository.save_saga(saga).await?;
+        Ok(payments.confirm(&hold_id).await?)
+    }
+
+    async fn compensate_booking(
+        &self,
+        payments: &dyn PaymentHolds,
+        saga: &BookingSaga,
+    ) -> Result<(), ServiceError> {
+        if let Some(hold_id) = &saga.hold_id {
+            payments.release(hold_id).await?;
+        }
         self.repository
             .update_walk_requests_by_query(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by: Some(user_id.to_owned()),
+                    id: Some(saga.request_id.clone()),
+                    accepted_by: Some(saga.walker_id.clone()),
                     ..Default::default()
                 },
                 WalkRequestUpdate {
                     unset_accepted_by: true,
                     unset_accepted_at: true,
-                    remove_from_acceptances: Some(user_id.to_owned()),
                     ..Default::default()
                 },
             )
+            .await?;
+        self.repository
+            .upsert_application(&saga.request_id, &saga.walker_id, ApplicationState::Pending)
             .await
-            .and_then(|n| {
-                if n == 1 {
-                    Ok(())
-                } else {
-                    Err(Error::msg("请求不存在或已被狗狗主人取消"))
-                }
-            })
     }
 
-    pub async fn start_walk(&self, request_id: &str, user_id: &str) -> Result<WalkRequest, Error> {
-        self.repository
-            .update_walk_request_by_query(
+    /// Anonymized open request counts per area and hour for researchers.
+    pub async fn open_request_counts(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<AreaHourCount>, ServiceError> {
+        let max_range = chrono::Duration::days(MAX_RANGE_DAYS);
+        if to <= from || to - from > max_range {
+            return Err(ServiceError::Validation(format!(
+                "时间范围需大于0且不超过{}天",
+                MAX_RANGE_DAYS
+            )));
+        }
+        let requests = self
+            .repository
+            .query_walk_requests(
                 WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by: Some(user_id.to_owned()),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    started_at: Some(Utc::now()),
+                    created_at_gte: Some(from - max_range),
+                    created_at_lte: Some(to),
                     ..Default::default()
                 },
+                None,
+                None,
             )
-            .await
+            .await?;
+        Ok(open_request_counts(&requests, from, to))
     }
 
-    pub async fn record_walking_location(
+    /// The walks `user_id` finished as `role` between `from` and `to`, the
+    /// last `DEFAULT_REPORT_DAYS` days unless given.
+    pub async fn walk_report(
         &self,
-        walk_request_id: &str,
-        longitude: f64,
-        latitute: f64,
-    ) -> Result<String, Error> {
-        self.repository
-            .create_walking_location(WalkingLocationCreate {
-                walk_request_id,
-                longitude,
-                latitude: latitute,
-            })
-            .await
+        user_id: &str,
+        role: ReportRole,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        interval: ReportInterval,
+    ) -> Result<WalkReport, ServiceError> {
+        let to = to.unwrap_or_else(Utc::now);
+        let from = from.unwrap_or(to - chrono::Duration::days(DEFAULT_REPORT_DAYS));
+        if to <= from || to - from > chrono::Duration::days(MAX_REPORT_DAYS) {
+            return Err(ServiceError::Validation(format!(
+                "时间范围需大于0且不超过{}天",
+                MAX_REPORT_DAYS
+            )));
+        }
+        let mut query = WalkRequestQuery {
+            finished_at_is_null: Some(false),
+            finished_at_gte: Some(from),
+            finished_at_lte: Some(to),
+            ..Default::default()
+        };
+        match role {
+            ReportRole::Walker => query.accepted_by = Some(user_id.to_owned()),
+            ReportRole::Owner => query.created_by = Some(user_id.to_owned()),
+        }
+        let buckets = self.repository.walk_report(query, interval).await?;
+        let mut report = WalkReport::new(role, from, to, interval, buckets);
+        if role == ReportRole::Owner {
+            report.spending = Some(self.monthly_spending(user_id).await?);
+        }
+        Ok(report)
+    }
+
+    pub async fn tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError> {
+        self.repository.get_tenant(id).await
+    }
+
+    pub async fn set_tenant(&self, tenant: Tenant) -> Result<(), ServiceError> {
+        if tenant.service_polygons.iter().any(|ring| ring.len() < 4) {
+            return Err(ServiceError::Validation(
+                "服务区域至少需要4个点组成的闭合多边形".to_owned(),
+            ));
+        }
+        self.repository.upsert_tenant(tenant).await
     }
 
-    pub async fn finish_walk(&self, request_id: &str, user_id: &str) -> Result<WalkRequest, Error> {
+    pub async fn stuck_sagas(
+        &self,
+        stale_for: chrono::Duration,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError> {
         self.repository
-            .update_walk_request_by_query(
-                WalkRequestQuery {
-                    id: Some(request_id.to_owned()),
-                    accepted_by: Some(user_id.to_owned()),
-                    ..Default::default()
-                },
-                WalkRequestUpdate {
-                    finished_at: Some(Utc::now()),
-                    ..Default::default()
-                },
-            )
+            .query_stuck_sagas(Utc::now() - stale_for, limit)
             .await
     }
 }
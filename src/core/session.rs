@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// One device of a user, named by the `X-Device-ID` header of its calls,
+/// which opens walk request streams and receives pushes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceSession {
+    pub id: String,
+    pub user_id: String,
+    pub device_id: String,
+    pub label: Option<String>,
+    /// FCM registration token, pushes go to the user's topic without one.
+    pub push_token: Option<String>,
+    /// Walk requests the device follows, added when it streams or polls one.
+    pub subscriptions: Vec<String>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeviceSessionRegister {
+    pub label: Option<String>,
+    pub push_token: Option<String>,
+}
+
+impl DeviceSession {
+    pub fn id_of(user_id: &str, device_id: &str) -> String {
+        format!("{}:{}", user_id, device_id)
+    }
+
+    pub fn new(user_id: &str, device_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Self::id_of(user_id, device_id),
+            user_id: user_id.to_owned(),
+            device_id: device_id.to_owned(),
+            label: None,
+            push_token: None,
+            subscriptions: Vec::new(),
+            revoked_at: None,
+            created_at: now,
+            last_seen_at: now,
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.revoked_at.is_none()
+    }
+
+    pub fn subscribe(&mut self, request_id: &str) {
+        if !self.subscriptions.iter().any(|s| s == request_id) {
+            self.subscriptions.push(request_id.to_owned());
+        }
+        self.last_seen_at = Utc::now();
+    }
+}
+
+/// Tokens of the devices to push news of `request_id` to: those following
+/// it, or every active device with a token when none does. `None` when the
+/// user never registered a token, in which case the user's topic is used.
+pub fn push_tokens(sessions: &[DeviceSession], request_id: &str) -> Option<Vec<String>> {
+    if !sessions.iter().any(|s| s.push_token.is_some()) {
+        return None;
+    }
+    let with_token: Vec<&DeviceSession> = sessions
+        .iter()
+        .filter(|s| s.active() && s.push_token.is_some())
+        .collect();
+    let following: Vec<&DeviceSession> = with_token
+        .iter()
+        .copied()
+        .filter(|s| s.subscriptions.iter().any(|r| r == request_id))
+        .collect();
+    let targets = if following.is_empty() {
+        with_token
+    } else {
+        following
+    };
+    Some(
+        targets
+            .into_iter()
+            .filter_map(|s| s.push_token.clone())
+            .collect(),
+    )
+}
@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::{currency::ExchangeRate, entities::WalkRequest, metrics::DEFAULT_REGION, units::Money};
+
+/// Alternative matching and pricing parameters to replay a day with. Unset
+/// fields keep what was in effect that day.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulationParams {
+    pub date: NaiveDate,
+    /// Price of one walk before adjustments, converted to each region's
+    /// currency.
+    pub base_price: Money,
+    /// Applicant cap, walkers who applied beyond it are never considered.
+    pub max_applicants: Option<i64>,
+    /// Requests accepted later than this after creation count as unfilled.
+    pub accept_within_minutes: Option<i64>,
+    /// Replaces the holiday multiplier of every region.
+    pub price_multiplier: Option<f64>,
+}
+
+/// Outcome of one region's requests created on the simulated day, as they
+/// happened and under the alternative parameters.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionSimulation {
+    pub region: String,
+    pub requests: u64,
+    pub baseline_fill_rate: f64,
+    pub simulated_fill_rate: f64,
+    pub fill_rate_delta: f64,
+    pub baseline_revenue: Money,
+    pub simulated_revenue: Money,
+    pub revenue_delta: Money,
+    /// Rate `base_price` was converted at, unset when no conversion was needed.
+    pub exchange_rate: Option<ExchangeRate>,
+}
+
+/// Whether `request` would have been filled under `params`: its accepter
+/// applied within the cap and it was accepted within the window.
+fn filled(request: &WalkRequest, params: &SimulationParams) -> bool {
+    let (Some(accepted_by), Some(accepted_at)) = (&request.accepted_by, request.accepted_at) else {
+        return false;
+    };
+    let within_cap = params.max_applicants.map_or(true, |cap| {
+        request
+            .acceptances
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .position(|a| a == accepted_by)
+            .map_or(true, |position| (position as i64) < cap)
+    });
+    let within_window = match (params.accept_within_minutes, request.created_at) {
+        (Some(minutes), Some(created_at)) => {
+            accepted_at - created_at <= chrono::Duration::minutes(minutes)
+        }
+        _ => true,
+    };
+    within_cap && within_window
+}
+
+fn fill_rate(filled: u64, requests: u64) -> f64 {
+    if requests == 0 {
+        0.0
+    } else {
+        filled as f64 / requests as f64
+    }
+}
+
+/// Replays `requests` created on `params.date`. `multipliers` holds the
+/// holiday price multiplier each region had that day, missing means 1.
+/// `rates` converts the base price for regions charging another currency.
+pub fn simulate(
+    requests: &[WalkRequest],
+    params: &SimulationParams,
+    multipliers: &HashMap<String, f64>,
+    rates: &HashMap<String, ExchangeRate>,
+) -> Vec<RegionSimulation> {
+    let mut regions: BTreeMap<String, (u64, u64, u64)> = BTreeMap::new();
+    for request in requests.iter().filter(|r| {
+        r.created_at
+            .map_or(false, |c| c.date_naive() == params.date)
+    }) {
+        let region = request
+            .region
+            .clone()
+            .unwrap_or_else(|| DEFAULT_REGION.to_owned());
+        let (total, baseline, simulated) = regions.entry(region).or_default();
+        *total += 1;
+        if request.accepted_at.is_some() {
+            *baseline += 1;
+        }
+        if filled(request, params) {
+            *simulated += 1;
+        }
+    }
+    regions
+        .into_iter()
+        .map(|(region, (total, baseline, simulated))| {
+            let multiplier = multipliers.get(&region).copied().unwrap_or(1.0);
+            let exchange_rate = rates.get(&region).cloned();
+            let base_price = exchange_rate.as_ref().map_or_else(
+                || params.base_price.clone(),
+                |r| params.base_price.convert(r),
+            );
+            let unit_price = base_price.scale(multiplier);
+            let simulated_price = base_price.scale(params.price_multiplier.unwrap_or(multiplier));
+            let baseline_revenue = Money::new(
+                unit_price.minor_units * baseline as i64,
+                &base_price.currency,
+            );
+            let simulated_revenue = Money::new(
+                simulated_price.minor_units * simulated as i64,
+                &base_price.currency,
+            );
+            RegionSimulation {
+                baseline_fill_rate: fill_rate(baseline, total),
+                simulated_fill_rate: fill_rate(simulated, total),
+                fill_rate_delta: fill_rate(simulated, total) - fill_rate(baseline, total),
+                revenue_delta: Money::new(
+                    simulated_revenue.minor_units - baseline_revenue.minor_units,
+                    &base_price.currency,
+                ),
+                exchange_rate,
+                baseline_revenue,
+                simulated_revenue,
+                region,
+                requests: total,
+            }
+        })
+        .collect()
+}
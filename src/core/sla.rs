@@ -0,0 +1,19 @@
+use chrono::Duration;
+
+/// Time-to-accept objective: a request still unaccepted `accept_within` after
+/// creation is a breach. Only requests in `regions` are covered, an empty list
+/// covers every region.
+#[derive(Debug, Clone)]
+pub struct SlaPolicy {
+    pub accept_within: Duration,
+    pub regions: Vec<String>,
+}
+
+impl Default for SlaPolicy {
+    fn default() -> Self {
+        Self {
+            accept_within: Duration::minutes(30),
+            regions: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{receipt::Receipt, report::ReportInterval, units::Money};
+
+/// How much an owner means to spend on walks in a calendar month, in the
+/// deployment's default currency.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SpendingCap {
+    pub owner_id: String,
+    pub monthly_limit: Money,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What an owner spent in the month starting `month`, summed over the
+/// `settled` amounts of their receipts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MonthlySpending {
+    pub month: NaiveDate,
+    pub spent: Money,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cap: Option<Money>,
+}
+
+impl MonthlySpending {
+    pub fn new(month: NaiveDate, currency: &str, receipts: &[Receipt], cap: Option<Money>) -> Self {
+        let minor_units = receipts
+            .iter()
+            .filter(|r| r.settled.currency == currency)
+            .map(|r| r.settled.minor_units)
+            .sum();
+        Self {
+            month,
+            spent: Money::new(minor_units, currency),
+            cap,
+        }
+    }
+
+    /// Whether spending `quote` more, in the same currency, goes over the
+    /// cap. Never without a cap.
+    pub fn exceeded_by(&self, quote: &Money) -> bool {
+        self.cap.as_ref().map_or(false, |cap| {
+            self.spent
+                .checked_add(quote)
+                .map_or(true, |total| total.minor_units > cap.minor_units)
+        })
+    }
+}
+
+/// The UTC calendar month `at` falls in, as `[from, to)`.
+pub fn month_of(at: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let first = ReportInterval::Month.period_start(at);
+    let next = first
+        .checked_add_months(chrono::Months::new(1))
+        .expect("months after the first are in range");
+    let midnight = |day: NaiveDate| {
+        Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).expect("midnight is valid"))
+    };
+    (midnight(first), midnight(next))
+}
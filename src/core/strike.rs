@@ -0,0 +1,22 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StrikeReason {
+    /// Accepted the walk but never started it within the start window.
+    NoShow,
+    /// Dismissed by the owner after being accepted.
+    Dismissed,
+    /// Gave the walk up after accepting it.
+    Resigned,
+}
+
+/// A mark against a walker's reliability, at most one per request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Strike {
+    pub walker_id: String,
+    pub request_id: String,
+    pub reason: StrikeReason,
+    pub recorded_at: DateTime<Utc>,
+}
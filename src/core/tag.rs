@@ -0,0 +1,31 @@
+/// Tags a request may carry.
+pub const MAX_TAGS: usize = 10;
+
+pub const MAX_TAG_CHARS: usize = 20;
+
+/// Tags as stored and searched: trimmed, in lower case, without blanks or
+/// repeats, in the order given.
+pub fn normalize_tags<S: AsRef<str>>(tags: &[S]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags {
+        let tag = tag.as_ref().trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Whether a normalized tag may be stored: letters, digits, `-` and `_`,
+/// at most `MAX_TAG_CHARS` of them.
+pub fn is_valid_tag(tag: &str) -> bool {
+    tag.chars().count() <= MAX_TAG_CHARS
+        && tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Tags from a comma separated query parameter, e.g. `morning,park-route`.
+pub fn parse_tag_list(list: &str) -> Vec<String> {
+    normalize_tags(&list.split(',').collect::<Vec<_>>())
+}
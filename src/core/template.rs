@@ -0,0 +1,161 @@
+use chrono::{DateTime, Duration, Utc};
+use little_walk_dog::core::entities::Dog;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    entities::{TrackVisibility, WalkRequest},
+    metrics::DEFAULT_REGION,
+    repository::WalkRequestCreate,
+    service::TimeWindows,
+    timezone::DEFAULT_TIMEZONE,
+    units::{Meters, Money},
+    walker_capabilities::DogRequirements,
+};
+
+/// Templates an owner may keep.
+pub const MAX_TEMPLATES_PER_OWNER: usize = 20;
+
+pub const MAX_TEMPLATE_NAME_CHARS: usize = 50;
+
+/// Time windows of a request as minutes after it is posted, so that
+/// re-posting keeps them the same distance ahead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowOffsets {
+    pub start_after_minutes: Option<i64>,
+    pub start_before_minutes: Option<i64>,
+    pub end_after_minutes: Option<i64>,
+    pub end_before_minutes: Option<i64>,
+}
+
+impl WindowOffsets {
+    /// The windows of `request` relative to when it was created.
+    pub fn of(request: &WalkRequest) -> Self {
+        let created_at = request.created_at.unwrap_or_else(Utc::now);
+        let offset = |time: Option<DateTime<Utc>>| time.map(|t| (t - created_at).num_minutes());
+        Self {
+            start_after_minutes: offset(request.should_start_after),
+            start_before_minutes: offset(request.should_start_before),
+            end_after_minutes: offset(request.should_end_after),
+            end_before_minutes: offset(request.should_end_before),
+        }
+    }
+
+    /// The windows of a request posted at `time`.
+    pub fn at(&self, time: DateTime<Utc>) -> TimeWindows {
+        let shift = |minutes: Option<i64>| minutes.map(|m| time + Duration::minutes(m));
+        TimeWindows {
+            should_start_after: shift(self.start_after_minutes),
+            should_start_before: shift(self.start_before_minutes),
+            should_end_after: shift(self.end_after_minutes),
+            should_end_before: shift(self.end_before_minutes),
+        }
+    }
+}
+
+fn default_timezone() -> String {
+    DEFAULT_TIMEZONE.to_owned()
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_owned()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WalkRequestTemplateCreate {
+    pub name: String,
+    #[serde(default)]
+    pub windows: WindowOffsets,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+    pub max_walkers: Option<i64>,
+}
+
+/// A request an owner saved to post again in one tap.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkRequestTemplate {
+    pub id: String,
+    pub owner_id: String,
+    pub name: String,
+    pub windows: WindowOffsets,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub timezone: String,
+    pub region: String,
+    pub max_applicants: Option<i64>,
+    pub requirements: DogRequirements,
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+    pub max_walkers: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl WalkRequestTemplate {
+    pub fn new(id: String, owner_id: &str, create: WalkRequestTemplateCreate) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            owner_id: owner_id.to_owned(),
+            name: create.name.trim().to_owned(),
+            windows: create.windows,
+            dogs: create.dogs,
+            latitude: create.latitude,
+            longitude: create.longitude,
+            timezone: create.timezone,
+            region: create.region,
+            max_applicants: create.max_applicants,
+            requirements: create.requirements,
+            track_visibility: create.track_visibility,
+            max_radius: create.max_radius,
+            price: create.price,
+            max_walkers: create.max_walkers,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// The walk request of the template posted at `time`.
+    pub fn request_at(&self, time: DateTime<Utc>) -> WalkRequestCreate {
+        let windows = self.windows.at(time);
+        WalkRequestCreate {
+            dogs: self.dogs.clone(),
+            should_start_after: windows.should_start_after,
+            should_start_before: windows.should_start_before,
+            should_end_after: windows.should_end_after,
+            should_end_before: windows.should_end_before,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: self.timezone.clone(),
+            region: self.region.clone(),
+            max_applicants: self.max_applicants,
+            requirements: self.requirements,
+            track_visibility: self.track_visibility,
+            max_radius: self.max_radius,
+            price: self.price.clone(),
+            max_walkers: self.max_walkers,
+            apply_before: None,
+            created_by: self.owner_id.clone(),
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: None,
+            invitation_expires_at: None,
+            tags: Vec::new(),
+        }
+    }
+}
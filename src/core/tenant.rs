@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use super::{distance::DistanceStrategy, units::Meters};
+
+/// White-label partner or city configuration, selected by the `tenant` claim
+/// of the access token in JWT mode and by the `X-Tenant-ID` header
+/// otherwise, and handed to the partner's client apps through `/apis/meta`.
+///
+/// Only walk requests are kept apart by tenant. Applications, offers and
+/// messages are reached through their request, so a tenant's callers only
+/// get at those of its requests, but listings by user such as a walker's
+/// applications span tenants. Schedules, templates and saved searches
+/// belong to their user and aren't scoped either.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Tenant {
+    pub id: String,
+    pub branding_name: String,
+    pub support_contact: String,
+    pub pricing_table_id: Option<String>,
+    /// ISO 4217 code prices are charged in, the region's currency when unset.
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Areas served, each a closed ring of `[longitude, latitude]` points.
+    #[serde(default)]
+    pub service_polygons: Vec<Vec<[f64; 2]>>,
+    /// How walk distances are computed, the service default when unset.
+    #[serde(default)]
+    pub distance_strategy: Option<DistanceStrategy>,
+    /// Cap on the radius of nearby searches, the service's cap when unset.
+    #[serde(default)]
+    pub max_search_radius: Option<Meters>,
+}
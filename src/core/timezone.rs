@@ -0,0 +1,41 @@
+use anyhow::Error;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+pub fn parse_timezone(name: &str) -> Result<Tz, Error> {
+    name.parse::<Tz>()
+        .map_err(|_| Error::msg(format!("无效的时区: {}", name)))
+}
+
+/// Converts a stored UTC timestamp to the request's local time, falling back to
+/// UTC for requests created before the timezone was recorded.
+pub fn to_local(time: &DateTime<Utc>, timezone: Option<&str>) -> DateTime<Tz> {
+    let tz = timezone
+        .and_then(|name| parse_timezone(name).ok())
+        .unwrap_or(Tz::UTC);
+    time.with_timezone(&tz)
+}
+
+/// strftime pattern for human readable times in `locale` (an Accept-Language
+/// tag such as `zh-CN` or `en-US`).
+fn display_format(locale: &str) -> &'static str {
+    let locale = locale.to_ascii_lowercase();
+    if locale.starts_with("zh") {
+        "%Y年%-m月%-d日 %H:%M"
+    } else if locale == "en-us" || locale == "en" {
+        "%b %-d, %Y %-I:%M %p"
+    } else if locale.starts_with("en") {
+        "%-d %b %Y %H:%M"
+    } else {
+        "%Y-%m-%d %H:%M"
+    }
+}
+
+/// `time` in the request's timezone, formatted for `locale`.
+pub fn display(time: &DateTime<Utc>, timezone: Option<&str>, locale: &str) -> String {
+    to_local(time, timezone)
+        .format(display_format(locale))
+        .to_string()
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::currency::ExchangeRate;
+
+/// A distance in meters. Serialized as a plain number of meters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize, ToSchema)]
+#[serde(transparent)]
+pub struct Meters(pub f64);
+
+impl Meters {
+    pub fn from_kilometers(km: f64) -> Self {
+        Meters(km * 1000.0)
+    }
+
+    pub fn kilometers(self) -> f64 {
+        self.0 / 1000.0
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Meters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}m", self.0)
+    }
+}
+
+/// An amount of money in the currency's minor unit (e.g. fen for CNY), so
+/// prices never go through floating point.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Money {
+    pub minor_units: i64,
+    /// ISO 4217 code.
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: &str) -> Self {
+        Self {
+            minor_units,
+            currency: currency.to_owned(),
+        }
+    }
+
+    /// Scales the amount, rounding half away from zero to the minor unit.
+    pub fn scale(&self, factor: f64) -> Self {
+        Self {
+            minor_units: (self.minor_units as f64 * factor).round() as i64,
+            currency: self.currency.clone(),
+        }
+    }
+
+    /// The amount in `rate.to`, rounding half away from zero to its minor unit.
+    pub fn convert(&self, rate: &ExchangeRate) -> Self {
+        let digits = minor_unit_digits(&rate.to) as i32 - minor_unit_digits(&self.currency) as i32;
+        Self {
+            minor_units: (self.minor_units as f64 * rate.rate * 10f64.powi(digits)).round() as i64,
+            currency: rate.to.clone(),
+        }
+    }
+
+    /// Splits the amount into `parts` shares differing by one minor unit at
+    /// most, the first shares taking the remainder.
+    pub fn split(&self, parts: usize) -> Vec<Self> {
+        if parts == 0 {
+            return Vec::new();
+        }
+        let (share, remainder) = (
+            self.minor_units.div_euclid(parts as i64),
+            self.minor_units.rem_euclid(parts as i64),
+        );
+        (0..parts as i64)
+            .map(|i| Self::new(share + i64::from(i < remainder), &self.currency))
+            .collect()
+    }
+
+    /// `None` when the currencies differ.
+    pub fn checked_add(&self, other: &Money) -> Option<Self> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(Self {
+            minor_units: self.minor_units.checked_add(other.minor_units)?,
+            currency: self.currency.clone(),
+        })
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        let abs = self.minor_units.unsigned_abs();
+        let digits = minor_unit_digits(&self.currency);
+        if digits == 0 {
+            return write!(f, "{}{} {}", sign, abs, self.currency);
+        }
+        let unit = 10u64.pow(digits);
+        write!(
+            f,
+            "{}{}.{:0width$} {}",
+            sign,
+            abs / unit,
+            abs % unit,
+            self.currency,
+            width = digits as usize
+        )
+    }
+}
+
+/// Digits after the decimal point of the currency's minor unit, ISO 4217.
+pub fn minor_unit_digits(currency: &str) -> u32 {
+    match currency {
+        "JPY" | "KRW" | "VND" | "CLP" | "ISK" | "UGX" => 0,
+        "BHD" | "KWD" | "OMR" | "JOD" | "TND" | "IQD" | "LYD" => 3,
+        _ => 2,
+    }
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::Error;
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::security::find_key;
+
+/// Calls of one API key to one endpoint during one month, the unit usage is
+/// billed by. Calls rejected over the quota are counted apart and not billed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UsageRecord {
+    pub api_key: String,
+    /// Method and route pattern, e.g. `GET /apis/research/open_requests`.
+    pub endpoint: String,
+    /// `YYYY-MM`, in UTC.
+    pub month: String,
+    pub calls: u64,
+    pub rejected: u64,
+}
+
+pub fn month_of(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m").to_string()
+}
+
+/// Whether `month` is a `YYYY-MM` month.
+pub fn valid_month(month: &str) -> bool {
+    month.len() == 7 && NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").is_ok()
+}
+
+/// When the month of `time` ends and quotas start over.
+pub fn month_end(time: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = match time.month() {
+        12 => (time.year() + 1, 1),
+        m => (time.year(), m + 1),
+    };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0)
+        .single()
+        .expect("first of month")
+}
+
+/// Monthly call quotas of partner API keys, keys without one aren't
+/// metered.
+#[derive(Debug, Clone, Default)]
+pub struct MonthlyQuotas {
+    limits: Arc<HashMap<String, u64>>,
+}
+
+impl MonthlyQuotas {
+    /// Parses `key:limit,key:limit`.
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let mut limits = HashMap::new();
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (key, limit) = entry
+                .split_once(':')
+                .ok_or_else(|| Error::msg(format!("无效的配额配置: {}", entry)))?;
+            limits.insert(key.trim().to_owned(), limit.trim().parse()?);
+        }
+        Ok(Self {
+            limits: Arc::new(limits),
+        })
+    }
+
+    pub fn limit(&self, api_key: &str) -> Option<u64> {
+        find_key(self.limits.iter(), api_key).copied()
+    }
+}
+
+/// Where a metered key stands after a call, sent back as quota headers.
+#[derive(Debug, Clone, Copy)]
+pub struct QuotaStatus {
+    pub limit: u64,
+    /// Calls of the month so far, this one included unless it was rejected.
+    pub used: u64,
+    pub reset_at: DateTime<Utc>,
+    pub exceeded: bool,
+}
+
+impl QuotaStatus {
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.used)
+    }
+}
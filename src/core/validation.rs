@@ -0,0 +1,252 @@
+use serde::Serialize;
+
+use super::{
+    entities::MAX_POOL_WALKERS,
+    error::ServiceError,
+    message::{MessageCreate, MAX_MESSAGE_CHARS},
+    offer::OfferCreate,
+    repository::{WalkRequestCreate, WalkRequestUpdate, WalkingLocationCreate},
+    saved_search::{SavedSearchCreate, MAX_SAVED_SEARCH_NAME_CHARS},
+    service::RecordedLocation,
+    tag::{is_valid_tag, normalize_tags, MAX_TAGS, MAX_TAG_CHARS},
+    timezone::parse_timezone,
+    units::Money,
+};
+
+/// What is wrong with one field of a payload.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &'static str, message: &str) -> Self {
+        Self {
+            field,
+            message: message.to_owned(),
+        }
+    }
+}
+
+/// Payloads checked before they reach the repository.
+pub trait Validate {
+    /// Every problem found, empty when the payload is valid.
+    fn field_errors(&self) -> Vec<FieldError>;
+
+    fn validate(&self) -> Result<(), ServiceError> {
+        let errors = self.field_errors();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ServiceError::InvalidFields(errors))
+        }
+    }
+}
+
+fn check_coordinates(errors: &mut Vec<FieldError>, latitude: Option<f64>, longitude: Option<f64>) {
+    if latitude.map_or(false, |l| !(-90.0..=90.0).contains(&l)) {
+        errors.push(FieldError::new("latitude", "纬度超出范围"));
+    }
+    if longitude.map_or(false, |l| !(-180.0..=180.0).contains(&l)) {
+        errors.push(FieldError::new("longitude", "经度超出范围"));
+    }
+}
+
+fn check_price(errors: &mut Vec<FieldError>, price: &Money) {
+    if price.minor_units <= 0 {
+        errors.push(FieldError::new("price", "价格必须大于0"));
+    }
+    if price.currency.len() != 3 || !price.currency.chars().all(|c| c.is_ascii_uppercase()) {
+        errors.push(FieldError::new("price", "无效的币种"));
+    }
+}
+
+/// Checks the tags as they will be stored, see `normalize_tags`.
+fn check_tags(errors: &mut Vec<FieldError>, tags: &[String]) {
+    let tags = normalize_tags(tags);
+    if tags.len() > MAX_TAGS {
+        errors.push(FieldError {
+            field: "tags",
+            message: format!("标签不能超过{}个", MAX_TAGS),
+        });
+    }
+    if !tags.iter().all(|t| is_valid_tag(t)) {
+        errors.push(FieldError {
+            field: "tags",
+            message: format!(
+                "标签只能包含字母、数字、-和_，且不能超过{}个字符",
+                MAX_TAG_CHARS
+            ),
+        });
+    }
+}
+
+/// Checks the time windows, bounds left unset are not compared.
+fn check_windows<T: PartialOrd>(
+    errors: &mut Vec<FieldError>,
+    start_after: Option<T>,
+    start_before: Option<T>,
+    end_after: Option<T>,
+    end_before: Option<T>,
+) {
+    if matches!((&start_after, &start_before), (Some(a), Some(b)) if a >= b) {
+        errors.push(FieldError::new(
+            "should_start_before",
+            "开始时间范围起点不得大于等于终点",
+        ));
+    }
+    if matches!((&end_after, &end_before), (Some(a), Some(b)) if a >= b) {
+        errors.push(FieldError::new(
+            "should_end_before",
+            "结束时间范围起点不得大于等于终点",
+        ));
+    }
+    if matches!((&start_after, &end_before), (Some(a), Some(b)) if a >= b) {
+        errors.push(FieldError::new(
+            "should_end_before",
+            "结束时间不得早于开始时间",
+        ));
+    }
+}
+
+impl Validate for WalkRequestCreate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.dogs.is_empty() {
+            errors.push(FieldError::new("dogs", "至少需要一只狗"));
+        }
+        check_coordinates(&mut errors, Some(self.latitude), Some(self.longitude));
+        check_windows(
+            &mut errors,
+            self.should_start_after,
+            self.should_start_before,
+            self.should_end_after,
+            self.should_end_before,
+        );
+        if parse_timezone(&self.timezone).is_err() {
+            errors.push(FieldError::new("timezone", "无效的时区"));
+        }
+        if self.max_applicants.map_or(false, |m| m <= 0) {
+            errors.push(FieldError::new("max_applicants", "报名人数上限必须大于0"));
+        }
+        if matches!((self.apply_before, self.should_start_before), (Some(a), Some(b)) if a > b) {
+            errors.push(FieldError::new(
+                "apply_before",
+                "报名截止时间不得晚于最晚开始时间",
+            ));
+        }
+        if self.max_radius.map_or(false, |r| r.value() <= 0.0) {
+            errors.push(FieldError::new("max_radius", "活动半径必须大于0"));
+        }
+        if let Some(max_walkers) = self.max_walkers {
+            if max_walkers <= 0 {
+                errors.push(FieldError::new("max_walkers", "遛狗人数必须大于0"));
+            } else if max_walkers > MAX_POOL_WALKERS {
+                errors.push(FieldError::new(
+                    "max_walkers",
+                    &format!("遛狗人数不能超过{}", MAX_POOL_WALKERS),
+                ));
+            } else if max_walkers as usize > self.dogs.len() {
+                errors.push(FieldError::new("max_walkers", "遛狗人数不能多于狗的数量"));
+            }
+        }
+        if let Some(price) = &self.price {
+            check_price(&mut errors, price);
+        }
+        check_tags(&mut errors, &self.tags);
+        errors
+    }
+}
+
+impl Validate for WalkRequestUpdate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.dogs.as_ref().map_or(false, Vec::is_empty) {
+            errors.push(FieldError::new("dogs", "至少需要一只狗"));
+        }
+        check_coordinates(&mut errors, self.latitude, self.longitude);
+        check_windows(
+            &mut errors,
+            self.should_start_after,
+            self.should_start_before,
+            self.should_end_after,
+            self.should_end_before,
+        );
+        if self
+            .timezone
+            .as_deref()
+            .map_or(false, |t| parse_timezone(t).is_err())
+        {
+            errors.push(FieldError::new("timezone", "无效的时区"));
+        }
+        if let Some(tags) = &self.tags {
+            check_tags(&mut errors, tags);
+        }
+        errors
+    }
+}
+
+impl Validate for SavedSearchCreate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_SAVED_SEARCH_NAME_CHARS {
+            errors.push(FieldError {
+                field: "name",
+                message: format!(
+                    "搜索名称不能为空且不能超过{}个字符",
+                    MAX_SAVED_SEARCH_NAME_CHARS
+                ),
+            });
+        }
+        check_coordinates(&mut errors, Some(self.latitude), Some(self.longitude));
+        if self.radius.value() <= 0.0 {
+            errors.push(FieldError::new("radius", "搜索半径必须大于0"));
+        }
+        if matches!((self.min_dogs, self.max_dogs), (Some(min), Some(max)) if min > max) {
+            errors.push(FieldError::new("max_dogs", "狗的数量上限不得小于下限"));
+        }
+        check_tags(&mut errors, &self.tags);
+        errors
+    }
+}
+
+impl Validate for OfferCreate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        check_price(&mut errors, &self.price);
+        errors
+    }
+}
+
+impl Validate for WalkingLocationCreate<'_> {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        check_coordinates(&mut errors, Some(self.latitude), Some(self.longitude));
+        errors
+    }
+}
+
+impl Validate for MessageCreate {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.body.trim().is_empty() {
+            errors.push(FieldError::new("body", "消息不能为空"));
+        } else if self.body.chars().count() > MAX_MESSAGE_CHARS {
+            errors.push(FieldError {
+                field: "body",
+                message: format!("消息不能超过{}个字符", MAX_MESSAGE_CHARS),
+            });
+        }
+        errors
+    }
+}
+
+impl Validate for RecordedLocation {
+    fn field_errors(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        check_coordinates(&mut errors, Some(self.latitude), Some(self.longitude));
+        errors
+    }
+}
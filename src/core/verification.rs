@@ -0,0 +1,81 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::{
+    entities::{WalkRequest, WalkingLocation},
+    error::ServiceError,
+    geo::{distance, track_length},
+    units::Meters,
+};
+
+pub const LOCATION_REQUIRED: &str = "请提供当前位置";
+pub const TOO_FAR_FROM_PICKUP: &str = "距接狗地点太远，请到达后再开始";
+pub const WALK_TOO_SHORT: &str = "遛狗时间或距离过短，暂不能结束";
+
+/// Keeps walkers from starting a walk away from the pickup point or
+/// finishing one right after starting it. Limits of zero aren't checked,
+/// and none are once the owner waived them, see
+/// `WalkRequest::verification_waived_at`.
+#[derive(Debug, Clone, Copy)]
+pub struct WalkVerification {
+    /// How far from the pickup point a walk may be started.
+    pub start_radius: Meters,
+    pub min_duration: Duration,
+    /// Length the track must reach before the walk is finished.
+    pub min_distance: Meters,
+}
+
+impl Default for WalkVerification {
+    fn default() -> Self {
+        Self {
+            start_radius: Meters(0.0),
+            min_duration: Duration::zero(),
+            min_distance: Meters(0.0),
+        }
+    }
+}
+
+impl WalkVerification {
+    /// Whether the walker, at `location` as (longitude, latitude), may
+    /// start the request.
+    pub fn check_start(
+        &self,
+        request: &WalkRequest,
+        location: Option<(f64, f64)>,
+    ) -> Result<(), ServiceError> {
+        if self.start_radius.value() <= 0.0 || request.verification_waived_at.is_some() {
+            return Ok(());
+        }
+        let Some(location) = location else {
+            return Err(ServiceError::Validation(LOCATION_REQUIRED.to_owned()));
+        };
+        if distance((request.longitude, request.latitude), location) > self.start_radius {
+            return Err(ServiceError::Validation(TOO_FAR_FROM_PICKUP.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Whether the track is long enough to be read, see `check_finish`.
+    pub fn checks_track(&self, request: &WalkRequest) -> bool {
+        self.min_distance.value() > 0.0 && request.verification_waived_at.is_none()
+    }
+
+    /// Whether a walk started at `started_at` with `track` may be finished
+    /// at `now`. The track is only looked at when `checks_track`.
+    pub fn check_finish(
+        &self,
+        request: &WalkRequest,
+        started_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+        track: &[WalkingLocation],
+    ) -> Result<(), ServiceError> {
+        if request.verification_waived_at.is_some() {
+            return Ok(());
+        }
+        let too_short =
+            self.min_duration > Duration::zero() && now - started_at < self.min_duration;
+        if too_short || (self.checks_track(request) && track_length(track) < self.min_distance) {
+            return Err(ServiceError::Validation(WALK_TOO_SHORT.to_owned()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+
+use super::entities::WalkRequest;
+
+/// Labor-safety cap on the minutes a walker spends walking per UTC day. Only
+/// requests in `regions` are blocked, an empty list covers every region.
+#[derive(Debug, Clone)]
+pub struct WalkBudgetPolicy {
+    pub daily_minutes: i64,
+    pub regions: Vec<String>,
+}
+
+impl WalkBudgetPolicy {
+    pub fn covers(&self, region: Option<&str>) -> bool {
+        self.regions.is_empty() || region.map_or(false, |r| self.regions.iter().any(|c| c == r))
+    }
+}
+
+/// What a walker has used of today's budget.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalkBudget {
+    pub date: NaiveDate,
+    pub used_minutes: i64,
+    /// Unset when no cap is configured.
+    pub daily_minutes: Option<i64>,
+    pub remaining_minutes: Option<i64>,
+}
+
+/// Minutes of `walks` spent walking on `date`, walks still in progress count
+/// up to `now`.
+pub fn active_minutes(walks: &[WalkRequest], date: NaiveDate, now: DateTime<Utc>) -> i64 {
+    let day_start = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+    let day_end = day_start + Duration::days(1);
+    walks
+        .iter()
+        .filter_map(|w| {
+            let started_at = w.started_at?;
+            let finished_at = w.finished_at.unwrap_or(now);
+            let overlap = finished_at.min(day_end) - started_at.max(day_start);
+            (overlap > Duration::zero()).then(|| overlap.num_minutes())
+        })
+        .sum()
+}
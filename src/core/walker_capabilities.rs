@@ -0,0 +1,116 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use little_walk_dog::core::entities::Dog;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What the owner says the dogs of a request need from a walker.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default, ToSchema)]
+#[serde(default)]
+pub struct DogRequirements {
+    pub large_breed: bool,
+    pub puppy: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DogSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl DogSize {
+    /// The name sizes are stored under.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DogSize::Small => "small",
+            DogSize::Medium => "medium",
+            DogSize::Large => "large",
+        }
+    }
+}
+
+/// What a walker declares they can handle. Walkers who declared nothing take
+/// any number of dogs of any size and breed but neither large breeds nor
+/// puppies.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct WalkerCapabilities {
+    pub max_dogs: Option<i64>,
+    pub large_breeds: bool,
+    pub puppies: bool,
+    /// Sizes the walker takes, any when unset.
+    pub accepted_sizes: Option<Vec<DogSize>>,
+    /// Breeds the walker won't take, compared ignoring case.
+    pub excluded_breeds: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CapabilityMismatch {
+    TooManyDogs,
+    LargeBreed,
+    Puppy,
+    /// A dog is of a size the walker doesn't take.
+    DogSize,
+    /// A dog is of a breed the walker excluded.
+    ExcludedBreed,
+}
+
+/// A text field of a dog, dogs are owned by the dog service so their fields
+/// are read loosely.
+pub(crate) fn dog_field(dog: &Dog, field: &str) -> Option<String> {
+    serde_json::to_value(dog)
+        .ok()?
+        .get(field)?
+        .as_str()
+        .map(str::to_owned)
+}
+
+impl WalkerCapabilities {
+    /// Why the walker can't take a walk with `dogs` needing `requirements`.
+    /// Dogs without a known size or breed pass those checks.
+    pub fn mismatches(
+        &self,
+        dogs: &[Dog],
+        requirements: DogRequirements,
+    ) -> Vec<CapabilityMismatch> {
+        let mut mismatches = Vec::new();
+        if self.max_dogs.map_or(false, |max| dogs.len() as i64 > max) {
+            mismatches.push(CapabilityMismatch::TooManyDogs);
+        }
+        if requirements.large_breed && !self.large_breeds {
+            mismatches.push(CapabilityMismatch::LargeBreed);
+        }
+        if requirements.puppy && !self.puppies {
+            mismatches.push(CapabilityMismatch::Puppy);
+        }
+        if let Some(sizes) = &self.accepted_sizes {
+            if dogs.iter().any(|d| {
+                dog_field(d, "size")
+                    .and_then(|s| serde_json::from_value(serde_json::Value::String(s)).ok())
+                    .map_or(false, |size: DogSize| !sizes.contains(&size))
+            }) {
+                mismatches.push(CapabilityMismatch::DogSize);
+            }
+        }
+        if dogs.iter().any(|d| {
+            dog_field(d, "breed").map_or(false, |breed| {
+                self.excluded_breeds
+                    .iter()
+                    .any(|b| b.trim().eq_ignore_ascii_case(breed.trim()))
+            })
+        }) {
+            mismatches.push(CapabilityMismatch::ExcludedBreed);
+        }
+        mismatches
+    }
+}
+
+/// Source of walkers' capabilities when they are owned by the user service
+/// rather than stored with walk requests.
+#[async_trait]
+pub trait CapabilityDirectory: Send + Sync {
+    async fn capabilities(&self, user_id: &str) -> Result<WalkerCapabilities, Error>;
+}
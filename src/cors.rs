@@ -0,0 +1,115 @@
+use std::str::FromStr;
+
+use actix_cors::Cors;
+use actix_web::{
+    http::{
+        header::{
+            HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+            STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+        Method,
+    },
+    middleware::DefaultHeaders,
+};
+use anyhow::Error;
+
+/// Response headers browsers let scripts of other origins read.
+const EXPOSED_HEADERS: [&str; 6] = [
+    "etag",
+    "retry-after",
+    "content-language",
+    "x-quota-limit",
+    "x-quota-remaining",
+    "x-quota-reset",
+];
+
+/// Which other origins may call the API from a browser. Without origins
+/// cross-origin calls stay blocked by the browser.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    /// `None` allows any origin.
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    headers: Vec<HeaderName>,
+    max_age: usize,
+}
+
+fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+impl CorsPolicy {
+    /// Parses comma separated lists, `*` as the origins allows any.
+    pub fn parse(
+        origins: &str,
+        methods: &str,
+        headers: &str,
+        max_age_seconds: usize,
+    ) -> Result<Self, Error> {
+        let origins = if origins.trim() == "*" {
+            None
+        } else {
+            Some(
+                split(origins)
+                    .map(|origin| {
+                        let valid = (origin.starts_with("https://")
+                            || origin.starts_with("http://"))
+                            && HeaderValue::from_str(origin).is_ok();
+                        if !valid {
+                            return Err(Error::msg(format!("无效的来源: {}", origin)));
+                        }
+                        Ok(origin.trim_end_matches('/').to_owned())
+                    })
+                    .collect::<Result<_, _>>()?,
+            )
+        };
+        let methods = split(methods)
+            .map(|m| Method::from_str(&m.to_uppercase()))
+            .collect::<Result<_, _>>()?;
+        let headers = split(headers)
+            .map(HeaderName::from_str)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            origins,
+            methods,
+            headers,
+            max_age: max_age_seconds,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.origins.as_ref().map_or(true, |o| !o.is_empty())
+    }
+
+    /// The middleware enforcing the policy. Requests carry bearer tokens
+    /// rather than cookies, so credentials are never allowed.
+    pub fn middleware(&self) -> Cors {
+        let cors = match &self.origins {
+            None => Cors::default().allow_any_origin(),
+            Some(origins) => origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin)),
+        };
+        cors.allowed_methods(self.methods.clone())
+            .allowed_headers(self.headers.clone())
+            .expose_headers(EXPOSED_HEADERS)
+            .max_age(self.max_age)
+    }
+}
+
+/// Headers hardening every response: no sniffing, framing or referrers.
+/// HSTS is only sent with a positive max age, when served over HTTPS.
+pub fn security_headers(hsts_max_age_seconds: u64) -> DefaultHeaders {
+    let headers = DefaultHeaders::new()
+        .add((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .add((X_FRAME_OPTIONS, "DENY"))
+        .add((REFERRER_POLICY, "no-referrer"))
+        .add((CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"));
+    if hsts_max_age_seconds == 0 {
+        return headers;
+    }
+    headers.add((
+        STRICT_TRANSPORT_SECURITY,
+        format!("max-age={}; includeSubDomains", hsts_max_age_seconds),
+    ))
+}
@@ -0,0 +1,42 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use lettre::{
+    message::header::ContentType, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::core::email::{EmailMessage, EmailSender};
+
+pub struct Smtp {
+    from: String,
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Smtp {
+    pub fn new(host: &str, username: &str, password: &str, from: &str) -> Result<Self, Error> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::relay(host)?
+            .credentials(Credentials::new(username.to_owned(), password.to_owned()))
+            .build();
+        Ok(Self {
+            from: from.to_owned(),
+            transport,
+        })
+    }
+}
+
+#[async_trait]
+impl EmailSender for Smtp {
+    async fn send(&self, message: EmailMessage) -> Result<(), Error> {
+        let email = Message::builder()
+            .from(self.from.parse()?)
+            .to(message.to.parse()?)
+            .subject(message.subject)
+            .header(ContentType::TEXT_HTML)
+            .body(message.html)?;
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| Error::new(e).context("发送邮件失败"))?;
+        Ok(())
+    }
+}
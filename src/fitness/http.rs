@@ -0,0 +1,138 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+
+use crate::core::fitness::{FitnessActivity, FitnessProvider, FitnessToken};
+
+/// A provider speaking standard OAuth 2 at `token_url` and taking activities
+/// as JSON at `upload_url`.
+pub struct HttpFitness {
+    name: String,
+    token_url: String,
+    upload_url: String,
+    client_id: String,
+    client_secret: String,
+    client: reqwest::Client,
+}
+
+impl HttpFitness {
+    /// Parses `name|token_url|upload_url|client_id|client_secret` entries
+    /// separated by `;`.
+    pub fn parse_all(spec: &str) -> Result<Vec<Self>, Error> {
+        spec.split(';')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(|entry| {
+                let parts: Vec<&str> = entry.split('|').map(str::trim).collect();
+                let [name, token_url, upload_url, client_id, client_secret] = parts[..] else {
+                    return Err(Error::msg(format!("无效的健身平台配置: {}", entry)));
+                };
+                Ok(Self {
+                    name: name.to_owned(),
+                    token_url: token_url.to_owned(),
+                    upload_url: upload_url.to_owned(),
+                    client_id: client_id.to_owned(),
+                    client_secret: client_secret.to_owned(),
+                    client: reqwest::Client::new(),
+                })
+            })
+            .collect()
+    }
+
+    async fn grant(&self, user_id: &str, form: &[(&str, &str)]) -> Result<FitnessToken, Error> {
+        let grant = self
+            .client
+            .post(&self.token_url)
+            .form(
+                &[
+                    ("client_id", self.client_id.as_str()),
+                    ("client_secret", self.client_secret.as_str()),
+                ]
+                .iter()
+                .chain(form)
+                .collect::<Vec<_>>(),
+            )
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Grant>()
+            .await?;
+        Ok(FitnessToken {
+            user_id: user_id.to_owned(),
+            provider: self.name.clone(),
+            access_token: grant.access_token,
+            refresh_token: grant.refresh_token,
+            expires_at: grant.expires_in.map(|s| Utc::now() + Duration::seconds(s)),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Grant {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Uploaded {
+    id: serde_json::Value,
+}
+
+#[async_trait]
+impl FitnessProvider for HttpFitness {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn authorize(&self, user_id: &str, code: &str) -> Result<FitnessToken, Error> {
+        self.grant(
+            user_id,
+            &[("grant_type", "authorization_code"), ("code", code)],
+        )
+        .await
+    }
+
+    async fn refresh(&self, token: &FitnessToken) -> Result<FitnessToken, Error> {
+        let refresh_token = token
+            .refresh_token
+            .as_deref()
+            .ok_or_else(|| Error::msg("授权已过期，请重新连接"))?;
+        let mut refreshed = self
+            .grant(
+                &token.user_id,
+                &[
+                    ("grant_type", "refresh_token"),
+                    ("refresh_token", refresh_token),
+                ],
+            )
+            .await?;
+        // Providers may keep the refresh token and leave it out.
+        if refreshed.refresh_token.is_none() {
+            refreshed.refresh_token = token.refresh_token.clone();
+        }
+        Ok(refreshed)
+    }
+
+    async fn upload(
+        &self,
+        token: &FitnessToken,
+        activity: &FitnessActivity,
+    ) -> Result<String, Error> {
+        let uploaded = self
+            .client
+            .post(&self.upload_url)
+            .bearer_auth(&token.access_token)
+            .json(activity)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Uploaded>()
+            .await?;
+        Ok(match uploaded.id {
+            serde_json::Value::String(id) => id,
+            other => other.to_string(),
+        })
+    }
+}
@@ -0,0 +1,5 @@
+pub mod server;
+
+pub mod proto {
+    tonic::include_proto!("little_walk.request.v1");
+}
@@ -0,0 +1,282 @@
+use chrono::{DateTime, TimeZone, Utc};
+use prost_types::Timestamp;
+use tonic::{Request, Response, Status};
+
+use super::proto::{
+    walk_requests_server::WalkRequests, CreateWalkRequestRequest, CreateWalkRequestResponse, Empty,
+    FinishWalkRequest, NearbyRequest, NearbyResponse, RecordLocationRequest,
+    RecordLocationResponse, StartWalkRequest, WalkRequest as WalkRequestMessage, WalkRequestId,
+};
+use crate::core::{
+    delegation::ServiceClients,
+    entities::WalkRequest,
+    error::ServiceError,
+    location_filter::LocationWrite,
+    metrics::DEFAULT_REGION,
+    repository::{NearbyFilter, Pagination, Repository, WalkRequestCreate},
+    service::Service,
+    timezone::DEFAULT_TIMEZONE,
+    units::Meters,
+    validation::Validate,
+};
+
+/// The gRPC face of `Service`, for internal clients acting for users.
+pub struct GrpcServer<R> {
+    service: Service<R>,
+    clients: ServiceClients,
+}
+
+impl<R> GrpcServer<R>
+where
+    R: Repository + Clone,
+{
+    pub fn new(service: Service<R>, clients: ServiceClients) -> Self {
+        Self { service, clients }
+    }
+
+    /// The service acting for the `x-user-id` of the call, and that user.
+    fn caller<T>(&self, request: &Request<T>) -> Result<(Service<R>, String), Status> {
+        let metadata = request.metadata();
+        let client = metadata
+            .get("x-api-key")
+            .and_then(|k| k.to_str().ok())
+            .and_then(|k| self.clients.client(k))
+            .ok_or_else(|| Status::unauthenticated("无效的API密钥"))?;
+        let user_id = metadata
+            .get("x-user-id")
+            .and_then(|u| u.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("无权限"))?;
+        Ok((
+            self.service.on_behalf_of(client, user_id),
+            user_id.to_owned(),
+        ))
+    }
+}
+
+impl From<ServiceError> for Status {
+    fn from(e: ServiceError) -> Self {
+        let message = e.to_string();
+        match e {
+            ServiceError::NotFound(_) => Status::not_found(message),
+            ServiceError::Conflict(_) => Status::failed_precondition(message),
+            ServiceError::Validation(_) | ServiceError::InvalidFields(_) => {
+                Status::invalid_argument(message)
+            }
+            ServiceError::Unauthorized(_) => Status::permission_denied(message),
+            ServiceError::OnboardingIncomplete(_) => Status::permission_denied(message),
+            ServiceError::CapabilityMismatch(_) => Status::failed_precondition(message),
+            ServiceError::FeatureDisabled(_) => Status::permission_denied(message),
+            ServiceError::SpendingCapExceeded(_) => Status::failed_precondition(message),
+            ServiceError::Overloaded(_) => Status::unavailable(message),
+            ServiceError::Internal(_) => Status::internal(message),
+        }
+    }
+}
+
+fn timestamp(time: Option<DateTime<Utc>>) -> Option<Timestamp> {
+    time.map(|t| Timestamp {
+        seconds: t.timestamp(),
+        nanos: t.timestamp_subsec_nanos() as i32,
+    })
+}
+
+fn datetime(timestamp: Option<Timestamp>) -> Option<DateTime<Utc>> {
+    timestamp.and_then(|t| Utc.timestamp_opt(t.seconds, t.nanos as u32).single())
+}
+
+impl TryFrom<WalkRequest> for WalkRequestMessage {
+    type Error = Status;
+
+    fn try_from(request: WalkRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            dogs_json: serde_json::to_string(&request.dogs)
+                .map_err(|e| Status::internal(e.to_string()))?,
+            id: request.id,
+            should_start_after: timestamp(request.should_start_after),
+            should_start_before: timestamp(request.should_start_before),
+            should_end_after: timestamp(request.should_end_after),
+            should_end_before: timestamp(request.should_end_before),
+            latitude: request.latitude,
+            longitude: request.longitude,
+            timezone: request.timezone,
+            region: request.region,
+            distance: request.distance.map(Meters::value),
+            status: format!("{:?}", request.status),
+            created_by: request.created_by,
+            accepted_by: request.accepted_by,
+            acceptances: request.acceptances.unwrap_or_default(),
+            accepted_at: timestamp(request.accepted_at),
+            started_at: timestamp(request.started_at),
+            finished_at: timestamp(request.finished_at),
+            canceled_at: timestamp(request.canceled_at),
+            created_at: timestamp(request.created_at),
+            updated_at: timestamp(request.updated_at),
+        })
+    }
+}
+
+fn reply(request: WalkRequest) -> Result<Response<WalkRequestMessage>, Status> {
+    Ok(Response::new(request.try_into()?))
+}
+
+#[tonic::async_trait]
+impl<R> WalkRequests for GrpcServer<R>
+where
+    R: Repository + Clone + Send + Sync + 'static,
+{
+    async fn create_walk_request(
+        &self,
+        request: Request<CreateWalkRequestRequest>,
+    ) -> Result<Response<CreateWalkRequestResponse>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        let body = request.into_inner();
+        let non_empty = |s: String, default: &str| {
+            if s.is_empty() {
+                default.to_owned()
+            } else {
+                s
+            }
+        };
+        let create = WalkRequestCreate {
+            dogs: serde_json::from_str(&body.dogs_json)
+                .map_err(|e| Status::invalid_argument(format!("无效的狗信息: {}", e)))?,
+            should_start_after: datetime(body.should_start_after),
+            should_start_before: datetime(body.should_start_before),
+            should_end_before: datetime(body.should_end_before),
+            should_end_after: datetime(body.should_end_after),
+            latitude: body.latitude,
+            longitude: body.longitude,
+            timezone: non_empty(body.timezone, DEFAULT_TIMEZONE),
+            region: non_empty(body.region, DEFAULT_REGION),
+            max_applicants: body.max_applicants,
+            requirements: Default::default(),
+            track_visibility: Default::default(),
+            max_radius: None,
+            price: None,
+            max_walkers: None,
+            apply_before: None,
+            created_by: user_id,
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: None,
+            invitation_expires_at: None,
+            tags: Vec::new(),
+        };
+        create.validate()?;
+        let id = service.create_walk_request(create).await?;
+        Ok(Response::new(CreateWalkRequestResponse { id }))
+    }
+
+    async fn get_walk_request(
+        &self,
+        request: Request<WalkRequestId>,
+    ) -> Result<Response<WalkRequestMessage>, Status> {
+        let (service, _) = self.caller(&request)?;
+        match service.get_walk_request(&request.into_inner().id).await? {
+            Some(walk_request) => reply(walk_request),
+            None => Err(Status::not_found("代遛请求不存在")),
+        }
+    }
+
+    async fn nearby(
+        &self,
+        request: Request<NearbyRequest>,
+    ) -> Result<Response<NearbyResponse>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        let params = request.into_inner();
+        let paged = service
+            .nearby_walk_requests(
+                params.latitude,
+                params.longitude,
+                Meters(params.radius),
+                Some(&user_id),
+                NearbyFilter::default(),
+                Pagination::new(params.page, params.size),
+                None,
+            )
+            .await?;
+        Ok(Response::new(NearbyResponse {
+            items: paged
+                .items
+                .into_iter()
+                .map(WalkRequestMessage::try_from)
+                .collect::<Result<_, _>>()?,
+            total: paged.total,
+            has_more: paged.has_more,
+        }))
+    }
+
+    async fn accept(
+        &self,
+        request: Request<WalkRequestId>,
+    ) -> Result<Response<WalkRequestMessage>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        reply(service.accept(&request.into_inner().id, &user_id).await?)
+    }
+
+    async fn apply(&self, request: Request<WalkRequestId>) -> Result<Response<Empty>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        service.apply(&request.into_inner().id, &user_id).await?;
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn start_walk(
+        &self,
+        request: Request<StartWalkRequest>,
+    ) -> Result<Response<WalkRequestMessage>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        let start = request.into_inner();
+        reply(
+            service
+                .start_walk(
+                    &start.id,
+                    &user_id,
+                    start.longitude.zip(start.latitude),
+                    datetime(start.occurred_at),
+                )
+                .await?,
+        )
+    }
+
+    async fn record_location(
+        &self,
+        request: Request<RecordLocationRequest>,
+    ) -> Result<Response<RecordLocationResponse>, Status> {
+        let (service, _) = self.caller(&request)?;
+        let location = request.into_inner();
+        let write = service
+            .record_walking_location(
+                &location.walk_request_id,
+                location.longitude,
+                location.latitude,
+                datetime(location.recorded_at),
+            )
+            .await?;
+        // The id is empty when the location was queued or coalesced.
+        Ok(Response::new(match write {
+            LocationWrite::Stored(id) => RecordLocationResponse {
+                id,
+                coalesced: false,
+            },
+            LocationWrite::Queued => RecordLocationResponse::default(),
+            LocationWrite::Coalesced => RecordLocationResponse {
+                id: String::new(),
+                coalesced: true,
+            },
+        }))
+    }
+
+    async fn finish_walk(
+        &self,
+        request: Request<FinishWalkRequest>,
+    ) -> Result<Response<WalkRequestMessage>, Status> {
+        let (service, user_id) = self.caller(&request)?;
+        let finish = request.into_inner();
+        reply(
+            service
+                .finish_walk(&finish.id, &user_id, datetime(finish.occurred_at))
+                .await?,
+        )
+    }
+}
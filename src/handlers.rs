@@ -1,17 +1,176 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
 use actix_web::{
-    error::{Error, ErrorInternalServerError, ErrorUnauthorized},
-    web::{Data, Json, Path, Query},
-    FromRequest, HttpRequest, HttpResponse, Result,
+    error::{
+        Error, ErrorBadRequest, ErrorForbidden, ErrorInternalServerError, ErrorTooManyRequests,
+        ErrorUnauthorized,
+    },
+    http::{
+        header::{
+            ContentEncoding, ACCEPT, AUTHORIZATION, CACHE_CONTROL, CONTENT_DISPOSITION,
+            CONTENT_LANGUAGE, ETAG, IF_MATCH, IF_NONE_MATCH, RETRY_AFTER,
+        },
+        StatusCode,
+    },
+    rt::time::timeout,
+    web::{Bytes, Data, Json, Path, Query},
+    FromRequest, HttpRequest, HttpResponse, ResponseError, Result,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{
+    future::{ready, Ready},
+    stream::{self, Stream},
+    StreamExt,
 };
-use futures::future::{ready, Ready};
 
 use crate::core::{
-    entities::WalkRequest,
-    repository::{Pagination, Repository, WalkRequestCreate},
-    service::Service,
+    admin_query::AdminQuery,
+    audit::AuditVerification,
+    auth::{Authenticator, Role},
+    backfill::{BackfillDefinition, BackfillRun},
+    bulk::BulkUpdateReport,
+    calendar::{render_ics, CalendarTokenSigner},
+    card::SummaryCard,
+    delegation::ServiceClients,
+    entities::{Application, TrackVisibility, WalkRequest, WalkingLocation},
+    error::ServiceError,
+    events::WalkRequestEvent,
+    export::{self, ExportFormat},
+    favorite::FavoriteWalker,
+    feature_flags::{Feature, FeatureFlag, FeatureFlagUpdate},
+    feed::{atom, AtomEntry},
+    filter::parse_filter,
+    geo::{BoundingBox, WalkProgress, WalkSummary},
+    geojson,
+    holiday::Holiday,
+    i18n::{self, Locale},
+    import::{DumpFormat, FieldMapping, ImportReport},
+    location_filter::LocationWrite,
+    message::{Message, MessageCreate},
+    meta::Capabilities,
+    metrics::{DailyMetrics, FunnelMetrics},
+    offer::{Offer, OfferCreate},
+    onboarding::OnboardingStep,
+    outbox::OutboxMessage,
+    payment::Payment,
+    preview::WalkRequestPreview,
+    receipt::{Payout, Receipt},
+    recompute::{RecomputeProgress, RecomputeScope},
+    reconcile::ReconcileReport,
+    report::{ReportInterval, ReportRole, WalkReport},
+    repository::{
+        NearbyFilter, Order, Paged, Pagination, Repository, SortBy, WalkRequestQuery,
+        WalkRequestUpdate,
+    },
+    reputation::Reputation,
+    research::{ApiQuotas, AreaHourCount, QuotaError},
+    retention::PurgeReport,
+    saga::BookingSaga,
+    saved_search::SavedSearch,
+    schedule::WalkSchedule,
+    security::{KeyInfo, KeyRotation},
+    service::{
+        ApplicantSelection, HistoryFilter, LocationBatchReport, MyWalkRequestsFilter,
+        RecordedLocation, Service, TimeWindows, WalkRequestEdit,
+    },
+    session::{DeviceSession, DeviceSessionRegister},
+    simulation::{RegionSimulation, SimulationParams},
+    spending::{MonthlySpending, SpendingCap},
+    strike::Strike,
+    tag::parse_tag_list,
+    template::WalkRequestTemplate,
+    tenant::Tenant,
+    units::{Meters, Money},
+    validation::{FieldError, Validate},
+    walk_budget::WalkBudget,
+    walker_capabilities::{CapabilityMismatch, WalkerCapabilities},
+};
+use crate::supervisor::{Supervisor, WorkerHealth};
+
+pub(crate) mod dto;
+
+use dto::{
+    BulkUpdateBody, CreateWalkRequestBody, EditWalkRequestBody, SavedSearchBody, TenantBody,
+    WalkRequestTemplateBody, WalkScheduleBody,
 };
 
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+/// The caller's id and roles, from the bearer token in JWT mode or from the
+/// gateway headers in header mode. Nobody is identified without an
+/// `Authenticator`.
+pub(crate) fn principal(req: &HttpRequest) -> Result<(String, Vec<Role>)> {
+    match req.app_data::<Data<Authenticator>>().map(|a| a.as_ref()) {
+        Some(Authenticator::Jwt(verifier)) => {
+            let token = req
+                .headers()
+                .get(AUTHORIZATION)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .ok_or(ErrorUnauthorized("无权限"))?;
+            let claims = verifier
+                .verify(token)
+                .map_err(|_| ErrorUnauthorized("无效的访问令牌"))?;
+            Ok((claims.sub, claims.roles))
+        }
+        Some(Authenticator::Header) => {
+            let user_id = req
+                .headers()
+                .get("X-User-ID")
+                .ok_or(ErrorUnauthorized("无权限"))?
+                .to_str()
+                .map_err(ErrorUnauthorized)?;
+            let roles = req
+                .headers()
+                .get("X-User-Role")
+                .and_then(|role| role.to_str().ok())
+                .and_then(Role::parse)
+                .into_iter()
+                .collect();
+            Ok((user_id.to_owned(), roles))
+        }
+        None => Err(ErrorUnauthorized("无权限")),
+    }
+}
+
+/// The caller's device, named by the `X-Device-ID` header.
+fn device_id(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("X-Device-ID")
+        .and_then(|id| id.to_str().ok())
+}
+
+/// Whether the client asked not to be tracked, by `Sec-GPC` or `DNT`.
+fn tracking_opted_out(req: &HttpRequest) -> bool {
+    ["Sec-GPC", "DNT"]
+        .iter()
+        .any(|name| req.headers().get(*name).map_or(false, |v| v == "1"))
+}
+
+/// The tenant of the caller. In JWT mode only the `tenant` claim of the
+/// access token counts, the `X-Tenant-ID` header would let any caller pick
+/// a tenant. Otherwise the header.
+pub(crate) fn tenant_id(req: &HttpRequest) -> Option<String> {
+    if let Some(Authenticator::Jwt(verifier)) =
+        req.app_data::<Data<Authenticator>>().map(|a| a.as_ref())
+    {
+        return req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .and_then(|token| verifier.verify(token).ok())
+            .and_then(|claims| claims.tenant);
+    }
+    req.headers()
+        .get("X-Tenant-ID")
+        .and_then(|id| id.to_str().ok())
+        .map(str::to_owned)
+}
 
 pub(crate) struct UserID(String);
 
@@ -20,234 +179,3125 @@ impl FromRequest for UserID {
     type Future = Ready<Result<Self, Self::Error>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
-        if let Some(user_id) = req.headers().get("X-User-ID") {
-            match user_id.to_str() {
-                Ok(user_id) => return ready(Ok(UserID(user_id.to_owned()))),
-                Err(e) => return ready(Err(ErrorUnauthorized(e))),
-            }
+        ready(principal(req).map(|(user_id, _)| UserID(user_id)))
+    }
+}
+
+pub(crate) struct Admin(String);
+
+impl FromRequest for Admin {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        match principal(req) {
+            Ok((user_id, roles)) if roles.contains(&Role::Admin) => ready(Ok(Admin(user_id))),
+            _ => ready(Err(ErrorForbidden("无权限"))),
+        }
+    }
+}
+
+/// An internal service authenticated by its `X-Api-Key`.
+pub(crate) struct ServiceClient(String);
+
+impl FromRequest for ServiceClient {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        let client = req
+            .app_data::<Data<ServiceClients>>()
+            .zip(req.headers().get("X-Api-Key"))
+            .and_then(|(clients, key)| clients.client(key.to_str().ok()?).map(str::to_owned));
+        match client {
+            Some(client) => ready(Ok(ServiceClient(client))),
+            None => ready(Err(ErrorUnauthorized("无效的API密钥"))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody<'a> {
+    code: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    missing_steps: Option<&'a [OnboardingStep]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mismatches: Option<&'a [CapabilityMismatch]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry_after_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<Vec<FieldError>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spending: Option<&'a MonthlySpending>,
+}
+
+impl ResponseError for ServiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServiceError::Conflict(_) => StatusCode::CONFLICT,
+            ServiceError::Validation(_) => StatusCode::BAD_REQUEST,
+            ServiceError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            ServiceError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ServiceError::OnboardingIncomplete(_) => StatusCode::FORBIDDEN,
+            ServiceError::CapabilityMismatch(_) => StatusCode::CONFLICT,
+            ServiceError::FeatureDisabled(_) => StatusCode::FORBIDDEN,
+            ServiceError::SpendingCapExceeded(_) => StatusCode::CONFLICT,
+            // Nothing was stored, but the client isn't at fault either.
+            ServiceError::Overloaded(_) => StatusCode::ACCEPTED,
+            ServiceError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ServiceError::Internal(e) = self {
+            log::error!("{:?}", e);
+        }
+        localized_error_response(self, Locale::default())
+    }
+}
+
+/// The error response in `locale`, see `ResponseError::error_response`.
+pub(crate) fn localized_error_response(error: &ServiceError, locale: Locale) -> HttpResponse {
+    let retry_after_seconds = match error {
+        ServiceError::Overloaded(after) => Some(after.as_secs().max(1)),
+        _ => None,
+    };
+    let mut response = HttpResponse::build(error.status_code());
+    if let Some(seconds) = retry_after_seconds {
+        response.insert_header((RETRY_AFTER, seconds));
+    }
+    response.insert_header((CONTENT_LANGUAGE, locale.tag()));
+    response.json(ErrorBody {
+        code: error.code(),
+        reason: error.reason(),
+        message: error.message(locale),
+        missing_steps: match error {
+            ServiceError::OnboardingIncomplete(steps) => Some(steps),
+            _ => None,
+        },
+        mismatches: match error {
+            ServiceError::CapabilityMismatch(mismatches) => Some(mismatches),
+            _ => None,
+        },
+        retry_after_seconds,
+        fields: match error {
+            ServiceError::InvalidFields(fields) => Some(
+                fields
+                    .iter()
+                    .map(|f| FieldError {
+                        field: f.field,
+                        message: i18n::localize(&f.message, "validation", locale),
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        },
+        spending: match error {
+            ServiceError::SpendingCapExceeded(spending) => Some(spending),
+            _ => None,
+        },
+    })
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub(crate) struct SpendingCapParams {
+    /// Goes ahead with a price over the owner's monthly spending cap, which
+    /// is otherwise answered with `spending_cap_exceeded`.
+    #[serde(default)]
+    confirm_over_cap: bool,
+}
+
+impl SpendingCapParams {
+    fn service<R>(&self, service: &Service<R>) -> Service<R>
+    where
+        R: Repository + Clone,
+    {
+        if self.confirm_over_cap {
+            service.confirming_over_cap()
+        } else {
+            service.clone()
         }
-        ready(Err(ErrorUnauthorized("无权限")))
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/apis/walk_requests",
+    request_body = CreateWalkRequestBody,
+    params(SpendingCapParams),
+    responses((status = 200, description = "已创建")),
+    tag = "walk_requests"
+)]
 pub(crate) async fn create_walk_request<R>(
     service: Data<Service<R>>,
     UserID(user_id): UserID,
-    Json(mut body): Json<WalkRequestCreate>,
+    Query(params): Query<SpendingCapParams>,
+    Json(body): Json<CreateWalkRequestBody>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
-    body.created_by = user_id;
-    service
-        .create_walk_request(body)
+    let create = body.into_create(user_id);
+    create.validate().map_err(Error::from)?;
+    params
+        .service(&service)
+        .create_walk_request(create)
         .await
-        .map_err(ErrorInternalServerError)?;
+        .map_err(Error::from)?;
     Ok(HttpResponse::Ok().finish())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Validates a draft and estimates its reach without posting it, errors
+/// are reported in the body rather than as a failed response.
+pub(crate) async fn preview_walk_request<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Json(body): Json<CreateWalkRequestBody>,
+) -> Result<Json<WalkRequestPreview>>
+where
+    R: Repository + Clone,
+{
+    service
+        .preview_walk_request(body.into_create(user_id))
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/apis/walk_requests/{id}",
+    params(
+        ("id" = String, Path, description = "代遛请求ID"),
+        ("If-Match" = Option<String>, Header, description = "修改所基于的版本"),
+    ),
+    request_body = EditWalkRequestBody,
+    responses(
+        (status = 200, body = WalkRequest),
+        (status = 409, description = "代遛请求已被修改或不在等待接单状态"),
+    ),
+    tag = "walk_requests"
+)]
+pub(crate) async fn edit_walk_request<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    req: HttpRequest,
+    path: Path<(String,)>,
+    Json(body): Json<EditWalkRequestBody>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let mut edit = WalkRequestEdit::from(body);
+    if edit.expected_version.is_none() {
+        edit.expected_version = match req.headers().get(IF_MATCH) {
+            Some(tag) => Some(parse_version_tag(tag.to_str().unwrap_or_default())?),
+            None => None,
+        };
+    }
+    let request = service
+        .edit_walk_request(&path.0, &user_id, edit)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .insert_header((ETAG, format!("\"{}\"", request.version)))
+        .json(request))
+}
+
+/// The version of an `If-Match` entity tag such as `"3"` or `W/"3"`. Tags
+/// of reads may carry a suffix after the version, see `walk_request_tag`.
+fn parse_version_tag(tag: &str) -> Result<i64> {
+    let tag = tag.trim().trim_start_matches("W/").trim_matches('"');
+    tag.split_once('-')
+        .map_or(tag, |(version, _)| version)
+        .parse()
+        .map_err(|_| Error::from(ServiceError::Validation("无效的版本号".to_owned())))
+}
+
+/// The entity tag of a walk request as read by a user: its version, which
+/// `If-Match` takes, and the unread message count shown to them.
+fn walk_request_tag(request: &WalkRequest) -> String {
+    match request.unread_messages {
+        Some(unread) => format!("\"{}-{}\"", request.version, unread),
+        None => format!("\"{}\"", request.version),
+    }
+}
+
+/// `body` tagged with `tag`, or an empty 304 when the client's copy, named
+/// by `If-None-Match`, is current. Clients always have to revalidate.
+fn conditional<T: Serialize>(req: &HttpRequest, tag: String, body: &T) -> HttpResponse {
+    let current = req
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|tags| tags.to_str().ok())
+        .map_or(false, |tags| {
+            tags.split(',')
+                .map(|t| t.trim().trim_start_matches("W/"))
+                .any(|t| t == "*" || t == tag)
+        });
+    let mut response = if current {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((ETAG, tag))
+        .insert_header((CACHE_CONTROL, "private, no-cache"));
+    if current {
+        response.finish()
+    } else {
+        response.json(body)
+    }
+}
+
+pub(crate) async fn duplicate_walk_request<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Query(params): Query<SpendingCapParams>,
+) -> Result<Json<String>>
+where
+    R: Repository + Clone,
+{
+    params
+        .service(&service)
+        .duplicate_walk_request(&path.0, &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn clone_walk_request<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Query(params): Query<SpendingCapParams>,
+    Json(windows): Json<TimeWindows>,
+) -> Result<Json<String>>
+where
+    R: Repository + Clone,
+{
+    params
+        .service(&service)
+        .clone_walk_request(&path.0, &user_id, windows)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/{id}",
+    params(
+        ("id" = String, Path, description = "代遛请求ID或参考编号"),
+        ("If-None-Match" = Option<String>, Header, description = "已有副本的ETag"),
+    ),
+    responses(
+        (status = 200, body = WalkRequest),
+        (status = 304, description = "代遛请求未变化"),
+        (status = 404, description = "代遛请求不存在"),
+    ),
+    tag = "walk_requests"
+)]
+pub(crate) async fn get_walk_request<R>(
+    service: Data<Service<R>>,
+    user_id: Option<UserID>,
+    req: HttpRequest,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let mut request = service
+        .get_walk_request(path.0.as_str())
+        .await
+        .map_err(Error::from)?
+        .ok_or_else(|| Error::from(ServiceError::NotFound("代遛请求不存在".to_owned())))?;
+    if let Some(UserID(user_id)) = user_id {
+        service
+            .count_unread_messages(&user_id, std::slice::from_mut(&mut request))
+            .await;
+    }
+    Ok(conditional(&req, walk_request_tag(&request), &request))
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
 pub struct NearbyWalkRequestsParams {
     pub latitude: f64,
     pub longitude: f64,
-    pub radius: f64,
+    pub radius: Meters,
+    #[serde(default = "first_page")]
     pub page: i64,
     pub size: i64,
+    /// `next_cursor` of the previous page, `page` is ignored with it.
+    pub cursor: Option<String>,
+    /// `true` lists only requests with applicants, `false` only fresh ones.
+    pub has_acceptances: Option<bool>,
+    pub min_dogs: Option<i64>,
+    /// Combined with the walker's declared capacity, the lower one applies.
+    pub max_dogs: Option<i64>,
+    /// Only requests which may start within this many minutes from now,
+    /// requests without a start time are left out.
+    pub starting_within_minutes: Option<i64>,
+    /// Comma separated, only requests carrying all of them.
+    pub tags: Option<String>,
+    /// `geojson` for a FeatureCollection of points, for map UIs.
+    pub format: Option<ExportFormat>,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+const NDJSON: &str = "application/x-ndjson";
+
+fn wants_ndjson(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.contains(NDJSON))
 }
 
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/nearby",
+    params(NearbyWalkRequestsParams),
+    responses((status = 200, body = PagedWalkRequest)),
+    tag = "walk_requests"
+)]
+/// With `Accept: application/x-ndjson` every match is streamed one per
+/// line, nearest first, and paging parameters are ignored. `format` doesn't
+/// apply to the stream.
 pub(crate) async fn nearby_walk_requests<R>(
+    req: HttpRequest,
     service: Data<Service<R>>,
+    user_id: Option<UserID>,
     Query(params): Query<NearbyWalkRequestsParams>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
+    let walker = user_id.as_ref().map(|UserID(id)| id.as_str());
+    let filter = NearbyFilter {
+        has_acceptances: params.has_acceptances,
+        min_dogs: params.min_dogs,
+        max_dogs: params.max_dogs,
+        starting_within_minutes: params.starting_within_minutes,
+        tags: params
+            .tags
+            .as_deref()
+            .map(parse_tag_list)
+            .unwrap_or_default(),
+    };
+    if wants_ndjson(&req) {
+        let walk_requests = service
+            .stream_nearby_walk_requests(
+                params.latitude,
+                params.longitude,
+                params.radius,
+                walker,
+                filter,
+            )
+            .await
+            .map_err(Error::from)?;
+        let lines = walk_requests.map(|request| {
+            let request = request.map_err(|e| {
+                log::error!("failed to stream nearby walk requests: {}", e);
+                Error::from(e)
+            })?;
+            let mut line = serde_json::to_vec(&request).map_err(ErrorInternalServerError)?;
+            line.push(b'\n');
+            Ok::<_, Error>(Bytes::from(line))
+        });
+        return Ok(HttpResponse::Ok().content_type(NDJSON).streaming(lines));
+    }
     let walk_requests = service
         .nearby_walk_requests(
             params.latitude,
             params.longitude,
             params.radius,
+            walker,
+            filter,
             Pagination::new(params.page, params.size),
+            params.cursor.as_deref(),
         )
         .await
-        .map_err(ErrorInternalServerError)?;
-    Ok(HttpResponse::Ok().json(walk_requests))
+        .map_err(Error::from)?;
+    paged_walk_requests(walk_requests, params.format)
+}
+
+/// The page as JSON, or as a GeoJSON FeatureCollection when asked for.
+fn paged_walk_requests(
+    walk_requests: Paged<WalkRequest>,
+    format: Option<ExportFormat>,
+) -> Result<HttpResponse> {
+    let Some(format) = format else {
+        return Ok(HttpResponse::Ok().json(walk_requests));
+    };
+    format.require(ExportFormat::GeoJson).map_err(Error::from)?;
+    let collection = geojson::walk_requests(&walk_requests).map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type(geojson::GEOJSON)
+        .json(collection))
+}
+
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct AreaWalkRequestsParams {
+    pub min_latitude: f64,
+    pub min_longitude: f64,
+    pub max_latitude: f64,
+    pub max_longitude: f64,
+    #[serde(default = "first_page")]
+    pub page: i64,
+    pub size: i64,
+    pub has_acceptances: Option<bool>,
+    pub min_dogs: Option<i64>,
+    pub max_dogs: Option<i64>,
+    pub starting_within_minutes: Option<i64>,
+    /// Comma separated, only requests carrying all of them.
+    pub tags: Option<String>,
+    /// `geojson` for a FeatureCollection of points.
+    pub format: Option<ExportFormat>,
+}
+
+/// Open requests within the map viewport, for map UIs which pan rather than
+/// search around a point.
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/in_area",
+    params(AreaWalkRequestsParams),
+    responses((status = 200, body = PagedWalkRequest)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn walk_requests_in_area<R>(
+    service: Data<Service<R>>,
+    user_id: Option<UserID>,
+    Query(params): Query<AreaWalkRequestsParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(params.page, params.size)
+        .map_err(Error::from)?;
+    let walk_requests = service
+        .walk_requests_in_area(
+            BoundingBox {
+                min_longitude: params.min_longitude,
+                min_latitude: params.min_latitude,
+                max_longitude: params.max_longitude,
+                max_latitude: params.max_latitude,
+            },
+            user_id.as_ref().map(|UserID(id)| id.as_str()),
+            NearbyFilter {
+                has_acceptances: params.has_acceptances,
+                min_dogs: params.min_dogs,
+                max_dogs: params.max_dogs,
+                starting_within_minutes: params.starting_within_minutes,
+                tags: params
+                    .tags
+                    .as_deref()
+                    .map(parse_tag_list)
+                    .unwrap_or_default(),
+            },
+            pagination,
+        )
+        .await
+        .map_err(Error::from)?;
+    paged_walk_requests(walk_requests, params.format)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Impressions {
+    request_ids: Vec<String>,
+}
+
+/// Request ids a walker saw in a feed, sent in batches. Clients opted out of
+/// tracking are acknowledged without recording anything.
+pub(crate) async fn record_impressions<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Json(body): Json<Impressions>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    if tracking_opted_out(&req) {
+        return Ok(HttpResponse::Accepted().finish());
+    }
+    service
+        .record_impressions(&user_id, body.request_ids)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Accepted().finish())
 }
 
 pub(crate) async fn my_walk_requests<R>(
     service: Data<Service<R>>,
     UserID(user_id): UserID,
     Query(pagination): Query<Pagination>,
+    Query(filter): Query<MyWalkRequestsFilter>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
     let walk_requests = service
-        .my_walk_requests(&user_id, Pagination::new(pagination.page, pagination.size))
+        .my_walk_requests(&user_id, filter, pagination)
         .await
-        .map_err(ErrorInternalServerError)?;
+        .map_err(Error::from)?;
     Ok(HttpResponse::Ok().json(walk_requests))
 }
 
-pub(crate) async fn accept<R>(
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExportParams {
+    format: ExportFormat,
+}
+
+/// Streams an export, logging failures which can only cut the body short
+/// once the response has started.
+fn export_body<T>(
+    items: impl Stream<Item = std::result::Result<T, ServiceError>>,
+    mut chunk: impl FnMut(T) -> std::result::Result<Vec<u8>, ServiceError>,
+) -> impl Stream<Item = Result<Bytes>> {
+    items.map(move |item| {
+        item.and_then(&mut chunk).map(Bytes::from).map_err(|e| {
+            log::error!("failed to stream export: {}", e);
+            Error::from(e)
+        })
+    })
+}
+
+/// The caller's walk history, one CSV line per request, oldest first.
+pub(crate) async fn export_walk_requests<R>(
     service: Data<Service<R>>,
-    path: Path<(String,)>,
-    req: HttpRequest,
-) -> Result<Json<WalkRequest>>
+    UserID(user_id): UserID,
+    Query(params): Query<ExportParams>,
+) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
-    let user_id = req
-        .headers()
-        .get("X-User-ID")
-        .ok_or(ErrorUnauthorized("无权限"))?
-        .to_str()
-        .map_err(ErrorUnauthorized)?;
-    service
-        .accept(path.0.as_str(), user_id)
+    params
+        .format
+        .require(ExportFormat::Csv)
+        .map_err(Error::from)?;
+    let walk_requests = service
+        .export_walk_requests(&user_id)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(Json)
+        .map_err(Error::from)?;
+    let header = stream::once(ready(
+        export::csv_header().map(Bytes::from).map_err(Error::from),
+    ));
+    let rows = export_body(walk_requests, |request| export::csv_row(&request));
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"walk_requests.csv\"",
+        ))
+        .streaming(header.chain(rows)))
 }
 
-pub(crate) async fn remove_acceptance<R>(
+pub(crate) async fn accepted_walk_requests<R>(
     service: Data<Service<R>>,
-    path: Path<(String,)>,
-    req: HttpRequest,
+    UserID(user_id): UserID,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<MyWalkRequestsFilter>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
-    let user_id = req
-        .headers()
-        .get("X-User-ID")
-        .ok_or(ErrorUnauthorized("无权限"))?
-        .to_str()
-        .map_err(ErrorUnauthorized)?;
-    service
-        .remove_acceptance(path.0.as_str(), user_id)
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
+    let walk_requests = service
+        .accepted_walk_requests(&user_id, filter, pagination)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().json(walk_requests))
 }
 
-pub(crate) async fn assign_accepter<R>(
+pub(crate) async fn applied_walk_requests<R>(
     service: Data<Service<R>>,
-    path: Path<(String, String)>,
+    UserID(user_id): UserID,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<MyWalkRequestsFilter>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
-    service
-        .assign_accepter(path.0.as_str(), path.1.as_str())
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
+    let walk_requests = service
+        .applied_walk_requests(&user_id, filter, pagination)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().json(walk_requests))
 }
 
-pub(crate) async fn dismiss_accepter<R>(
+pub(crate) async fn walk_request_history<R>(
     service: Data<Service<R>>,
-    path: Path<(String, String)>,
-) -> Result<HttpResponse>
+    UserID(user_id): UserID,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<HistoryFilter>,
+) -> Result<Json<Vec<WalkRequest>>>
 where
     R: Repository + Clone,
 {
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
     service
-        .dismiss_accepter(path.0.as_str(), path.1.as_str())
+        .walk_request_history(&user_id, filter, pagination)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)
+        .map(Json)
 }
 
-pub(crate) async fn resign_acceptance<R>(
+pub(crate) async fn my_walk_schedules<R>(
     service: Data<Service<R>>,
-    path: Path<(String, String)>,
-) -> Result<HttpResponse>
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<WalkSchedule>>>
 where
     R: Repository + Clone,
 {
     service
-        .resign_acceptance(path.0.as_str(), path.1.as_str())
+        .walk_schedules(&user_id)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)
+        .map(Json)
 }
 
-pub(crate) async fn cancel_accepted_request<R>(
+pub(crate) async fn create_walk_schedule<R>(
     service: Data<Service<R>>,
-    path: Path<(String, String)>,
-) -> Result<HttpResponse>
+    UserID(user_id): UserID,
+    Json(body): Json<WalkScheduleBody>,
+) -> Result<Json<WalkSchedule>>
 where
     R: Repository + Clone,
 {
     service
-        .cancel_accepted_request(path.0.as_str(), path.1.as_str())
+        .create_walk_schedule(&user_id, body.into())
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)
+        .map(Json)
 }
 
-pub(crate) async fn cancel_unaccepted_request<R>(
+pub(crate) async fn cancel_walk_schedule<R>(
     service: Data<Service<R>>,
+    UserID(user_id): UserID,
     path: Path<(String,)>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
     service
-        .cancel_unaccepted_request(path.0.as_str())
+        .cancel_walk_schedule(&path.0, &user_id)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-pub(crate) async fn start_walk<R>(
+pub(crate) async fn my_walk_request_templates<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<WalkRequestTemplate>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_request_templates(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn create_walk_request_template<R>(
+    service: Data<Service<R>>,
     UserID(user_id): UserID,
+    Json(body): Json<WalkRequestTemplateBody>,
+) -> Result<Json<WalkRequestTemplate>>
+where
+    R: Repository + Clone,
+{
+    service
+        .create_walk_request_template(&user_id, body.into())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn get_walk_request_template<R>(
     service: Data<Service<R>>,
+    UserID(user_id): UserID,
     path: Path<(String,)>,
-) -> Result<Json<WalkRequest>>
+) -> Result<Json<WalkRequestTemplate>>
 where
     R: Repository + Clone,
 {
     service
-        .start_walk(path.0.as_str(), &user_id)
+        .walk_request_template(&path.0, &user_id)
         .await
-        .map_err(ErrorInternalServerError)
+        .map_err(Error::from)
         .map(Json)
 }
 
-#[derive(Debug, Deserialize)]
-pub(crate) struct Location {
-    longitude: f64,
-    latitude: f64,
+pub(crate) async fn update_walk_request_template<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<WalkRequestTemplateBody>,
+) -> Result<Json<WalkRequestTemplate>>
+where
+    R: Repository + Clone,
+{
+    service
+        .update_walk_request_template(&path.0, &user_id, body.into())
+        .await
+        .map_err(Error::from)
+        .map(Json)
 }
 
-pub(crate) async fn record_walking_location<R>(
+pub(crate) async fn delete_walk_request_template<R>(
     service: Data<Service<R>>,
-    request_id: Path<(String,)>,
-    Json(location): Json<Location>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
 ) -> Result<HttpResponse>
 where
     R: Repository + Clone,
 {
     service
-        .record_walking_location(request_id.0.as_str(), location.longitude, location.latitude)
+        .delete_walk_request_template(&path.0, &user_id)
         .await
-        .map_err(ErrorInternalServerError)
-        .map(|_| HttpResponse::Ok().finish())
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
 }
 
-pub(crate) async fn finish_walk<R>(
-    UserID(user_id): UserID,
+pub(crate) async fn post_walk_request_template<R>(
     service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Query(params): Query<SpendingCapParams>,
+) -> Result<Json<String>>
+where
+    R: Repository + Clone,
+{
+    params
+        .service(&service)
+        .post_walk_request_template(&path.0, &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn my_saved_searches<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<SavedSearch>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .saved_searches(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn create_saved_search<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Json(body): Json<SavedSearchBody>,
+) -> Result<Json<SavedSearch>>
+where
+    R: Repository + Clone,
+{
+    service
+        .create_saved_search(&user_id, body.into())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn get_saved_search<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<Json<SavedSearch>>
+where
+    R: Repository + Clone,
+{
+    service
+        .saved_search(&path.0, &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn update_saved_search<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<SavedSearchBody>,
+) -> Result<Json<SavedSearch>>
+where
+    R: Repository + Clone,
+{
+    service
+        .update_saved_search(&path.0, &user_id, body.into())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn delete_saved_search<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .delete_saved_search(&path.0, &user_id)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SavedSearchResultsParams {
+    #[serde(default = "first_page")]
+    page: i64,
+    size: i64,
+    /// `next_cursor` of the previous page, `page` is ignored with it.
+    cursor: Option<String>,
+}
+
+/// The open requests the saved search finds now, as a nearby search would.
+pub(crate) async fn saved_search_results<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Query(params): Query<SavedSearchResultsParams>,
+) -> Result<Json<Paged<WalkRequest>>>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(params.page, params.size)
+        .map_err(Error::from)?;
+    service
+        .run_saved_search(&path.0, &user_id, pagination, params.cursor.as_deref())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn my_device_sessions<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<DeviceSession>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .device_sessions(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn register_device_session<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<DeviceSessionRegister>,
+) -> Result<Json<DeviceSession>>
+where
+    R: Repository + Clone,
+{
+    service
+        .register_device_session(&user_id, &path.0, body)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn revoke_device_session<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .revoke_device_session(&user_id, &path.0)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn unfollow_walk_request<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .unfollow(&user_id, &path.0, &path.1)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn my_fitness_connections<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<String>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .fitness_connections(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct FitnessConsent {
+    code: String,
+}
+
+pub(crate) async fn connect_fitness<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<FitnessConsent>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .connect_fitness(&user_id, &path.0, &body.code)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn disconnect_fitness<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .disconnect_fitness(&user_id, &path.0)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn my_favorite_walkers<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<Vec<FavoriteWalker>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .favorite_walkers(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn add_favorite_walker<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<Json<FavoriteWalker>>
+where
+    R: Repository + Clone,
+{
+    service
+        .add_favorite_walker(&user_id, &path.0)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn remove_favorite_walker<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .remove_favorite_walker(&user_id, &path.0)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn decline_invitation<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .decline_invitation(&path.0, &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ConflictParams {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+}
+
+pub(crate) async fn walker_conflicts<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Query(params): Query<ConflictParams>,
+) -> Result<Json<Vec<WalkRequest>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .conflicting_walks(&user_id, params.start, params.end)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn my_capabilities<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<WalkerCapabilities>>
+where
+    R: Repository + Clone,
+{
+    Ok(Json(
+        service
+            .walker_capabilities(&user_id)
+            .await
+            .map_err(Error::from)?,
+    ))
+}
+
+pub(crate) async fn set_my_capabilities<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Json(capabilities): Json<WalkerCapabilities>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .set_walker_capabilities(&user_id, capabilities)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Meta {
+    #[serde(flatten)]
+    capabilities: Capabilities,
+    tenant: Option<Tenant>,
+}
+
+pub(crate) async fn meta<R>(
+    service: Data<Service<R>>,
+    capabilities: Data<Capabilities>,
+    req: HttpRequest,
+) -> Result<Json<Meta>>
+where
+    R: Repository + Clone,
+{
+    let tenant = match tenant_id(&req) {
+        Some(id) => service.tenant(&id).await.map_err(Error::from)?,
+        None => None,
+    };
+    let flags = service.feature_flags().await.map_err(Error::from)?;
+    let user_id = principal(&req).ok().map(|(user_id, _)| user_id);
+    Ok(Json(Meta {
+        capabilities: capabilities
+            .as_ref()
+            .clone()
+            .with_features(&flags, user_id.as_deref()),
+        tenant,
+    }))
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct CalendarToken {
+    token: String,
+}
+
+pub(crate) async fn calendar_token(
+    signer: Data<CalendarTokenSigner>,
+    UserID(user_id): UserID,
+) -> Result<Json<CalendarToken>> {
+    Ok(Json(CalendarToken {
+        token: signer.sign(&user_id),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct CalendarParams {
+    token: String,
+}
+
+pub(crate) async fn calendar<R>(
+    service: Data<Service<R>>,
+    signer: Data<CalendarTokenSigner>,
+    Query(params): Query<CalendarParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let user_id = signer.verify(&params.token).map_err(ErrorUnauthorized)?;
+    let walk_requests = service
+        .calendar_walk_requests(&user_id)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(render_ics(&walk_requests, Utc::now())))
+}
+
+pub(crate) async fn my_applications<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<Application>>>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
+    service
+        .my_applications(&user_id, pagination)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn my_walk_budget<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<Json<WalkBudget>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_budget(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/apis/walk_requests/{id}/accepted_by",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    responses((status = 200, body = WalkRequest)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn accept<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .accept(path.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Body of the apply endpoint, optional so bodiless applications keep
+/// working.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ApplyBody {
+    /// Price the walker asks for, opening a negotiation.
+    price: Option<Money>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/apis/walk_requests/{id}/acceptances",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    responses((status = 200, description = "已报名")),
+    tag = "walk_requests"
+)]
+
+pub(crate) async fn apply<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+    body: Option<Json<ApplyBody>>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .apply(path.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)?;
+    if let Some(price) = body.and_then(|b| b.into_inner().price) {
+        service
+            .make_offer(path.0.as_str(), &user_id, OfferCreate { price })
+            .await
+            .map_err(Error::from)?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn remove_acceptance<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .remove_acceptance(path.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AssignParams {
+    /// Assigns the walker even when another of their walks overlaps.
+    #[serde(default)]
+    force: bool,
+}
+
+pub(crate) async fn assign_accepter<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String, String)>,
+    Query(params): Query<AssignParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .book(path.0.as_str(), &owner_id, path.1.as_str(), params.force)
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SwapAccepterBody {
+    walker_id: String,
+    /// Assigns the walker even when another of their walks overlaps.
+    #[serde(default)]
+    force: bool,
+}
+
+pub(crate) async fn swap_accepter<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<SwapAccepterBody>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .swap_accepter(path.0.as_str(), &owner_id, &body.walker_id, body.force)
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn dismiss_accepter<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .dismiss_accepter(path.0.as_str(), &owner_id, path.1.as_str())
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn resign_acceptance<R>(
+    service: Data<Service<R>>,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .resign_acceptance(path.0.as_str(), path.1.as_str())
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+/// Body of the cancel endpoints, optional so bodiless cancels keep working.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CancelBody {
+    reason: Option<String>,
+}
+
+pub(crate) async fn cancel_accepted_request<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String, String)>,
+    body: Option<Json<CancelBody>>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .cancel_accepted_request(
+            path.0.as_str(),
+            &owner_id,
+            path.1.as_str(),
+            body.and_then(|b| b.into_inner().reason),
+        )
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn dismiss_applicants<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String,)>,
+    Json(selection): Json<ApplicantSelection>,
+) -> Result<Json<Vec<String>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .dismiss_applicants(&path.0, &owner_id, selection)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn undo_cancel<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .undo_cancel(&path.0, &user_id)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn cancel_unaccepted_request<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String,)>,
+    body: Option<Json<CancelBody>>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .cancel_unaccepted_request(
+            path.0.as_str(),
+            &owner_id,
+            body.and_then(|b| b.into_inner().reason),
+        )
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TrackVisibilityBody {
+    track_visibility: TrackVisibility,
+}
+
+pub(crate) async fn set_track_visibility<R>(
+    service: Data<Service<R>>,
+    UserID(owner_id): UserID,
+    path: Path<(String,)>,
+    Json(body): Json<TrackVisibilityBody>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .set_track_visibility(path.0.as_str(), &owner_id, body.track_visibility)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/apis/walk_requests/{id}/start",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    request_body = Option<WalkStart>,
+    responses((status = 200, body = WalkRequest)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn start_walk<R>(
+    UserID(user_id): UserID,
+    service: Data<Service<R>>,
+    path: Path<(String,)>,
+    body: Option<Json<WalkStart>>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    let start = body.map(|Json(b)| b).unwrap_or_default();
+    let location = start.longitude.zip(start.latitude);
+    service
+        .start_walk(path.0.as_str(), &user_id, location, start.occurred_at)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    put,
+    path = "/apis/walk_requests/{id}/verification_waiver",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    responses((status = 200, body = WalkRequest)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn waive_walk_verification<R>(
+    UserID(owner_id): UserID,
+    service: Data<Service<R>>,
+    path: Path<(String,)>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .waive_walk_verification(&path.0, &owner_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Body of the start endpoint, optional so bodiless starts keep working.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub(crate) struct WalkStart {
+    longitude: Option<f64>,
+    latitude: Option<f64>,
+    /// When the walk started, for walks started offline.
+    occurred_at: Option<DateTime<Utc>>,
+}
+
+/// Body of the finish endpoint, optional like `WalkStart`.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub(crate) struct WalkFinish {
+    /// When the walk finished, for walks finished offline.
+    occurred_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct Location {
+    longitude: f64,
+    latitude: f64,
+    /// When the point was recorded, for points synced after the fact.
+    recorded_at: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/apis/walk_requests/{id}/locations",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    request_body = Location,
+    responses(
+        (status = 200, description = "已记录、已排队或与上一定位过近而被合并，见status"),
+        (status = 202, description = "服务繁忙未记录，按Retry-After重试")
+    ),
+    tag = "walk_requests"
+)]
+pub(crate) async fn record_walking_location<R>(
+    service: Data<Service<R>>,
+    request_id: Path<(String,)>,
+    Json(location): Json<Location>,
+) -> Result<Json<LocationWrite>>
+where
+    R: Repository + Clone,
+{
+    service
+        .record_walking_location(
+            request_id.0.as_str(),
+            location.longitude,
+            location.latitude,
+            location.recorded_at,
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn record_walking_locations<R>(
+    service: Data<Service<R>>,
+    request_id: Path<(String,)>,
+    Json(locations): Json<Vec<RecordedLocation>>,
+) -> Result<Json<LocationBatchReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .record_walking_locations(request_id.0.as_str(), locations)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Server-sent events stream of the walker's locations as they are recorded.
+pub(crate) async fn live_walking_locations<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let locations = service
+        .subscribe_walking_locations(request_id.0.as_str(), &user_id, device_id(&req))
+        .await
+        .map_err(Error::from)?;
+    let events = locations.map(|location| {
+        serde_json::to_string(&location)
+            .map(|data| Bytes::from(format!("data: {}\n\n", data)))
+            .map_err(ErrorInternalServerError)
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // Compressed events would be held back until a block fills up.
+        .insert_header(ContentEncoding::Identity)
+        .streaming(events))
+}
+
+pub(crate) async fn payment<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+) -> Result<Json<Payment>>
+where
+    R: Repository + Clone,
+{
+    service
+        .payment(request_id.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// The receipt of a walk, for its owner.
+pub(crate) async fn receipt<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+) -> Result<Json<Receipt>>
+where
+    R: Repository + Clone,
+{
+    service
+        .receipt(request_id.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Longest a poll is held open.
+const MAX_POLL_SECONDS: u64 = 30;
+
+/// Changes made by other instances are only seen by re-reading the request,
+/// this often.
+const POLL_RECHECK_SECONDS: u64 = 5;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PollParams {
+    #[serde(default)]
+    since_version: i64,
+    wait_seconds: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct VersionedWalkRequest {
+    version: i64,
+    walk_request: WalkRequest,
+}
+
+/// Long-poll fallback for clients which can't keep an event stream open:
+/// answers with the request once its version is newer than `since_version`,
+/// or with 204 when nothing changed within `wait_seconds`.
+pub(crate) async fn poll_walk_request<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+    Query(params): Query<PollParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let wait = params
+        .wait_seconds
+        .unwrap_or(MAX_POLL_SECONDS)
+        .min(MAX_POLL_SECONDS);
+    let deadline = Instant::now() + Duration::from_secs(wait);
+    // Subscribed before the first read so no change falls in between.
+    let mut changes = service
+        .watch_walk_request(request_id.0.as_str(), &user_id, device_id(&req))
+        .await
+        .map_err(Error::from)?;
+    loop {
+        let walk_request = service
+            .get_walk_request(request_id.0.as_str())
+            .await
+            .map_err(Error::from)?
+            .ok_or_else(|| Error::from(ServiceError::NotFound("代遛请求不存在".to_owned())))?;
+        let version = walk_request.revision();
+        if version > params.since_version {
+            return Ok(HttpResponse::Ok().json(VersionedWalkRequest {
+                version,
+                walk_request,
+            }));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(HttpResponse::NoContent().finish());
+        }
+        let recheck = (deadline - now).min(Duration::from_secs(POLL_RECHECK_SECONDS));
+        if let Ok(None) = timeout(recheck, changes.next()).await {
+            // The server is shutting down.
+            return Ok(HttpResponse::NoContent().finish());
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct WalkingLocationsParams {
+    created_after: Option<chrono::DateTime<Utc>>,
+    created_before: Option<chrono::DateTime<Utc>>,
+    page: Option<i64>,
+    size: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/{id}/locations",
+    params(
+        ("id" = String, Path, description = "代遛请求ID"),
+        WalkingLocationsParams,
+        ("If-None-Match" = Option<String>, Header, description = "已有副本的ETag"),
+    ),
+    responses(
+        (status = 200, body = Vec<WalkingLocation>),
+        (status = 304, description = "轨迹未变化"),
+    ),
+    tag = "walk_requests"
+)]
+pub(crate) async fn walking_locations<R>(
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    req: HttpRequest,
+    request_id: Path<(String,)>,
+    Query(params): Query<WalkingLocationsParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let pagination = match (params.page, params.size) {
+        (Some(page), Some(size)) => Some(service.pagination(page, size).map_err(Error::from)?),
+        _ => None,
+    };
+    let locations = service
+        .walking_locations(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+            params.created_after,
+            params.created_before,
+            pagination,
+        )
+        .await
+        .map_err(Error::from)?;
+    // Points are only ever added at the end, or dropped by downsampling,
+    // either changes the count or the last one.
+    let tag = format!(
+        "\"{}-{}\"",
+        locations.len(),
+        locations.last().map_or("", |l| l.id.as_str())
+    );
+    Ok(conditional(&req, tag, &locations))
+}
+
+/// The track as a GeoJSON LineString, streamed as it is read.
+pub(crate) async fn export_walking_locations<R>(
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+    Query(params): Query<ExportParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone + 'static,
+{
+    params
+        .format
+        .require(ExportFormat::GeoJson)
+        .map_err(Error::from)?;
+    let locations = service
+        .export_walking_locations(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+        )
+        .await
+        .map_err(Error::from)?;
+    let start = stream::once(ready(Ok(Bytes::from(export::geojson_start(
+        request_id.0.as_str(),
+    )))));
+    let mut first = true;
+    let coordinates = export_body(locations, move |location| {
+        let coordinate = export::geojson_coordinate(first, location.longitude, location.latitude);
+        first = false;
+        Ok(coordinate.into_bytes())
+    });
+    let end = stream::once(ready(Ok(Bytes::from_static(
+        export::GEOJSON_END.as_bytes(),
+    ))));
+    Ok(HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .streaming(start.chain(coordinates).chain(end)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/{id}/summary",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    responses((status = 200, body = WalkSummary)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn walk_summary<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+) -> Result<Json<WalkSummary>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_summary(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+            tenant_id(&req).as_deref(),
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+fn default_route_tolerance() -> f64 {
+    5.0
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub(crate) struct WalkRouteParams {
+    /// Meters a dropped point may be off the simplified line.
+    #[serde(default = "default_route_tolerance")]
+    tolerance: f64,
+}
+
+/// The track simplified to `[longitude, latitude]` points.
+pub(crate) async fn walk_route<R>(
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+    Query(params): Query<WalkRouteParams>,
+) -> Result<Json<Vec<[f64; 2]>>>
+where
+    R: Repository + Clone,
+{
+    if params.tolerance.is_nan() || params.tolerance < 0.0 {
+        return Err(ErrorBadRequest("tolerance不能为负数"));
+    }
+    service
+        .walk_route(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+            Meters(params.tolerance),
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/apis/walk_requests/{id}/progress",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    responses((status = 200, body = WalkProgress)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn walk_progress<R>(
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+) -> Result<Json<WalkProgress>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_progress(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn walk_summary_card<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+) -> Result<Json<SummaryCard>>
+where
+    R: Repository + Clone,
+{
+    service
+        .summary_card(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+            tenant_id(&req).as_deref(),
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// The card rendered as an image for sharing where the client can't draw it.
+pub(crate) async fn walk_summary_card_svg<R>(
+    req: HttpRequest,
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let card = service
+        .summary_card(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+            tenant_id(&req).as_deref(),
+        )
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .body(card.svg()))
+}
+
+/// Finished routes don't change, so the image is cached for a day.
+pub(crate) async fn walk_route_thumbnail<R>(
+    service: Data<Service<R>>,
+    viewer: Option<UserID>,
+    request_id: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let png = service
+        .route_thumbnail(
+            request_id.0.as_str(),
+            viewer.as_ref().map(|UserID(user_id)| user_id.as_str()),
+        )
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header((CACHE_CONTROL, "private, max-age=86400"))
+        .body(png))
+}
+
+#[utoipa::path(
+    put,
+    path = "/apis/walk_requests/{id}/finish",
+    params(("id" = String, Path, description = "代遛请求ID")),
+    request_body = Option<WalkFinish>,
+    responses((status = 200, body = WalkRequest)),
+    tag = "walk_requests"
+)]
+pub(crate) async fn finish_walk<R>(
+    UserID(user_id): UserID,
+    service: Data<Service<R>>,
+    path: Path<(String,)>,
+    body: Option<Json<WalkFinish>>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    let finish = body.map(|Json(b)| b).unwrap_or_default();
+    service
+        .finish_walk(path.0.as_str(), &user_id, finish.occurred_at)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn holidays<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    region: Path<(String,)>,
+) -> Result<Json<Vec<Holiday>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .holidays(region.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct HolidayOverride {
+    name: String,
+    price_multiplier: f64,
+    opens_at: Option<chrono::NaiveTime>,
+    closes_at: Option<chrono::NaiveTime>,
+}
+
+pub(crate) async fn set_holiday<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    path: Path<(String, NaiveDate)>,
+    Json(body): Json<HolidayOverride>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let (region, date) = path.into_inner();
+    service
+        .set_holiday(Holiday {
+            region,
+            date,
+            name: body.name,
+            price_multiplier: body.price_multiplier,
+            opens_at: body.opens_at,
+            closes_at: body.closes_at,
+        })
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn remove_holiday<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    path: Path<(String, NaiveDate)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .remove_holiday(path.0.as_str(), path.1)
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BulkUpdateParams {
+    #[serde(default = "default_dry_run")]
+    dry_run: bool,
+}
+
+fn default_dry_run() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BulkUpdate {
+    query: WalkRequestQuery,
+    update: BulkUpdateBody,
+    /// The `affected_ids` of the dry run, required to apply it.
+    #[serde(default)]
+    expected_ids: Option<Vec<String>>,
+}
+
+pub(crate) async fn bulk_update_walk_requests<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<BulkUpdateParams>,
+    Json(body): Json<BulkUpdate>,
+) -> Result<Json<BulkUpdateReport>>
+where
+    R: Repository + Clone,
+{
+    let update = WalkRequestUpdate::from(body.update);
+    update.validate().map_err(Error::from)?;
+    service
+        .bulk_update_walk_requests(body.query, update, params.dry_run, body.expected_ids)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AdminWalkRequestsParams {
+    #[serde(default)]
+    filter: String,
+    page: i64,
+    size: i64,
+}
+
+pub(crate) async fn admin_walk_requests<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<AdminWalkRequestsParams>,
+) -> Result<Json<Vec<WalkRequest>>>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(params.page, params.size)
+        .map_err(Error::from)?;
+    let query = parse_filter(&params.filter).map_err(ErrorBadRequest)?;
+    let sort_by = SortBy {
+        field: WalkRequest::created_at(),
+        order: Order::Desc,
+    };
+    service
+        .admin_walk_requests(query, sort_by, pagination)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Ad-hoc queries for support and ops, with the filter, sort and fields
+/// given as JSON, see `AdminQuery`.
+pub(crate) async fn admin_query_walk_requests<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Json(admin_query): Json<AdminQuery>,
+) -> Result<Json<Vec<serde_json::Value>>>
+where
+    R: Repository + Clone,
+{
+    let query = admin_query.walk_request_query().map_err(ErrorBadRequest)?;
+    let sort_by = admin_query.sort_by().map_err(ErrorBadRequest)?;
+    let pagination = admin_query.pagination().map_err(ErrorBadRequest)?;
+    let requests = service
+        .admin_walk_requests(query, sort_by, pagination)
+        .await
+        .map_err(Error::from)?;
+    requests
+        .iter()
+        .map(|r| admin_query.project(r).map_err(ErrorInternalServerError))
+        .collect::<Result<Vec<_>>>()
+        .map(Json)
+}
+
+pub(crate) async fn admin_cancel<R>(
+    service: Data<Service<R>>,
+    Admin(admin_id): Admin,
+    path: Path<(String,)>,
+    body: Option<Json<CancelBody>>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .admin_cancel(&path.0, &admin_id, body.and_then(|b| b.into_inner().reason))
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn admin_delete_walk_request<R>(
+    service: Data<Service<R>>,
+    Admin(admin_id): Admin,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .delete_walk_request(&path.0, &admin_id)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReassignBody {
+    user_id: String,
+}
+
+pub(crate) async fn admin_reassign<R>(
+    service: Data<Service<R>>,
+    Admin(admin_id): Admin,
+    path: Path<(String,)>,
+    Json(body): Json<ReassignBody>,
+) -> Result<Json<WalkRequest>>
+where
+    R: Repository + Clone,
+{
+    service
+        .admin_reassign(&path.0, &admin_id, &body.user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DailyMetricsParams {
+    region: String,
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+pub(crate) async fn daily_metrics<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<DailyMetricsParams>,
+) -> Result<Json<Vec<DailyMetrics>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .daily_metrics(&params.region, params.from, params.to)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct FunnelMetricsParams {
+    region: Option<String>,
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+pub(crate) async fn funnel_metrics<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<FunnelMetricsParams>,
+) -> Result<Json<Vec<FunnelMetrics>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .funnel_metrics(params.region.as_deref(), params.from, params.to)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn simulate_day<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Json(params): Json<SimulationParams>,
+) -> Result<Json<Vec<RegionSimulation>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .simulate_day(params)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// What happened to the request and when, for its owner and walker.
+pub(crate) async fn timeline<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+) -> Result<Json<Vec<WalkRequestEvent>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .timeline(request_id.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn send_message<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+    Json(create): Json<MessageCreate>,
+) -> Result<Json<Message>>
+where
+    R: Repository + Clone,
+{
+    service
+        .send_message(request_id.0.as_str(), &user_id, create)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn offers<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+) -> Result<Json<Vec<Offer>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .offers(request_id.0.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn make_offer<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+    Json(create): Json<OfferCreate>,
+) -> Result<Json<Offer>>
+where
+    R: Repository + Clone,
+{
+    service
+        .make_offer(request_id.0.as_str(), &user_id, create)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn counter_offer<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String, String)>,
+    Json(create): Json<OfferCreate>,
+) -> Result<Json<Offer>>
+where
+    R: Repository + Clone,
+{
+    service
+        .counter_offer(path.0.as_str(), path.1.as_str(), &user_id, create)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn accept_offer<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    path: Path<(String, String)>,
+    Query(params): Query<SpendingCapParams>,
+) -> Result<Json<Offer>>
+where
+    R: Repository + Clone,
+{
+    params
+        .service(&service)
+        .accept_offer(path.0.as_str(), path.1.as_str(), &user_id)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesParams {
+    /// `sent_at` of the last message already fetched.
+    pub after: Option<DateTime<Utc>>,
+}
+
+pub(crate) async fn messages<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    request_id: Path<(String,)>,
+    Query(params): Query<MessagesParams>,
+) -> Result<Json<Vec<Message>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .messages(request_id.0.as_str(), &user_id, params.after)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn admin_timeline<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    request_id: Path<(String,)>,
+) -> Result<Json<Vec<WalkRequestEvent>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .admin_timeline(request_id.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn verify_audit_chain<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    path: Path<(String,)>,
+) -> Result<Json<AuditVerification>>
+where
+    R: Repository + Clone,
+{
+    service
+        .verify_audit_chain(path.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ImportBody {
+    #[serde(default)]
+    format: DumpFormat,
+    #[serde(default)]
+    mapping: FieldMapping,
+    walk_requests: Option<String>,
+    tracks: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub(crate) async fn import_legacy<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Json(body): Json<ImportBody>,
+) -> Result<Json<ImportReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .import_legacy(
+            body.format,
+            &body.mapping,
+            body.walk_requests.as_deref(),
+            body.tracks.as_deref(),
+            body.dry_run,
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PurgeParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub(crate) async fn purge_expired_data<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<PurgeParams>,
+) -> Result<Json<PurgeReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .purge_expired_data(params.dry_run)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReconcileParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub(crate) async fn reconcile_walk_requests<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<ReconcileParams>,
+) -> Result<Json<ReconcileReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .reconcile_walk_requests(params.dry_run)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Starts recomputing summaries in the background, its progress is at
+/// `admin/summaries/recompute/{id}`.
+pub(crate) async fn recompute_summaries<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Json(scope): Json<RecomputeScope>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone + 'static,
+{
+    let progress = service.start_recompute(scope).map_err(Error::from)?;
+    let id = progress.id.clone();
+    let service = service.into_inner();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = service.recompute_summaries(&id).await {
+            log::error!("summary recomputation {} stopped: {}", id, e);
+        }
+    });
+    Ok(HttpResponse::Accepted().json(progress))
+}
+
+pub(crate) async fn recompute_progress<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    job_id: Path<(String,)>,
+) -> Result<Json<RecomputeProgress>>
+where
+    R: Repository + Clone,
+{
+    service
+        .recompute_progress(job_id.0.as_str())
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct UsageParams {
+    /// `YYYY-MM`, the current month by default.
+    month: Option<String>,
+    /// `csv` for a CSV export, JSON otherwise.
+    format: Option<String>,
+}
+
+/// Usage of partner API keys per endpoint for billing.
+pub(crate) async fn api_usage<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(params): Query<UsageParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let records = service
+        .api_usage(params.month.as_deref())
+        .await
+        .map_err(Error::from)?;
+    if params.format.as_deref() != Some("csv") {
+        return Ok(HttpResponse::Ok().json(records));
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in &records {
+        writer.serialize(record).map_err(ErrorInternalServerError)?;
+    }
+    let body = writer.into_inner().map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().content_type("text/csv").body(body))
+}
+
+pub(crate) async fn backfills<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+) -> Result<Json<Vec<BackfillDefinition>>>
+where
+    R: Repository + Clone,
+{
+    service.backfills().await.map_err(Error::from).map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BackfillParams {
+    /// Only counts the walk requests which would change.
+    #[serde(default)]
+    dry_run: bool,
+    /// Starts over instead of resuming an unfinished run.
+    #[serde(default)]
+    restart: bool,
+}
+
+/// Dry runs answer with their counts, real runs go on in the background and
+/// their progress is at `admin/backfills/{name}`.
+pub(crate) async fn run_backfill<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    name: Path<(String,)>,
+    Query(params): Query<BackfillParams>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone + 'static,
+{
+    let name = name.into_inner().0;
+    if params.dry_run {
+        let result = service.dry_run_backfill(&name).await.map_err(Error::from)?;
+        return Ok(HttpResponse::Ok().json(result));
+    }
+    let run = service
+        .start_backfill(&name, params.restart)
+        .await
+        .map_err(Error::from)?;
+    let service = service.into_inner();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = service.run_backfill(&name).await {
+            log::error!("backfill {} stopped: {}", name, e);
+        }
+    });
+    Ok(HttpResponse::Accepted().json(run))
+}
+
+pub(crate) async fn backfill_run<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    name: Path<(String,)>,
+) -> Result<Json<BackfillRun>>
+where
+    R: Repository + Clone,
+{
+    service
+        .backfill_run(name.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn feature_flags<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+) -> Result<Json<Vec<FeatureFlag>>>
+where
+    R: Repository + Clone,
+{
+    service.feature_flags().await.map_err(Error::from).map(Json)
+}
+
+/// Turns a feature on or off without a redeploy.
+pub(crate) async fn set_feature_flag<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    feature: Path<(Feature,)>,
+    Json(update): Json<FeatureFlagUpdate>,
+) -> Result<Json<FeatureFlag>>
+where
+    R: Repository + Clone,
+{
+    service
+        .set_feature_flag(feature.into_inner().0, update)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Key ids of the rotatable secrets, by ring.
+pub(crate) async fn secret_keys<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+) -> Json<BTreeMap<&'static str, Vec<KeyInfo>>>
+where
+    R: Repository + Clone,
+{
+    Json(service.secret_keys())
+}
+
+/// Makes the posted key the active one of the ring, previous keys are still
+/// accepted until retired.
+pub(crate) async fn rotate_secret<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    ring: Path<(String,)>,
+    Json(rotation): Json<KeyRotation>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .rotate_secret(ring.0.as_str(), rotation)
+        .map_err(Error::from)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub(crate) async fn retire_secret<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    path: Path<(String, String)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let (ring, kid) = path.into_inner();
+    service.retire_secret(&ring, &kid).map_err(Error::from)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Readiness {
+    ready: bool,
+    workers: Vec<WorkerHealth>,
+}
+
+/// 503 unless every background worker is running, so that an instance whose
+/// workers keep failing, or which is shutting down, is taken out of rotation.
+pub(crate) async fn readyz(supervisor: Data<Supervisor>) -> HttpResponse {
+    let readiness = Readiness {
+        ready: supervisor.ready(),
+        workers: supervisor.health(),
+    };
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SlaMetrics {
+    open_breaches: u64,
+}
+
+pub(crate) async fn sla_metrics<R>(service: Data<Service<R>>, _: Admin) -> Result<Json<SlaMetrics>>
+where
+    R: Repository + Clone,
+{
+    service
+        .open_sla_breaches()
+        .await
+        .map_err(Error::from)
+        .map(|open_breaches| Json(SlaMetrics { open_breaches }))
+}
+
+/// Sagas still running after this long are reported as stuck.
+const STUCK_SAGA_MINUTES: i64 = 5;
+
+/// Sagas listed at most, those stuck the longest.
+const STUCK_SAGA_LIMIT: i64 = 100;
+
+pub(crate) async fn stuck_sagas<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+) -> Result<Json<Vec<BookingSaga>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .stuck_sagas(
+            chrono::Duration::minutes(STUCK_SAGA_MINUTES),
+            STUCK_SAGA_LIMIT,
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn walker_strikes<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    walker_id: Path<(String,)>,
+) -> Result<Json<Vec<Strike>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walker_strikes(walker_id.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Any signed-in user may look at a walker's reputation before picking them.
+pub(crate) async fn user_reputation<R>(
+    service: Data<Service<R>>,
+    _: UserID,
+    user_id: Path<(String,)>,
+) -> Result<Json<Reputation>>
+where
+    R: Repository + Clone,
+{
+    service
+        .reputation(user_id.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Deliveries given up on, for admins to look into and requeue.
+pub(crate) async fn dead_letters<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    Query(pagination): Query<Pagination>,
+) -> Result<Json<Vec<OutboxMessage>>>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
+    service
+        .dead_letters(pagination)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn requeue_outbox_message<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    id: Path<(String,)>,
+) -> Result<Json<OutboxMessage>>
+where
+    R: Repository + Clone,
+{
+    service
+        .requeue_outbox_message(id.0.as_str())
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn tenant<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    id: Path<(String,)>,
+) -> Result<Json<Tenant>>
+where
+    R: Repository + Clone,
+{
+    service
+        .tenant(id.0.as_str())
+        .await
+        .map_err(Error::from)?
+        .ok_or_else(|| ServiceError::NotFound("租户不存在".to_owned()).into())
+        .map(Json)
+}
+
+pub(crate) async fn set_tenant<R>(
+    service: Data<Service<R>>,
+    _: Admin,
+    id: Path<(String,)>,
+    Json(body): Json<TenantBody>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .set_tenant(body.into_tenant(id.into_inner().0))
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ReportParams {
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    group_by: ReportInterval,
+}
+
+/// What the walker earned with the walks they finished.
+pub(crate) async fn walker_earnings<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Query(params): Query<ReportParams>,
+) -> Result<Json<WalkReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_report(
+            &user_id,
+            ReportRole::Walker,
+            params.from,
+            params.to,
+            params.group_by,
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// What the walker is paid out for their walks, in `[from, to)`.
+pub(crate) async fn payouts<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Query(params): Query<ReportParams>,
+) -> Result<Json<Vec<Payout>>>
+where
+    R: Repository + Clone,
+{
+    service
+        .payouts(&user_id, params.from, params.to)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Caps what the owner spends on walks a month, in the default currency.
+pub(crate) async fn set_spending_cap<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Json(monthly_limit): Json<Money>,
+) -> Result<Json<SpendingCap>>
+where
+    R: Repository + Clone,
+{
+    service
+        .set_spending_cap(&user_id, monthly_limit)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn remove_spending_cap<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    service
+        .remove_spending_cap(&user_id)
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+/// How much the owner's dogs were walked and what the walks cost, with
+/// their spending this month.
+pub(crate) async fn owner_activity<R>(
+    service: Data<Service<R>>,
+    UserID(user_id): UserID,
+    Query(params): Query<ReportParams>,
+) -> Result<Json<WalkReport>>
+where
+    R: Repository + Clone,
+{
+    service
+        .walk_report(
+            &user_id,
+            ReportRole::Owner,
+            params.from,
+            params.to,
+            params.group_by,
+        )
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpenRequestCountsParams {
+    from: chrono::DateTime<Utc>,
+    to: chrono::DateTime<Utc>,
+}
+
+/// Public, API key gated aggregate for researchers, carries no personal data.
+pub(crate) async fn open_request_counts<R>(
+    service: Data<Service<R>>,
+    quotas: Data<ApiQuotas>,
+    req: HttpRequest,
+    Query(params): Query<OpenRequestCountsParams>,
+) -> Result<Json<Vec<AreaHourCount>>>
+where
+    R: Repository + Clone,
+{
+    let key = req
+        .headers()
+        .get("X-Api-Key")
+        .and_then(|key| key.to_str().ok())
+        .ok_or(ErrorUnauthorized("缺少API密钥"))?;
+    match quotas.check(key) {
+        Ok(()) => {}
+        Err(QuotaError::UnknownKey) => return Err(ErrorUnauthorized("无效的API密钥")),
+        Err(QuotaError::Exceeded) => return Err(ErrorTooManyRequests("请求次数超出配额")),
+    }
+    service
+        .open_request_counts(params.from, params.to)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+pub(crate) async fn create_walk_request_for_user<R>(
+    service: Data<Service<R>>,
+    ServiceClient(client): ServiceClient,
+    path: Path<(String,)>,
+    Json(body): Json<CreateWalkRequestBody>,
+) -> Result<Json<String>>
+where
+    R: Repository + Clone,
+{
+    let create = body.into_create(path.0.clone());
+    create.validate().map_err(Error::from)?;
+    service
+        .on_behalf_of(&client, &path.0)
+        .create_walk_request(create)
+        .await
+        .map_err(Error::from)
+        .map(Json)
+}
+
+/// Atom feed of requests recently created in a region, for ops monitoring.
+pub(crate) async fn walk_request_feed<R>(
+    service: Data<Service<R>>,
+    _: ServiceClient,
+    path: Path<(String,)>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let region = path.0.as_str();
+    let window = |t: Option<DateTime<Utc>>| t.map_or("未指定".to_owned(), |t| t.to_rfc3339());
+    let entries: Vec<AtomEntry> = service
+        .recently_created(region)
+        .await
+        .map_err(Error::from)?
+        .into_iter()
+        .map(|(created_at, request)| AtomEntry {
+            id: format!("urn:little-walk:walk-request:{}", request.id),
+            title: format!("新代遛请求 {}", request.id),
+            summary: format!(
+                "{}只狗，位置 {:.5},{:.5}，开始时间 {} 至 {}",
+                request.dogs.len(),
+                request.latitude,
+                request.longitude,
+                window(request.should_start_after),
+                window(request.should_start_before)
+            ),
+            updated: created_at,
+        })
+        .collect();
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(atom(
+            &format!("urn:little-walk:feeds:{}:walk-requests", region),
+            &format!("{}新代遛请求", region),
+            &entries,
+        )))
+}
+
+pub(crate) async fn walk_requests_of_user<R>(
+    service: Data<Service<R>>,
+    _: ServiceClient,
     path: Path<(String,)>,
+    Query(pagination): Query<Pagination>,
+    Query(filter): Query<MyWalkRequestsFilter>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let pagination = service
+        .pagination(pagination.page, pagination.size)
+        .map_err(Error::from)?;
+    let walk_requests = service
+        .my_walk_requests(&path.0, filter, pagination)
+        .await
+        .map_err(Error::from)?;
+    Ok(HttpResponse::Ok().json(walk_requests))
+}
+
+pub(crate) async fn cancel_unaccepted_request_for_user<R>(
+    service: Data<Service<R>>,
+    ServiceClient(client): ServiceClient,
+    path: Path<(String, String)>,
+    body: Option<Json<CancelBody>>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let (user_id, request_id) = path.into_inner();
+    service
+        .on_behalf_of(&client, &user_id)
+        .cancel_unaccepted_request(
+            &request_id,
+            &user_id,
+            body.and_then(|b| b.into_inner().reason),
+        )
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn cancel_accepted_request_for_user<R>(
+    service: Data<Service<R>>,
+    ServiceClient(client): ServiceClient,
+    path: Path<(String, String, String)>,
+    body: Option<Json<CancelBody>>,
+) -> Result<HttpResponse>
+where
+    R: Repository + Clone,
+{
+    let (user_id, request_id, walker_id) = path.into_inner();
+    service
+        .on_behalf_of(&client, &user_id)
+        .cancel_accepted_request(
+            &request_id,
+            &user_id,
+            &walker_id,
+            body.and_then(|b| b.into_inner().reason),
+        )
+        .await
+        .map_err(Error::from)
+        .map(|_| HttpResponse::Ok().finish())
+}
+
+pub(crate) async fn set_track_visibility_for_user<R>(
+    service: Data<Service<R>>,
+    ServiceClient(client): ServiceClient,
+    path: Path<(String, String)>,
+    Json(body): Json<TrackVisibilityBody>,
 ) -> Result<Json<WalkRequest>>
 where
     R: Repository + Clone,
 {
+    let (user_id, request_id) = path.into_inner();
     service
-        .finish_walk(path.0.as_str(), &user_id)
+        .on_behalf_of(&client, &user_id)
+        .set_track_visibility(&request_id, &user_id, body.track_visibility)
         .await
-        .map_err(ErrorInternalServerError)
+        .map_err(Error::from)
         .map(Json)
 }
@@ -0,0 +1,330 @@
+//! Bodies the API accepts, kept apart from the core and storage types they
+//! map into so that fields those grow are never settable by clients.
+//! Responses still serialize the core types, a field added to those shows
+//! up in the API.
+
+use chrono::{DateTime, Utc};
+use little_walk_dog::core::entities::Dog;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::core::{
+    distance::DistanceStrategy,
+    entities::TrackVisibility,
+    metrics::DEFAULT_REGION,
+    repository::{WalkRequestCreate, WalkRequestUpdate},
+    saved_search::SavedSearchCreate,
+    schedule::WalkScheduleCreate,
+    service::WalkRequestEdit,
+    template::{WalkRequestTemplateCreate, WindowOffsets},
+    tenant::Tenant,
+    timezone::DEFAULT_TIMEZONE,
+    units::{Meters, Money},
+    walker_capabilities::DogRequirements,
+};
+
+fn default_timezone() -> String {
+    DEFAULT_TIMEZONE.to_owned()
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_owned()
+}
+
+fn default_start_window_minutes() -> i64 {
+    30
+}
+
+fn default_duration_minutes() -> i64 {
+    60
+}
+
+fn notify_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateWalkRequestBody {
+    #[schema(value_type = Vec<Object>)]
+    pub dogs: Vec<Dog>,
+    pub should_start_after: Option<DateTime<Utc>>,
+    pub should_start_before: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+    pub should_end_after: Option<DateTime<Utc>>,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    /// See `WalkRequest::apply_before`.
+    pub apply_before: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    /// See `WalkRequest::max_radius`.
+    pub max_radius: Option<Meters>,
+    /// See `WalkRequest::price`.
+    pub price: Option<Money>,
+    /// See `WalkRequest::max_walkers`.
+    pub max_walkers: Option<i64>,
+    /// See `WalkRequest::invited_walker_id`.
+    pub invited_walker_id: Option<String>,
+    /// See `WalkRequest::tags`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl CreateWalkRequestBody {
+    /// The request to create for `created_by`.
+    pub fn into_create(self, created_by: String) -> WalkRequestCreate {
+        WalkRequestCreate {
+            dogs: self.dogs,
+            should_start_after: self.should_start_after,
+            should_start_before: self.should_start_before,
+            should_end_before: self.should_end_before,
+            should_end_after: self.should_end_after,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: self.timezone,
+            region: self.region,
+            max_applicants: self.max_applicants,
+            requirements: self.requirements,
+            track_visibility: self.track_visibility,
+            max_radius: self.max_radius,
+            price: self.price,
+            max_walkers: self.max_walkers,
+            apply_before: self.apply_before,
+            created_by,
+            delegation: None,
+            tenant_id: None,
+            reference_code: None,
+            invited_walker_id: self.invited_walker_id,
+            invitation_expires_at: None,
+            tags: self.tags,
+        }
+    }
+}
+
+/// Fields left out are kept.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditWalkRequestBody {
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub dogs: Option<Vec<Dog>>,
+    pub should_start_after: Option<DateTime<Utc>>,
+    pub should_start_before: Option<DateTime<Utc>>,
+    pub should_end_after: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Replaces the tags, see `WalkRequest::tags`.
+    pub tags: Option<Vec<String>>,
+    /// The version the edit is based on, also taken from `If-Match`.
+    pub expected_version: Option<i64>,
+}
+
+impl From<EditWalkRequestBody> for WalkRequestEdit {
+    fn from(body: EditWalkRequestBody) -> Self {
+        WalkRequestEdit {
+            dogs: body.dogs,
+            should_start_after: body.should_start_after,
+            should_start_before: body.should_start_before,
+            should_end_after: body.should_end_after,
+            should_end_before: body.should_end_before,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            tags: body.tags,
+            expected_version: body.expected_version,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalkScheduleBody {
+    /// See `Recurrence`.
+    pub rule: String,
+    #[serde(default = "default_start_window_minutes")]
+    pub start_window_minutes: i64,
+    #[serde(default = "default_duration_minutes")]
+    pub duration_minutes: i64,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+}
+
+impl From<WalkScheduleBody> for WalkScheduleCreate {
+    fn from(body: WalkScheduleBody) -> Self {
+        WalkScheduleCreate {
+            rule: body.rule,
+            start_window_minutes: body.start_window_minutes,
+            duration_minutes: body.duration_minutes,
+            dogs: body.dogs,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            timezone: body.timezone,
+            region: body.region,
+            max_applicants: body.max_applicants,
+            requirements: body.requirements,
+            track_visibility: body.track_visibility,
+            max_radius: body.max_radius,
+            price: body.price,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalkRequestTemplateBody {
+    pub name: String,
+    #[serde(default)]
+    pub windows: WindowOffsets,
+    pub dogs: Vec<Dog>,
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    #[serde(alias = "max_acceptances")]
+    pub max_applicants: Option<i64>,
+    #[serde(default)]
+    pub requirements: DogRequirements,
+    #[serde(default)]
+    pub track_visibility: TrackVisibility,
+    pub max_radius: Option<Meters>,
+    pub price: Option<Money>,
+    pub max_walkers: Option<i64>,
+}
+
+impl From<WalkRequestTemplateBody> for WalkRequestTemplateCreate {
+    fn from(body: WalkRequestTemplateBody) -> Self {
+        WalkRequestTemplateCreate {
+            name: body.name,
+            windows: body.windows,
+            dogs: body.dogs,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            timezone: body.timezone,
+            region: body.region,
+            max_applicants: body.max_applicants,
+            requirements: body.requirements,
+            track_visibility: body.track_visibility,
+            max_radius: body.max_radius,
+            price: body.price,
+            max_walkers: body.max_walkers,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedSearchBody {
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius: Meters,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub min_dogs: Option<i64>,
+    pub max_dogs: Option<i64>,
+    #[serde(default = "notify_by_default")]
+    pub notify: bool,
+}
+
+impl From<SavedSearchBody> for SavedSearchCreate {
+    fn from(body: SavedSearchBody) -> Self {
+        SavedSearchCreate {
+            name: body.name,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            radius: body.radius,
+            tags: body.tags,
+            min_dogs: body.min_dogs,
+            max_dogs: body.max_dogs,
+            notify: body.notify,
+        }
+    }
+}
+
+/// The tenant's id comes from the path.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantBody {
+    pub branding_name: String,
+    pub support_contact: String,
+    pub pricing_table_id: Option<String>,
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub service_polygons: Vec<Vec<[f64; 2]>>,
+    pub distance_strategy: Option<DistanceStrategy>,
+    pub max_search_radius: Option<Meters>,
+}
+
+impl TenantBody {
+    pub fn into_tenant(self, id: String) -> Tenant {
+        Tenant {
+            id,
+            branding_name: self.branding_name,
+            support_contact: self.support_contact,
+            pricing_table_id: self.pricing_table_id,
+            currency: self.currency,
+            service_polygons: self.service_polygons,
+            distance_strategy: self.distance_strategy,
+            max_search_radius: self.max_search_radius,
+        }
+    }
+}
+
+/// What admins may change on many requests at once, each shown in the dry
+/// run's diff. Acceptances, pool walkers and the times the walk itself
+/// records are left to the flows that own them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BulkUpdateBody {
+    pub should_start_after: Option<DateTime<Utc>>,
+    pub should_start_before: Option<DateTime<Utc>>,
+    pub should_end_after: Option<DateTime<Utc>>,
+    pub should_end_before: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub timezone: Option<String>,
+    pub requirements: Option<DogRequirements>,
+    pub expired_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<BulkUpdateBody> for WalkRequestUpdate {
+    fn from(body: BulkUpdateBody) -> Self {
+        WalkRequestUpdate {
+            should_start_after: body.should_start_after,
+            should_start_before: body.should_start_before,
+            should_end_after: body.should_end_after,
+            should_end_before: body.should_end_before,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            timezone: body.timezone,
+            requirements: body.requirements,
+            expired_at: body.expired_at,
+            deleted_at: body.deleted_at,
+            ..Default::default()
+        }
+    }
+}
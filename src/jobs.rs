@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use actix_web::rt::time::{interval, sleep};
+use chrono::{Days, TimeZone, Utc};
+
+use crate::core::{repository::Repository, service::Service};
+
+/// Offset past midnight (UTC) at which the previous day is rolled up, leaving
+/// late writes a few minutes to land.
+const DAILY_METRICS_DELAY_SECONDS: i64 = 10 * 60;
+
+pub async fn daily_metrics<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    loop {
+        let now = Utc::now();
+        let next = Utc.from_utc_datetime(
+            &(now.date_naive() + Days::new(1))
+                .and_hms_opt(0, 0, 0)
+                .expect("midnight is valid"),
+        ) + chrono::Duration::seconds(DAILY_METRICS_DELAY_SECONDS);
+        sleep(Duration::from_secs((next - now).num_seconds().max(1) as u64)).await;
+        let yesterday = Utc::now().date_naive() - Days::new(1);
+        if let Err(e) = service.rollup_daily_metrics(yesterday).await {
+            log::error!("failed to roll up daily metrics for {}: {}", yesterday, e);
+        }
+    }
+}
+
+const SLA_CHECK_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn sla_breaches<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(SLA_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.detect_sla_breaches().await {
+            Ok(breached) => {
+                for request in breached {
+                    log::warn!("walk request {} breached the accept sla", request.id);
+                }
+            }
+            Err(e) => log::error!("failed to detect sla breaches: {}", e),
+        }
+    }
+}
+
+const EXPIRY_CHECK_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn expire_walk_requests<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(EXPIRY_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.expire_walk_requests().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("expired {} walk requests", n),
+            Err(e) => log::error!("failed to expire walk requests: {}", e),
+        }
+    }
+}
+
+const NO_SHOW_CHECK_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn time_out_no_shows<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(NO_SHOW_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.time_out_no_shows().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("dismissed {} walkers who didn't show up", n),
+            Err(e) => log::error!("failed to time out no-shows: {}", e),
+        }
+    }
+}
+
+const OUTBOX_DISPATCH_INTERVAL_SECONDS: u64 = 2;
+
+pub async fn dispatch_outbox<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(OUTBOX_DISPATCH_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        if let Err(e) = service.dispatch_outbox().await {
+            log::error!("failed to dispatch the outbox: {}", e);
+        }
+    }
+}
+
+const REMINDER_CHECK_INTERVAL_SECONDS: u64 = 30;
+
+pub async fn send_start_reminders<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(REMINDER_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.send_start_reminders().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("sent {} start reminders", n),
+            Err(e) => log::error!("failed to send start reminders: {}", e),
+        }
+    }
+}
+
+const CANCELLATION_CHECK_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn finalize_cancellations<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(CANCELLATION_CHECK_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.finalize_cancellations().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("finalized {} cancellations", n),
+            Err(e) => log::error!("failed to finalize cancellations: {}", e),
+        }
+    }
+}
+
+const TRACK_DOWNSAMPLE_INTERVAL_SECONDS: u64 = 60 * 60;
+
+pub async fn downsample_tracks<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(TRACK_DOWNSAMPLE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.downsample_tracks().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("dropped {} walking locations by downsampling", n),
+            Err(e) => log::error!("failed to downsample tracks: {}", e),
+        }
+    }
+}
+
+const RETENTION_PURGE_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+
+pub async fn purge_expired_data<R>(service: Service<R>, dry_run: bool)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(RETENTION_PURGE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.purge_expired_data(dry_run).await {
+            Ok(report) => {
+                for purge in report.purged {
+                    log::info!(
+                        "{} {} {:?} records older than {}",
+                        if dry_run { "would purge" } else { "purged" },
+                        purge.count,
+                        purge.class,
+                        purge.before
+                    );
+                }
+            }
+            Err(e) => log::error!("failed to purge expired data: {}", e),
+        }
+    }
+}
+
+const RECONCILE_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+
+pub async fn reconcile_walk_requests<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(RECONCILE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.reconcile_walk_requests(false).await {
+            Ok(report) if report.inconsistent > 0 => log::warn!(
+                "reconciled walk requests: {} inconsistent, {} repaired, {} unresolved, {} failed",
+                report.inconsistent,
+                report.repaired,
+                report.unresolved,
+                report.failed
+            ),
+            Ok(_) => {}
+            Err(e) => log::error!("failed to reconcile walk requests: {}", e),
+        }
+    }
+}
+
+const ARCHIVE_INTERVAL_SECONDS: u64 = 24 * 60 * 60;
+
+pub async fn archive_walk_requests<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(ARCHIVE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.archive_walk_requests().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("archived {} walk requests", n),
+            Err(e) => log::error!("failed to archive walk requests: {}", e),
+        }
+    }
+}
+
+const FITNESS_EXPORT_INTERVAL_SECONDS: u64 = 60;
+
+pub async fn fitness_exports<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(FITNESS_EXPORT_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.run_fitness_exports().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("exported {} walks to fitness platforms", n),
+            Err(e) => log::error!("failed to export walks to fitness platforms: {}", e),
+        }
+    }
+}
+
+const SCHEDULE_INTERVAL_SECONDS: u64 = 15 * 60;
+
+pub async fn materialize_schedules<R>(service: Service<R>)
+where
+    R: Repository + Clone,
+{
+    let mut interval = interval(Duration::from_secs(SCHEDULE_INTERVAL_SECONDS));
+    loop {
+        interval.tick().await;
+        match service.materialize_schedules().await {
+            Ok(0) => {}
+            Ok(n) => log::info!("created {} scheduled walk requests", n),
+            Err(e) => log::error!("failed to materialize walk schedules: {}", e),
+        }
+    }
+}
@@ -0,0 +1,34 @@
+#![allow(async_fn_in_trait)]
+
+pub mod alerts;
+pub mod app;
+pub mod archives;
+pub mod buses;
+pub mod caches;
+pub mod change_streams;
+pub mod config;
+pub mod core;
+pub mod cors;
+pub mod emails;
+pub mod fitness;
+pub mod grpc;
+pub mod handlers;
+pub mod jobs;
+pub mod metering;
+pub mod notifications;
+pub mod openapi;
+pub mod payments;
+pub mod rankers;
+pub mod rate_limiting;
+pub mod repositories;
+pub mod request_id;
+pub mod responses;
+pub mod routing;
+pub mod static_maps;
+pub mod supervisor;
+pub mod tenancy;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod tls;
+pub mod users;
+pub mod webhooks;
@@ -1,88 +1,711 @@
-#![allow(async_fn_in_trait)]
-
-pub mod core;
-pub mod handlers;
-pub mod repositories;
-
-use crate::core::service::Service;
 use actix_web::{
-    middleware::Logger,
-    web::{delete, get, post, put, scope, Data},
+    dev::ServerHandle,
+    http::KeepAlive,
+    middleware::{Compress, Condition, Logger},
+    rt::signal::ctrl_c,
+    web::Data,
     App, HttpServer,
 };
 use dotenv::dotenv;
-use futures::io;
-use handlers::{
-    accept, assign_accepter, cancel_accepted_request, cancel_unaccepted_request, dismiss_accepter,
-    finish_walk, record_walking_location, remove_acceptance, resign_acceptance, start_walk,
+use futures::{future, io};
+use little_walk_request::{
+    alerts::webhook::ChatWebhook,
+    app::routes,
+    archives::s3::S3Archive,
+    buses::redis::RedisLocationBus,
+    caches::redis::RedisCache,
+    change_streams::WalkRequestListener,
+    config::Config,
+    core::{
+        alert::AlertRouter,
+        auth::Authenticator,
+        cache::NearbyCache,
+        calendar::CalendarTokenSigner,
+        currency::{CurrencyZones, FixedRates},
+        delegation::ServiceClients,
+        distance::DistanceStrategy,
+        email::{EmailRenderer, Emailer},
+        experiments::Experiments,
+        feature_flags::FeatureFlags,
+        fitness::FitnessProvider,
+        holiday::HolidayCalendar,
+        ids::{IdFormat, YearlySequence},
+        impression::ImpressionSampling,
+        ingestion::{LocationQueue, LocationQueueConfig},
+        live::LocationBroker,
+        location_filter::LocationFilter,
+        meta::{Capabilities, API_VERSIONS},
+        ranking::{ByDistance, ExposureBalanced, Ranker, RankerKind, SoonestStart, VariantRanker},
+        rate_limit::RateLimiter,
+        repository::{PageLimits, Repository},
+        research::ApiQuotas,
+        retention::{RetentionPolicy, TrackDownsampling},
+        security::{KeyRing, SecretRings},
+        service::Service,
+        sla::SlaPolicy,
+        units::Meters,
+        usage::MonthlyQuotas,
+        verification::WalkVerification,
+        walk_budget::WalkBudgetPolicy,
+    },
+    cors::{security_headers, CorsPolicy},
+    emails::smtp::Smtp,
+    fitness::http::HttpFitness,
+    grpc::{proto::walk_requests_server::WalkRequestsServer, server::GrpcServer},
+    jobs,
+    notifications::fcm::Fcm,
+    payments::http::HttpPayments,
+    rankers::http::HttpRanker,
+    repositories::{
+        event_sourced::EventSourced, memory::InMemory, mongodb::Mongodb, postgres::Postgres,
+    },
+    request_id::RequestId,
+    responses::{Casing, DistanceUnit, ResponsePolicy},
+    routing::osrm::Osrm,
+    static_maps::http::HttpStaticMaps,
+    supervisor::Supervisor,
+    tls,
+    users::http::HttpUsers,
+    webhooks::http::HttpWebhook,
 };
-use mongodb::Client;
-use nb_from_env::{FromEnv, FromEnvDerive};
-use repositories::mongodb::Mongodb;
+use mongodb::{bson::doc, options::ClientOptions, Client};
+use sqlx::postgres::PgPoolOptions;
+use std::{sync::Arc, time::Duration};
+use tracing_subscriber::EnvFilter;
+
+const MONGODB_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MONGODB_MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
 
-#[derive(FromEnvDerive)]
-pub struct Config {
-    pub listen_address: String,
-    pub database_url: String,
-    pub database_name: String,
-    #[env_default("info")]
-    pub log_level: String,
-    #[env_default("%t %r %s %T")]
-    pub log_format: String,
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
 }
 
-#[actix_web::main]
-async fn main() -> io::Result<()> {
-    dotenv().ok();
-    let config = Config::from_env();
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or(config.log_level));
-    let db = Client::with_uri_str(&config.database_url)
-        .await
-        .expect("failed to connect to mongodb")
-        .database(&config.database_name);
-    let repository = Mongodb::new(db);
-    let service = Service::new(repository);
-    HttpServer::new(move || {
-        let log_format = config.log_format.clone();
+fn capabilities(config: &Config) -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        api_versions: API_VERSIONS,
+        payments: !config.payment_service_url.is_empty(),
+        chat: true,
+        live_tracking: true,
+        email_notifications: !config.smtp_host.is_empty(),
+        streaming: "sse",
+        regions: split_list(&config.regions_served),
+        max_nearby_radius: (config.max_nearby_radius_meters > 0.0)
+            .then_some(Meters(config.max_nearby_radius_meters)),
+        max_applicants: (config.max_applicants > 0).then_some(config.max_applicants),
+        currency: config.default_currency.to_uppercase(),
+    }
+}
+
+fn build_service<R>(config: &Config, repository: R, supervisor: &Supervisor) -> Service<R>
+where
+    R: Repository + Clone + 'static,
+{
+    let broker = if config.relay_locations {
+        assert!(
+            !config.redis_url.is_empty(),
+            "relayed locations require REDIS_URL"
+        );
+        let bus = RedisLocationBus::new(&config.redis_url).expect("invalid REDIS_URL");
+        let (broker, relay) = LocationBroker::with_bus(Arc::new(bus));
+        supervisor.supervise_once("location_relay", relay);
+        supervisor.supervise_with("location_listener", broker.clone(), LocationBroker::listen);
+        broker
+    } else {
+        LocationBroker::new()
+    };
+    let mut service = Service::new(repository.clone()).with_location_broker(broker.clone());
+    if config.location_queue_capacity > 0 {
+        let (queue, flusher) = LocationQueue::new(
+            repository,
+            Some(broker),
+            LocationQueueConfig {
+                capacity: config.location_queue_capacity,
+                batch_size: config.location_flush_batch_size.max(1),
+                flush_interval: Duration::from_millis(config.location_flush_millis),
+                retry_after: Duration::from_secs(config.location_retry_after_seconds),
+            },
+        );
+        supervisor.supervise_once("location_queue", flusher);
+        service = service.with_location_queue(queue);
+    }
+    service = service.with_location_filter(LocationFilter::new(
+        Meters(config.location_min_distance_meters),
+        chrono::Duration::milliseconds(config.location_min_interval_millis),
+    ));
+    service = service
+        .with_invitation_timeout(chrono::Duration::minutes(config.invitation_timeout_minutes));
+    if !config.reference_code_prefix.is_empty() {
+        service =
+            service.with_id_generator(Arc::new(YearlySequence::new(&config.reference_code_prefix)));
+    }
+    service = service.with_walk_verification(WalkVerification {
+        start_radius: Meters(config.walk_start_radius_meters),
+        min_duration: chrono::Duration::minutes(config.walk_min_minutes),
+        min_distance: Meters(config.walk_min_distance_meters),
+    });
+    if !config.smtp_host.is_empty() {
+        let sender = Smtp::new(
+            &config.smtp_host,
+            &config.smtp_username,
+            &config.smtp_password,
+            &config.smtp_from,
+        )
+        .expect("failed to create smtp sender");
+        let renderer = EmailRenderer::new().expect("failed to load email templates");
+        service = service.with_emailer(Emailer::new(renderer, Box::new(sender)));
+    }
+    let holiday_ttl = Duration::from_secs(config.holiday_cache_seconds);
+    let holidays = if config.holidays_file.is_empty() {
+        HolidayCalendar::new(Vec::new(), holiday_ttl)
+    } else {
+        HolidayCalendar::from_file(&config.holidays_file, holiday_ttl)
+            .expect("failed to load holidays file")
+    };
+    service = service.with_holidays(holidays);
+    service = service.with_sla(SlaPolicy {
+        accept_within: chrono::Duration::minutes(config.sla_accept_minutes),
+        regions: split_list(&config.sla_regions),
+    });
+    service = service.with_currencies(
+        CurrencyZones::parse(&config.default_currency, &config.region_currencies)
+            .expect("invalid region currencies"),
+        Arc::new(FixedRates::parse(&config.exchange_rates).expect("invalid exchange rates")),
+    );
+    if config.walk_budget_daily_minutes > 0 {
+        service = service.with_walk_budget(WalkBudgetPolicy {
+            daily_minutes: config.walk_budget_daily_minutes,
+            regions: split_list(&config.walk_budget_regions),
+        });
+    }
+    if config.cancel_undo_seconds > 0 {
+        service =
+            service.with_cancel_undo_window(chrono::Duration::seconds(config.cancel_undo_seconds));
+    }
+    if config.archive_after_days > 0 {
+        service = service.with_archive_after(chrono::Duration::days(config.archive_after_days));
+    }
+    if config.track_downsample_after_days > 0 {
+        service = service.with_track_downsampling(TrackDownsampling {
+            after: chrono::Duration::days(config.track_downsample_after_days),
+            interval: chrono::Duration::seconds(config.track_downsample_seconds),
+        });
+    }
+    if config.backfill_batch_size > 0 {
+        service = service.with_backfill_rate(
+            config.backfill_batch_size,
+            std::time::Duration::from_millis(config.backfill_pause_millis),
+        );
+    }
+    let secrets = SecretRings {
+        jwt: KeyRing::parse(&config.jwt_keys, &config.jwt_key).expect("invalid JWT_KEYS"),
+        calendar: KeyRing::parse(&config.calendar_token_keys, &config.calendar_token_secret)
+            .expect("invalid CALENDAR_TOKEN_KEYS"),
+        webhook: KeyRing::parse(&config.webhook_secrets, "").expect("invalid WEBHOOK_SECRETS"),
+    };
+    service = service.with_secret_rings(secrets);
+    service = service.with_monthly_quotas(
+        MonthlyQuotas::parse(&config.partner_monthly_quotas)
+            .expect("invalid PARTNER_MONTHLY_QUOTAS"),
+    );
+    service = service.with_start_reminders(
+        split_list(&config.start_reminder_minutes)
+            .iter()
+            .map(|m| m.parse::<i64>())
+            .collect::<Result<_, _>>()
+            .expect("invalid START_REMINDER_MINUTES"),
+    );
+    if config.schedule_horizon_hours > 0 {
+        service =
+            service.with_schedule_horizon(chrono::Duration::hours(config.schedule_horizon_hours));
+    }
+    if config.max_applicants > 0 {
+        service = service.with_max_applicants(config.max_applicants);
+    }
+    if config.max_nearby_radius_meters > 0.0 {
+        service = service.with_max_radius(Meters(config.max_nearby_radius_meters));
+    }
+    service = service.with_page_limits(PageLimits {
+        max_page: config.max_page,
+        max_size: config.max_page_size,
+    });
+    let experiments = Experiments::parse(&config.experiments).expect("invalid EXPERIMENTS");
+    if config.nearby_ranking_experiment.is_empty() {
+        // Distance order is what the repository returns, no ranker needed.
+        match RankerKind::parse(&config.nearby_ranking).expect("invalid NEARBY_RANKING") {
+            RankerKind::Distance => {}
+            kind => service = service.with_ranker(ranker(kind, config)),
+        }
+    } else {
+        let experiment = &config.nearby_ranking_experiment;
+        let rankers = experiments
+            .variants(experiment)
+            .expect("NEARBY_RANKING_EXPERIMENT is not in EXPERIMENTS")
+            .into_iter()
+            .map(|variant| {
+                let kind = RankerKind::parse(variant).expect("invalid NEARBY_RANKING_EXPERIMENT");
+                (variant.to_owned(), ranker(kind, config))
+            })
+            .collect();
+        service = service.with_ranker(Arc::new(VariantRanker {
+            experiment: experiment.clone(),
+            rankers,
+        }));
+    }
+    service = service.with_experiments(experiments);
+    service = service.with_feature_flags(
+        FeatureFlags::parse(
+            &config.feature_flags,
+            Duration::from_secs(config.feature_flag_cache_seconds),
+        )
+        .expect("invalid FEATURE_FLAGS"),
+    );
+    if !config.redis_url.is_empty() {
+        service = service.with_nearby_cache(NearbyCache::new(
+            Arc::new(RedisCache::new(&config.redis_url).expect("invalid REDIS_URL")),
+            Duration::from_secs(config.nearby_cache_seconds),
+        ));
+    }
+    service = service.with_impression_sampling(
+        ImpressionSampling::new(config.impression_sample_rate)
+            .expect("invalid IMPRESSION_SAMPLE_RATE"),
+    );
+    if !config.payment_service_url.is_empty() {
+        service = service
+            .with_payments(Arc::new(HttpPayments::new(&config.payment_service_url)))
+            .with_payment_gateway(Arc::new(HttpPayments::new(&config.payment_service_url)));
+    }
+    service = service.with_retention(
+        RetentionPolicy::parse(&config.retention_policy).expect("invalid retention policy"),
+    );
+    if !config.track_archive_bucket.is_empty() {
+        service = service.with_track_archive(Arc::new(
+            S3Archive::new(
+                &config.track_archive_endpoint,
+                &config.track_archive_region,
+                &config.track_archive_bucket,
+                &config.track_archive_access_key,
+                &config.track_archive_secret_key,
+            )
+            .expect("invalid track archive configuration"),
+        ));
+    }
+    service = service.with_fitness_providers(
+        HttpFitness::parse_all(&config.fitness_providers)
+            .expect("invalid FITNESS_PROVIDERS")
+            .into_iter()
+            .map(|p| Arc::new(p) as Arc<dyn FitnessProvider>)
+            .collect(),
+    );
+    service = service.with_distance_strategy(
+        DistanceStrategy::parse(&config.distance_strategy).expect("invalid DISTANCE_STRATEGY"),
+    );
+    if !config.static_map_url.is_empty() {
+        service = service.with_static_maps(Arc::new(HttpStaticMaps::new(
+            &config.static_map_url,
+            &config.static_map_key,
+        )));
+    }
+    if !config.osrm_url.is_empty() {
+        service = service.with_routing(Arc::new(Osrm::new(&config.osrm_url, &config.osrm_profile)));
+    }
+    if !config.fcm_credentials_file.is_empty() {
+        service = service.with_notifications(Arc::new(
+            Fcm::new(&config.fcm_credentials_file).expect("invalid FCM credentials"),
+        ));
+    }
+    service = service.with_alerts(
+        AlertRouter::parse(&config.alert_routes, |url| Arc::new(ChatWebhook::new(url)))
+            .expect("invalid ALERT_ROUTES"),
+    );
+    if !config.webhook_url.is_empty() {
+        let webhook =
+            HttpWebhook::new(&config.webhook_url).with_keys(service.secret_rings().webhook.clone());
+        service = service.with_event_publisher(Arc::new(webhook));
+    }
+    if !config.user_service_url.is_empty() {
+        service = service
+            .with_onboarding(Arc::new(HttpUsers::new(&config.user_service_url)))
+            .with_user_directory(Arc::new(HttpUsers::new(&config.user_service_url)));
+        if config.walker_capabilities_source == "user_service" {
+            service = service
+                .with_capability_directory(Arc::new(HttpUsers::new(&config.user_service_url)));
+        }
+    }
+    service
+}
+
+fn ranker(kind: RankerKind, config: &Config) -> Arc<dyn Ranker> {
+    match kind {
+        RankerKind::Distance => Arc::new(ByDistance),
+        RankerKind::SoonestStart => Arc::new(SoonestStart),
+        RankerKind::ExposureBalanced => Arc::new(ExposureBalanced::default()),
+        RankerKind::Scored => {
+            assert!(
+                !config.ranking_service_url.is_empty(),
+                "scored ranking requires RANKING_SERVICE_URL"
+            );
+            Arc::new(HttpRanker::new(&config.ranking_service_url))
+        }
+    }
+}
+
+async fn serve<R>(config: Config, service: Service<R>, supervisor: Supervisor) -> io::Result<()>
+where
+    R: Repository + Clone + Send + Sync + 'static,
+{
+    supervisor.supervise_with("daily_metrics", service.clone(), jobs::daily_metrics);
+    supervisor.supervise_with("sla_breaches", service.clone(), jobs::sla_breaches);
+    supervisor.supervise_with(
+        "expire_walk_requests",
+        service.clone(),
+        jobs::expire_walk_requests,
+    );
+    supervisor.supervise_with(
+        "time_out_no_shows",
+        service.clone(),
+        jobs::time_out_no_shows,
+    );
+    supervisor.supervise_with("dispatch_outbox", service.clone(), jobs::dispatch_outbox);
+    supervisor.supervise_with(
+        "send_start_reminders",
+        service.clone(),
+        jobs::send_start_reminders,
+    );
+    supervisor.supervise_with(
+        "finalize_cancellations",
+        service.clone(),
+        jobs::finalize_cancellations,
+    );
+    supervisor.supervise_with(
+        "archive_walk_requests",
+        service.clone(),
+        jobs::archive_walk_requests,
+    );
+    supervisor.supervise_with("fitness_exports", service.clone(), jobs::fitness_exports);
+    supervisor.supervise_with(
+        "materialize_schedules",
+        service.clone(),
+        jobs::materialize_schedules,
+    );
+    supervisor.supervise_with(
+        "downsample_tracks",
+        service.clone(),
+        jobs::downsample_tracks,
+    );
+    supervisor.supervise_with(
+        "reconcile_walk_requests",
+        service.clone(),
+        jobs::reconcile_walk_requests,
+    );
+    let dry_run = config.retention_dry_run;
+    supervisor.supervise_with("purge_expired_data", service.clone(), move |service| {
+        jobs::purge_expired_data(service, dry_run)
+    });
+    let rings = service.secret_rings().clone();
+    let calendar_signer = CalendarTokenSigner::new(rings.calendar.clone());
+    let log_format = config.log_format.clone();
+    let capabilities = capabilities(&config);
+    let quotas = ApiQuotas::parse(
+        &config.research_api_keys,
+        Duration::from_secs(config.research_quota_window_seconds),
+    )
+    .expect("invalid RESEARCH_API_KEYS");
+    let service_clients =
+        ServiceClients::parse(&config.internal_api_keys).expect("invalid INTERNAL_API_KEYS");
+    if !config.grpc_listen_address.is_empty() {
+        let address = config
+            .grpc_listen_address
+            .parse()
+            .expect("invalid GRPC_LISTEN_ADDRESS");
+        let (service, service_clients) = (service.clone(), service_clients.clone());
+        supervisor.supervise("grpc", move || {
+            let grpc =
+                WalkRequestsServer::new(GrpcServer::new(service.clone(), service_clients.clone()));
+            async move {
+                if let Err(e) = tonic::transport::Server::builder()
+                    .add_service(grpc)
+                    .serve(address)
+                    .await
+                {
+                    log::error!("gRPC server stopped: {}", e);
+                }
+            }
+        });
+    }
+    let authenticator = Authenticator::parse(
+        &config.auth_mode,
+        &config.jwt_algorithm,
+        rings.jwt.clone(),
+        config.trust_identity_headers,
+    )
+    .expect("invalid authentication configuration");
+    let policy = ResponsePolicy {
+        casing: Casing::parse(&config.v2_field_casing).expect("invalid V2_FIELD_CASING"),
+    };
+    let distance_unit = DistanceUnit::parse(&config.distance_unit).expect("invalid DISTANCE_UNIT");
+    let mut rate_limiter = RateLimiter::new(
+        config.rate_limit_reads,
+        config.rate_limit_writes,
+        Duration::from_secs(config.rate_limit_window_seconds),
+    );
+    if config.rate_limit_shared {
+        assert!(
+            !config.redis_url.is_empty(),
+            "shared rate limits require REDIS_URL"
+        );
+        rate_limiter = rate_limiter.with_shared_counters(Arc::new(
+            RedisCache::new(&config.redis_url).expect("invalid REDIS_URL"),
+        ));
+    }
+    let cors = CorsPolicy::parse(
+        &config.cors_allowed_origins,
+        &config.cors_allowed_methods,
+        &config.cors_allowed_headers,
+        config.cors_max_age_seconds,
+    )
+    .expect("invalid CORS configuration");
+    let hsts_max_age = config.hsts_max_age_seconds;
+    let compress_responses = config.compress_responses;
+    let shutdown = service.clone();
+    let flushing = service.clone();
+    let workers = supervisor.clone();
+    let mut server = HttpServer::new(move || {
         App::new()
             .app_data(Data::new(service.clone()))
+            .app_data(Data::new(workers.clone()))
+            .app_data(Data::new(rate_limiter.clone()))
+            .app_data(Data::new(calendar_signer.clone()))
+            .app_data(Data::new(capabilities.clone()))
+            .app_data(Data::new(quotas.clone()))
+            .app_data(Data::new(service_clients.clone()))
+            .app_data(Data::new(authenticator.clone()))
+            .wrap(Condition::new(compress_responses, Compress::default()))
+            .wrap(security_headers(hsts_max_age))
+            .wrap(Condition::new(cors.is_enabled(), cors.middleware()))
             .wrap(Logger::new(&log_format))
-            .service(
-                scope("apis").service(
-                    scope("walk_requests")
-                        .route("", post().to(handlers::create_walk_request::<Mongodb>))
-                        .route(
-                            "nearby",
-                            get().to(handlers::nearby_walk_requests::<Mongodb>),
-                        )
-                        .route("mine", get().to(handlers::my_walk_requests::<Mongodb>))
-                        .route("/{id}/accepted_by", put().to(accept::<Mongodb>))
-                        .route(
-                            "/{id}/acceptances",
-                            delete().to(remove_acceptance::<Mongodb>),
-                        )
-                        .route("/{id}/accepter/{uid}", put().to(assign_accepter::<Mongodb>))
-                        .route(
-                            "/{id}/accepter/{uid}",
-                            delete().to(dismiss_accepter::<Mongodb>),
-                        )
-                        .route("/{id}/resign", delete().to(resign_acceptance::<Mongodb>))
-                        .route(
-                            "/{id}/accepted_by/{uid}",
-                            delete().to(cancel_accepted_request::<Mongodb>),
-                        )
-                        .route("/{id}", delete().to(cancel_unaccepted_request::<Mongodb>))
-                        .route("/{id}/start", put().to(start_walk::<Mongodb>))
-                        .route("/{id}/finish", put().to(finish_walk::<Mongodb>))
-                        .route(
-                            "/{id}/locations",
-                            post().to(record_walking_location::<Mongodb>),
-                        ),
-                ),
-            )
+            .wrap(RequestId)
+            .configure(|cfg| routes::<R>(cfg, policy, distance_unit))
     })
-    .bind(config.listen_address)
+    .keep_alive(match config.keep_alive_seconds {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(Duration::from_secs(seconds)),
+    })
+    .client_request_timeout(Duration::from_millis(config.client_request_timeout_millis))
+    .shutdown_timeout(config.shutdown_timeout_seconds)
+    .disable_signals();
+    if config.http_workers > 0 {
+        server = server.workers(config.http_workers);
+    }
+    let server = if config.tls_cert_file.is_empty() {
+        server.bind(&config.listen_address)
+    } else {
+        let tls = tls::server_config(&config.tls_cert_file, &config.tls_key_file)?;
+        server.bind_rustls_021(&config.listen_address, tls)
+    }
     .expect("Can't bind to address")
-    .run()
-    .await
+    .run();
+    actix_web::rt::spawn(stop_on_signal(server.handle(), shutdown, supervisor));
+    let result = server.await;
+    flushing.flush_locations().await;
+    result
+}
+
+#[cfg(unix)]
+async fn terminated() {
+    use actix_web::rt::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            terminate.recv().await;
+        }
+        Err(e) => {
+            log::error!("failed to listen for SIGTERM: {}", e);
+            future::pending::<()>().await
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    future::pending::<()>().await
+}
+
+/// On SIGTERM or Ctrl-C stops the background workers and taking
+/// connections, and lets the requests in flight, location uploads included,
+/// finish within the shutdown timeout.
+async fn stop_on_signal<R>(server: ServerHandle, service: Service<R>, supervisor: Supervisor)
+where
+    R: Repository + Clone,
+{
+    future::select(Box::pin(terminated()), Box::pin(ctrl_c())).await;
+    log::info!("shutting down");
+    supervisor.shutdown().await;
+    service.shutdown();
+    server.stop(true).await;
+}
+
+fn listen_walk_request_changes<R>(
+    config: &Config,
+    mongodb: Mongodb,
+    service: &Service<R>,
+    supervisor: &Supervisor,
+) where
+    R: Repository + Clone + 'static,
+{
+    if config.mongodb.change_streams {
+        supervisor.supervise_with(
+            "walk_request_changes",
+            WalkRequestListener::new(mongodb, service.clone()),
+            WalkRequestListener::run,
+        );
+    }
+}
+
+/// Connects to MongoDB, retrying with exponential backoff while it isn't up
+/// yet.
+async fn connect_mongodb(config: &Config) -> io::Result<Client> {
+    let mut delay = MONGODB_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match try_connect_mongodb(config).await {
+            Ok(client) => return Ok(client),
+            Err(e) if attempt < config.mongodb.connect_attempts => {
+                log::warn!(
+                    "failed to connect to mongodb (attempt {}), retrying in {:?}: {}",
+                    attempt,
+                    delay,
+                    e
+                );
+                actix_web::rt::time::sleep(delay).await;
+                delay = (delay * 2).min(MONGODB_MAX_RETRY_DELAY);
+                attempt += 1;
+            }
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("failed to connect to mongodb: {}", e),
+                ))
+            }
+        }
+    }
+}
+
+async fn try_connect_mongodb(config: &Config) -> Result<Client, mongodb::error::Error> {
+    let mut options = ClientOptions::parse(&config.database_url).await?;
+    if config.mongodb.max_pool_size > 0 {
+        options.max_pool_size = Some(config.mongodb.max_pool_size);
+    }
+    if config.mongodb.min_pool_size > 0 {
+        options.min_pool_size = Some(config.mongodb.min_pool_size);
+    }
+    let client = Client::with_options(options)?;
+    // The client connects lazily, a ping finds out whether the server is up.
+    client
+        .database("admin")
+        .run_command(doc! {"ping": 1}, None)
+        .await?;
+    Ok(client)
+}
+
+/// Logs through `tracing`, lines of the `log` crate included.
+fn init_logging(config: &Config) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.log_level));
+    let logs = tracing_subscriber::fmt().with_env_filter(filter);
+    if config.log_json {
+        logs.json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .init();
+    } else {
+        logs.init();
+    }
+}
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    dotenv().ok();
+    let args: Vec<String> = std::env::args().collect();
+    let file = args
+        .iter()
+        .position(|a| a == "--config")
+        .and_then(|i| args.get(i + 1).cloned())
+        .or_else(|| std::env::var("CONFIG_FILE").ok());
+    let config = match Config::load(file.as_deref()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+    if args.iter().any(|a| a == "--print-config") {
+        let printable = config
+            .printable()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        println!("{}", printable);
+        return Ok(());
+    }
+    init_logging(&config);
+    if config.persistence_mode == "memory" {
+        let supervisor = Supervisor::default();
+        let service = build_service(&config, InMemory::new(), &supervisor);
+        return serve(config, service, supervisor).await;
+    }
+    if config.database_kind == "postgres" {
+        let pool = PgPoolOptions::new()
+            .connect(&config.database_url)
+            .await
+            .expect("failed to connect to postgres");
+        let repository = Postgres::new(pool.clone());
+        repository
+            .migrate()
+            .await
+            .expect("failed to migrate postgres");
+        let supervisor = Supervisor::default();
+        let service = build_service(&config, repository, &supervisor);
+        let result = serve(config, service, supervisor).await;
+        pool.close().await;
+        return result;
+    }
+    let client = connect_mongodb(&config).await?;
+    let db = client.database(&config.database_name);
+    let id_format = IdFormat::parse(&config.id_format).expect("invalid id format");
+    if !config.skip_index_bootstrap {
+        Mongodb::new(db.clone())
+            .ensure_indexes(
+                (config.walking_location_ttl_days > 0)
+                    .then(|| Duration::from_secs(config.walking_location_ttl_days * 24 * 60 * 60)),
+            )
+            .await
+            .expect("failed to create mongodb indexes");
+    }
+    Mongodb::new(db.clone())
+        .rebuild_feed()
+        .await
+        .expect("failed to build the nearby feed");
+    let mut mongodb = Mongodb::new(db.clone()).with_id_format(id_format);
+    if config.mongodb.transactions {
+        mongodb = mongodb.with_transactions(client.clone());
+    }
+    let supervisor = Supervisor::default();
+    let result = match config.persistence_mode.as_str() {
+        "event_sourced" => {
+            let service =
+                build_service(&config, EventSourced::new(mongodb.clone(), db), &supervisor);
+            listen_walk_request_changes(&config, mongodb, &service, &supervisor);
+            serve(config, service, supervisor).await
+        }
+        _ => {
+            let service = build_service(&config, mongodb.clone(), &supervisor);
+            listen_walk_request_changes(&config, mongodb, &service, &supervisor);
+            serve(config, service, supervisor).await
+        }
+    };
+    // Waits for operations still running on the client.
+    client.shutdown().await;
+    result
 }
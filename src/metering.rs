@@ -0,0 +1,138 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{forward_ready, Service as ActixService, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue, ACCEPT_LANGUAGE, RETRY_AFTER},
+    web::Data,
+    Error, HttpResponse,
+};
+use chrono::Utc;
+use futures::future::{ready, LocalBoxFuture, Ready};
+use serde_json::json;
+
+use crate::core::{
+    i18n::{self, Locale},
+    repository::Repository,
+    service::Service,
+    usage::QuotaStatus,
+};
+
+const QUOTA_LIMIT: HeaderName = HeaderName::from_static("x-quota-limit");
+const QUOTA_REMAINING: HeaderName = HeaderName::from_static("x-quota-remaining");
+const QUOTA_RESET: HeaderName = HeaderName::from_static("x-quota-reset");
+
+/// Meters calls made with an `X-Api-Key` against the key's monthly quota,
+/// answering 429 once it is used up. Responses of metered keys carry the
+/// quota headers. Metering failures let the call through.
+pub struct Metering<R> {
+    repository: PhantomData<R>,
+}
+
+impl<R> Default for Metering<R> {
+    fn default() -> Self {
+        Self {
+            repository: PhantomData,
+        }
+    }
+}
+
+impl<S, B, R> Transform<S, ServiceRequest> for Metering<R>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: Repository + Clone + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = MeteringMiddleware<S, R>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(MeteringMiddleware {
+            service: Rc::new(service),
+            repository: PhantomData,
+        }))
+    }
+}
+
+pub struct MeteringMiddleware<S, R> {
+    service: Rc<S>,
+    repository: PhantomData<R>,
+}
+
+fn insert_quota_headers(headers: &mut actix_web::http::header::HeaderMap, status: &QuotaStatus) {
+    for (name, value) in [
+        (QUOTA_LIMIT, status.limit),
+        (QUOTA_REMAINING, status.remaining()),
+        (QUOTA_RESET, status.reset_at.timestamp() as u64),
+    ] {
+        headers.insert(name, HeaderValue::from(value));
+    }
+}
+
+impl<S, B, R> ActixService<ServiceRequest> for MeteringMiddleware<S, R>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: Repository + Clone + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let key = req
+                .headers()
+                .get("X-Api-Key")
+                .and_then(|k| k.to_str().ok())
+                .map(str::to_owned);
+            let app = req.app_data::<Data<Service<R>>>().cloned();
+            let (Some(key), Some(app)) = (key, app) else {
+                return service.call(req).await.map(|r| r.map_into_boxed_body());
+            };
+            let endpoint = format!(
+                "{} {}",
+                req.method(),
+                req.match_pattern().unwrap_or_else(|| req.path().to_owned())
+            );
+            let status = match app.meter_api_call(&key, &endpoint).await {
+                Ok(status) => status,
+                Err(e) => {
+                    log::error!("failed to meter a call of {}: {}", endpoint, e);
+                    None
+                }
+            };
+            let Some(status) = status else {
+                return service.call(req).await.map(|r| r.map_into_boxed_body());
+            };
+            if status.exceeded {
+                let locale = Locale::negotiate(
+                    req.headers()
+                        .get(ACCEPT_LANGUAGE)
+                        .and_then(|v| v.to_str().ok()),
+                );
+                let mut response = HttpResponse::TooManyRequests()
+                    .insert_header((
+                        RETRY_AFTER,
+                        (status.reset_at - Utc::now()).num_seconds().max(1),
+                    ))
+                    .json(json!({
+                        "code": "quota_exceeded",
+                        "reason": "quota_exceeded",
+                        "message": i18n::localize("请求次数超出配额", "quota_exceeded", locale),
+                    }));
+                insert_quota_headers(response.headers_mut(), &status);
+                return Ok(req.into_response(response));
+            }
+            let mut res = service.call(req).await?;
+            insert_quota_headers(res.headers_mut(), &status);
+            Ok(res.map_into_boxed_body())
+        })
+    }
+}
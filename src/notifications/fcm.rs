@@ -0,0 +1,132 @@
+use std::sync::Mutex;
+
+use anyhow::Error;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::core::notification::{Notification, Notifier};
+
+const MESSAGING_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+/// Sends through the FCM HTTP v1 API, which delivers to iOS devices through
+/// APNs. Devices registered with a push token are reached by token, the
+/// others subscribe to the `user-<id>` topic.
+pub struct Fcm {
+    credentials: Credentials,
+    client: reqwest::Client,
+    access_token: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+/// The fields of a service account key file used here.
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Assertion<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct Grant {
+    access_token: String,
+    expires_in: i64,
+}
+
+impl Fcm {
+    /// `credentials_file` is the JSON key of a service account allowed to
+    /// send messages.
+    pub fn new(credentials_file: &str) -> Result<Self, Error> {
+        let credentials = serde_json::from_str(&std::fs::read_to_string(credentials_file)?)?;
+        Ok(Self {
+            credentials,
+            client: reqwest::Client::new(),
+            access_token: Mutex::new(None),
+        })
+    }
+
+    async fn access_token(&self) -> Result<String, Error> {
+        let now = Utc::now();
+        if let Some((token, expires_at)) = self.access_token.lock().unwrap().as_ref() {
+            if *expires_at > now {
+                return Ok(token.clone());
+            }
+        }
+        let assertion = encode(
+            &Header::new(Algorithm::RS256),
+            &Assertion {
+                iss: &self.credentials.client_email,
+                scope: MESSAGING_SCOPE,
+                aud: &self.credentials.token_uri,
+                iat: now.timestamp(),
+                exp: (now + Duration::hours(1)).timestamp(),
+            },
+            &EncodingKey::from_rsa_pem(self.credentials.private_key.as_bytes())?,
+        )?;
+        let grant = self
+            .client
+            .post(&self.credentials.token_uri)
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Grant>()
+            .await?;
+        // Renewed a minute early so a token doesn't expire in flight.
+        *self.access_token.lock().unwrap() = Some((
+            grant.access_token.clone(),
+            now + Duration::seconds(grant.expires_in - 60),
+        ));
+        Ok(grant.access_token)
+    }
+}
+
+#[async_trait]
+impl Notifier for Fcm {
+    async fn notify(&self, notification: &Notification) -> Result<(), Error> {
+        let token = self.access_token().await?;
+        let targets = match &notification.devices {
+            Some(devices) => devices.iter().map(|d| ("token", d.clone())).collect(),
+            None => vec![("topic", format!("user-{}", notification.user_id))],
+        };
+        for (kind, target) in targets {
+            let mut message = json!({
+                "notification": {
+                    "title": notification.kind.title(),
+                    "body": notification.body,
+                },
+                "data": {
+                    "kind": notification.kind,
+                    "request_id": notification.request_id,
+                },
+                "apns": { "payload": { "aps": { "sound": "default" } } },
+            });
+            message[kind] = target.into();
+            self.client
+                .post(format!(
+                    "https://fcm.googleapis.com/v1/projects/{}/messages:send",
+                    self.credentials.project_id
+                ))
+                .bearer_auth(&token)
+                .json(&json!({ "message": message }))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use utoipa::OpenApi;
+
+use crate::{
+    core::{
+        delegation::Delegation,
+        distance::DistanceStrategy,
+        entities::{PoolWalker, TrackVisibility, WalkRequest, WalkRequestStatus, WalkingLocation},
+        export::ExportFormat,
+        geo::{WalkProgress, WalkSummary},
+        profile::UserProfile,
+        repository::PagedWalkRequest,
+        units::Meters,
+        walker_capabilities::DogRequirements,
+    },
+    handlers::{self, dto},
+};
+
+/// OpenAPI document of the walk request routes, served at
+/// `/apis/openapi.json` and browsable at `/apis/swagger-ui/`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "little-walk-request"),
+    paths(
+        handlers::create_walk_request,
+        handlers::nearby_walk_requests,
+        handlers::walk_requests_in_area,
+        handlers::get_walk_request,
+        handlers::edit_walk_request,
+        handlers::accept,
+        handlers::apply,
+        handlers::start_walk,
+        handlers::waive_walk_verification,
+        handlers::finish_walk,
+        handlers::walk_summary,
+        handlers::walk_progress,
+        handlers::record_walking_location,
+        handlers::walking_locations,
+    ),
+    components(schemas(
+        dto::CreateWalkRequestBody,
+        dto::EditWalkRequestBody,
+        WalkRequest,
+        WalkRequestStatus,
+        TrackVisibility,
+        PoolWalker,
+        UserProfile,
+        DogRequirements,
+        Delegation,
+        Meters,
+        WalkSummary,
+        WalkProgress,
+        DistanceStrategy,
+        ExportFormat,
+        WalkingLocation,
+        PagedWalkRequest,
+        handlers::Location,
+        handlers::WalkStart,
+        handlers::WalkFinish,
+    )),
+    tags((name = "walk_requests", description = "代遛请求"))
+)]
+pub struct ApiDoc;
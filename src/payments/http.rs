@@ -0,0 +1,90 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::core::{
+    payment::{Payment, PaymentGateway},
+    saga::PaymentHolds,
+};
+
+/// Client for the payment service's hold and charge APIs.
+pub struct HttpPayments {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl HttpPayments {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Hold {
+    id: String,
+}
+
+#[async_trait]
+impl PaymentHolds for HttpPayments {
+    async fn hold(&self, request_id: &str, owner_id: &str) -> Result<String, Error> {
+        let hold = self
+            .client
+            .post(format!("{}/holds", self.base_url))
+            .json(&json!({"reference": request_id, "payer": owner_id}))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Hold>()
+            .await?;
+        Ok(hold.id)
+    }
+
+    async fn confirm(&self, hold_id: &str) -> Result<(), Error> {
+        self.client
+            .put(format!("{}/holds/{}/confirm", self.base_url, hold_id))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn release(&self, hold_id: &str) -> Result<(), Error> {
+        self.client
+            .delete(format!("{}/holds/{}", self.base_url, hold_id))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Charge {
+    id: String,
+}
+
+#[async_trait]
+impl PaymentGateway for HttpPayments {
+    async fn charge(&self, payment: &Payment) -> Result<String, Error> {
+        let charge = self
+            .client
+            .post(format!("{}/charges", self.base_url))
+            .json(&json!({
+                "reference": payment.request_id,
+                "payer": payment.payer_id,
+                "payee": payment.payee_id,
+                "amount": payment.amount.minor_units,
+                "currency": payment.amount.currency,
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Charge>()
+            .await?;
+        Ok(charge.id)
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    entities::WalkRequest,
+    ranking::{sort_by_scores, Ranker, RankingContext},
+};
+
+/// Scores requests with an external model service.
+pub struct HttpRanker {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpRanker {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScoreRequest<'a> {
+    walker_id: Option<&'a str>,
+    requests: &'a [WalkRequest],
+    impressions: Vec<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    variant: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct ScoreResponse {
+    /// In the order of the requests sent.
+    scores: Vec<f64>,
+}
+
+#[async_trait]
+impl Ranker for HttpRanker {
+    async fn rank(
+        &self,
+        requests: &mut [WalkRequest],
+        context: &RankingContext,
+    ) -> Result<(), Error> {
+        let impressions = requests
+            .iter()
+            .map(|r| context.impressions.get(&r.id).copied().unwrap_or(0))
+            .collect();
+        let response: ScoreResponse = self
+            .client
+            .post(&self.url)
+            .json(&ScoreRequest {
+                walker_id: context.walker.as_deref(),
+                requests,
+                impressions,
+                variant: context.variant.as_deref(),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        sort_by_scores(requests, &response.scores)
+    }
+}
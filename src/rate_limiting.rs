@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{forward_ready, Service as ActixService, ServiceRequest, ServiceResponse, Transform},
+    http::header::{ACCEPT_LANGUAGE, RETRY_AFTER},
+    web::Data,
+    Error, HttpResponse,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use serde_json::json;
+
+use crate::{
+    core::{
+        i18n::{self, Locale},
+        rate_limit::{RateLimiter, RouteGroup},
+    },
+    handlers::principal,
+};
+
+/// Limits how often each user calls the API, answering 429 with
+/// `Retry-After` once the budget of the route group is used up. Calls
+/// without a user are left to the handlers to reject.
+#[derive(Default)]
+pub struct RateLimiting;
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimiting
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = RateLimitingMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitingMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RateLimitingMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> ActixService<ServiceRequest> for RateLimitingMiddleware<S>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let limiter = req.app_data::<Data<RateLimiter>>().cloned();
+            let user_id = principal(req.request()).ok().map(|(user_id, _)| user_id);
+            let (Some(limiter), Some(user_id)) = (limiter, user_id) else {
+                return service.call(req).await.map(|r| r.map_into_boxed_body());
+            };
+            let group = RouteGroup::of(req.method().as_str());
+            if let Err(limited) = limiter.check(&user_id, group).await {
+                let locale = Locale::negotiate(
+                    req.headers()
+                        .get(ACCEPT_LANGUAGE)
+                        .and_then(|v| v.to_str().ok()),
+                );
+                let response = HttpResponse::TooManyRequests()
+                    .insert_header((RETRY_AFTER, limited.retry_after.as_secs().max(1)))
+                    .json(json!({
+                        "code": "rate_limited",
+                        "reason": "rate_limited",
+                        "message": i18n::localize("请求过于频繁", "rate_limited", locale),
+                    }));
+                return Ok(req.into_response(response));
+            }
+            service.call(req).await.map(|r| r.map_into_boxed_body())
+        })
+    }
+}
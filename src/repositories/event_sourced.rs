@@ -0,0 +1,926 @@
+use std::{collections::HashMap, future::Future};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, from_document, to_document, Document},
+    options::{FindOneOptions, FindOptions},
+    Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    audit::{chain_hash, AuditVerification},
+    backfill::BackfillRun,
+    entities::{Application, ApplicationState, WalkRequest, WalkingLocation},
+    error::ServiceError,
+    events,
+    favorite::FavoriteWalker,
+    feature_flags::FeatureFlag,
+    fitness::{FitnessExport, FitnessToken},
+    holiday::Holiday,
+    message::Message,
+    metrics::{DailyMetrics, FunnelMarks, FunnelStage},
+    offer::Offer,
+    outbox::OutboxMessage,
+    payment::Payment,
+    receipt::{Payout, Receipt},
+    reminder::StartReminder,
+    report::{ReportBucket, ReportInterval},
+    repository::{
+        Pagination, Repository, SortBy, WalkRequestCreate, WalkRequestQuery, WalkRequestStream,
+        WalkRequestUpdate, WalkingLocationCreate, WalkingLocationQuery,
+    },
+    reputation::{Reputation, ReputationUpdate},
+    retention::DataClass,
+    saga::BookingSaga,
+    saved_search::SavedSearch,
+    schedule::WalkSchedule,
+    session::DeviceSession,
+    spending::SpendingCap,
+    strike::Strike,
+    template::WalkRequestTemplate,
+    tenant::Tenant,
+    units::Meters,
+    usage::UsageRecord,
+    walker_capabilities::WalkerCapabilities,
+};
+
+/// A snapshot of the replayed state is stored every this many events.
+const SNAPSHOT_INTERVAL: i64 = 20;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EventKind {
+    Created,
+    Updated,
+}
+
+impl EventKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "Created",
+            EventKind::Updated => "Updated",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalkRequestEvent {
+    pub request_id: String,
+    pub seq: i64,
+    pub kind: EventKind,
+    pub payload: Document,
+    pub occurred_at: DateTime<Utc>,
+    /// `hash` of the previous event of the request, empty for the first one.
+    #[serde(default)]
+    pub prev_hash: String,
+    #[serde(default)]
+    pub hash: String,
+}
+
+impl WalkRequestEvent {
+    fn compute_hash(&self) -> Result<String, ServiceError> {
+        let payload = serde_json::to_string(&self.payload).map_err(anyhow::Error::from)?;
+        Ok(chain_hash(
+            &self.prev_hash,
+            &self.request_id,
+            self.seq,
+            self.kind.as_str(),
+            &payload,
+            self.occurred_at,
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    request_id: String,
+    seq: i64,
+    state: WalkRequest,
+    taken_at: DateTime<Utc>,
+}
+
+/// Event-sourced persistence: every walk request change is appended to the
+/// `walk_request_events` log (with periodic snapshots) before being applied to
+/// the read model kept by the inner repository, which keeps serving queries.
+/// The log gives full history and time-travel reads through `walk_request_at`.
+#[derive(Debug, Clone)]
+pub struct EventSourced<R> {
+    inner: R,
+    db: Database,
+}
+
+impl<R> EventSourced<R>
+where
+    R: Repository + Clone,
+{
+    pub fn new(inner: R, db: Database) -> Self {
+        Self { inner, db }
+    }
+
+    async fn append(
+        &self,
+        request_id: &str,
+        kind: EventKind,
+        payload: Document,
+    ) -> Result<(), ServiceError> {
+        let events = self
+            .db
+            .collection::<WalkRequestEvent>("walk_request_events");
+        // Continue from the last event rather than counting, the oldest ones
+        // may have been purged.
+        let (seq, prev_hash) = events
+            .find_one(
+                doc! {"request_id": request_id},
+                FindOneOptions::builder().sort(doc! {"seq": -1}).build(),
+            )
+            .await?
+            .map_or((1, String::new()), |last| (last.seq + 1, last.hash));
+        let now = Utc::now();
+        let mut event = WalkRequestEvent {
+            request_id: request_id.to_owned(),
+            seq,
+            kind,
+            payload,
+            occurred_at: now,
+            prev_hash,
+            hash: String::new(),
+        };
+        event.hash = event.compute_hash()?;
+        events.insert_one(event, None).await?;
+        if seq % SNAPSHOT_INTERVAL == 0 {
+            if let Some(state) = self.walk_request_at(request_id, now).await? {
+                self.db
+                    .collection::<Snapshot>("walk_request_snapshots")
+                    .insert_one(
+                        Snapshot {
+                            request_id: request_id.to_owned(),
+                            seq,
+                            state,
+                            taken_at: now,
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Full event history of a walk request, oldest first.
+    pub async fn history(&self, request_id: &str) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        self.db
+            .collection::<WalkRequestEvent>("walk_request_events")
+            .find(
+                doc! {"request_id": request_id},
+                FindOptions::builder().sort(doc! {"seq": 1}).build(),
+            )
+            .await?
+            .try_collect()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    /// Re-validates the hash chain of the request's remaining events.
+    pub async fn verify_chain(&self, request_id: &str) -> Result<AuditVerification, ServiceError> {
+        let events = self.history(request_id).await?;
+        if events.is_empty() {
+            return Err(ServiceError::NotFound("审计记录不存在".to_owned()));
+        }
+        let truncated = events[0].seq > 1;
+        let mut first_invalid_seq = None;
+        let mut prev: Option<&WalkRequestEvent> = None;
+        for event in &events {
+            let linked = match prev {
+                Some(prev) => event.seq == prev.seq + 1 && event.prev_hash == prev.hash,
+                None => truncated || event.prev_hash.is_empty(),
+            };
+            if !linked || event.hash != event.compute_hash()? {
+                first_invalid_seq = Some(event.seq);
+                break;
+            }
+            prev = Some(event);
+        }
+        Ok(AuditVerification {
+            request_id: request_id.to_owned(),
+            entries: events.len() as u64,
+            valid: first_invalid_seq.is_none(),
+            first_invalid_seq,
+            truncated,
+        })
+    }
+
+    /// State of the walk request as of `at`, replayed from the closest snapshot.
+    pub async fn walk_request_at(
+        &self,
+        request_id: &str,
+        at: DateTime<Utc>,
+    ) -> Result<Option<WalkRequest>, ServiceError> {
+        let snapshot = self
+            .db
+            .collection::<Snapshot>("walk_request_snapshots")
+            .find_one(
+                doc! {"request_id": request_id, "taken_at": {"$lte": at}},
+                FindOneOptions::builder().sort(doc! {"seq": -1}).build(),
+            )
+            .await?;
+        let (mut state, after_seq) = match snapshot {
+            Some(snapshot) => (Some(snapshot.state), snapshot.seq),
+            None => (None, 0),
+        };
+        let events: Vec<WalkRequestEvent> = self
+            .db
+            .collection::<WalkRequestEvent>("walk_request_events")
+            .find(
+                doc! {
+                    "request_id": request_id,
+                    "seq": {"$gt": after_seq},
+                    "occurred_at": {"$lte": at},
+                },
+                FindOptions::builder().sort(doc! {"seq": 1}).build(),
+            )
+            .await?
+            .try_collect()
+            .await?;
+        for event in events {
+            match event.kind {
+                EventKind::Created => {
+                    let create: WalkRequestCreate = from_document(event.payload)?;
+                    state = Some(create.into_walk_request(request_id, event.occurred_at));
+                }
+                EventKind::Updated => {
+                    if let Some(request) = state.as_mut() {
+                        let update: WalkRequestUpdate = from_document(event.payload)?;
+                        update.apply_to(request);
+                        request.updated_at = Some(event.occurred_at);
+                    }
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+impl<R> Repository for EventSourced<R>
+where
+    R: Repository + Clone,
+{
+    /// The inner repository's transaction, events are appended outside of it.
+    async fn transaction<T, F, Fut>(&self, operation: F) -> Result<T, ServiceError>
+    where
+        Self: Clone,
+        F: FnOnce(Self) -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        let db = self.db.clone();
+        self.inner
+            .transaction(|inner| operation(Self { inner, db }))
+            .await
+    }
+
+    fn for_tenant(&self, tenant_id: &str) -> Self {
+        Self {
+            inner: self.inner.for_tenant(tenant_id),
+            db: self.db.clone(),
+        }
+    }
+
+    async fn create_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<String, ServiceError> {
+        let payload = to_document(&request)?;
+        let id = self.inner.create_walk_request(request).await?;
+        self.append(&id, EventKind::Created, payload).await?;
+        Ok(id)
+    }
+
+    async fn update_walk_request(
+        &self,
+        id: &str,
+        request: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        let payload = to_document(&request)?;
+        let updated = self.inner.update_walk_request(id, request).await?;
+        self.append(&updated.id, EventKind::Updated, payload)
+            .await?;
+        Ok(updated)
+    }
+
+    async fn update_walk_request_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        let payload = to_document(&update)?;
+        let updated = self
+            .inner
+            .update_walk_request_by_query(query, update)
+            .await?;
+        self.append(&updated.id, EventKind::Updated, payload)
+            .await?;
+        Ok(updated)
+    }
+
+    async fn update_walk_requests_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<u64, ServiceError> {
+        let payload = to_document(&update)?;
+        let matched = self
+            .inner
+            .query_walk_requests(query.clone(), None, None)
+            .await?;
+        let modified = self
+            .inner
+            .update_walk_requests_by_query(query, update)
+            .await?;
+        if modified > 0 {
+            for request in matched {
+                self.append(&request.id, EventKind::Updated, payload.clone())
+                    .await?;
+            }
+        }
+        Ok(modified)
+    }
+
+    async fn count_walk_requests(&self, query: WalkRequestQuery) -> Result<u64, ServiceError> {
+        self.inner.count_walk_requests(query).await
+    }
+
+    async fn walk_report(
+        &self,
+        query: WalkRequestQuery,
+        interval: ReportInterval,
+    ) -> Result<Vec<ReportBucket>, ServiceError> {
+        self.inner.walk_report(query, interval).await
+    }
+
+    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, ServiceError> {
+        self.inner.get_walk_request(id).await
+    }
+
+    async fn query_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        self.inner
+            .query_walk_requests(query, sort_by, pagination)
+            .await
+    }
+
+    async fn stream_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<WalkRequestStream, ServiceError> {
+        self.inner
+            .stream_walk_requests(query, sort_by, pagination)
+            .await
+    }
+
+    async fn create_walking_location(
+        &self,
+        create: WalkingLocationCreate<'_>,
+    ) -> Result<String, ServiceError> {
+        self.inner.create_walking_location(create).await
+    }
+
+    async fn create_walking_locations(
+        &self,
+        creates: Vec<WalkingLocationCreate<'_>>,
+    ) -> Result<Vec<String>, ServiceError> {
+        self.inner.create_walking_locations(creates).await
+    }
+
+    async fn query_walking_locations(
+        &self,
+        query: WalkingLocationQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError> {
+        self.inner.query_walking_locations(query, pagination).await
+    }
+
+    async fn simplified_track(
+        &self,
+        walk_request_id: &str,
+        tolerance: Meters,
+    ) -> Result<Vec<[f64; 2]>, ServiceError> {
+        self.inner
+            .simplified_track(walk_request_id, tolerance)
+            .await
+    }
+
+    async fn delete_walking_locations(&self, ids: &[String]) -> Result<u64, ServiceError> {
+        self.inner.delete_walking_locations(ids).await
+    }
+
+    async fn walk_requests_with_tracks_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, ServiceError> {
+        self.inner.walk_requests_with_tracks_before(before).await
+    }
+
+    async fn query_holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError> {
+        self.inner.query_holidays(region).await
+    }
+
+    async fn upsert_holiday(&self, holiday: Holiday) -> Result<(), ServiceError> {
+        self.inner.upsert_holiday(holiday).await
+    }
+
+    async fn delete_holiday(&self, region: &str, date: NaiveDate) -> Result<u64, ServiceError> {
+        self.inner.delete_holiday(region, date).await
+    }
+
+    async fn archive_walk_requests(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        self.inner.archive_walk_requests(before).await
+    }
+
+    async fn query_archived_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        self.inner
+            .query_archived_walk_requests(query, pagination)
+            .await
+    }
+
+    async fn query_fitness_tokens(&self, user_id: &str) -> Result<Vec<FitnessToken>, ServiceError> {
+        self.inner.query_fitness_tokens(user_id).await
+    }
+
+    async fn upsert_fitness_token(&self, token: FitnessToken) -> Result<(), ServiceError> {
+        self.inner.upsert_fitness_token(token).await
+    }
+
+    async fn delete_fitness_token(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<u64, ServiceError> {
+        self.inner.delete_fitness_token(user_id, provider).await
+    }
+
+    async fn query_favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError> {
+        self.inner.query_favorite_walkers(owner_id).await
+    }
+
+    async fn upsert_favorite_walker(&self, favorite: FavoriteWalker) -> Result<(), ServiceError> {
+        self.inner.upsert_favorite_walker(favorite).await
+    }
+
+    async fn delete_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<u64, ServiceError> {
+        self.inner.delete_favorite_walker(owner_id, walker_id).await
+    }
+
+    async fn save_fitness_export(&self, export: &FitnessExport) -> Result<(), ServiceError> {
+        self.inner.save_fitness_export(export).await
+    }
+
+    async fn due_fitness_exports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<FitnessExport>, ServiceError> {
+        self.inner.due_fitness_exports(now).await
+    }
+
+    async fn save_payment(&self, payment: &Payment) -> Result<(), ServiceError> {
+        self.inner.save_payment(payment).await
+    }
+
+    async fn get_payment(&self, request_id: &str) -> Result<Option<Payment>, ServiceError> {
+        self.inner.get_payment(request_id).await
+    }
+
+    async fn save_receipt(&self, receipt: &Receipt) -> Result<(), ServiceError> {
+        self.inner.save_receipt(receipt).await
+    }
+
+    async fn get_receipt(&self, request_id: &str) -> Result<Option<Receipt>, ServiceError> {
+        self.inner.get_receipt(request_id).await
+    }
+
+    async fn query_receipts(
+        &self,
+        owner_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        self.inner.query_receipts(owner_id, from, to).await
+    }
+
+    async fn save_payout(&self, payout: &Payout) -> Result<(), ServiceError> {
+        self.inner.save_payout(payout).await
+    }
+
+    async fn query_payouts(
+        &self,
+        walker_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payout>, ServiceError> {
+        self.inner.query_payouts(walker_id, from, to).await
+    }
+
+    async fn get_spending_cap(&self, owner_id: &str) -> Result<Option<SpendingCap>, ServiceError> {
+        self.inner.get_spending_cap(owner_id).await
+    }
+
+    async fn save_spending_cap(&self, cap: &SpendingCap) -> Result<(), ServiceError> {
+        self.inner.save_spending_cap(cap).await
+    }
+
+    async fn delete_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_spending_cap(owner_id).await
+    }
+
+    async fn mark_funnel_stage(
+        &self,
+        request_id: &str,
+        stage: FunnelStage,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        self.inner.mark_funnel_stage(request_id, stage, at).await
+    }
+
+    async fn record_impressions(
+        &self,
+        request_ids: &[String],
+        weight: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        self.inner.record_impressions(request_ids, weight, at).await
+    }
+
+    async fn query_funnel_marks(
+        &self,
+        request_ids: &[String],
+    ) -> Result<Vec<FunnelMarks>, ServiceError> {
+        self.inner.query_funnel_marks(request_ids).await
+    }
+
+    async fn save_device_session(&self, session: &DeviceSession) -> Result<(), ServiceError> {
+        self.inner.save_device_session(session).await
+    }
+
+    async fn query_device_sessions(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<DeviceSession>, ServiceError> {
+        self.inner.query_device_sessions(user_id).await
+    }
+
+    async fn record_strike(&self, strike: &Strike) -> Result<bool, ServiceError> {
+        self.inner.record_strike(strike).await
+    }
+
+    async fn query_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError> {
+        self.inner.query_strikes(walker_id).await
+    }
+
+    async fn update_reputation(
+        &self,
+        user_id: &str,
+        update: ReputationUpdate,
+    ) -> Result<(), ServiceError> {
+        self.inner.update_reputation(user_id, update).await
+    }
+
+    async fn get_reputation(&self, user_id: &str) -> Result<Option<Reputation>, ServiceError> {
+        self.inner.get_reputation(user_id).await
+    }
+
+    async fn save_feature_flag(&self, flag: &FeatureFlag) -> Result<(), ServiceError> {
+        self.inner.save_feature_flag(flag).await
+    }
+
+    async fn query_feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError> {
+        self.inner.query_feature_flags().await
+    }
+
+    async fn save_offer(&self, offer: &Offer) -> Result<(), ServiceError> {
+        self.inner.save_offer(offer).await
+    }
+
+    async fn get_offer(&self, id: &str) -> Result<Option<Offer>, ServiceError> {
+        self.inner.get_offer(id).await
+    }
+
+    async fn query_offers(
+        &self,
+        request_id: &str,
+        walker_id: Option<&str>,
+    ) -> Result<Vec<Offer>, ServiceError> {
+        self.inner.query_offers(request_id, walker_id).await
+    }
+
+    async fn save_outbox_message(&self, message: &OutboxMessage) -> Result<(), ServiceError> {
+        self.inner.save_outbox_message(message).await
+    }
+
+    async fn get_outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, ServiceError> {
+        self.inner.get_outbox_message(id).await
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_outbox_message(id).await
+    }
+
+    async fn due_outbox_messages(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        self.inner.due_outbox_messages(now, limit).await
+    }
+
+    async fn dead_outbox_messages(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        self.inner.dead_outbox_messages(pagination).await
+    }
+
+    async fn save_start_reminders(&self, reminders: &[StartReminder]) -> Result<(), ServiceError> {
+        self.inner.save_start_reminders(reminders).await
+    }
+
+    async fn due_start_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<StartReminder>, ServiceError> {
+        self.inner.due_start_reminders(now, limit).await
+    }
+
+    async fn delete_start_reminder(&self, id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_start_reminder(id).await
+    }
+
+    async fn delete_start_reminders(&self, request_id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_start_reminders(request_id).await
+    }
+
+    async fn save_walk_schedule(&self, schedule: &WalkSchedule) -> Result<(), ServiceError> {
+        self.inner.save_walk_schedule(schedule).await
+    }
+
+    async fn get_walk_schedule(&self, id: &str) -> Result<Option<WalkSchedule>, ServiceError> {
+        self.inner.get_walk_schedule(id).await
+    }
+
+    async fn query_walk_schedules(
+        &self,
+        owner_id: Option<&str>,
+    ) -> Result<Vec<WalkSchedule>, ServiceError> {
+        self.inner.query_walk_schedules(owner_id).await
+    }
+
+    async fn save_walk_request_template(
+        &self,
+        template: &WalkRequestTemplate,
+    ) -> Result<(), ServiceError> {
+        self.inner.save_walk_request_template(template).await
+    }
+
+    async fn get_walk_request_template(
+        &self,
+        id: &str,
+    ) -> Result<Option<WalkRequestTemplate>, ServiceError> {
+        self.inner.get_walk_request_template(id).await
+    }
+
+    async fn query_walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError> {
+        self.inner.query_walk_request_templates(owner_id).await
+    }
+
+    async fn delete_walk_request_template(&self, id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_walk_request_template(id).await
+    }
+
+    async fn save_saved_search(&self, search: &SavedSearch) -> Result<(), ServiceError> {
+        self.inner.save_saved_search(search).await
+    }
+
+    async fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>, ServiceError> {
+        self.inner.get_saved_search(id).await
+    }
+
+    async fn query_saved_searches(
+        &self,
+        walker_id: &str,
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        self.inner.query_saved_searches(walker_id).await
+    }
+
+    async fn query_notifying_saved_searches(
+        &self,
+        tags: &[String],
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        self.inner.query_notifying_saved_searches(tags).await
+    }
+
+    async fn delete_saved_search(&self, id: &str) -> Result<(), ServiceError> {
+        self.inner.delete_saved_search(id).await
+    }
+
+    async fn get_backfill_run(&self, name: &str) -> Result<Option<BackfillRun>, ServiceError> {
+        self.inner.get_backfill_run(name).await
+    }
+
+    async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), ServiceError> {
+        self.inner.save_backfill_run(run).await
+    }
+
+    async fn record_event(&self, event: &events::WalkRequestEvent) -> Result<(), ServiceError> {
+        self.inner.record_event(event).await
+    }
+
+    async fn query_events(
+        &self,
+        request_id: &str,
+    ) -> Result<Vec<events::WalkRequestEvent>, ServiceError> {
+        self.inner.query_events(request_id).await
+    }
+
+    async fn create_message(&self, message: &Message, recipient: &str) -> Result<(), ServiceError> {
+        self.inner.create_message(message, recipient).await
+    }
+
+    async fn query_messages(
+        &self,
+        request_id: &str,
+        after: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Message>, ServiceError> {
+        self.inner.query_messages(request_id, after, limit).await
+    }
+
+    async fn mark_messages_read(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        self.inner.mark_messages_read(request_id, user_id).await
+    }
+
+    async fn unread_messages(
+        &self,
+        user_id: &str,
+        request_ids: &[String],
+    ) -> Result<HashMap<String, u64>, ServiceError> {
+        self.inner.unread_messages(user_id, request_ids).await
+    }
+
+    async fn record_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        month: &str,
+        rejected: bool,
+    ) -> Result<(), ServiceError> {
+        self.inner
+            .record_api_call(api_key, endpoint, month, rejected)
+            .await
+    }
+
+    async fn query_api_usage(
+        &self,
+        month: &str,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UsageRecord>, ServiceError> {
+        self.inner.query_api_usage(month, api_key).await
+    }
+
+    async fn walk_requests_active_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        self.inner.walk_requests_active_between(from, to).await
+    }
+
+    async fn save_daily_metrics(&self, metrics: Vec<DailyMetrics>) -> Result<(), ServiceError> {
+        self.inner.save_daily_metrics(metrics).await
+    }
+
+    async fn query_daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        self.inner.query_daily_metrics(region, from, to).await
+    }
+
+    async fn upsert_application(
+        &self,
+        request_id: &str,
+        applicant_id: &str,
+        state: ApplicationState,
+    ) -> Result<(), ServiceError> {
+        self.inner
+            .upsert_application(request_id, applicant_id, state)
+            .await
+    }
+
+    async fn query_applications(
+        &self,
+        applicant_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError> {
+        self.inner
+            .query_applications(applicant_id, pagination)
+            .await
+    }
+
+    async fn save_saga(&self, saga: &BookingSaga) -> Result<(), ServiceError> {
+        self.inner.save_saga(saga).await
+    }
+
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError> {
+        self.inner.get_tenant(id).await
+    }
+
+    async fn upsert_tenant(&self, tenant: Tenant) -> Result<(), ServiceError> {
+        self.inner.upsert_tenant(tenant).await
+    }
+
+    async fn get_walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<WalkerCapabilities>, ServiceError> {
+        self.inner.get_walker_capabilities(user_id).await
+    }
+
+    async fn upsert_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError> {
+        self.inner
+            .upsert_walker_capabilities(user_id, capabilities)
+            .await
+    }
+
+    async fn verify_audit_chain(
+        &self,
+        request_id: &str,
+    ) -> Result<AuditVerification, ServiceError> {
+        self.verify_chain(request_id).await
+    }
+
+    async fn purge(
+        &self,
+        class: DataClass,
+        before: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<u64, ServiceError> {
+        if class != DataClass::Audit {
+            return self.inner.purge(class, before, dry_run).await;
+        }
+        let events = self.db.collection::<Document>("walk_request_events");
+        let filter = doc! {"occurred_at": {"$lt": before}};
+        if dry_run {
+            return Ok(events.count_documents(filter, None).await?);
+        }
+        Ok(events.delete_many(filter, None).await?.deleted_count)
+    }
+
+    async fn imported_id(&self, key: &str) -> Result<Option<String>, ServiceError> {
+        self.inner.imported_id(key).await
+    }
+
+    async fn record_import(&self, key: &str, id: &str) -> Result<(), ServiceError> {
+        self.inner.record_import(key, id).await
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<u64, ServiceError> {
+        self.inner.next_sequence(name).await
+    }
+
+    async fn query_stuck_sagas(
+        &self,
+        updated_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError> {
+        self.inner.query_stuck_sagas(updated_before, limit).await
+    }
+}
@@ -0,0 +1,1763 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use little_walk_dog::core::entities::Dog;
+
+use crate::core::{
+    backfill::BackfillRun,
+    entities::{Application, ApplicationState, WalkRequest, WalkingLocation},
+    error::ServiceError,
+    events::WalkRequestEvent,
+    favorite::FavoriteWalker,
+    feature_flags::{Feature, FeatureFlag},
+    fitness::{ExportStatus, FitnessExport, FitnessToken},
+    geo,
+    holiday::Holiday,
+    message::Message,
+    metrics::{DailyMetrics, FunnelMarks, FunnelStage},
+    offer::Offer,
+    outbox::{OutboxMessage, OutboxStatus},
+    payment::Payment,
+    receipt::{Payout, Receipt},
+    reminder::StartReminder,
+    report::{self, ReportBucket, ReportInterval},
+    repository::{
+        Order, Pagination, Repository, SortBy, WalkRequestCreate, WalkRequestQuery,
+        WalkRequestUpdate, WalkingLocationCreate, WalkingLocationQuery,
+    },
+    reputation::{Reputation, ReputationUpdate},
+    retention::DataClass,
+    saga::{BookingSaga, SagaStatus},
+    saved_search::SavedSearch,
+    schedule::WalkSchedule,
+    session::DeviceSession,
+    spending::SpendingCap,
+    strike::Strike,
+    template::WalkRequestTemplate,
+    tenant::Tenant,
+    usage::UsageRecord,
+    walker_capabilities::{dog_field, WalkerCapabilities},
+};
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    walk_requests: HashMap<String, WalkRequest>,
+    archived_walk_requests: HashMap<String, WalkRequest>,
+    walking_locations: Vec<WalkingLocation>,
+    holidays: Vec<Holiday>,
+    daily_metrics: Vec<DailyMetrics>,
+    applications: Vec<Application>,
+    sagas: HashMap<String, BookingSaga>,
+    tenants: HashMap<String, Tenant>,
+    walker_capabilities: HashMap<String, WalkerCapabilities>,
+    imports: HashMap<String, String>,
+    sequences: HashMap<String, u64>,
+    fitness_tokens: HashMap<(String, String), FitnessToken>,
+    favorite_walkers: HashMap<(String, String), FavoriteWalker>,
+    fitness_exports: HashMap<String, FitnessExport>,
+    walk_schedules: HashMap<String, WalkSchedule>,
+    walk_request_templates: HashMap<String, WalkRequestTemplate>,
+    saved_searches: HashMap<String, SavedSearch>,
+    backfill_runs: HashMap<String, BackfillRun>,
+    events: Vec<WalkRequestEvent>,
+    messages: Vec<Message>,
+    /// Unread messages by request and recipient.
+    unread_messages: HashMap<(String, String), u64>,
+    api_usage: HashMap<(String, String, String), UsageRecord>,
+    payments: HashMap<String, Payment>,
+    receipts: HashMap<String, Receipt>,
+    payouts: HashMap<String, Payout>,
+    spending_caps: HashMap<String, SpendingCap>,
+    device_sessions: HashMap<String, DeviceSession>,
+    strikes: HashMap<(String, String), Strike>,
+    reputations: HashMap<String, Reputation>,
+    feature_flags: HashMap<Feature, FeatureFlag>,
+    offers: HashMap<String, Offer>,
+    outbox: HashMap<String, OutboxMessage>,
+    start_reminders: HashMap<String, StartReminder>,
+    funnel_marks: HashMap<String, FunnelMarks>,
+}
+
+impl State {
+    fn next_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:024x}", self.next_id)
+    }
+}
+
+/// In-process `Repository` for tests and local runs without MongoDB. Queries
+/// are evaluated the way the MongoDB filters are, `nearby` included.
+#[derive(Clone, Default)]
+pub struct InMemory {
+    state: Arc<RwLock<State>>,
+    tenant: Option<String>,
+}
+
+impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn is_null_matches<T>(expected: Option<bool>, value: &Option<T>) -> bool {
+    expected.map_or(true, |is_null| is_null == value.is_none())
+}
+
+fn dog_matches(query: &WalkRequestQuery, dog: &Dog) -> bool {
+    query.dog_name_contains.as_ref().map_or(true, |name| {
+        dog_field(dog, "name").map_or(false, |n| n.to_lowercase().contains(&name.to_lowercase()))
+    }) && query.dog_breed.as_ref().map_or(true, |breed| {
+        dog_field(dog, "breed").map_or(false, |b| b.trim().eq_ignore_ascii_case(breed.trim()))
+    }) && query.dog_size.map_or(true, |size| {
+        dog_field(dog, "size").as_deref() == Some(size.as_str())
+    })
+}
+
+fn matches(query: &WalkRequestQuery, request: &WalkRequest) -> bool {
+    let acceptances = request.acceptances.as_deref().unwrap_or_default();
+    let dismissed = request.dismissed_applicants.as_deref().unwrap_or_default();
+    let accepted_by = request.accepted_by.as_ref();
+    query.id.as_ref().map_or(true, |id| &request.id == id)
+        && query
+            .reference_code
+            .as_ref()
+            .map_or(true, |code| request.reference_code.as_ref() == Some(code))
+        && query
+            .ids_in
+            .as_ref()
+            .map_or(true, |ids| ids.contains(&request.id))
+        && query.dog_ids_includes_all.as_ref().map_or(true, |ids| {
+            ids.iter()
+                .all(|id| request.dogs.iter().any(|d| &d.id == id))
+        })
+        && query.dog_ids_includes_any.as_ref().map_or(true, |ids| {
+            ids.iter()
+                .any(|id| request.dogs.iter().any(|d| &d.id == id))
+        })
+        && query
+            .accepted_by
+            .as_ref()
+            .map_or(true, |u| accepted_by == Some(u))
+        && query
+            .accepted_by_neq
+            .as_ref()
+            .map_or(true, |u| accepted_by != Some(u))
+        && is_null_matches(query.accepted_by_is_null, &request.accepted_by)
+        && query
+            .accepted_by_in
+            .as_ref()
+            .map_or(true, |us| accepted_by.map_or(false, |u| us.contains(u)))
+        && query.pool_walker.as_ref().map_or(true, |walker| {
+            request.pool_walkers.iter().any(|w| &w.walker_id == walker)
+        })
+        && query
+            .acceptances_includes_all
+            .as_ref()
+            .map_or(true, |us| us.iter().all(|u| acceptances.contains(u)))
+        && query
+            .acceptances_includes_any
+            .as_ref()
+            .map_or(true, |us| us.iter().any(|u| acceptances.contains(u)))
+        && query
+            .tags_includes_all
+            .as_ref()
+            .map_or(true, |ts| ts.iter().all(|t| request.tags.contains(t)))
+        && query
+            .tags_includes_any
+            .as_ref()
+            .map_or(true, |ts| ts.iter().any(|t| request.tags.contains(t)))
+        && query
+            .created_by
+            .as_ref()
+            .map_or(true, |u| request.created_by.as_ref() == Some(u))
+        && query
+            .created_by_neq
+            .as_ref()
+            .map_or(true, |u| request.created_by.as_ref() != Some(u))
+        && query.version.map_or(true, |v| request.version == v)
+        && query.within_box.map_or(true, |area| {
+            area.contains((request.longitude, request.latitude))
+        })
+        && query.created_by_in.as_ref().map_or(true, |us| {
+            request
+                .created_by
+                .as_ref()
+                .map_or(false, |u| us.contains(u))
+        })
+        && query
+            .created_at_gte
+            .map_or(true, |t| request.created_at.map_or(false, |c| c >= t))
+        && query
+            .created_at_lte
+            .map_or(true, |t| request.created_at.map_or(false, |c| c <= t))
+        && is_null_matches(
+            query.cancel_requested_at_is_null,
+            &request.cancel_requested_at,
+        )
+        && query.cancel_requested_at_gte.map_or(true, |t| {
+            request.cancel_requested_at.map_or(false, |c| c >= t)
+        })
+        && query.cancel_requested_at_lte.map_or(true, |t| {
+            request.cancel_requested_at.map_or(false, |c| c <= t)
+        })
+        && query.should_start_after_gte.map_or(true, |t| {
+            request.should_start_after.map_or(false, |s| s >= t)
+        })
+        && query.should_start_after_lte.map_or(true, |t| {
+            request.should_start_after.map_or(false, |s| s <= t)
+        })
+        && query.should_start_after_lt.map_or(true, |t| {
+            request.should_start_after.map_or(false, |s| s < t)
+        })
+        && query
+            .should_end_before_gt
+            .map_or(true, |t| request.should_end_before.map_or(false, |e| e > t))
+        && is_null_matches(query.canceled_at_is_null, &request.canceled_at)
+        && is_null_matches(query.started_at_is_null, &request.started_at)
+        && query
+            .started_at_gte
+            .map_or(true, |t| request.started_at.map_or(false, |s| s >= t))
+        && query
+            .started_at_lte
+            .map_or(true, |t| request.started_at.map_or(false, |s| s <= t))
+        && is_null_matches(query.finished_at_is_null, &request.finished_at)
+        && query
+            .finished_at_gte
+            .map_or(true, |t| request.finished_at.map_or(false, |f| f >= t))
+        && query
+            .finished_at_lte
+            .map_or(true, |t| request.finished_at.map_or(false, |f| f <= t))
+        && is_null_matches(query.sla_breached_at_is_null, &request.sla_breached_at)
+        && is_null_matches(query.expired_at_is_null, &request.expired_at)
+        && is_null_matches(
+            query.geofence_violated_at_is_null,
+            &request.geofence_violated_at,
+        )
+        && (query.include_deleted || request.deleted_at.is_none())
+        && query.status.map_or(true, |s| request.derive_status() == s)
+        && query.should_start_before_lt.map_or(true, |t| {
+            request.should_start_before.map_or(false, |s| s < t)
+        })
+        && query
+            .tenant_id
+            .as_ref()
+            .map_or(true, |tenant| request.tenant_id.as_ref() == Some(tenant))
+        && query.regions_in.as_ref().map_or(true, |regions| {
+            request
+                .region
+                .as_ref()
+                .map_or(false, |r| regions.contains(r))
+        })
+        && query
+            .dismissed_applicants_excludes
+            .as_ref()
+            .map_or(true, |u| !dismissed.contains(u))
+        && query.below_applicant_cap.map_or(true, |cap| {
+            (acceptances.len() as i64) < request.max_applicants.unwrap_or(cap)
+        })
+        && query
+            .apply_open_at
+            .map_or(true, |t| request.apply_before.map_or(true, |b| b > t))
+        && query
+            .invitation_visible_to
+            .as_ref()
+            .map_or(true, |viewer| viewer.sees(request))
+        && query
+            .dog_count_lte
+            .map_or(true, |max| request.dogs.len() as i64 <= max)
+        && query
+            .dog_count_gte
+            .map_or(true, |min| request.dogs.len() as i64 >= min)
+        && (query.dog_name_contains.is_none()
+            && query.dog_breed.is_none()
+            && query.dog_size.is_none()
+            || request.dogs.iter().any(|d| dog_matches(query, d)))
+        && query
+            .has_acceptances
+            .map_or(true, |has| acceptances.is_empty() != has)
+        && query
+            .requires_large_breed
+            .map_or(true, |r| request.requirements.large_breed == r)
+        && query
+            .requires_puppy
+            .map_or(true, |r| request.requirements.puppy == r)
+}
+
+/// The stored request as the MongoDB projection would return it.
+fn view(request: &WalkRequest) -> WalkRequest {
+    let mut view = request.clone();
+    view.status = view.derive_status();
+    view.time_to_accept_seconds = match (view.accepted_at, view.created_at) {
+        (Some(accepted_at), Some(created_at)) => Some((accepted_at - created_at).num_seconds()),
+        _ => None,
+    };
+    view.remaining_slots = view.open_slots();
+    view
+}
+
+fn paginate<T>(items: Vec<T>, pagination: Option<&Pagination>) -> Vec<T> {
+    match pagination {
+        Some(p) => items
+            .into_iter()
+            .skip(((p.page - 1) * p.size).max(0) as usize)
+            .take(p.size.max(0) as usize)
+            .collect(),
+        None => items,
+    }
+}
+
+fn sort_key(request: &WalkRequest, field: &str) -> Option<DateTime<Utc>> {
+    match field {
+        "should_start_after" => request.should_start_after,
+        "should_start_before" => request.should_start_before,
+        "updated_at" => request.updated_at,
+        _ => request.created_at,
+    }
+}
+
+impl InMemory {
+    /// `query` within the tenant the repository is scoped to.
+    fn scoped(&self, query: &WalkRequestQuery) -> WalkRequestQuery {
+        query.clone().within_tenant(self.tenant.as_deref())
+    }
+
+    fn matching(&self, query: &WalkRequestQuery) -> Vec<WalkRequest> {
+        let query = &self.scoped(query);
+        let state = self.state.read().unwrap();
+        let mut requests: Vec<WalkRequest> = state
+            .walk_requests
+            .values()
+            .filter(|r| matches(query, r))
+            .map(view)
+            .collect();
+        requests.sort_by(|a, b| a.id.cmp(&b.id));
+        requests
+    }
+
+    fn update_matching(
+        &self,
+        query: &WalkRequestQuery,
+        update: WalkRequestUpdate,
+        limit: usize,
+    ) -> Vec<WalkRequest> {
+        let query = &self.scoped(query);
+        let mut state = self.state.write().unwrap();
+        let mut ids: Vec<String> = state
+            .walk_requests
+            .values()
+            .filter(|r| matches(query, r))
+            .map(|r| r.id.clone())
+            .collect();
+        ids.sort();
+        ids.truncate(limit);
+        let now = Utc::now();
+        let mut updated = Vec::new();
+        for id in ids {
+            if let Some(request) = state.walk_requests.get_mut(&id) {
+                update.clone().apply_to(request);
+                request.updated_at = Some(now);
+                updated.push(view(request));
+            }
+        }
+        updated
+    }
+}
+
+impl Repository for InMemory {
+    fn for_tenant(&self, tenant_id: &str) -> Self {
+        Self {
+            tenant: Some(tenant_id.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    async fn create_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<String, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let id = state.next_id();
+        state
+            .walk_requests
+            .insert(id.clone(), request.into_walk_request(&id, Utc::now()));
+        Ok(id)
+    }
+
+    async fn update_walk_request(
+        &self,
+        id: &str,
+        request: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        self.update_walk_request_by_query(
+            WalkRequestQuery {
+                id: Some(id.to_owned()),
+                ..Default::default()
+            },
+            request,
+        )
+        .await
+    }
+
+    async fn update_walk_request_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        self.update_matching(&query, update, 1)
+            .pop()
+            .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))
+    }
+
+    async fn update_walk_requests_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<u64, ServiceError> {
+        Ok(self.update_matching(&query, update, usize::MAX).len() as u64)
+    }
+
+    async fn count_walk_requests(&self, query: WalkRequestQuery) -> Result<u64, ServiceError> {
+        Ok(self.query_walk_requests(query, None, None).await?.len() as u64)
+    }
+
+    async fn walk_report(
+        &self,
+        query: WalkRequestQuery,
+        interval: ReportInterval,
+    ) -> Result<Vec<ReportBucket>, ServiceError> {
+        let requests = self.query_walk_requests(query, None, None).await?;
+        Ok(report::buckets(&requests, interval))
+    }
+
+    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, ServiceError> {
+        self.state
+            .read()
+            .unwrap()
+            .walk_requests
+            .get(id)
+            .filter(|r| self.tenant.is_none() || r.tenant_id == self.tenant)
+            .map(view)
+            .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))
+    }
+
+    async fn query_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let mut requests = self.matching(&query);
+        if let Some(nearby) = &query.nearby {
+            if nearby.len() != 3 {
+                return Err(ServiceError::Validation(
+                    "Invalid nearby query, expect [f64;3]".to_owned(),
+                ));
+            }
+            for request in requests.iter_mut() {
+                request.distance = Some(geo::distance(
+                    (nearby[0], nearby[1]),
+                    (request.longitude, request.latitude),
+                ));
+            }
+            requests.retain(|r| r.distance.map_or(false, |d| d.value() <= nearby[2]));
+            if let Some(after) = &query.nearby_after {
+                requests.retain(|r| {
+                    let distance = r.distance.map_or(0.0, |d| d.value());
+                    distance > after.distance || (distance == after.distance && r.id > after.id)
+                });
+            }
+            requests.sort_by(|a, b| {
+                a.distance
+                    .partial_cmp(&b.distance)
+                    .unwrap()
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+        }
+        if let Some(sort_by) = sort_by {
+            requests.sort_by_key(|r| sort_key(r, &sort_by.field));
+            if sort_by.order == Order::Desc {
+                requests.reverse();
+            }
+        }
+        Ok(paginate(requests, pagination.as_ref()))
+    }
+
+    async fn create_walking_location<'a>(
+        &self,
+        create: WalkingLocationCreate<'a>,
+    ) -> Result<String, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let id = state.next_id();
+        state.walking_locations.push(WalkingLocation {
+            id: id.clone(),
+            request_id: create.walk_request_id.to_owned(),
+            longitude: create.longitude,
+            latitude: create.latitude,
+            created_at: Some(create.recorded_at.unwrap_or_else(Utc::now)),
+        });
+        Ok(id)
+    }
+
+    async fn create_walking_locations<'a>(
+        &self,
+        creates: Vec<WalkingLocationCreate<'a>>,
+    ) -> Result<Vec<String>, ServiceError> {
+        let mut ids = Vec::with_capacity(creates.len());
+        for create in creates {
+            ids.push(self.create_walking_location(create).await?);
+        }
+        Ok(ids)
+    }
+
+    async fn query_walking_locations(
+        &self,
+        query: WalkingLocationQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError> {
+        let locations = self
+            .state
+            .read()
+            .unwrap()
+            .walking_locations
+            .iter()
+            .filter(|l| l.request_id == query.walk_request_id)
+            .filter(|l| {
+                query
+                    .created_after
+                    .map_or(true, |t| l.created_at.map_or(false, |c| c >= t))
+            })
+            .filter(|l| {
+                query
+                    .created_before
+                    .map_or(true, |t| l.created_at.map_or(false, |c| c < t))
+            })
+            .cloned()
+            .collect();
+        Ok(paginate(locations, pagination.as_ref()))
+    }
+
+    async fn delete_walking_locations(&self, ids: &[String]) -> Result<u64, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let before = state.walking_locations.len();
+        state.walking_locations.retain(|l| !ids.contains(&l.id));
+        Ok((before - state.walking_locations.len()) as u64)
+    }
+
+    async fn walk_requests_with_tracks_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, ServiceError> {
+        let mut ids: Vec<String> = self
+            .state
+            .read()
+            .unwrap()
+            .walking_locations
+            .iter()
+            .filter(|l| l.created_at.map_or(false, |c| c < before))
+            .map(|l| l.request_id.clone())
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    async fn archive_walk_requests(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let ended = |t: Option<DateTime<Utc>>| t.map_or(false, |t| t < before);
+        let ids: Vec<String> = state
+            .walk_requests
+            .values()
+            .filter(|r| ended(r.finished_at) || ended(r.canceled_at))
+            .map(|r| r.id.clone())
+            .collect();
+        for id in &ids {
+            if let Some(request) = state.walk_requests.remove(id) {
+                state.archived_walk_requests.insert(id.clone(), request);
+            }
+        }
+        Ok(ids.len() as u64)
+    }
+
+    async fn query_archived_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let query = self.scoped(&query);
+        let mut requests: Vec<WalkRequest> = self
+            .state
+            .read()
+            .unwrap()
+            .archived_walk_requests
+            .values()
+            .filter(|r| matches(&query, r))
+            .map(view)
+            .collect();
+        requests.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(paginate(requests, pagination.as_ref()))
+    }
+
+    async fn query_holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .holidays
+            .iter()
+            .filter(|h| h.region == region)
+            .cloned()
+            .collect())
+    }
+
+    async fn upsert_holiday(&self, holiday: Holiday) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        state
+            .holidays
+            .retain(|h| h.region != holiday.region || h.date != holiday.date);
+        state.holidays.push(holiday);
+        Ok(())
+    }
+
+    async fn delete_holiday(&self, region: &str, date: NaiveDate) -> Result<u64, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let before = state.holidays.len();
+        state
+            .holidays
+            .retain(|h| h.region != region || h.date != date);
+        Ok((before - state.holidays.len()) as u64)
+    }
+
+    async fn walk_requests_active_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let within = |t: Option<DateTime<Utc>>| t.map_or(false, |t| t >= from && t < to);
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .walk_requests
+            .values()
+            .filter(|r| {
+                within(r.created_at)
+                    || within(r.accepted_at)
+                    || within(r.finished_at)
+                    || within(r.canceled_at)
+                    || within(r.sla_breached_at)
+            })
+            .map(view)
+            .collect())
+    }
+
+    async fn save_daily_metrics(&self, metrics: Vec<DailyMetrics>) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        for m in metrics {
+            state
+                .daily_metrics
+                .retain(|d| d.region != m.region || d.date != m.date);
+            state.daily_metrics.push(m);
+        }
+        Ok(())
+    }
+
+    async fn query_daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        let mut metrics: Vec<DailyMetrics> = self
+            .state
+            .read()
+            .unwrap()
+            .daily_metrics
+            .iter()
+            .filter(|m| m.region == region && m.date >= from && m.date <= to)
+            .cloned()
+            .collect();
+        metrics.sort_by_key(|m| m.date);
+        Ok(metrics)
+    }
+
+    async fn upsert_application(
+        &self,
+        request_id: &str,
+        applicant_id: &str,
+        state: ApplicationState,
+    ) -> Result<(), ServiceError> {
+        let now = Utc::now();
+        let mut store = self.state.write().unwrap();
+        match store
+            .applications
+            .iter_mut()
+            .find(|a| a.request_id == request_id && a.applicant_id == applicant_id)
+        {
+            Some(application) => {
+                application.state = state;
+                application.updated_at = now;
+            }
+            None => store.applications.push(Application {
+                request_id: request_id.to_owned(),
+                applicant_id: applicant_id.to_owned(),
+                state,
+                applied_at: now,
+                updated_at: now,
+            }),
+        }
+        Ok(())
+    }
+
+    async fn query_applications(
+        &self,
+        applicant_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError> {
+        let mut applications: Vec<Application> = self
+            .state
+            .read()
+            .unwrap()
+            .applications
+            .iter()
+            .filter(|a| a.applicant_id == applicant_id)
+            .cloned()
+            .collect();
+        applications.sort_by(|a, b| b.applied_at.cmp(&a.applied_at));
+        Ok(paginate(applications, Some(&pagination)))
+    }
+
+    async fn save_saga(&self, saga: &BookingSaga) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .sagas
+            .insert(saga.id.clone(), saga.clone());
+        Ok(())
+    }
+
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError> {
+        Ok(self.state.read().unwrap().tenants.get(id).cloned())
+    }
+
+    async fn upsert_tenant(&self, tenant: Tenant) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .tenants
+            .insert(tenant.id.clone(), tenant);
+        Ok(())
+    }
+
+    async fn get_walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<WalkerCapabilities>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .walker_capabilities
+            .get(user_id)
+            .cloned())
+    }
+
+    async fn upsert_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .walker_capabilities
+            .insert(user_id.to_owned(), capabilities);
+        Ok(())
+    }
+
+    async fn purge(
+        &self,
+        class: DataClass,
+        before: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<u64, ServiceError> {
+        if class != DataClass::Tracks {
+            return Ok(0);
+        }
+        let mut state = self.state.write().unwrap();
+        let expired = |l: &WalkingLocation| l.created_at.map_or(false, |c| c < before);
+        let count = state
+            .walking_locations
+            .iter()
+            .filter(|l| expired(l))
+            .count();
+        if !dry_run {
+            state.walking_locations.retain(|l| !expired(l));
+        }
+        Ok(count as u64)
+    }
+
+    async fn imported_id(&self, key: &str) -> Result<Option<String>, ServiceError> {
+        Ok(self.state.read().unwrap().imports.get(key).cloned())
+    }
+
+    async fn record_import(&self, key: &str, id: &str) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .imports
+            .insert(key.to_owned(), id.to_owned());
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<u64, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let value = state.sequences.entry(name.to_owned()).or_default();
+        *value += 1;
+        Ok(*value)
+    }
+
+    async fn query_fitness_tokens(&self, user_id: &str) -> Result<Vec<FitnessToken>, ServiceError> {
+        let mut tokens: Vec<FitnessToken> = self
+            .state
+            .read()
+            .unwrap()
+            .fitness_tokens
+            .values()
+            .filter(|t| t.user_id == user_id)
+            .cloned()
+            .collect();
+        tokens.sort_by(|a, b| a.provider.cmp(&b.provider));
+        Ok(tokens)
+    }
+
+    async fn upsert_fitness_token(&self, token: FitnessToken) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .fitness_tokens
+            .insert((token.user_id.clone(), token.provider.clone()), token);
+        Ok(())
+    }
+
+    async fn delete_fitness_token(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(self
+            .state
+            .write()
+            .unwrap()
+            .fitness_tokens
+            .remove(&(user_id.to_owned(), provider.to_owned()))
+            .map_or(0, |_| 1))
+    }
+
+    async fn query_favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError> {
+        let mut favorites: Vec<FavoriteWalker> = self
+            .state
+            .read()
+            .unwrap()
+            .favorite_walkers
+            .values()
+            .filter(|f| f.owner_id == owner_id)
+            .cloned()
+            .collect();
+        favorites.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(favorites)
+    }
+
+    async fn upsert_favorite_walker(&self, favorite: FavoriteWalker) -> Result<(), ServiceError> {
+        self.state.write().unwrap().favorite_walkers.insert(
+            (favorite.owner_id.clone(), favorite.walker_id.clone()),
+            favorite,
+        );
+        Ok(())
+    }
+
+    async fn delete_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(self
+            .state
+            .write()
+            .unwrap()
+            .favorite_walkers
+            .remove(&(owner_id.to_owned(), walker_id.to_owned()))
+            .map_or(0, |_| 1))
+    }
+
+    async fn save_fitness_export(&self, export: &FitnessExport) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .fitness_exports
+            .insert(export.id.clone(), export.clone());
+        Ok(())
+    }
+
+    async fn due_fitness_exports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<FitnessExport>, ServiceError> {
+        let mut exports: Vec<FitnessExport> = self
+            .state
+            .read()
+            .unwrap()
+            .fitness_exports
+            .values()
+            .filter(|e| e.status == ExportStatus::Pending && e.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        exports.sort_by_key(|e| e.next_attempt_at);
+        Ok(exports)
+    }
+
+    async fn save_payment(&self, payment: &Payment) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .payments
+            .insert(payment.id.clone(), payment.clone());
+        Ok(())
+    }
+
+    async fn get_payment(&self, request_id: &str) -> Result<Option<Payment>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .payments
+            .values()
+            .find(|p| p.request_id == request_id)
+            .cloned())
+    }
+
+    async fn save_receipt(&self, receipt: &Receipt) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .receipts
+            .insert(receipt.id.clone(), receipt.clone());
+        Ok(())
+    }
+
+    async fn get_receipt(&self, request_id: &str) -> Result<Option<Receipt>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .receipts
+            .values()
+            .find(|r| r.request_id == request_id)
+            .cloned())
+    }
+
+    async fn query_receipts(
+        &self,
+        owner_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        let mut receipts: Vec<Receipt> = self
+            .state
+            .read()
+            .unwrap()
+            .receipts
+            .values()
+            .filter(|r| r.owner_id == owner_id && r.issued_at >= from && r.issued_at < to)
+            .cloned()
+            .collect();
+        receipts.sort_by_key(|r| r.issued_at);
+        Ok(receipts)
+    }
+
+    async fn save_payout(&self, payout: &Payout) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .payouts
+            .insert(payout.id.clone(), payout.clone());
+        Ok(())
+    }
+
+    async fn query_payouts(
+        &self,
+        walker_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payout>, ServiceError> {
+        let mut payouts: Vec<Payout> = self
+            .state
+            .read()
+            .unwrap()
+            .payouts
+            .values()
+            .filter(|p| p.walker_id == walker_id && p.created_at >= from && p.created_at < to)
+            .cloned()
+            .collect();
+        payouts.sort_by_key(|p| p.created_at);
+        Ok(payouts)
+    }
+
+    async fn get_spending_cap(&self, owner_id: &str) -> Result<Option<SpendingCap>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .spending_caps
+            .get(owner_id)
+            .cloned())
+    }
+
+    async fn save_spending_cap(&self, cap: &SpendingCap) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .spending_caps
+            .insert(cap.owner_id.clone(), cap.clone());
+        Ok(())
+    }
+
+    async fn delete_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError> {
+        self.state.write().unwrap().spending_caps.remove(owner_id);
+        Ok(())
+    }
+
+    async fn mark_funnel_stage(
+        &self,
+        request_id: &str,
+        stage: FunnelStage,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let marks = state
+            .funnel_marks
+            .entry(request_id.to_owned())
+            .or_insert_with(|| FunnelMarks {
+                request_id: request_id.to_owned(),
+                ..Default::default()
+            });
+        let mark = match stage {
+            FunnelStage::Viewed => &mut marks.first_viewed_at,
+            FunnelStage::Applied => &mut marks.first_applied_at,
+        };
+        if mark.map_or(true, |t| at < t) {
+            *mark = Some(at);
+        }
+        Ok(())
+    }
+
+    async fn record_impressions(
+        &self,
+        request_ids: &[String],
+        weight: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        for request_id in request_ids {
+            let marks = state
+                .funnel_marks
+                .entry(request_id.clone())
+                .or_insert_with(|| FunnelMarks {
+                    request_id: request_id.clone(),
+                    ..Default::default()
+                });
+            marks.impressions += weight;
+            if marks.first_viewed_at.map_or(true, |t| at < t) {
+                marks.first_viewed_at = Some(at);
+            }
+        }
+        Ok(())
+    }
+
+    async fn query_funnel_marks(
+        &self,
+        request_ids: &[String],
+    ) -> Result<Vec<FunnelMarks>, ServiceError> {
+        let state = self.state.read().unwrap();
+        Ok(request_ids
+            .iter()
+            .filter_map(|id| state.funnel_marks.get(id).cloned())
+            .collect())
+    }
+
+    async fn save_device_session(&self, session: &DeviceSession) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .device_sessions
+            .insert(session.id.clone(), session.clone());
+        Ok(())
+    }
+
+    async fn query_device_sessions(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<DeviceSession>, ServiceError> {
+        let mut sessions: Vec<DeviceSession> = self
+            .state
+            .read()
+            .unwrap()
+            .device_sessions
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|s| s.created_at);
+        Ok(sessions)
+    }
+
+    async fn record_strike(&self, strike: &Strike) -> Result<bool, ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let key = (strike.walker_id.clone(), strike.request_id.clone());
+        if state.strikes.contains_key(&key) {
+            return Ok(false);
+        }
+        state.strikes.insert(key, strike.clone());
+        Ok(true)
+    }
+
+    async fn query_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut strikes: Vec<Strike> = state
+            .strikes
+            .values()
+            .filter(|s| s.walker_id == walker_id)
+            .cloned()
+            .collect();
+        strikes.sort_by(|a, b| b.recorded_at.cmp(&a.recorded_at));
+        Ok(strikes)
+    }
+
+    async fn update_reputation(
+        &self,
+        user_id: &str,
+        update: ReputationUpdate,
+    ) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let reputation = state
+            .reputations
+            .entry(user_id.to_owned())
+            .or_insert_with(|| Reputation::new(user_id));
+        reputation.completed_walks += update.completed_walks;
+        reputation.strikes += update.strikes;
+        if update.banned_until.is_some() {
+            reputation.banned_until = update.banned_until;
+        }
+        Ok(())
+    }
+
+    async fn get_reputation(&self, user_id: &str) -> Result<Option<Reputation>, ServiceError> {
+        Ok(self.state.read().unwrap().reputations.get(user_id).cloned())
+    }
+
+    async fn save_feature_flag(&self, flag: &FeatureFlag) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .feature_flags
+            .insert(flag.feature, flag.clone());
+        Ok(())
+    }
+
+    async fn query_feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .feature_flags
+            .values()
+            .cloned()
+            .collect())
+    }
+
+    async fn save_offer(&self, offer: &Offer) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .offers
+            .insert(offer.id.clone(), offer.clone());
+        Ok(())
+    }
+
+    async fn get_offer(&self, id: &str) -> Result<Option<Offer>, ServiceError> {
+        Ok(self.state.read().unwrap().offers.get(id).cloned())
+    }
+
+    async fn query_offers(
+        &self,
+        request_id: &str,
+        walker_id: Option<&str>,
+    ) -> Result<Vec<Offer>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut offers: Vec<Offer> = state
+            .offers
+            .values()
+            .filter(|o| o.request_id == request_id)
+            .filter(|o| walker_id.map_or(true, |w| o.walker_id == w))
+            .cloned()
+            .collect();
+        offers.sort_by_key(|o| o.created_at);
+        Ok(offers)
+    }
+
+    async fn save_outbox_message(&self, message: &OutboxMessage) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .outbox
+            .insert(message.id.clone(), message.clone());
+        Ok(())
+    }
+
+    async fn get_outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, ServiceError> {
+        Ok(self.state.read().unwrap().outbox.get(id).cloned())
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> Result<(), ServiceError> {
+        self.state.write().unwrap().outbox.remove(id);
+        Ok(())
+    }
+
+    async fn due_outbox_messages(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut due: Vec<OutboxMessage> = state
+            .outbox
+            .values()
+            .filter(|m| m.status == OutboxStatus::Pending && m.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|m| m.created_at);
+        due.truncate(limit.max(0) as usize);
+        Ok(due)
+    }
+
+    async fn dead_outbox_messages(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut dead: Vec<OutboxMessage> = state
+            .outbox
+            .values()
+            .filter(|m| m.status == OutboxStatus::DeadLettered)
+            .cloned()
+            .collect();
+        dead.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(paginate(dead, Some(&pagination)))
+    }
+
+    async fn save_start_reminders(&self, reminders: &[StartReminder]) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        for reminder in reminders {
+            state
+                .start_reminders
+                .insert(reminder.id.clone(), reminder.clone());
+        }
+        Ok(())
+    }
+
+    async fn due_start_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<StartReminder>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut due: Vec<StartReminder> = state
+            .start_reminders
+            .values()
+            .filter(|r| r.remind_at <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|r| r.remind_at);
+        due.truncate(limit.max(0) as usize);
+        Ok(due)
+    }
+
+    async fn delete_start_reminder(&self, id: &str) -> Result<(), ServiceError> {
+        self.state.write().unwrap().start_reminders.remove(id);
+        Ok(())
+    }
+
+    async fn delete_start_reminders(&self, request_id: &str) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .start_reminders
+            .retain(|_, r| r.request_id != request_id);
+        Ok(())
+    }
+
+    async fn save_walk_schedule(&self, schedule: &WalkSchedule) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .walk_schedules
+            .insert(schedule.id.clone(), schedule.clone());
+        Ok(())
+    }
+
+    async fn get_walk_schedule(&self, id: &str) -> Result<Option<WalkSchedule>, ServiceError> {
+        Ok(self.state.read().unwrap().walk_schedules.get(id).cloned())
+    }
+
+    async fn query_walk_schedules(
+        &self,
+        owner_id: Option<&str>,
+    ) -> Result<Vec<WalkSchedule>, ServiceError> {
+        let mut schedules: Vec<WalkSchedule> = self
+            .state
+            .read()
+            .unwrap()
+            .walk_schedules
+            .values()
+            .filter(|s| s.canceled_at.is_none())
+            .filter(|s| owner_id.map_or(true, |o| s.owner_id == o))
+            .cloned()
+            .collect();
+        schedules.sort_by_key(|s| s.created_at);
+        Ok(schedules)
+    }
+
+    async fn save_walk_request_template(
+        &self,
+        template: &WalkRequestTemplate,
+    ) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .walk_request_templates
+            .insert(template.id.clone(), template.clone());
+        Ok(())
+    }
+
+    async fn get_walk_request_template(
+        &self,
+        id: &str,
+    ) -> Result<Option<WalkRequestTemplate>, ServiceError> {
+        Ok(self
+            .state
+            .read()
+            .unwrap()
+            .walk_request_templates
+            .get(id)
+            .cloned())
+    }
+
+    async fn query_walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError> {
+        let mut templates: Vec<WalkRequestTemplate> = self
+            .state
+            .read()
+            .unwrap()
+            .walk_request_templates
+            .values()
+            .filter(|t| t.owner_id == owner_id)
+            .cloned()
+            .collect();
+        templates.sort_by_key(|t| t.created_at);
+        Ok(templates)
+    }
+
+    async fn delete_walk_request_template(&self, id: &str) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .walk_request_templates
+            .remove(id);
+        Ok(())
+    }
+
+    async fn save_saved_search(&self, search: &SavedSearch) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .saved_searches
+            .insert(search.id.clone(), search.clone());
+        Ok(())
+    }
+
+    async fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>, ServiceError> {
+        Ok(self.state.read().unwrap().saved_searches.get(id).cloned())
+    }
+
+    async fn query_saved_searches(
+        &self,
+        walker_id: &str,
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        let mut searches: Vec<SavedSearch> = self
+            .state
+            .read()
+            .unwrap()
+            .saved_searches
+            .values()
+            .filter(|s| s.walker_id == walker_id)
+            .cloned()
+            .collect();
+        searches.sort_by_key(|s| s.created_at);
+        Ok(searches)
+    }
+
+    async fn query_notifying_saved_searches(
+        &self,
+        tags: &[String],
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        let mut searches: Vec<SavedSearch> = self
+            .state
+            .read()
+            .unwrap()
+            .saved_searches
+            .values()
+            .filter(|s| s.notify && s.tags.iter().all(|t| tags.contains(t)))
+            .cloned()
+            .collect();
+        searches.sort_by_key(|s| s.created_at);
+        Ok(searches)
+    }
+
+    async fn delete_saved_search(&self, id: &str) -> Result<(), ServiceError> {
+        self.state.write().unwrap().saved_searches.remove(id);
+        Ok(())
+    }
+
+    async fn get_backfill_run(&self, name: &str) -> Result<Option<BackfillRun>, ServiceError> {
+        Ok(self.state.read().unwrap().backfill_runs.get(name).cloned())
+    }
+
+    async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .backfill_runs
+            .insert(run.name.clone(), run.clone());
+        Ok(())
+    }
+
+    async fn record_event(&self, event: &WalkRequestEvent) -> Result<(), ServiceError> {
+        self.state.write().unwrap().events.push(event.clone());
+        Ok(())
+    }
+
+    async fn query_events(&self, request_id: &str) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        let state = self.state.read().unwrap();
+        Ok(state
+            .events
+            .iter()
+            .filter(|e| e.request_id == request_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_message(&self, message: &Message, recipient: &str) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        state.messages.push(message.clone());
+        *state
+            .unread_messages
+            .entry((message.request_id.clone(), recipient.to_owned()))
+            .or_default() += 1;
+        Ok(())
+    }
+
+    async fn query_messages(
+        &self,
+        request_id: &str,
+        after: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Message>, ServiceError> {
+        let state = self.state.read().unwrap();
+        let mut messages: Vec<Message> = state
+            .messages
+            .iter()
+            .filter(|m| m.request_id == request_id)
+            .filter(|m| after.map_or(true, |after| m.sent_at > after))
+            .cloned()
+            .collect();
+        messages.sort_by(|a, b| (a.sent_at, &a.id).cmp(&(b.sent_at, &b.id)));
+        messages.truncate(limit.max(0) as usize);
+        Ok(messages)
+    }
+
+    async fn mark_messages_read(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        self.state
+            .write()
+            .unwrap()
+            .unread_messages
+            .remove(&(request_id.to_owned(), user_id.to_owned()));
+        Ok(())
+    }
+
+    async fn unread_messages(
+        &self,
+        user_id: &str,
+        request_ids: &[String],
+    ) -> Result<HashMap<String, u64>, ServiceError> {
+        let state = self.state.read().unwrap();
+        Ok(request_ids
+            .iter()
+            .filter_map(|id| {
+                state
+                    .unread_messages
+                    .get(&(id.clone(), user_id.to_owned()))
+                    .map(|unread| (id.clone(), *unread))
+            })
+            .collect())
+    }
+
+    async fn record_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        month: &str,
+        rejected: bool,
+    ) -> Result<(), ServiceError> {
+        let mut state = self.state.write().unwrap();
+        let record = state
+            .api_usage
+            .entry((api_key.to_owned(), endpoint.to_owned(), month.to_owned()))
+            .or_insert_with(|| UsageRecord {
+                api_key: api_key.to_owned(),
+                endpoint: endpoint.to_owned(),
+                month: month.to_owned(),
+                calls: 0,
+                rejected: 0,
+            });
+        if rejected {
+            record.rejected += 1;
+        } else {
+            record.calls += 1;
+        }
+        Ok(())
+    }
+
+    async fn query_api_usage(
+        &self,
+        month: &str,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UsageRecord>, ServiceError> {
+        let mut records: Vec<UsageRecord> = self
+            .state
+            .read()
+            .unwrap()
+            .api_usage
+            .values()
+            .filter(|r| r.month == month && api_key.map_or(true, |k| r.api_key == k))
+            .cloned()
+            .collect();
+        records.sort_by(|a, b| (&a.api_key, &a.endpoint).cmp(&(&b.api_key, &b.endpoint)));
+        Ok(records)
+    }
+
+    async fn query_stuck_sagas(
+        &self,
+        updated_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError> {
+        let mut sagas: Vec<BookingSaga> = self
+            .state
+            .read()
+            .unwrap()
+            .sagas
+            .values()
+            .filter(|s| {
+                (s.status == SagaStatus::Running && s.updated_at < updated_before)
+                    || s.status == SagaStatus::Failed
+            })
+            .cloned()
+            .collect();
+        sagas.sort_by_key(|s| s.updated_at);
+        sagas.truncate(limit.max(0) as usize);
+        Ok(sagas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        entities::WalkRequestStatus, repository::NearbyFilter, service::Service, units::Meters,
+    };
+    use crate::testing::{create, OWNER, WALKER};
+
+    async fn service_with_request() -> (Service<InMemory>, String) {
+        let service = Service::new(InMemory::new());
+        let id = service
+            .create_walk_request(create(116.397, 39.908))
+            .await
+            .unwrap();
+        (service, id)
+    }
+
+    #[actix_web::test]
+    async fn nearby_filters_by_distance() {
+        let (service, id) = service_with_request().await;
+        service
+            .create_walk_request(create(121.473, 31.230))
+            .await
+            .unwrap();
+        let nearby = service
+            .nearby_walk_requests(
+                39.909,
+                116.398,
+                Meters(1_000.0),
+                None,
+                NearbyFilter::default(),
+                Pagination::new(1, 10),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(nearby.total, 1);
+        assert!(!nearby.has_more);
+        let nearby = nearby.items;
+        assert_eq!(nearby.len(), 1);
+        assert_eq!(nearby[0].id, id);
+        assert!(nearby[0].distance.unwrap() < Meters(1_000.0));
+    }
+
+    #[actix_web::test]
+    async fn accept_start_finish() {
+        let (service, id) = service_with_request().await;
+        service.apply(&id, WALKER).await.unwrap();
+        service.book(&id, OWNER, WALKER, false).await.unwrap();
+        let request = service.get_walk_request(&id).await.unwrap().unwrap();
+        assert_eq!(request.status, WalkRequestStatus::Accepted);
+        assert_eq!(request.accepted_by.as_deref(), Some(WALKER));
+
+        let started = service.start_walk(&id, WALKER, None, None).await.unwrap();
+        assert_eq!(started.status, WalkRequestStatus::Started);
+        service
+            .record_walking_location(&id, 116.398, 39.909, None)
+            .await
+            .unwrap();
+        let finished = service.finish_walk(&id, WALKER, None).await.unwrap();
+        assert_eq!(finished.status, WalkRequestStatus::Finished);
+
+        let locations = service
+            .walking_locations(&id, Some(OWNER), None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(locations.len(), 1);
+        assert!(matches!(
+            service.walking_locations(&id, None, None, None, None).await,
+            Err(ServiceError::Unauthorized(_))
+        ));
+        let applications = service
+            .my_applications(WALKER, Pagination::new(1, 10))
+            .await
+            .unwrap();
+        assert_eq!(applications[0].state, ApplicationState::Assigned);
+    }
+
+    #[actix_web::test]
+    async fn out_of_order_transitions_conflict() {
+        let (service, id) = service_with_request().await;
+        assert!(matches!(
+            service.finish_walk(&id, WALKER, None).await,
+            Err(ServiceError::Conflict(_))
+        ));
+        service.accept(&id, WALKER).await.unwrap();
+        assert!(matches!(
+            service.finish_walk(&id, WALKER, None).await,
+            Err(ServiceError::Conflict(_))
+        ));
+        service.start_walk(&id, WALKER, None, None).await.unwrap();
+        assert!(matches!(
+            service
+                .cancel_accepted_request(&id, OWNER, WALKER, None)
+                .await,
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn accepted_request_leaves_nearby() {
+        let (service, id) = service_with_request().await;
+        service.accept(&id, WALKER).await.unwrap();
+        let nearby = service
+            .nearby_walk_requests(
+                39.908,
+                116.397,
+                Meters(1_000.0),
+                None,
+                NearbyFilter::default(),
+                Pagination::new(1, 10),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(nearby.items.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn dismissed_walker_cannot_reapply() {
+        let (service, id) = service_with_request().await;
+        service.apply(&id, WALKER).await.unwrap();
+        service
+            .assign_accepter(&id, OWNER, WALKER, false)
+            .await
+            .unwrap();
+        service.dismiss_accepter(&id, OWNER, WALKER).await.unwrap();
+        assert!(matches!(
+            service.apply(&id, WALKER).await,
+            Err(ServiceError::Conflict(_))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn offline_walk_keeps_client_times() {
+        let (service, id) = service_with_request().await;
+        service.accept(&id, WALKER).await.unwrap();
+        let accepted_at = service
+            .get_walk_request(&id)
+            .await
+            .unwrap()
+            .unwrap()
+            .accepted_at
+            .unwrap();
+        let too_old = Utc::now() - chrono::Duration::days(4);
+        assert!(matches!(
+            service.start_walk(&id, WALKER, None, Some(too_old)).await,
+            Err(ServiceError::Validation(_))
+        ));
+        let before_accept = accepted_at - chrono::Duration::minutes(5);
+        let started = service
+            .start_walk(&id, WALKER, None, Some(before_accept))
+            .await
+            .unwrap();
+        assert_eq!(started.started_at, Some(accepted_at));
+        assert!(started.start_received_at.unwrap() >= accepted_at);
+        assert!(started.clock_skew_detected_at.is_some());
+
+        let ahead = Utc::now() + chrono::Duration::hours(1);
+        let finished = service.finish_walk(&id, WALKER, Some(ahead)).await.unwrap();
+        assert!(finished.finished_at.unwrap() < ahead);
+        assert_eq!(finished.finished_at, finished.finish_received_at);
+    }
+
+    #[actix_web::test]
+    async fn missing_request_is_not_found() {
+        let service = Service::new(InMemory::new());
+        assert!(matches!(
+            service.start_walk("missing", WALKER, None, None).await,
+            Err(ServiceError::NotFound(_))
+        ));
+    }
+}
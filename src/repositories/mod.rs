@@ -1 +1,4 @@
-pub(crate) mod mongodb;
+pub mod event_sourced;
+pub mod memory;
+pub mod mongodb;
+pub mod postgres;
@@ -1,20 +1,64 @@
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{from_document, Document};
-use mongodb::options::FindOneAndUpdateOptions;
+use mongodb::bson::{from_document, to_bson, Bson, Document};
+use mongodb::change_stream::event::{ChangeStreamEvent, OperationType, ResumeToken};
+use mongodb::change_stream::ChangeStream;
+use mongodb::options::{
+    ChangeStreamOptions, FindOneAndUpdateOptions, FullDocumentType, IndexOptions, ReplaceOptions,
+    UpdateOptions,
+};
+use mongodb::IndexModel;
 use mongodb::{
     bson::doc,
     options::{FindOneOptions, FindOptions},
-    Database,
+    Client, ClientSession, Database,
 };
 
-use crate::core::entities::WalkRequest;
+use crate::core::backfill::BackfillRun;
+use crate::core::condition::{Condition, Filter, Operand};
+use crate::core::entities::{Application, ApplicationState, WalkRequest, WalkingLocation};
+use crate::core::error::ServiceError;
+use crate::core::events::{WalkRequestEvent, WalkRequestEventKind};
+use crate::core::favorite::FavoriteWalker;
+use crate::core::feature_flags::FeatureFlag;
+use crate::core::fitness::{ExportStatus, FitnessExport, FitnessToken};
+use crate::core::holiday::Holiday;
+use crate::core::ids::{is_ulid, new_ulid, normalize_id, IdFormat};
+use crate::core::live::WalkRequestChange;
+use crate::core::message::Message;
+use crate::core::metrics::{DailyMetrics, FunnelMarks, FunnelStage};
+use crate::core::offer::Offer;
+use crate::core::outbox::OutboxMessage;
+use crate::core::payment::Payment;
+use crate::core::receipt::{Payout, Receipt};
+use crate::core::reminder::StartReminder;
+use crate::core::report::{collect_buckets, ReportBucket, ReportInterval, ReportRow};
 use crate::core::repository::{Order, Pagination, Repository, SortBy, WalkingLocationCreate};
-use crate::core::repository::{WalkRequestCreate, WalkRequestQuery, WalkRequestUpdate};
+use crate::core::repository::{
+    WalkRequestCreate, WalkRequestQuery, WalkRequestStream, WalkRequestUpdate, WalkingLocationQuery,
+};
+use crate::core::reputation::{Reputation, ReputationUpdate};
+use crate::core::retention::DataClass;
+use crate::core::saga::{BookingSaga, SagaStatus};
+use crate::core::saved_search::SavedSearch;
+use crate::core::schedule::WalkSchedule;
+use crate::core::session::DeviceSession;
+use crate::core::spending::SpendingCap;
+use crate::core::strike::Strike;
+use crate::core::template::WalkRequestTemplate;
+use crate::core::tenant::Tenant;
+use crate::core::units::{Meters, Money};
+use crate::core::usage::UsageRecord;
+use crate::core::walker_capabilities::WalkerCapabilities;
 use anyhow::Error;
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::lock::Mutex;
 use futures::{StreamExt, TryStreamExt};
 use little_walk_dog::core::entities::Dog;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
 use std::str::FromStr;
+use std::sync::Arc;
 
 impl WalkRequest {
     pub fn projection() -> Document {
@@ -27,8 +71,18 @@ impl WalkRequest {
             "should_end_before": {"$dateToString": {"date":"$should_end_before", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "longitude": { "$arrayElemAt": [ "$location.coordinates", 0]},
             "latitude": { "$arrayElemAt": [ "$location.coordinates", 1]},
+            "timezone": "$timezone",
+            "region": "$region",
+            "tenant_id": "$tenant_id",
+            "reference_code": "$reference_code",
+            "tags": "$tags",
+            "invited_walker_id": "$invited_walker_id",
+            "invitation_expires_at": {"$dateToString": {"date":"$invitation_expires_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "distance": "$distance",
             "canceled_at": {"$dateToString": {"date":"$canceled_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "canceled_by": "$canceled_by",
+            "cancellation_reason": "$cancellation_reason",
+            "cancel_requested_at": {"$dateToString": {"date":"$cancel_requested_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "accepted_by": "$accepted_by",
             "accepted_at": {"$dateToString": {"date":"$accepted_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "started_at": {"$dateToString": {"date":"$started_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
@@ -37,74 +91,366 @@ impl WalkRequest {
                 "$switch": {
                     "branches": [
                         {"case": {"$ne": [{"$ifNull": ["$canceled_at", null]}, null]}, "then": "Canceled" },
-                        {"case": {"$ne": [{"$ifNull": ["$accepted_at", null]}, null]}, "then": "Accepted" },
-                        {"case": {"$ne": [{"$ifNull": ["$started_at", null]}, null]}, "then": "Started" },
+                        {"case": {"$ne": [{"$ifNull": ["$expired_at", null]}, null]}, "then": "Expired" },
+                        {"case": {"$ne": [{"$ifNull": ["$cancel_requested_at", null]}, null]}, "then": "PendingCancel" },
                         {"case": {"$ne": [{"$ifNull": ["$finished_at", null]}, null]}, "then": "Finished" },
+                        {"case": {"$ne": [{"$ifNull": ["$started_at", null]}, null]}, "then": "Started" },
+                        {"case": {"$ne": [{"$ifNull": ["$accepted_at", null]}, null]}, "then": "Accepted" },
                     ],
                     "default": "Waiting"
                 }
             },
+            "created_by": "$created_by",
             "acceptances": "$acceptances",
+            "dismissed_applicants": "$dismissed_applicants",
+            "max_applicants": "$max_applicants",
+            "apply_before": {"$dateToString": {"date":"$apply_before", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "remaining_slots": {
+                "$cond": [
+                    {"$eq": [{"$ifNull": ["$max_applicants", null]}, null]},
+                    null,
+                    {"$max": [0, {"$subtract": ["$max_applicants", {"$size": {"$ifNull": ["$acceptances", []]}}]}]},
+                ]
+            },
+            "requirements": {
+                "large_breed": {"$ifNull": ["$requirements.large_breed", false]},
+                "puppy": {"$ifNull": ["$requirements.puppy", false]},
+            },
+            "time_to_accept_seconds": {"$toLong": {"$divide": [{"$subtract": ["$accepted_at", "$created_at"]}, 1000]}},
+            "sla_breached_at": {"$dateToString": {"date":"$sla_breached_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "expired_at": {"$dateToString": {"date":"$expired_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "track_visibility": "$track_visibility",
+            "track_archived_at": {"$dateToString": {"date":"$track_archived_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "track_downsampled_at": {"$dateToString": {"date":"$track_downsampled_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "summary": {
+                "$cond": [
+                    {"$eq": [{"$ifNull": ["$summary", null]}, null]},
+                    null,
+                    {
+                        "distance": "$summary.distance",
+                        "distance_strategy": {"$ifNull": ["$summary.distance_strategy", "raw"]},
+                        "raw_distance": "$summary.raw_distance",
+                        "duration_seconds": "$summary.duration_seconds",
+                        "average_speed_kmh": "$summary.average_speed_kmh",
+                        "points": "$summary.points",
+                        "route": "$summary.route",
+                        "quality": "$summary.quality",
+                        "computed_at": {"$dateToString": {"date":"$summary.computed_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+                    },
+                ]
+            },
+            "deleted_at": {"$dateToString": {"date":"$deleted_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "max_radius": "$max_radius",
+            "price": "$price",
+            "max_walkers": "$max_walkers",
+            "pool_walkers": {
+                "$map": {
+                    "input": {"$ifNull": ["$pool_walkers", []]},
+                    "as": "walker",
+                    "in": {
+                        "walker_id": "$$walker.walker_id",
+                        "accepted_at": {"$dateToString": {"date":"$$walker.accepted_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+                        "started_at": {"$dateToString": {"date":"$$walker.started_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+                        "finished_at": {"$dateToString": {"date":"$$walker.finished_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+                    },
+                }
+            },
+            "version": {"$ifNull": ["$version", 0i64]},
+            "geofence_violated_at": {"$dateToString": {"date":"$geofence_violated_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "verification_waived_at": {"$dateToString": {"date":"$verification_waived_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "start_received_at": {"$dateToString": {"date":"$start_received_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "finish_received_at": {"$dateToString": {"date":"$finish_received_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+            "clock_skew_detected_at": {"$dateToString": {"date":"$clock_skew_detected_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "created_at": {"$dateToString": {"date":"$created_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
             "updated_at": {"$dateToString": {"date":"$updated_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
         }
     }
 }
 
-impl TryFrom<WalkRequestQuery> for Document {
-    type Error = Error;
-    fn try_from(value: WalkRequestQuery) -> Result<Self, Self::Error> {
-        let mut q = doc! {};
-        if let Some(id) = value.id {
-            q.insert("_id", ObjectId::from_str(&id)?);
+impl WalkingLocation {
+    pub fn projection() -> Document {
+        doc! {
+            "id": {"$toString": "$_id"},
+            "request_id": "$walk_request_id",
+            "longitude": "$longitude",
+            "latitude": "$latitude",
+            "created_at": {"$dateToString": {"date":"$created_at", "format": "%Y-%m-%dT%H:%M:%S.%LZ"}},
+        }
+    }
+}
+
+impl From<WalkingLocationQuery> for Document {
+    fn from(value: WalkingLocationQuery) -> Self {
+        let mut q = doc! {"walk_request_id": value.walk_request_id};
+        let mut created_at = doc! {};
+        if let Some(after) = value.created_after {
+            created_at.insert("$gte", after);
+        }
+        if let Some(before) = value.created_before {
+            created_at.insert("$lt", before);
+        }
+        if !created_at.is_empty() {
+            q.insert("created_at", created_at);
+        }
+        q
+    }
+}
+
+/// The stored `_id` of `id`: an ObjectId, or a string for ULIDs.
+fn id_bson(id: &str) -> Result<Bson, ServiceError> {
+    let id = normalize_id(id);
+    if is_ulid(id) {
+        return Ok(Bson::String(id.to_owned()));
+    }
+    Ok(Bson::ObjectId(ObjectId::from_str(id)?))
+}
+
+/// The id as returned by the API, the hex string for ObjectIds.
+fn id_string(id: &Bson) -> String {
+    match id {
+        Bson::ObjectId(oid) => oid.to_hex(),
+        Bson::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The transition a change stream event of `walk_requests` stands for, if
+/// any. Deletes are archiving, not transitions.
+pub fn walk_request_change(event: &ChangeStreamEvent<Document>) -> Option<WalkRequestChange> {
+    let request_id = id_string(event.document_key.as_ref()?.get("_id")?);
+    let kind = match event.operation_type {
+        OperationType::Insert => WalkRequestEventKind::Created,
+        OperationType::Update => {
+            let update = event.update_description.as_ref()?;
+            WalkRequestChange::kind_of_update(
+                update
+                    .updated_fields
+                    .iter()
+                    .map(|(field, value)| (field.as_str(), *value != Bson::Null))
+                    .chain(update.removed_fields.iter().map(|f| (f.as_str(), false))),
+            )?
         }
-        if let Some(ids) = value.dog_ids_includes_any {
-            q.insert("dogs.id", doc! {"$elemMatch": {"$in": ids }});
+        _ => return None,
+    };
+    let actor = WalkRequestChange::actor_field(kind)
+        .and_then(|field| event.full_document.as_ref()?.get_str(field).ok())
+        .map(str::to_owned);
+    Some(WalkRequestChange {
+        request_id,
+        kind,
+        actor,
+    })
+}
+
+/// `text` matched literally inside a regular expression.
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
         }
-        if let Some(ids) = value.dog_ids_includes_all {
-            q.insert("dogs.id", doc! {"$all": ids });
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// `time` as stored, an ISO string, which compares in time order, when
+/// `dates_as_strings`.
+fn time_bson(time: DateTime<Utc>, dates_as_strings: bool) -> Bson {
+    if dates_as_strings {
+        Bson::String(time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+    } else {
+        Bson::from(time)
+    }
+}
+
+/// Whether the conditions are matched against the feed, whose documents
+/// hold dates as projected. That's the case for a `GeoNear`.
+fn on_feed(conditions: &[Condition]) -> bool {
+    conditions
+        .iter()
+        .any(|c| matches!(c.filter, Filter::GeoNear { .. }))
+}
+
+/// The operators a filter compiles to, with their operands. Dates become
+/// ISO strings, which compare in time order, when `dates_as_strings`.
+fn operators(
+    filter: Filter,
+    dates_as_strings: bool,
+) -> Result<Vec<(&'static str, Bson)>, ServiceError> {
+    let operand = |value: Operand| -> Result<Bson, ServiceError> {
+        Ok(match value {
+            Operand::Id(id) => id_bson(&id)?,
+            Operand::Text(text) => Bson::String(text),
+            Operand::Int(n) => Bson::Int64(n),
+            Operand::Bool(b) => Bson::Boolean(b),
+            Operand::Time(time) => time_bson(time, dates_as_strings),
+            Operand::Null => Bson::Null,
+        })
+    };
+    let operands = |values: Vec<Operand>| -> Result<Bson, ServiceError> {
+        Ok(Bson::Array(
+            values.into_iter().map(operand).collect::<Result<_, _>>()?,
+        ))
+    };
+    Ok(match filter {
+        Filter::Eq(value) => vec![("$eq", operand(value)?)],
+        Filter::Ne(value) => vec![("$ne", operand(value)?)],
+        Filter::In(values) => vec![("$in", operands(values)?)],
+        Filter::All(values) => vec![("$all", operands(values)?)],
+        Filter::IsNull(true) => vec![("$eq", Bson::Null)],
+        Filter::IsNull(false) => vec![("$ne", Bson::Null)],
+        Filter::Range { gt, gte, lt, lte } => {
+            [("$gt", gt), ("$gte", gte), ("$lt", lt), ("$lte", lte)]
+                .into_iter()
+                .filter_map(|(op, bound)| bound.map(|b| operand(b).map(|b| (op, b))))
+                .collect::<Result<_, _>>()?
         }
-        if let Some(accepted_by) = value.accepted_by {
-            q.insert("accepted_by", accepted_by);
+        Filter::GeoNear { .. } => {
+            return Err(anyhow::anyhow!("$geoNear is a stage, not an operator").into())
         }
-        if let Some(accepted_by_neq) = value.accepted_by_neq {
-            q.insert("accepted_by", doc! {"$ne": accepted_by_neq });
+    })
+}
+
+/// The filter document matching every condition but `GeoNear`, which is
+/// left to `geo_near`. Conditions on one field share its operator
+/// document, an operator used twice on a field goes under `$and` so that
+/// neither replaces the other. Dates are compared as strings on the feed.
+fn translate(conditions: &[Condition]) -> Result<Document, ServiceError> {
+    let dates_as_strings = on_feed(conditions);
+    let mut query = Document::new();
+    let mut and = Vec::new();
+    for condition in conditions {
+        if matches!(condition.filter, Filter::GeoNear { .. }) {
+            continue;
         }
-        if let Some(accepted_by_is_null) = value.accepted_by_is_null {
-            if accepted_by_is_null {
-                q.insert("accepted_by", doc! {"$eq": null});
-            } else {
-                q.insert("accepted_by", doc! {"$neq": null});
+        let field = match condition.field.as_str() {
+            "id" => "_id".to_owned(),
+            field => field.to_owned(),
+        };
+        for (op, value) in operators(condition.filter.clone(), dates_as_strings)? {
+            match query
+                .entry(field.clone())
+                .or_insert_with(|| Bson::Document(Document::new()))
+            {
+                Bson::Document(ops) if !ops.contains_key(op) => {
+                    ops.insert(op, value);
+                }
+                _ => and.push(doc! {field.as_str(): {op: value}}),
             }
         }
-        if let Some(acceptances_includes_all) = value.acceptances_includes_all {
-            q.insert("acceptances", doc! {"$all": acceptances_includes_all });
+    }
+    if !and.is_empty() {
+        query.insert("$and", and);
+    }
+    Ok(query)
+}
+
+/// `query` as the filter of the `$geoNear` stage of the conditions'
+/// `GeoNear`, as is without one.
+fn geo_near(conditions: &[Condition], query: Document) -> Document {
+    let near = conditions
+        .iter()
+        .find_map(|condition| match condition.filter {
+            Filter::GeoNear {
+                longitude,
+                latitude,
+                max_distance,
+                min_distance,
+            } => Some((longitude, latitude, max_distance, min_distance)),
+            _ => None,
+        });
+    let Some((longitude, latitude, max_distance, min_distance)) = near else {
+        return query;
+    };
+    let mut stage = doc! {
+        "near": { "type": "Point", "coordinates": [longitude, latitude] },
+        "distanceField": "distance",
+        "maxDistance": max_distance,
+        "spherical": true,
+        "query": query,
+        "includeLocs": "location",
+    };
+    // Requests closer than the cursor are left out by the geo search
+    // itself, ties are settled by id after it.
+    if let Some(min_distance) = min_distance {
+        stage.insert("minDistance", min_distance);
+    }
+    doc! {"$geoNear": stage}
+}
+
+impl TryFrom<WalkRequestQuery> for Document {
+    type Error = ServiceError;
+    fn try_from(value: WalkRequestQuery) -> Result<Self, Self::Error> {
+        let conditions = value.conditions()?;
+        let mut q = translate(&conditions)?;
+        let dates_as_strings = on_feed(&conditions);
+        if let Some(default_cap) = value.below_applicant_cap {
+            q.insert(
+                "$expr",
+                doc! {"$lt": [
+                    {"$size": {"$ifNull": ["$acceptances", []]}},
+                    {"$ifNull": ["$max_applicants", default_cap]},
+                ]},
+            );
         }
-        if let Some(acceptances_includes_any) = value.acceptances_includes_any {
+        if let Some(open_at) = value.apply_open_at {
             q.insert(
-                "acceptances",
-                doc! {"$elemMatch": {"$in": acceptances_includes_any }},
+                "$or",
+                vec![
+                    doc! {"apply_before": null},
+                    doc! {"apply_before": {"$gt": time_bson(open_at, dates_as_strings)}},
+                ],
             );
         }
-        if let Some(nearby) = value.nearby {
-            if nearby.len() != 3 {
-                return Err(anyhow::anyhow!("Invalid nearby query, expect [f64;3]"));
+        if let Some(viewer) = value.invitation_visible_to {
+            let mut visible = vec![
+                doc! {"invitation_expires_at": null},
+                doc! {"invitation_expires_at": {"$lte": time_bson(viewer.at, dates_as_strings)}},
+            ];
+            if let Some(walker) = viewer.walker {
+                visible.push(doc! {"invited_walker_id": walker});
             }
-            return Ok(doc! {
-                "$geoNear": {
-                    "near": { "type": "Point", "coordinates": [nearby[0], nearby[1]] },
-                    "distanceField": "distance",
-                    "maxDistance": nearby[2],
-                    "spherical": true,
-                    "query": q,
-                    "includeLocs": "location",
+            // `$or` may already hold the apply deadline.
+            let visible = Bson::Document(doc! {"$or": visible});
+            match q.get_array_mut("$and") {
+                Ok(and) => and.push(visible),
+                Err(_) => {
+                    q.insert("$and", vec![visible]);
                 }
-            });
+            }
+        }
+        let mut dog = doc! {};
+        if let Some(name) = value.dog_name_contains {
+            dog.insert(
+                "name",
+                doc! {"$regex": escape_regex(&name), "$options": "i"},
+            );
+        }
+        if let Some(breed) = value.dog_breed {
+            dog.insert(
+                "breed",
+                doc! {"$regex": format!("^\\s*{}\\s*$", escape_regex(breed.trim())), "$options": "i"},
+            );
+        }
+        if let Some(size) = value.dog_size {
+            dog.insert("size", size.as_str());
         }
-        if let Some(created_by) = value.created_by {
-            q.insert("created_by", created_by);
+        // `dogs` is only set by dog counts which can't match, leaving
+        // nothing to narrow.
+        if !dog.is_empty() && !q.contains_key("dogs") {
+            q.insert("dogs", doc! {"$elemMatch": dog});
         }
-        Ok(q)
+        if let Some(area) = value.within_box {
+            // `$box` only matches legacy coordinate pairs, the GeoJSON
+            // locations are matched by the box as a polygon.
+            let ring: Vec<Vec<f64>> = area.ring().iter().map(|c| c.to_vec()).collect();
+            q.insert(
+                "location",
+                doc! {"$geoWithin": {"$geometry": {"type": "Polygon", "coordinates": [ring]}}},
+            );
+        }
+        Ok(geo_near(&conditions, q))
     }
 }
 
@@ -120,11 +466,24 @@ impl From<WalkRequestUpdate> for Document {
         if let Some(accepted_at) = update.accepted_at {
             set.insert("accepted_at", accepted_at);
         }
+        // The point is stored as `location`, [longitude, latitude].
         if let Some(latitude) = update.latitude {
-            set.insert("latitude", latitude);
+            set.insert("location.coordinates.1", latitude);
         }
         if let Some(longitude) = update.longitude {
-            set.insert("longitude", longitude);
+            set.insert("location.coordinates.0", longitude);
+        }
+        if let Some(timezone) = update.timezone {
+            set.insert("timezone", timezone);
+        }
+        if let Some(canceled_at) = update.canceled_at {
+            set.insert("canceled_at", canceled_at);
+        }
+        if let Some(canceled_by) = update.canceled_by {
+            set.insert("canceled_by", canceled_by);
+        }
+        if let Some(cancellation_reason) = update.cancellation_reason {
+            set.insert("cancellation_reason", cancellation_reason);
         }
         if let Some(should_start_after) = update.should_start_after {
             set.insert("should_start_after", should_start_after);
@@ -138,8 +497,8 @@ impl From<WalkRequestUpdate> for Document {
         if let Some(should_end_after) = update.should_end_after {
             set.insert("should_end_after", should_end_after);
         }
-        if let Some(add_to_acceptances) = update.add_to_acceptances {
-            set.insert("$addToSet", doc! {"acceptances": add_to_acceptances});
+        if let Some(cancel_requested_at) = update.cancel_requested_at {
+            set.insert("cancel_requested_at", cancel_requested_at);
         }
         if let Some(started_at) = update.started_at {
             set.insert("started_at", started_at);
@@ -147,9 +506,106 @@ impl From<WalkRequestUpdate> for Document {
         if let Some(finished_at) = update.finished_at {
             set.insert("finished_at", finished_at);
         }
+        if let Some(sla_breached_at) = update.sla_breached_at {
+            set.insert("sla_breached_at", sla_breached_at);
+        }
+        if let Some(expired_at) = update.expired_at {
+            set.insert("expired_at", expired_at);
+        }
+        if let Some(requirements) = update.requirements {
+            set.insert(
+                "requirements",
+                doc! {
+                    "large_breed": requirements.large_breed,
+                    "puppy": requirements.puppy,
+                },
+            );
+        }
+        if let Some(track_visibility) = update.track_visibility {
+            set.insert("track_visibility", track_visibility.as_str());
+        }
+        if let Some(price) = update.price {
+            set.insert(
+                "price",
+                doc! {"minor_units": price.minor_units, "currency": price.currency},
+            );
+        }
+        if let Some(track_archived_at) = update.track_archived_at {
+            set.insert("track_archived_at", track_archived_at);
+        }
+        if let Some(track_downsampled_at) = update.track_downsampled_at {
+            set.insert("track_downsampled_at", track_downsampled_at);
+        }
+        if let Some(deleted_at) = update.deleted_at {
+            set.insert("deleted_at", deleted_at);
+        }
+        if let Some(geofence_violated_at) = update.geofence_violated_at {
+            set.insert("geofence_violated_at", geofence_violated_at);
+        }
+        if let Some(verification_waived_at) = update.verification_waived_at {
+            set.insert("verification_waived_at", verification_waived_at);
+        }
+        if let Some(start_received_at) = update.start_received_at {
+            set.insert("start_received_at", start_received_at);
+        }
+        if let Some(finish_received_at) = update.finish_received_at {
+            set.insert("finish_received_at", finish_received_at);
+        }
+        if let Some(clock_skew_detected_at) = update.clock_skew_detected_at {
+            set.insert("clock_skew_detected_at", clock_skew_detected_at);
+        }
+        if let Some(invitation_expires_at) = update.invitation_expires_at {
+            set.insert("invitation_expires_at", invitation_expires_at);
+        }
+        if let Some(tags) = update.tags {
+            set.insert("tags", tags);
+        }
+        if let Some(pool_walkers) = update.pool_walkers {
+            set.insert(
+                "pool_walkers",
+                pool_walkers
+                    .into_iter()
+                    .map(|w| {
+                        doc! {
+                            "walker_id": w.walker_id,
+                            "accepted_at": w.accepted_at,
+                            "started_at": w.started_at,
+                            "finished_at": w.finished_at,
+                        }
+                    })
+                    .collect::<Vec<_>>(),
+            );
+        }
+        if let Some(summary) = update.summary {
+            set.insert(
+                "summary",
+                doc! {
+                    "distance": summary.distance.value(),
+                    "distance_strategy": summary.distance_strategy.as_str(),
+                    "raw_distance": summary.raw_distance.map(|d| d.value()),
+                    "duration_seconds": summary.duration_seconds,
+                    "average_speed_kmh": summary.average_speed_kmh,
+                    "points": summary.points,
+                    "route": summary.route.iter().map(|p| p.to_vec()).collect::<Vec<_>>(),
+                    "quality": summary.quality,
+                    "computed_at": summary.computed_at,
+                },
+            );
+        }
+        let mut add_to_set = doc! {};
+        if let Some(add_to_acceptances) = update.add_to_acceptances {
+            add_to_set.insert("acceptances", add_to_acceptances);
+        }
+        if let Some(add_to_dismissed_applicants) = update.add_to_dismissed_applicants {
+            add_to_set.insert("dismissed_applicants", add_to_dismissed_applicants);
+        } else if let Some(user_ids) = update.add_all_to_dismissed_applicants {
+            add_to_set.insert("dismissed_applicants", doc! {"$each": user_ids});
+        }
         let mut pull = doc! {};
         if let Some(remove_from_acceptances) = update.remove_from_acceptances {
             pull.insert("acceptances", remove_from_acceptances);
+        } else if let Some(user_ids) = update.remove_all_from_acceptances {
+            pull.insert("acceptances", doc! {"$in": user_ids});
         }
         let mut unset = doc! {};
         if update.unset_accepted_by {
@@ -158,7 +614,18 @@ impl From<WalkRequestUpdate> for Document {
         if update.unset_accepted_at {
             unset.insert("accepted_at", "");
         }
-        doc! {"$set": set, "$unset": unset, "$pull": pull}
+        if update.unset_cancel_request {
+            unset.insert("cancel_requested_at", "");
+            unset.insert("canceled_by", "");
+            unset.insert("cancellation_reason", "");
+        }
+        doc! {
+            "$set": set,
+            "$unset": unset,
+            "$pull": pull,
+            "$addToSet": add_to_set,
+            "$inc": {"version": 1i64},
+        }
     }
 }
 
@@ -171,6 +638,23 @@ impl From<WalkRequestCreate> for Document {
             "should_end_before": value.should_end_before,
             "should_end_after": value.should_end_after,
             "location": { "type": "Point", "coordinates": [value.longitude, value.latitude] },
+            "timezone": value.timezone,
+            "region": value.region,
+            "tenant_id": value.tenant_id,
+            "reference_code": value.reference_code,
+            "tags": value.tags,
+            "invited_walker_id": value.invited_walker_id,
+            "invitation_expires_at": value.invitation_expires_at,
+            "max_applicants": value.max_applicants,
+            "apply_before": value.apply_before,
+            "requirements": {
+                "large_breed": value.requirements.large_breed,
+                "puppy": value.requirements.puppy,
+            },
+            "track_visibility": value.track_visibility.as_str(),
+            "max_radius": value.max_radius.map(|r| r.value()),
+            "price": value.price.map(|p| doc! {"minor_units": p.minor_units, "currency": p.currency}),
+            "max_walkers": value.max_walkers,
             "created_by": value.created_by,
             "created_at": Utc::now(),
             "updated_at": Utc::now(),
@@ -180,48 +664,507 @@ impl From<WalkRequestCreate> for Document {
 
 impl<'a> From<WalkingLocationCreate<'a>> for Document {
     fn from(value: WalkingLocationCreate) -> Self {
+        let now = Utc::now();
         doc! {
             "walk_request_id": value.walk_request_id,
             "longitude": value.longitude,
             "latitude": value.latitude,
-            "created_at": Utc::now(),
-            "updated_at": Utc::now(),
+            "created_at": value.recorded_at.unwrap_or(now),
+            "received_at": now,
+            "updated_at": now,
         }
     }
 }
 
+impl From<mongodb::error::Error> for ServiceError {
+    fn from(e: mongodb::error::Error) -> Self {
+        ServiceError::internal(e)
+    }
+}
+
+impl From<mongodb::bson::oid::Error> for ServiceError {
+    fn from(_: mongodb::bson::oid::Error) -> Self {
+        ServiceError::Validation("无效的ID".to_owned())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for ServiceError {
+    fn from(e: mongodb::bson::ser::Error) -> Self {
+        ServiceError::internal(e)
+    }
+}
+
+impl From<mongodb::bson::de::Error> for ServiceError {
+    fn from(e: mongodb::bson::de::Error) -> Self {
+        ServiceError::internal(e)
+    }
+}
+
+/// The session of a running transaction, shared by the clones of the
+/// repository running in it.
+#[derive(Clone)]
+struct Transaction {
+    session: Arc<Mutex<ClientSession>>,
+    /// `$merge` can't run in a transaction, requests to re-project into the
+    /// feed are collected and projected after the commit.
+    feed_ids: Arc<std::sync::Mutex<Vec<Bson>>>,
+}
+
+impl fmt::Debug for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transaction")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Mongodb {
     db: Database,
+    id_format: IdFormat,
+    /// Set on replica sets, transactions need one.
+    client: Option<Client>,
+    transaction: Option<Transaction>,
+    /// See `Repository::for_tenant`.
+    tenant: Option<String>,
 }
 
+/// Read model serving the nearby feed: one pre-projected document per open
+/// (Waiting) request, refreshed on every write, so the hot `$geoNear` path
+/// skips the `$project` stage.
+const FEED_COLLECTION: &str = "walk_request_feed";
+
+/// Finished and canceled requests moved out of `walk_requests`, kept as they
+/// were stored.
+const ARCHIVE_COLLECTION: &str = "walk_requests_archive";
+
 impl Mongodb {
     pub fn new(db: Database) -> Self {
-        Mongodb { db }
+        Mongodb {
+            db,
+            id_format: IdFormat::default(),
+            client: None,
+            transaction: None,
+            tenant: None,
+        }
+    }
+
+    /// Runs `Repository::transaction` in MongoDB transactions, which only
+    /// replica sets and sharded clusters support. Only the walk request
+    /// updates and applications take part so far.
+    pub fn with_transactions(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn with_id_format(mut self, id_format: IdFormat) -> Self {
+        self.id_format = id_format;
+        self
+    }
+
+    /// Writes to walk requests as they happen, after `resume_after` when
+    /// given so a restarted listener misses nothing. Change streams need a
+    /// replica set.
+    pub async fn walk_request_changes(
+        &self,
+        resume_after: Option<ResumeToken>,
+    ) -> Result<ChangeStream<ChangeStreamEvent<Document>>, Error> {
+        let options = ChangeStreamOptions::builder()
+            .full_document(Some(FullDocumentType::UpdateLookup))
+            .resume_after(resume_after)
+            .build();
+        Ok(self
+            .db
+            .collection::<Document>("walk_requests")
+            .watch(None, options)
+            .await?)
+    }
+
+    /// `query` within the tenant the repository is scoped to.
+    fn scoped(&self, query: WalkRequestQuery) -> WalkRequestQuery {
+        query.within_tenant(self.tenant.as_deref())
+    }
+
+    /// The filter of the walk request `id`, within the tenant.
+    fn by_id(&self, id: &str) -> Result<Document, ServiceError> {
+        let mut filter = doc! {"_id": id_bson(id)?};
+        if let Some(tenant) = &self.tenant {
+            filter.insert("tenant_id", tenant);
+        }
+        Ok(filter)
+    }
+
+    /// Sets a generated `_id` when ids aren't left to MongoDB.
+    fn with_new_id(&self, mut document: Document) -> Document {
+        if self.id_format == IdFormat::Ulid {
+            document.insert("_id", new_ulid());
+        }
+        document
+    }
+
+    /// Re-projects the given walk requests into the feed, dropping the ones
+    /// which are no longer open.
+    async fn refresh_feed(&self, ids: Vec<Bson>) -> Result<(), Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        if let Some(transaction) = &self.transaction {
+            transaction.feed_ids.lock().unwrap().extend(ids);
+            return Ok(());
+        }
+        self.db
+            .collection::<Document>(FEED_COLLECTION)
+            .delete_many(doc! {"_id": {"$in": ids.clone()}}, None)
+            .await?;
+        self.project_feed(doc! {"_id": {"$in": ids}}).await?;
+        Ok(())
+    }
+
+    async fn project_feed(&self, filter: Document) -> Result<(), Error> {
+        let mut projection = WalkRequest::projection();
+        projection.insert("location", "$location");
+        self.db
+            .collection::<Document>("walk_requests")
+            .aggregate(
+                vec![
+                    doc! {"$match": filter},
+                    doc! {"$project": projection},
+                    doc! {"$match": {"status": "Waiting"}},
+                    doc! {"$merge": {
+                        "into": FEED_COLLECTION,
+                        "on": "_id",
+                        "whenMatched": "replace",
+                        "whenNotMatched": "insert",
+                    }},
+                ],
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the indexes queries rely on, `$geoNear` fails without the
+    /// `2dsphere` one. Creating an existing index is a no-op. With
+    /// `location_ttl` walking locations expire that long after being recorded.
+    pub async fn ensure_indexes(
+        &self,
+        location_ttl: Option<std::time::Duration>,
+    ) -> Result<(), Error> {
+        let index = |keys: Document| IndexModel::builder().keys(keys).build();
+        self.db
+            .collection::<Document>("walk_requests")
+            .create_indexes(
+                vec![
+                    index(doc! {"location": "2dsphere"}),
+                    index(doc! {"created_by": 1, "created_at": -1}),
+                    index(doc! {"accepted_by": 1}),
+                    index(doc! {"created_at": 1}),
+                    index(doc! {"dogs.$**": 1}),
+                    index(doc! {"tenant_id": 1, "created_at": -1}),
+                    index(doc! {"tags": 1}),
+                    IndexModel::builder()
+                        .keys(doc! {"reference_code": 1})
+                        .options(
+                            IndexOptions::builder()
+                                .unique(true)
+                                .partial_filter_expression(
+                                    doc! {"reference_code": {"$type": "string"}},
+                                )
+                                .build(),
+                        )
+                        .build(),
+                ],
+                None,
+            )
+            .await?;
+        self.db
+            .collection::<Document>(ARCHIVE_COLLECTION)
+            .create_indexes(
+                vec![
+                    index(doc! {"created_by": 1, "created_at": -1}),
+                    index(doc! {"accepted_by": 1, "created_at": -1}),
+                ],
+                None,
+            )
+            .await?;
+        self.db
+            .collection::<Document>("walking_locations")
+            .create_index(index(doc! {"walk_request_id": 1, "created_at": 1}), None)
+            .await?;
+        if let Some(ttl) = location_ttl {
+            self.ensure_location_ttl(ttl).await?;
+        }
+        self.db
+            .collection::<Document>("events")
+            .create_index(index(doc! {"request_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("messages")
+            .create_index(index(doc! {"request_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("outbox")
+            .create_index(index(doc! {"status": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("receipts")
+            .create_index(index(doc! {"owner_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("payouts")
+            .create_index(index(doc! {"walker_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("spending_caps")
+            .create_index(index(doc! {"owner_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("start_reminders")
+            .create_index(index(doc! {"request_id": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("message_reads")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"user_id": 1, "request_id": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await?;
+        self.db
+            .collection::<Document>("saved_searches")
+            .create_index(index(doc! {"walker_id": 1, "created_at": 1}), None)
+            .await?;
+        self.db
+            .collection::<Document>("walk_request_events")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"request_id": 1, "seq": 1})
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn ensure_location_ttl(&self, ttl: std::time::Duration) -> Result<(), Error> {
+        let created = self
+            .db
+            .collection::<Document>("walking_locations")
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! {"created_at": 1})
+                    .options(IndexOptions::builder().expire_after(ttl).build())
+                    .build(),
+                None,
+            )
+            .await;
+        if created.is_ok() {
+            return Ok(());
+        }
+        // The index exists with another TTL, which only `collMod` changes.
+        self.db
+            .run_command(
+                doc! {
+                    "collMod": "walking_locations",
+                    "index": {
+                        "keyPattern": {"created_at": 1},
+                        "expireAfterSeconds": ttl.as_secs() as i64,
+                    },
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Creates the feed's geo index and rebuilds it from scratch.
+    pub async fn rebuild_feed(&self) -> Result<(), Error> {
+        let feed = self.db.collection::<Document>(FEED_COLLECTION);
+        feed.create_index(
+            IndexModel::builder()
+                .keys(doc! {"location": "2dsphere"})
+                .build(),
+            None,
+        )
+        .await?;
+        feed.delete_many(doc! {}, None).await?;
+        self.project_feed(doc! {}).await
     }
 }
 
 impl Repository for Mongodb {
-    async fn create_walk_request(&self, request: WalkRequestCreate) -> Result<String, Error> {
-        let inserted = self
+    fn for_tenant(&self, tenant_id: &str) -> Self {
+        Self {
+            tenant: Some(tenant_id.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    async fn transaction<T, F, Fut>(&self, operation: F) -> Result<T, ServiceError>
+    where
+        Self: Clone,
+        F: FnOnce(Self) -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        let Some(client) = self.client.as_ref().filter(|_| self.transaction.is_none()) else {
+            // Nested transactions join the running one.
+            return operation(self.clone()).await;
+        };
+        let mut session = client.start_session(None).await?;
+        session.start_transaction(None).await?;
+        let transaction = Transaction {
+            session: Arc::new(Mutex::new(session)),
+            feed_ids: Default::default(),
+        };
+        let result = operation(Self {
+            transaction: Some(transaction.clone()),
+            ..self.clone()
+        })
+        .await;
+        let mut session = transaction.session.lock().await;
+        match result {
+            Ok(value) => {
+                session.commit_transaction().await?;
+                drop(session);
+                let ids = std::mem::take(&mut *transaction.feed_ids.lock().unwrap());
+                self.refresh_feed(ids).await?;
+                Ok(value)
+            }
+            Err(e) => {
+                session.abort_transaction().await?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn create_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<String, ServiceError> {
+        let collection = self.db.collection::<Document>("walk_requests");
+        let document = self.with_new_id(Document::from(request));
+        let inserted = match &self.transaction {
+            Some(transaction) => {
+                collection
+                    .insert_one_with_session(document, None, &mut *transaction.session.lock().await)
+                    .await?
+            }
+            None => collection.insert_one(document, None).await?,
+        };
+        self.refresh_feed(vec![inserted.inserted_id.clone()])
+            .await?;
+        Ok(id_string(&inserted.inserted_id))
+    }
+
+    async fn count_walk_requests(&self, query: WalkRequestQuery) -> Result<u64, ServiceError> {
+        if query.nearby.is_some() {
+            let counted = self
+                .db
+                .collection::<Document>(FEED_COLLECTION)
+                .aggregate(
+                    vec![
+                        Document::try_from(self.scoped(query))?,
+                        doc! {"$count": "total"},
+                    ],
+                    None,
+                )
+                .await?
+                .try_next()
+                .await?;
+            return Ok(counted
+                .and_then(|d| {
+                    d.get("total")
+                        .and_then(|t| t.as_i64().or(t.as_i32().map(i64::from)))
+                })
+                .unwrap_or(0) as u64);
+        }
+        Ok(self
+            .db
+            .collection::<Document>("walk_requests")
+            .count_documents(Document::try_from(self.scoped(query))?, None)
+            .await?)
+    }
+
+    async fn walk_report(
+        &self,
+        query: WalkRequestQuery,
+        interval: ReportInterval,
+    ) -> Result<Vec<ReportBucket>, ServiceError> {
+        let period_start = doc! {"$dateTrunc": {
+            "date": "$finished_at",
+            "unit": interval.unit(),
+            "startOfWeek": "monday",
+        }};
+        let docs: Vec<Document> = self
             .db
             .collection::<Document>("walk_requests")
-            .insert_one(Document::from(request), None)
+            .aggregate(
+                vec![
+                    doc! {"$match": Document::try_from(self.scoped(query))?},
+                    doc! {"$match": {"finished_at": {"$ne": null}}},
+                    doc! {"$group": {
+                        "_id": {
+                            "period_start": {"$dateToString": {
+                                "date": period_start,
+                                "format": "%Y-%m-%d",
+                            }},
+                            "currency": "$price.currency",
+                        },
+                        "walks": {"$sum": 1},
+                        "distance": {"$sum": {"$ifNull": ["$summary.distance", 0]}},
+                        "millis": {"$sum": {"$ifNull": [
+                            {"$subtract": ["$finished_at", "$started_at"]},
+                            0,
+                        ]}},
+                        "amount": {"$sum": "$price.minor_units"},
+                    }},
+                ],
+                None,
+            )
+            .await?
+            .try_collect()
             .await?;
-        Ok(inserted.inserted_id.to_string())
+        let number = |doc: &Document, key: &str| match doc.get(key) {
+            Some(Bson::Double(n)) => *n,
+            Some(Bson::Int32(n)) => f64::from(*n),
+            Some(Bson::Int64(n)) => *n as f64,
+            _ => 0.0,
+        };
+        let rows = docs
+            .iter()
+            .map(|doc| -> Result<ReportRow, ServiceError> {
+                let group = doc.get_document("_id").map_err(Error::from)?;
+                let period_start =
+                    NaiveDate::from_str(group.get_str("period_start").map_err(Error::from)?)
+                        .map_err(Error::from)?;
+                let amount = group
+                    .get_str("currency")
+                    .ok()
+                    .map(|currency| Money::new(number(doc, "amount") as i64, currency));
+                Ok((
+                    period_start,
+                    number(doc, "walks") as u64,
+                    Meters(number(doc, "distance")),
+                    number(doc, "millis") / 1000.0,
+                    amount,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(collect_buckets(rows))
     }
 
-    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, Error> {
+    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, ServiceError> {
         self.db
             .collection::<WalkRequest>("walk_requests")
             .find_one(
-                doc! {"_id": ObjectId::from_str(id)?},
+                self.by_id(id)?,
                 FindOneOptions::builder()
                     .projection(WalkRequest::projection())
                     .build(),
             )
             .await?
-            .ok_or(Error::msg("walk request not found"))
+            .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))
     }
 
     async fn query_walk_requests(
@@ -229,12 +1172,29 @@ impl Repository for Mongodb {
         query: WalkRequestQuery,
         sort_by: Option<SortBy>,
         pagination: Option<Pagination>,
-    ) -> Result<Vec<WalkRequest>, Error> {
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        self.stream_walk_requests(query, sort_by, pagination)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    async fn stream_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<WalkRequestStream, ServiceError> {
         if query.nearby.is_some() {
-            let mut pipeline = vec![
-                Document::try_from(query)?,
-                doc! { "$project": WalkRequest::projection() },
-            ];
+            let after = query.nearby_after.clone();
+            let mut pipeline = vec![Document::try_from(self.scoped(query))?];
+            if let Some(after) = after {
+                pipeline.push(doc! {"$match": {"$or": [
+                    {"distance": {"$gt": after.distance}},
+                    {"id": {"$gt": after.id}},
+                ]}});
+                pipeline.push(doc! {"$sort": {"distance": 1, "id": 1}});
+            }
             if let Some(pagination) = pagination {
                 pipeline.push(doc! {
                     "$skip": (pagination.page - 1) * pagination.size
@@ -248,22 +1208,22 @@ impl Repository for Mongodb {
                     "$sort": {sort_by.field: if sort_by.order == Order::Asc { 1 } else { - 1} }
                 })
             }
-            return self
+            return Ok(self
                 .db
-                .collection::<WalkRequest>("walk_requests")
+                .collection::<WalkRequest>(FEED_COLLECTION)
                 .aggregate(pipeline, None)
                 .await?
                 .map(|res| match res {
-                    Err(e) => Err(Error::from(e)),
-                    Ok(doc) => from_document::<WalkRequest>(doc).map_err(Error::from),
+                    Err(e) => Err(ServiceError::from(e)),
+                    Ok(doc) => from_document::<WalkRequest>(doc).map_err(ServiceError::from),
                 })
-                .try_collect::<Vec<WalkRequest>>()
-                .await;
+                .boxed_local());
         }
-        self.db
+        Ok(self
+            .db
             .collection::<WalkRequest>("walk_requests")
             .find(
-                Document::try_from(query)?,
+                Document::try_from(self.scoped(query))?,
                 FindOptions::builder()
                     .projection(WalkRequest::projection())
                     .limit(pagination.as_ref().map(|p| p.size))
@@ -278,20 +1238,20 @@ impl Repository for Mongodb {
                     .build(),
             )
             .await?
-            .try_collect::<Vec<WalkRequest>>()
-            .await
-            .map_err(|e| e.into())
+            .map_err(ServiceError::from)
+            .boxed_local())
     }
 
     async fn update_walk_request(
         &self,
         id: &str,
         request: WalkRequestUpdate,
-    ) -> Result<WalkRequest, Error> {
-        self.db
+    ) -> Result<WalkRequest, ServiceError> {
+        let updated: WalkRequest = self
+            .db
             .collection("walk_requests")
             .find_one_and_update(
-                doc! {"_id": ObjectId::from_str(id)?},
+                self.by_id(id)?,
                 Document::from(request),
                 FindOneAndUpdateOptions::builder()
                     .return_document(Some(mongodb::options::ReturnDocument::After))
@@ -299,50 +1259,1585 @@ impl Repository for Mongodb {
                     .build(),
             )
             .await?
-            .ok_or(Error::msg("代遛请求不存在"))
+            .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))?;
+        self.refresh_feed(vec![id_bson(&updated.id)?]).await?;
+        Ok(updated)
     }
 
     async fn update_walk_request_by_query(
         &self,
         query: WalkRequestQuery,
         update: WalkRequestUpdate,
-    ) -> Result<WalkRequest, Error> {
-        self.db
-            .collection("walk_requests")
-            .find_one_and_update(
-                Document::try_from(query)?,
-                Document::from(update),
-                FindOneAndUpdateOptions::builder()
-                    .return_document(Some(mongodb::options::ReturnDocument::After))
-                    .projection(WalkRequest::projection())
-                    .build(),
-            )
-            .await?
-            .ok_or(Error::msg("代遛请求不存在"))
+    ) -> Result<WalkRequest, ServiceError> {
+        let collection = self.db.collection::<WalkRequest>("walk_requests");
+        let filter = Document::try_from(self.scoped(query))?;
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(Some(mongodb::options::ReturnDocument::After))
+            .projection(WalkRequest::projection())
+            .build();
+        let updated = match &self.transaction {
+            Some(transaction) => {
+                collection
+                    .find_one_and_update_with_session(
+                        filter,
+                        Document::from(update),
+                        options,
+                        &mut *transaction.session.lock().await,
+                    )
+                    .await?
+            }
+            None => {
+                collection
+                    .find_one_and_update(filter, Document::from(update), options)
+                    .await?
+            }
+        }
+        .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))?;
+        self.refresh_feed(vec![id_bson(&updated.id)?]).await?;
+        Ok(updated)
     }
 
     async fn update_walk_requests_by_query(
         &self,
         query: WalkRequestQuery,
         update: WalkRequestUpdate,
-    ) -> Result<u64, Error> {
-        Ok(self
-            .db
-            .collection::<Document>("walk_requests")
-            .update_many(Document::try_from(query)?, Document::from(update), None)
+    ) -> Result<u64, ServiceError> {
+        let collection = self.db.collection::<Document>("walk_requests");
+        let filter = Document::try_from(self.scoped(query))?;
+        let options = FindOptions::builder().projection(doc! {"_id": 1}).build();
+        let (matched, modified) = match &self.transaction {
+            Some(transaction) => {
+                let mut session = transaction.session.lock().await;
+                let matched = collection
+                    .find_with_session(filter.clone(), options, &mut session)
+                    .await?
+                    .stream(&mut session)
+                    .try_collect::<Vec<Document>>()
+                    .await?;
+                let modified = collection
+                    .update_many_with_session(filter, Document::from(update), None, &mut session)
+                    .await?
+                    .modified_count;
+                (matched, modified)
+            }
+            None => {
+                let matched = collection
+                    .find(filter.clone(), options)
+                    .await?
+                    .try_collect::<Vec<Document>>()
+                    .await?;
+                let modified = collection
+                    .update_many(filter, Document::from(update), None)
+                    .await?
+                    .modified_count;
+                (matched, modified)
+            }
+        };
+        let ids = matched
+            .into_iter()
+            .filter_map(|d| d.get("_id").cloned())
+            .collect::<Vec<Bson>>();
+        if modified > 0 {
+            self.refresh_feed(ids).await?;
+        }
+        Ok(modified)
+    }
+
+    async fn query_walking_locations(
+        &self,
+        query: WalkingLocationQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError> {
+        self.db
+            .collection::<WalkingLocation>("walking_locations")
+            .find(
+                Document::from(query),
+                FindOptions::builder()
+                    .projection(WalkingLocation::projection())
+                    .sort(doc! {"created_at": 1})
+                    .limit(pagination.as_ref().map(|p| p.size))
+                    .skip(
+                        pagination
+                            .as_ref()
+                            .map(|p| (p.page as u64 - 1) * p.size as u64),
+                    )
+                    .build(),
+            )
             .await?
-            .modified_count)
+            .try_collect::<Vec<WalkingLocation>>()
+            .await
+            .map_err(|e| e.into())
     }
 
     async fn create_walking_location<'a>(
         &self,
         create: WalkingLocationCreate<'a>,
-    ) -> Result<String, Error> {
+    ) -> Result<String, ServiceError> {
         self.db
             .collection("walking_locations")
-            .insert_one(Document::from(create), None)
+            .insert_one(self.with_new_id(Document::from(create)), None)
+            .await
+            .map_err(|e| ServiceError::from(Error::new(e).context("创建Walking定位失败")))
+            .map(|r| id_string(&r.inserted_id))
+    }
+
+    async fn create_walking_locations<'a>(
+        &self,
+        creates: Vec<WalkingLocationCreate<'a>>,
+    ) -> Result<Vec<String>, ServiceError> {
+        if creates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let count = creates.len();
+        let inserted = self
+            .db
+            .collection("walking_locations")
+            .insert_many(
+                creates
+                    .into_iter()
+                    .map(|c| self.with_new_id(Document::from(c))),
+                None,
+            )
+            .await
+            .map_err(|e| ServiceError::from(Error::new(e).context("创建Walking定位失败")))?;
+        Ok((0..count)
+            .filter_map(|i| inserted.inserted_ids.get(&i).map(id_string))
+            .collect())
+    }
+
+    async fn delete_walking_locations(&self, ids: &[String]) -> Result<u64, ServiceError> {
+        let ids = ids
+            .iter()
+            .map(|id| id_bson(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self
+            .db
+            .collection::<Document>("walking_locations")
+            .delete_many(doc! {"_id": {"$in": ids}}, None)
+            .await?
+            .deleted_count)
+    }
+
+    async fn walk_requests_with_tracks_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Document>("walking_locations")
+            .distinct(
+                "walk_request_id",
+                doc! {"created_at": {"$lt": before}},
+                None,
+            )
+            .await?
+            .into_iter()
+            .filter_map(|id| id.as_str().map(str::to_owned))
+            .collect())
+    }
+
+    async fn query_holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError> {
+        self.db
+            .collection::<Holiday>("holidays")
+            .find(doc! {"region": region}, None)
+            .await?
+            .try_collect::<Vec<Holiday>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn upsert_holiday(&self, holiday: Holiday) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Holiday>("holidays")
+            .replace_one(
+                doc! {"region": &holiday.region, "date": holiday.date.to_string()},
+                &holiday,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_holiday(&self, region: &str, date: NaiveDate) -> Result<u64, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Holiday>("holidays")
+            .delete_one(doc! {"region": region, "date": date.to_string()}, None)
+            .await?
+            .deleted_count)
+    }
+
+    async fn walk_requests_active_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let range = doc! {"$gte": from, "$lt": to};
+        self.db
+            .collection::<WalkRequest>("walk_requests")
+            .find(
+                doc! {"$or": [
+                    {"created_at": range.clone()},
+                    {"accepted_at": range.clone()},
+                    {"finished_at": range.clone()},
+                    {"canceled_at": range.clone()},
+                    {"sla_breached_at": range},
+                ]},
+                FindOptions::builder()
+                    .projection(WalkRequest::projection())
+                    .build(),
+            )
+            .await?
+            .try_collect::<Vec<WalkRequest>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn save_daily_metrics(&self, metrics: Vec<DailyMetrics>) -> Result<(), ServiceError> {
+        let collection = self.db.collection::<DailyMetrics>("metrics_daily");
+        for m in metrics {
+            collection
+                .replace_one(
+                    doc! {"region": &m.region, "date": m.date.to_string()},
+                    &m,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn query_daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        self.db
+            .collection::<DailyMetrics>("metrics_daily")
+            .find(
+                doc! {"region": region, "date": {"$gte": from.to_string(), "$lte": to.to_string()}},
+                FindOptions::builder().sort(doc! {"date": 1}).build(),
+            )
+            .await?
+            .try_collect::<Vec<DailyMetrics>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn upsert_application(
+        &self,
+        request_id: &str,
+        applicant_id: &str,
+        state: ApplicationState,
+    ) -> Result<(), ServiceError> {
+        let now = Utc::now();
+        let collection = self.db.collection::<Application>("applications");
+        let filter = doc! {"request_id": request_id, "applicant_id": applicant_id};
+        let update = doc! {
+            "$set": {"state": to_bson(&state)?, "updated_at": now},
+            "$setOnInsert": {"applied_at": now},
+        };
+        let options = UpdateOptions::builder().upsert(true).build();
+        match &self.transaction {
+            Some(transaction) => {
+                collection
+                    .update_one_with_session(
+                        filter,
+                        update,
+                        options,
+                        &mut *transaction.session.lock().await,
+                    )
+                    .await?
+            }
+            None => collection.update_one(filter, update, options).await?,
+        };
+        Ok(())
+    }
+
+    async fn query_applications(
+        &self,
+        applicant_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError> {
+        // The times are stored as BSON dates, `Application` reads strings.
+        let date = |field: &str| {
+            doc! {"$dateToString": {"date": format!("${}", field), "format": "%Y-%m-%dT%H:%M:%S.%LZ"}}
+        };
+        let docs: Vec<Document> = self
+            .db
+            .collection::<Document>("applications")
+            .aggregate(
+                vec![
+                    doc! {"$match": {"applicant_id": applicant_id}},
+                    doc! {"$sort": {"applied_at": -1}},
+                    doc! {"$skip": (pagination.page - 1) * pagination.size},
+                    doc! {"$limit": pagination.size},
+                    doc! {"$project": {
+                        "_id": 0,
+                        "request_id": 1,
+                        "applicant_id": 1,
+                        "state": 1,
+                        "applied_at": date("applied_at"),
+                        "updated_at": date("updated_at"),
+                    }},
+                ],
+                None,
+            )
+            .await?
+            .try_collect()
+            .await?;
+        docs.into_iter()
+            .map(|doc| from_document::<Application>(doc).map_err(ServiceError::from))
+            .collect()
+    }
+
+    async fn save_saga(&self, saga: &BookingSaga) -> Result<(), ServiceError> {
+        self.db
+            .collection::<BookingSaga>("sagas")
+            .replace_one(
+                doc! {"id": &saga.id},
+                saga,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Tenant>("tenants")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn upsert_tenant(&self, tenant: Tenant) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Tenant>("tenants")
+            .replace_one(
+                doc! {"id": &tenant.id},
+                &tenant,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<WalkerCapabilities>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<WalkerCapabilities>("walker_capabilities")
+            .find_one(doc! {"user_id": user_id}, None)
+            .await?)
+    }
+
+    async fn upsert_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Document>("walker_capabilities")
+            .update_one(
+                doc! {"user_id": user_id},
+                doc! {"$set": {
+                    "max_dogs": capabilities.max_dogs,
+                    "large_breeds": capabilities.large_breeds,
+                    "puppies": capabilities.puppies,
+                    "accepted_sizes": to_bson(&capabilities.accepted_sizes)?,
+                    "excluded_breeds": capabilities.excluded_breeds,
+                }},
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn archive_walk_requests(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        let walk_requests = self.db.collection::<Document>("walk_requests");
+        let archive = self.db.collection::<Document>(ARCHIVE_COLLECTION);
+        let mut ended = walk_requests
+            .find(
+                doc! {"$or": [
+                    {"finished_at": {"$lt": before}},
+                    {"canceled_at": {"$lt": before}},
+                ]},
+                None,
+            )
+            .await?;
+        let mut archived = 0;
+        while let Some(request) = ended.try_next().await? {
+            let id = request.get("_id").cloned().unwrap_or(Bson::Null);
+            // Copied before it is deleted, an interrupted run leaves the
+            // request in both and the next run replaces the copy.
+            archive
+                .replace_one(
+                    doc! {"_id": id.clone()},
+                    request,
+                    ReplaceOptions::builder().upsert(true).build(),
+                )
+                .await?;
+            walk_requests.delete_one(doc! {"_id": id}, None).await?;
+            archived += 1;
+        }
+        Ok(archived)
+    }
+
+    async fn query_archived_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        self.db
+            .collection::<WalkRequest>(ARCHIVE_COLLECTION)
+            .find(
+                Document::try_from(self.scoped(query))?,
+                FindOptions::builder()
+                    .projection(WalkRequest::projection())
+                    .limit(pagination.as_ref().map(|p| p.size))
+                    .skip(
+                        pagination
+                            .as_ref()
+                            .map(|p| (p.page as u64 - 1) * p.size as u64),
+                    )
+                    .sort(doc! {"created_at": -1})
+                    .build(),
+            )
+            .await?
+            .try_collect::<Vec<WalkRequest>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn purge(
+        &self,
+        class: DataClass,
+        before: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<u64, ServiceError> {
+        let collection = match class {
+            DataClass::Tracks => self.db.collection::<Document>("walking_locations"),
+            // Not stored by this service.
+            DataClass::Chat | DataClass::Photos | DataClass::Audit => return Ok(0),
+        };
+        let filter = doc! {"created_at": {"$lt": before}};
+        if dry_run {
+            return Ok(collection.count_documents(filter, None).await?);
+        }
+        Ok(collection.delete_many(filter, None).await?.deleted_count)
+    }
+
+    async fn imported_id(&self, key: &str) -> Result<Option<String>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Document>("legacy_imports")
+            .find_one(doc! {"_id": key}, None)
+            .await?
+            .and_then(|d| d.get_str("id").ok().map(str::to_owned)))
+    }
+
+    async fn record_import(&self, key: &str, id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Document>("legacy_imports")
+            .replace_one(
+                doc! {"_id": key},
+                doc! {"_id": key, "id": id, "imported_at": Utc::now()},
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<u64, ServiceError> {
+        let counter = self
+            .db
+            .collection::<Document>("counters")
+            .find_one_and_update(
+                doc! {"_id": name},
+                doc! {"$inc": {"value": 1i64}},
+                FindOneAndUpdateOptions::builder()
+                    .upsert(true)
+                    .return_document(Some(mongodb::options::ReturnDocument::After))
+                    .build(),
+            )
+            .await?
+            .ok_or_else(|| ServiceError::Internal(anyhow::anyhow!("计数器不存在")))?;
+        Ok(counter.get_i64("value").map_err(anyhow::Error::from)? as u64)
+    }
+
+    async fn query_fitness_tokens(&self, user_id: &str) -> Result<Vec<FitnessToken>, ServiceError> {
+        self.db
+            .collection::<FitnessToken>("fitness_tokens")
+            .find(
+                doc! {"user_id": user_id},
+                FindOptions::builder().sort(doc! {"provider": 1}).build(),
+            )
+            .await?
+            .try_collect::<Vec<FitnessToken>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn upsert_fitness_token(&self, token: FitnessToken) -> Result<(), ServiceError> {
+        self.db
+            .collection::<FitnessToken>("fitness_tokens")
+            .replace_one(
+                doc! {"user_id": &token.user_id, "provider": &token.provider},
+                &token,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_fitness_token(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Document>("fitness_tokens")
+            .delete_one(doc! {"user_id": user_id, "provider": provider}, None)
+            .await?
+            .deleted_count)
+    }
+
+    async fn query_favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError> {
+        self.db
+            .collection::<FavoriteWalker>("favorite_walkers")
+            .find(
+                doc! {"owner_id": owner_id},
+                FindOptions::builder().sort(doc! {"created_at": -1}).build(),
+            )
+            .await?
+            .try_collect::<Vec<FavoriteWalker>>()
             .await
-            .map_err(|e| Error::new(e).context("创建Walking定位失败"))
-            .map(|r| r.inserted_id.to_string())
+            .map_err(|e| e.into())
+    }
+
+    async fn upsert_favorite_walker(&self, favorite: FavoriteWalker) -> Result<(), ServiceError> {
+        self.db
+            .collection::<FavoriteWalker>("favorite_walkers")
+            .replace_one(
+                doc! {"owner_id": &favorite.owner_id, "walker_id": &favorite.walker_id},
+                &favorite,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Document>("favorite_walkers")
+            .delete_one(doc! {"owner_id": owner_id, "walker_id": walker_id}, None)
+            .await?
+            .deleted_count)
+    }
+
+    async fn save_fitness_export(&self, export: &FitnessExport) -> Result<(), ServiceError> {
+        self.db
+            .collection::<FitnessExport>("fitness_exports")
+            .replace_one(
+                doc! {"id": &export.id},
+                export,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn due_fitness_exports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<FitnessExport>, ServiceError> {
+        // Times are stored as serde strings, the due ones are picked here.
+        let mut exports: Vec<FitnessExport> = self
+            .db
+            .collection::<FitnessExport>("fitness_exports")
+            .find(doc! {"status": to_bson(&ExportStatus::Pending)?}, None)
+            .await?
+            .try_collect()
+            .await?;
+        exports.retain(|e| e.next_attempt_at <= now);
+        exports.sort_by_key(|e| e.next_attempt_at);
+        Ok(exports)
+    }
+
+    async fn save_payment(&self, payment: &Payment) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Payment>("payments")
+            .replace_one(
+                doc! {"id": &payment.id},
+                payment,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_payment(&self, request_id: &str) -> Result<Option<Payment>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Payment>("payments")
+            .find_one(doc! {"request_id": request_id}, None)
+            .await?)
+    }
+
+    async fn save_receipt(&self, receipt: &Receipt) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Receipt>("receipts")
+            .replace_one(
+                doc! {"id": &receipt.id},
+                receipt,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_receipt(&self, request_id: &str) -> Result<Option<Receipt>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Receipt>("receipts")
+            .find_one(doc! {"request_id": request_id}, None)
+            .await?)
+    }
+
+    async fn query_receipts(
+        &self,
+        owner_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        let mut receipts: Vec<Receipt> = self
+            .db
+            .collection::<Receipt>("receipts")
+            .find(doc! {"owner_id": owner_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        receipts.retain(|r| r.issued_at >= from && r.issued_at < to);
+        receipts.sort_by_key(|r| r.issued_at);
+        Ok(receipts)
+    }
+
+    async fn save_payout(&self, payout: &Payout) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Payout>("payouts")
+            .replace_one(
+                doc! {"id": &payout.id},
+                payout,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn query_payouts(
+        &self,
+        walker_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payout>, ServiceError> {
+        let mut payouts: Vec<Payout> = self
+            .db
+            .collection::<Payout>("payouts")
+            .find(doc! {"walker_id": walker_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        payouts.retain(|p| p.created_at >= from && p.created_at < to);
+        payouts.sort_by_key(|p| p.created_at);
+        Ok(payouts)
+    }
+
+    async fn get_spending_cap(&self, owner_id: &str) -> Result<Option<SpendingCap>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<SpendingCap>("spending_caps")
+            .find_one(doc! {"owner_id": owner_id}, None)
+            .await?)
+    }
+
+    async fn save_spending_cap(&self, cap: &SpendingCap) -> Result<(), ServiceError> {
+        self.db
+            .collection::<SpendingCap>("spending_caps")
+            .replace_one(
+                doc! {"owner_id": &cap.owner_id},
+                cap,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<SpendingCap>("spending_caps")
+            .delete_one(doc! {"owner_id": owner_id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_funnel_stage(
+        &self,
+        request_id: &str,
+        stage: FunnelStage,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Document>("walk_request_funnels")
+            .update_one(
+                doc! {"request_id": request_id},
+                doc! {"$min": {stage.field(): at}},
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_impressions(
+        &self,
+        request_ids: &[String],
+        weight: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        let collection = self.db.collection::<Document>("walk_request_funnels");
+        for request_id in request_ids {
+            collection
+                .update_one(
+                    doc! {"request_id": request_id},
+                    doc! {
+                        "$min": {FunnelStage::Viewed.field(): at},
+                        "$inc": {"impressions": weight as i64},
+                    },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn query_funnel_marks(
+        &self,
+        request_ids: &[String],
+    ) -> Result<Vec<FunnelMarks>, ServiceError> {
+        let date = |field: &str| {
+            doc! {"$dateToString": {"date": format!("${}", field), "format": "%Y-%m-%dT%H:%M:%S.%LZ"}}
+        };
+        let docs: Vec<Document> = self
+            .db
+            .collection::<Document>("walk_request_funnels")
+            .aggregate(
+                vec![
+                    doc! {"$match": {"request_id": {"$in": request_ids.to_vec()}}},
+                    doc! {"$project": {
+                        "_id": 0,
+                        "request_id": 1,
+                        "first_viewed_at": date("first_viewed_at"),
+                        "first_applied_at": date("first_applied_at"),
+                        "impressions": {"$ifNull": ["$impressions", 0]},
+                    }},
+                ],
+                None,
+            )
+            .await?
+            .try_collect()
+            .await?;
+        docs.into_iter()
+            .map(|doc| from_document::<FunnelMarks>(doc).map_err(ServiceError::from))
+            .collect()
+    }
+
+    async fn save_device_session(&self, session: &DeviceSession) -> Result<(), ServiceError> {
+        self.db
+            .collection::<DeviceSession>("device_sessions")
+            .replace_one(
+                doc! {"id": &session.id},
+                session,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn query_device_sessions(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<DeviceSession>, ServiceError> {
+        let mut sessions: Vec<DeviceSession> = self
+            .db
+            .collection::<DeviceSession>("device_sessions")
+            .find(doc! {"user_id": user_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        sessions.sort_by_key(|s| s.created_at);
+        Ok(sessions)
+    }
+
+    async fn record_strike(&self, strike: &Strike) -> Result<bool, ServiceError> {
+        let result = self
+            .db
+            .collection::<Strike>("walker_strikes")
+            .update_one(
+                doc! {"walker_id": &strike.walker_id, "request_id": &strike.request_id},
+                doc! {"$setOnInsert": to_bson(strike)?},
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(result.upserted_id.is_some())
+    }
+
+    async fn query_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError> {
+        let mut strikes: Vec<Strike> = self
+            .db
+            .collection::<Strike>("walker_strikes")
+            .find(doc! {"walker_id": walker_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        strikes.sort_by(|a, b| b.recorded_at.cmp(&a.recorded_at));
+        Ok(strikes)
+    }
+
+    async fn update_reputation(
+        &self,
+        user_id: &str,
+        update: ReputationUpdate,
+    ) -> Result<(), ServiceError> {
+        let mut change = doc! {
+            "$inc": {"completed_walks": update.completed_walks, "strikes": update.strikes},
+        };
+        if let Some(until) = update.banned_until {
+            change.insert("$set", doc! {"banned_until": until});
+        }
+        self.db
+            .collection::<Document>("user_reputations")
+            .update_one(
+                doc! {"user_id": user_id},
+                change,
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_reputation(&self, user_id: &str) -> Result<Option<Reputation>, ServiceError> {
+        let Some(reputation) = self
+            .db
+            .collection::<Document>("user_reputations")
+            .find_one(doc! {"user_id": user_id}, None)
+            .await?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Reputation {
+            user_id: user_id.to_owned(),
+            completed_walks: reputation.get_i64("completed_walks").unwrap_or_default(),
+            strikes: reputation.get_i64("strikes").unwrap_or_default(),
+            banned_until: reputation
+                .get_datetime("banned_until")
+                .ok()
+                .map(|until| until.to_chrono()),
+        }))
+    }
+
+    async fn save_feature_flag(&self, flag: &FeatureFlag) -> Result<(), ServiceError> {
+        self.db
+            .collection::<FeatureFlag>("feature_flags")
+            .replace_one(
+                doc! {"feature": flag.feature.as_str()},
+                flag,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn query_feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<FeatureFlag>("feature_flags")
+            .find(doc! {}, None)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn save_offer(&self, offer: &Offer) -> Result<(), ServiceError> {
+        let collection = self.db.collection::<Offer>("offers");
+        let filter = doc! {"id": &offer.id};
+        let options = ReplaceOptions::builder().upsert(true).build();
+        match &self.transaction {
+            Some(transaction) => {
+                collection
+                    .replace_one_with_session(
+                        filter,
+                        offer,
+                        options,
+                        &mut *transaction.session.lock().await,
+                    )
+                    .await?
+            }
+            None => collection.replace_one(filter, offer, options).await?,
+        };
+        Ok(())
+    }
+
+    async fn get_offer(&self, id: &str) -> Result<Option<Offer>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<Offer>("offers")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn query_offers(
+        &self,
+        request_id: &str,
+        walker_id: Option<&str>,
+    ) -> Result<Vec<Offer>, ServiceError> {
+        let mut filter = doc! {"request_id": request_id};
+        if let Some(walker_id) = walker_id {
+            filter.insert("walker_id", walker_id);
+        }
+        let mut offers: Vec<Offer> = self
+            .db
+            .collection::<Offer>("offers")
+            .find(filter, None)
+            .await?
+            .try_collect()
+            .await?;
+        offers.sort_by_key(|o| o.created_at);
+        Ok(offers)
+    }
+
+    async fn save_outbox_message(&self, message: &OutboxMessage) -> Result<(), ServiceError> {
+        let collection = self.db.collection::<OutboxMessage>("outbox");
+        let filter = doc! {"id": &message.id};
+        let options = ReplaceOptions::builder().upsert(true).build();
+        match &self.transaction {
+            Some(transaction) => {
+                collection
+                    .replace_one_with_session(
+                        filter,
+                        message,
+                        options,
+                        &mut *transaction.session.lock().await,
+                    )
+                    .await?
+            }
+            None => collection.replace_one(filter, message, options).await?,
+        };
+        Ok(())
+    }
+
+    async fn get_outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<OutboxMessage>("outbox")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<OutboxMessage>("outbox")
+            .delete_one(doc! {"id": id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn due_outbox_messages(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let pending: Vec<OutboxMessage> = self
+            .db
+            .collection::<OutboxMessage>("outbox")
+            .find(doc! {"status": "pending"}, None)
+            .await?
+            .try_collect()
+            .await?;
+        let mut due: Vec<OutboxMessage> = pending
+            .into_iter()
+            .filter(|m| m.next_attempt_at <= now)
+            .collect();
+        due.sort_by_key(|m| m.created_at);
+        due.truncate(limit.max(0) as usize);
+        Ok(due)
+    }
+
+    async fn dead_outbox_messages(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let mut dead: Vec<OutboxMessage> = self
+            .db
+            .collection::<OutboxMessage>("outbox")
+            .find(doc! {"status": "dead_lettered"}, None)
+            .await?
+            .try_collect()
+            .await?;
+        dead.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+        Ok(dead
+            .into_iter()
+            .skip(((pagination.page - 1) * pagination.size).max(0) as usize)
+            .take(pagination.size.max(0) as usize)
+            .collect())
+    }
+
+    async fn save_start_reminders(&self, reminders: &[StartReminder]) -> Result<(), ServiceError> {
+        if reminders.is_empty() {
+            return Ok(());
+        }
+        self.db
+            .collection::<StartReminder>("start_reminders")
+            .insert_many(reminders, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn due_start_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<StartReminder>, ServiceError> {
+        let pending: Vec<StartReminder> = self
+            .db
+            .collection::<StartReminder>("start_reminders")
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?;
+        let mut due: Vec<StartReminder> =
+            pending.into_iter().filter(|r| r.remind_at <= now).collect();
+        due.sort_by_key(|r| r.remind_at);
+        due.truncate(limit.max(0) as usize);
+        Ok(due)
+    }
+
+    async fn delete_start_reminder(&self, id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<StartReminder>("start_reminders")
+            .delete_one(doc! {"id": id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_start_reminders(&self, request_id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<StartReminder>("start_reminders")
+            .delete_many(doc! {"request_id": request_id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_walk_schedule(&self, schedule: &WalkSchedule) -> Result<(), ServiceError> {
+        self.db
+            .collection::<WalkSchedule>("walk_schedules")
+            .replace_one(
+                doc! {"id": &schedule.id},
+                schedule,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_walk_schedule(&self, id: &str) -> Result<Option<WalkSchedule>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<WalkSchedule>("walk_schedules")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn query_walk_schedules(
+        &self,
+        owner_id: Option<&str>,
+    ) -> Result<Vec<WalkSchedule>, ServiceError> {
+        let mut filter = doc! {"canceled_at": null};
+        if let Some(owner_id) = owner_id {
+            filter.insert("owner_id", owner_id);
+        }
+        let mut schedules: Vec<WalkSchedule> = self
+            .db
+            .collection::<WalkSchedule>("walk_schedules")
+            .find(filter, None)
+            .await?
+            .try_collect()
+            .await?;
+        schedules.sort_by_key(|s| s.created_at);
+        Ok(schedules)
+    }
+
+    async fn save_walk_request_template(
+        &self,
+        template: &WalkRequestTemplate,
+    ) -> Result<(), ServiceError> {
+        self.db
+            .collection::<WalkRequestTemplate>("walk_request_templates")
+            .replace_one(
+                doc! {"id": &template.id},
+                template,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_walk_request_template(
+        &self,
+        id: &str,
+    ) -> Result<Option<WalkRequestTemplate>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<WalkRequestTemplate>("walk_request_templates")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn query_walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError> {
+        let mut templates: Vec<WalkRequestTemplate> = self
+            .db
+            .collection::<WalkRequestTemplate>("walk_request_templates")
+            .find(doc! {"owner_id": owner_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        templates.sort_by_key(|t| t.created_at);
+        Ok(templates)
+    }
+
+    async fn delete_walk_request_template(&self, id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<WalkRequestTemplate>("walk_request_templates")
+            .delete_one(doc! {"id": id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_saved_search(&self, search: &SavedSearch) -> Result<(), ServiceError> {
+        self.db
+            .collection::<SavedSearch>("saved_searches")
+            .replace_one(
+                doc! {"id": &search.id},
+                search,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<SavedSearch>("saved_searches")
+            .find_one(doc! {"id": id}, None)
+            .await?)
+    }
+
+    async fn query_saved_searches(
+        &self,
+        walker_id: &str,
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        self.db
+            .collection::<SavedSearch>("saved_searches")
+            .find(
+                doc! {"walker_id": walker_id},
+                FindOptions::builder().sort(doc! {"created_at": 1}).build(),
+            )
+            .await?
+            .try_collect::<Vec<SavedSearch>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn query_notifying_saved_searches(
+        &self,
+        tags: &[String],
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        // No tag outside of `tags`, searches without tags included.
+        self.db
+            .collection::<SavedSearch>("saved_searches")
+            .find(
+                doc! {"notify": true, "tags": {"$not": {"$elemMatch": {"$nin": tags}}}},
+                FindOptions::builder().sort(doc! {"created_at": 1}).build(),
+            )
+            .await?
+            .try_collect::<Vec<SavedSearch>>()
+            .await
+            .map_err(|e| e.into())
+    }
+
+    async fn delete_saved_search(&self, id: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<SavedSearch>("saved_searches")
+            .delete_one(doc! {"id": id}, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_backfill_run(&self, name: &str) -> Result<Option<BackfillRun>, ServiceError> {
+        Ok(self
+            .db
+            .collection::<BackfillRun>("backfill_runs")
+            .find_one(doc! {"name": name}, None)
+            .await?)
+    }
+
+    async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), ServiceError> {
+        self.db
+            .collection::<BackfillRun>("backfill_runs")
+            .replace_one(
+                doc! {"name": &run.name},
+                run,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn record_event(&self, event: &WalkRequestEvent) -> Result<(), ServiceError> {
+        self.db
+            .collection::<WalkRequestEvent>("events")
+            .insert_one(event, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn query_events(&self, request_id: &str) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        let mut events: Vec<WalkRequestEvent> = self
+            .db
+            .collection::<WalkRequestEvent>("events")
+            .find(doc! {"request_id": request_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        events.sort_by_key(|e| e.occurred_at);
+        Ok(events)
+    }
+
+    async fn create_message(&self, message: &Message, recipient: &str) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Message>("messages")
+            .insert_one(message, None)
+            .await?;
+        self.db
+            .collection::<Document>("message_reads")
+            .update_one(
+                doc! {"request_id": &message.request_id, "user_id": recipient},
+                doc! {"$inc": {"unread": 1i64}},
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn query_messages(
+        &self,
+        request_id: &str,
+        after: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Message>, ServiceError> {
+        let mut messages: Vec<Message> = self
+            .db
+            .collection::<Message>("messages")
+            .find(doc! {"request_id": request_id}, None)
+            .await?
+            .try_collect()
+            .await?;
+        messages.retain(|m| after.map_or(true, |after| m.sent_at > after));
+        messages.sort_by(|a, b| (a.sent_at, &a.id).cmp(&(b.sent_at, &b.id)));
+        messages.truncate(limit.max(0) as usize);
+        Ok(messages)
+    }
+
+    async fn mark_messages_read(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        self.db
+            .collection::<Document>("message_reads")
+            .update_one(
+                doc! {"request_id": request_id, "user_id": user_id},
+                doc! {"$set": {"unread": 0i64}},
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn unread_messages(
+        &self,
+        user_id: &str,
+        request_ids: &[String],
+    ) -> Result<HashMap<String, u64>, ServiceError> {
+        let reads: Vec<Document> = self
+            .db
+            .collection::<Document>("message_reads")
+            .find(
+                doc! {"user_id": user_id, "request_id": {"$in": request_ids}, "unread": {"$gt": 0}},
+                None,
+            )
+            .await?
+            .try_collect()
+            .await?;
+        Ok(reads
+            .iter()
+            .filter_map(|r| {
+                let request_id = r.get_str("request_id").ok()?;
+                let unread = r.get_i64("unread").ok()?;
+                Some((request_id.to_owned(), unread as u64))
+            })
+            .collect())
+    }
+
+    async fn record_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        month: &str,
+        rejected: bool,
+    ) -> Result<(), ServiceError> {
+        let (counted, other) = if rejected {
+            ("rejected", "calls")
+        } else {
+            ("calls", "rejected")
+        };
+        self.db
+            .collection::<Document>("api_usage")
+            .update_one(
+                doc! {"api_key": api_key, "endpoint": endpoint, "month": month},
+                doc! {
+                    "$inc": {counted: 1i64},
+                    "$setOnInsert": {other: 0i64},
+                },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn query_api_usage(
+        &self,
+        month: &str,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UsageRecord>, ServiceError> {
+        let mut filter = doc! {"month": month};
+        if let Some(api_key) = api_key {
+            filter.insert("api_key", api_key);
+        }
+        Ok(self
+            .db
+            .collection::<UsageRecord>("api_usage")
+            .find(
+                filter,
+                FindOptions::builder()
+                    .projection(doc! {"_id": 0})
+                    .sort(doc! {"api_key": 1, "endpoint": 1})
+                    .build(),
+            )
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn query_stuck_sagas(
+        &self,
+        updated_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError> {
+        // Sagas are stored through serde, `updated_at` is a string which
+        // doesn't compare with dates in queries.
+        let sagas: Vec<BookingSaga> = self
+            .db
+            .collection::<BookingSaga>("sagas")
+            .find(
+                doc! {"status": {"$in": [
+                    to_bson(&SagaStatus::Running)?,
+                    to_bson(&SagaStatus::Failed)?,
+                ]}},
+                None,
+            )
+            .await?
+            .try_collect()
+            .await?;
+        let mut stuck: Vec<BookingSaga> = sagas
+            .into_iter()
+            .filter(|s| s.status == SagaStatus::Failed || s.updated_at < updated_before)
+            .collect();
+        stuck.sort_by_key(|s| s.updated_at);
+        stuck.truncate(limit.max(0) as usize);
+        Ok(stuck)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::entities::WalkRequestStatus;
+    use chrono::TimeZone;
+    use proptest::prelude::*;
+
+    const FIELDS: [&str; 4] = ["accepted_by", "created_by", "canceled_at", "dogs.id"];
+
+    fn any_operand() -> impl Strategy<Value = Operand> {
+        prop_oneof![
+            "[a-z0-9]{1,8}".prop_map(Operand::Text),
+            any::<i64>().prop_map(Operand::Int),
+            any::<bool>().prop_map(Operand::Bool),
+            (0i64..4_000_000_000).prop_map(|s| Operand::Time(Utc.timestamp_opt(s, 0).unwrap())),
+            Just(Operand::Null),
+        ]
+    }
+
+    fn any_filter() -> impl Strategy<Value = Filter> {
+        let bound = || proptest::option::of(any_operand());
+        prop_oneof![
+            any_operand().prop_map(Filter::Eq),
+            any_operand().prop_map(Filter::Ne),
+            proptest::collection::vec(any_operand(), 0..4).prop_map(Filter::In),
+            proptest::collection::vec(any_operand(), 0..4).prop_map(Filter::All),
+            any::<bool>().prop_map(Filter::IsNull),
+            (bound(), bound(), bound(), bound()).prop_map(|(gt, gte, lt, lte)| Filter::Range {
+                gt,
+                gte,
+                lt,
+                lte
+            }),
+        ]
+    }
+
+    fn any_conditions() -> impl Strategy<Value = Vec<Condition>> {
+        proptest::collection::vec(
+            (proptest::sample::select(FIELDS.to_vec()), any_filter())
+                .prop_map(|(field, filter)| Condition::new(field, filter)),
+            0..8,
+        )
+    }
+
+    /// Whether `query` matches `value` with `op` on `field`, in the field's
+    /// operator document or under `$and`.
+    fn has_operator(query: &Document, field: &str, op: &str, value: &Bson) -> bool {
+        let in_field = |document: &Document| {
+            document
+                .get_document(field)
+                .map_or(false, |ops| ops.get(op) == Some(value))
+        };
+        in_field(query)
+            || query.get_array("$and").map_or(false, |and| {
+                and.iter().filter_map(Bson::as_document).any(in_field)
+            })
+    }
+
+    fn operator_count(query: &Document) -> usize {
+        query
+            .iter()
+            .map(|(key, value)| match (key.as_str(), value) {
+                ("$and", Bson::Array(items)) => items
+                    .iter()
+                    .filter_map(Bson::as_document)
+                    .map(operator_count)
+                    .sum(),
+                (_, Bson::Document(ops)) => ops.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    proptest! {
+        #[test]
+        fn translate_keeps_every_operator(conditions in any_conditions()) {
+            let query = translate(&conditions).unwrap();
+            let mut expected = 0;
+            for condition in &conditions {
+                for (op, value) in operators(condition.filter.clone(), false).unwrap() {
+                    prop_assert!(has_operator(&query, &condition.field, op, &value));
+                    expected += 1;
+                }
+            }
+            prop_assert_eq!(operator_count(&query), expected);
+        }
+
+        #[test]
+        fn geo_near_wraps_the_filter(
+            conditions in any_conditions(),
+            longitude in -180.0..180.0f64,
+            latitude in -90.0..90.0f64,
+            max_distance in 0.0..50_000.0f64,
+        ) {
+            let plain = translate(&conditions).unwrap();
+            prop_assert_eq!(geo_near(&conditions, plain.clone()), plain);
+            let mut near = conditions.clone();
+            near.push(Condition::new(
+                "location",
+                Filter::GeoNear { longitude, latitude, max_distance, min_distance: None },
+            ));
+            let query = translate(&near).unwrap();
+            // Feed documents hold dates as strings.
+            prop_assert!(!format!("{:?}", query).contains("DateTime"));
+            let stage = geo_near(&near, query.clone());
+            let stage = stage.get_document("$geoNear").unwrap();
+            prop_assert_eq!(stage.get_document("query").unwrap(), &query);
+            prop_assert_eq!(stage.get_f64("maxDistance").unwrap(), max_distance);
+        }
+    }
+
+    #[test]
+    fn accepted_by_filters_are_combined() {
+        let query = Document::try_from(WalkRequestQuery {
+            accepted_by_neq: Some("walker".to_owned()),
+            accepted_by_is_null: Some(false),
+            include_deleted: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            query,
+            doc! {
+                "accepted_by": {"$ne": "walker"},
+                "$and": [{"accepted_by": {"$ne": null}}],
+            }
+        );
+    }
+
+    #[test]
+    fn status_keeps_the_is_null_filters() {
+        let query = Document::try_from(WalkRequestQuery {
+            status: Some(WalkRequestStatus::Canceled),
+            canceled_at_is_null: Some(true),
+            include_deleted: true,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            query,
+            doc! {
+                "canceled_at": {"$eq": null},
+                "$and": [{"canceled_at": {"$ne": null}}],
+            }
+        );
     }
 }
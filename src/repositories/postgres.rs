@@ -0,0 +1,2292 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{channel::mpsc, lock::Mutex, SinkExt, StreamExt};
+use little_walk_dog::core::entities::Dog;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::types::Json;
+use sqlx::{QueryBuilder, Row};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::core::backfill::BackfillRun;
+use crate::core::entities::{
+    Application, ApplicationState, PoolWalker, WalkRequest, WalkingLocation,
+};
+use crate::core::error::ServiceError;
+use crate::core::events::WalkRequestEvent;
+use crate::core::favorite::FavoriteWalker;
+use crate::core::feature_flags::FeatureFlag;
+use crate::core::fitness::{ExportStatus, FitnessExport, FitnessToken};
+use crate::core::geo::WalkSummary;
+use crate::core::holiday::Holiday;
+use crate::core::message::Message;
+use crate::core::metrics::{DailyMetrics, FunnelMarks, FunnelStage};
+use crate::core::offer::Offer;
+use crate::core::outbox::{OutboxMessage, OutboxStatus};
+use crate::core::payment::Payment;
+use crate::core::receipt::{Payout, Receipt};
+use crate::core::reminder::StartReminder;
+use crate::core::report::{collect_buckets, ReportBucket, ReportInterval, ReportRow};
+use crate::core::repository::{Order, Pagination, Repository, SortBy, WalkingLocationCreate};
+use crate::core::repository::{
+    WalkRequestCreate, WalkRequestQuery, WalkRequestStream, WalkRequestUpdate, WalkingLocationQuery,
+};
+use crate::core::reputation::{Reputation, ReputationUpdate};
+use crate::core::retention::DataClass;
+use crate::core::saga::{BookingSaga, SagaStatus};
+use crate::core::saved_search::SavedSearch;
+use crate::core::schedule::WalkSchedule;
+use crate::core::session::DeviceSession;
+use crate::core::spending::SpendingCap;
+use crate::core::strike::Strike;
+use crate::core::template::WalkRequestTemplate;
+use crate::core::tenant::Tenant;
+use crate::core::units::{Meters, Money};
+use crate::core::usage::UsageRecord;
+use crate::core::walker_capabilities::{DogRequirements, WalkerCapabilities};
+
+const WALK_REQUEST_COLUMNS: &str = "id::TEXT AS id, dogs, should_start_after, \
+    should_start_before, should_end_after, should_end_before, latitude, longitude, timezone, \
+    region, tenant_id, reference_code, tags, max_applicants, apply_before, requires_large_breed, requires_puppy, created_by, accepted_by, \
+    accepted_at, canceled_at, canceled_by, cancellation_reason, cancel_requested_at, started_at, \
+    finished_at, sla_breached_at, expired_at, track_visibility, track_archived_at, \
+    track_downsampled_at, summary, acceptances, dismissed_applicants, deleted_at, max_radius, \
+    geofence_violated_at, verification_waived_at, start_received_at, finish_received_at, \
+    clock_skew_detected_at, invited_walker_id, invitation_expires_at, \
+    price_minor_units, price_currency, max_walkers, pool_walkers, version, created_at, updated_at";
+
+const MAKE_POINT: &str = "ST_SetSRID(ST_MakePoint(";
+
+impl From<sqlx::Error> for ServiceError {
+    fn from(e: sqlx::Error) -> Self {
+        ServiceError::internal(e)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for ServiceError {
+    fn from(e: sqlx::migrate::MigrateError) -> Self {
+        ServiceError::internal(e)
+    }
+}
+
+/// `text` matched literally by `LIKE`.
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn parse_id(id: &str) -> Result<i64, ServiceError> {
+    id.parse()
+        .map_err(|_| ServiceError::Validation("无效的ID".to_owned()))
+}
+
+/// Unit enums are stored by their serde name.
+fn enum_name<T: Serialize>(value: T) -> Result<String, ServiceError> {
+    match serde_json::to_value(value).map_err(anyhow::Error::from)? {
+        Value::String(name) => Ok(name),
+        other => Err(anyhow::anyhow!("unexpected enum value: {}", other).into()),
+    }
+}
+
+fn from_enum_name<T: DeserializeOwned>(name: String) -> Result<T, ServiceError> {
+    Ok(serde_json::from_value(Value::String(name)).map_err(anyhow::Error::from)?)
+}
+
+fn walk_request(row: &PgRow) -> Result<WalkRequest, ServiceError> {
+    let dogs: Json<Vec<Dog>> = row.try_get("dogs")?;
+    let mut request = WalkRequest {
+        id: row.try_get("id")?,
+        dogs: dogs.0,
+        should_start_after: row.try_get("should_start_after")?,
+        should_start_before: row.try_get("should_start_before")?,
+        should_end_after: row.try_get("should_end_after")?,
+        should_end_before: row.try_get("should_end_before")?,
+        latitude: row.try_get("latitude")?,
+        longitude: row.try_get("longitude")?,
+        timezone: row.try_get("timezone")?,
+        region: row.try_get("region")?,
+        tenant_id: row.try_get("tenant_id")?,
+        reference_code: row.try_get("reference_code")?,
+        distance: row
+            .try_get::<Option<f64>, _>("distance")
+            .ok()
+            .flatten()
+            .map(Meters),
+        canceled_at: row.try_get("canceled_at")?,
+        canceled_by: row.try_get("canceled_by")?,
+        cancellation_reason: row.try_get("cancellation_reason")?,
+        cancel_requested_at: row.try_get("cancel_requested_at")?,
+        accepted_by: row.try_get("accepted_by")?,
+        accepted_at: row.try_get("accepted_at")?,
+        started_at: row.try_get("started_at")?,
+        finished_at: row.try_get("finished_at")?,
+        created_by: row.try_get("created_by")?,
+        acceptances: Some(row.try_get("acceptances")?),
+        dismissed_applicants: Some(row.try_get("dismissed_applicants")?),
+        max_applicants: row.try_get("max_applicants")?,
+        apply_before: row.try_get("apply_before")?,
+        requirements: DogRequirements {
+            large_breed: row.try_get("requires_large_breed")?,
+            puppy: row.try_get("requires_puppy")?,
+        },
+        sla_breached_at: row.try_get("sla_breached_at")?,
+        expired_at: row.try_get("expired_at")?,
+        track_visibility: from_enum_name(row.try_get("track_visibility")?)?,
+        track_archived_at: row.try_get("track_archived_at")?,
+        track_downsampled_at: row.try_get("track_downsampled_at")?,
+        deleted_at: row.try_get("deleted_at")?,
+        max_radius: row.try_get::<Option<f64>, _>("max_radius")?.map(Meters),
+        geofence_violated_at: row.try_get("geofence_violated_at")?,
+        verification_waived_at: row.try_get("verification_waived_at")?,
+        start_received_at: row.try_get("start_received_at")?,
+        finish_received_at: row.try_get("finish_received_at")?,
+        clock_skew_detected_at: row.try_get("clock_skew_detected_at")?,
+        invited_walker_id: row.try_get("invited_walker_id")?,
+        invitation_expires_at: row.try_get("invitation_expires_at")?,
+        tags: row.try_get("tags")?,
+        price: match (
+            row.try_get::<Option<i64>, _>("price_minor_units")?,
+            row.try_get::<Option<String>, _>("price_currency")?,
+        ) {
+            (Some(minor_units), Some(currency)) => Some(Money {
+                minor_units,
+                currency,
+            }),
+            _ => None,
+        },
+        summary: row
+            .try_get::<Option<Json<WalkSummary>>, _>("summary")?
+            .map(|s| s.0),
+        max_walkers: row.try_get("max_walkers")?,
+        pool_walkers: row.try_get::<Json<Vec<PoolWalker>>, _>("pool_walkers")?.0,
+        version: row.try_get("version")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        ..Default::default()
+    };
+    request.status = request.derive_status();
+    request.remaining_slots = request.open_slots();
+    request.time_to_accept_seconds = match (request.accepted_at, request.created_at) {
+        (Some(accepted_at), Some(created_at)) => Some((accepted_at - created_at).num_seconds()),
+        _ => None,
+    };
+    Ok(request)
+}
+
+fn walking_location(row: &PgRow) -> Result<WalkingLocation, ServiceError> {
+    Ok(WalkingLocation {
+        id: row.try_get("id")?,
+        request_id: row.try_get("request_id")?,
+        longitude: row.try_get("longitude")?,
+        latitude: row.try_get("latitude")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn push_is_null(builder: &mut QueryBuilder<sqlx::Postgres>, column: &str, is_null: Option<bool>) {
+    match is_null {
+        Some(true) => builder.push(format!(" AND {} IS NULL", column)),
+        Some(false) => builder.push(format!(" AND {} IS NOT NULL", column)),
+        None => builder,
+    };
+}
+
+/// Appends the `WHERE` clause equivalent to the MongoDB filter of `query`.
+fn push_filter(
+    builder: &mut QueryBuilder<sqlx::Postgres>,
+    query: &WalkRequestQuery,
+) -> Result<(), ServiceError> {
+    builder.push(" WHERE TRUE");
+    if let Some(id) = &query.id {
+        builder.push(" AND id = ").push_bind(parse_id(id)?);
+    }
+    if let Some(code) = &query.reference_code {
+        builder
+            .push(" AND reference_code = ")
+            .push_bind(code.clone());
+    }
+    if let Some(version) = query.version {
+        builder.push(" AND version = ").push_bind(version);
+    }
+    if let Some(ids) = &query.ids_in {
+        let ids = ids
+            .iter()
+            .map(|id| parse_id(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        builder.push(" AND id = ANY(").push_bind(ids).push(")");
+    }
+    if let Some(ids) = &query.dog_ids_includes_all {
+        builder.push(" AND dog_ids @> ").push_bind(ids.clone());
+    }
+    if let Some(ids) = &query.dog_ids_includes_any {
+        builder.push(" AND dog_ids && ").push_bind(ids.clone());
+    }
+    if let Some(nearby) = &query.nearby {
+        if nearby.len() != 3 {
+            return Err(ServiceError::Validation(
+                "Invalid nearby query, expect [f64;3]".to_owned(),
+            ));
+        }
+        builder
+            .push(" AND ST_DWithin(location, ")
+            .push(MAKE_POINT)
+            .push_bind(nearby[0])
+            .push(", ")
+            .push_bind(nearby[1])
+            .push("), 4326)::geography, ")
+            .push_bind(nearby[2])
+            .push(")");
+        if let Some(after) = &query.nearby_after {
+            builder
+                .push(" AND (ST_Distance(location, ")
+                .push(MAKE_POINT)
+                .push_bind(nearby[0])
+                .push(", ")
+                .push_bind(nearby[1])
+                .push("), 4326)::geography) > ")
+                .push_bind(after.distance)
+                .push(" OR (ST_Distance(location, ")
+                .push(MAKE_POINT)
+                .push_bind(nearby[0])
+                .push(", ")
+                .push_bind(nearby[1])
+                .push("), 4326)::geography) = ")
+                .push_bind(after.distance)
+                .push(" AND id > ")
+                .push_bind(parse_id(&after.id)?)
+                .push("))");
+        }
+    }
+    if let Some(area) = &query.within_box {
+        builder
+            .push(" AND ST_Intersects(location, ST_MakeEnvelope(")
+            .push_bind(area.min_longitude)
+            .push(", ")
+            .push_bind(area.min_latitude)
+            .push(", ")
+            .push_bind(area.max_longitude)
+            .push(", ")
+            .push_bind(area.max_latitude)
+            .push(", 4326)::geography)");
+    }
+    if let Some(accepted_by) = &query.accepted_by {
+        builder
+            .push(" AND accepted_by = ")
+            .push_bind(accepted_by.clone());
+    }
+    if let Some(accepted_by) = &query.accepted_by_neq {
+        builder
+            .push(" AND accepted_by IS DISTINCT FROM ")
+            .push_bind(accepted_by.clone());
+    }
+    push_is_null(builder, "accepted_by", query.accepted_by_is_null);
+    if let Some(users) = &query.accepted_by_in {
+        builder
+            .push(" AND accepted_by = ANY(")
+            .push_bind(users.clone())
+            .push(")");
+    }
+    if let Some(walker) = &query.pool_walker {
+        builder
+            .push(" AND pool_walkers @> ")
+            .push_bind(Json(serde_json::json!([{ "walker_id": walker }])));
+    }
+    if let Some(users) = &query.acceptances_includes_all {
+        builder
+            .push(" AND acceptances @> ")
+            .push_bind(users.clone());
+    }
+    if let Some(users) = &query.acceptances_includes_any {
+        builder
+            .push(" AND acceptances && ")
+            .push_bind(users.clone());
+    }
+    if let Some(tags) = &query.tags_includes_all {
+        builder.push(" AND tags @> ").push_bind(tags.clone());
+    }
+    if let Some(tags) = &query.tags_includes_any {
+        builder.push(" AND tags && ").push_bind(tags.clone());
+    }
+    if let Some(created_by) = &query.created_by {
+        builder
+            .push(" AND created_by = ")
+            .push_bind(created_by.clone());
+    }
+    if let Some(created_by) = &query.created_by_neq {
+        builder
+            .push(" AND created_by IS DISTINCT FROM ")
+            .push_bind(created_by.clone());
+    }
+    if let Some(users) = &query.created_by_in {
+        builder
+            .push(" AND created_by = ANY(")
+            .push_bind(users.clone())
+            .push(")");
+    }
+    if let Some(t) = query.created_at_gte {
+        builder.push(" AND created_at >= ").push_bind(t);
+    }
+    if let Some(t) = query.created_at_lte {
+        builder.push(" AND created_at <= ").push_bind(t);
+    }
+    push_is_null(
+        builder,
+        "cancel_requested_at",
+        query.cancel_requested_at_is_null,
+    );
+    if let Some(t) = query.cancel_requested_at_gte {
+        builder.push(" AND cancel_requested_at >= ").push_bind(t);
+    }
+    if let Some(t) = query.cancel_requested_at_lte {
+        builder.push(" AND cancel_requested_at <= ").push_bind(t);
+    }
+    if let Some(t) = query.should_start_after_gte {
+        builder.push(" AND should_start_after >= ").push_bind(t);
+    }
+    if let Some(t) = query.should_start_after_lte {
+        builder.push(" AND should_start_after <= ").push_bind(t);
+    }
+    if let Some(t) = query.should_start_before_lt {
+        builder.push(" AND should_start_before < ").push_bind(t);
+    }
+    if let Some(t) = query.should_start_after_lt {
+        builder.push(" AND should_start_after < ").push_bind(t);
+    }
+    if let Some(t) = query.should_end_before_gt {
+        builder.push(" AND should_end_before > ").push_bind(t);
+    }
+    push_is_null(builder, "canceled_at", query.canceled_at_is_null);
+    push_is_null(builder, "started_at", query.started_at_is_null);
+    if let Some(t) = query.started_at_gte {
+        builder.push(" AND started_at >= ").push_bind(t);
+    }
+    if let Some(t) = query.started_at_lte {
+        builder.push(" AND started_at <= ").push_bind(t);
+    }
+    push_is_null(builder, "finished_at", query.finished_at_is_null);
+    if let Some(t) = query.finished_at_gte {
+        builder.push(" AND finished_at >= ").push_bind(t);
+    }
+    if let Some(t) = query.finished_at_lte {
+        builder.push(" AND finished_at <= ").push_bind(t);
+    }
+    push_is_null(builder, "sla_breached_at", query.sla_breached_at_is_null);
+    push_is_null(builder, "expired_at", query.expired_at_is_null);
+    push_is_null(
+        builder,
+        "geofence_violated_at",
+        query.geofence_violated_at_is_null,
+    );
+    if !query.include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(status) = query.status {
+        for (column, is_null) in WalkRequest::status_conditions(status) {
+            push_is_null(builder, column, Some(is_null));
+        }
+    }
+    if let Some(regions) = &query.regions_in {
+        builder
+            .push(" AND region = ANY(")
+            .push_bind(regions.clone())
+            .push(")");
+    }
+    if let Some(tenant_id) = &query.tenant_id {
+        builder
+            .push(" AND tenant_id = ")
+            .push_bind(tenant_id.clone());
+    }
+    if let Some(user) = &query.dismissed_applicants_excludes {
+        builder
+            .push(" AND NOT (")
+            .push_bind(user.clone())
+            .push(" = ANY(dismissed_applicants))");
+    }
+    if let Some(cap) = query.below_applicant_cap {
+        builder
+            .push(" AND cardinality(acceptances) < COALESCE(max_applicants, ")
+            .push_bind(cap)
+            .push(")");
+    }
+    if let Some(open_at) = query.apply_open_at {
+        builder
+            .push(" AND (apply_before IS NULL OR apply_before > ")
+            .push_bind(open_at)
+            .push(")");
+    }
+    if let Some(viewer) = &query.invitation_visible_to {
+        builder
+            .push(" AND (invitation_expires_at IS NULL OR invitation_expires_at <= ")
+            .push_bind(viewer.at)
+            .push(" OR invited_walker_id = ")
+            .push_bind(viewer.walker.clone())
+            .push(")");
+    }
+    if let Some(max) = query.dog_count_lte {
+        builder
+            .push(" AND jsonb_array_length(dogs) <= ")
+            .push_bind(max);
+    }
+    if let Some(min) = query.dog_count_gte {
+        builder
+            .push(" AND jsonb_array_length(dogs) >= ")
+            .push_bind(min);
+    }
+    if let Some(has_acceptances) = query.has_acceptances {
+        builder.push(if has_acceptances {
+            " AND cardinality(acceptances) > 0"
+        } else {
+            " AND cardinality(acceptances) = 0"
+        });
+    }
+    if let Some(large_breed) = query.requires_large_breed {
+        builder
+            .push(" AND requires_large_breed = ")
+            .push_bind(large_breed);
+    }
+    if let Some(puppy) = query.requires_puppy {
+        builder.push(" AND requires_puppy = ").push_bind(puppy);
+    }
+    if query.dog_name_contains.is_some() || query.dog_breed.is_some() || query.dog_size.is_some() {
+        builder.push(" AND EXISTS (SELECT 1 FROM jsonb_array_elements(dogs) dog WHERE true");
+        if let Some(name) = &query.dog_name_contains {
+            builder
+                .push(" AND dog->>'name' ILIKE ")
+                .push_bind(format!("%{}%", escape_like(name)));
+        }
+        if let Some(breed) = &query.dog_breed {
+            builder
+                .push(" AND lower(trim(dog->>'breed')) = lower(")
+                .push_bind(breed.trim().to_owned())
+                .push(")");
+        }
+        if let Some(size) = query.dog_size {
+            builder
+                .push(" AND dog->>'size' = ")
+                .push_bind(size.as_str());
+        }
+        builder.push(")");
+    }
+    Ok(())
+}
+
+/// Appends the `SET` list of `update`, keeping the stored `location` in sync
+/// with the coordinates.
+fn push_update(builder: &mut QueryBuilder<sqlx::Postgres>, update: WalkRequestUpdate) {
+    builder.push(" SET updated_at = now(), version = version + 1");
+    if let Some(dogs) = update.dogs {
+        let ids: Vec<String> = dogs.iter().map(|d| d.id.clone()).collect();
+        builder.push(", dogs = ").push_bind(Json(dogs));
+        builder.push(", dog_ids = ").push_bind(ids);
+    }
+    if let Some(tags) = update.tags {
+        builder.push(", tags = ").push_bind(tags);
+    }
+    let times = [
+        ("should_start_after", update.should_start_after),
+        ("should_start_before", update.should_start_before),
+        ("should_end_after", update.should_end_after),
+        ("should_end_before", update.should_end_before),
+        ("canceled_at", update.canceled_at),
+        ("started_at", update.started_at),
+        ("finished_at", update.finished_at),
+        ("sla_breached_at", update.sla_breached_at),
+        ("expired_at", update.expired_at),
+        ("track_archived_at", update.track_archived_at),
+        ("track_downsampled_at", update.track_downsampled_at),
+        ("deleted_at", update.deleted_at),
+        ("geofence_violated_at", update.geofence_violated_at),
+        ("verification_waived_at", update.verification_waived_at),
+        ("start_received_at", update.start_received_at),
+        ("finish_received_at", update.finish_received_at),
+        ("clock_skew_detected_at", update.clock_skew_detected_at),
+        ("invitation_expires_at", update.invitation_expires_at),
+    ];
+    for (column, value) in times {
+        if let Some(value) = value {
+            builder.push(format!(", {} = ", column)).push_bind(value);
+        }
+    }
+    if update.latitude.is_some() || update.longitude.is_some() {
+        builder
+            .push(", latitude = COALESCE(")
+            .push_bind(update.latitude)
+            .push("::DOUBLE PRECISION, latitude), longitude = COALESCE(")
+            .push_bind(update.longitude)
+            .push("::DOUBLE PRECISION, longitude), location = ")
+            .push(MAKE_POINT)
+            .push("COALESCE(")
+            .push_bind(update.longitude)
+            .push("::DOUBLE PRECISION, longitude), COALESCE(")
+            .push_bind(update.latitude)
+            .push("::DOUBLE PRECISION, latitude)), 4326)::geography");
+    }
+    if let Some(timezone) = update.timezone {
+        builder.push(", timezone = ").push_bind(timezone);
+    }
+    if let Some(summary) = update.summary {
+        builder.push(", summary = ").push_bind(Json(summary));
+    }
+    if let Some(canceled_by) = update.canceled_by {
+        builder.push(", canceled_by = ").push_bind(canceled_by);
+    }
+    if let Some(reason) = update.cancellation_reason {
+        builder.push(", cancellation_reason = ").push_bind(reason);
+    }
+    if let Some(cancel_requested_at) = update.cancel_requested_at {
+        builder
+            .push(", cancel_requested_at = ")
+            .push_bind(cancel_requested_at);
+    }
+    if update.unset_cancel_request {
+        builder
+            .push(", cancel_requested_at = NULL, canceled_by = NULL, cancellation_reason = NULL");
+    }
+    if let Some(requirements) = update.requirements {
+        builder
+            .push(", requires_large_breed = ")
+            .push_bind(requirements.large_breed)
+            .push(", requires_puppy = ")
+            .push_bind(requirements.puppy);
+    }
+    if let Some(track_visibility) = update.track_visibility {
+        builder
+            .push(", track_visibility = ")
+            .push_bind(track_visibility.as_str());
+    }
+    if let Some(price) = update.price {
+        builder
+            .push(", price_minor_units = ")
+            .push_bind(price.minor_units)
+            .push(", price_currency = ")
+            .push_bind(price.currency);
+    }
+    if let Some(pool_walkers) = update.pool_walkers {
+        builder
+            .push(", pool_walkers = ")
+            .push_bind(Json(pool_walkers));
+    }
+    if update.unset_accepted_by {
+        builder.push(", accepted_by = NULL");
+    } else if let Some(accepted_by) = update.accepted_by {
+        builder.push(", accepted_by = ").push_bind(accepted_by);
+    }
+    if update.unset_accepted_at {
+        builder.push(", accepted_at = NULL");
+    } else if let Some(accepted_at) = update.accepted_at {
+        builder.push(", accepted_at = ").push_bind(accepted_at);
+    }
+    if let Some(user) = update.add_to_acceptances {
+        builder
+            .push(", acceptances = CASE WHEN ")
+            .push_bind(user.clone())
+            .push(" = ANY(acceptances) THEN acceptances ELSE array_append(acceptances, ")
+            .push_bind(user)
+            .push(") END");
+    } else if let Some(user) = update.remove_from_acceptances {
+        builder
+            .push(", acceptances = array_remove(acceptances, ")
+            .push_bind(user)
+            .push(")");
+    } else if let Some(users) = update.remove_all_from_acceptances {
+        builder
+            .push(", acceptances = ARRAY(SELECT a FROM unnest(acceptances) a WHERE a <> ALL(")
+            .push_bind(users)
+            .push("))");
+    }
+    if let Some(user) = update.add_to_dismissed_applicants {
+        builder
+            .push(", dismissed_applicants = CASE WHEN ")
+            .push_bind(user.clone())
+            .push(" = ANY(dismissed_applicants) THEN dismissed_applicants ")
+            .push("ELSE array_append(dismissed_applicants, ")
+            .push_bind(user)
+            .push(") END");
+    } else if let Some(users) = update.add_all_to_dismissed_applicants {
+        builder
+            .push(", dismissed_applicants = dismissed_applicants || ARRAY(SELECT DISTINCT u ")
+            .push("FROM unnest(")
+            .push_bind(users)
+            .push("::TEXT[]) u WHERE u <> ALL(dismissed_applicants))");
+    }
+}
+
+fn sort_column(field: &str) -> &'static str {
+    match field {
+        "should_start_after" => "should_start_after",
+        "should_start_before" => "should_start_before",
+        "updated_at" => "updated_at",
+        _ => "created_at",
+    }
+}
+
+fn push_pagination(builder: &mut QueryBuilder<sqlx::Postgres>, pagination: Option<Pagination>) {
+    if let Some(p) = pagination {
+        builder
+            .push(" LIMIT ")
+            .push_bind(p.size.max(0))
+            .push(" OFFSET ")
+            .push_bind(((p.page - 1) * p.size).max(0));
+    }
+}
+
+/// The transaction of `Repository::transaction`, shared by the clones of
+/// the repository passed to the operation. Writes of walk requests,
+/// applications, offers and outbox messages run in it, other statements on
+/// the pool.
+type Transaction = Arc<Mutex<sqlx::Transaction<'static, sqlx::Postgres>>>;
+
+/// `Repository` on PostgreSQL with PostGIS, for deployments on managed
+/// Postgres. The schema lives in `migrations/`.
+#[derive(Clone)]
+pub struct Postgres {
+    pool: PgPool,
+    /// See `Repository::for_tenant`.
+    tenant: Option<String>,
+    transaction: Option<Transaction>,
+}
+
+impl Postgres {
+    pub fn new(pool: PgPool) -> Self {
+        Postgres {
+            pool,
+            tenant: None,
+            transaction: None,
+        }
+    }
+
+    /// `query` within the tenant the repository is scoped to.
+    fn scoped(&self, query: WalkRequestQuery) -> WalkRequestQuery {
+        query.within_tenant(self.tenant.as_deref())
+    }
+
+    pub async fn migrate(&self) -> Result<(), ServiceError> {
+        sqlx::migrate!().run(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Rows a walk request stream reads ahead of its consumer.
+const STREAM_BUFFER: usize = 64;
+
+fn select_walk_requests(
+    query: WalkRequestQuery,
+    sort_by: Option<SortBy>,
+    pagination: Option<Pagination>,
+) -> Result<QueryBuilder<'static, sqlx::Postgres>, ServiceError> {
+    let mut builder = QueryBuilder::new("SELECT ");
+    builder.push(WALK_REQUEST_COLUMNS);
+    match &query.nearby {
+        Some(nearby) if nearby.len() == 3 => {
+            builder
+                .push(", ST_Distance(location, ")
+                .push(MAKE_POINT)
+                .push_bind(nearby[0])
+                .push(", ")
+                .push_bind(nearby[1])
+                .push("), 4326)::geography) AS distance");
+        }
+        _ => {
+            builder.push(", NULL::DOUBLE PRECISION AS distance");
+        }
+    }
+    builder.push(" FROM walk_requests");
+    push_filter(&mut builder, &query)?;
+    match sort_by {
+        Some(sort_by) => {
+            builder.push(format!(
+                " ORDER BY {} {}",
+                sort_column(&sort_by.field),
+                if sort_by.order == Order::Desc {
+                    "DESC"
+                } else {
+                    "ASC"
+                }
+            ));
+        }
+        None if query.nearby.is_some() => {
+            builder.push(" ORDER BY distance, id");
+        }
+        None => {
+            builder.push(" ORDER BY id");
+        }
+    }
+    push_pagination(&mut builder, pagination);
+    Ok(builder)
+}
+
+impl Repository for Postgres {
+    async fn transaction<T, F, Fut>(&self, operation: F) -> Result<T, ServiceError>
+    where
+        Self: Clone,
+        F: FnOnce(Self) -> Fut,
+        Fut: Future<Output = Result<T, ServiceError>>,
+    {
+        if self.transaction.is_some() {
+            // Nested transactions join the running one.
+            return operation(self.clone()).await;
+        }
+        let transaction = Arc::new(Mutex::new(self.pool.begin().await?));
+        let result = operation(Self {
+            transaction: Some(transaction.clone()),
+            ..self.clone()
+        })
+        .await;
+        let transaction = Arc::try_unwrap(transaction)
+            .map_err(|_| {
+                ServiceError::internal(anyhow::anyhow!("transaction outlived its operation"))
+            })?
+            .into_inner();
+        match result {
+            Ok(value) => {
+                transaction.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                transaction.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    fn for_tenant(&self, tenant_id: &str) -> Self {
+        Self {
+            tenant: Some(tenant_id.to_owned()),
+            ..self.clone()
+        }
+    }
+
+    async fn create_walk_request(
+        &self,
+        request: WalkRequestCreate,
+    ) -> Result<String, ServiceError> {
+        let dog_ids: Vec<String> = request.dogs.iter().map(|d| d.id.clone()).collect();
+        let query = sqlx::query_scalar(
+            "INSERT INTO walk_requests (dogs, dog_ids, should_start_after, should_start_before, \
+             should_end_after, should_end_before, latitude, longitude, location, timezone, region, \
+             max_applicants, requires_large_breed, requires_puppy, track_visibility, created_by, \
+             max_radius, price_minor_units, price_currency, max_walkers, apply_before, tenant_id, \
+             invited_walker_id, invitation_expires_at, reference_code, tags) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, \
+             ST_SetSRID(ST_MakePoint($8, $7), 4326)::geography, $9, $10, $11, $12, $13, $14, $15, \
+             $16, $17, $18, $19, $20, $21, $22, $23, $24, $25) \
+             RETURNING id::TEXT",
+        )
+        .bind(Json(request.dogs))
+        .bind(dog_ids)
+        .bind(request.should_start_after)
+        .bind(request.should_start_before)
+        .bind(request.should_end_after)
+        .bind(request.should_end_before)
+        .bind(request.latitude)
+        .bind(request.longitude)
+        .bind(request.timezone)
+        .bind(request.region)
+        .bind(request.max_applicants)
+        .bind(request.requirements.large_breed)
+        .bind(request.requirements.puppy)
+        .bind(request.track_visibility.as_str())
+        .bind(request.created_by)
+        .bind(request.max_radius.map(Meters::value))
+        .bind(request.price.as_ref().map(|p| p.minor_units))
+        .bind(request.price.map(|p| p.currency))
+        .bind(request.max_walkers)
+        .bind(request.apply_before)
+        .bind(request.tenant_id)
+        .bind(request.invited_walker_id)
+        .bind(request.invitation_expires_at)
+        .bind(request.reference_code)
+        .bind(request.tags);
+        let id: String = match &self.transaction {
+            Some(transaction) => query.fetch_one(&mut **transaction.lock().await).await?,
+            None => query.fetch_one(&self.pool).await?,
+        };
+        Ok(id)
+    }
+
+    async fn update_walk_request(
+        &self,
+        id: &str,
+        request: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        self.update_walk_request_by_query(
+            WalkRequestQuery {
+                id: Some(id.to_owned()),
+                ..Default::default()
+            },
+            request,
+        )
+        .await
+    }
+
+    async fn update_walk_request_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<WalkRequest, ServiceError> {
+        let mut builder = QueryBuilder::new("UPDATE walk_requests");
+        push_update(&mut builder, update);
+        builder.push(" WHERE id = (SELECT id FROM walk_requests");
+        push_filter(&mut builder, &self.scoped(query))?;
+        builder
+            .push(" ORDER BY id LIMIT 1 FOR UPDATE) RETURNING ")
+            .push(WALK_REQUEST_COLUMNS);
+        let row = match &self.transaction {
+            Some(transaction) => {
+                builder
+                    .build()
+                    .fetch_optional(&mut **transaction.lock().await)
+                    .await?
+            }
+            None => builder.build().fetch_optional(&self.pool).await?,
+        }
+        .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))?;
+        walk_request(&row)
+    }
+
+    async fn update_walk_requests_by_query(
+        &self,
+        query: WalkRequestQuery,
+        update: WalkRequestUpdate,
+    ) -> Result<u64, ServiceError> {
+        let mut builder = QueryBuilder::new("UPDATE walk_requests");
+        push_update(&mut builder, update);
+        push_filter(&mut builder, &self.scoped(query))?;
+        let result = match &self.transaction {
+            Some(transaction) => {
+                builder
+                    .build()
+                    .execute(&mut **transaction.lock().await)
+                    .await?
+            }
+            None => builder.build().execute(&self.pool).await?,
+        };
+        Ok(result.rows_affected())
+    }
+
+    async fn count_walk_requests(&self, query: WalkRequestQuery) -> Result<u64, ServiceError> {
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM walk_requests");
+        push_filter(&mut builder, &self.scoped(query))?;
+        let count: i64 = builder.build_query_scalar().fetch_one(&self.pool).await?;
+        Ok(count as u64)
+    }
+
+    async fn walk_report(
+        &self,
+        query: WalkRequestQuery,
+        interval: ReportInterval,
+    ) -> Result<Vec<ReportBucket>, ServiceError> {
+        // `date_trunc` weeks start on Monday, as the report's do.
+        let mut builder = QueryBuilder::new("SELECT date_trunc(");
+        builder.push_bind(interval.unit()).push(
+            ", finished_at AT TIME ZONE 'UTC')::DATE AS period_start, \
+                 price_currency, COUNT(*) AS walks, \
+                 COALESCE(SUM((summary->>'distance')::FLOAT8), 0)::FLOAT8 AS distance, \
+                 COALESCE(SUM(EXTRACT(EPOCH FROM finished_at - started_at)), 0)::FLOAT8 \
+                 AS seconds, SUM(price_minor_units)::BIGINT AS amount FROM walk_requests",
+        );
+        push_filter(&mut builder, &self.scoped(query))?;
+        builder.push(" AND finished_at IS NOT NULL GROUP BY 1, 2");
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let rows = rows
+            .iter()
+            .map(|row| -> Result<ReportRow, ServiceError> {
+                let currency: Option<String> = row.try_get("price_currency")?;
+                let amount: Option<i64> = row.try_get("amount")?;
+                Ok((
+                    row.try_get("period_start")?,
+                    row.try_get::<i64, _>("walks")? as u64,
+                    Meters(row.try_get("distance")?),
+                    row.try_get("seconds")?,
+                    currency
+                        .zip(amount)
+                        .map(|(currency, amount)| Money::new(amount, &currency)),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(collect_buckets(rows))
+    }
+
+    async fn get_walk_request(&self, id: &str) -> Result<WalkRequest, ServiceError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM walk_requests WHERE id = $1 AND ($2::TEXT IS NULL OR tenant_id = $2)",
+            WALK_REQUEST_COLUMNS
+        ))
+        .bind(parse_id(id)?)
+        .bind(self.tenant.as_deref())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(ServiceError::NotFound("代遛请求不存在".to_owned()))?;
+        walk_request(&row)
+    }
+
+    async fn query_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        select_walk_requests(self.scoped(query), sort_by, pagination)?
+            .build()
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(walk_request)
+            .collect()
+    }
+
+    async fn stream_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        sort_by: Option<SortBy>,
+        pagination: Option<Pagination>,
+    ) -> Result<WalkRequestStream, ServiceError> {
+        let mut builder = select_walk_requests(self.scoped(query), sort_by, pagination)?;
+        let pool = self.pool.clone();
+        // The rows borrow the query, so they are read by a task of their own
+        // which stops once the receiver is dropped.
+        let (mut sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        actix_web::rt::spawn(async move {
+            let mut rows = builder.build().fetch(&pool);
+            while let Some(row) = rows.next().await {
+                let request = row
+                    .map_err(ServiceError::from)
+                    .and_then(|r| walk_request(&r));
+                if sender.send(request).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver.boxed_local())
+    }
+
+    async fn create_walking_location<'a>(
+        &self,
+        create: WalkingLocationCreate<'a>,
+    ) -> Result<String, ServiceError> {
+        let id: String = sqlx::query_scalar(
+            "INSERT INTO walking_locations (request_id, longitude, latitude, created_at) \
+             VALUES ($1, $2, $3, COALESCE($4, now())) RETURNING id::TEXT",
+        )
+        .bind(create.walk_request_id)
+        .bind(create.longitude)
+        .bind(create.latitude)
+        .bind(create.recorded_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    async fn create_walking_locations<'a>(
+        &self,
+        creates: Vec<WalkingLocationCreate<'a>>,
+    ) -> Result<Vec<String>, ServiceError> {
+        if creates.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut builder = QueryBuilder::new(
+            "INSERT INTO walking_locations (request_id, longitude, latitude, created_at) ",
+        );
+        builder.push_values(creates, |mut row, create| {
+            row.push_bind(create.walk_request_id.to_owned())
+                .push_bind(create.longitude)
+                .push_bind(create.latitude)
+                .push("COALESCE(")
+                .push_bind_unseparated(create.recorded_at)
+                .push_unseparated(", now())");
+        });
+        builder.push(" RETURNING id::TEXT");
+        Ok(builder.build_query_scalar().fetch_all(&self.pool).await?)
+    }
+
+    async fn query_walking_locations(
+        &self,
+        query: WalkingLocationQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkingLocation>, ServiceError> {
+        let mut builder = QueryBuilder::new(
+            "SELECT id::TEXT AS id, request_id, longitude, latitude, created_at \
+             FROM walking_locations WHERE request_id = ",
+        );
+        builder.push_bind(query.walk_request_id);
+        if let Some(after) = query.created_after {
+            builder.push(" AND created_at >= ").push_bind(after);
+        }
+        if let Some(before) = query.created_before {
+            builder.push(" AND created_at < ").push_bind(before);
+        }
+        builder.push(" ORDER BY id");
+        push_pagination(&mut builder, pagination);
+        builder
+            .build()
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(walking_location)
+            .collect()
+    }
+
+    async fn delete_walking_locations(&self, ids: &[String]) -> Result<u64, ServiceError> {
+        let ids = ids
+            .iter()
+            .map(|id| parse_id(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(
+            sqlx::query("DELETE FROM walking_locations WHERE id = ANY($1)")
+                .bind(ids)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn walk_requests_with_tracks_before(
+        &self,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<String>, ServiceError> {
+        Ok(sqlx::query_scalar(
+            "SELECT DISTINCT request_id FROM walking_locations WHERE created_at < $1",
+        )
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    async fn query_holidays(&self, region: &str) -> Result<Vec<Holiday>, ServiceError> {
+        let rows = sqlx::query(
+            "SELECT region, date, name, price_multiplier, opens_at, closes_at \
+             FROM holidays WHERE region = $1 ORDER BY date",
+        )
+        .bind(region)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Holiday {
+                    region: row.try_get("region")?,
+                    date: row.try_get("date")?,
+                    name: row.try_get("name")?,
+                    price_multiplier: row.try_get("price_multiplier")?,
+                    opens_at: row.try_get("opens_at")?,
+                    closes_at: row.try_get("closes_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn upsert_holiday(&self, holiday: Holiday) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO holidays (region, date, name, price_multiplier, opens_at, closes_at) \
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (region, date) DO UPDATE SET \
+             name = EXCLUDED.name, price_multiplier = EXCLUDED.price_multiplier, \
+             opens_at = EXCLUDED.opens_at, closes_at = EXCLUDED.closes_at",
+        )
+        .bind(holiday.region)
+        .bind(holiday.date)
+        .bind(holiday.name)
+        .bind(holiday.price_multiplier)
+        .bind(holiday.opens_at)
+        .bind(holiday.closes_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_holiday(&self, region: &str, date: NaiveDate) -> Result<u64, ServiceError> {
+        Ok(
+            sqlx::query("DELETE FROM holidays WHERE region = $1 AND date = $2")
+                .bind(region)
+                .bind(date)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn walk_requests_active_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        sqlx::query(&format!(
+            "SELECT {} FROM walk_requests WHERE \
+             (created_at >= $1 AND created_at < $2) \
+             OR (accepted_at >= $1 AND accepted_at < $2) \
+             OR (finished_at >= $1 AND finished_at < $2) \
+             OR (canceled_at >= $1 AND canceled_at < $2) \
+             OR (sla_breached_at >= $1 AND sla_breached_at < $2)",
+            WALK_REQUEST_COLUMNS
+        ))
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(walk_request)
+        .collect()
+    }
+
+    async fn save_daily_metrics(&self, metrics: Vec<DailyMetrics>) -> Result<(), ServiceError> {
+        let mut tx = self.pool.begin().await?;
+        for m in metrics {
+            sqlx::query(
+                "INSERT INTO daily_metrics (region, date, body) VALUES ($1, $2, $3) \
+                 ON CONFLICT (region, date) DO UPDATE SET body = EXCLUDED.body",
+            )
+            .bind(m.region.clone())
+            .bind(m.date)
+            .bind(Json(m))
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn query_daily_metrics(
+        &self,
+        region: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<Vec<DailyMetrics>, ServiceError> {
+        let metrics: Vec<Json<DailyMetrics>> = sqlx::query_scalar(
+            "SELECT body FROM daily_metrics WHERE region = $1 AND date >= $2 AND date <= $3 \
+             ORDER BY date",
+        )
+        .bind(region)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(metrics.into_iter().map(|m| m.0).collect())
+    }
+
+    async fn upsert_application(
+        &self,
+        request_id: &str,
+        applicant_id: &str,
+        state: ApplicationState,
+    ) -> Result<(), ServiceError> {
+        let query = sqlx::query(
+            "INSERT INTO applications (request_id, applicant_id, state, applied_at, updated_at) \
+             VALUES ($1, $2, $3, now(), now()) ON CONFLICT (request_id, applicant_id) \
+             DO UPDATE SET state = EXCLUDED.state, updated_at = EXCLUDED.updated_at",
+        )
+        .bind(request_id)
+        .bind(applicant_id)
+        .bind(enum_name(state)?);
+        match &self.transaction {
+            Some(transaction) => query.execute(&mut **transaction.lock().await).await?,
+            None => query.execute(&self.pool).await?,
+        };
+        Ok(())
+    }
+
+    async fn query_applications(
+        &self,
+        applicant_id: &str,
+        pagination: Pagination,
+    ) -> Result<Vec<Application>, ServiceError> {
+        let mut builder = QueryBuilder::new(
+            "SELECT request_id, applicant_id, state, applied_at, updated_at \
+             FROM applications WHERE applicant_id = ",
+        );
+        builder
+            .push_bind(applicant_id.to_owned())
+            .push(" ORDER BY applied_at DESC");
+        push_pagination(&mut builder, Some(pagination));
+        builder
+            .build()
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| {
+                Ok(Application {
+                    request_id: row.try_get("request_id")?,
+                    applicant_id: row.try_get("applicant_id")?,
+                    state: from_enum_name(row.try_get("state")?)?,
+                    applied_at: row.try_get("applied_at")?,
+                    updated_at: row.try_get("updated_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_saga(&self, saga: &BookingSaga) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO booking_sagas (id, status, updated_at, body) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (id) DO UPDATE SET status = EXCLUDED.status, \
+             updated_at = EXCLUDED.updated_at, body = EXCLUDED.body",
+        )
+        .bind(&saga.id)
+        .bind(enum_name(saga.status)?)
+        .bind(saga.updated_at)
+        .bind(Json(saga))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_tenant(&self, id: &str) -> Result<Option<Tenant>, ServiceError> {
+        let tenant: Option<Json<Tenant>> =
+            sqlx::query_scalar("SELECT body FROM tenants WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(tenant.map(|t| t.0))
+    }
+
+    async fn upsert_tenant(&self, tenant: Tenant) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO tenants (id, body) VALUES ($1, $2) \
+             ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(tenant.id.clone())
+        .bind(Json(tenant))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_walker_capabilities(
+        &self,
+        user_id: &str,
+    ) -> Result<Option<WalkerCapabilities>, ServiceError> {
+        let capabilities: Option<Json<WalkerCapabilities>> =
+            sqlx::query_scalar("SELECT body FROM walker_capabilities WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(capabilities.map(|c| c.0))
+    }
+
+    async fn upsert_walker_capabilities(
+        &self,
+        user_id: &str,
+        capabilities: WalkerCapabilities,
+    ) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO walker_capabilities (user_id, body) VALUES ($1, $2) \
+             ON CONFLICT (user_id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(user_id)
+        .bind(Json(capabilities))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn archive_walk_requests(&self, before: DateTime<Utc>) -> Result<u64, ServiceError> {
+        Ok(sqlx::query(
+            "WITH moved AS (DELETE FROM walk_requests \
+            WHERE finished_at < $1 OR canceled_at < $1 RETURNING *) \
+            INSERT INTO walk_requests_archive SELECT * FROM moved ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(before)
+        .execute(&self.pool)
+        .await?
+        .rows_affected())
+    }
+
+    async fn query_archived_walk_requests(
+        &self,
+        query: WalkRequestQuery,
+        pagination: Option<Pagination>,
+    ) -> Result<Vec<WalkRequest>, ServiceError> {
+        let mut builder = QueryBuilder::new("SELECT ");
+        builder
+            .push(WALK_REQUEST_COLUMNS)
+            .push(", NULL::DOUBLE PRECISION AS distance FROM walk_requests_archive");
+        push_filter(&mut builder, &self.scoped(query))?;
+        builder.push(" ORDER BY created_at DESC");
+        push_pagination(&mut builder, pagination);
+        builder
+            .build()
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(walk_request)
+            .collect()
+    }
+
+    async fn purge(
+        &self,
+        class: DataClass,
+        before: DateTime<Utc>,
+        dry_run: bool,
+    ) -> Result<u64, ServiceError> {
+        let table = match class {
+            DataClass::Tracks => "walking_locations",
+            DataClass::Chat | DataClass::Photos | DataClass::Audit => return Ok(0),
+        };
+        if dry_run {
+            let count: i64 = sqlx::query_scalar(&format!(
+                "SELECT COUNT(*) FROM {} WHERE created_at < $1",
+                table
+            ))
+            .bind(before)
+            .fetch_one(&self.pool)
+            .await?;
+            return Ok(count as u64);
+        }
+        Ok(
+            sqlx::query(&format!("DELETE FROM {} WHERE created_at < $1", table))
+                .bind(before)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn imported_id(&self, key: &str) -> Result<Option<String>, ServiceError> {
+        Ok(
+            sqlx::query_scalar("SELECT id FROM legacy_imports WHERE key = $1")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?,
+        )
+    }
+
+    async fn record_import(&self, key: &str, id: &str) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO legacy_imports (key, id) VALUES ($1, $2) \
+             ON CONFLICT (key) DO UPDATE SET id = EXCLUDED.id",
+        )
+        .bind(key)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn next_sequence(&self, name: &str) -> Result<u64, ServiceError> {
+        let value: i64 = sqlx::query_scalar(
+            "INSERT INTO counters (name, value) VALUES ($1, 1) \
+             ON CONFLICT (name) DO UPDATE SET value = counters.value + 1 \
+             RETURNING value",
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(value as u64)
+    }
+
+    async fn query_fitness_tokens(&self, user_id: &str) -> Result<Vec<FitnessToken>, ServiceError> {
+        let tokens: Vec<Json<FitnessToken>> = sqlx::query_scalar(
+            "SELECT body FROM fitness_tokens WHERE user_id = $1 ORDER BY provider",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tokens.into_iter().map(|t| t.0).collect())
+    }
+
+    async fn upsert_fitness_token(&self, token: FitnessToken) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO fitness_tokens (user_id, provider, body) VALUES ($1, $2, $3) \
+             ON CONFLICT (user_id, provider) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(token.user_id.clone())
+        .bind(token.provider.clone())
+        .bind(Json(token))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_fitness_token(
+        &self,
+        user_id: &str,
+        provider: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(
+            sqlx::query("DELETE FROM fitness_tokens WHERE user_id = $1 AND provider = $2")
+                .bind(user_id)
+                .bind(provider)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn query_favorite_walkers(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<FavoriteWalker>, ServiceError> {
+        let favorites: Vec<Json<FavoriteWalker>> = sqlx::query_scalar(
+            "SELECT body FROM favorite_walkers WHERE owner_id = $1 \
+             ORDER BY body->>'created_at' DESC",
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(favorites.into_iter().map(|f| f.0).collect())
+    }
+
+    async fn upsert_favorite_walker(&self, favorite: FavoriteWalker) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO favorite_walkers (owner_id, walker_id, body) VALUES ($1, $2, $3) \
+             ON CONFLICT (owner_id, walker_id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(favorite.owner_id.clone())
+        .bind(favorite.walker_id.clone())
+        .bind(Json(favorite))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_favorite_walker(
+        &self,
+        owner_id: &str,
+        walker_id: &str,
+    ) -> Result<u64, ServiceError> {
+        Ok(
+            sqlx::query("DELETE FROM favorite_walkers WHERE owner_id = $1 AND walker_id = $2")
+                .bind(owner_id)
+                .bind(walker_id)
+                .execute(&self.pool)
+                .await?
+                .rows_affected(),
+        )
+    }
+
+    async fn save_fitness_export(&self, export: &FitnessExport) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO fitness_exports (id, status, next_attempt_at, body) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO UPDATE SET status = EXCLUDED.status, \
+             next_attempt_at = EXCLUDED.next_attempt_at, body = EXCLUDED.body",
+        )
+        .bind(&export.id)
+        .bind(enum_name(export.status)?)
+        .bind(export.next_attempt_at)
+        .bind(Json(export))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn due_fitness_exports(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<FitnessExport>, ServiceError> {
+        let exports: Vec<Json<FitnessExport>> = sqlx::query_scalar(
+            "SELECT body FROM fitness_exports \
+             WHERE status = $1 AND next_attempt_at <= $2 ORDER BY next_attempt_at",
+        )
+        .bind(enum_name(ExportStatus::Pending)?)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(exports.into_iter().map(|e| e.0).collect())
+    }
+
+    async fn save_payment(&self, payment: &Payment) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO payments (id, request_id, status, body) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (id) DO UPDATE SET status = EXCLUDED.status, body = EXCLUDED.body",
+        )
+        .bind(&payment.id)
+        .bind(&payment.request_id)
+        .bind(enum_name(payment.status)?)
+        .bind(Json(payment))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_payment(&self, request_id: &str) -> Result<Option<Payment>, ServiceError> {
+        let payment: Option<Json<Payment>> =
+            sqlx::query_scalar("SELECT body FROM payments WHERE request_id = $1")
+                .bind(request_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(payment.map(|p| p.0))
+    }
+
+    async fn save_receipt(&self, receipt: &Receipt) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO receipts (id, request_id, owner_id, issued_at, body) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&receipt.id)
+        .bind(&receipt.request_id)
+        .bind(&receipt.owner_id)
+        .bind(receipt.issued_at)
+        .bind(Json(receipt))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_receipt(&self, request_id: &str) -> Result<Option<Receipt>, ServiceError> {
+        let receipt: Option<Json<Receipt>> =
+            sqlx::query_scalar("SELECT body FROM receipts WHERE request_id = $1")
+                .bind(request_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(receipt.map(|r| r.0))
+    }
+
+    async fn query_receipts(
+        &self,
+        owner_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Receipt>, ServiceError> {
+        let receipts: Vec<Json<Receipt>> = sqlx::query_scalar(
+            "SELECT body FROM receipts WHERE owner_id = $1 AND issued_at >= $2 \
+             AND issued_at < $3 ORDER BY issued_at",
+        )
+        .bind(owner_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(receipts.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn save_payout(&self, payout: &Payout) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO payouts (id, request_id, walker_id, created_at, body) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&payout.id)
+        .bind(&payout.request_id)
+        .bind(&payout.walker_id)
+        .bind(payout.created_at)
+        .bind(Json(payout))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_payouts(
+        &self,
+        walker_id: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payout>, ServiceError> {
+        let payouts: Vec<Json<Payout>> = sqlx::query_scalar(
+            "SELECT body FROM payouts WHERE walker_id = $1 AND created_at >= $2 \
+             AND created_at < $3 ORDER BY created_at",
+        )
+        .bind(walker_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(payouts.into_iter().map(|p| p.0).collect())
+    }
+
+    async fn get_spending_cap(&self, owner_id: &str) -> Result<Option<SpendingCap>, ServiceError> {
+        let cap: Option<Json<SpendingCap>> =
+            sqlx::query_scalar("SELECT body FROM spending_caps WHERE owner_id = $1")
+                .bind(owner_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(cap.map(|c| c.0))
+    }
+
+    async fn save_spending_cap(&self, cap: &SpendingCap) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO spending_caps (owner_id, body) VALUES ($1, $2) \
+             ON CONFLICT (owner_id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&cap.owner_id)
+        .bind(Json(cap))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_spending_cap(&self, owner_id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM spending_caps WHERE owner_id = $1")
+            .bind(owner_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn mark_funnel_stage(
+        &self,
+        request_id: &str,
+        stage: FunnelStage,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        // The column comes from `FunnelStage::field`, never from input.
+        let sql = format!(
+            "INSERT INTO walk_request_funnels (request_id, {field}) VALUES ($1, $2) \
+             ON CONFLICT (request_id) DO UPDATE SET \
+             {field} = LEAST(walk_request_funnels.{field}, EXCLUDED.{field})",
+            field = stage.field()
+        );
+        sqlx::query(&sql)
+            .bind(request_id)
+            .bind(at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn record_impressions(
+        &self,
+        request_ids: &[String],
+        weight: u64,
+        at: DateTime<Utc>,
+    ) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO walk_request_funnels (request_id, first_viewed_at, impressions) \
+             SELECT id, $2, $3 FROM UNNEST($1::TEXT[]) AS id \
+             ON CONFLICT (request_id) DO UPDATE SET \
+             first_viewed_at = LEAST(walk_request_funnels.first_viewed_at, EXCLUDED.first_viewed_at), \
+             impressions = walk_request_funnels.impressions + EXCLUDED.impressions",
+        )
+        .bind(request_ids)
+        .bind(at)
+        .bind(weight as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_funnel_marks(
+        &self,
+        request_ids: &[String],
+    ) -> Result<Vec<FunnelMarks>, ServiceError> {
+        let rows = sqlx::query(
+            "SELECT request_id, first_viewed_at, first_applied_at, impressions \
+             FROM walk_request_funnels \
+             WHERE request_id = ANY($1)",
+        )
+        .bind(request_ids)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(FunnelMarks {
+                    request_id: row.try_get("request_id")?,
+                    first_viewed_at: row.try_get("first_viewed_at")?,
+                    first_applied_at: row.try_get("first_applied_at")?,
+                    impressions: row.try_get::<i64, _>("impressions")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn save_device_session(&self, session: &DeviceSession) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO device_sessions (id, user_id, created_at, body) VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&session.id)
+        .bind(&session.user_id)
+        .bind(session.created_at)
+        .bind(Json(session))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_device_sessions(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<DeviceSession>, ServiceError> {
+        let sessions: Vec<Json<DeviceSession>> = sqlx::query_scalar(
+            "SELECT body FROM device_sessions WHERE user_id = $1 ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sessions.into_iter().map(|s| s.0).collect())
+    }
+
+    async fn record_strike(&self, strike: &Strike) -> Result<bool, ServiceError> {
+        let result = sqlx::query(
+            "INSERT INTO walker_strikes (walker_id, request_id, recorded_at, body) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (walker_id, request_id) DO NOTHING",
+        )
+        .bind(&strike.walker_id)
+        .bind(&strike.request_id)
+        .bind(strike.recorded_at)
+        .bind(Json(strike))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn query_strikes(&self, walker_id: &str) -> Result<Vec<Strike>, ServiceError> {
+        let strikes: Vec<Json<Strike>> = sqlx::query_scalar(
+            "SELECT body FROM walker_strikes WHERE walker_id = $1 ORDER BY recorded_at DESC",
+        )
+        .bind(walker_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(strikes.into_iter().map(|s| s.0).collect())
+    }
+
+    async fn update_reputation(
+        &self,
+        user_id: &str,
+        update: ReputationUpdate,
+    ) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO user_reputations (user_id, completed_walks, strikes, banned_until) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (user_id) DO UPDATE SET \
+             completed_walks = user_reputations.completed_walks + EXCLUDED.completed_walks, \
+             strikes = user_reputations.strikes + EXCLUDED.strikes, \
+             banned_until = COALESCE(EXCLUDED.banned_until, user_reputations.banned_until)",
+        )
+        .bind(user_id)
+        .bind(update.completed_walks)
+        .bind(update.strikes)
+        .bind(update.banned_until)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_reputation(&self, user_id: &str) -> Result<Option<Reputation>, ServiceError> {
+        let row = sqlx::query(
+            "SELECT completed_walks, strikes, banned_until FROM user_reputations \
+             WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| -> Result<Reputation, ServiceError> {
+            Ok(Reputation {
+                user_id: user_id.to_owned(),
+                completed_walks: row.try_get("completed_walks")?,
+                strikes: row.try_get("strikes")?,
+                banned_until: row.try_get("banned_until")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn save_feature_flag(&self, flag: &FeatureFlag) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO feature_flags (feature, body) VALUES ($1, $2) \
+             ON CONFLICT (feature) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(flag.feature.as_str())
+        .bind(Json(flag))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_feature_flags(&self) -> Result<Vec<FeatureFlag>, ServiceError> {
+        let flags: Vec<Json<FeatureFlag>> = sqlx::query_scalar("SELECT body FROM feature_flags")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(flags.into_iter().map(|f| f.0).collect())
+    }
+
+    async fn save_offer(&self, offer: &Offer) -> Result<(), ServiceError> {
+        let query = sqlx::query(
+            "INSERT INTO offers (id, request_id, walker_id, created_at, body) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&offer.id)
+        .bind(&offer.request_id)
+        .bind(&offer.walker_id)
+        .bind(offer.created_at)
+        .bind(Json(offer));
+        match &self.transaction {
+            Some(transaction) => query.execute(&mut **transaction.lock().await).await?,
+            None => query.execute(&self.pool).await?,
+        };
+        Ok(())
+    }
+
+    async fn get_offer(&self, id: &str) -> Result<Option<Offer>, ServiceError> {
+        let offer: Option<Json<Offer>> =
+            sqlx::query_scalar("SELECT body FROM offers WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(offer.map(|o| o.0))
+    }
+
+    async fn query_offers(
+        &self,
+        request_id: &str,
+        walker_id: Option<&str>,
+    ) -> Result<Vec<Offer>, ServiceError> {
+        let offers: Vec<Json<Offer>> = sqlx::query_scalar(
+            "SELECT body FROM offers WHERE request_id = $1 \
+             AND ($2::TEXT IS NULL OR walker_id = $2) ORDER BY created_at",
+        )
+        .bind(request_id)
+        .bind(walker_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(offers.into_iter().map(|o| o.0).collect())
+    }
+
+    async fn save_outbox_message(&self, message: &OutboxMessage) -> Result<(), ServiceError> {
+        let query = sqlx::query(
+            "INSERT INTO outbox (id, status, next_attempt_at, created_at, body) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET \
+             status = EXCLUDED.status, next_attempt_at = EXCLUDED.next_attempt_at, \
+             body = EXCLUDED.body",
+        )
+        .bind(&message.id)
+        .bind(enum_name(message.status)?)
+        .bind(message.next_attempt_at)
+        .bind(message.created_at)
+        .bind(Json(message));
+        match &self.transaction {
+            Some(transaction) => query.execute(&mut **transaction.lock().await).await?,
+            None => query.execute(&self.pool).await?,
+        };
+        Ok(())
+    }
+
+    async fn get_outbox_message(&self, id: &str) -> Result<Option<OutboxMessage>, ServiceError> {
+        let message: Option<Json<OutboxMessage>> =
+            sqlx::query_scalar("SELECT body FROM outbox WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(message.map(|m| m.0))
+    }
+
+    async fn delete_outbox_message(&self, id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM outbox WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn due_outbox_messages(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let messages: Vec<Json<OutboxMessage>> = sqlx::query_scalar(
+            "SELECT body FROM outbox WHERE status = $1 AND next_attempt_at <= $2 \
+             ORDER BY created_at LIMIT $3",
+        )
+        .bind(enum_name(OutboxStatus::Pending)?)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages.into_iter().map(|m| m.0).collect())
+    }
+
+    async fn dead_outbox_messages(
+        &self,
+        pagination: Pagination,
+    ) -> Result<Vec<OutboxMessage>, ServiceError> {
+        let messages: Vec<Json<OutboxMessage>> = sqlx::query_scalar(
+            "SELECT body FROM outbox WHERE status = $1 ORDER BY created_at DESC \
+             LIMIT $2 OFFSET $3",
+        )
+        .bind(enum_name(OutboxStatus::DeadLettered)?)
+        .bind(pagination.size.max(0))
+        .bind(((pagination.page - 1) * pagination.size).max(0))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages.into_iter().map(|m| m.0).collect())
+    }
+
+    async fn save_start_reminders(&self, reminders: &[StartReminder]) -> Result<(), ServiceError> {
+        for reminder in reminders {
+            sqlx::query(
+                "INSERT INTO start_reminders (id, request_id, remind_at, body) \
+                 VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO NOTHING",
+            )
+            .bind(&reminder.id)
+            .bind(&reminder.request_id)
+            .bind(reminder.remind_at)
+            .bind(Json(reminder))
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn due_start_reminders(
+        &self,
+        now: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<StartReminder>, ServiceError> {
+        let reminders: Vec<Json<StartReminder>> = sqlx::query_scalar(
+            "SELECT body FROM start_reminders WHERE remind_at <= $1 ORDER BY remind_at LIMIT $2",
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(reminders.into_iter().map(|r| r.0).collect())
+    }
+
+    async fn delete_start_reminder(&self, id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM start_reminders WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_start_reminders(&self, request_id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM start_reminders WHERE request_id = $1")
+            .bind(request_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_walk_schedule(&self, schedule: &WalkSchedule) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO walk_schedules (id, owner_id, canceled_at, created_at, body) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (id) DO UPDATE SET \
+             canceled_at = EXCLUDED.canceled_at, body = EXCLUDED.body",
+        )
+        .bind(&schedule.id)
+        .bind(&schedule.owner_id)
+        .bind(schedule.canceled_at)
+        .bind(schedule.created_at)
+        .bind(Json(schedule))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_walk_schedule(&self, id: &str) -> Result<Option<WalkSchedule>, ServiceError> {
+        let schedule: Option<Json<WalkSchedule>> =
+            sqlx::query_scalar("SELECT body FROM walk_schedules WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(schedule.map(|s| s.0))
+    }
+
+    async fn query_walk_schedules(
+        &self,
+        owner_id: Option<&str>,
+    ) -> Result<Vec<WalkSchedule>, ServiceError> {
+        let schedules: Vec<Json<WalkSchedule>> = sqlx::query_scalar(
+            "SELECT body FROM walk_schedules \
+             WHERE canceled_at IS NULL AND ($1::TEXT IS NULL OR owner_id = $1) ORDER BY created_at",
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(schedules.into_iter().map(|s| s.0).collect())
+    }
+
+    async fn save_walk_request_template(
+        &self,
+        template: &WalkRequestTemplate,
+    ) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO walk_request_templates (id, owner_id, created_at, body) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&template.id)
+        .bind(&template.owner_id)
+        .bind(template.created_at)
+        .bind(Json(template))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_walk_request_template(
+        &self,
+        id: &str,
+    ) -> Result<Option<WalkRequestTemplate>, ServiceError> {
+        let template: Option<Json<WalkRequestTemplate>> =
+            sqlx::query_scalar("SELECT body FROM walk_request_templates WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(template.map(|t| t.0))
+    }
+
+    async fn query_walk_request_templates(
+        &self,
+        owner_id: &str,
+    ) -> Result<Vec<WalkRequestTemplate>, ServiceError> {
+        let templates: Vec<Json<WalkRequestTemplate>> = sqlx::query_scalar(
+            "SELECT body FROM walk_request_templates WHERE owner_id = $1 ORDER BY created_at",
+        )
+        .bind(owner_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(templates.into_iter().map(|t| t.0).collect())
+    }
+
+    async fn delete_walk_request_template(&self, id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM walk_request_templates WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_saved_search(&self, search: &SavedSearch) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO saved_searches (id, walker_id, notify, tags, created_at, body) \
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO UPDATE SET \
+             notify = EXCLUDED.notify, tags = EXCLUDED.tags, body = EXCLUDED.body",
+        )
+        .bind(&search.id)
+        .bind(&search.walker_id)
+        .bind(search.notify)
+        .bind(&search.tags)
+        .bind(search.created_at)
+        .bind(Json(search))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_saved_search(&self, id: &str) -> Result<Option<SavedSearch>, ServiceError> {
+        let search: Option<Json<SavedSearch>> =
+            sqlx::query_scalar("SELECT body FROM saved_searches WHERE id = $1")
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(search.map(|s| s.0))
+    }
+
+    async fn query_saved_searches(
+        &self,
+        walker_id: &str,
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        let searches: Vec<Json<SavedSearch>> = sqlx::query_scalar(
+            "SELECT body FROM saved_searches WHERE walker_id = $1 ORDER BY created_at",
+        )
+        .bind(walker_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(searches.into_iter().map(|s| s.0).collect())
+    }
+
+    async fn query_notifying_saved_searches(
+        &self,
+        tags: &[String],
+    ) -> Result<Vec<SavedSearch>, ServiceError> {
+        let searches: Vec<Json<SavedSearch>> = sqlx::query_scalar(
+            "SELECT body FROM saved_searches WHERE notify AND tags <@ $1 ORDER BY created_at",
+        )
+        .bind(tags)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(searches.into_iter().map(|s| s.0).collect())
+    }
+
+    async fn delete_saved_search(&self, id: &str) -> Result<(), ServiceError> {
+        sqlx::query("DELETE FROM saved_searches WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_backfill_run(&self, name: &str) -> Result<Option<BackfillRun>, ServiceError> {
+        let run: Option<Json<BackfillRun>> =
+            sqlx::query_scalar("SELECT body FROM backfill_runs WHERE name = $1")
+                .bind(name)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(run.map(|r| r.0))
+    }
+
+    async fn save_backfill_run(&self, run: &BackfillRun) -> Result<(), ServiceError> {
+        sqlx::query(
+            "INSERT INTO backfill_runs (name, body) VALUES ($1, $2) \
+             ON CONFLICT (name) DO UPDATE SET body = EXCLUDED.body",
+        )
+        .bind(&run.name)
+        .bind(Json(run))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn record_event(&self, event: &WalkRequestEvent) -> Result<(), ServiceError> {
+        sqlx::query("INSERT INTO events (request_id, occurred_at, body) VALUES ($1, $2, $3)")
+            .bind(&event.request_id)
+            .bind(event.occurred_at)
+            .bind(Json(event))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn query_events(&self, request_id: &str) -> Result<Vec<WalkRequestEvent>, ServiceError> {
+        let events: Vec<Json<WalkRequestEvent>> = sqlx::query_scalar(
+            "SELECT body FROM events WHERE request_id = $1 ORDER BY occurred_at, id",
+        )
+        .bind(request_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(events.into_iter().map(|e| e.0).collect())
+    }
+
+    async fn create_message(&self, message: &Message, recipient: &str) -> Result<(), ServiceError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO messages (id, request_id, sender, body, sent_at) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&message.id)
+        .bind(&message.request_id)
+        .bind(&message.sender)
+        .bind(&message.body)
+        .bind(message.sent_at)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO message_reads (request_id, user_id, unread) VALUES ($1, $2, 1) \
+             ON CONFLICT (request_id, user_id) DO UPDATE SET \
+             unread = message_reads.unread + 1",
+        )
+        .bind(&message.request_id)
+        .bind(recipient)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn query_messages(
+        &self,
+        request_id: &str,
+        after: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Message>, ServiceError> {
+        let rows = sqlx::query(
+            "SELECT id, request_id, sender, body, sent_at FROM messages \
+             WHERE request_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR sent_at > $2) \
+             ORDER BY sent_at, id LIMIT $3",
+        )
+        .bind(request_id)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Message {
+                    id: row.try_get("id")?,
+                    request_id: row.try_get("request_id")?,
+                    sender: row.try_get("sender")?,
+                    body: row.try_get("body")?,
+                    sent_at: row.try_get("sent_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_messages_read(
+        &self,
+        request_id: &str,
+        user_id: &str,
+    ) -> Result<(), ServiceError> {
+        sqlx::query("UPDATE message_reads SET unread = 0 WHERE request_id = $1 AND user_id = $2")
+            .bind(request_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn unread_messages(
+        &self,
+        user_id: &str,
+        request_ids: &[String],
+    ) -> Result<HashMap<String, u64>, ServiceError> {
+        let rows: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT request_id, unread FROM message_reads \
+             WHERE user_id = $1 AND request_id = ANY($2) AND unread > 0",
+        )
+        .bind(user_id)
+        .bind(request_ids)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|(request_id, unread)| (request_id, unread as u64))
+            .collect())
+    }
+
+    async fn record_api_call(
+        &self,
+        api_key: &str,
+        endpoint: &str,
+        month: &str,
+        rejected: bool,
+    ) -> Result<(), ServiceError> {
+        let (calls, rejected) = if rejected { (0i64, 1i64) } else { (1, 0) };
+        sqlx::query(
+            "INSERT INTO api_usage (api_key, endpoint, month, calls, rejected) \
+             VALUES ($1, $2, $3, $4, $5) ON CONFLICT (api_key, endpoint, month) DO UPDATE SET \
+             calls = api_usage.calls + EXCLUDED.calls, \
+             rejected = api_usage.rejected + EXCLUDED.rejected",
+        )
+        .bind(api_key)
+        .bind(endpoint)
+        .bind(month)
+        .bind(calls)
+        .bind(rejected)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn query_api_usage(
+        &self,
+        month: &str,
+        api_key: Option<&str>,
+    ) -> Result<Vec<UsageRecord>, ServiceError> {
+        let rows = sqlx::query(
+            "SELECT api_key, endpoint, month, calls, rejected FROM api_usage \
+             WHERE month = $1 AND ($2::TEXT IS NULL OR api_key = $2) ORDER BY api_key, endpoint",
+        )
+        .bind(month)
+        .bind(api_key)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.into_iter()
+            .map(|row| -> Result<UsageRecord, ServiceError> {
+                Ok(UsageRecord {
+                    api_key: row.try_get("api_key")?,
+                    endpoint: row.try_get("endpoint")?,
+                    month: row.try_get("month")?,
+                    calls: row.try_get::<i64, _>("calls")? as u64,
+                    rejected: row.try_get::<i64, _>("rejected")? as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn query_stuck_sagas(
+        &self,
+        updated_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<BookingSaga>, ServiceError> {
+        let sagas: Vec<Json<BookingSaga>> = sqlx::query_scalar(
+            "SELECT body FROM booking_sagas \
+             WHERE (status = $1 AND updated_at < $2) OR status = $3 ORDER BY updated_at LIMIT $4",
+        )
+        .bind(enum_name(SagaStatus::Running)?)
+        .bind(updated_before)
+        .bind(enum_name(SagaStatus::Failed)?)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sagas.into_iter().map(|s| s.0).collect())
+    }
+}
@@ -0,0 +1,94 @@
+use std::rc::Rc;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{forward_ready, Service as ActixService, ServiceRequest, ServiceResponse, Transform},
+    http::header::{HeaderName, HeaderValue},
+    Error,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+use tracing::Instrument;
+
+use crate::core::{correlation, ids::new_ulid};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Ids callers send are kept up to this length, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Tags each call with the `X-Request-ID` it came with, or a new one, so
+/// its log lines can be told apart and followed across services. The id is
+/// set on the request for the access log, returned in the response and
+/// carried by the faults the call runs into, see `correlation::current`.
+#[derive(Default)]
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> ActixService<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let (id, value) = match req.headers().get(&REQUEST_ID).filter(|v| is_valid(v)) {
+            // Visible ASCII, which `to_str` takes.
+            Some(value) => (value.to_str().unwrap_or_default().to_owned(), value.clone()),
+            None => {
+                let id = new_ulid();
+                let value = HeaderValue::from_str(&id).expect("ULIDs are valid header values");
+                (id, value)
+            }
+        };
+        req.headers_mut().insert(REQUEST_ID, value.clone());
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.path(),
+        );
+        Box::pin(correlation::scope(
+            id,
+            async move {
+                let mut res = service.call(req).await?;
+                res.headers_mut().insert(REQUEST_ID, value);
+                Ok(res)
+            }
+            .instrument(span),
+        ))
+    }
+}
+
+fn is_valid(value: &HeaderValue) -> bool {
+    let bytes = value.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= MAX_REQUEST_ID_LEN
+        && bytes.iter().all(|b| b.is_ascii_graphic())
+}
@@ -0,0 +1,238 @@
+use actix_web::{
+    body::{to_bytes, BoxBody, MessageBody},
+    dev::ServiceResponse,
+    error::ErrorInternalServerError,
+    http::header::{HeaderValue, ACCEPT_LANGUAGE, CONTENT_TYPE},
+    Error, HttpRequest,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::core::timezone::display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Casing {
+    #[serde(rename = "snake_case")]
+    Snake,
+    #[serde(rename = "camelCase")]
+    Camel,
+}
+
+impl Casing {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.to_owned())).ok()
+    }
+}
+
+/// How v2 JSON responses are shaped: field casing, and `null` fields are
+/// always omitted so optional fields are either present or absent.
+#[derive(Debug, Clone, Copy)]
+pub struct ResponsePolicy {
+    pub casing: Casing,
+}
+
+fn camel_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' {
+            upper = !out.is_empty();
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+impl ResponsePolicy {
+    pub fn shape(&self, value: Value) -> Value {
+        match value {
+            Value::Object(fields) => Value::Object(
+                fields
+                    .into_iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| {
+                        let key = match self.casing {
+                            Casing::Snake => k,
+                            Casing::Camel => camel_case(&k),
+                        };
+                        (key, self.shape(v))
+                    })
+                    .collect::<Map<String, Value>>(),
+            ),
+            Value::Array(items) => Value::Array(items.into_iter().map(|v| self.shape(v)).collect()),
+            other => other,
+        }
+    }
+
+    /// Rewrites a JSON response body according to the policy, other bodies
+    /// are passed through untouched.
+    pub async fn apply<B>(&self, res: ServiceResponse<B>) -> Result<ServiceResponse<BoxBody>, Error>
+    where
+        B: MessageBody + 'static,
+    {
+        rewrite_json(res, |value| self.shape(value)).await
+    }
+}
+
+/// Preferred language of the caller, the first tag of `Accept-Language`.
+pub fn preferred_locale(req: &HttpRequest) -> String {
+    req.headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(|tag| tag.split(';').next().unwrap_or_default().trim().to_owned())
+        .filter(|tag| !tag.is_empty() && tag != "*")
+        .unwrap_or_else(|| "en-US".to_owned())
+}
+
+/// Whether the caller opted into localized display times with `display_times=true`.
+pub fn wants_display_times(req: &HttpRequest) -> bool {
+    req.query_string()
+        .split('&')
+        .any(|pair| pair == "display_times=true")
+}
+
+/// Adds a `<field>_display` string next to every timestamp of objects carrying
+/// a `timezone`, rendered in that timezone for `locale`.
+pub fn add_display_times(value: Value, locale: &str) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let timezone = fields
+                .get("timezone")
+                .and_then(Value::as_str)
+                .map(str::to_owned);
+            let mut out = Map::new();
+            for (key, value) in fields {
+                let shown = timezone.as_ref().and_then(|tz| {
+                    value
+                        .as_str()
+                        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                        .map(|t| display(&t.with_timezone(&Utc), Some(tz), locale))
+                });
+                if let Some(shown) = shown {
+                    out.insert(format!("{}_display", key), Value::String(shown));
+                }
+                out.insert(key, add_display_times(value, locale));
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|v| add_display_times(v, locale))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Unit distances of search results are returned in, `m` unless asked for
+/// with `unit=` or configured otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistanceUnit {
+    #[default]
+    M,
+    Km,
+    Mi,
+}
+
+const METERS_PER_MILE: f64 = 1609.344;
+
+impl DistanceUnit {
+    pub fn parse(name: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(name.to_owned())).ok()
+    }
+
+    /// Meters in this unit, whole meters or hundredths of the larger units.
+    pub fn convert(self, meters: f64) -> f64 {
+        match self {
+            DistanceUnit::M => meters.round(),
+            DistanceUnit::Km => (meters / 10.0).round() / 100.0,
+            DistanceUnit::Mi => (meters / METERS_PER_MILE * 100.0).round() / 100.0,
+        }
+    }
+
+    /// e.g. `850 m`, `1.2 km`.
+    pub fn text(self, meters: f64) -> String {
+        match self {
+            DistanceUnit::M => format!("{} m", meters.round()),
+            DistanceUnit::Km => format!("{:.1} km", meters / 1000.0),
+            DistanceUnit::Mi => format!("{:.1} mi", meters / METERS_PER_MILE),
+        }
+    }
+}
+
+/// The unit asked for with `unit=`, `None` when absent or unknown.
+pub fn requested_unit(req: &HttpRequest) -> Option<DistanceUnit> {
+    req.query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("unit="))
+        .and_then(DistanceUnit::parse)
+}
+
+/// Converts the `distance` of located objects, those carrying a `latitude`
+/// and `longitude`, from meters to `unit` and adds a `distance_text` next to
+/// it. Other distances, such as walk summaries', stay in meters.
+pub fn convert_distances(value: Value, unit: DistanceUnit) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let located = fields.contains_key("latitude") && fields.contains_key("longitude");
+            let mut out = Map::new();
+            for (key, value) in fields {
+                match value.as_f64().filter(|_| located && key == "distance") {
+                    Some(meters) => {
+                        out.insert("distance_text".to_owned(), Value::String(unit.text(meters)));
+                        out.insert(key, Value::from(unit.convert(meters)));
+                    }
+                    None => {
+                        out.insert(key, convert_distances(value, unit));
+                    }
+                }
+            }
+            Value::Object(out)
+        }
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|v| convert_distances(v, unit))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+/// Passes JSON response bodies through `f`, other bodies are left untouched.
+pub async fn rewrite_json<B, F>(
+    res: ServiceResponse<B>,
+    f: F,
+) -> Result<ServiceResponse<BoxBody>, Error>
+where
+    B: MessageBody + 'static,
+    F: FnOnce(Value) -> Value,
+{
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.starts_with("application/json"));
+    if !is_json {
+        return Ok(res.map_into_boxed_body());
+    }
+    let (req, res) = res.into_parts();
+    let (res, body) = res.into_parts();
+    let bytes = to_bytes(body)
+        .await
+        .map_err(|_| ErrorInternalServerError("读取响应失败"))?;
+    let value: Value = serde_json::from_slice(&bytes).map_err(ErrorInternalServerError)?;
+    let body = serde_json::to_vec(&f(value)).map_err(ErrorInternalServerError)?;
+    let mut res = res.set_body(BoxBody::new(body));
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(ServiceResponse::new(req, res))
+}
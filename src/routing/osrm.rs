@@ -0,0 +1,86 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::core::{entities::WalkingLocation, routing::RoutingProvider};
+
+/// OSRM's match service refuses longer traces by default, tracks are matched
+/// in chunks of this many points.
+const MAX_MATCH_POINTS: usize = 100;
+
+/// Client for an OSRM server's match API.
+pub struct Osrm {
+    base_url: String,
+    profile: String,
+    client: reqwest::Client,
+}
+
+impl Osrm {
+    pub fn new(base_url: &str, profile: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            profile: profile.to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Matched {
+    code: String,
+    tracepoints: Vec<Option<Tracepoint>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tracepoint {
+    location: [f64; 2],
+}
+
+#[async_trait]
+impl RoutingProvider for Osrm {
+    async fn match_track(
+        &self,
+        track: &[WalkingLocation],
+    ) -> Result<Vec<Option<(f64, f64)>>, Error> {
+        let mut points = Vec::with_capacity(track.len());
+        for chunk in track.chunks(MAX_MATCH_POINTS) {
+            if chunk.len() < 2 {
+                points.push(None);
+                continue;
+            }
+            let coordinates = chunk
+                .iter()
+                .map(|l| format!("{},{}", l.longitude, l.latitude))
+                .collect::<Vec<_>>()
+                .join(";");
+            let mut request = self
+                .client
+                .get(format!(
+                    "{}/match/v1/{}/{}",
+                    self.base_url, self.profile, coordinates
+                ))
+                .query(&[("overview", "false")]);
+            // Timestamps help matching but must be given for every point.
+            let timestamps: Option<Vec<String>> = chunk
+                .iter()
+                .map(|l| l.created_at.map(|t| t.timestamp().to_string()))
+                .collect();
+            if let Some(timestamps) = timestamps {
+                request = request.query(&[("timestamps", timestamps.join(";"))]);
+            }
+            let matched = request.send().await?.json::<Matched>().await?;
+            // NoMatch and friends leave the chunk as recorded.
+            if matched.code != "Ok" {
+                points.extend(chunk.iter().map(|_| None));
+                continue;
+            }
+            points.extend(
+                matched
+                    .tracepoints
+                    .into_iter()
+                    .map(|t| t.map(|t| (t.location[0], t.location[1]))),
+            );
+        }
+        Ok(points)
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Error;
+use async_trait::async_trait;
+
+use crate::core::route_map::StaticMapProvider;
+
+/// Client for a static maps API taking the route as an encoded polyline
+/// path, in the style of Google's and most of its clones.
+pub struct HttpStaticMaps {
+    url: String,
+    key: String,
+    client: reqwest::Client,
+}
+
+impl HttpStaticMaps {
+    pub fn new(url: &str, key: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            key: key.to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl StaticMapProvider for HttpStaticMaps {
+    async fn render(&self, polyline: &str, width: u32, height: u32) -> Result<Vec<u8>, Error> {
+        let mut query = vec![
+            ("size", format!("{}x{}", width, height)),
+            ("format", "png".to_owned()),
+            (
+                "path",
+                format!("color:0xff7a00ff|weight:4|enc:{}", polyline),
+            ),
+        ];
+        if !self.key.is_empty() {
+            query.push(("key", self.key.clone()));
+        }
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    future::Future,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use actix_web::rt::{task::JoinHandle, time::sleep};
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use serde::Serialize;
+
+/// First pause before restarting a failed worker, doubled on every failure
+/// in a row.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A worker which ran this long before failing starts over from the initial
+/// backoff.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    Running,
+    /// Failed, waiting out the backoff before it is started again.
+    Restarting,
+    /// Failed and not restartable, see `Supervisor::supervise_once`.
+    Failed,
+    /// Stopped by shutdown.
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkerHealth {
+    pub name: &'static str,
+    pub state: WorkerState,
+    pub restarts: u32,
+    pub last_error: Option<String>,
+    /// When the worker entered its state.
+    pub since: DateTime<Utc>,
+}
+
+impl WorkerHealth {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            state: WorkerState::Running,
+            restarts: 0,
+            last_error: None,
+            since: Utc::now(),
+        }
+    }
+}
+
+/// Owns the background workers: restarts those which fail, reports their
+/// health and stops them on shutdown.
+#[derive(Clone, Default)]
+pub struct Supervisor {
+    workers: Arc<Mutex<BTreeMap<&'static str, WorkerHealth>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    stopping: Arc<AtomicBool>,
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => (*message).to_owned(),
+            Err(_) => "panicked".to_owned(),
+        },
+    }
+}
+
+impl Supervisor {
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut WorkerHealth)) {
+        let mut workers = self.workers.lock().unwrap();
+        let health = workers
+            .entry(name)
+            .or_insert_with(|| WorkerHealth::new(name));
+        update(health);
+    }
+
+    fn set_state(&self, name: &'static str, state: WorkerState, error: Option<String>) {
+        self.update(name, |health| {
+            health.state = state;
+            health.since = Utc::now();
+            if error.is_some() {
+                health.last_error = error;
+            }
+        });
+    }
+
+    /// Runs the worker `start` makes, starting a new one with backoff each
+    /// time it returns or panics. Workers are expected to run until shutdown.
+    pub fn supervise<F, Fut>(&self, name: &'static str, start: F)
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.update(name, |_| {});
+        let supervisor = self.clone();
+        let task = actix_web::rt::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let started = Instant::now();
+                let error = match AssertUnwindSafe(start()).catch_unwind().await {
+                    Ok(()) => "worker returned".to_owned(),
+                    Err(panic) => panic_message(panic),
+                };
+                if supervisor.stopping.load(Ordering::SeqCst) {
+                    return;
+                }
+                if started.elapsed() >= HEALTHY_RUN {
+                    backoff = INITIAL_BACKOFF;
+                }
+                log::error!(
+                    "worker {} stopped, restarting in {:?}: {}",
+                    name,
+                    backoff,
+                    error
+                );
+                supervisor.set_state(name, WorkerState::Restarting, Some(error));
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                supervisor.update(name, |health| health.restarts += 1);
+                supervisor.set_state(name, WorkerState::Running, None);
+            }
+        });
+        self.tasks.lock().unwrap().push(task);
+    }
+
+    /// Supervises a worker started from a clone of `state` each time.
+    pub fn supervise_with<T, F, Fut>(&self, name: &'static str, state: T, start: F)
+    where
+        T: Clone + 'static,
+        F: Fn(T) -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.supervise(name, move || start(state.clone()))
+    }
+
+    /// Watches a worker which can't be started again, such as a queue
+    /// consumer owning the receiving end of its queue. It is left to finish
+    /// on its own at shutdown, ending earlier marks it failed.
+    pub fn supervise_once<Fut>(&self, name: &'static str, worker: Fut)
+    where
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.update(name, |_| {});
+        let supervisor = self.clone();
+        actix_web::rt::spawn(async move {
+            let outcome = AssertUnwindSafe(worker).catch_unwind().await;
+            if supervisor.stopping.load(Ordering::SeqCst) {
+                supervisor.set_state(name, WorkerState::Stopped, None);
+                return;
+            }
+            let error = match outcome {
+                Ok(()) => "worker returned".to_owned(),
+                Err(panic) => panic_message(panic),
+            };
+            log::error!("worker {} stopped: {}", name, error);
+            supervisor.set_state(name, WorkerState::Failed, Some(error));
+        });
+    }
+
+    pub fn health(&self) -> Vec<WorkerHealth> {
+        self.workers.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Whether every worker is running.
+    pub fn ready(&self) -> bool {
+        self.workers
+            .lock()
+            .unwrap()
+            .values()
+            .all(|w| w.state == WorkerState::Running)
+    }
+
+    /// Stops the restartable workers, waiting for them to be dropped. Their
+    /// iterations are idempotent, cutting one short is redone by the next
+    /// run.
+    pub async fn shutdown(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let tasks: Vec<_> = self.tasks.lock().unwrap().drain(..).collect();
+        for task in &tasks {
+            task.abort();
+        }
+        for task in tasks {
+            let _ = task.await;
+        }
+        let mut workers = self.workers.lock().unwrap();
+        for health in workers.values_mut() {
+            if health.state != WorkerState::Failed {
+                health.state = WorkerState::Stopped;
+                health.since = Utc::now();
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::{marker::PhantomData, rc::Rc};
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{
+        forward_ready, Extensions, Service as ActixService, ServiceRequest, ServiceResponse,
+        Transform,
+    },
+    web::Data,
+    Error,
+};
+use futures::future::{ready, LocalBoxFuture, Ready};
+
+use crate::core::{repository::Repository, service::Service};
+use crate::handlers::tenant_id;
+
+/// Serves calls naming a tenant, see `handlers::tenant_id`, with the service
+/// scoped to it: handlers extracting `Data<Service<R>>` get the scoped copy
+/// and only see the tenant's walk requests. Unknown tenants are refused.
+/// Calls without a tenant see every request, as before tenants.
+pub struct Tenancy<R> {
+    repository: PhantomData<R>,
+}
+
+impl<R> Default for Tenancy<R> {
+    fn default() -> Self {
+        Self {
+            repository: PhantomData,
+        }
+    }
+}
+
+impl<S, B, R> Transform<S, ServiceRequest> for Tenancy<R>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: Repository + Clone + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = TenancyMiddleware<S, R>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(TenancyMiddleware {
+            service: Rc::new(service),
+            repository: PhantomData,
+        }))
+    }
+}
+
+pub struct TenancyMiddleware<S, R> {
+    service: Rc<S>,
+    repository: PhantomData<R>,
+}
+
+impl<S, B, R> ActixService<ServiceRequest> for TenancyMiddleware<S, R>
+where
+    S: ActixService<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+    R: Repository + Clone + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        Box::pin(async move {
+            let tenant = tenant_id(req.request());
+            let app = req.app_data::<Data<Service<R>>>().cloned();
+            let (Some(tenant), Some(app)) = (tenant, app) else {
+                return service.call(req).await.map(|r| r.map_into_boxed_body());
+            };
+            let scoped = match app.for_tenant(&tenant).await {
+                Ok(scoped) => scoped,
+                Err(e) => return Ok(req.error_response(e)),
+            };
+            // Looked up before the app's own data, so it shadows the
+            // unscoped service for this request only.
+            let mut data = Extensions::new();
+            data.insert(Data::new(scoped));
+            req.add_data_container(Rc::new(data));
+            service.call(req).await.map(|r| r.map_into_boxed_body())
+        })
+    }
+}
@@ -0,0 +1,48 @@
+//! Fixtures shared by the unit tests and the integration tests, which get
+//! them through the `testing` feature.
+
+use little_walk_dog::core::entities::Dog;
+
+use crate::core::repository::WalkRequestCreate;
+
+pub const OWNER: &str = "owner";
+pub const WALKER: &str = "walker";
+
+/// A dog of `OWNER`'s as the dog service hands it out.
+pub fn dog() -> Dog {
+    Dog {
+        id: "dog".to_owned(),
+        name: "旺财".to_owned(),
+        breed: Some("柴犬".to_owned()),
+        size: Some("medium".to_owned()),
+    }
+}
+
+/// A request of `OWNER` to walk `dog` at the location.
+pub fn create(longitude: f64, latitude: f64) -> WalkRequestCreate {
+    WalkRequestCreate {
+        dogs: vec![dog()],
+        should_start_after: None,
+        should_start_before: None,
+        should_end_before: None,
+        should_end_after: None,
+        latitude,
+        longitude,
+        timezone: "Asia/Shanghai".to_owned(),
+        region: "default".to_owned(),
+        max_applicants: None,
+        requirements: Default::default(),
+        track_visibility: Default::default(),
+        max_radius: None,
+        price: None,
+        max_walkers: None,
+        apply_before: None,
+        created_by: OWNER.to_owned(),
+        delegation: None,
+        tenant_id: None,
+        reference_code: None,
+        invited_walker_id: None,
+        invitation_expires_at: None,
+        tags: Vec::new(),
+    }
+}
@@ -0,0 +1,39 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+};
+
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Serves the certificate chain and the first private key of the PEM files.
+/// Actix offers HTTP/2 and HTTP/1.1 over ALPN on top of it.
+pub fn server_config(cert_file: &str, key_file: &str) -> io::Result<ServerConfig> {
+    let certs: Vec<Certificate> =
+        rustls_pemfile::certs(&mut BufReader::new(File::open(cert_file)?))?
+            .into_iter()
+            .map(Certificate)
+            .collect();
+    if certs.is_empty() {
+        return Err(invalid(format!("no certificate in {}", cert_file)));
+    }
+    let mut keys = BufReader::new(File::open(key_file)?);
+    let key = loop {
+        match rustls_pemfile::read_one(&mut keys)? {
+            Some(Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key)) => {
+                break PrivateKey(key)
+            }
+            Some(_) => continue,
+            None => return Err(invalid(format!("no private key in {}", key_file))),
+        }
+    };
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| invalid(e.to_string()))
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+use async_trait::async_trait;
+
+use crate::core::{
+    onboarding::{Onboarding, OnboardingDirectory},
+    profile::{UserContact, UserDirectory, UserProfile},
+    walker_capabilities::{CapabilityDirectory, WalkerCapabilities},
+};
+
+/// Client for the user service's onboarding, capabilities and profile APIs.
+pub struct HttpUsers {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl HttpUsers {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl OnboardingDirectory for HttpUsers {
+    async fn onboarding(&self, user_id: &str) -> Result<Onboarding, Error> {
+        Ok(self
+            .client
+            .get(format!("{}/users/{}/onboarding", self.base_url, user_id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Onboarding>()
+            .await?)
+    }
+}
+
+#[async_trait]
+impl CapabilityDirectory for HttpUsers {
+    async fn capabilities(&self, user_id: &str) -> Result<WalkerCapabilities, Error> {
+        Ok(self
+            .client
+            .get(format!("{}/users/{}/capabilities", self.base_url, user_id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<WalkerCapabilities>()
+            .await?)
+    }
+}
+
+#[async_trait]
+impl UserDirectory for HttpUsers {
+    async fn profiles(&self, user_ids: &[String]) -> Result<HashMap<String, UserProfile>, Error> {
+        Ok(self
+            .client
+            .get(format!("{}/users/profiles", self.base_url))
+            .query(&[("ids", user_ids.join(","))])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Vec<UserProfile>>()
+            .await?
+            .into_iter()
+            .map(|p| (p.id.clone(), p))
+            .collect())
+    }
+
+    async fn contact(&self, user_id: &str) -> Result<UserContact, Error> {
+        Ok(self
+            .client
+            .get(format!("{}/users/{}/contact", self.base_url, user_id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<UserContact>()
+            .await?)
+    }
+}
@@ -0,0 +1,62 @@
+use anyhow::Error;
+use async_trait::async_trait;
+use serde::Serialize;
+
+use crate::core::{
+    events::{EventPublisher, WalkRequestEvent},
+    experiments::Exposure,
+    security::{hmac_sha256, KeyRing},
+};
+
+/// Posts every event as JSON to a configured webhook endpoint. With signing
+/// keys, bodies are signed with the active key, receivers look the key up by
+/// `X-Webhook-Key-Id` so that they can accept both keys while rotating.
+pub struct HttpWebhook {
+    url: String,
+    client: reqwest::Client,
+    keys: KeyRing,
+}
+
+impl HttpWebhook {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            client: reqwest::Client::new(),
+            keys: KeyRing::default(),
+        }
+    }
+
+    pub fn with_keys(mut self, keys: KeyRing) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    async fn post(&self, payload: &impl Serialize) -> Result<(), Error> {
+        let body = serde_json::to_vec(payload)?;
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some((kid, secret)) = self.keys.active() {
+            request = request
+                .header(
+                    "X-Webhook-Signature",
+                    format!("sha256={}", hex::encode(hmac_sha256(&secret, &body))),
+                )
+                .header("X-Webhook-Key-Id", kid);
+        }
+        request.body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventPublisher for HttpWebhook {
+    async fn publish(&self, event: &WalkRequestEvent) -> Result<(), Error> {
+        self.post(event).await
+    }
+
+    async fn publish_exposure(&self, exposure: &Exposure) -> Result<(), Error> {
+        self.post(exposure).await
+    }
+}
@@ -0,0 +1,610 @@
+//! Drives the HTTP API against a real MongoDB, started in a container, so
+//! these tests need a Docker daemon.
+
+use actix_web::{
+    http::{Method, StatusCode},
+    test::{self, TestRequest},
+    web::Data,
+    App,
+};
+use little_walk_request::{
+    app::routes,
+    core::{
+        auth::{Authenticator, JwtVerifier},
+        events::WalkRequestEventKind,
+        holiday::{Holiday, HolidayCalendar},
+        repository::{Repository, WalkRequestCreate},
+        saga::{BookingSaga, SagaStatus},
+        security::KeyRing,
+        service::Service,
+        units::Money,
+    },
+    repositories::mongodb::Mongodb,
+    responses::{Casing, DistanceUnit, ResponsePolicy},
+    testing::{create, dog, OWNER, WALKER},
+};
+use mongodb::Client;
+use serde_json::{json, Value};
+use testcontainers::clients::Cli;
+use testcontainers_modules::mongo::Mongo;
+
+const POLICY: ResponsePolicy = ResponsePolicy {
+    casing: Casing::Snake,
+};
+
+/// A fresh database with the indexes and the feed the server bootstraps.
+async fn repository(port: u16) -> Mongodb {
+    let client = Client::with_uri_str(format!("mongodb://127.0.0.1:{}", port))
+        .await
+        .unwrap();
+    let repository = Mongodb::new(client.database("little_walk_request"));
+    repository.ensure_indexes(None).await.unwrap();
+    repository.rebuild_feed().await.unwrap();
+    repository
+}
+
+fn call(method: Method, uri: &str, user_id: &str) -> TestRequest {
+    TestRequest::default()
+        .method(method)
+        .uri(uri)
+        .insert_header(("X-User-ID", user_id))
+}
+
+#[actix_web::test]
+async fn lifecycle() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+
+    let res = test::call_service(
+        &app,
+        call(Method::POST, "/apis/walk_requests", OWNER)
+            .set_json(json!({"dogs": [], "latitude": 39.908, "longitude": 116.397}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let res = test::call_service(
+        &app,
+        call(Method::POST, "/apis/walk_requests", OWNER)
+            .set_json(json!({"dogs": [dog()], "latitude": 39.908, "longitude": 116.397}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let mine: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/mine?page=1&size=10",
+            OWNER,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(mine["items"][0]["dogs"][0]["id"], dog().id);
+    let id = mine["items"][0]["id"].as_str().unwrap().to_owned();
+    let uri = |path: &str| format!("/apis/walk_requests/{}{}", id, path);
+    let steps = [
+        (Method::POST, uri("/acceptances"), WALKER),
+        (Method::PUT, uri(&format!("/accepter/{}", WALKER)), OWNER),
+        (Method::PUT, uri("/start"), WALKER),
+    ];
+    for (method, uri, user_id) in steps {
+        let res = test::call_service(&app, call(method, &uri, user_id).to_request()).await;
+        assert!(res.status().is_success(), "{} {}", uri, res.status());
+    }
+
+    let applications: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/applications/mine?page=1&size=10",
+            WALKER,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(applications[0]["request_id"], id.as_str());
+    assert!(applications[0]["applied_at"].is_string());
+
+    let written: Value = test::call_and_read_body_json(
+        &app,
+        call(Method::POST, &uri("/locations"), WALKER)
+            .set_json(json!({"longitude": 116.398, "latitude": 39.909}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(written["status"], "stored");
+
+    let finished: Value = test::call_and_read_body_json(
+        &app,
+        call(Method::PUT, &uri("/finish"), WALKER).to_request(),
+    )
+    .await;
+    assert_eq!(finished["status"], "Finished");
+    assert_eq!(finished["accepted_by"], WALKER);
+
+    let locations: Value = test::call_and_read_body_json(
+        &app,
+        call(Method::GET, &uri("/locations"), OWNER).to_request(),
+    )
+    .await;
+    assert_eq!(locations.as_array().unwrap().len(), 1);
+    let res = test::call_service(
+        &app,
+        TestRequest::get().uri(&uri("/locations")).to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn nearby_uses_geo_near() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    // Tiananmen, the Forbidden City, the Summer Palace and Shanghai.
+    let mut seeded = Vec::new();
+    for (longitude, latitude) in [
+        (116.3975, 39.9087),
+        (116.3972, 39.9163),
+        (116.2755, 39.9999),
+        (121.4737, 31.2304),
+    ] {
+        let id = service
+            .create_walk_request(create(longitude, latitude))
+            .await
+            .unwrap();
+        seeded.push(id);
+    }
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+
+    let nearby: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/nearby?latitude=39.9088&longitude=116.3974&radius=20000&size=10",
+            WALKER,
+        )
+        .to_request(),
+    )
+    .await;
+    let ids: Vec<&str> = nearby["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, seeded[..3]);
+    assert_eq!(nearby["total"], 3);
+
+    let nearby: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/nearby?latitude=39.9088&longitude=116.3974&radius=2000&size=10",
+            WALKER,
+        )
+        .to_request(),
+    )
+    .await;
+    assert_eq!(nearby["items"].as_array().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn nearby_leaves_out_closed_requests() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    let mut open = Vec::new();
+    for apply_before in [
+        None,
+        Some(chrono::Utc::now() + chrono::Duration::hours(1)),
+        Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+    ] {
+        let id = service
+            .create_walk_request(WalkRequestCreate {
+                apply_before,
+                ..create(116.397, 39.908)
+            })
+            .await
+            .unwrap();
+        open.push(id);
+    }
+    open.pop();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+
+    let nearby: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/nearby?latitude=39.9088&longitude=116.3974&radius=2000&size=10",
+            WALKER,
+        )
+        .to_request(),
+    )
+    .await;
+    let mut ids: Vec<&str> = nearby["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    ids.sort();
+    open.sort();
+    assert_eq!(ids, open);
+}
+
+#[actix_web::test]
+async fn nearby_shows_expired_invitations_to_everyone() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let repository = repository(mongo.get_host_port_ipv4(27017)).await;
+    let service = Service::new(repository.clone());
+    service.add_favorite_walker(OWNER, WALKER).await.unwrap();
+    let invite = || WalkRequestCreate {
+        invited_walker_id: Some(WALKER.to_owned()),
+        ..create(116.397, 39.908)
+    };
+    let expired = service
+        .clone()
+        .with_invitation_timeout(chrono::Duration::zero())
+        .create_walk_request(invite())
+        .await
+        .unwrap();
+    let pending = service.create_walk_request(invite()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+    let uri = "/apis/walk_requests/nearby?latitude=39.9088&longitude=116.3974&radius=2000&size=10";
+
+    let nearby: Value =
+        test::call_and_read_body_json(&app, call(Method::GET, uri, "stranger").to_request()).await;
+    let ids: Vec<&str> = nearby["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, [expired.as_str()]);
+
+    let nearby: Value =
+        test::call_and_read_body_json(&app, call(Method::GET, uri, WALKER).to_request()).await;
+    let mut ids: Vec<&str> = nearby["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    ids.sort();
+    let mut invited = [expired.as_str(), pending.as_str()];
+    invited.sort();
+    assert_eq!(ids, invited);
+}
+
+#[actix_web::test]
+async fn owner_cancels() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    let id = service
+        .create_walk_request(create(116.397, 39.908))
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+    let uri = |path: &str| format!("/apis/walk_requests/{}{}", id, path);
+
+    let res = test::call_service(
+        &app,
+        call(Method::DELETE, &uri(""), OWNER)
+            .set_json(json!({"reason": "改天再遛"}))
+            .to_request(),
+    )
+    .await;
+    assert!(res.status().is_success(), "{}", res.status());
+
+    let canceled: Value =
+        test::call_and_read_body_json(&app, call(Method::GET, &uri(""), OWNER).to_request()).await;
+    assert_eq!(canceled["status"], "Canceled");
+    assert_eq!(canceled["canceled_by"], OWNER);
+    assert_eq!(canceled["cancellation_reason"], "改天再遛");
+    assert!(canceled["canceled_at"].is_string());
+
+    let res = test::call_service(
+        &app,
+        call(Method::POST, &uri("/acceptances"), WALKER).to_request(),
+    )
+    .await;
+    assert!(!res.status().is_success());
+}
+
+#[actix_web::test]
+async fn cancellations_are_finalized_once() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let repository = repository(mongo.get_host_port_ipv4(27017)).await;
+    let service =
+        Service::new(repository.clone()).with_cancel_undo_window(chrono::Duration::zero());
+    let id = service
+        .create_walk_request(create(116.397, 39.908))
+        .await
+        .unwrap();
+    service
+        .cancel_unaccepted_request(&id, OWNER, None)
+        .await
+        .unwrap();
+
+    assert_eq!(service.finalize_cancellations().await.unwrap(), 1);
+    assert_eq!(service.finalize_cancellations().await.unwrap(), 0);
+    let request = service.get_walk_request(&id).await.unwrap().unwrap();
+    assert!(request.canceled_at.is_some());
+    let canceled = repository
+        .query_events(&id)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|e| e.kind == WalkRequestEventKind::Canceled)
+        .count();
+    assert_eq!(canceled, 1);
+}
+
+#[actix_web::test]
+async fn stuck_sagas_are_found() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let repository = repository(mongo.get_host_port_ipv4(27017)).await;
+    let service = Service::new(repository.clone());
+    let mut stale = BookingSaga::new("stale", WALKER);
+    stale.updated_at = stale.updated_at - chrono::Duration::hours(1);
+    let mut failed = BookingSaga::new("failed", WALKER);
+    failed.finish(SagaStatus::Failed, None);
+    for saga in [&stale, &failed, &BookingSaga::new("fresh", WALKER)] {
+        repository.save_saga(saga).await.unwrap();
+    }
+
+    let stuck = service
+        .stuck_sagas(chrono::Duration::minutes(5), 10)
+        .await
+        .unwrap();
+    let ids: Vec<&str> = stuck.iter().map(|s| s.request_id.as_str()).collect();
+    assert_eq!(ids, ["stale", "failed"]);
+    let stuck = service
+        .stuck_sagas(chrono::Duration::minutes(5), 1)
+        .await
+        .unwrap();
+    assert_eq!(stuck.len(), 1);
+}
+
+#[actix_web::test]
+async fn holidays_price_and_hours() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let national_day = chrono::NaiveDate::from_ymd_opt(2030, 10, 1).unwrap();
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await).with_holidays(
+        HolidayCalendar::new(
+            vec![Holiday {
+                region: "default".to_owned(),
+                date: national_day,
+                name: "国庆节".to_owned(),
+                price_multiplier: 2.0,
+                opens_at: chrono::NaiveTime::from_hms_opt(9, 0, 0),
+                closes_at: chrono::NaiveTime::from_hms_opt(17, 0, 0),
+            }],
+            std::time::Duration::from_secs(300),
+        ),
+    );
+    // 10:00 and 20:00 in Shanghai.
+    let mut ids = Vec::new();
+    for hour in [2, 12] {
+        let id = service
+            .create_walk_request(WalkRequestCreate {
+                should_start_after: Some(national_day.and_hms_opt(hour, 0, 0).unwrap().and_utc()),
+                price: Some(Money::new(5000, "CNY")),
+                ..create(116.397, 39.908)
+            })
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+    let priced = service.get_walk_request(&ids[0]).await.unwrap().unwrap();
+    assert_eq!(priced.price.unwrap().minor_units, 10000);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+
+    let nearby: Value = test::call_and_read_body_json(
+        &app,
+        call(
+            Method::GET,
+            "/apis/walk_requests/nearby?latitude=39.9088&longitude=116.3974&radius=2000&size=10",
+            WALKER,
+        )
+        .to_request(),
+    )
+    .await;
+    let open: Vec<&str> = nearby["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(open, [ids[0].as_str()]);
+    assert_eq!(nearby["total"], 1);
+}
+
+#[actix_web::test]
+async fn steps_out_of_order_are_refused() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    let id = service
+        .create_walk_request(create(116.397, 39.908))
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+    let uri = |path: &str| format!("/apis/walk_requests/{}{}", id, path);
+
+    let refused = |user_id: &'static str, path: &str| call(Method::PUT, &uri(path), user_id);
+    for req in [refused(WALKER, "/start"), refused(WALKER, "/finish")] {
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(!res.status().is_success(), "{}", res.status());
+    }
+
+    let steps = [
+        (Method::POST, uri("/acceptances"), WALKER),
+        (Method::PUT, uri(&format!("/accepter/{}", WALKER)), OWNER),
+    ];
+    for (method, uri, user_id) in steps {
+        let res = test::call_service(&app, call(method, &uri, user_id).to_request()).await;
+        assert!(res.status().is_success(), "{} {}", uri, res.status());
+    }
+    // Only the accepted walker starts, and only a started walk finishes.
+    for req in [refused("stranger", "/start"), refused(WALKER, "/finish")] {
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(!res.status().is_success(), "{}", res.status());
+    }
+    let request: Value =
+        test::call_and_read_body_json(&app, call(Method::GET, &uri(""), OWNER).to_request()).await;
+    assert_eq!(request["status"], "Accepted");
+}
+
+#[actix_web::test]
+async fn jwt_callers_are_who_the_token_says() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    let verifier = JwtVerifier::new("HS256", KeyRing::parse("", "secret").unwrap()).unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Jwt(verifier)))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &json!({"sub": OWNER, "exp": chrono::Utc::now().timestamp() + 3600}),
+        &jsonwebtoken::EncodingKey::from_secret(b"secret"),
+    )
+    .unwrap();
+    let body = json!({"dogs": [dog()], "latitude": 39.908, "longitude": 116.397});
+
+    let res = test::call_service(
+        &app,
+        call(Method::POST, "/apis/walk_requests", OWNER)
+            .set_json(&body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // The tenant comes from the token alone, an unknown one in the header
+    // is ignored rather than refused.
+    let res = test::call_service(
+        &app,
+        TestRequest::post()
+            .uri("/apis/walk_requests")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .insert_header(("X-Tenant-ID", "nowhere"))
+            .set_json(&body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let mine: Value = test::call_and_read_body_json(
+        &app,
+        TestRequest::get()
+            .uri("/apis/walk_requests/mine?page=1&size=10")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(mine["items"][0]["created_by"], OWNER);
+    assert!(mine["items"][0]["tenant_id"].is_null());
+}
+
+#[actix_web::test]
+async fn prices_over_the_spending_cap_need_confirming() {
+    let docker = Cli::default();
+    let mongo = docker.run(Mongo);
+    let service = Service::new(repository(mongo.get_host_port_ipv4(27017)).await);
+    service
+        .set_spending_cap(OWNER, Money::new(10000, "CNY"))
+        .await
+        .unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(service))
+            .app_data(Data::new(Authenticator::Header))
+            .configure(|cfg| routes::<Mongodb>(cfg, POLICY, DistanceUnit::default())),
+    )
+    .await;
+    let body = json!({
+        "dogs": [dog()],
+        "latitude": 39.908,
+        "longitude": 116.397,
+        "price": {"minor_units": 20000, "currency": "CNY"},
+    });
+
+    let res = test::call_service(
+        &app,
+        call(Method::POST, "/apis/walk_requests", OWNER)
+            .set_json(&body)
+            .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    let res = test::call_service(
+        &app,
+        call(
+            Method::POST,
+            "/apis/walk_requests?confirm_over_cap=true",
+            OWNER,
+        )
+        .set_json(&body)
+        .to_request(),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+}